@@ -22,7 +22,10 @@
 //! - Upperlayer stacks (fs, net, display)
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `fs-irq`: Wait for block I/O by the interrupts of block devices, instead of polling them.
 //!     - `net`: Enable networking support.
+//!     - `dhcp`: Configure the network interfaces by DHCP.
+//!     - `net-irq`: Run the network stack in the background, driven by NIC interrupts.
 //!     - `display`: Enable graphics support.
 //! - Device drivers
 //!     - `bus-mmio`: Use device tree to probe all MMIO devices.
@@ -0,0 +1,142 @@
+#include <errno.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <arpa/inet.h>
+#include <linux/netlink.h>
+#include <linux/rtnetlink.h>
+#include <net/if.h>
+#include <netinet/in.h>
+#include <sys/ioctl.h>
+#include <sys/mount.h>
+#include <sys/socket.h>
+#include <sys/stat.h>
+
+static int failed = 0;
+
+// 检查返回值为 -1 且 errno 为 EPERM
+static void expect_eperm(const char *name, int ret) {
+    if (ret == -1 && errno == EPERM) {
+        printf("%s: EPERM OK\n", name);
+    } else {
+        printf("%s: expected EPERM, got %d (%s)\n", name, ret, strerror(errno));
+        failed = 1;
+    }
+}
+
+// 发送一条 rtnetlink 请求，返回应答中的错误码
+static int netlink_request(struct nlmsghdr *req) {
+    int fd = socket(AF_NETLINK, SOCK_RAW, NETLINK_ROUTE);
+    if (fd < 0) {
+        perror("socket(AF_NETLINK)");
+        return -1;
+    }
+    struct sockaddr_nl kernel = {.nl_family = AF_NETLINK};
+    if (sendto(fd, req, req->nlmsg_len, 0, (struct sockaddr *)&kernel, sizeof(kernel)) < 0) {
+        close(fd);
+        return -errno;
+    }
+    char buf[4096];
+    int len = recv(fd, buf, sizeof(buf), 0);
+    close(fd);
+    struct nlmsghdr *ack = (struct nlmsghdr *)buf;
+    if (len < (int)NLMSG_LENGTH(sizeof(struct nlmsgerr)) || ack->nlmsg_type != NLMSG_ERROR) {
+        return -EIO;
+    }
+    return ((struct nlmsgerr *)NLMSG_DATA(ack))->error;
+}
+
+// 在 lo 上添加地址 10.1.2.3/8
+static int add_address(void) {
+    struct {
+        struct nlmsghdr hdr;
+        struct ifaddrmsg msg;
+        struct rtattr attr;
+        struct in_addr addr;
+    } req = {0};
+    req.hdr.nlmsg_len = sizeof(req);
+    req.hdr.nlmsg_type = RTM_NEWADDR;
+    req.hdr.nlmsg_flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE;
+    req.msg.ifa_family = AF_INET;
+    req.msg.ifa_prefixlen = 8;
+    req.msg.ifa_index = if_nametoindex("lo");
+    req.attr.rta_type = IFA_LOCAL;
+    req.attr.rta_len = RTA_LENGTH(sizeof(req.addr));
+    inet_pton(AF_INET, "10.1.2.3", &req.addr);
+    return netlink_request(&req.hdr);
+}
+
+// 添加经由 lo 的路由 10.0.0.0/8
+static int add_route(void) {
+    struct {
+        struct nlmsghdr hdr;
+        struct rtmsg msg;
+        struct rtattr dst_attr;
+        struct in_addr dst;
+        struct rtattr oif_attr;
+        int oif;
+    } req = {0};
+    req.hdr.nlmsg_len = sizeof(req);
+    req.hdr.nlmsg_type = RTM_NEWROUTE;
+    req.hdr.nlmsg_flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE;
+    req.msg.rtm_family = AF_INET;
+    req.msg.rtm_dst_len = 8;
+    req.msg.rtm_table = RT_TABLE_MAIN;
+    req.msg.rtm_type = RTN_UNICAST;
+    req.dst_attr.rta_type = RTA_DST;
+    req.dst_attr.rta_len = RTA_LENGTH(sizeof(req.dst));
+    inet_pton(AF_INET, "10.0.0.0", &req.dst);
+    req.oif_attr.rta_type = RTA_OIF;
+    req.oif_attr.rta_len = RTA_LENGTH(sizeof(req.oif));
+    req.oif = if_nametoindex("lo");
+    return netlink_request(&req.hdr);
+}
+
+// 用 ioctl 设置 lo 的地址或掩码
+static int set_ifreq(int fd, unsigned long request, const char *addr) {
+    struct ifreq ifr = {0};
+    strncpy(ifr.ifr_name, "lo", IFNAMSIZ - 1);
+    struct sockaddr_in *sin = (struct sockaddr_in *)&ifr.ifr_addr;
+    sin->sin_family = AF_INET;
+    inet_pton(AF_INET, addr, &sin->sin_addr);
+    return ioctl(fd, request, &ifr);
+}
+
+int main() {
+    // root 可以挂载，作为对照
+    mkdir("/tmp_priv", 0777);
+    if (mount("tmpfs", "/tmp_priv", "tmpfs", 0, "size=1m") != 0) {
+        perror("mount as root");
+        return 1;
+    }
+
+    // 降为普通用户后，挂载与修改网络配置都应返回 EPERM
+    if (setuid(1000) != 0) {
+        perror("setuid(1000)");
+        return 1;
+    }
+    expect_eperm("mount", mount("tmpfs", "/tmp", "tmpfs", 0, NULL));
+    expect_eperm("umount", umount2("/tmp_priv", 0));
+
+    int fd = socket(AF_INET, SOCK_DGRAM, 0);
+    if (fd < 0) {
+        perror("socket(AF_INET)");
+        return 1;
+    }
+    expect_eperm("SIOCSIFADDR", set_ifreq(fd, SIOCSIFADDR, "127.0.0.2"));
+    expect_eperm("SIOCSIFNETMASK", set_ifreq(fd, SIOCSIFNETMASK, "255.255.0.0"));
+    close(fd);
+
+    int err = add_address();
+    errno = -err;
+    expect_eperm("RTM_NEWADDR", err ? -1 : 0);
+    err = add_route();
+    errno = -err;
+    expect_eperm("RTM_NEWROUTE", err ? -1 : 0);
+
+    if (failed) {
+        return 1;
+    }
+    printf("privilege test OK\n");
+    return 0;
+}
@@ -0,0 +1,148 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/ipc.h>
+#include <sys/msg.h>
+#include <sys/sem.h>
+#include <sys/wait.h>
+
+static int failed = 0;
+
+#define CHECK(cond)                                                   \
+    do {                                                              \
+        if (!(cond)) {                                                \
+            printf("%s:%d: %s failed (%s)\n", __FILE__, __LINE__,     \
+                   #cond, strerror(errno));                           \
+            failed = 1;                                               \
+        }                                                             \
+    } while (0)
+
+struct message {
+    long mtype;
+    char mtext[32];
+};
+
+// 消息的收发，以及长度的检查
+static void test_msg(int msqid) {
+    struct message msg = {.mtype = 2};
+    strcpy(msg.mtext, "hello");
+    CHECK(msgsnd(msqid, &msg, 6, 0) == 0);
+    msg.mtype = 1;
+    strcpy(msg.mtext, "first");
+    CHECK(msgsnd(msqid, &msg, 6, 0) == 0);
+
+    // 负的类型取类型最小的消息
+    memset(&msg, 0, sizeof(msg));
+    CHECK(msgrcv(msqid, &msg, sizeof(msg.mtext), -2, 0) == 6);
+    CHECK(msg.mtype == 1 && strcmp(msg.mtext, "first") == 0);
+    // 缓冲区不够时失败，MSG_NOERROR 时截断
+    CHECK(msgrcv(msqid, &msg, 2, 0, IPC_NOWAIT) == -1 && errno == E2BIG);
+    CHECK(msgrcv(msqid, &msg, 2, 0, MSG_NOERROR) == 2);
+    CHECK(msg.mtype == 2 && memcmp(msg.mtext, "he", 2) == 0);
+    CHECK(msgrcv(msqid, &msg, sizeof(msg.mtext), 0, IPC_NOWAIT) == -1 && errno == ENOMSG);
+
+    // 过长的消息，以及末尾超出地址空间的缓冲区（Linux 对后者返回 EFAULT）
+    CHECK(msgsnd(msqid, &msg, 8193, 0) == -1 && errno == EINVAL);
+    CHECK(msgsnd(msqid, &msg, SIZE_MAX - 4, 0) == -1 && errno == EINVAL);
+    CHECK(msgsnd(msqid, (void *)(UINTPTR_MAX - 8), 64, 0) == -1 &&
+          (errno == EINVAL || errno == EFAULT));
+}
+
+// 信号量的操作
+static void test_sem(int semid) {
+    struct sembuf up = {0, 2, 0};
+    struct sembuf down = {0, -1, IPC_NOWAIT};
+    struct sembuf zero = {1, 0, IPC_NOWAIT};
+    CHECK(semop(semid, &up, 1) == 0);
+    CHECK(semop(semid, &down, 1) == 0);
+    CHECK(semctl(semid, 0, GETVAL) == 1);
+    CHECK(semop(semid, &zero, 1) == 0);
+
+    // 所有操作原子地完成，任一操作无法完成时都不生效
+    struct sembuf ops[2] = {{0, -1, IPC_NOWAIT}, {1, -1, IPC_NOWAIT}};
+    CHECK(semop(semid, ops, 2) == -1 && errno == EAGAIN);
+    CHECK(semctl(semid, 0, GETVAL) == 1);
+    struct sembuf bad = {5, 1, 0};
+    CHECK(semop(semid, &bad, 1) == -1 && errno == EFBIG);
+}
+
+// MSG_STAT 与 SEM_STAT 的参数为表中的下标，*_INFO 返回已使用的最大下标，
+// 二者返回该下标处的对象的 id
+static void test_stat(int msqid, int semid) {
+    struct msginfo msginfo;
+    struct msqid_ds msqds;
+    int max_index = msgctl(0, MSG_INFO, (struct msqid_ds *)&msginfo);
+    CHECK(max_index >= 0);
+    int found = 0;
+    for (int i = 0; i <= max_index; i++) {
+        if (msgctl(i, MSG_STAT, &msqds) == msqid) {
+            found = 1;
+        }
+    }
+    CHECK(found);
+    CHECK(msgctl(max_index + 1, MSG_STAT, &msqds) == -1 && errno == EINVAL);
+
+    struct seminfo seminfo;
+    struct semid_ds semds;
+    max_index = semctl(0, 0, SEM_INFO, &seminfo);
+    CHECK(max_index >= 0);
+    found = 0;
+    for (int i = 0; i <= max_index; i++) {
+        if (semctl(i, 0, SEM_STAT, &semds) == semid) {
+            found = 1;
+            CHECK(semds.sem_nsems == 2);
+        }
+    }
+    CHECK(found);
+    CHECK(semctl(max_index + 1, 0, SEM_STAT, &semds) == -1 && errno == EINVAL);
+}
+
+// 普通用户对只有属主可读写的对象没有权限
+static void test_perm(int msqid, int semid) {
+    struct message msg = {.mtype = 1};
+    struct sembuf up = {0, 1, IPC_NOWAIT};
+    struct sembuf zero = {1, 0, IPC_NOWAIT};
+    struct msqid_ds ds;
+    CHECK(setuid(1000) == 0);
+    CHECK(msgsnd(msqid, &msg, 1, IPC_NOWAIT) == -1 && errno == EACCES);
+    CHECK(msgrcv(msqid, &msg, 1, 0, IPC_NOWAIT) == -1 && errno == EACCES);
+    CHECK(msgctl(msqid, IPC_STAT, &ds) == -1 && errno == EACCES);
+    CHECK(msgctl(msqid, IPC_RMID, NULL) == -1 && errno == EPERM);
+    CHECK(semop(semid, &up, 1) == -1 && errno == EACCES);
+    CHECK(semop(semid, &zero, 1) == -1 && errno == EACCES);
+    CHECK(semctl(semid, 0, IPC_RMID) == -1 && errno == EPERM);
+    CHECK(msgget(0x1234, 0600) == -1 && errno == EACCES);
+}
+
+int main() {
+    int msqid = msgget(0x1234, IPC_CREAT | 0600);
+    int semid = semget(IPC_PRIVATE, 2, IPC_CREAT | 0600);
+    if (msqid < 0 || semid < 0) {
+        perror("msgget/semget");
+        return 1;
+    }
+    test_msg(msqid);
+    test_sem(semid);
+    test_stat(msqid, semid);
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        failed = 0;
+        test_perm(msqid, semid);
+        return failed;
+    }
+    int status;
+    CHECK(waitpid(pid, &status, 0) == pid);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    CHECK(msgctl(msqid, IPC_RMID, NULL) == 0);
+    CHECK(semctl(semid, 0, IPC_RMID) == 0);
+    if (failed) {
+        return 1;
+    }
+    printf("sysv ipc test OK\n");
+    return 0;
+}
@@ -8,4 +8,5 @@ fn main() {
     axstd::println!("Benchmarking bandwidth...");
     axnet::bench_transmit();
     // axnet::bench_receive();
+    // axnet::bench_tcp_loopback(1 << 30, axnet::CongestionControl::Cubic).unwrap();
 }
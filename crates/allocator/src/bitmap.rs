@@ -9,11 +9,19 @@ use crate::{AllocError, AllocResult, BaseAllocator, PageAllocator};
 // Support max 1M * 4096 = 4GB memory.
 type BitAllocUsed = bitmap_allocator::BitAlloc1M;
 
+/// The max number of discontiguous memory regions managed by one allocator.
+const MAX_REGIONS: usize = 16;
+
 /// A page-granularity memory allocator based on the [bitmap_allocator].
 ///
 /// It internally uses a bitmap, each bit indicates whether a page has been
 /// allocated.
 ///
+/// It can manage multiple discontiguous memory regions, as long as all of them
+/// lie in the address window covered by the bitmap, which starts from the
+/// region given to [`BaseAllocator::init`]. Pages in the holes between regions
+/// are never allocated.
+///
 /// The `PAGE_SIZE` must be a power of two.
 ///
 /// [bitmap_allocator]: https://github.com/rcore-os/bitmap-allocator
@@ -21,6 +29,9 @@ pub struct BitmapPageAllocator<const PAGE_SIZE: usize> {
     base: usize,
     total_pages: usize,
     used_pages: usize,
+    /// Page index ranges `[start, end)` of the managed regions, relative to `base`.
+    regions: [(usize, usize); MAX_REGIONS],
+    num_regions: usize,
     inner: BitAllocUsed,
 }
 
@@ -31,9 +42,24 @@ impl<const PAGE_SIZE: usize> BitmapPageAllocator<PAGE_SIZE> {
             base: 0,
             total_pages: 0,
             used_pages: 0,
+            regions: [(0, 0); MAX_REGIONS],
+            num_regions: 0,
             inner: BitAllocUsed::DEFAULT,
         }
     }
+
+    /// Returns the managed memory regions as `(start, size)` pairs.
+    pub fn regions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.regions[..self.num_regions]
+            .iter()
+            .map(|&(start, end)| (self.base + start * PAGE_SIZE, (end - start) * PAGE_SIZE))
+    }
+
+    fn contains_page(&self, idx: usize) -> bool {
+        self.regions[..self.num_regions]
+            .iter()
+            .any(|&(start, end)| start <= idx && idx < end)
+    }
 }
 
 impl<const PAGE_SIZE: usize> BaseAllocator for BitmapPageAllocator<PAGE_SIZE> {
@@ -43,11 +69,40 @@ impl<const PAGE_SIZE: usize> BaseAllocator for BitmapPageAllocator<PAGE_SIZE> {
         let start = super::align_up(start, PAGE_SIZE);
         self.base = start;
         self.total_pages = (end - start) / PAGE_SIZE;
+        self.regions[0] = (0, self.total_pages);
+        self.num_regions = 1;
         self.inner.insert(0..self.total_pages);
     }
 
-    fn add_memory(&mut self, _start: usize, _size: usize) -> AllocResult {
-        Err(AllocError::NoMemory) // unsupported
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        if self.num_regions == 0 {
+            self.init(start, size);
+            return Ok(());
+        }
+        let end = super::align_down(start + size, PAGE_SIZE);
+        let start = super::align_up(start, PAGE_SIZE);
+        if start >= end
+            || start < self.base
+            || (end - self.base) / PAGE_SIZE > BitAllocUsed::CAP
+            || self.num_regions == MAX_REGIONS
+        {
+            return Err(AllocError::InvalidParam);
+        }
+        let (start_idx, end_idx) = (
+            (start - self.base) / PAGE_SIZE,
+            (end - self.base) / PAGE_SIZE,
+        );
+        if self.regions[..self.num_regions]
+            .iter()
+            .any(|&(s, e)| start_idx < e && s < end_idx)
+        {
+            return Err(AllocError::MemoryOverlap);
+        }
+        self.regions[self.num_regions] = (start_idx, end_idx);
+        self.num_regions += 1;
+        self.total_pages += end_idx - start_idx;
+        self.inner.insert(start_idx..end_idx);
+        Ok(())
     }
 }
 
@@ -76,9 +131,17 @@ impl<const PAGE_SIZE: usize> PageAllocator for BitmapPageAllocator<PAGE_SIZE> {
     }
 
     fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
-        // TODO: not decrease `used_pages` if deallocation failed
-        self.used_pages -= num_pages;
-        self.inner.dealloc((pos - self.base) / PAGE_SIZE)
+        if pos < self.base {
+            return;
+        }
+        let start_idx = (pos - self.base) / PAGE_SIZE;
+        for idx in start_idx..start_idx + num_pages {
+            // skip the pages which are not managed by this allocator or not allocated
+            if self.contains_page(idx) && !self.inner.test(idx) {
+                self.inner.dealloc(idx);
+                self.used_pages -= 1;
+            }
+        }
     }
 
     fn total_pages(&self) -> usize {
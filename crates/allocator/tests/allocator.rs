@@ -5,7 +5,10 @@ use std::alloc::{Allocator, Layout};
 use std::collections::BTreeMap;
 use std::io::Write;
 
-use allocator::{AllocatorRc, BuddyByteAllocator, SlabByteAllocator, TlsfByteAllocator};
+use allocator::{
+    AllocError, AllocatorRc, BaseAllocator, BitmapPageAllocator, BuddyByteAllocator, PageAllocator,
+    SlabByteAllocator, TlsfByteAllocator,
+};
 use rand::{prelude::SliceRandom, Rng};
 
 const POOL_SIZE: usize = 1024 * 1024 * 128;
@@ -141,3 +144,37 @@ fn tlsf_alloc() {
         test_btree_map(50_000, &alloc);
     })
 }
+
+#[test]
+fn bitmap_page_alloc_regions() {
+    const PAGE_SIZE: usize = 4096;
+    let base = 0x1000_0000;
+    let mut alloc = Box::new(BitmapPageAllocator::<PAGE_SIZE>::new());
+    alloc.init(base, 16 * PAGE_SIZE);
+    alloc
+        .add_memory(base + 32 * PAGE_SIZE, 16 * PAGE_SIZE)
+        .unwrap();
+    assert!(matches!(
+        alloc.add_memory(base + 40 * PAGE_SIZE, PAGE_SIZE),
+        Err(AllocError::MemoryOverlap)
+    ));
+    assert!(matches!(
+        alloc.add_memory(base - PAGE_SIZE, PAGE_SIZE),
+        Err(AllocError::InvalidParam)
+    ));
+    assert_eq!(alloc.total_pages(), 32);
+
+    // contiguous pages never span the hole between the regions
+    let mut blocks = [
+        alloc.alloc_pages(16, PAGE_SIZE).unwrap(),
+        alloc.alloc_pages(16, PAGE_SIZE).unwrap(),
+    ];
+    blocks.sort();
+    assert_eq!(blocks, [base, base + 32 * PAGE_SIZE]);
+    assert!(alloc.alloc_pages(1, PAGE_SIZE).is_err());
+
+    // all the pages of a contiguous block are given back
+    alloc.dealloc_pages(blocks[1], 16);
+    assert_eq!(alloc.used_pages(), 16);
+    assert_eq!(alloc.alloc_pages(16, PAGE_SIZE).unwrap(), blocks[1]);
+}
@@ -7,13 +7,31 @@ use spin::Mutex;
 /// A random device behaves like `/dev/random` or `/dev/urandom`.
 ///
 /// It always returns a chunk of random bytes when read, and all writes are discarded.
+/// The bytes come from the given entropy source, e.g., the kernel RNG, or from a
+/// fixed-seed pseudo random generator by default.
 ///
 /// TODO: update entropy pool with data written.
-pub struct RandomDev(Mutex<SmallRng>);
+pub struct RandomDev {
+    rng: Mutex<SmallRng>,
+    source: Option<fn(&mut [u8])>,
+}
+
+impl RandomDev {
+    /// Create a random device that reads from the given entropy source.
+    pub fn new(source: fn(&mut [u8])) -> Self {
+        Self {
+            source: Some(source),
+            ..Default::default()
+        }
+    }
+}
 
 impl Default for RandomDev {
     fn default() -> Self {
-        Self(Mutex::new(SmallRng::from_seed([0; 32])))
+        Self {
+            rng: Mutex::new(SmallRng::from_seed([0; 32])),
+            source: None,
+        }
     }
 }
 
@@ -28,7 +46,10 @@ impl VfsNodeOps for RandomDev {
     }
 
     fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        buf.try_fill(self.0.lock().deref_mut()).unwrap();
+        match self.source {
+            Some(source) => source(buf),
+            None => buf.try_fill(self.rng.lock().deref_mut()).unwrap(),
+        }
         Ok(buf.len())
     }
 
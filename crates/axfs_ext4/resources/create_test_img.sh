@@ -0,0 +1,25 @@
+#!/bin/bash
+
+# Creates the ext4 image for the tests of this crate, with 1K blocks, a
+# journal and metadata checksums. Only needs e2fsprogs, no mounting.
+
+CUR_DIR=`dirname $0`
+
+create_test_img() {
+	local name=$1
+	local blkcount=$2
+	local src=`mktemp -d`
+	for i in $(seq 1 1000); do
+	  echo "Rust is cool!" >>"$src/long.txt"
+	done
+	echo "Rust is cool!" >>"$src/short.txt"
+	mkdir -p "$src/very/long/path"
+	echo "Rust is cool!" >>"$src/very/long/path/test.txt"
+	rm -f "$name"
+	mkfs.ext4 -q -b 1024 -N 4096 -O metadata_csum,dir_index -L "Test!" \
+		-U 12345678-1234-1234-1234-123456789abc -E hash_seed=12345678-1234-1234-1234-123456789abc \
+		-d "$src" "$name" $blkcount
+	rm -rf "$src"
+}
+
+create_test_img "$CUR_DIR/test.img" 4096
@@ -0,0 +1,251 @@
+//! Allocation of blocks and inodes in the bitmaps of the groups.
+//!
+//! Blocks are allocated as close to a goal as possible, so that a file grows
+//! contiguously. Groups whose bitmaps are not initialized (`uninit_bg`) get
+//! them when first allocated from.
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use crate::fs::State;
+use crate::layout::*;
+
+fn test_bit(bitmap: &[u8], bit: usize) -> bool {
+    bitmap[bit / 8] & (1 << (bit % 8)) != 0
+}
+
+fn set_bit(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] |= 1 << (bit % 8);
+}
+
+fn clear_bit(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] &= !(1 << (bit % 8));
+}
+
+/// Finds the first zero bit in `start..end`.
+fn find_zero(bitmap: &[u8], start: usize, end: usize) -> Option<usize> {
+    let mut bit = start;
+    while bit < end {
+        if bit % 8 == 0 && bitmap[bit / 8] == 0xff {
+            bit += 8;
+        } else if !test_bit(bitmap, bit) {
+            return Some(bit);
+        } else {
+            bit += 1;
+        }
+    }
+    None
+}
+
+impl State {
+    /// Returns the block bitmap of the group, initializing it first if the
+    /// group has `BLOCK_UNINIT`: only the copies of the superblock and the
+    /// descriptors, and the bitmaps and the inode table of the group if they
+    /// are in it, are in use.
+    fn block_bitmap(&mut self, group: u32) -> VfsResult<u64> {
+        let desc = self.groups[group as usize].clone();
+        if desc.flags() & BG_BLOCK_UNINIT == 0 {
+            return Ok(desc.block_bitmap());
+        }
+        let first = self.group_first_block(group);
+        let end = first + self.sb.blocks_per_group() as u64;
+        let meta = self.base_meta_blocks(group) as usize;
+        let blocks = self.group_blocks(group) as usize;
+        let table_blocks = (self.sb.inodes_per_group() as usize * self.sb.inode_size())
+            .div_ceil(self.block_size) as u64;
+        let table = desc.inode_table();
+        let bits = self.block_size * 8;
+
+        let data = self.cache.zeroed(desc.block_bitmap());
+        for bit in (0..meta).chain(blocks..bits) {
+            set_bit(data, bit);
+        }
+        let own = [desc.block_bitmap(), desc.inode_bitmap()];
+        for block in own.into_iter().chain(table..table + table_blocks) {
+            if (first..end).contains(&block) {
+                set_bit(data, (block - first) as usize);
+            }
+        }
+        self.groups[group as usize].set_flags(desc.flags() & !BG_BLOCK_UNINIT);
+        self.mark_group_dirty(group);
+        Ok(desc.block_bitmap())
+    }
+
+    /// Returns the inode bitmap of the group, initializing it first if the
+    /// group has `INODE_UNINIT`.
+    fn inode_bitmap(&mut self, group: u32) -> VfsResult<u64> {
+        let desc = self.groups[group as usize].clone();
+        if desc.flags() & BG_INODE_UNINIT == 0 {
+            return Ok(desc.inode_bitmap());
+        }
+        let inodes = self.sb.inodes_per_group() as usize;
+        let bits = self.block_size * 8;
+        let data = self.cache.zeroed(desc.inode_bitmap());
+        for bit in inodes..bits {
+            set_bit(data, bit);
+        }
+        self.groups[group as usize].set_flags(desc.flags() & !BG_INODE_UNINIT);
+        self.mark_group_dirty(group);
+        Ok(desc.inode_bitmap())
+    }
+
+    /// Allocates up to `count` contiguous blocks, starting at `goal` or the
+    /// nearest free block after it. Returns the first block and the number
+    /// of blocks allocated.
+    ///
+    /// Fails with [`VfsError::StorageFull`] if there are no free blocks.
+    pub fn alloc_blocks(&mut self, goal: u64, count: u64) -> VfsResult<(u64, u64)> {
+        self.check_writable()?;
+        if let Some(found) = self.try_alloc_blocks(goal, count)? {
+            return Ok(found);
+        }
+        // reuse the blocks freed in the running transaction
+        if self.release_pending()? {
+            if let Some(found) = self.try_alloc_blocks(goal, count)? {
+                return Ok(found);
+            }
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    fn try_alloc_blocks(&mut self, goal: u64, count: u64) -> VfsResult<Option<(u64, u64)>> {
+        let first_data_block = self.sb.first_data_block();
+        let goal = if (first_data_block..self.sb.blocks_count()).contains(&goal) {
+            goal
+        } else {
+            first_data_block
+        };
+        let groups = self.group_count();
+        let goal_group = self.group_of_block(goal);
+        // the goal group is searched again from its start at last
+        for i in 0..=groups {
+            let group = (goal_group + i) % groups;
+            if self.groups[group as usize].free_blocks() == 0 {
+                continue;
+            }
+            let first = self.group_first_block(group);
+            let start = if i == 0 { (goal - first) as usize } else { 0 };
+            let end = self.group_blocks(group) as usize;
+            let bitmap = self.block_bitmap(group)?;
+            let data = self.cache.read(bitmap)?;
+            let Some(bit) = find_zero(data, start, end) else {
+                continue;
+            };
+            let mut len = 1;
+            while len < count as usize && bit + len < end && !test_bit(data, bit + len) {
+                len += 1;
+            }
+
+            let data = self.cache.write(bitmap)?;
+            for bit in bit..bit + len {
+                set_bit(data, bit);
+            }
+            let desc = &mut self.groups[group as usize];
+            desc.set_free_blocks(desc.free_blocks().saturating_sub(len as u32));
+            self.sb
+                .set_free_blocks(self.sb.free_blocks().saturating_sub(len as u64));
+            self.mark_group_dirty(group);
+            return Ok(Some((first + bit as u64, len as u64)));
+        }
+        Ok(None)
+    }
+
+    /// Marks the blocks as free in the bitmaps.
+    pub fn release_blocks(&mut self, start: u64, count: u64) -> VfsResult {
+        let mut block = start;
+        while block < start + count {
+            let group = self.group_of_block(block);
+            let first = self.group_first_block(group);
+            let end = (start + count).min(first + self.group_blocks(group) as u64);
+            if block < self.sb.first_data_block() || end <= block {
+                warn!("ext4: freeing invalid blocks {}+{}", start, count);
+                return Err(VfsError::InvalidData);
+            }
+            let bitmap = self.block_bitmap(group)?;
+            let data = self.cache.write(bitmap)?;
+            for b in block..end {
+                let bit = (b - first) as usize;
+                if !test_bit(data, bit) {
+                    warn!("ext4: freeing free block {}", b);
+                }
+                clear_bit(data, bit);
+            }
+            let len = end - block;
+            let desc = &mut self.groups[group as usize];
+            desc.set_free_blocks(desc.free_blocks() + len as u32);
+            self.sb.set_free_blocks(self.sb.free_blocks() + len);
+            self.mark_group_dirty(group);
+            block = end;
+        }
+        Ok(())
+    }
+
+    /// Allocates an inode, in the group of the parent directory for a file,
+    /// or in the group with the most free inodes for a directory to spread
+    /// directories out.
+    pub fn alloc_inode(&mut self, parent: u32, is_dir: bool) -> VfsResult<u32> {
+        self.check_writable()?;
+        let inodes = self.sb.inodes_per_group();
+        let groups = self.group_count();
+        let start = if is_dir {
+            (0..groups)
+                .filter(|&g| self.groups[g as usize].free_blocks() > 0)
+                .max_by_key(|&g| (self.groups[g as usize].free_inodes(), groups - g))
+                .unwrap_or(0)
+        } else {
+            (parent - 1) / inodes
+        };
+        let first_ino = self.sb.first_ino();
+        for i in 0..groups {
+            let group = (start + i) % groups;
+            if self.groups[group as usize].free_inodes() == 0 {
+                continue;
+            }
+            let base = group * inodes;
+            let from = first_ino.saturating_sub(base + 1).min(inodes) as usize;
+            let bitmap = self.inode_bitmap(group)?;
+            let data = self.cache.read(bitmap)?;
+            let Some(index) = find_zero(data, from, inodes as usize) else {
+                continue;
+            };
+            set_bit(self.cache.write(bitmap)?, index);
+
+            let has_csum = self
+                .sb
+                .has_ro_compat(RO_COMPAT_GDT_CSUM | RO_COMPAT_METADATA_CSUM);
+            let desc = &mut self.groups[group as usize];
+            desc.set_free_inodes(desc.free_inodes() - 1);
+            if is_dir {
+                desc.set_used_dirs(desc.used_dirs() + 1);
+            }
+            if has_csum && index as u32 >= inodes - desc.itable_unused() {
+                desc.set_itable_unused(inodes - index as u32 - 1);
+            }
+            self.sb.set_free_inodes(self.sb.free_inodes() - 1);
+            self.mark_group_dirty(group);
+            return Ok(base + index as u32 + 1);
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    /// Marks the inode as free in the bitmap.
+    pub fn release_inode(&mut self, ino: u32, is_dir: bool) -> VfsResult {
+        let inodes = self.sb.inodes_per_group();
+        let group = (ino - 1) / inodes;
+        let bitmap = self.inode_bitmap(group)?;
+        let data = self.cache.write(bitmap)?;
+        let index = ((ino - 1) % inodes) as usize;
+        if !test_bit(data, index) {
+            warn!("ext4: freeing free inode {}", ino);
+            return Err(VfsError::InvalidData);
+        }
+        clear_bit(data, index);
+        let desc = &mut self.groups[group as usize];
+        desc.set_free_inodes(desc.free_inodes() + 1);
+        if is_dir {
+            desc.set_used_dirs(desc.used_dirs().saturating_sub(1));
+        }
+        self.sb.set_free_inodes(self.sb.free_inodes() + 1);
+        self.mark_group_dirty(group);
+        Ok(())
+    }
+}
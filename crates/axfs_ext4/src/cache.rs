@@ -0,0 +1,116 @@
+//! The cache of metadata blocks.
+//!
+//! Metadata is only changed in the cache. The changed blocks stay there until
+//! the running transaction is committed, when they are written to the
+//! journal and then to their places on the disk. File data doesn't go
+//! through the cache.
+
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec};
+
+use axfs_vfs::VfsResult;
+
+use crate::BlockDevice;
+
+/// The number of unchanged blocks kept.
+const CAPACITY: usize = 1024;
+
+pub(crate) struct BlockCache {
+    dev: Arc<dyn BlockDevice>,
+    block_size: usize,
+    /// Unchanged blocks, with the time they were last used.
+    clean: BTreeMap<u64, (Box<[u8]>, u64)>,
+    /// Blocks changed in the running transaction.
+    dirty: BTreeMap<u64, Box<[u8]>>,
+    clock: u64,
+}
+
+impl BlockCache {
+    pub fn new(dev: Arc<dyn BlockDevice>, block_size: usize) -> Self {
+        Self {
+            dev,
+            block_size,
+            clean: BTreeMap::new(),
+            dirty: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn load(&mut self, block: u64) -> VfsResult {
+        if self.dirty.contains_key(&block) || self.clean.contains_key(&block) {
+            return Ok(());
+        }
+        let mut data = vec![0; self.block_size].into_boxed_slice();
+        self.dev
+            .read_at(block * self.block_size as u64, &mut data)?;
+        self.insert_clean(block, data);
+        Ok(())
+    }
+
+    fn insert_clean(&mut self, block: u64, data: Box<[u8]>) {
+        if self.clean.len() >= CAPACITY {
+            // drop the least recently used quarter
+            let mut used: alloc::vec::Vec<u64> = self.clean.values().map(|(_, t)| *t).collect();
+            used.sort_unstable();
+            let limit = used[CAPACITY / 4];
+            self.clean.retain(|_, (_, t)| *t > limit);
+        }
+        self.clock += 1;
+        self.clean.insert(block, (data, self.clock));
+    }
+
+    /// Returns the content of the block.
+    pub fn read(&mut self, block: u64) -> VfsResult<&[u8]> {
+        self.load(block)?;
+        self.clock += 1;
+        if let Some(data) = self.dirty.get(&block) {
+            return Ok(data);
+        }
+        let (data, used) = self.clean.get_mut(&block).unwrap();
+        *used = self.clock;
+        Ok(data)
+    }
+
+    /// Returns the content of the block to be changed in the running
+    /// transaction.
+    pub fn write(&mut self, block: u64) -> VfsResult<&mut [u8]> {
+        self.load(block)?;
+        if let Some((data, _)) = self.clean.remove(&block) {
+            self.dirty.insert(block, data);
+        }
+        Ok(self.dirty.get_mut(&block).unwrap())
+    }
+
+    /// Returns a zeroed block to be filled in the running transaction,
+    /// without reading it from the disk.
+    pub fn zeroed(&mut self, block: u64) -> &mut [u8] {
+        self.clean.remove(&block);
+        let data = self
+            .dirty
+            .entry(block)
+            .or_insert_with(|| vec![0; self.block_size].into_boxed_slice());
+        data.fill(0);
+        data
+    }
+
+    /// Drops the block after it's freed, it may be used for file data later.
+    pub fn forget(&mut self, block: u64) {
+        self.clean.remove(&block);
+        self.dirty.remove(&block);
+    }
+
+    /// The number of blocks changed in the running transaction.
+    pub fn dirty_count(&self) -> usize {
+        self.dirty.len()
+    }
+
+    pub fn dirty(&self) -> impl Iterator<Item = (&u64, &Box<[u8]>)> {
+        self.dirty.iter()
+    }
+
+    /// Marks all changed blocks as written.
+    pub fn clean_all(&mut self) {
+        for (block, data) in core::mem::take(&mut self.dirty) {
+            self.insert_clean(block, data);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! The checksums of ext4 and jbd2.
+//!
+//! Both use CRC32C without the final inversion, continued from a seed, so
+//! that a checksum can be computed over several pieces. Group descriptors of
+//! filesystems with `uninit_bg` but without `metadata_csum` use CRC16.
+
+const fn table(poly: u32) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32C_TABLE: [u32; 256] = table(0x82f6_3b78);
+static CRC16_TABLE: [u32; 256] = table(0xa001);
+
+/// Continues the CRC32C `crc` over `data`.
+pub(crate) fn crc32c(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &b| {
+        CRC32C_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Continues the CRC16 `crc` over `data`.
+pub(crate) fn crc16(crc: u16, data: &[u8]) -> u16 {
+    data.iter().fold(crc, |crc, &b| {
+        (CRC16_TABLE[((crc ^ b as u16) & 0xff) as usize] ^ (crc as u32 >> 8)) as u16
+    })
+}
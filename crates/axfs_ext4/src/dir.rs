@@ -0,0 +1,851 @@
+//! Directories: linear lists of entries, and indexed directories whose
+//! blocks are found by the hash of the name in an htree.
+//!
+//! A directory of one block that fills up becomes indexed if the filesystem
+//! has `dir_index`. The blocks of an indexed directory are also valid
+//! linear blocks, so they are listed by scanning them in order.
+
+use alloc::{vec, vec::Vec};
+
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+
+use crate::crc::crc32c;
+use crate::extent::{Extent, ExtentTree};
+use crate::fs::State;
+use crate::hash;
+use crate::layout::*;
+
+/// The size of the fixed part of an entry.
+const ENTRY_HEADER: usize = 8;
+/// The size of the tail holding the checksum of a block of entries.
+const TAIL_SIZE: usize = 12;
+const TAIL_FILE_TYPE: u8 = 0xde;
+/// The offset of `dx_root_info` in the root block of an index.
+const ROOT_INFO: usize = 24;
+const MAX_NAME_LEN: usize = 255;
+
+/// The file type in a directory entry.
+pub(crate) fn dirent_type(ty: VfsNodeType) -> u8 {
+    match ty {
+        VfsNodeType::File => 1,
+        VfsNodeType::Dir => 2,
+        VfsNodeType::CharDevice => 3,
+        VfsNodeType::BlockDevice => 4,
+        VfsNodeType::Fifo => 5,
+        VfsNodeType::Socket => 6,
+        VfsNodeType::SymLink => 7,
+    }
+}
+
+fn node_type(ty: u8) -> Option<VfsNodeType> {
+    Some(match ty {
+        1 => VfsNodeType::File,
+        2 => VfsNodeType::Dir,
+        3 => VfsNodeType::CharDevice,
+        4 => VfsNodeType::BlockDevice,
+        5 => VfsNodeType::Fifo,
+        6 => VfsNodeType::Socket,
+        7 => VfsNodeType::SymLink,
+        _ => return None,
+    })
+}
+
+/// The space taken by an entry with a name of `name_len` bytes.
+fn entry_size(name_len: usize) -> usize {
+    (ENTRY_HEADER + name_len + 3) & !3
+}
+
+fn rec_len(block: &[u8], off: usize) -> usize {
+    match le16(block, off + 4) as usize {
+        0 | 65535 if block.len() == 65536 => 65536,
+        len => len,
+    }
+}
+
+fn set_rec_len(block: &mut [u8], off: usize, len: usize) {
+    put16(block, off + 4, len.min(65535) as u16);
+}
+
+/// An entry in a block.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    off: usize,
+    ino: u32,
+    rec_len: usize,
+    name_len: usize,
+    file_type: u8,
+}
+
+impl Entry {
+    fn name<'a>(&self, block: &'a [u8]) -> &'a [u8] {
+        &block[self.off + ENTRY_HEADER..self.off + ENTRY_HEADER + self.name_len]
+    }
+
+    /// The space used by the entry, none if it's free.
+    fn used(&self) -> usize {
+        if self.ino == 0 {
+            0
+        } else {
+            entry_size(self.name_len)
+        }
+    }
+}
+
+/// Parses the entries of a block, failing if they are malformed.
+fn entries(block: &[u8]) -> VfsResult<Vec<Entry>> {
+    let mut list = Vec::new();
+    let mut off = 0;
+    while off < block.len() {
+        if off + ENTRY_HEADER > block.len() {
+            return Err(VfsError::InvalidData);
+        }
+        let entry = Entry {
+            off,
+            ino: le32(block, off),
+            rec_len: rec_len(block, off),
+            name_len: block[off + 6] as usize,
+            file_type: block[off + 7],
+        };
+        if entry.rec_len < ENTRY_HEADER
+            || entry.rec_len % 4 != 0
+            || off + entry.rec_len > block.len()
+            || entry.used() > entry.rec_len
+        {
+            warn!("ext4: bad directory entry");
+            return Err(VfsError::InvalidData);
+        }
+        list.push(entry);
+        off += entry.rec_len;
+    }
+    Ok(list)
+}
+
+fn write_entry(block: &mut [u8], off: usize, ino: u32, rec_len: usize, name: &[u8], ty: u8) {
+    put32(block, off, ino);
+    set_rec_len(block, off, rec_len);
+    block[off + 6] = name.len() as u8;
+    block[off + 7] = ty;
+    block[off + ENTRY_HEADER..off + ENTRY_HEADER + name.len()].copy_from_slice(name);
+    let end = off + entry_size(name.len()).min(rec_len);
+    block[off + ENTRY_HEADER + name.len()..end].fill(0);
+}
+
+/// An entry found by name: the logical block, the offset in it, and the
+/// inode it refers to.
+struct Found {
+    lblk: u32,
+    off: usize,
+    ino: u32,
+}
+
+/// A level of the path through an index to a leaf block.
+#[derive(Clone)]
+struct Frame {
+    lblk: u32,
+    /// The offset of `dx_countlimit`, followed by the entries.
+    base: usize,
+    /// The entry followed.
+    at: usize,
+}
+
+/// An index block.
+struct DxBlock {
+    data: Vec<u8>,
+    base: usize,
+}
+
+impl DxBlock {
+    fn limit(&self) -> usize {
+        le16(&self.data, self.base) as usize
+    }
+
+    fn count(&self) -> usize {
+        le16(&self.data, self.base + 2) as usize
+    }
+
+    fn set_count(&mut self, count: usize) {
+        put16(&mut self.data, self.base + 2, count as u16);
+    }
+
+    fn hash(&self, i: usize) -> u32 {
+        le32(&self.data, self.base + i * 8)
+    }
+
+    fn block(&self, i: usize) -> u32 {
+        le32(&self.data, self.base + i * 8 + 4)
+    }
+
+    fn set_entry(&mut self, i: usize, hash: u32, block: u32) {
+        if i > 0 {
+            put32(&mut self.data, self.base + i * 8, hash);
+        }
+        put32(&mut self.data, self.base + i * 8 + 4, block);
+    }
+
+    /// Inserts an entry at `i`, there must be room for it.
+    fn insert(&mut self, i: usize, hash: u32, block: u32) {
+        let count = self.count();
+        let start = self.base + i * 8;
+        let end = self.base + count * 8;
+        self.data.copy_within(start..end, start + 8);
+        self.set_count(count + 1);
+        self.set_entry(i, hash, block);
+    }
+
+    /// The last entry whose hash is not above `hash`.
+    fn find(&self, hash: u32) -> usize {
+        let (mut lo, mut hi) = (1, self.count());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.hash(mid) <= hash {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo - 1
+    }
+}
+
+impl State {
+    /// The space for entries in a leaf block, before the checksum tail.
+    fn leaf_space(&self) -> usize {
+        if self.csum_seed.is_some() {
+            self.block_size - TAIL_SIZE
+        } else {
+            self.block_size
+        }
+    }
+
+    fn has_filetype(&self) -> bool {
+        self.sb.has_incompat(INCOMPAT_FILETYPE)
+    }
+
+    /// Reads a block of the directory.
+    fn read_dir_block(&mut self, dir: &Inode, lblk: u32) -> VfsResult<(u64, Vec<u8>)> {
+        let Some((pblk, _, false)) = self.map_block(dir, lblk)? else {
+            warn!("ext4: hole in directory {}", dir.ino);
+            return Err(VfsError::InvalidData);
+        };
+        Ok((pblk, self.cache.read(pblk)?.to_vec()))
+    }
+
+    /// Writes a leaf block of the directory, with its checksum.
+    fn write_leaf(&mut self, dir: &Inode, pblk: u64, mut data: Vec<u8>) -> VfsResult {
+        if let Some(seed) = self.inode_seed(dir) {
+            let tail = self.block_size - TAIL_SIZE;
+            data[tail..].fill(0);
+            set_rec_len(&mut data, tail, TAIL_SIZE);
+            data[tail + 7] = TAIL_FILE_TYPE;
+            let csum = crc32c(seed, &data[..tail]);
+            put32(&mut data, tail + 8, csum);
+        }
+        self.cache.write(pblk)?.copy_from_slice(&data);
+        Ok(())
+    }
+
+    /// Writes an index block of the directory, with its checksum.
+    fn write_dx(&mut self, dir: &Inode, pblk: u64, mut dx: DxBlock) -> VfsResult {
+        if let Some(seed) = self.inode_seed(dir) {
+            let tail = dx.base + dx.limit() * 8;
+            put32(&mut dx.data, tail, 0);
+            let size = dx.base + dx.count() * 8;
+            let mut csum = crc32c(seed, &dx.data[..size]);
+            csum = crc32c(csum, &dx.data[tail..tail + 4]);
+            csum = crc32c(csum, &[0; 4]);
+            put32(&mut dx.data, tail + 4, csum);
+        }
+        self.cache.write(pblk)?.copy_from_slice(&dx.data);
+        Ok(())
+    }
+
+    /// Returns the entries of the block, checking its checksum if it has
+    /// one.
+    fn leaf_entries(&self, dir: &Inode, data: &[u8]) -> VfsResult<Vec<Entry>> {
+        let tail = self.block_size - TAIL_SIZE;
+        let has_tail = self.csum_seed.is_some()
+            && le32(data, tail) == 0
+            && rec_len(data, tail) == TAIL_SIZE
+            && data[tail + 7] == TAIL_FILE_TYPE;
+        if has_tail {
+            let seed = self.inode_seed(dir).unwrap();
+            if crc32c(seed, &data[..tail]) != le32(data, tail + 8) {
+                warn!("ext4: bad checksum of directory {}", dir.ino);
+                return Err(VfsError::InvalidData);
+            }
+            return entries(&data[..tail]);
+        }
+        entries(data)
+    }
+
+    fn dir_blocks(&self, dir: &Inode) -> u32 {
+        (dir.size() / self.block_size as u64) as u32
+    }
+
+    /// Whether the directory is indexed, and its index can be used.
+    fn is_indexed(&self, dir: &Inode) -> bool {
+        dir.flags() & INDEX_FL != 0 && self.sb.has_compat(COMPAT_DIR_INDEX)
+    }
+
+    /// The hash of the name in the index with the root block `root`, or
+    /// `None` if the index can't be used.
+    fn dx_hash(&self, root: &[u8], name: &[u8]) -> Option<u32> {
+        let version = root[ROOT_INFO + 4];
+        if !hash::is_supported(version) || root[ROOT_INFO + 5] != 8 {
+            return None;
+        }
+        let version = hash::version_of(version, self.sb.hash_unsigned());
+        Some(hash::dirhash(name, version, self.sb.hash_seed()))
+    }
+
+    fn max_dx_levels(&self) -> usize {
+        if self.sb.has_incompat(INCOMPAT_LARGEDIR) {
+            3
+        } else {
+            2
+        }
+    }
+
+    /// Walks the index down to the leaf block for the hash.
+    fn dx_probe(&mut self, dir: &Inode, hash: u32) -> VfsResult<Vec<Frame>> {
+        let (_, root) = self.read_dir_block(dir, 0)?;
+        let levels = root[ROOT_INFO + 6] as usize;
+        if levels >= self.max_dx_levels() {
+            warn!("ext4: too deep index of directory {}", dir.ino);
+            return Err(VfsError::InvalidData);
+        }
+        let mut frames = Vec::new();
+        let mut block = DxBlock {
+            data: root,
+            base: ROOT_INFO + 8,
+        };
+        let mut lblk = 0;
+        loop {
+            // the entries and the tail must be in the block, which is written
+            // back with them when the index changes
+            if block.limit() != self.dx_limit(block.base)
+                || block.count() == 0
+                || block.count() > block.limit()
+            {
+                warn!("ext4: corrupted index of directory {}", dir.ino);
+                return Err(VfsError::Io);
+            }
+            let at = block.find(hash);
+            frames.push(Frame {
+                lblk,
+                base: block.base,
+                at,
+            });
+            lblk = block.block(at);
+            if frames.len() > levels {
+                return Ok(frames);
+            }
+            let (_, data) = self.read_dir_block(dir, lblk)?;
+            block = DxBlock { data, base: 8 };
+        }
+    }
+
+    /// The leaf block the frames lead to.
+    fn dx_leaf(&mut self, dir: &Inode, frames: &[Frame]) -> VfsResult<u32> {
+        let last = frames.last().unwrap();
+        Ok(self.read_dx(dir, last)?.1.block(last.at))
+    }
+
+    /// The leaf block after the frames, if the hashes of its entries may
+    /// continue `hash`, moving the frames to it.
+    fn dx_next_leaf(
+        &mut self,
+        dir: &Inode,
+        frames: &mut [Frame],
+        hash: u32,
+    ) -> VfsResult<Option<u32>> {
+        // find the deepest level that has a next entry
+        let mut level = frames.len();
+        let mut next_hash = None;
+        while level > 0 {
+            level -= 1;
+            let frame = &frames[level];
+            let (_, data) = self.read_dir_block(dir, frame.lblk)?;
+            let block = DxBlock {
+                data,
+                base: frame.base,
+            };
+            if frame.at + 1 < block.count() {
+                next_hash = Some(block.hash(frame.at + 1));
+                frames[level].at += 1;
+                break;
+            }
+        }
+        let Some(next_hash) = next_hash else {
+            return Ok(None);
+        };
+        if next_hash & !1 != hash {
+            return Ok(None);
+        }
+        // follow the first entries down to the leaf
+        for i in level..frames.len() {
+            let (_, data) = self.read_dir_block(dir, frames[i].lblk)?;
+            let block = DxBlock {
+                data,
+                base: frames[i].base,
+            };
+            let child = block.block(frames[i].at);
+            if i + 1 == frames.len() {
+                return Ok(Some(child));
+            }
+            frames[i + 1] = Frame {
+                lblk: child,
+                base: 8,
+                at: 0,
+            };
+        }
+        Ok(None)
+    }
+
+    /// Finds the entry with the name in the leaf block.
+    fn find_in_block(&mut self, dir: &Inode, lblk: u32, name: &[u8]) -> VfsResult<Option<Found>> {
+        let (_, data) = self.read_dir_block(dir, lblk)?;
+        let found = self
+            .leaf_entries(dir, &data)?
+            .into_iter()
+            .find(|e| e.ino != 0 && e.name(&data) == name)
+            .map(|e| Found {
+                lblk,
+                off: e.off,
+                ino: e.ino,
+            });
+        Ok(found)
+    }
+
+    fn dir_find(&mut self, dir: &Inode, name: &[u8]) -> VfsResult<Option<Found>> {
+        if name == b"." || name == b".." {
+            // always in the first block, which is the root of an index
+            return self.find_in_block(dir, 0, name);
+        }
+        if self.is_indexed(dir) {
+            let (_, root) = self.read_dir_block(dir, 0)?;
+            if let Some(hash) = self.dx_hash(&root, name) {
+                let mut frames = self.dx_probe(dir, hash)?;
+                let mut leaf = Some(self.dx_leaf(dir, &frames)?);
+                while let Some(lblk) = leaf {
+                    if let Some(found) = self.find_in_block(dir, lblk, name)? {
+                        return Ok(Some(found));
+                    }
+                    leaf = self.dx_next_leaf(dir, &mut frames, hash)?;
+                }
+                return Ok(None);
+            }
+        }
+        for lblk in 0..self.dir_blocks(dir) {
+            if let Some(found) = self.find_in_block(dir, lblk, name)? {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the inode of the entry with the name in the directory.
+    pub fn dir_lookup(&mut self, dir: &Inode, name: &str) -> VfsResult<Option<u32>> {
+        if name.len() > MAX_NAME_LEN {
+            return Err(VfsError::InvalidInput);
+        }
+        Ok(self.dir_find(dir, name.as_bytes())?.map(|f| f.ino))
+    }
+
+    /// Calls `f` with the name, the inode and the type of the entries of the
+    /// directory, in the order of the blocks, until it returns `false`.
+    pub fn dir_iter(
+        &mut self,
+        dir: &Inode,
+        mut f: impl FnMut(&[u8], u32, Option<VfsNodeType>) -> bool,
+    ) -> VfsResult {
+        for lblk in 0..self.dir_blocks(dir) {
+            let (_, data) = self.read_dir_block(dir, lblk)?;
+            for e in self.leaf_entries(dir, &data)? {
+                if e.ino == 0 {
+                    continue;
+                }
+                let ty = if self.has_filetype() {
+                    node_type(e.file_type)
+                } else {
+                    None
+                };
+                if !f(e.name(&data), e.ino, ty) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the directory has no entries other than `.` and `..`.
+    pub fn dir_is_empty(&mut self, dir: &Inode) -> VfsResult<bool> {
+        let mut empty = true;
+        self.dir_iter(dir, |name, _, _| {
+            empty = name == b"." || name == b"..";
+            empty
+        })?;
+        Ok(empty)
+    }
+
+    /// Fills the first block of a new directory with `.` and `..`.
+    pub fn dir_init(&mut self, dir: &mut Inode, parent: u32) -> VfsResult {
+        let (_, pblk) = self.dir_append_block(dir)?;
+        let mut data = vec![0; self.block_size];
+        let ty = if self.has_filetype() { 2 } else { 0 };
+        write_entry(&mut data, 0, dir.ino, 12, b".", ty);
+        write_entry(&mut data, 12, parent, self.leaf_space() - 12, b"..", ty);
+        self.write_leaf(dir, pblk, data)
+    }
+
+    /// Adds a block at the end of the directory, returns its logical and
+    /// physical numbers.
+    fn dir_append_block(&mut self, dir: &mut Inode) -> VfsResult<(u32, u64)> {
+        if dir.flags() & EXTENTS_FL == 0 {
+            return Err(VfsError::Unsupported); // block maps can't grow
+        }
+        let lblk = self.dir_blocks(dir);
+        let mut tree = ExtentTree::load(self, dir)?;
+        let goal = tree.extents.last().map_or(0, |e| e.pblk + e.len as u64);
+        let (pblk, _) = self.alloc_blocks(goal, 1)?;
+        tree.insert(Extent {
+            lblk,
+            pblk,
+            len: 1,
+            uninit: false,
+        });
+        if let Err(e) = tree.store(self, dir) {
+            self.release_blocks(pblk, 1)?;
+            return Err(e);
+        }
+        let sectors = dir.sectors(self.block_size) + (self.block_size / 512) as u64;
+        dir.set_sectors(sectors);
+        dir.set_size(dir.size() + self.block_size as u64);
+        let mut data = vec![0; self.block_size];
+        set_rec_len(&mut data, 0, self.leaf_space());
+        self.cache.zeroed(pblk);
+        self.write_leaf(dir, pblk, data)?;
+        Ok((lblk, pblk))
+    }
+
+    /// Tries to add the entry to the leaf block, returns whether it fits.
+    fn add_to_leaf(
+        &mut self,
+        dir: &Inode,
+        lblk: u32,
+        name: &[u8],
+        ino: u32,
+        ty: u8,
+    ) -> VfsResult<bool> {
+        let (pblk, mut data) = self.read_dir_block(dir, lblk)?;
+        let needed = entry_size(name.len());
+        for e in self.leaf_entries(dir, &data)? {
+            if e.rec_len - e.used() < needed {
+                continue;
+            }
+            let off = if e.ino == 0 {
+                e.off
+            } else {
+                set_rec_len(&mut data, e.off, e.used());
+                e.off + e.used()
+            };
+            write_entry(&mut data, off, ino, e.rec_len - (off - e.off), name, ty);
+            self.write_leaf(dir, pblk, data)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Adds an entry to the directory, the name must not exist.
+    pub fn dir_add(&mut self, dir: &mut Inode, name: &str, ino: u32, ty: VfsNodeType) -> VfsResult {
+        let name = name.as_bytes();
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(VfsError::InvalidInput);
+        }
+        let ty = if self.has_filetype() {
+            dirent_type(ty)
+        } else {
+            0
+        };
+        if self.is_indexed(dir) {
+            let (_, root) = self.read_dir_block(dir, 0)?;
+            if let Some(hash) = self.dx_hash(&root, name) {
+                return self.dx_add(dir, name, hash, ino, ty);
+            }
+            // an unknown hash: keep the index, only as linear blocks
+        }
+        let blocks = self.dir_blocks(dir);
+        for lblk in 0..blocks {
+            if self.add_to_leaf(dir, lblk, name, ino, ty)? {
+                return Ok(());
+            }
+        }
+        if blocks == 1 && dir.flags() & INDEX_FL == 0 && self.sb.has_compat(COMPAT_DIR_INDEX) {
+            self.make_indexed(dir)?;
+            return self.dir_add_indexed(dir, name, ino, ty);
+        }
+        let (lblk, _) = self.dir_append_block(dir)?;
+        if !self.add_to_leaf(dir, lblk, name, ino, ty)? {
+            return Err(VfsError::InvalidData);
+        }
+        Ok(())
+    }
+
+    fn dir_add_indexed(&mut self, dir: &mut Inode, name: &[u8], ino: u32, ty: u8) -> VfsResult {
+        let (_, root) = self.read_dir_block(dir, 0)?;
+        let hash = self.dx_hash(&root, name).ok_or(VfsError::InvalidData)?;
+        self.dx_add(dir, name, hash, ino, ty)
+    }
+
+    /// Turns a linear directory of one full block into an indexed one: its
+    /// entries are moved to a new leaf block, and the first block becomes the
+    /// root of the index.
+    fn make_indexed(&mut self, dir: &mut Inode) -> VfsResult {
+        let (root_pblk, mut root) = self.read_dir_block(dir, 0)?;
+        let list = self.leaf_entries(dir, &root)?;
+        if list.len() < 2 || list[0].name(&root) != b"." || list[1].name(&root) != b".." {
+            return Err(VfsError::InvalidData);
+        }
+        let (leaf_lblk, leaf_pblk) = self.dir_append_block(dir)?;
+
+        // move the entries after `..` to the leaf
+        let mut leaf = vec![0; self.block_size];
+        let mut off = 0;
+        let live: Vec<Entry> = list[2..].iter().filter(|e| e.ino != 0).copied().collect();
+        for (i, e) in live.iter().enumerate() {
+            let size = if i + 1 == live.len() {
+                self.leaf_space() - off
+            } else {
+                entry_size(e.name_len)
+            };
+            write_entry(&mut leaf, off, e.ino, size, e.name(&root), e.file_type);
+            off += size;
+        }
+        if live.is_empty() {
+            set_rec_len(&mut leaf, 0, self.leaf_space());
+        }
+        self.write_leaf(dir, leaf_pblk, leaf)?;
+
+        let dotdot = list[1].off;
+        root[dotdot + 12..].fill(0);
+        set_rec_len(&mut root, dotdot, self.block_size - dotdot);
+        root[ROOT_INFO + 4] = self.sb.def_hash_version();
+        root[ROOT_INFO + 5] = 8;
+        root[ROOT_INFO + 6] = 0;
+        let base = ROOT_INFO + 8;
+        let mut dx = DxBlock { data: root, base };
+        let limit = self.dx_limit(base);
+        put16(&mut dx.data, base, limit as u16);
+        dx.set_count(1);
+        dx.set_entry(0, 0, leaf_lblk);
+        self.write_dx(dir, root_pblk, dx)?;
+        dir.set_flags(dir.flags() | INDEX_FL);
+        Ok(())
+    }
+
+    /// The number of index entries in a block with them at `base`.
+    fn dx_limit(&self, base: usize) -> usize {
+        let tail = if self.csum_seed.is_some() { 8 } else { 0 };
+        (self.block_size - base - tail) / 8
+    }
+
+    fn read_dx(&mut self, dir: &Inode, frame: &Frame) -> VfsResult<(u64, DxBlock)> {
+        let (pblk, data) = self.read_dir_block(dir, frame.lblk)?;
+        Ok((
+            pblk,
+            DxBlock {
+                data,
+                base: frame.base,
+            },
+        ))
+    }
+
+    /// Adds an entry to an indexed directory, splitting the leaf block and
+    /// the index blocks as needed.
+    fn dx_add(&mut self, dir: &mut Inode, name: &[u8], hash: u32, ino: u32, ty: u8) -> VfsResult {
+        let mut frames = self.dx_probe(dir, hash)?;
+        let leaf = self.dx_leaf(dir, &frames)?;
+        if self.add_to_leaf(dir, leaf, name, ino, ty)? {
+            return Ok(());
+        }
+        let depth = frames.len() - 1;
+        self.dx_make_room(dir, &mut frames, depth)?;
+
+        // split the leaf by the hashes of its entries
+        let (leaf_pblk, data) = self.read_dir_block(dir, leaf)?;
+        let (_, root) = self.read_dir_block(dir, 0)?;
+        let mut list: Vec<(u32, Entry)> = Vec::new();
+        for e in self.leaf_entries(dir, &data)? {
+            if e.ino != 0 {
+                let hash = self.dx_hash(&root, e.name(&data)).unwrap();
+                list.push((hash, e));
+            }
+        }
+        list.sort_by_key(|(hash, _)| *hash);
+        let half = self.block_size / 2;
+        let mut moved = 0;
+        let mut size = 0;
+        for (_, e) in list.iter().rev() {
+            let entry = entry_size(e.name_len);
+            if size + entry / 2 > half {
+                break;
+            }
+            size += entry;
+            moved += 1;
+        }
+        let split = (list.len() - moved).clamp(1, list.len() - 1);
+        let split_hash = list[split].0;
+        let continued = (split_hash == list[split - 1].0) as u32;
+
+        let (new_lblk, new_pblk) = self.dir_append_block(dir)?;
+        let space = self.leaf_space();
+        let fill = |entries: &[(u32, Entry)]| {
+            let mut block = vec![0; self.block_size];
+            let mut off = 0;
+            for (i, (_, e)) in entries.iter().enumerate() {
+                let size = if i + 1 == entries.len() {
+                    space - off
+                } else {
+                    entry_size(e.name_len)
+                };
+                write_entry(&mut block, off, e.ino, size, e.name(&data), e.file_type);
+                off += size;
+            }
+            block
+        };
+        let (low, high) = (fill(&list[..split]), fill(&list[split..]));
+        self.write_leaf(dir, leaf_pblk, low)?;
+        self.write_leaf(dir, new_pblk, high)?;
+
+        let last = frames.last().unwrap().clone();
+        let (pblk, mut dx) = self.read_dx(dir, &last)?;
+        dx.insert(last.at + 1, split_hash | continued, new_lblk);
+        self.write_dx(dir, pblk, dx)?;
+
+        let target = if hash >= split_hash { new_lblk } else { leaf };
+        if !self.add_to_leaf(dir, target, name, ino, ty)? {
+            return Err(VfsError::StorageFull);
+        }
+        Ok(())
+    }
+
+    /// Makes room for an entry in the index block of the frame at `level`,
+    /// by splitting it or adding a level to the index.
+    fn dx_make_room(
+        &mut self,
+        dir: &mut Inode,
+        frames: &mut Vec<Frame>,
+        level: usize,
+    ) -> VfsResult {
+        let (pblk, mut dx) = self.read_dx(dir, &frames[level])?;
+        if dx.count() < dx.limit() {
+            return Ok(());
+        }
+        if level == 0 {
+            // move the root entries to a new index block below it
+            if frames.len() >= self.max_dx_levels() {
+                warn!("ext4: index of directory {} is full", dir.ino);
+                return Err(VfsError::StorageFull);
+            }
+            let (node_lblk, node_pblk) = self.dir_append_block(dir)?;
+            let mut node = DxBlock {
+                data: vec![0; self.block_size],
+                base: 8,
+            };
+            set_rec_len(&mut node.data, 0, self.block_size);
+            let limit = self.dx_limit(8);
+            put16(&mut node.data, 8, limit as u16);
+            let count = dx.count();
+            node.data[8 + 4..8 + count * 8]
+                .copy_from_slice(&dx.data[dx.base + 4..dx.base + count * 8]);
+            node.set_count(count);
+            self.write_dx(dir, node_pblk, node)?;
+
+            dx.set_count(1);
+            dx.set_entry(0, 0, node_lblk);
+            dx.data[ROOT_INFO + 6] += 1;
+            self.write_dx(dir, pblk, dx)?;
+            let at = frames[0].at;
+            frames[0].at = 0;
+            frames.insert(
+                1,
+                Frame {
+                    lblk: node_lblk,
+                    base: 8,
+                    at,
+                },
+            );
+            return Ok(());
+        }
+
+        // split the index block, the parent gets an entry for the new half
+        self.dx_make_room(dir, frames, level - 1)?;
+        let (pblk, mut dx) = self.read_dx(dir, &frames[level])?;
+        let count = dx.count();
+        let keep = count / 2;
+        let split_hash = dx.hash(keep);
+        let (node_lblk, node_pblk) = self.dir_append_block(dir)?;
+        let mut node = DxBlock {
+            data: vec![0; self.block_size],
+            base: 8,
+        };
+        set_rec_len(&mut node.data, 0, self.block_size);
+        put16(&mut node.data, 8, dx.limit() as u16);
+        node.data[8 + 4..8 + (count - keep) * 8]
+            .copy_from_slice(&dx.data[dx.base + keep * 8 + 4..dx.base + count * 8]);
+        node.set_count(count - keep);
+        self.write_dx(dir, node_pblk, node)?;
+        dx.set_count(keep);
+        self.write_dx(dir, pblk, dx)?;
+
+        let parent = frames[level - 1].clone();
+        let (parent_pblk, mut parent_dx) = self.read_dx(dir, &parent)?;
+        parent_dx.insert(parent.at + 1, split_hash, node_lblk);
+        self.write_dx(dir, parent_pblk, parent_dx)?;
+        if frames[level].at >= keep {
+            frames[level - 1].at += 1;
+            frames[level] = Frame {
+                lblk: node_lblk,
+                base: 8,
+                at: frames[level].at - keep,
+            };
+        }
+        Ok(())
+    }
+
+    /// Removes the entry with the name, returns the inode it referred to.
+    pub fn dir_remove(&mut self, dir: &Inode, name: &str) -> VfsResult<u32> {
+        let found = self
+            .dir_find(dir, name.as_bytes())?
+            .ok_or(VfsError::NotFound)?;
+        let (pblk, mut data) = self.read_dir_block(dir, found.lblk)?;
+        let list = self.leaf_entries(dir, &data)?;
+        let i = list.iter().position(|e| e.off == found.off).unwrap();
+        if i > 0 && list[i - 1].off + list[i - 1].rec_len == found.off {
+            let prev = list[i - 1];
+            set_rec_len(&mut data, prev.off, prev.rec_len + list[i].rec_len);
+        } else {
+            put32(&mut data, found.off, 0);
+        }
+        self.write_leaf(dir, pblk, data)?;
+        Ok(found.ino)
+    }
+
+    /// Points the entry with the name to another inode.
+    pub fn dir_replace(&mut self, dir: &Inode, name: &str, ino: u32, ty: VfsNodeType) -> VfsResult {
+        let found = self
+            .dir_find(dir, name.as_bytes())?
+            .ok_or(VfsError::NotFound)?;
+        let (pblk, mut data) = self.read_dir_block(dir, found.lblk)?;
+        put32(&mut data, found.off, ino);
+        if self.has_filetype() {
+            data[found.off + 7] = dirent_type(ty);
+        }
+        if found.lblk == 0 && self.is_indexed(dir) {
+            // `..` in the root of the index
+            let base = ROOT_INFO + 8;
+            return self.write_dx(dir, pblk, DxBlock { data, base });
+        }
+        self.write_leaf(dir, pblk, data)
+    }
+}
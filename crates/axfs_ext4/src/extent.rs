@@ -0,0 +1,495 @@
+//! The mapping of the logical blocks of a file to the blocks on the disk.
+//!
+//! Files use extent trees, rooted in `i_block`. To change a tree, its
+//! extents are loaded into a sorted list, changed there, and the tree is
+//! rebuilt with fully packed nodes, reusing the old node blocks, so only the
+//! nodes whose content changes are written. Files of ext2/3 with block maps
+//! can be read and deleted, but not written.
+
+use alloc::{vec, vec::Vec};
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use crate::crc::crc32c;
+use crate::fs::State;
+use crate::layout::*;
+
+const EXTENT_MAGIC: u16 = 0xf30a;
+/// The size of the header, an index entry and an extent.
+const ENTRY_SIZE: usize = 12;
+/// The number of entries in the root in `i_block`.
+const ROOT_ENTRIES: usize = (I_BLOCK_SIZE - ENTRY_SIZE) / ENTRY_SIZE;
+/// The longest initialized extent, a longer length marks it uninitialized.
+const INIT_MAX_LEN: u32 = 32768;
+const UNINIT_MAX_LEN: u32 = 32767;
+/// The deepest tree that is read.
+const MAX_DEPTH: u16 = 5;
+/// The number of blocks mapped directly in a block map.
+const DIRECT_BLOCKS: u64 = 12;
+
+/// A run of contiguous blocks of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Extent {
+    /// The first logical block.
+    pub lblk: u32,
+    /// The first block on the disk.
+    pub pblk: u64,
+    pub len: u32,
+    /// Allocated but not written, reads as zeros.
+    pub uninit: bool,
+}
+
+impl Extent {
+    fn parse(raw: &[u8]) -> Self {
+        let len = le16(raw, 4) as u32;
+        let pblk = le32(raw, 8) as u64 | (le16(raw, 6) as u64) << 32;
+        let (len, uninit) = if len > INIT_MAX_LEN {
+            (len - INIT_MAX_LEN, true)
+        } else {
+            (len, false)
+        };
+        Self {
+            lblk: le32(raw, 0),
+            pblk,
+            len,
+            uninit,
+        }
+    }
+
+    fn write(&self, raw: &mut [u8]) {
+        let len = if self.uninit {
+            self.len + INIT_MAX_LEN
+        } else {
+            self.len
+        };
+        put32(raw, 0, self.lblk);
+        put16(raw, 4, len as u16);
+        put16(raw, 6, (self.pblk >> 32) as u16);
+        put32(raw, 8, self.pblk as u32);
+    }
+
+    /// The logical block after the extent.
+    pub fn end(&self) -> u64 {
+        self.lblk as u64 + self.len as u64
+    }
+
+    fn max_len(&self) -> u32 {
+        if self.uninit {
+            UNINIT_MAX_LEN
+        } else {
+            INIT_MAX_LEN
+        }
+    }
+
+    /// Whether `next` continues this extent on the disk and can be merged.
+    fn can_merge(&self, next: &Self) -> bool {
+        self.end() == next.lblk as u64
+            && self.pblk + self.len as u64 == next.pblk
+            && self.uninit == next.uninit
+            && self.len + next.len <= self.max_len()
+    }
+}
+
+struct Header {
+    entries: usize,
+    max: usize,
+    depth: u16,
+}
+
+impl Header {
+    fn parse(raw: &[u8]) -> VfsResult<Self> {
+        let header = Self {
+            entries: le16(raw, 2) as usize,
+            max: le16(raw, 4) as usize,
+            depth: le16(raw, 6),
+        };
+        let capacity = (raw.len() - ENTRY_SIZE) / ENTRY_SIZE;
+        if le16(raw, 0) != EXTENT_MAGIC
+            || header.entries > header.max
+            || header.max > capacity
+            || header.depth > MAX_DEPTH
+        {
+            warn!("ext4: bad extent header");
+            return Err(VfsError::InvalidData);
+        }
+        Ok(header)
+    }
+
+    fn write(&self, raw: &mut [u8]) {
+        put16(raw, 0, EXTENT_MAGIC);
+        put16(raw, 2, self.entries as u16);
+        put16(raw, 4, self.max as u16);
+        put16(raw, 6, self.depth);
+        put32(raw, 8, 0);
+    }
+}
+
+fn index_child(raw: &[u8]) -> u64 {
+    le32(raw, 4) as u64 | (le16(raw, 8) as u64) << 32
+}
+
+fn entry(node: &[u8], i: usize) -> &[u8] {
+    &node[ENTRY_SIZE * (i + 1)..ENTRY_SIZE * (i + 2)]
+}
+
+/// Makes `i_block` the root of an empty extent tree.
+pub(crate) fn init_root(inode: &mut Inode) {
+    let root = inode.i_block_mut();
+    root.fill(0);
+    Header {
+        entries: 0,
+        max: ROOT_ENTRIES,
+        depth: 0,
+    }
+    .write(root);
+    inode.set_flags(inode.flags() | EXTENTS_FL);
+}
+
+/// The offset of the checksum in a node block of `max` entries.
+fn tail_offset(max: usize) -> usize {
+    ENTRY_SIZE * (max + 1)
+}
+
+/// The extents of a file, loaded from its tree to be changed.
+pub(crate) struct ExtentTree {
+    pub extents: Vec<Extent>,
+    /// The blocks of the nodes of the tree.
+    nodes: Vec<u64>,
+}
+
+impl State {
+    /// Reads a node block of the extent tree of the inode.
+    fn read_extent_node(&mut self, block: u64, seed: Option<u32>) -> VfsResult<Vec<u8>> {
+        if block < self.sb.first_data_block() || block >= self.sb.blocks_count() {
+            warn!("ext4: bad extent node {}", block);
+            return Err(VfsError::InvalidData);
+        }
+        let node = self.cache.read(block)?.to_vec();
+        let header = Header::parse(&node)?;
+        if let Some(seed) = seed {
+            let tail = tail_offset(header.max);
+            if tail + 4 <= node.len() && crc32c(seed, &node[..tail]) != le32(&node, tail) {
+                warn!("ext4: bad checksum of extent node {}", block);
+                return Err(VfsError::InvalidData);
+            }
+        }
+        Ok(node)
+    }
+
+    /// Maps the logical block of the inode. Returns the block on the disk,
+    /// the number of contiguous blocks mapped from it, and whether they are
+    /// uninitialized, or `None` for a hole.
+    pub fn map_block(&mut self, inode: &Inode, lblk: u32) -> VfsResult<Option<(u64, u32, bool)>> {
+        if inode.flags() & EXTENTS_FL == 0 {
+            return Ok(self.map_indirect(inode, lblk)?.map(|pblk| (pblk, 1, false)));
+        }
+        let seed = self.inode_seed(inode);
+        let mut node = inode.i_block().to_vec();
+        loop {
+            let header = Header::parse(&node)?;
+            // the last entry starting at or before `lblk`
+            let found = (0..header.entries)
+                .take_while(|&i| le32(entry(&node, i), 0) <= lblk)
+                .last();
+            let Some(i) = found else {
+                return Ok(None);
+            };
+            if header.depth == 0 {
+                let extent = Extent::parse(entry(&node, i));
+                if (lblk as u64) < extent.end() {
+                    let offset = lblk - extent.lblk;
+                    let mapped = (extent.pblk + offset as u64, extent.len - offset);
+                    return Ok(Some((mapped.0, mapped.1, extent.uninit)));
+                }
+                return Ok(None);
+            }
+            node = self.read_extent_node(index_child(entry(&node, i)), seed)?;
+        }
+    }
+
+    /// Maps the logical block of an inode with a block map.
+    fn map_indirect(&mut self, inode: &Inode, lblk: u32) -> VfsResult<Option<u64>> {
+        let per_block = (self.block_size / 4) as u64;
+        let mut lblk = lblk as u64;
+        let (slot, levels) = if lblk < DIRECT_BLOCKS {
+            (lblk as usize, 0)
+        } else if lblk - DIRECT_BLOCKS < per_block {
+            lblk -= DIRECT_BLOCKS;
+            (12, 1)
+        } else if lblk - DIRECT_BLOCKS - per_block < per_block * per_block {
+            lblk -= DIRECT_BLOCKS + per_block;
+            (13, 2)
+        } else {
+            lblk -= DIRECT_BLOCKS + per_block + per_block * per_block;
+            (14, 3)
+        };
+        let mut block = le32(inode.i_block(), slot * 4) as u64;
+        for level in (0..levels).rev() {
+            if block == 0 {
+                return Ok(None);
+            }
+            let index = (lblk / per_block.pow(level)) % per_block;
+            block = le32(self.cache.read(block)?, index as usize * 4) as u64;
+        }
+        Ok((block != 0).then_some(block))
+    }
+
+    /// Frees all blocks of an inode with a block map, including the blocks
+    /// of the map.
+    pub fn free_indirect(&mut self, inode: &mut Inode) -> VfsResult {
+        let slots: Vec<u64> = (0..15)
+            .map(|i| le32(inode.i_block(), i * 4) as u64)
+            .collect();
+        for (i, &block) in slots.iter().enumerate() {
+            let levels = i.saturating_sub(11) as u32;
+            self.free_indirect_tree(block, levels)?;
+        }
+        inode.i_block_mut().fill(0);
+        Ok(())
+    }
+
+    fn free_indirect_tree(&mut self, block: u64, levels: u32) -> VfsResult {
+        if block == 0 {
+            return Ok(());
+        }
+        if levels > 0 {
+            let children: Vec<u64> = self
+                .cache
+                .read(block)?
+                .chunks_exact(4)
+                .map(|raw| le32(raw, 0) as u64)
+                .collect();
+            for child in children {
+                self.free_indirect_tree(child, levels - 1)?;
+            }
+        }
+        self.free_blocks_later(block, 1);
+        Ok(())
+    }
+}
+
+impl ExtentTree {
+    /// Loads the extents of the inode, which must use extents.
+    pub fn load(state: &mut State, inode: &Inode) -> VfsResult<Self> {
+        let mut tree = Self {
+            extents: Vec::new(),
+            nodes: Vec::new(),
+        };
+        let seed = state.inode_seed(inode);
+        tree.load_node(state, inode.i_block().to_vec(), None, seed)?;
+        Ok(tree)
+    }
+
+    fn load_node(
+        &mut self,
+        state: &mut State,
+        node: Vec<u8>,
+        depth: Option<u16>,
+        seed: Option<u32>,
+    ) -> VfsResult {
+        let header = Header::parse(&node)?;
+        if depth.is_some_and(|depth| depth != header.depth) {
+            warn!("ext4: bad depth of extent node");
+            return Err(VfsError::InvalidData);
+        }
+        for i in 0..header.entries {
+            let raw = entry(&node, i);
+            if header.depth == 0 {
+                let extent = Extent::parse(raw);
+                if extent.len == 0
+                    || self
+                        .extents
+                        .last()
+                        .is_some_and(|last| last.end() > extent.lblk as u64)
+                {
+                    warn!("ext4: bad extent order");
+                    return Err(VfsError::InvalidData);
+                }
+                self.extents.push(extent);
+            } else {
+                let child = index_child(raw);
+                self.nodes.push(child);
+                let child_node = state.read_extent_node(child, seed)?;
+                self.load_node(state, child_node, Some(header.depth - 1), seed)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The index of the extent containing `lblk`, or where an extent starting
+    /// at `lblk` would be inserted.
+    pub fn find(&self, lblk: u32) -> Result<usize, usize> {
+        let i = self.extents.partition_point(|e| e.end() <= lblk as u64);
+        match self.extents.get(i) {
+            Some(e) if e.lblk <= lblk => Ok(i),
+            _ => Err(i),
+        }
+    }
+
+    /// Adds an extent for blocks not mapped yet.
+    pub fn insert(&mut self, extent: Extent) {
+        let i = self.extents.partition_point(|e| e.lblk < extent.lblk);
+        self.extents.insert(i, extent);
+        self.merge();
+    }
+
+    /// Marks the blocks `lblk..lblk + len` as initialized, splitting the
+    /// uninitialized extents they are in.
+    pub fn set_init(&mut self, lblk: u32, len: u32) {
+        let (start, end) = (lblk as u64, lblk as u64 + len as u64);
+        let mut extents = Vec::with_capacity(self.extents.len() + 2);
+        for &e in &self.extents {
+            if !e.uninit || e.end() <= start || e.lblk as u64 >= end {
+                extents.push(e);
+                continue;
+            }
+            let from = start.max(e.lblk as u64);
+            let to = end.min(e.end());
+            let piece = |a: u64, b: u64, uninit| Extent {
+                lblk: a as u32,
+                pblk: e.pblk + (a - e.lblk as u64),
+                len: (b - a) as u32,
+                uninit,
+            };
+            if from > e.lblk as u64 {
+                extents.push(piece(e.lblk as u64, from, true));
+            }
+            extents.push(piece(from, to, false));
+            if to < e.end() {
+                extents.push(piece(to, e.end(), true));
+            }
+        }
+        self.extents = extents;
+        self.merge();
+    }
+
+    /// Drops the blocks from `blocks` on, and returns the blocks on the disk
+    /// to be freed.
+    pub fn truncate(&mut self, blocks: u32) -> Vec<(u64, u64)> {
+        let mut freed = Vec::new();
+        while let Some(last) = self.extents.last_mut() {
+            if last.end() <= blocks as u64 {
+                break;
+            }
+            if last.lblk >= blocks {
+                freed.push((last.pblk, last.len as u64));
+                self.extents.pop();
+            } else {
+                let keep = blocks - last.lblk;
+                freed.push((last.pblk + keep as u64, (last.len - keep) as u64));
+                last.len = keep;
+            }
+        }
+        freed
+    }
+
+    /// Merges the extents that continue each other.
+    fn merge(&mut self) {
+        let mut merged: Vec<Extent> = Vec::with_capacity(self.extents.len());
+        for &e in &self.extents {
+            match merged.last_mut() {
+                Some(last) if last.can_merge(&e) => last.len += e.len,
+                _ => merged.push(e),
+            }
+        }
+        self.extents = merged;
+    }
+
+    /// Writes the tree back to the inode and its node blocks, allocating or
+    /// freeing node blocks as needed. The blocks taken by the nodes are
+    /// counted in the inode.
+    pub fn store(self, state: &mut State, inode: &mut Inode) -> VfsResult {
+        let block_size = state.block_size;
+        let per_node = (block_size - ENTRY_SIZE) / ENTRY_SIZE;
+        let mut needed = 0;
+        let mut n = self.extents.len();
+        while n > ROOT_ENTRIES {
+            n = n.div_ceil(per_node);
+            needed += n;
+        }
+
+        // allocate all nodes first, so a failure leaves the tree unchanged
+        let mut free_nodes = self.nodes.clone();
+        let mut new_nodes = Vec::new();
+        let goal = self.extents.first().map_or(0, |e| e.pblk);
+        while free_nodes.len() + new_nodes.len() < needed {
+            match state.alloc_blocks(goal, 1) {
+                Ok((block, _)) => new_nodes.push(block),
+                Err(e) => {
+                    for &block in &new_nodes {
+                        state.release_blocks(block, 1)?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        free_nodes.reverse();
+
+        let seed = state.inode_seed(inode);
+        let mut level: Vec<[u8; ENTRY_SIZE]> = self
+            .extents
+            .iter()
+            .map(|e| {
+                let mut raw = [0; ENTRY_SIZE];
+                e.write(&mut raw);
+                raw
+            })
+            .collect();
+        let mut depth = 0;
+        let mut node = vec![0; block_size];
+        while level.len() > ROOT_ENTRIES {
+            let mut next = Vec::new();
+            for chunk in level.chunks(per_node) {
+                let (block, reused) = match free_nodes.pop() {
+                    Some(block) => (block, true),
+                    None => (new_nodes.pop().unwrap(), false),
+                };
+                node.fill(0);
+                Header {
+                    entries: chunk.len(),
+                    max: per_node,
+                    depth,
+                }
+                .write(&mut node);
+                for (i, raw) in chunk.iter().enumerate() {
+                    node[ENTRY_SIZE * (i + 1)..ENTRY_SIZE * (i + 2)].copy_from_slice(raw);
+                }
+                if let Some(seed) = seed {
+                    let tail = tail_offset(per_node);
+                    let csum = crc32c(seed, &node[..tail]);
+                    put32(&mut node, tail, csum);
+                }
+                if !reused || state.cache.read(block)? != node.as_slice() {
+                    state.cache.zeroed(block).copy_from_slice(&node);
+                }
+
+                let mut index = [0; ENTRY_SIZE];
+                index[..4].copy_from_slice(&chunk[0][..4]);
+                put32(&mut index, 4, block as u32);
+                put16(&mut index, 8, (block >> 32) as u16);
+                next.push(index);
+            }
+            level = next;
+            depth += 1;
+        }
+
+        let root = inode.i_block_mut();
+        root.fill(0);
+        Header {
+            entries: level.len(),
+            max: ROOT_ENTRIES,
+            depth,
+        }
+        .write(root);
+        for (i, raw) in level.iter().enumerate() {
+            root[ENTRY_SIZE * (i + 1)..ENTRY_SIZE * (i + 2)].copy_from_slice(raw);
+        }
+        for &block in &free_nodes {
+            state.free_blocks_later(block, 1);
+        }
+        let sectors_per_block = (block_size / 512) as u64;
+        let sectors = inode.sectors(block_size) + needed as u64 * sectors_per_block;
+        inode.set_sectors(sectors - self.nodes.len() as u64 * sectors_per_block);
+        Ok(())
+    }
+}
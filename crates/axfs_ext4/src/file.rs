@@ -0,0 +1,329 @@
+//! The data of files, and the freeing of inodes.
+//!
+//! An inode without links that is still open is put on the orphan list,
+//! which starts at the superblock and goes on through `i_dtime`, so that it's
+//! freed on the next mount if it was not closed before a crash.
+
+use alloc::vec;
+
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+
+use crate::extent::{init_root, Extent, ExtentTree};
+use crate::fs::State;
+use crate::layout::*;
+
+/// The longest extent that is allocated at once.
+const MAX_ALLOC: u64 = 32768;
+/// The largest number of blocks of a file.
+const MAX_BLOCKS: u64 = u32::MAX as u64;
+
+impl State {
+    /// Whether the inode is a symbolic link with the target in `i_block`.
+    fn is_fast_symlink(&self, inode: &Inode) -> bool {
+        inode.file_type() == VfsNodeType::SymLink
+            && inode.flags() & EXTENTS_FL == 0
+            && inode.size() < I_BLOCK_SIZE as u64
+    }
+
+    /// Makes a file use extents, it must have no blocks if it has a block
+    /// map as block maps are only read.
+    fn use_extents(&mut self, inode: &mut Inode) -> VfsResult {
+        if inode.flags() & EXTENTS_FL != 0 {
+            return Ok(());
+        }
+        if !self.sb.has_incompat(INCOMPAT_EXTENTS) || inode.i_block().iter().any(|&b| b != 0) {
+            return Err(VfsError::Unsupported);
+        }
+        init_root(inode);
+        Ok(())
+    }
+
+    pub fn read_data(&mut self, inode: &Inode, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let size = inode.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let buf = &mut buf[..len];
+        if self.is_fast_symlink(inode) {
+            let start = offset as usize;
+            buf.copy_from_slice(&inode.i_block()[start..start + len]);
+            return Ok(len);
+        }
+        let bs = self.block_size as u64;
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let in_block = pos % bs;
+            let n = match self.map_block(inode, (pos / bs) as u32)? {
+                Some((pblk, count, false)) => {
+                    let n = (len - done).min((count as u64 * bs - in_block) as usize);
+                    self.dev
+                        .read_at(pblk * bs + in_block, &mut buf[done..done + n])?;
+                    n
+                }
+                // a hole or uninitialized blocks
+                _ => {
+                    let n = (len - done).min((bs - in_block) as usize);
+                    buf[done..done + n].fill(0);
+                    n
+                }
+            };
+            done += n;
+        }
+        Ok(len)
+    }
+
+    /// Writes to the blocks of a file. The data is written to the disk now,
+    /// the inode must be written by the caller.
+    pub fn write_data(&mut self, inode: &mut Inode, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.check_writable()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let bs = self.block_size as u64;
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .filter(|end| end.div_ceil(bs) <= MAX_BLOCKS)
+            .ok_or(VfsError::InvalidInput)?;
+        self.use_extents(inode)?;
+
+        // map the blocks, allocating the holes
+        let mut tree = ExtentTree::load(self, inode)?;
+        let (first, last) = ((offset / bs) as u32, ((end - 1) / bs) as u32);
+        let mut runs = vec![]; // (lblk, pblk, len, fresh)
+        let mut allocated = vec![];
+        let mut lblk = first;
+        while lblk <= last {
+            let stop = last as u64 + 1;
+            let run = match tree.find(lblk) {
+                Ok(i) => {
+                    let e = tree.extents[i];
+                    let len = e.end().min(stop) - lblk as u64;
+                    (lblk, e.pblk + (lblk - e.lblk) as u64, len as u32, e.uninit)
+                }
+                Err(i) => {
+                    let until = tree
+                        .extents
+                        .get(i)
+                        .map_or(stop, |e| stop.min(e.lblk as u64));
+                    let goal = match i.checked_sub(1) {
+                        Some(prev) => {
+                            let prev = tree.extents[prev];
+                            prev.pblk + (lblk - prev.lblk) as u64
+                        }
+                        None => {
+                            self.group_first_block((inode.ino - 1) / self.sb.inodes_per_group())
+                        }
+                    };
+                    let want = (until - lblk as u64).min(MAX_ALLOC);
+                    let (pblk, len) = match self.alloc_blocks(goal, want) {
+                        Ok(found) => found,
+                        Err(e) => {
+                            self.release_all(&allocated)?;
+                            return Err(e);
+                        }
+                    };
+                    allocated.push((pblk, len));
+                    tree.insert(Extent {
+                        lblk,
+                        pblk,
+                        len: len as u32,
+                        uninit: false,
+                    });
+                    (lblk, pblk, len as u32, true)
+                }
+            };
+            runs.push(run);
+            lblk += run.2;
+        }
+        for &(lblk, _, len, fresh) in &runs {
+            if fresh {
+                tree.set_init(lblk, len);
+            }
+        }
+        let new_blocks: u64 = allocated.iter().map(|&(_, len)| len).sum();
+        let sectors = inode.sectors(self.block_size);
+        inode.set_sectors(sectors + new_blocks * (bs / 512));
+        if let Err(e) = tree.store(self, inode) {
+            inode.set_sectors(sectors);
+            self.release_all(&allocated)?;
+            return Err(e);
+        }
+
+        for (lblk, pblk, len, fresh) in runs {
+            let run_start = lblk as u64 * bs;
+            let from = offset.max(run_start);
+            let to = end.min(run_start + len as u64 * bs);
+            let data = &buf[(from - offset) as usize..(to - offset) as usize];
+            self.write_run(pblk, from - run_start, data, fresh)?;
+        }
+        if end > inode.size() {
+            inode.set_size(end);
+        }
+        let now = self.now();
+        inode.set_mtime(now);
+        inode.set_ctime(now);
+        Ok(buf.len())
+    }
+
+    fn release_all(&mut self, runs: &[(u64, u64)]) -> VfsResult {
+        for &(start, count) in runs {
+            self.release_blocks(start, count)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the data at `offset` in the blocks from `start`. The parts of
+    /// the blocks not written are zeroed if the blocks are `fresh`, or kept.
+    fn write_run(
+        &mut self,
+        start: u64,
+        mut offset: u64,
+        mut data: &[u8],
+        fresh: bool,
+    ) -> VfsResult {
+        let bs = self.block_size as u64;
+        while !data.is_empty() {
+            let block = start + offset / bs;
+            let in_block = (offset % bs) as usize;
+            let n = if in_block == 0 && data.len() as u64 >= bs {
+                let n = (data.len() as u64 / bs * bs) as usize;
+                self.dev.write_at(block * bs, &data[..n])?;
+                n
+            } else {
+                let n = (bs as usize - in_block).min(data.len());
+                let mut buf = vec![0; bs as usize];
+                if !fresh {
+                    self.dev.read_at(block * bs, &mut buf)?;
+                }
+                buf[in_block..in_block + n].copy_from_slice(&data[..n]);
+                self.dev.write_at(block * bs, &buf)?;
+                n
+            };
+            offset += n as u64;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    /// Changes the size of a file, freeing the blocks after it when it
+    /// shrinks. The inode must be written by the caller.
+    pub fn truncate_data(&mut self, inode: &mut Inode, size: u64) -> VfsResult {
+        self.check_writable()?;
+        let bs = self.block_size as u64;
+        if size.div_ceil(bs) > MAX_BLOCKS {
+            return Err(VfsError::InvalidInput);
+        }
+        if size < inode.size() && !self.is_fast_symlink(inode) {
+            let blocks = size.div_ceil(bs) as u32;
+            if inode.flags() & EXTENTS_FL != 0 {
+                let mut tree = ExtentTree::load(self, inode)?;
+                let mut freed = 0;
+                for (start, count) in tree.truncate(blocks) {
+                    self.free_blocks_later(start, count);
+                    freed += count;
+                }
+                let sectors = inode.sectors(self.block_size);
+                inode.set_sectors(sectors - freed * (bs / 512));
+                tree.store(self, inode)?;
+            } else if blocks == 0 {
+                self.free_indirect(inode)?;
+                let xattr_sectors = if inode.file_acl() != 0 { bs / 512 } else { 0 };
+                inode.set_sectors(xattr_sectors);
+            } else {
+                return Err(VfsError::Unsupported);
+            }
+            // the rest of the last block reads as zeros if the file grows
+            let tail = size % bs;
+            if tail != 0 {
+                if let Some((pblk, _, false)) = self.map_block(inode, (size / bs) as u32)? {
+                    let zeros = vec![0; (bs - tail) as usize];
+                    self.dev.write_at(pblk * bs + tail, &zeros)?;
+                }
+            }
+        }
+        inode.set_size(size);
+        let now = self.now();
+        inode.set_mtime(now);
+        inode.set_ctime(now);
+        Ok(())
+    }
+
+    /// Frees an inode without links, and its blocks.
+    pub fn free_inode(&mut self, ino: u32) -> VfsResult {
+        let mut inode = self.read_inode(ino)?;
+        if !self.is_fast_symlink(&inode) {
+            if inode.flags() & EXTENTS_FL != 0 {
+                let mut tree = ExtentTree::load(self, &inode)?;
+                for (start, count) in tree.truncate(0) {
+                    self.free_blocks_later(start, count);
+                }
+                // the blocks of the nodes are freed by `store`
+                tree.store(self, &mut inode)?;
+            } else {
+                self.free_indirect(&mut inode)?;
+            }
+        }
+        self.free_xattr_block(&mut inode)?;
+        inode.set_size(0);
+        inode.set_sectors(0);
+        inode.set_links(0);
+        inode.set_dtime(self.now().as_secs() as u32);
+        self.write_inode(&mut inode)?;
+        self.release_inode(ino, inode.is_dir())
+    }
+
+    /// Puts an inode without links on the orphan list, to be freed when it's
+    /// no longer open.
+    pub fn add_orphan(&mut self, inode: &mut Inode) {
+        inode.set_dtime(self.sb.last_orphan());
+        self.sb.set_last_orphan(inode.ino);
+        self.orphans.insert(inode.ino);
+    }
+
+    /// Takes an inode off the orphan list and frees it.
+    pub fn free_orphan(&mut self, ino: u32) -> VfsResult {
+        let next = self.read_inode(ino)?.dtime();
+        if self.sb.last_orphan() == ino {
+            self.sb.set_last_orphan(next);
+        } else {
+            let mut prev = self.sb.last_orphan();
+            for _ in 0..self.sb.inodes_count() {
+                if prev == 0 {
+                    break;
+                }
+                let mut inode = self.read_inode(prev)?;
+                if inode.dtime() == ino {
+                    inode.set_dtime(next);
+                    self.write_inode(&mut inode)?;
+                    break;
+                }
+                prev = inode.dtime();
+            }
+        }
+        self.free_inode(ino)
+    }
+
+    /// Frees the inodes left on the orphan list by a crash.
+    pub fn free_orphans(&mut self) -> VfsResult {
+        let mut ino = self.sb.last_orphan();
+        for _ in 0..self.sb.inodes_count() {
+            if ino == 0 {
+                break;
+            }
+            let mut inode = self.read_inode(ino)?;
+            let next = inode.dtime();
+            if inode.links() == 0 {
+                info!("ext4: freeing orphan inode {}", ino);
+                self.free_inode(ino)?;
+            } else {
+                inode.set_dtime(0);
+                self.write_inode(&mut inode)?;
+            }
+            ino = next;
+        }
+        self.sb.set_last_orphan(0);
+        self.commit()
+    }
+}
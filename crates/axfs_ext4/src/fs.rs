@@ -0,0 +1,423 @@
+//! The state of a mounted filesystem: the superblock, the group descriptors,
+//! the metadata cache and the journal, and the transactions over them.
+//!
+//! All metadata is changed through the cache in the running transaction.
+//! It's committed to the journal, and then written in place, on `fsync`,
+//! on unmounting or when it grows too large, so after a crash the disk is
+//! always as of the last commit. File data is written to the disk directly
+//! before the metadata referring to it is committed, as the `ordered` mode of
+//! Linux, and blocks freed in the running transaction are not reused until
+//! it's committed.
+
+use alloc::{collections::BTreeSet, sync::Arc, vec, vec::Vec};
+use core::time::Duration;
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use crate::cache::BlockCache;
+use crate::journal::Journal;
+use crate::layout::*;
+use crate::BlockDevice;
+
+pub(crate) struct State {
+    pub dev: Arc<dyn BlockDevice>,
+    pub sb: SuperBlock,
+    pub groups: Vec<GroupDesc>,
+    pub cache: BlockCache,
+    pub journal: Option<Journal>,
+    pub read_only: bool,
+    pub block_size: usize,
+    /// The seed of the checksums, if the filesystem has `metadata_csum`.
+    pub csum_seed: Option<u32>,
+    /// Inodes without links that are still open, to be freed when closed.
+    pub orphans: BTreeSet<u32>,
+    /// Groups whose descriptors or bitmaps are changed.
+    dirty_groups: BTreeSet<u32>,
+    /// Blocks freed in the running transaction, released when it's committed.
+    pending_free: Vec<(u64, u64)>,
+    next_generation: u32,
+    clock: fn() -> Duration,
+}
+
+impl State {
+    /// Reads the superblock and the group descriptors, and replays the
+    /// journal if the filesystem was not cleanly unmounted.
+    pub fn open(dev: Arc<dyn BlockDevice>, clock: fn() -> Duration) -> VfsResult<Self> {
+        let mut state = Self::load(dev.clone(), clock)?;
+        if let Some(journal) = &mut state.journal {
+            if journal.needs_recovery() {
+                info!("ext4: recovering the journal");
+                journal.recover(&*dev)?;
+                // the superblock and the descriptors may have been replayed
+                let journal = state.journal.take();
+                state = Self::load(dev, clock)?;
+                state.journal = journal;
+            }
+        }
+        if state.sb.has_incompat(INCOMPAT_RECOVER) && state.journal.is_none() {
+            warn!("ext4: the journal needs recovery, mounting read-only");
+            state.read_only = true;
+        }
+        Ok(state)
+    }
+
+    fn load(dev: Arc<dyn BlockDevice>, clock: fn() -> Duration) -> VfsResult<Self> {
+        let mut raw = vec![0; SUPERBLOCK_SIZE];
+        dev.read_at(SUPERBLOCK_OFFSET, &mut raw)?;
+        let sb = SuperBlock::parse(&raw)?;
+        let block_size = sb.block_size();
+        if sb.blocks_count() * block_size as u64 > dev.size() {
+            warn!("ext4: the filesystem is larger than the device");
+            return Err(VfsError::InvalidData);
+        }
+        let csum_seed = sb
+            .has_ro_compat(RO_COMPAT_METADATA_CSUM)
+            .then(|| sb.csum_seed());
+        let read_only = !sb.writable();
+        if read_only {
+            warn!("ext4: unsupported read-only features, mounting read-only");
+        }
+        let mut state = Self {
+            cache: BlockCache::new(dev.clone(), block_size),
+            dev,
+            sb,
+            groups: Vec::new(),
+            journal: None,
+            read_only,
+            block_size,
+            csum_seed,
+            orphans: BTreeSet::new(),
+            dirty_groups: BTreeSet::new(),
+            pending_free: Vec::new(),
+            next_generation: clock().as_nanos() as u32,
+            clock,
+        };
+        for group in 0..state.group_count() {
+            let (block, offset) = state.desc_location(group);
+            let size = state.sb.desc_size();
+            let data = state.cache.read(block)?;
+            let desc = GroupDesc::new(&data[offset..offset + size]);
+            state.groups.push(desc);
+        }
+        if state.sb.has_compat(COMPAT_HAS_JOURNAL) {
+            match Journal::open(&mut state)? {
+                Some(journal) => state.journal = Some(journal),
+                None => state.read_only = true,
+            }
+        }
+        Ok(state)
+    }
+
+    /// The current time, for timestamps.
+    pub fn now(&self) -> Duration {
+        (self.clock)()
+    }
+
+    /// Fails with [`VfsError::PermissionDenied`] if the filesystem is
+    /// mounted read-only.
+    pub fn check_writable(&self) -> VfsResult {
+        if self.read_only {
+            return Err(VfsError::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    pub fn group_count(&self) -> u32 {
+        let blocks = self.sb.blocks_count() - self.sb.first_data_block();
+        blocks.div_ceil(self.sb.blocks_per_group() as u64) as u32
+    }
+
+    pub fn group_first_block(&self, group: u32) -> u64 {
+        self.sb.first_data_block() + group as u64 * self.sb.blocks_per_group() as u64
+    }
+
+    /// The number of blocks in the group, the last one may be smaller.
+    pub fn group_blocks(&self, group: u32) -> u32 {
+        let end = self.sb.blocks_count() - self.group_first_block(group);
+        end.min(self.sb.blocks_per_group() as u64) as u32
+    }
+
+    pub fn group_of_block(&self, block: u64) -> u32 {
+        ((block - self.sb.first_data_block()) / self.sb.blocks_per_group() as u64) as u32
+    }
+
+    fn descs_per_block(&self) -> u32 {
+        (self.block_size / self.sb.desc_size()) as u32
+    }
+
+    /// Whether the group has a copy of the superblock.
+    pub fn has_super(&self, group: u32) -> bool {
+        if group == 0 {
+            return true;
+        }
+        if self.sb.has_compat(COMPAT_SPARSE_SUPER2) {
+            return self.sb.backup_bgs().contains(&group);
+        }
+        if group == 1 || !self.sb.has_ro_compat(RO_COMPAT_SPARSE_SUPER) {
+            return true;
+        }
+        let is_power_of = |base: u32| {
+            let mut n = base;
+            while n < group {
+                n *= base;
+            }
+            n == group
+        };
+        group % 2 == 1 && (is_power_of(3) || is_power_of(5) || is_power_of(7))
+    }
+
+    /// The number of blocks at the start of the group taken by the copies of
+    /// the superblock and the descriptors.
+    pub fn base_meta_blocks(&self, group: u32) -> u32 {
+        let has_super = self.has_super(group) as u32;
+        let per_block = self.descs_per_block();
+        if !self.sb.has_incompat(INCOMPAT_META_BG) {
+            if has_super == 0 {
+                return 0;
+            }
+            let gdt_blocks = self.group_count().div_ceil(per_block);
+            return 1 + gdt_blocks + self.sb.reserved_gdt_blocks();
+        }
+        if group < self.sb.first_meta_bg() * per_block {
+            if has_super == 0 {
+                return 0;
+            }
+            return 1 + self.sb.first_meta_bg() + self.sb.reserved_gdt_blocks();
+        }
+        let first = group / per_block * per_block;
+        let has_desc = group == first || group == first + 1 || group == first + per_block - 1;
+        has_super + has_desc as u32
+    }
+
+    /// The block and the offset in it of the descriptor of the group.
+    fn desc_location(&self, group: u32) -> (u64, usize) {
+        let per_block = self.descs_per_block();
+        let nr = group / per_block;
+        let offset = (group % per_block) as usize * self.sb.desc_size();
+        let sb_block = SUPERBLOCK_OFFSET / self.block_size as u64;
+        if !self.sb.has_incompat(INCOMPAT_META_BG) || nr < self.sb.first_meta_bg() {
+            return (sb_block + 1 + nr as u64, offset);
+        }
+        let first = nr * per_block;
+        let mut has_super = self.has_super(first) as u64;
+        if self.block_size == 1024 && nr == 0 && self.sb.first_data_block() == 0 {
+            has_super += 1;
+        }
+        (self.group_first_block(first) + has_super, offset)
+    }
+
+    /// Records that the descriptor or the bitmaps of the group are changed.
+    pub fn mark_group_dirty(&mut self, group: u32) {
+        self.dirty_groups.insert(group);
+    }
+
+    /// Writes the changed descriptors to their blocks, with the checksums of
+    /// their bitmaps.
+    fn flush_groups(&mut self) -> VfsResult {
+        for group in core::mem::take(&mut self.dirty_groups) {
+            let mut desc = self.groups[group as usize].clone();
+            if let Some(seed) = self.csum_seed {
+                if desc.flags() & BG_BLOCK_UNINIT == 0 {
+                    let len = self.sb.blocks_per_group() as usize / 8;
+                    let data = self.cache.read(desc.block_bitmap())?;
+                    desc.set_block_bitmap_csum(crate::crc::crc32c(seed, &data[..len]));
+                }
+                if desc.flags() & BG_INODE_UNINIT == 0 {
+                    let len = self.sb.inodes_per_group() as usize / 8;
+                    let data = self.cache.read(desc.inode_bitmap())?;
+                    desc.set_inode_bitmap_csum(crate::crc::crc32c(seed, &data[..len]));
+                }
+            }
+            desc.update_checksum(&self.sb, group);
+            let (block, offset) = self.desc_location(group);
+            let data = self.cache.write(block)?;
+            data[offset..offset + desc.raw().len()].copy_from_slice(desc.raw());
+            self.groups[group as usize] = desc;
+        }
+        Ok(())
+    }
+
+    /// Writes the superblock to its block in the running transaction.
+    fn flush_superblock(&mut self) -> VfsResult {
+        self.sb.update_checksum();
+        let block = SUPERBLOCK_OFFSET / self.block_size as u64;
+        let offset = SUPERBLOCK_OFFSET as usize % self.block_size;
+        let data = self.cache.write(block)?;
+        data[offset..offset + SUPERBLOCK_SIZE].copy_from_slice(self.sb.raw());
+        Ok(())
+    }
+
+    /// Returns the block and the offset in it of the inode.
+    fn inode_location(&self, ino: u32) -> VfsResult<(u64, usize)> {
+        if ino == 0 || ino > self.sb.inodes_count() {
+            return Err(VfsError::InvalidData);
+        }
+        let group = (ino - 1) / self.sb.inodes_per_group();
+        let index = ((ino - 1) % self.sb.inodes_per_group()) as usize;
+        let offset = index * self.sb.inode_size();
+        let table = self.groups[group as usize].inode_table();
+        let block = table + (offset / self.block_size) as u64;
+        Ok((block, offset % self.block_size))
+    }
+
+    pub fn read_inode(&mut self, ino: u32) -> VfsResult<Inode> {
+        let (block, offset) = self.inode_location(ino)?;
+        let size = self.sb.inode_size();
+        let data = self.cache.read(block)?;
+        let inode = Inode::new(ino, &data[offset..offset + size]);
+        if let Some(seed) = self.csum_seed {
+            if !inode.verify_checksum(seed) {
+                warn!("ext4: bad checksum of inode {}", ino);
+                return Err(VfsError::InvalidData);
+            }
+        }
+        Ok(inode)
+    }
+
+    pub fn write_inode(&mut self, inode: &mut Inode) -> VfsResult {
+        if let Some(seed) = self.csum_seed {
+            inode.update_checksum(seed);
+        }
+        let (block, offset) = self.inode_location(inode.ino)?;
+        let data = self.cache.write(block)?;
+        data[offset..offset + inode.raw().len()].copy_from_slice(inode.raw());
+        Ok(())
+    }
+
+    /// A new inode with the extra fields the filesystem wants.
+    pub fn new_inode(&mut self, ino: u32, mode: u16) -> Inode {
+        let extra_isize = match self.sb.want_extra_isize() {
+            0 => 32,
+            n => n,
+        };
+        let mut inode = Inode::empty(ino, self.sb.inode_size(), extra_isize);
+        let now = self.now();
+        inode.set_mode(mode);
+        inode.set_atime(now);
+        inode.set_ctime(now);
+        inode.set_mtime(now);
+        inode.set_crtime(now);
+        self.next_generation = self.next_generation.wrapping_add(1);
+        inode.set_generation(self.next_generation);
+        inode
+    }
+
+    /// The seed of the checksums of the blocks of the inode.
+    pub fn inode_seed(&self, inode: &Inode) -> Option<u32> {
+        self.csum_seed.map(|seed| inode.csum_seed(seed))
+    }
+
+    /// Frees the blocks when the running transaction is committed, so they
+    /// are not overwritten before the metadata no longer refers to them.
+    pub fn free_blocks_later(&mut self, start: u64, count: u64) {
+        for block in start..start + count {
+            self.cache.forget(block);
+        }
+        self.pending_free.push((start, count));
+    }
+
+    /// Frees the blocks of the running transaction now, when no other space
+    /// is left.
+    pub fn release_pending(&mut self) -> VfsResult<bool> {
+        let pending = core::mem::take(&mut self.pending_free);
+        for &(start, count) in &pending {
+            self.release_blocks(start, count)?;
+        }
+        Ok(!pending.is_empty())
+    }
+
+    /// Commits the running transaction.
+    pub fn commit(&mut self) -> VfsResult {
+        if self.read_only {
+            return Ok(());
+        }
+        self.release_pending()?;
+        self.flush_groups()?;
+        if self.cache.dirty_count() == 0 {
+            return self.dev.flush();
+        }
+        let now = self.now();
+        self.sb.set_wtime(now);
+        self.flush_superblock()?;
+
+        let count = self.cache.dirty_count();
+        match &mut self.journal {
+            Some(journal) => {
+                // a transaction too large for the log is committed in parts,
+                // each written in place before the next one is logged
+                let capacity = journal.capacity();
+                if count > capacity {
+                    warn!(
+                        "ext4: transaction of {} blocks committed in {} parts",
+                        count,
+                        count.div_ceil(capacity)
+                    );
+                }
+                let dirty: Vec<_> = self.cache.dirty().collect();
+                for part in dirty.chunks(capacity) {
+                    journal.commit(&*self.dev, part.iter().copied(), part.len(), now)?;
+                    for &(&block, data) in part {
+                        self.dev.write_at(block * self.block_size as u64, data)?;
+                    }
+                    self.dev.flush()?;
+                    journal.checkpointed(&*self.dev)?;
+                }
+            }
+            None => {
+                for (&block, data) in self.cache.dirty() {
+                    self.dev.write_at(block * self.block_size as u64, data)?;
+                }
+                self.dev.flush()?;
+            }
+        }
+        self.cache.clean_all();
+        Ok(())
+    }
+
+    /// Commits the running transaction if it's getting too large, at the end
+    /// of an operation when the metadata is consistent.
+    pub fn end_op(&mut self) -> VfsResult {
+        let limit = match &self.journal {
+            Some(journal) => journal.capacity() / 2,
+            None => 1024,
+        };
+        if self.cache.dirty_count() + self.dirty_groups.len() > limit {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Marks the filesystem as in use on mounting, so that a crash is noticed
+    /// by the next mount or by `e2fsck`.
+    pub fn start(&mut self) -> VfsResult {
+        if self.read_only {
+            return Ok(());
+        }
+        let now = self.now();
+        self.sb.set_mtime(now);
+        self.sb.inc_mount_count();
+        if self.journal.is_some() {
+            self.sb.set_incompat(INCOMPAT_RECOVER, true);
+        } else {
+            self.sb.set_state(self.sb.state() & !STATE_VALID);
+        }
+        self.flush_superblock()?;
+        self.commit()?;
+        self.free_orphans()
+    }
+
+    /// Commits everything and marks the filesystem as cleanly unmounted.
+    pub fn stop(&mut self) -> VfsResult {
+        if self.read_only {
+            return Ok(());
+        }
+        for ino in core::mem::take(&mut self.orphans) {
+            self.free_orphan(ino)?;
+        }
+        self.commit()?;
+        self.sb.set_incompat(INCOMPAT_RECOVER, false);
+        self.sb.set_state(self.sb.state() | STATE_VALID);
+        self.flush_superblock()?;
+        self.commit()
+    }
+}
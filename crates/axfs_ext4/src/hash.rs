@@ -0,0 +1,179 @@
+//! The hashes of names in indexed directories.
+//!
+//! The hash version is in the root of each directory index, with the
+//! `unsigned` variants chosen by a flag in the superblock, for the
+//! `char` signedness of the machine that made the filesystem.
+
+const DX_HASH_LEGACY: u8 = 0;
+const DX_HASH_HALF_MD4: u8 = 1;
+const DX_HASH_TEA: u8 = 2;
+const DX_HASH_LEGACY_UNSIGNED: u8 = 3;
+const DX_HASH_HALF_MD4_UNSIGNED: u8 = 4;
+const DX_HASH_TEA_UNSIGNED: u8 = 5;
+
+const DEFAULT_SEED: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+/// Whether the hash version can be computed.
+pub(crate) fn is_supported(version: u8) -> bool {
+    version <= DX_HASH_TEA
+}
+
+fn char_value(c: u8, unsigned: bool) -> u32 {
+    if unsigned {
+        c as u32
+    } else {
+        c as i8 as i32 as u32
+    }
+}
+
+fn legacy(name: &[u8], unsigned: bool) -> u32 {
+    let (mut hash0, mut hash1) = (0x12a3_fe2d_u32, 0x37ab_e8f9_u32);
+    for &c in name {
+        let mut hash = hash1.wrapping_add(hash0 ^ char_value(c, unsigned).wrapping_mul(7_152_373));
+        if hash & 0x8000_0000 != 0 {
+            hash = hash.wrapping_sub(0x7fff_ffff);
+        }
+        hash1 = hash0;
+        hash0 = hash;
+    }
+    hash0 << 1
+}
+
+/// Packs up to `out.len() * 4` bytes of the rest of the name into words,
+/// padded with the length of the rest.
+fn str2hashbuf(name: &[u8], out: &mut [u32], unsigned: bool) {
+    let len = name.len() as u32;
+    let mut pad = len | len << 8;
+    pad |= pad << 16;
+    let mut val = pad;
+    let max = out.len() * 4;
+    let mut words = out.iter_mut();
+    for (i, &c) in name.iter().take(max).enumerate() {
+        val = char_value(c, unsigned).wrapping_add(val << 8);
+        if i % 4 == 3 {
+            *words.next().unwrap() = val;
+            val = pad;
+        }
+    }
+    if let Some(word) = words.next() {
+        *word = val;
+    }
+    for word in words {
+        *word = pad;
+    }
+}
+
+fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+    let (mut sum, mut b0, mut b1) = (0u32, buf[0], buf[1]);
+    let [a, b, c, d] = *input;
+    for _ in 0..16 {
+        sum = sum.wrapping_add(0x9e37_79b9);
+        b0 = b0.wrapping_add(
+            (b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+        );
+        b1 = b1.wrapping_add(
+            (b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+        );
+    }
+    buf[0] = buf[0].wrapping_add(b0);
+    buf[1] = buf[1].wrapping_add(b1);
+}
+
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+    let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+    let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+    const K2: u32 = 0o13240474631;
+    const K3: u32 = 0o15666365641;
+    let [mut a, mut b, mut c, mut d] = *buf;
+    macro_rules! round {
+        ($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+            $a = $a
+                .wrapping_add($f($b, $c, $d))
+                .wrapping_add($x)
+                .rotate_left($s)
+        };
+    }
+    let x = input;
+    round!(f, a, b, c, d, x[0], 3);
+    round!(f, d, a, b, c, x[1], 7);
+    round!(f, c, d, a, b, x[2], 11);
+    round!(f, b, c, d, a, x[3], 19);
+    round!(f, a, b, c, d, x[4], 3);
+    round!(f, d, a, b, c, x[5], 7);
+    round!(f, c, d, a, b, x[6], 11);
+    round!(f, b, c, d, a, x[7], 19);
+
+    round!(g, a, b, c, d, x[1].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, x[3].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, x[5].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, x[7].wrapping_add(K2), 13);
+    round!(g, a, b, c, d, x[0].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, x[2].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, x[4].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, x[6].wrapping_add(K2), 13);
+
+    round!(h, a, b, c, d, x[3].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, x[7].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, x[2].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, x[6].wrapping_add(K3), 15);
+    round!(h, a, b, c, d, x[1].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, x[5].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, x[0].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, x[4].wrapping_add(K3), 15);
+
+    buf[0] = buf[0].wrapping_add(a);
+    buf[1] = buf[1].wrapping_add(b);
+    buf[2] = buf[2].wrapping_add(c);
+    buf[3] = buf[3].wrapping_add(d);
+}
+
+/// Returns the hash of the name, with the lowest bit cleared as it marks
+/// collisions in the index.
+pub(crate) fn dirhash(name: &[u8], version: u8, seed: [u32; 4]) -> u32 {
+    let mut buf = if seed.iter().any(|&s| s != 0) {
+        seed
+    } else {
+        DEFAULT_SEED
+    };
+    let hash = match version {
+        DX_HASH_LEGACY | DX_HASH_LEGACY_UNSIGNED => {
+            legacy(name, version == DX_HASH_LEGACY_UNSIGNED)
+        }
+        DX_HASH_HALF_MD4 | DX_HASH_HALF_MD4_UNSIGNED => {
+            let mut input = [0; 8];
+            let mut rest = name;
+            while !rest.is_empty() {
+                str2hashbuf(rest, &mut input, version == DX_HASH_HALF_MD4_UNSIGNED);
+                half_md4_transform(&mut buf, &input);
+                rest = &rest[rest.len().min(32)..];
+            }
+            buf[1]
+        }
+        DX_HASH_TEA | DX_HASH_TEA_UNSIGNED => {
+            let mut input = [0; 4];
+            let mut rest = name;
+            while !rest.is_empty() {
+                str2hashbuf(rest, &mut input, version == DX_HASH_TEA_UNSIGNED);
+                tea_transform(&mut buf, &input);
+                rest = &rest[rest.len().min(16)..];
+            }
+            buf[0]
+        }
+        _ => 0,
+    };
+    match hash & !1 {
+        0xffff_fffe => 0xffff_fffc,
+        hash => hash,
+    }
+}
+
+/// The hash version to use for the directory, given the version in its
+/// index root.
+pub(crate) fn version_of(root_version: u8, unsigned: bool) -> u8 {
+    if unsigned && root_version <= DX_HASH_TEA {
+        root_version + 3
+    } else {
+        root_version
+    }
+}
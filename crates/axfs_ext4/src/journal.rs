@@ -0,0 +1,498 @@
+//! The jbd2 journal, in the journal inode.
+//!
+//! A transaction is written as descriptor blocks, each followed by the
+//! copies of the metadata blocks it lists, and a commit block. It's written
+//! in place right after the commit, and the journal is marked empty again,
+//! so each transaction starts at the beginning of the log. All fields of the
+//! journal are big-endian.
+//!
+//! On mounting, the committed transactions of a journal that is not empty
+//! are replayed, skipping the blocks revoked by later transactions.
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::time::Duration;
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use crate::crc::crc32c;
+use crate::fs::State;
+use crate::BlockDevice;
+
+const JBD2_MAGIC: u32 = 0xc03b_3998;
+
+const DESCRIPTOR_BLOCK: u32 = 1;
+const COMMIT_BLOCK: u32 = 2;
+const SUPERBLOCK_V1: u32 = 3;
+const SUPERBLOCK_V2: u32 = 4;
+const REVOKE_BLOCK: u32 = 5;
+
+const INCOMPAT_REVOKE: u32 = 0x1;
+const INCOMPAT_64BIT: u32 = 0x2;
+const INCOMPAT_ASYNC_COMMIT: u32 = 0x4;
+const INCOMPAT_CSUM_V2: u32 = 0x8;
+const INCOMPAT_CSUM_V3: u32 = 0x10;
+const KNOWN_INCOMPAT: u32 =
+    INCOMPAT_REVOKE | INCOMPAT_64BIT | INCOMPAT_ASYNC_COMMIT | INCOMPAT_CSUM_V2 | INCOMPAT_CSUM_V3;
+
+const CRC32C_CHKSUM: u8 = 4;
+
+const FLAG_ESCAPE: u32 = 0x1;
+const FLAG_SAME_UUID: u32 = 0x2;
+const FLAG_LAST_TAG: u32 = 0x8;
+
+/// The size of the header of every block but data blocks.
+const HEADER_SIZE: usize = 12;
+const JSB_SIZE: usize = 1024;
+
+fn be32(b: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes(b[off..off + 4].try_into().unwrap())
+}
+
+fn put_be32(b: &mut [u8], off: usize, v: u32) {
+    b[off..off + 4].copy_from_slice(&v.to_be_bytes());
+}
+
+fn put_header(b: &mut [u8], blocktype: u32, sequence: u32) {
+    put_be32(b, 0, JBD2_MAGIC);
+    put_be32(b, 4, blocktype);
+    put_be32(b, 8, sequence);
+}
+
+/// A block listed in a descriptor: where it goes on the filesystem, where
+/// its copy is in the log, and whether the copy is escaped.
+struct Tag {
+    block: u64,
+    log_block: u32,
+    escaped: bool,
+    csum: u32,
+}
+
+/// A committed transaction found in the log.
+struct Transaction {
+    sequence: u32,
+    tags: Vec<Tag>,
+}
+
+pub(crate) struct Journal {
+    /// The runs of blocks of the journal inode: the first logical block, the
+    /// first block on the disk, and the length.
+    runs: Vec<(u32, u64, u32)>,
+    block_size: usize,
+    /// The superblock of the journal, changed and written back.
+    sb: Vec<u8>,
+    /// The first and the end of the blocks of the log.
+    first: u32,
+    maxlen: u32,
+    incompat: u32,
+    /// The seed of the checksums, if the journal has them.
+    csum_seed: Option<u32>,
+    /// The sequence of the next transaction.
+    sequence: u32,
+}
+
+impl Journal {
+    /// Opens the journal of the filesystem. Returns `None` if the filesystem
+    /// can only be read, as the journal can't be used.
+    pub fn open(state: &mut State) -> VfsResult<Option<Self>> {
+        let ino = state.sb.journal_inum();
+        if ino == 0 {
+            warn!("ext4: external journals are not supported");
+            return Ok(None);
+        }
+        let inode = state.read_inode(ino)?;
+        let block_size = state.block_size;
+        let blocks = (inode.size() / block_size as u64) as u32;
+        let mut runs: Vec<(u32, u64, u32)> = Vec::new();
+        let mut lblk = 0;
+        while lblk < blocks {
+            let Some((pblk, count, _)) = state.map_block(&inode, lblk)? else {
+                warn!("ext4: hole in the journal");
+                return Err(VfsError::InvalidData);
+            };
+            let count = count.min(blocks - lblk);
+            runs.push((lblk, pblk, count));
+            lblk += count;
+        }
+        if runs.is_empty() {
+            return Err(VfsError::InvalidData);
+        }
+
+        let mut sb = vec![0; JSB_SIZE];
+        state.dev.read_at(runs[0].1 * block_size as u64, &mut sb)?;
+        let blocktype = be32(&sb, 4);
+        if be32(&sb, 0) != JBD2_MAGIC || !matches!(blocktype, SUPERBLOCK_V1 | SUPERBLOCK_V2) {
+            warn!("ext4: bad journal superblock");
+            return Err(VfsError::InvalidData);
+        }
+        let (first, maxlen) = (be32(&sb, 0x14), be32(&sb, 0x10));
+        if be32(&sb, 0xc) as usize != block_size || maxlen > blocks || first == 0 || first >= maxlen
+        {
+            warn!("ext4: bad journal geometry");
+            return Err(VfsError::InvalidData);
+        }
+        let incompat = if blocktype == SUPERBLOCK_V2 {
+            be32(&sb, 0x28)
+        } else {
+            0
+        };
+        if incompat & !KNOWN_INCOMPAT != 0 {
+            warn!("ext4: unsupported journal features {:#x}", incompat);
+            return Ok(None);
+        }
+        let mut journal = Self {
+            runs,
+            block_size,
+            first,
+            maxlen,
+            incompat,
+            csum_seed: None,
+            sequence: be32(&sb, 0x18),
+            sb,
+        };
+        if incompat & (INCOMPAT_CSUM_V2 | INCOMPAT_CSUM_V3) != 0 {
+            if journal.sb[0x50] != CRC32C_CHKSUM {
+                warn!("ext4: unsupported journal checksum");
+                return Ok(None);
+            }
+            if journal.sb_checksum() != be32(&journal.sb, 0xfc) {
+                warn!("ext4: bad checksum of journal superblock");
+                return Err(VfsError::InvalidData);
+            }
+            journal.csum_seed = Some(crc32c(!0, &journal.sb[0x30..0x40]));
+        }
+        Ok(Some(journal))
+    }
+
+    /// Whether the log has transactions to replay.
+    pub fn needs_recovery(&self) -> bool {
+        be32(&self.sb, 0x1c) != 0
+    }
+
+    fn has(&self, feature: u32) -> bool {
+        self.incompat & feature != 0
+    }
+
+    /// The block on the disk of the block of the log.
+    fn map(&self, block: u32) -> u64 {
+        let i = self.runs.partition_point(|run| run.0 <= block) - 1;
+        let (lblk, pblk, _) = self.runs[i];
+        pblk + (block - lblk) as u64
+    }
+
+    fn read(&self, dev: &dyn BlockDevice, block: u32, buf: &mut [u8]) -> VfsResult {
+        dev.read_at(self.map(block) * self.block_size as u64, buf)
+    }
+
+    fn write(&self, dev: &dyn BlockDevice, block: u32, buf: &[u8]) -> VfsResult {
+        dev.write_at(self.map(block) * self.block_size as u64, buf)
+    }
+
+    fn next(&self, block: u32) -> u32 {
+        if block + 1 >= self.maxlen {
+            self.first
+        } else {
+            block + 1
+        }
+    }
+
+    fn sb_checksum(&self) -> u32 {
+        let mut sb = self.sb.clone();
+        put_be32(&mut sb, 0xfc, 0);
+        crc32c(!0, &sb)
+    }
+
+    fn write_sb(&mut self, dev: &dyn BlockDevice, start: u32, sequence: u32) -> VfsResult {
+        put_be32(&mut self.sb, 0x18, sequence);
+        put_be32(&mut self.sb, 0x1c, start);
+        if self.csum_seed.is_some() {
+            let csum = self.sb_checksum();
+            put_be32(&mut self.sb, 0xfc, csum);
+        }
+        self.write(dev, 0, &self.sb)
+    }
+
+    /// The size of a tag in a descriptor block.
+    fn tag_size(&self) -> usize {
+        if self.has(INCOMPAT_CSUM_V3) {
+            return 16;
+        }
+        let size = if self.has(INCOMPAT_CSUM_V2) { 14 } else { 12 };
+        if self.has(INCOMPAT_64BIT) {
+            size
+        } else {
+            size - 4
+        }
+    }
+
+    /// The end of the tags in a descriptor or revoke block, before the tail
+    /// with its checksum.
+    fn tags_end(&self) -> usize {
+        if self.csum_seed.is_some() {
+            self.block_size - 4
+        } else {
+            self.block_size
+        }
+    }
+
+    /// Whether the checksum in the tail of a descriptor or revoke block is
+    /// right.
+    fn verify_tail(&self, block: &[u8]) -> bool {
+        let Some(seed) = self.csum_seed else {
+            return true;
+        };
+        let tail = self.block_size - 4;
+        let mut copy = block.to_vec();
+        put_be32(&mut copy, tail, 0);
+        crc32c(seed, &copy) == be32(block, tail)
+    }
+
+    fn set_tail(&self, block: &mut [u8]) {
+        if let Some(seed) = self.csum_seed {
+            let tail = self.block_size - 4;
+            put_be32(block, tail, 0);
+            let csum = crc32c(seed, block);
+            put_be32(block, tail, csum);
+        }
+    }
+
+    fn commit_checksum(&self, block: &[u8]) -> u32 {
+        let mut copy = block.to_vec();
+        copy[12] = 0;
+        copy[13] = 0;
+        put_be32(&mut copy, 16, 0);
+        crc32c(self.csum_seed.unwrap(), &copy)
+    }
+
+    /// The checksum of a block in the log, as it's in the log.
+    fn data_checksum(&self, sequence: u32, data: &[u8]) -> u32 {
+        let csum = crc32c(self.csum_seed.unwrap(), &sequence.to_be_bytes());
+        crc32c(csum, data)
+    }
+
+    /// Parses the tags of a descriptor block, whose blocks follow it from
+    /// `log_block`.
+    fn parse_tags(&self, block: &[u8], mut log_block: u32, tags: &mut Vec<Tag>) -> u32 {
+        let tag_size = self.tag_size();
+        let end = self.tags_end();
+        let mut off = HEADER_SIZE;
+        while off + tag_size <= end {
+            let (flags, csum) = if self.has(INCOMPAT_CSUM_V3) {
+                (be32(block, off + 4), be32(block, off + 12))
+            } else {
+                let flags = u16::from_be_bytes([block[off + 6], block[off + 7]]);
+                let csum = u16::from_be_bytes([block[off + 4], block[off + 5]]);
+                (flags as u32, csum as u32)
+            };
+            let mut fs_block = be32(block, off) as u64;
+            if self.has(INCOMPAT_64BIT) {
+                fs_block |= (be32(block, off + 8) as u64) << 32;
+            }
+            tags.push(Tag {
+                block: fs_block,
+                log_block,
+                escaped: flags & FLAG_ESCAPE != 0,
+                csum,
+            });
+            log_block = self.next(log_block);
+            off += tag_size;
+            if flags & FLAG_SAME_UUID == 0 {
+                off += 16;
+            }
+            if flags & FLAG_LAST_TAG != 0 {
+                break;
+            }
+        }
+        log_block
+    }
+
+    /// Finds the committed transactions in the log, and the latest
+    /// transaction revoking each block.
+    fn scan(&self, dev: &dyn BlockDevice) -> VfsResult<(Vec<Transaction>, BTreeMap<u64, u32>)> {
+        let mut transactions = Vec::new();
+        let mut revoked = BTreeMap::new();
+        let mut sequence = be32(&self.sb, 0x18);
+        let mut block = be32(&self.sb, 0x1c);
+        let mut tags = Vec::new();
+        let mut revokes = Vec::new();
+        let mut buf = vec![0; self.block_size];
+        for _ in 0..self.maxlen {
+            self.read(dev, block, &mut buf)?;
+            if be32(&buf, 0) != JBD2_MAGIC || be32(&buf, 8) != sequence {
+                break;
+            }
+            match be32(&buf, 4) {
+                DESCRIPTOR_BLOCK if self.verify_tail(&buf) => {
+                    block = self.parse_tags(&buf, self.next(block), &mut tags);
+                }
+                REVOKE_BLOCK if self.verify_tail(&buf) => {
+                    let size = if self.has(INCOMPAT_64BIT) { 8 } else { 4 };
+                    let count = (be32(&buf, 12) as usize).min(self.tags_end());
+                    for raw in buf[16.min(count)..count].chunks_exact(size) {
+                        let revoked_block = match size {
+                            8 => u64::from_be_bytes(raw.try_into().unwrap()),
+                            _ => be32(raw, 0) as u64,
+                        };
+                        revokes.push(revoked_block);
+                    }
+                    block = self.next(block);
+                }
+                COMMIT_BLOCK => {
+                    if self.csum_seed.is_some() && self.commit_checksum(&buf) != be32(&buf, 16) {
+                        warn!("ext4: bad checksum of journal commit {}", sequence);
+                        break;
+                    }
+                    for b in revokes.drain(..) {
+                        revoked.insert(b, sequence);
+                    }
+                    transactions.push(Transaction {
+                        sequence,
+                        tags: core::mem::take(&mut tags),
+                    });
+                    sequence = sequence.wrapping_add(1);
+                    block = self.next(block);
+                }
+                _ => break,
+            }
+        }
+        Ok((transactions, revoked))
+    }
+
+    /// Replays the committed transactions in the log, and marks the journal
+    /// empty.
+    pub fn recover(&mut self, dev: &dyn BlockDevice) -> VfsResult {
+        let (transactions, revoked) = self.scan(dev)?;
+        let mut buf = vec![0; self.block_size];
+        for transaction in &transactions {
+            for tag in &transaction.tags {
+                if revoked
+                    .get(&tag.block)
+                    .is_some_and(|&seq| seq.wrapping_sub(transaction.sequence) as i32 >= 0)
+                {
+                    continue;
+                }
+                self.read(dev, tag.log_block, &mut buf)?;
+                if self.csum_seed.is_some() {
+                    let csum = self.data_checksum(transaction.sequence, &buf);
+                    let csum = if self.has(INCOMPAT_CSUM_V3) {
+                        csum
+                    } else {
+                        csum & 0xffff
+                    };
+                    if csum != tag.csum {
+                        warn!("ext4: bad checksum of journaled block {}", tag.block);
+                        continue;
+                    }
+                }
+                if tag.escaped {
+                    put_be32(&mut buf, 0, JBD2_MAGIC);
+                }
+                dev.write_at(tag.block * self.block_size as u64, &buf)?;
+            }
+        }
+        dev.flush()?;
+        if let Some(last) = transactions.last() {
+            info!(
+                "ext4: replayed {} transactions of the journal",
+                transactions.len()
+            );
+            self.sequence = last.sequence.wrapping_add(1);
+        }
+        self.write_sb(dev, 0, self.sequence)?;
+        dev.flush()
+    }
+
+    /// The number of tags in a descriptor block.
+    fn tags_per_block(&self) -> usize {
+        (self.tags_end() - HEADER_SIZE - 16) / self.tag_size()
+    }
+
+    /// The largest number of blocks in a transaction.
+    pub fn capacity(&self) -> usize {
+        let per_block = self.tags_per_block();
+        let log = (self.maxlen - self.first - 1) as usize;
+        log * per_block / (per_block + 1)
+    }
+
+    /// Writes the blocks to the log as a transaction, and commits it. The
+    /// blocks can then be written in place.
+    pub fn commit<'a>(
+        &mut self,
+        dev: &dyn BlockDevice,
+        blocks: impl Iterator<Item = (&'a u64, &'a alloc::boxed::Box<[u8]>)>,
+        count: usize,
+        now: Duration,
+    ) -> VfsResult {
+        let sequence = self.sequence;
+        let tag_size = self.tag_size();
+        let blocks: Vec<_> = blocks.collect();
+        debug_assert_eq!(blocks.len(), count);
+        let mut pos = self.first;
+        let mut data = vec![0; self.block_size];
+        for chunk in blocks.chunks(self.tags_per_block()) {
+            let mut desc = vec![0; self.block_size];
+            put_header(&mut desc, DESCRIPTOR_BLOCK, sequence);
+            let desc_pos = pos;
+            pos += 1;
+            let mut off = HEADER_SIZE;
+            for (i, &(&block, content)) in chunk.iter().enumerate() {
+                data.copy_from_slice(content);
+                let mut flags = 0;
+                if be32(&data, 0) == JBD2_MAGIC {
+                    put_be32(&mut data, 0, 0);
+                    flags |= FLAG_ESCAPE;
+                }
+                if i > 0 {
+                    flags |= FLAG_SAME_UUID;
+                }
+                if i + 1 == chunk.len() {
+                    flags |= FLAG_LAST_TAG;
+                }
+                let csum = self
+                    .csum_seed
+                    .map_or(0, |_| self.data_checksum(sequence, &data));
+                put_be32(&mut desc, off, block as u32);
+                if self.has(INCOMPAT_CSUM_V3) {
+                    put_be32(&mut desc, off + 4, flags);
+                    put_be32(&mut desc, off + 12, csum);
+                } else {
+                    desc[off + 4..off + 6].copy_from_slice(&(csum as u16).to_be_bytes());
+                    desc[off + 6..off + 8].copy_from_slice(&(flags as u16).to_be_bytes());
+                }
+                if self.has(INCOMPAT_64BIT) {
+                    put_be32(&mut desc, off + 8, (block >> 32) as u32);
+                }
+                off += tag_size;
+                if i == 0 {
+                    desc[off..off + 16].copy_from_slice(&self.sb[0x30..0x40]);
+                    off += 16;
+                }
+                self.write(dev, pos, &data)?;
+                pos += 1;
+            }
+            self.set_tail(&mut desc);
+            self.write(dev, desc_pos, &desc)?;
+        }
+        self.write_sb(dev, self.first, sequence)?;
+        dev.flush()?;
+
+        let mut commit = vec![0; self.block_size];
+        put_header(&mut commit, COMMIT_BLOCK, sequence);
+        commit[0x30..0x38].copy_from_slice(&now.as_secs().to_be_bytes());
+        put_be32(&mut commit, 0x38, now.subsec_nanos());
+        if self.csum_seed.is_some() {
+            let csum = self.commit_checksum(&commit);
+            put_be32(&mut commit, 16, csum);
+        }
+        self.write(dev, pos, &commit)?;
+        dev.flush()?;
+        self.sequence = sequence.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Marks the journal empty after the committed blocks are written in
+    /// place.
+    pub fn checkpointed(&mut self, dev: &dyn BlockDevice) -> VfsResult {
+        self.write_sb(dev, 0, self.sequence)?;
+        dev.flush()
+    }
+}
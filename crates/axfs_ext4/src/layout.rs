@@ -0,0 +1,741 @@
+//! The on-disk structures of ext4: the superblock, group descriptors and
+//! inodes.
+//!
+//! They are kept as the raw little-endian bytes read from the disk, with
+//! accessors for the fields, so the fields not used here are preserved when
+//! they are written back.
+
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+
+use crate::crc::{crc16, crc32c};
+
+pub(crate) fn le16(b: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([b[off], b[off + 1]])
+}
+
+pub(crate) fn le32(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([b[off], b[off + 1], b[off + 2], b[off + 3]])
+}
+
+pub(crate) fn put16(b: &mut [u8], off: usize, v: u16) {
+    b[off..off + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+pub(crate) fn put32(b: &mut [u8], off: usize, v: u32) {
+    b[off..off + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+/// The superblock is at byte 1024 of the device, whatever the block size.
+pub(crate) const SUPERBLOCK_OFFSET: u64 = 1024;
+pub(crate) const SUPERBLOCK_SIZE: usize = 1024;
+/// The magic number of ext2/3/4, also the `f_type` of `statfs`.
+pub(crate) const EXT4_MAGIC: u16 = 0xef53;
+/// The inode of the root directory.
+pub(crate) const ROOT_INO: u32 = 2;
+
+pub(crate) const COMPAT_HAS_JOURNAL: u32 = 0x4;
+pub(crate) const COMPAT_DIR_INDEX: u32 = 0x20;
+pub(crate) const COMPAT_SPARSE_SUPER2: u32 = 0x200;
+
+pub(crate) const INCOMPAT_FILETYPE: u32 = 0x2;
+pub(crate) const INCOMPAT_RECOVER: u32 = 0x4;
+pub(crate) const INCOMPAT_META_BG: u32 = 0x10;
+pub(crate) const INCOMPAT_EXTENTS: u32 = 0x40;
+pub(crate) const INCOMPAT_64BIT: u32 = 0x80;
+pub(crate) const INCOMPAT_FLEX_BG: u32 = 0x200;
+pub(crate) const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+pub(crate) const INCOMPAT_LARGEDIR: u32 = 0x4000;
+/// The incompatible features that can be mounted, others like
+/// `inline_data`, `encrypt` or an external journal can't.
+const INCOMPAT_SUPPORTED: u32 = INCOMPAT_FILETYPE
+    | INCOMPAT_RECOVER
+    | INCOMPAT_META_BG
+    | INCOMPAT_EXTENTS
+    | INCOMPAT_64BIT
+    | INCOMPAT_FLEX_BG
+    | INCOMPAT_CSUM_SEED
+    | INCOMPAT_LARGEDIR;
+
+pub(crate) const RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+pub(crate) const RO_COMPAT_LARGE_FILE: u32 = 0x2;
+pub(crate) const RO_COMPAT_HUGE_FILE: u32 = 0x8;
+pub(crate) const RO_COMPAT_GDT_CSUM: u32 = 0x10;
+pub(crate) const RO_COMPAT_DIR_NLINK: u32 = 0x20;
+pub(crate) const RO_COMPAT_EXTRA_ISIZE: u32 = 0x40;
+pub(crate) const RO_COMPAT_METADATA_CSUM: u32 = 0x400;
+/// The read-only compatible features that can be mounted read-write, others
+/// like quotas or `bigalloc` are mounted read-only.
+const RO_COMPAT_WRITABLE: u32 = RO_COMPAT_SPARSE_SUPER
+    | RO_COMPAT_LARGE_FILE
+    | RO_COMPAT_HUGE_FILE
+    | RO_COMPAT_GDT_CSUM
+    | RO_COMPAT_DIR_NLINK
+    | RO_COMPAT_EXTRA_ISIZE
+    | RO_COMPAT_METADATA_CSUM;
+
+/// `s_state`: cleanly unmounted.
+pub(crate) const STATE_VALID: u16 = 0x1;
+/// `s_flags`: directory hashes treat names as unsigned chars.
+const FLAGS_UNSIGNED_HASH: u32 = 0x2;
+
+/// The superblock.
+pub(crate) struct SuperBlock {
+    raw: Vec<u8>,
+}
+
+impl SuperBlock {
+    /// Parses the superblock, checking the magic number, the checksum and
+    /// the features.
+    pub fn parse(raw: &[u8]) -> VfsResult<Self> {
+        let sb = Self { raw: raw.to_vec() };
+        if le16(raw, 0x38) != EXT4_MAGIC {
+            return Err(VfsError::InvalidData);
+        }
+        if sb.has_ro_compat(RO_COMPAT_METADATA_CSUM) && sb.checksum() != le32(raw, 0x3fc) {
+            warn!("ext4: bad superblock checksum");
+            return Err(VfsError::InvalidData);
+        }
+        let unsupported = sb.feature_incompat() & !INCOMPAT_SUPPORTED;
+        if unsupported != 0 {
+            warn!("ext4: unsupported incompatible features {:#x}", unsupported);
+            return Err(VfsError::Unsupported);
+        }
+        let log_block_size = le32(raw, 0x18);
+        if log_block_size > 6
+            || sb.blocks_per_group() == 0
+            || sb.inodes_per_group() == 0
+            || sb.inode_size() < 128
+            || sb.inode_size() > sb.block_size()
+            || !sb.inode_size().is_power_of_two()
+            || sb.desc_size() < 32
+            || !sb.desc_size().is_power_of_two()
+        {
+            return Err(VfsError::InvalidData);
+        }
+        Ok(sb)
+    }
+
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Whether the filesystem may be written, without unknown read-only
+    /// compatible features.
+    pub fn writable(&self) -> bool {
+        le32(&self.raw, 0x64) & !RO_COMPAT_WRITABLE == 0
+    }
+
+    pub fn inodes_count(&self) -> u32 {
+        le32(&self.raw, 0x0)
+    }
+
+    pub fn blocks_count(&self) -> u64 {
+        self.lo_hi(0x4, 0x150)
+    }
+
+    pub fn reserved_blocks(&self) -> u64 {
+        self.lo_hi(0x8, 0x154)
+    }
+
+    pub fn free_blocks(&self) -> u64 {
+        self.lo_hi(0xc, 0x158)
+    }
+
+    pub fn set_free_blocks(&mut self, n: u64) {
+        put32(&mut self.raw, 0xc, n as u32);
+        if self.is_64bit() {
+            put32(&mut self.raw, 0x158, (n >> 32) as u32);
+        }
+    }
+
+    pub fn free_inodes(&self) -> u32 {
+        le32(&self.raw, 0x10)
+    }
+
+    pub fn set_free_inodes(&mut self, n: u32) {
+        put32(&mut self.raw, 0x10, n);
+    }
+
+    pub fn first_data_block(&self) -> u64 {
+        le32(&self.raw, 0x14) as u64
+    }
+
+    pub fn block_size(&self) -> usize {
+        1024 << le32(&self.raw, 0x18).min(6)
+    }
+
+    pub fn blocks_per_group(&self) -> u32 {
+        le32(&self.raw, 0x20)
+    }
+
+    pub fn inodes_per_group(&self) -> u32 {
+        le32(&self.raw, 0x28)
+    }
+
+    pub fn set_mtime(&mut self, now: Duration) {
+        put32(&mut self.raw, 0x2c, now.as_secs() as u32);
+    }
+
+    pub fn set_wtime(&mut self, now: Duration) {
+        put32(&mut self.raw, 0x30, now.as_secs() as u32);
+    }
+
+    pub fn inc_mount_count(&mut self) {
+        let count = le16(&self.raw, 0x34);
+        put16(&mut self.raw, 0x34, count.wrapping_add(1));
+    }
+
+    pub fn state(&self) -> u16 {
+        le16(&self.raw, 0x3a)
+    }
+
+    pub fn set_state(&mut self, state: u16) {
+        put16(&mut self.raw, 0x3a, state);
+    }
+
+    pub fn first_ino(&self) -> u32 {
+        if le32(&self.raw, 0x4c) == 0 {
+            11
+        } else {
+            le32(&self.raw, 0x54)
+        }
+    }
+
+    pub fn inode_size(&self) -> usize {
+        if le32(&self.raw, 0x4c) == 0 {
+            128
+        } else {
+            le16(&self.raw, 0x58) as usize
+        }
+    }
+
+    pub fn feature_compat(&self) -> u32 {
+        le32(&self.raw, 0x5c)
+    }
+
+    pub fn feature_incompat(&self) -> u32 {
+        le32(&self.raw, 0x60)
+    }
+
+    pub fn has_compat(&self, feature: u32) -> bool {
+        self.feature_compat() & feature != 0
+    }
+
+    pub fn has_incompat(&self, feature: u32) -> bool {
+        self.feature_incompat() & feature != 0
+    }
+
+    pub fn has_ro_compat(&self, feature: u32) -> bool {
+        le32(&self.raw, 0x64) & feature != 0
+    }
+
+    pub fn set_incompat(&mut self, feature: u32, on: bool) {
+        let features = self.feature_incompat();
+        let features = if on {
+            features | feature
+        } else {
+            features & !feature
+        };
+        put32(&mut self.raw, 0x60, features);
+    }
+
+    pub fn set_ro_compat(&mut self, feature: u32) {
+        let features = le32(&self.raw, 0x64);
+        put32(&mut self.raw, 0x64, features | feature);
+    }
+
+    pub fn uuid(&self) -> &[u8] {
+        &self.raw[0x68..0x78]
+    }
+
+    pub fn reserved_gdt_blocks(&self) -> u32 {
+        le16(&self.raw, 0xce) as u32
+    }
+
+    pub fn journal_inum(&self) -> u32 {
+        le32(&self.raw, 0xe0)
+    }
+
+    /// The first inode of the list of orphans, which have no links but are
+    /// still open.
+    pub fn last_orphan(&self) -> u32 {
+        le32(&self.raw, 0xe8)
+    }
+
+    pub fn set_last_orphan(&mut self, ino: u32) {
+        put32(&mut self.raw, 0xe8, ino);
+    }
+
+    pub fn hash_seed(&self) -> [u32; 4] {
+        core::array::from_fn(|i| le32(&self.raw, 0xec + i * 4))
+    }
+
+    pub fn def_hash_version(&self) -> u8 {
+        self.raw[0xfc]
+    }
+
+    pub fn desc_size(&self) -> usize {
+        if self.is_64bit() {
+            le16(&self.raw, 0xfe) as usize
+        } else {
+            32
+        }
+    }
+
+    pub fn first_meta_bg(&self) -> u32 {
+        le32(&self.raw, 0x104)
+    }
+
+    pub fn want_extra_isize(&self) -> u16 {
+        le16(&self.raw, 0x15e)
+    }
+
+    pub fn hash_unsigned(&self) -> bool {
+        le32(&self.raw, 0x160) & FLAGS_UNSIGNED_HASH != 0
+    }
+
+    pub fn backup_bgs(&self) -> [u32; 2] {
+        [le32(&self.raw, 0x24c), le32(&self.raw, 0x250)]
+    }
+
+    pub fn is_64bit(&self) -> bool {
+        self.has_incompat(INCOMPAT_64BIT)
+    }
+
+    /// The seed of the checksums of all metadata.
+    pub fn csum_seed(&self) -> u32 {
+        if self.has_incompat(INCOMPAT_CSUM_SEED) {
+            le32(&self.raw, 0x270)
+        } else {
+            crc32c(!0, self.uuid())
+        }
+    }
+
+    fn checksum(&self) -> u32 {
+        crc32c(!0, &self.raw[..0x3fc])
+    }
+
+    /// Updates the checksum before the superblock is written.
+    pub fn update_checksum(&mut self) {
+        if self.has_ro_compat(RO_COMPAT_METADATA_CSUM) {
+            let csum = self.checksum();
+            put32(&mut self.raw, 0x3fc, csum);
+        }
+    }
+
+    fn lo_hi(&self, lo: usize, hi: usize) -> u64 {
+        let hi = if self.is_64bit() {
+            le32(&self.raw, hi) as u64
+        } else {
+            0
+        };
+        le32(&self.raw, lo) as u64 | hi << 32
+    }
+}
+
+/// `bg_flags`: the inode table and bitmap are not initialized.
+pub(crate) const BG_INODE_UNINIT: u16 = 0x1;
+/// `bg_flags`: the block bitmap is not initialized.
+pub(crate) const BG_BLOCK_UNINIT: u16 = 0x2;
+
+/// A block group descriptor.
+#[derive(Clone)]
+pub(crate) struct GroupDesc {
+    raw: Vec<u8>,
+}
+
+impl GroupDesc {
+    pub fn new(raw: &[u8]) -> Self {
+        Self { raw: raw.to_vec() }
+    }
+
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    fn lo_hi32(&self, lo: usize, hi: usize) -> u64 {
+        let hi = if self.raw.len() >= 64 {
+            le32(&self.raw, hi) as u64
+        } else {
+            0
+        };
+        le32(&self.raw, lo) as u64 | hi << 32
+    }
+
+    fn lo_hi16(&self, lo: usize, hi: usize) -> u32 {
+        let hi = if self.raw.len() >= 64 {
+            le16(&self.raw, hi) as u32
+        } else {
+            0
+        };
+        le16(&self.raw, lo) as u32 | hi << 16
+    }
+
+    fn set_lo_hi16(&mut self, lo: usize, hi: usize, v: u32) {
+        put16(&mut self.raw, lo, v as u16);
+        if self.raw.len() >= 64 {
+            put16(&mut self.raw, hi, (v >> 16) as u16);
+        }
+    }
+
+    pub fn block_bitmap(&self) -> u64 {
+        self.lo_hi32(0x0, 0x20)
+    }
+
+    pub fn inode_bitmap(&self) -> u64 {
+        self.lo_hi32(0x4, 0x24)
+    }
+
+    pub fn inode_table(&self) -> u64 {
+        self.lo_hi32(0x8, 0x28)
+    }
+
+    pub fn free_blocks(&self) -> u32 {
+        self.lo_hi16(0xc, 0x2c)
+    }
+
+    pub fn set_free_blocks(&mut self, n: u32) {
+        self.set_lo_hi16(0xc, 0x2c, n);
+    }
+
+    pub fn free_inodes(&self) -> u32 {
+        self.lo_hi16(0xe, 0x2e)
+    }
+
+    pub fn set_free_inodes(&mut self, n: u32) {
+        self.set_lo_hi16(0xe, 0x2e, n);
+    }
+
+    pub fn used_dirs(&self) -> u32 {
+        self.lo_hi16(0x10, 0x30)
+    }
+
+    pub fn set_used_dirs(&mut self, n: u32) {
+        self.set_lo_hi16(0x10, 0x30, n);
+    }
+
+    pub fn flags(&self) -> u16 {
+        le16(&self.raw, 0x12)
+    }
+
+    pub fn set_flags(&mut self, flags: u16) {
+        put16(&mut self.raw, 0x12, flags);
+    }
+
+    pub fn itable_unused(&self) -> u32 {
+        self.lo_hi16(0x1c, 0x32)
+    }
+
+    pub fn set_itable_unused(&mut self, n: u32) {
+        self.set_lo_hi16(0x1c, 0x32, n);
+    }
+
+    pub fn set_block_bitmap_csum(&mut self, csum: u32) {
+        self.set_lo_hi16(0x18, 0x38, csum);
+    }
+
+    pub fn set_inode_bitmap_csum(&mut self, csum: u32) {
+        self.set_lo_hi16(0x1a, 0x3a, csum);
+    }
+
+    /// Updates the checksum of the descriptor of `group`, for `metadata_csum`
+    /// or `gdt_csum`.
+    pub fn update_checksum(&mut self, sb: &SuperBlock, group: u32) {
+        let group = group.to_le_bytes();
+        let csum = if sb.has_ro_compat(RO_COMPAT_METADATA_CSUM) {
+            put16(&mut self.raw, 0x1e, 0);
+            let csum = crc32c(crc32c(sb.csum_seed(), &group), &self.raw);
+            csum as u16
+        } else if sb.has_ro_compat(RO_COMPAT_GDT_CSUM) {
+            let mut csum = crc16(!0, sb.uuid());
+            csum = crc16(csum, &group);
+            csum = crc16(csum, &self.raw[..0x1e]);
+            crc16(csum, &self.raw[0x20..])
+        } else {
+            return;
+        };
+        put16(&mut self.raw, 0x1e, csum);
+    }
+}
+
+pub(crate) const S_IFMT: u16 = 0o170000;
+pub(crate) const S_IFDIR: u16 = 0o040000;
+pub(crate) const S_IFREG: u16 = 0o100000;
+pub(crate) const S_IFLNK: u16 = 0o120000;
+
+/// `i_flags`: the directory is indexed by an htree.
+pub(crate) const INDEX_FL: u32 = 0x1000;
+/// `i_flags`: `i_blocks` is in units of filesystem blocks.
+const HUGE_FILE_FL: u32 = 0x40000;
+/// `i_flags`: the blocks are mapped by an extent tree.
+pub(crate) const EXTENTS_FL: u32 = 0x80000;
+
+/// The size of `i_block`, which holds the block map, the root of the extent
+/// tree or the target of a fast symlink.
+pub(crate) const I_BLOCK_SIZE: usize = 60;
+const I_BLOCK: usize = 0x28;
+const GOOD_OLD_INODE_SIZE: usize = 128;
+
+/// An inode, with its number.
+#[derive(Clone)]
+pub(crate) struct Inode {
+    pub ino: u32,
+    raw: Vec<u8>,
+}
+
+impl Inode {
+    pub fn new(ino: u32, raw: &[u8]) -> Self {
+        Self {
+            ino,
+            raw: raw.to_vec(),
+        }
+    }
+
+    /// A zeroed inode of `size` bytes, with the extra fields as large as
+    /// `extra_isize`.
+    pub fn empty(ino: u32, size: usize, extra_isize: u16) -> Self {
+        let mut inode = Self {
+            ino,
+            raw: vec![0; size],
+        };
+        if size > GOOD_OLD_INODE_SIZE {
+            put16(&mut inode.raw, 0x80, extra_isize);
+        }
+        inode
+    }
+
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn mode(&self) -> u16 {
+        le16(&self.raw, 0x0)
+    }
+
+    pub fn set_mode(&mut self, mode: u16) {
+        put16(&mut self.raw, 0x0, mode);
+    }
+
+    pub fn file_type(&self) -> VfsNodeType {
+        match self.mode() & S_IFMT {
+            0o010000 => VfsNodeType::Fifo,
+            0o020000 => VfsNodeType::CharDevice,
+            S_IFDIR => VfsNodeType::Dir,
+            0o060000 => VfsNodeType::BlockDevice,
+            S_IFLNK => VfsNodeType::SymLink,
+            0o140000 => VfsNodeType::Socket,
+            _ => VfsNodeType::File,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode() & S_IFMT == S_IFDIR
+    }
+
+    pub fn uid(&self) -> u32 {
+        le16(&self.raw, 0x2) as u32 | (le16(&self.raw, 0x78) as u32) << 16
+    }
+
+    pub fn gid(&self) -> u32 {
+        le16(&self.raw, 0x18) as u32 | (le16(&self.raw, 0x7a) as u32) << 16
+    }
+
+    pub fn set_owner(&mut self, uid: u32, gid: u32) {
+        put16(&mut self.raw, 0x2, uid as u16);
+        put16(&mut self.raw, 0x78, (uid >> 16) as u16);
+        put16(&mut self.raw, 0x18, gid as u16);
+        put16(&mut self.raw, 0x7a, (gid >> 16) as u16);
+    }
+
+    pub fn size(&self) -> u64 {
+        le32(&self.raw, 0x4) as u64 | (le32(&self.raw, 0x6c) as u64) << 32
+    }
+
+    pub fn set_size(&mut self, size: u64) {
+        put32(&mut self.raw, 0x4, size as u32);
+        put32(&mut self.raw, 0x6c, (size >> 32) as u32);
+    }
+
+    pub fn links(&self) -> u16 {
+        le16(&self.raw, 0x1a)
+    }
+
+    pub fn set_links(&mut self, links: u16) {
+        put16(&mut self.raw, 0x1a, links);
+    }
+
+    pub fn flags(&self) -> u32 {
+        le32(&self.raw, 0x20)
+    }
+
+    pub fn set_flags(&mut self, flags: u32) {
+        put32(&mut self.raw, 0x20, flags);
+    }
+
+    /// The number of 512-byte sectors allocated, for blocks of `block_size`.
+    pub fn sectors(&self, block_size: usize) -> u64 {
+        let n = le32(&self.raw, 0x1c) as u64 | (le16(&self.raw, 0x74) as u64) << 32;
+        if self.flags() & HUGE_FILE_FL != 0 {
+            n * (block_size / 512) as u64
+        } else {
+            n
+        }
+    }
+
+    pub fn set_sectors(&mut self, n: u64) {
+        put32(&mut self.raw, 0x1c, n as u32);
+        put16(&mut self.raw, 0x74, (n >> 32) as u16);
+        self.set_flags(self.flags() & !HUGE_FILE_FL);
+    }
+
+    pub fn generation(&self) -> u32 {
+        le32(&self.raw, 0x64)
+    }
+
+    pub fn set_generation(&mut self, generation: u32) {
+        put32(&mut self.raw, 0x64, generation);
+    }
+
+    /// The block of extended attributes.
+    pub fn file_acl(&self) -> u64 {
+        le32(&self.raw, 0x68) as u64 | (le16(&self.raw, 0x76) as u64) << 32
+    }
+
+    pub fn set_file_acl(&mut self, block: u64) {
+        put32(&mut self.raw, 0x68, block as u32);
+        put16(&mut self.raw, 0x76, (block >> 32) as u16);
+    }
+
+    pub fn i_block(&self) -> &[u8] {
+        &self.raw[I_BLOCK..I_BLOCK + I_BLOCK_SIZE]
+    }
+
+    pub fn i_block_mut(&mut self) -> &mut [u8] {
+        &mut self.raw[I_BLOCK..I_BLOCK + I_BLOCK_SIZE]
+    }
+
+    /// The deletion time, or the next inode in the list of orphans.
+    pub fn dtime(&self) -> u32 {
+        le32(&self.raw, 0x14)
+    }
+
+    pub fn set_dtime(&mut self, dtime: u32) {
+        put32(&mut self.raw, 0x14, dtime);
+    }
+
+    /// The size of the fields after the first 128 bytes.
+    fn extra_isize(&self) -> usize {
+        if self.raw.len() > GOOD_OLD_INODE_SIZE {
+            le16(&self.raw, 0x80) as usize
+        } else {
+            0
+        }
+    }
+
+    /// The space after the extra fields, for extended attributes.
+    pub fn xattr_area(&self) -> Option<&[u8]> {
+        let start = GOOD_OLD_INODE_SIZE + self.extra_isize();
+        (self.raw.len() > GOOD_OLD_INODE_SIZE && start + 4 <= self.raw.len())
+            .then(|| &self.raw[start..])
+    }
+
+    pub fn xattr_area_mut(&mut self) -> Option<&mut [u8]> {
+        let start = GOOD_OLD_INODE_SIZE + self.extra_isize();
+        (self.raw.len() > GOOD_OLD_INODE_SIZE && start + 4 <= self.raw.len())
+            .then(|| &mut self.raw[start..])
+    }
+
+    /// Whether the extra fields reach the end of the field at `end`.
+    fn has_extra(&self, end: usize) -> bool {
+        GOOD_OLD_INODE_SIZE + self.extra_isize() >= end
+    }
+
+    fn time(&self, off: usize, extra: usize) -> Duration {
+        let secs = le32(&self.raw, off) as i32 as i64;
+        if !self.has_extra(extra + 4) {
+            return Duration::from_secs(secs.max(0) as u64);
+        }
+        let extra = le32(&self.raw, extra);
+        let secs = secs + (((extra & 0x3) as i64) << 32);
+        Duration::new(secs.max(0) as u64, (extra >> 2).min(999_999_999))
+    }
+
+    fn set_time(&mut self, off: usize, extra: usize, time: Duration) {
+        let secs = time.as_secs() as i64;
+        put32(&mut self.raw, off, secs as u32);
+        if self.has_extra(extra + 4) {
+            let epoch = ((secs - secs as i32 as i64) >> 32) as u32 & 0x3;
+            put32(&mut self.raw, extra, time.subsec_nanos() << 2 | epoch);
+        }
+    }
+
+    pub fn atime(&self) -> Duration {
+        self.time(0x8, 0x8c)
+    }
+
+    pub fn ctime(&self) -> Duration {
+        self.time(0xc, 0x84)
+    }
+
+    pub fn mtime(&self) -> Duration {
+        self.time(0x10, 0x88)
+    }
+
+    pub fn set_atime(&mut self, time: Duration) {
+        self.set_time(0x8, 0x8c, time);
+    }
+
+    pub fn set_ctime(&mut self, time: Duration) {
+        self.set_time(0xc, 0x84, time);
+    }
+
+    pub fn set_mtime(&mut self, time: Duration) {
+        self.set_time(0x10, 0x88, time);
+    }
+
+    pub fn set_crtime(&mut self, time: Duration) {
+        if self.has_extra(0x98) {
+            self.set_time(0x90, 0x94, time);
+        }
+    }
+
+    /// The seed of the checksums of the inode and its blocks.
+    pub fn csum_seed(&self, fs_seed: u32) -> u32 {
+        let seed = crc32c(fs_seed, &self.ino.to_le_bytes());
+        crc32c(seed, &self.generation().to_le_bytes())
+    }
+
+    fn checksum(&self, fs_seed: u32) -> u32 {
+        let has_hi = self.has_extra(0x84);
+        let mut raw = self.raw.clone();
+        put16(&mut raw, 0x7c, 0);
+        if has_hi {
+            put16(&mut raw, 0x82, 0);
+        }
+        let csum = crc32c(self.csum_seed(fs_seed), &raw);
+        if has_hi {
+            csum
+        } else {
+            csum & 0xffff
+        }
+    }
+
+    pub fn verify_checksum(&self, fs_seed: u32) -> bool {
+        let mut stored = le16(&self.raw, 0x7c) as u32;
+        if self.has_extra(0x84) {
+            stored |= (le16(&self.raw, 0x82) as u32) << 16;
+        }
+        stored == self.checksum(fs_seed)
+    }
+
+    pub fn update_checksum(&mut self, fs_seed: u32) {
+        let csum = self.checksum(fs_seed);
+        put16(&mut self.raw, 0x7c, csum as u16);
+        if self.has_extra(0x84) {
+            put16(&mut self.raw, 0x82, (csum >> 16) as u16);
+        }
+    }
+}
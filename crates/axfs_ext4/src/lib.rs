@@ -0,0 +1,126 @@
+//! ext4 filesystem used by [ArceOS](https://github.com/rcore-os/arceos).
+//!
+//! The implementation is based on [`axfs_vfs`], on any [`BlockDevice`]. It
+//! reads and writes files with extent trees and directories indexed by
+//! htrees, and keeps extended attributes and POSIX ACLs. Metadata is changed
+//! in transactions through the jbd2 journal, which is replayed on mounting
+//! after a crash. Files of ext2/3 with block maps can be read and removed,
+//! but not written.
+//!
+//! The filesystem is mounted read-only if it has features that are not
+//! supported, or a journal that can't be used.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod balloc;
+mod cache;
+mod crc;
+mod dir;
+mod extent;
+mod file;
+mod fs;
+mod hash;
+mod journal;
+mod layout;
+mod node;
+#[cfg(test)]
+mod tests;
+mod xattr;
+
+use self::fs::State;
+use self::layout::{EXT4_MAGIC, ROOT_INO};
+use self::node::Ext4;
+pub use self::node::Ext4Node;
+use alloc::sync::Arc;
+use axfs_vfs::{FileSystemInfo, VfsNodeRef, VfsOps, VfsResult};
+use core::time::Duration;
+use spin::once::Once;
+
+/// The device an ext4 filesystem is on.
+pub trait BlockDevice: Send + Sync {
+    /// The size of the device, in bytes.
+    fn size(&self) -> u64;
+    /// Reads from the device at `offset`, filling `buf`.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult;
+    /// Writes all of `buf` to the device at `offset`.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult;
+    /// Waits until the data written is on the device.
+    fn flush(&self) -> VfsResult;
+}
+
+/// An ext4 filesystem that implements [`axfs_vfs::VfsOps`].
+pub struct Ext4FileSystem {
+    fs: Arc<Ext4>,
+    parent: Once<VfsNodeRef>,
+    root: Arc<Ext4Node>,
+}
+
+impl Ext4FileSystem {
+    /// Opens the filesystem on the device, replaying its journal if needed.
+    /// Timestamps are taken from `clock`.
+    ///
+    /// Fails with [`InvalidData`](axfs_vfs::VfsError::InvalidData) if the
+    /// device has no valid ext4 filesystem.
+    pub fn open(dev: Arc<dyn BlockDevice>, clock: fn() -> Duration) -> VfsResult<Self> {
+        let mut state = State::open(dev, clock)?;
+        state.start()?;
+        let fs = Ext4::new(state);
+        let root = fs.node(ROOT_INO);
+        Ok(Self {
+            fs,
+            parent: Once::new(),
+            root,
+        })
+    }
+
+    /// Whether the filesystem is mounted read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.fs.state.lock().read_only
+    }
+
+    /// Returns the root directory node in [`Arc<Ext4Node>`](Ext4Node).
+    pub fn root_dir_node(&self) -> Arc<Ext4Node> {
+        self.root.clone()
+    }
+}
+
+impl VfsOps for Ext4FileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        if let Some(parent) = mount_point.parent() {
+            self.fs
+                .set_root_parent(Some(self.parent.call_once(|| parent)));
+        } else {
+            self.fs.set_root_parent(None);
+        }
+        Ok(())
+    }
+
+    /// Frees the removed files that are still open, commits all changes and
+    /// marks the filesystem clean.
+    fn umount(&self) -> VfsResult {
+        self.fs.state.lock().stop()
+    }
+
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        let state = self.fs.state.lock();
+        let sb = &state.sb;
+        Ok(FileSystemInfo {
+            fs_type: EXT4_MAGIC as u64,
+            block_size: state.block_size as u64,
+            blocks: sb.blocks_count(),
+            blocks_free: sb.free_blocks(),
+            blocks_available: sb.free_blocks().saturating_sub(sb.reserved_blocks()),
+            files: sb.inodes_count() as u64,
+            files_free: sb.free_inodes() as u64,
+            name_len: 255,
+        })
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
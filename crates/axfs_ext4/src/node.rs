@@ -0,0 +1,581 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult, XattrFlags};
+use spin::{Mutex, RwLock};
+
+use crate::extent::init_root;
+use crate::fs::State;
+use crate::layout::*;
+
+/// The most links of an inode, a directory with more subdirectories has a
+/// link count of 1.
+const LINK_MAX: u16 = 65000;
+
+/// A mounted filesystem, shared by its nodes.
+///
+/// The state is locked for each operation. The table of open nodes is
+/// locked after the state, and no node may be dropped with the state locked,
+/// as dropping the last reference to a node locks it.
+pub(crate) struct Ext4 {
+    pub state: Mutex<State>,
+    nodes: Mutex<BTreeMap<u32, Weak<Ext4Node>>>,
+    /// The parent of the root directory, in the filesystem it's mounted on.
+    root_parent: RwLock<Weak<dyn VfsNodeOps>>,
+}
+
+impl Ext4 {
+    pub fn new(state: State) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(state),
+            nodes: Mutex::new(BTreeMap::new()),
+            root_parent: RwLock::new(Weak::<Ext4Node>::new()),
+        })
+    }
+
+    /// Returns the node of the inode, the same while it's open.
+    pub fn node(self: &Arc<Self>, ino: u32) -> Arc<Ext4Node> {
+        let mut nodes = self.nodes.lock();
+        if let Some(node) = nodes.get(&ino).and_then(Weak::upgrade) {
+            return node;
+        }
+        let node = Arc::new_cyclic(|this| Ext4Node {
+            fs: self.clone(),
+            ino,
+            this: this.clone(),
+        });
+        nodes.insert(ino, Arc::downgrade(&node));
+        node
+    }
+
+    fn is_open(&self, ino: u32) -> bool {
+        self.nodes
+            .lock()
+            .get(&ino)
+            .is_some_and(|node| node.strong_count() > 0)
+    }
+
+    pub fn set_root_parent(&self, parent: Option<&VfsNodeRef>) {
+        *self.root_parent.write() = parent.map_or(Weak::<Ext4Node>::new() as _, Arc::downgrade);
+    }
+
+    /// Drops a link to the inode, which is freed with its last link, or when
+    /// it's closed if it's still open.
+    fn unlink(&self, state: &mut State, inode: &mut Inode) -> VfsResult {
+        let links = if inode.is_dir() {
+            0
+        } else {
+            inode.links().saturating_sub(1)
+        };
+        inode.set_links(links);
+        inode.set_ctime(state.now());
+        if links > 0 {
+            return state.write_inode(inode);
+        }
+        if self.is_open(inode.ino) {
+            state.add_orphan(inode);
+            state.write_inode(inode)
+        } else {
+            state.write_inode(inode)?;
+            state.free_inode(inode.ino)
+        }
+    }
+}
+
+/// Counts a new subdirectory in the links of the directory.
+fn inc_dir_links(state: &mut State, dir: &mut Inode) {
+    if dir.links() == 1 || dir.links() >= LINK_MAX - 1 {
+        dir.set_links(1);
+        state.sb.set_ro_compat(RO_COMPAT_DIR_NLINK);
+    } else {
+        dir.set_links(dir.links() + 1);
+    }
+}
+
+fn dec_dir_links(dir: &mut Inode) {
+    if dir.links() > 2 {
+        dir.set_links(dir.links() - 1);
+    }
+}
+
+fn touch(state: &State, dir: &mut Inode) {
+    let now = state.now();
+    dir.set_mtime(now);
+    dir.set_ctime(now);
+}
+
+/// A file or a directory in an ext4 filesystem.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct Ext4Node {
+    fs: Arc<Ext4>,
+    ino: u32,
+    this: Weak<Ext4Node>,
+}
+
+impl Ext4Node {
+    /// The number of the inode of the node.
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+
+    /// Runs `f` on the inode with the state locked, and writes the inode if
+    /// it returns `Ok(true)`.
+    fn update(&self, f: impl FnOnce(&mut State, &mut Inode) -> VfsResult<bool>) -> VfsResult {
+        let mut state = self.fs.state.lock();
+        let mut inode = state.read_inode(self.ino)?;
+        if f(&mut state, &mut inode)? {
+            state.write_inode(&mut inode)?;
+            state.end_op()?;
+        }
+        Ok(())
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&mut State, &Inode) -> VfsResult<T>) -> VfsResult<T> {
+        let mut state = self.fs.state.lock();
+        let inode = state.read_inode(self.ino)?;
+        f(&mut state, &inode)
+    }
+
+    /// Returns the node of the directory, failing if this is not one.
+    fn dir_inode(&self, state: &mut State) -> VfsResult<Inode> {
+        let inode = state.read_inode(self.ino)?;
+        if !inode.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        Ok(inode)
+    }
+
+    fn child(&self, name: &str) -> VfsResult<VfsNodeRef> {
+        let mut state = self.fs.state.lock();
+        let dir = self.dir_inode(&mut state)?;
+        let ino = state.dir_lookup(&dir, name)?.ok_or(VfsError::NotFound)?;
+        Ok(self.fs.node(ino))
+    }
+
+    /// Creates a new node with the given name and type in this directory.
+    ///
+    /// Fails with [`VfsError::StorageFull`] if the filesystem is out of
+    /// blocks or inodes.
+    pub fn create_node(&self, name: &str, ty: VfsNodeType) -> VfsResult {
+        let mut state = self.fs.state.lock();
+        state.check_writable()?;
+        let mut dir = self.dir_inode(&mut state)?;
+        if state.dir_lookup(&dir, name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        let mode = match ty {
+            VfsNodeType::File => S_IFREG | VfsNodePerm::default_file().bits(),
+            VfsNodeType::Dir => S_IFDIR | VfsNodePerm::default_dir().bits(),
+            _ => return Err(VfsError::Unsupported),
+        };
+        let is_dir = ty == VfsNodeType::Dir;
+        let ino = state.alloc_inode(self.ino, is_dir)?;
+        let mut inode = state.new_inode(ino, mode);
+        if state.sb.has_incompat(INCOMPAT_EXTENTS) {
+            init_root(&mut inode);
+        }
+        let result = if is_dir {
+            inode.set_links(2);
+            state.dir_init(&mut inode, self.ino)
+        } else {
+            inode.set_links(1);
+            Ok(())
+        };
+        let result = result
+            .and_then(|_| state.write_inode(&mut inode))
+            .and_then(|_| state.dir_add(&mut dir, name, ino, ty));
+        if let Err(e) = result {
+            inode.set_links(0);
+            state.write_inode(&mut inode)?;
+            state.free_inode(ino)?;
+            return Err(e);
+        }
+        // `dir_add` may change the directory
+        if is_dir {
+            inc_dir_links(&mut state, &mut dir);
+        }
+        touch(&state, &mut dir);
+        state.write_inode(&mut dir)?;
+        state.end_op()
+    }
+
+    /// Removes the node with the given name in this directory.
+    pub fn remove_node(&self, name: &str) -> VfsResult {
+        let mut state = self.fs.state.lock();
+        state.check_writable()?;
+        let mut dir = self.dir_inode(&mut state)?;
+        let ino = state.dir_lookup(&dir, name)?.ok_or(VfsError::NotFound)?;
+        let mut inode = state.read_inode(ino)?;
+        if inode.is_dir() && !state.dir_is_empty(&inode)? {
+            return Err(VfsError::DirectoryNotEmpty);
+        }
+        state.dir_remove(&dir, name)?;
+        if inode.is_dir() {
+            dec_dir_links(&mut dir);
+        }
+        touch(&state, &mut dir);
+        state.write_inode(&mut dir)?;
+        self.fs.unlink(&mut state, &mut inode)?;
+        state.end_op()
+    }
+
+    /// Looks up the directory that holds the last component of `path`, and
+    /// returns it with the name of that component.
+    fn entry_of<'a>(&self, path: &'a str) -> VfsResult<(Arc<Ext4Node>, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let dir = this.lookup(dir)?;
+        match dir.as_any().downcast_ref::<Ext4Node>() {
+            Some(node) if Arc::ptr_eq(&node.fs, &self.fs) => Ok((self.fs.node(node.ino), name)),
+            _ if dir.get_attr()?.is_dir() => Err(VfsError::Unsupported), // in another filesystem
+            _ => Err(VfsError::NotADirectory),
+        }
+    }
+
+    /// Whether the directory `ino` is the directory `dir` or one of its
+    /// ancestors.
+    fn is_ancestor(state: &mut State, ino: u32, dir: u32) -> VfsResult<bool> {
+        let mut current = dir;
+        loop {
+            if current == ino {
+                return Ok(true);
+            }
+            if current == ROOT_INO {
+                return Ok(false);
+            }
+            let inode = state.read_inode(current)?;
+            current = state
+                .dir_lookup(&inode, "..")?
+                .ok_or(VfsError::InvalidData)?;
+        }
+    }
+}
+
+impl VfsNodeOps for Ext4Node {
+    fn file_id(&self) -> Option<(usize, u64)> {
+        Some((Arc::as_ptr(&self.fs) as usize, self.ino as u64))
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.read(|state, inode| {
+            let perm = VfsNodePerm::from_bits_truncate(inode.mode() & !S_IFMT);
+            let blocks = inode.sectors(state.block_size);
+            let attr = VfsNodeAttr::new(perm, inode.file_type(), inode.size(), blocks)
+                .with_owner(inode.uid(), inode.gid())
+                .with_nlink(inode.links() as u64)
+                .with_times(inode.atime(), inode.mtime(), inode.ctime());
+            Ok(attr)
+        })
+    }
+
+    fn set_mode(&self, mode: VfsNodePerm) -> VfsResult {
+        self.update(|state, inode| {
+            state.check_writable()?;
+            inode.set_mode((inode.mode() & S_IFMT) | mode.bits());
+            inode.set_ctime(state.now());
+            Ok(true)
+        })
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> VfsResult {
+        self.update(|state, inode| {
+            state.check_writable()?;
+            inode.set_owner(uid, gid);
+            inode.set_ctime(state.now());
+            Ok(true)
+        })
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        self.update(|state, inode| {
+            state.check_writable()?;
+            if let Some(atime) = atime {
+                inode.set_atime(atime);
+            }
+            if let Some(mtime) = mtime {
+                inode.set_mtime(mtime);
+            }
+            inode.set_ctime(state.now());
+            Ok(true)
+        })
+    }
+
+    fn get_xattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.read(|state, inode| state.get_xattr(inode, name))
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> VfsResult {
+        self.update(|state, inode| {
+            state.set_xattr(inode, name, Some(value), flags)?;
+            Ok(true)
+        })
+    }
+
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        self.read(|state, inode| state.list_xattr(inode))
+    }
+
+    fn remove_xattr(&self, name: &str) -> VfsResult {
+        self.update(|state, inode| {
+            state.set_xattr(inode, name, None, XattrFlags::empty())?;
+            Ok(true)
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.read(|state, inode| {
+            if inode.is_dir() {
+                return Err(VfsError::IsADirectory);
+            }
+            state.read_data(inode, offset, buf)
+        })
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut written = 0;
+        self.update(|state, inode| {
+            if inode.is_dir() {
+                return Err(VfsError::IsADirectory);
+            }
+            written = state.write_data(inode, offset, buf)?;
+            Ok(true)
+        })?;
+        Ok(written)
+    }
+
+    /// Commits the running transaction, which has all changes to the
+    /// filesystem, not only those of this file.
+    fn fsync(&self) -> VfsResult {
+        self.fs.state.lock().commit()
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.update(|state, inode| {
+            if inode.is_dir() {
+                return Err(VfsError::IsADirectory);
+            }
+            state.truncate_data(inode, size)?;
+            Ok(true)
+        })
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        if self.ino == ROOT_INO {
+            return self.fs.root_parent.read().upgrade();
+        }
+        self.child("..").ok()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        if !self.read(|_, inode| Ok(inode.is_dir()))? {
+            return Err(VfsError::NotADirectory);
+        }
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self.child(name),
+        }?;
+        if let Some(rest) = rest {
+            node.lookup(rest)
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let mut state = self.fs.state.lock();
+        let dir = self.dir_inode(&mut state)?;
+        let mut entries = Vec::new();
+        let mut index = 0;
+        state.dir_iter(&dir, |name, ino, ty| {
+            if index >= start_idx {
+                entries.push((String::from_utf8_lossy(name).to_string(), ino, ty));
+            }
+            index += 1;
+            entries.len() < dirents.len()
+        })?;
+        for (ent, (name, ino, ty)) in dirents.iter_mut().zip(&entries) {
+            let ty = match ty {
+                Some(ty) => *ty,
+                None => state.read_inode(*ino)?.file_type(),
+            };
+            *ent = VfsDirEntry::new(name, ty);
+        }
+        Ok(entries.len())
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at ext4: {}", ty, path);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.create(rest, ty),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.create(rest, ty),
+                _ => self.child(name)?.create(rest, ty),
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Ok(()) // already exists
+        } else {
+            self.create_node(name, ty)
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at ext4: {}", path);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.remove(rest),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.remove(rest),
+                _ => self.child(name)?.remove(rest),
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::InvalidInput) // remove '.' or '..
+        } else {
+            self.remove_node(name)
+        }
+    }
+
+    /// Renames a node within the filesystem. Both paths are relative to this
+    /// directory. An existing `dst_path` is replaced, if it's a file or an
+    /// empty directory as `src_path` is.
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at ext4: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.entry_of(src_path)?;
+        let (dst_dir, dst_name) = self.entry_of(dst_path)?;
+        let mut state = self.fs.state.lock();
+        state.check_writable()?;
+        let dir = src_dir.dir_inode(&mut state)?;
+        let ino = state
+            .dir_lookup(&dir, src_name)?
+            .ok_or(VfsError::NotFound)?;
+        let mut inode = state.read_inode(ino)?;
+        let ty = inode.file_type();
+        let moved_dir = inode.is_dir();
+        if moved_dir && Self::is_ancestor(&mut state, ino, dst_dir.ino)? {
+            return Err(VfsError::InvalidInput); // move a directory into itself
+        }
+
+        let mut dst = dst_dir.dir_inode(&mut state)?;
+        let replaced = state.dir_lookup(&dst, dst_name)?;
+        if let Some(replaced) = replaced {
+            if replaced == ino {
+                return Ok(());
+            }
+            let mut replaced = state.read_inode(replaced)?;
+            match (moved_dir, replaced.is_dir()) {
+                (true, true) if !state.dir_is_empty(&replaced)? => {
+                    return Err(VfsError::DirectoryNotEmpty)
+                }
+                (true, false) => return Err(VfsError::NotADirectory),
+                (false, true) => return Err(VfsError::IsADirectory),
+                _ => {}
+            }
+            state.dir_replace(&dst, dst_name, ino, ty)?;
+            if replaced.is_dir() {
+                dec_dir_links(&mut dst);
+            }
+            self.fs.unlink(&mut state, &mut replaced)?;
+        } else {
+            state.dir_add(&mut dst, dst_name, ino, ty)?;
+        }
+        if moved_dir && src_dir.ino != dst_dir.ino {
+            inc_dir_links(&mut state, &mut dst);
+        }
+        touch(&state, &mut dst);
+        state.write_inode(&mut dst)?;
+
+        // the source directory may be the destination one
+        let mut dir = state.read_inode(src_dir.ino)?;
+        state.dir_remove(&dir, src_name)?;
+        if moved_dir && src_dir.ino != dst_dir.ino {
+            dec_dir_links(&mut dir);
+            state.dir_replace(&inode, "..", dst_dir.ino, VfsNodeType::Dir)?;
+        }
+        touch(&state, &mut dir);
+        state.write_inode(&mut dir)?;
+        inode.set_ctime(state.now());
+        state.write_inode(&mut inode)?;
+        state.end_op()
+    }
+
+    /// Adds `node`, a file of this filesystem, at `path` as a hard link.
+    fn link(&self, path: &str, node: VfsNodeRef) -> VfsResult {
+        debug!("link at ext4: {}", path);
+        let Some(file) = node.as_any().downcast_ref::<Ext4Node>() else {
+            return if node.get_attr()?.is_dir() {
+                Err(VfsError::PermissionDenied) // no hard links to directories
+            } else {
+                Err(VfsError::Unsupported)
+            };
+        };
+        if !Arc::ptr_eq(&file.fs, &self.fs) {
+            return Err(VfsError::Unsupported); // in another filesystem
+        }
+        let (dir, name) = self.entry_of(path)?;
+        let mut state = self.fs.state.lock();
+        state.check_writable()?;
+        let mut inode = state.read_inode(file.ino)?;
+        if inode.is_dir() {
+            return Err(VfsError::PermissionDenied);
+        }
+        if inode.links() == 0 {
+            return Err(VfsError::NotFound);
+        }
+        if inode.links() >= LINK_MAX {
+            return Err(VfsError::StorageFull);
+        }
+        let mut dir_inode = dir.dir_inode(&mut state)?;
+        if state.dir_lookup(&dir_inode, name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        state.dir_add(&mut dir_inode, name, file.ino, inode.file_type())?;
+        touch(&state, &mut dir_inode);
+        state.write_inode(&mut dir_inode)?;
+        inode.set_links(inode.links() + 1);
+        inode.set_ctime(state.now());
+        state.write_inode(&mut inode)?;
+        state.end_op()
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+impl Drop for Ext4Node {
+    fn drop(&mut self) {
+        let mut nodes = self.fs.nodes.lock();
+        if nodes
+            .get(&self.ino)
+            .is_some_and(|node| node.strong_count() == 0)
+        {
+            nodes.remove(&self.ino);
+        }
+        drop(nodes);
+        let mut state = self.fs.state.lock();
+        if state.orphans.remove(&self.ino) {
+            let result = state.free_orphan(self.ino).and_then(|_| state.end_op());
+            if let Err(e) = result {
+                warn!("ext4: failed to free inode {}: {:?}", self.ino, e);
+            }
+        }
+    }
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
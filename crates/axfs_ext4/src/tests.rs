@@ -0,0 +1,494 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axfs_vfs::acl::{PosixAcl, ACL_ACCESS};
+use axfs_vfs::XattrFlags;
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+
+use crate::*;
+
+const IMG_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/resources/test.img");
+
+/// A disk in memory, that loses the writes after a number of flushes as if
+/// the power was cut.
+struct RamDisk {
+    data: Mutex<Vec<u8>>,
+    flushes: AtomicUsize,
+    crash_after: AtomicUsize,
+}
+
+impl RamDisk {
+    fn load() -> Arc<Self> {
+        let data = std::fs::read(IMG_PATH).expect("failed to load disk image");
+        Self::from(data)
+    }
+
+    fn from(data: Vec<u8>) -> Arc<Self> {
+        Arc::new(Self {
+            data: Mutex::new(data),
+            flushes: AtomicUsize::new(0),
+            crash_after: AtomicUsize::new(usize::MAX),
+        })
+    }
+
+    /// A new disk with the data of this one.
+    fn restart(&self) -> Arc<Self> {
+        Self::from(self.data.lock().unwrap().clone())
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn size(&self) -> u64 {
+        self.data.lock().unwrap().len() as u64
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult {
+        let data = self.data.lock().unwrap();
+        let start = offset as usize;
+        buf.copy_from_slice(&data[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult {
+        if self.flushes.load(Ordering::SeqCst) >= self.crash_after.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let mut data = self.data.lock().unwrap();
+        let start = offset as usize;
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&self) -> VfsResult {
+        self.flushes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+fn clock() -> Duration {
+    Duration::from_secs(1_700_000_000)
+}
+
+fn open(disk: &Arc<RamDisk>) -> Ext4FileSystem {
+    Ext4FileSystem::open(disk.clone(), clock).unwrap()
+}
+
+fn read_all(node: &VfsNodeRef) -> VfsResult<Vec<u8>> {
+    let mut buf = vec![0; node.get_attr()?.size() as usize];
+    assert_eq!(node.read_at(0, &mut buf)?, buf.len());
+    Ok(buf)
+}
+
+fn entries(dir: &VfsNodeRef) -> VfsResult<Vec<String>> {
+    let mut names = Vec::new();
+    let mut dirents: Vec<VfsDirEntry> = (0..8).map(|_| VfsDirEntry::default()).collect();
+    loop {
+        let n = dir.read_dir(names.len(), &mut dirents)?;
+        if n == 0 {
+            break;
+        }
+        for entry in &dirents[..n] {
+            names.push(String::from_utf8(entry.name_as_bytes().to_vec()).unwrap());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn test_read(fs: &Ext4FileSystem) -> VfsResult {
+    let root = fs.root_dir();
+    assert!(root.get_attr()?.is_dir());
+    assert_eq!(
+        entries(&root)?,
+        [".", "..", "long.txt", "lost+found", "short.txt", "very"]
+    );
+    assert_eq!(
+        read_all(&root.clone().lookup("short.txt")?)?,
+        b"Rust is cool!\n"
+    );
+    let long = read_all(&root.clone().lookup("long.txt")?)?;
+    assert_eq!(long, b"Rust is cool!\n".repeat(1000));
+
+    let node = root
+        .clone()
+        .lookup("///very/long//.././long//./path/./test.txt")?;
+    assert_eq!(node.get_attr()?.file_type(), VfsNodeType::File);
+    assert_eq!(read_all(&node)?, b"Rust is cool!\n");
+    let mut buf = [0; 8];
+    assert_eq!(node.read_at(5, &mut buf)?, 8);
+    assert_eq!(&buf, b"is cool!");
+    assert_eq!(node.read_at(100, &mut buf)?, 0);
+
+    assert_eq!(
+        root.clone().lookup("short.txt/").err(),
+        Some(VfsError::NotADirectory)
+    );
+    assert_eq!(
+        root.clone().lookup("very/none").err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(
+        root.clone().lookup("very")?.read_at(0, &mut buf).err(),
+        Some(VfsError::IsADirectory)
+    );
+    assert!(Arc::ptr_eq(
+        &root.clone().lookup("very/long/path/..")?,
+        &root.clone().lookup("very/./long")?,
+    ));
+    let path = root.clone().lookup("very/long/path")?;
+    assert!(Arc::ptr_eq(
+        &path.parent().unwrap(),
+        &root.clone().lookup("very/long")?
+    ));
+    assert!(root.parent().is_none());
+    Ok(())
+}
+
+fn test_write(fs: &Ext4FileSystem) -> VfsResult {
+    let root = fs.root_dir();
+    root.create("dir", VfsNodeType::Dir)?;
+    root.create("dir/sub", VfsNodeType::Dir)?;
+    root.create("dir/sub/file", VfsNodeType::File)?;
+    assert_eq!(
+        root.create("dir", VfsNodeType::Dir).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.create("none/file", VfsNodeType::File).err(),
+        Some(VfsError::NotFound)
+    );
+    let file = root.clone().lookup("dir/sub/file")?;
+    let attr = file.get_attr()?;
+    assert_eq!(attr.perm().mode(), 0o666);
+    assert_eq!(attr.size(), 0);
+    assert_eq!(root.clone().lookup("dir")?.get_attr()?.perm().mode(), 0o755);
+
+    // across several blocks, and leaving a hole
+    let data: Vec<u8> = (0..10000u32).map(|i| (i % 251) as u8).collect();
+    assert_eq!(file.write_at(3000, &data)?, data.len());
+    let read = read_all(&file)?;
+    assert_eq!(read.len(), 13000);
+    assert!(read[..3000].iter().all(|&b| b == 0));
+    assert_eq!(read[3000..], data);
+    assert_eq!(file.get_attr()?.blocks(), 2 * 11);
+
+    file.truncate(5000)?;
+    file.truncate(6000)?;
+    let read = read_all(&file)?;
+    assert_eq!(read[3000..5000], data[..2000]);
+    assert!(read[5000..].iter().all(|&b| b == 0));
+    assert_eq!(file.get_attr()?.blocks(), 2 * 3);
+
+    // many extents need a deeper tree
+    let sparse = root.clone().lookup("dir")?;
+    sparse.create("sparse", VfsNodeType::File)?;
+    let sparse = sparse.lookup("sparse")?;
+    for i in 0..400 {
+        sparse.write_at(i * 2048, &[(i % 250) as u8 + 1])?;
+    }
+    for i in 0..400 {
+        let mut buf = [0; 2];
+        sparse.read_at(i * 2048, &mut buf)?;
+        assert_eq!(buf, [(i % 250) as u8 + 1, 0]);
+    }
+    assert_eq!(sparse.get_attr()?.size(), 399 * 2048 + 1);
+    sparse.truncate(0)?;
+    assert_eq!(sparse.get_attr()?.blocks(), 0);
+    Ok(())
+}
+
+fn test_dir(fs: &Ext4FileSystem) -> VfsResult {
+    const N: usize = 1000;
+    let root = fs.root_dir();
+    root.create("many", VfsNodeType::Dir)?;
+    let dir = root.clone().lookup("many")?;
+    for i in 0..N {
+        dir.create(&format!("file-with-a-long-name-{}", i), VfsNodeType::File)?;
+    }
+    for i in 0..N {
+        dir.clone()
+            .lookup(&format!("file-with-a-long-name-{}", i))?;
+    }
+    assert_eq!(entries(&dir)?.len(), N + 2);
+    for i in (0..N).step_by(2) {
+        dir.remove(&format!("file-with-a-long-name-{}", i))?;
+    }
+    for i in 0..N {
+        let found = dir.clone().lookup(&format!("file-with-a-long-name-{}", i));
+        assert_eq!(found.is_ok(), i % 2 == 1);
+    }
+    assert!(Arc::ptr_eq(&dir.clone().lookup("..")?, &root));
+
+    assert_eq!(root.remove("many").err(), Some(VfsError::DirectoryNotEmpty));
+    assert_eq!(root.remove("many/..").err(), Some(VfsError::InvalidInput));
+    assert_eq!(
+        root.remove("dir/sub").err(),
+        Some(VfsError::DirectoryNotEmpty)
+    );
+    root.remove("dir/sub/file")?;
+    root.remove("dir/sub")?;
+    assert_eq!(root.remove("dir/sub").err(), Some(VfsError::NotFound));
+    Ok(())
+}
+
+fn test_rename_link(fs: &Ext4FileSystem) -> VfsResult {
+    let root = fs.root_dir();
+    let file = root.clone().lookup("short.txt")?;
+    root.rename("short.txt", "many/moved.txt")?;
+    assert_eq!(
+        root.clone().lookup("short.txt").err(),
+        Some(VfsError::NotFound)
+    );
+    assert!(Arc::ptr_eq(&root.clone().lookup("many/moved.txt")?, &file));
+
+    // the directory moves into an indexed one, and `..` follows
+    root.rename("very/long", "many/long")?;
+    let path = root.clone().lookup("many/long/path")?;
+    assert!(Arc::ptr_eq(
+        &path.lookup("../..")?,
+        &root.clone().lookup("many")?
+    ));
+    assert_eq!(
+        root.rename("many", "many/long/many").err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert_eq!(
+        root.rename("many/long", "long.txt").err(),
+        Some(VfsError::NotADirectory)
+    );
+
+    root.link("hard", file.clone())?;
+    assert_eq!(
+        root.link("hard", file.clone()).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.link("dir2", root.clone().lookup("many")?).err(),
+        Some(VfsError::PermissionDenied)
+    );
+    root.remove("many/moved.txt")?;
+    assert_eq!(read_all(&root.clone().lookup("hard")?)?, b"Rust is cool!\n");
+
+    // replacing a file
+    root.rename("hard", "long.txt")?;
+    assert_eq!(
+        read_all(&root.clone().lookup("long.txt")?)?,
+        b"Rust is cool!\n"
+    );
+    Ok(())
+}
+
+fn test_xattr(fs: &Ext4FileSystem) -> VfsResult {
+    let node = fs.root_dir().lookup("long.txt")?;
+    node.set_xattr("user.small", b"value", XattrFlags::empty())?;
+    node.set_xattr("user.large", &[7; 500], XattrFlags::empty())?;
+    node.set_xattr("trusted.other", &[8; 100], XattrFlags::empty())?;
+    assert_eq!(
+        node.set_xattr("user.small", b"new", XattrFlags::CREATE)
+            .err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        node.set_xattr("user.none", b"new", XattrFlags::REPLACE)
+            .err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(
+        node.set_xattr("unknown.name", b"new", XattrFlags::empty())
+            .err(),
+        Some(VfsError::Unsupported)
+    );
+    assert_eq!(node.get_xattr("user.small")?, b"value");
+    assert_eq!(node.get_xattr("user.large")?, [7; 500]);
+    let mut names = node.list_xattr()?;
+    names.sort();
+    assert_eq!(names, ["trusted.other", "user.large", "user.small"]);
+
+    let acl = PosixAcl::from_mode(VfsNodePerm::from_bits_truncate(0o640));
+    node.set_xattr(ACL_ACCESS, &acl.to_bytes(), XattrFlags::empty())?;
+    assert_eq!(node.get_xattr(ACL_ACCESS)?, acl.to_bytes());
+
+    node.remove_xattr("user.small")?;
+    assert_eq!(node.get_xattr("user.small").err(), Some(VfsError::NotFound));
+    assert_eq!(
+        node.remove_xattr("user.small").err(),
+        Some(VfsError::NotFound)
+    );
+    Ok(())
+}
+
+fn test_persist(fs: &Ext4FileSystem) -> VfsResult {
+    let root = fs.root_dir();
+    let long = root.clone().lookup("long.txt")?;
+    assert_eq!(read_all(&long)?, b"Rust is cool!\n");
+    assert_eq!(long.get_xattr("user.large")?, [7; 500]);
+    assert_eq!(long.get_xattr("user.small").err(), Some(VfsError::NotFound));
+    let many = root.clone().lookup("many")?;
+    assert_eq!(entries(&many)?.len(), 500 + 3);
+    assert!(many.clone().lookup("file-with-a-long-name-999").is_ok());
+    assert_eq!(
+        read_all(&many.lookup("long/path/test.txt")?)?,
+        b"Rust is cool!\n"
+    );
+    assert_eq!(
+        entries(&root.clone().lookup("dir")?)?,
+        [".", "..", "sparse"]
+    );
+    assert_eq!(entries(&root.lookup("very")?)?, [".", ".."]);
+    Ok(())
+}
+
+#[test]
+fn test_ext4() {
+    let disk = RamDisk::load();
+    let fs = open(&disk);
+    assert!(!fs.is_read_only());
+    test_read(&fs).unwrap();
+    test_write(&fs).unwrap();
+    test_dir(&fs).unwrap();
+    test_rename_link(&fs).unwrap();
+    test_xattr(&fs).unwrap();
+    fs.umount().unwrap();
+    drop(fs);
+
+    let fs = open(&disk);
+    test_persist(&fs).unwrap();
+}
+
+#[test]
+fn test_corrupted_index() {
+    let disk = RamDisk::load();
+    let fs = open(&disk);
+    let root = fs.root_dir();
+    root.create("many", VfsNodeType::Dir).unwrap();
+    let dir = root.lookup("many").unwrap();
+    for i in 0..200 {
+        dir.create(&format!("file-with-a-long-name-{}", i), VfsNodeType::File)
+            .unwrap();
+    }
+    let block_size = fs.statfs().unwrap().block_size as usize;
+    fs.umount().unwrap();
+    drop((dir, fs));
+
+    // the root of the index, with a limit and a count past the block, and
+    // its copies in the journal
+    let mut roots = 0;
+    for block in disk.data.lock().unwrap().chunks_exact_mut(block_size) {
+        if &block[8..9] == b"."
+            && u16::from_le_bytes([block[16], block[17]]) as usize == block_size - 12
+            && block[24 + 5] == 8
+        {
+            block[32..34].copy_from_slice(&0xffffu16.to_le_bytes());
+            block[34..36].copy_from_slice(&0x2000u16.to_le_bytes());
+            roots += 1;
+        }
+    }
+    assert!(roots > 0, "no indexed directory");
+
+    let fs = open(&disk);
+    let dir = fs.root_dir().lookup("many").unwrap();
+    assert_eq!(
+        dir.clone().lookup("file-with-a-long-name-7").err(),
+        Some(VfsError::Io)
+    );
+    assert_eq!(
+        dir.create("new", VfsNodeType::File).err(),
+        Some(VfsError::Io)
+    );
+}
+
+#[test]
+fn test_orphan() {
+    let disk = RamDisk::load();
+    let fs = open(&disk);
+    let root = fs.root_dir();
+    let free = fs.statfs().unwrap().blocks_free;
+    root.create("file", VfsNodeType::File).unwrap();
+    let file = root.clone().lookup("file").unwrap();
+    file.write_at(0, &[1; 100000]).unwrap();
+    root.remove("file").unwrap();
+    assert_eq!(root.clone().lookup("file").err(), Some(VfsError::NotFound));
+
+    // still usable while open
+    file.write_at(100000, &[2; 10]).unwrap();
+    let mut buf = [0; 4];
+    assert_eq!(file.read_at(99998, &mut buf).unwrap(), 4);
+    assert_eq!(buf, [1, 1, 2, 2]);
+    assert!(fs.statfs().unwrap().blocks_free < free);
+    root.fsync().unwrap();
+
+    // freed on the next mount after a crash
+    let crashed = disk.restart();
+    drop(file);
+    root.fsync().unwrap();
+    assert_eq!(fs.statfs().unwrap().blocks_free, free);
+    let fs = open(&crashed);
+    assert_eq!(fs.statfs().unwrap().blocks_free, free);
+}
+
+#[test]
+fn test_recovery() {
+    let disk = RamDisk::load();
+    let fs = open(&disk);
+    let root = fs.root_dir();
+    root.create("new", VfsNodeType::Dir).unwrap();
+    root.create("new/file", VfsNodeType::File).unwrap();
+    let file = root.clone().lookup("new/file").unwrap();
+    file.write_at(0, b"journaled").unwrap();
+    root.remove("short.txt").unwrap();
+
+    // the power is cut after the commit, before anything is written in place
+    let flushes = disk.flushes.load(Ordering::SeqCst);
+    disk.crash_after.store(flushes + 2, Ordering::SeqCst);
+    file.fsync().unwrap();
+
+    let fs = open(&disk.restart());
+    let root = fs.root_dir();
+    assert_eq!(
+        read_all(&root.clone().lookup("new/file").unwrap()).unwrap(),
+        b"journaled"
+    );
+    assert_eq!(
+        root.clone().lookup("short.txt").err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(
+        entries(&root).unwrap(),
+        [".", "..", "long.txt", "lost+found", "new", "very"]
+    );
+}
+
+#[test]
+fn test_large_transaction() {
+    let disk = RamDisk::load();
+    // shrinks the log to a few blocks, so that the transactions do not fit
+    for block in disk.data.lock().unwrap().chunks_exact_mut(1024) {
+        if block[..8] == [0xc0, 0x3b, 0x39, 0x98, 0, 0, 0, 4] {
+            let first = u32::from_be_bytes(block[0x14..0x18].try_into().unwrap());
+            block[0x10..0x14].copy_from_slice(&(first + 5).to_be_bytes());
+        }
+    }
+    let fs = open(&disk);
+    let root = fs.root_dir();
+    let flushes = disk.flushes.load(Ordering::SeqCst);
+    root.create("new", VfsNodeType::Dir).unwrap();
+    // each part is logged, written in place and checkpointed
+    assert!(disk.flushes.load(Ordering::SeqCst) - flushes >= 8);
+    root.create("new/file", VfsNodeType::File).unwrap();
+    let file = root.clone().lookup("new/file").unwrap();
+    file.write_at(0, b"committed in parts").unwrap();
+    root.remove("short.txt").unwrap();
+    file.fsync().unwrap();
+
+    let fs = open(&disk.restart());
+    let root = fs.root_dir();
+    assert_eq!(
+        read_all(&root.clone().lookup("new/file").unwrap()).unwrap(),
+        b"committed in parts"
+    );
+    assert_eq!(
+        entries(&root).unwrap(),
+        [".", "..", "long.txt", "lost+found", "new", "very"]
+    );
+}
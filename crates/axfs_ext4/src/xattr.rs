@@ -0,0 +1,408 @@
+//! Extended attributes, in the space after the extra fields of the inode and
+//! in a block of attributes.
+//!
+//! The attributes of an inode are loaded, changed, and laid out again: the
+//! inode is filled first, and the rest go to the block. A block shared by
+//! several inodes is copied before it's changed. POSIX ACLs are kept in the
+//! shorter format of ext4 and converted to and from the format of Linux.
+
+use alloc::{string::String, vec, vec::Vec};
+
+use axfs_vfs::acl::{ACL_ACCESS, ACL_DEFAULT};
+use axfs_vfs::{VfsError, VfsResult, XattrFlags};
+
+use crate::crc::crc32c;
+use crate::fs::State;
+use crate::layout::*;
+
+const XATTR_MAGIC: u32 = 0xea02_0000;
+/// The size of the header of a block of attributes.
+const BLOCK_HEADER: usize = 32;
+/// The size of the fixed part of an entry.
+const ENTRY_HEADER: usize = 16;
+
+const INDEX_ACL_ACCESS: u8 = 2;
+const INDEX_ACL_DEFAULT: u8 = 3;
+/// The prefixes of names, by their index in the entries.
+const PREFIXES: [(u8, &str); 4] = [
+    (1, "user."),
+    (4, "trusted."),
+    (6, "security."),
+    (7, "system."),
+];
+
+const ACL_EXT4_VERSION: u32 = 1;
+const ACL_VERSION: u32 = 2;
+
+fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// An attribute, its name without the prefix of the index.
+#[derive(Debug, Clone)]
+struct Attr {
+    index: u8,
+    name: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Attr {
+    fn sort_key(&self) -> (u8, usize, &[u8]) {
+        (self.index, self.name.len(), &self.name)
+    }
+
+    /// The space taken by the entry and the value.
+    fn size(&self) -> usize {
+        pad4(ENTRY_HEADER + self.name.len()) + pad4(self.value.len())
+    }
+
+    fn hash(&self) -> u32 {
+        let mut hash = 0u32;
+        for &c in &self.name {
+            hash = (hash << 5) ^ (hash >> 27) ^ c as u32;
+        }
+        for word in self.value.chunks(4) {
+            let mut raw = [0; 4];
+            raw[..word.len()].copy_from_slice(word);
+            hash = (hash << 16) ^ (hash >> 16) ^ u32::from_le_bytes(raw);
+        }
+        hash
+    }
+}
+
+/// Splits a name into the index of its prefix and the rest.
+fn split_name(name: &str) -> VfsResult<(u8, &[u8])> {
+    if name == ACL_ACCESS {
+        return Ok((INDEX_ACL_ACCESS, b""));
+    }
+    if name == ACL_DEFAULT {
+        return Ok((INDEX_ACL_DEFAULT, b""));
+    }
+    let (index, rest) = PREFIXES
+        .iter()
+        .find_map(|&(index, prefix)| name.strip_prefix(prefix).map(|rest| (index, rest)))
+        .ok_or(VfsError::Unsupported)?;
+    if rest.is_empty() || rest.len() > 255 {
+        return Err(VfsError::InvalidInput);
+    }
+    Ok((index, rest.as_bytes()))
+}
+
+fn full_name(attr: &Attr) -> Option<String> {
+    let prefix = match attr.index {
+        INDEX_ACL_ACCESS => return Some(ACL_ACCESS.into()),
+        INDEX_ACL_DEFAULT => return Some(ACL_DEFAULT.into()),
+        index => PREFIXES.iter().find(|p| p.0 == index)?.1,
+    };
+    let name = core::str::from_utf8(&attr.name).ok()?;
+    Some(String::from(prefix) + name)
+}
+
+/// Converts an ACL from the format of Linux to that of ext4, where the
+/// entries without an id are shorter.
+fn acl_to_disk(value: &[u8]) -> VfsResult<Vec<u8>> {
+    if value.len() < 4 || le32(value, 0) != ACL_VERSION || (value.len() - 4) % 8 != 0 {
+        return Err(VfsError::InvalidInput);
+    }
+    let mut out = ACL_EXT4_VERSION.to_le_bytes().to_vec();
+    for raw in value[4..].chunks_exact(8) {
+        match le16(raw, 0) {
+            0x02 | 0x08 => out.extend_from_slice(raw),
+            0x01 | 0x04 | 0x10 | 0x20 => out.extend_from_slice(&raw[..4]),
+            _ => return Err(VfsError::InvalidInput),
+        }
+    }
+    Ok(out)
+}
+
+fn acl_from_disk(value: &[u8]) -> VfsResult<Vec<u8>> {
+    if value.len() < 4 || le32(value, 0) != ACL_EXT4_VERSION {
+        return Err(VfsError::InvalidData);
+    }
+    let mut out = ACL_VERSION.to_le_bytes().to_vec();
+    let mut off = 4;
+    while off < value.len() {
+        if off + 4 > value.len() {
+            return Err(VfsError::InvalidData);
+        }
+        let tag = le16(value, off);
+        out.extend_from_slice(&value[off..off + 4]);
+        if tag == 0x02 || tag == 0x08 {
+            if off + 8 > value.len() {
+                return Err(VfsError::InvalidData);
+            }
+            out.extend_from_slice(&value[off + 4..off + 8]);
+            off += 8;
+        } else {
+            out.extend_from_slice(&u32::MAX.to_le_bytes());
+            off += 4;
+        }
+    }
+    Ok(out)
+}
+
+/// Parses the entries from `start` in `data`, with the offsets of the values
+/// from `base`.
+fn parse_entries(data: &[u8], start: usize, base: usize, attrs: &mut Vec<Attr>) -> VfsResult {
+    let mut off = start;
+    while off + 4 <= data.len() && le32(data, off) != 0 {
+        if off + ENTRY_HEADER > data.len() {
+            return Err(VfsError::InvalidData);
+        }
+        let name_len = data[off] as usize;
+        let value_offs = base + le16(data, off + 2) as usize;
+        let value_size = le32(data, off + 8) as usize;
+        if le32(data, off + 4) != 0 {
+            warn!("ext4: values of extended attributes in inodes are not supported");
+            return Err(VfsError::Unsupported);
+        }
+        let name_end = off + ENTRY_HEADER + name_len;
+        if name_end > data.len() || value_offs + value_size > data.len() {
+            warn!("ext4: bad extended attribute entry");
+            return Err(VfsError::InvalidData);
+        }
+        attrs.push(Attr {
+            index: data[off + 1],
+            name: data[off + ENTRY_HEADER..name_end].to_vec(),
+            value: data[value_offs..value_offs + value_size].to_vec(),
+        });
+        off = pad4(name_end);
+    }
+    Ok(())
+}
+
+/// Lays out the entries in `data` from `start`, with the values at its end
+/// and their offsets from `base`. Entries in a block have their hashes.
+fn write_entries(data: &mut [u8], start: usize, base: usize, attrs: &[&Attr], hashed: bool) {
+    let mut off = start;
+    let mut value_end = data.len();
+    for attr in attrs {
+        let value_offs = value_end - pad4(attr.value.len());
+        data[value_offs..value_offs + attr.value.len()].copy_from_slice(&attr.value);
+        value_end = value_offs;
+
+        data[off] = attr.name.len() as u8;
+        data[off + 1] = attr.index;
+        let offs = if attr.value.is_empty() {
+            0
+        } else {
+            value_offs - base
+        };
+        put16(data, off + 2, offs as u16);
+        put32(data, off + 4, 0);
+        put32(data, off + 8, attr.value.len() as u32);
+        put32(data, off + 12, if hashed { attr.hash() } else { 0 });
+        data[off + ENTRY_HEADER..off + ENTRY_HEADER + attr.name.len()].copy_from_slice(&attr.name);
+        off = pad4(off + ENTRY_HEADER + attr.name.len());
+    }
+}
+
+impl State {
+    /// Reads the block of attributes, checking its header and checksum.
+    fn read_xattr_block(&mut self, block: u64) -> VfsResult<Vec<u8>> {
+        if block < self.sb.first_data_block() || block >= self.sb.blocks_count() {
+            return Err(VfsError::InvalidData);
+        }
+        let data = self.cache.read(block)?.to_vec();
+        if le32(&data, 0) != XATTR_MAGIC || le32(&data, 8) != 1 {
+            warn!("ext4: bad extended attribute block {}", block);
+            return Err(VfsError::InvalidData);
+        }
+        if let Some(seed) = self.csum_seed {
+            if xattr_block_checksum(seed, block, &data) != le32(&data, 0x10) {
+                warn!("ext4: bad checksum of extended attribute block {}", block);
+                return Err(VfsError::InvalidData);
+            }
+        }
+        Ok(data)
+    }
+
+    fn write_xattr_block(&mut self, block: u64, mut data: Vec<u8>) -> VfsResult {
+        if let Some(seed) = self.csum_seed {
+            let csum = xattr_block_checksum(seed, block, &data);
+            put32(&mut data, 0x10, csum);
+        }
+        self.cache.write(block)?.copy_from_slice(&data);
+        Ok(())
+    }
+
+    fn load_xattrs(&mut self, inode: &Inode) -> VfsResult<Vec<Attr>> {
+        let mut attrs = Vec::new();
+        if let Some(area) = inode.xattr_area() {
+            if le32(area, 0) == XATTR_MAGIC {
+                parse_entries(area, 4, 4, &mut attrs)?;
+            }
+        }
+        if inode.file_acl() != 0 {
+            let data = self.read_xattr_block(inode.file_acl())?;
+            parse_entries(&data, BLOCK_HEADER, 0, &mut attrs)?;
+        }
+        Ok(attrs)
+    }
+
+    pub fn get_xattr(&mut self, inode: &Inode, name: &str) -> VfsResult<Vec<u8>> {
+        let (index, name) = split_name(name)?;
+        let attr = self
+            .load_xattrs(inode)?
+            .into_iter()
+            .find(|a| a.index == index && a.name == name)
+            .ok_or(VfsError::NotFound)?;
+        match index {
+            INDEX_ACL_ACCESS | INDEX_ACL_DEFAULT => acl_from_disk(&attr.value),
+            _ => Ok(attr.value),
+        }
+    }
+
+    pub fn list_xattr(&mut self, inode: &Inode) -> VfsResult<Vec<String>> {
+        Ok(self
+            .load_xattrs(inode)?
+            .iter()
+            .filter_map(full_name)
+            .collect())
+    }
+
+    /// Sets the attribute, or removes it if `value` is `None`. The inode must
+    /// be written by the caller.
+    pub fn set_xattr(
+        &mut self,
+        inode: &mut Inode,
+        name: &str,
+        value: Option<&[u8]>,
+        flags: XattrFlags,
+    ) -> VfsResult {
+        self.check_writable()?;
+        let (index, name) = split_name(name)?;
+        let mut attrs = self.load_xattrs(inode)?;
+        let found = attrs
+            .iter()
+            .position(|a| a.index == index && a.name == name);
+        match (found, value) {
+            (Some(_), Some(_)) if flags.contains(XattrFlags::CREATE) => {
+                return Err(VfsError::AlreadyExists)
+            }
+            (None, Some(_)) if flags.contains(XattrFlags::REPLACE) => {
+                return Err(VfsError::NotFound)
+            }
+            (None, None) => return Err(VfsError::NotFound),
+            _ => {}
+        }
+        if let Some(i) = found {
+            attrs.remove(i);
+        }
+        if let Some(value) = value {
+            let value = match index {
+                INDEX_ACL_ACCESS | INDEX_ACL_DEFAULT => acl_to_disk(value)?,
+                _ => value.to_vec(),
+            };
+            attrs.push(Attr {
+                index,
+                name: name.to_vec(),
+                value,
+            });
+        }
+        self.store_xattrs(inode, attrs)?;
+        inode.set_ctime(self.now());
+        Ok(())
+    }
+
+    /// Lays out the attributes in the inode and the block.
+    fn store_xattrs(&mut self, inode: &mut Inode, mut attrs: Vec<Attr>) -> VfsResult {
+        attrs.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        // the magic and the end of the entries
+        let mut in_inode_space = inode.xattr_area().map_or(0, |area| area.len() - 8);
+        let mut block_space = self.block_size - BLOCK_HEADER - 4;
+        let (mut in_inode, mut in_block) = (Vec::new(), Vec::new());
+        for attr in &attrs {
+            if attr.size() <= in_inode_space {
+                in_inode_space -= attr.size();
+                in_inode.push(attr);
+            } else if attr.size() <= block_space {
+                block_space -= attr.size();
+                in_block.push(attr);
+            } else {
+                return Err(VfsError::StorageFull);
+            }
+        }
+
+        let old = inode.file_acl();
+        let spb = (self.block_size / 512) as u64;
+        if in_block.is_empty() {
+            if old != 0 {
+                self.release_xattr_block(old)?;
+                inode.set_file_acl(0);
+                inode.set_sectors(inode.sectors(self.block_size) - spb);
+            }
+        } else {
+            let shared = old != 0 && le32(&self.read_xattr_block(old)?, 4) > 1;
+            let block = if old != 0 && !shared {
+                old
+            } else {
+                let goal = self.group_first_block((inode.ino - 1) / self.sb.inodes_per_group());
+                let (block, _) = self.alloc_blocks(goal, 1)?;
+                if old != 0 {
+                    self.release_xattr_block(old)?;
+                } else {
+                    inode.set_sectors(inode.sectors(self.block_size) + spb);
+                }
+                block
+            };
+            let mut data = vec![0; self.block_size];
+            put32(&mut data, 0, XATTR_MAGIC);
+            put32(&mut data, 4, 1);
+            put32(&mut data, 8, 1);
+            write_entries(&mut data, BLOCK_HEADER, 0, &in_block, true);
+            let hashes: Vec<u32> = in_block.iter().map(|a| a.hash()).collect();
+            let hash = if hashes.contains(&0) {
+                0
+            } else {
+                hashes
+                    .iter()
+                    .fold(0u32, |hash, &h| (hash << 16) ^ (hash >> 16) ^ h)
+            };
+            put32(&mut data, 0xc, hash);
+            self.cache.zeroed(block);
+            self.write_xattr_block(block, data)?;
+            inode.set_file_acl(block);
+        }
+
+        if let Some(area) = inode.xattr_area_mut() {
+            area.fill(0);
+            if !in_inode.is_empty() {
+                put32(area, 0, XATTR_MAGIC);
+                write_entries(area, 4, 4, &in_inode, false);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops a reference to the block of attributes, freeing it with the
+    /// last one.
+    fn release_xattr_block(&mut self, block: u64) -> VfsResult {
+        let mut data = self.read_xattr_block(block)?;
+        let refcount = le32(&data, 4);
+        if refcount <= 1 {
+            self.free_blocks_later(block, 1);
+        } else {
+            put32(&mut data, 4, refcount - 1);
+            self.write_xattr_block(block, data)?;
+        }
+        Ok(())
+    }
+
+    /// Drops the block of attributes of an inode being freed.
+    pub fn free_xattr_block(&mut self, inode: &mut Inode) -> VfsResult {
+        let block = inode.file_acl();
+        if block != 0 {
+            self.release_xattr_block(block)?;
+            inode.set_file_acl(0);
+        }
+        Ok(())
+    }
+}
+
+fn xattr_block_checksum(seed: u32, block: u64, data: &[u8]) -> u32 {
+    let csum = crc32c(seed, &block.to_le_bytes());
+    let csum = crc32c(csum, &data[..0x10]);
+    let csum = crc32c(csum, &[0; 4]);
+    crc32c(csum, &data[0x14..])
+}
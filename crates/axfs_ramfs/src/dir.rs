@@ -1,12 +1,15 @@
 use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
+use core::time::Duration;
 
-use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
-use axfs_vfs::{VfsError, VfsResult};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult, XattrFlags};
 use spin::RwLock;
 
 use crate::file::FileNode;
+use crate::meta::Meta;
+use crate::super_block::SuperBlock;
 use crate::Interrupts;
 
 /// The directory node in the RAM filesystem.
@@ -16,21 +19,34 @@ pub struct DirNode {
     this: Weak<DirNode>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
     children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    sb: Arc<SuperBlock>,
+    meta: RwLock<Meta>,
 }
 
 impl DirNode {
-    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
-        Arc::new_cyclic(|this| Self {
+    pub(super) fn new(
+        parent: Option<Weak<dyn VfsNodeOps>>,
+        sb: &Arc<SuperBlock>,
+    ) -> VfsResult<Arc<Self>> {
+        sb.alloc_inode()?;
+        Ok(Arc::new_cyclic(|this| Self {
             this: this.clone(),
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
             children: RwLock::new(BTreeMap::new()),
-        })
+            sb: sb.clone(),
+            meta: RwLock::new(Meta::new(VfsNodePerm::default_dir(), 2, sb.now())),
+        }))
     }
 
     pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
         *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
     }
 
+    /// Records a change of the entries.
+    fn modified(&self) {
+        self.meta.write().modified(self.sb.now());
+    }
+
     /// Returns a string list of all entries in this directory.
     pub fn get_entries(&self) -> Vec<String> {
         self.children.read().keys().cloned().collect()
@@ -42,6 +58,9 @@ impl DirNode {
     }
 
     /// Creates a new node with the given name and type in this directory.
+    ///
+    /// Fails with [`VfsError::StorageFull`] if the filesystem is out of
+    /// nodes.
     pub fn create_node(&self, name: &str, ty: VfsNodeType) -> VfsResult {
         if self.exist(name) {
             log::error!("AlreadyExists {}", name);
@@ -53,13 +72,24 @@ impl DirNode {
                 if name == "interrupts" {
                     Arc::new(Interrupts)
                 } else {
-                    Arc::new(FileNode::new())
+                    Arc::new(FileNode::new_in(&self.sb)?)
                 }
             }
-            VfsNodeType::Dir => Self::new(Some(self.this.clone())),
+            VfsNodeType::Dir => Self::new(Some(self.this.clone()), &self.sb)?,
             _ => return Err(VfsError::Unsupported),
         };
         self.children.write().insert(name.into(), node);
+        self.modified();
+        Ok(())
+    }
+
+    /// Adds an existing node with the given name to this directory.
+    pub fn add_node(&self, name: &str, node: VfsNodeRef) -> VfsResult {
+        if self.exist(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        self.children.write().insert(name.into(), node);
+        self.modified();
         Ok(())
     }
 
@@ -72,14 +102,103 @@ impl DirNode {
                 return Err(VfsError::DirectoryNotEmpty);
             }
         }
+        if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+            file.drop_link();
+        }
         children.remove(name);
+        drop(children);
+        self.modified();
         Ok(())
     }
+
+    /// Looks up the directory that holds the last component of `path`, and
+    /// returns it with the name of that component.
+    fn entry_of<'a>(&self, path: &'a str) -> VfsResult<(VfsNodeRef, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let dir = this.lookup(dir)?;
+        if dir.as_any().downcast_ref::<DirNode>().is_some() {
+            Ok((dir, name))
+        } else if dir.get_attr()?.is_dir() {
+            Err(VfsError::Unsupported) // in another filesystem
+        } else {
+            Err(VfsError::NotADirectory)
+        }
+    }
+
+    /// Whether `node` is this directory or one of its ancestors in the
+    /// filesystem.
+    fn is_ancestor(&self, node: &VfsNodeRef) -> bool {
+        let mut dir = self.this.upgrade().map(|this| this as VfsNodeRef);
+        while let Some(current) = dir {
+            if Arc::ptr_eq(&current, node) {
+                return true;
+            }
+            dir = current.parent();
+        }
+        false
+    }
 }
 
 impl VfsNodeOps for DirNode {
+    fn file_id(&self) -> Option<(usize, u64)> {
+        Some((self as *const Self as usize, 0))
+    }
+
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(4096, 0))
+        // each subdirectory links back with its `..`
+        let subdirs = self
+            .children
+            .read()
+            .values()
+            .filter(|node| node.as_any().downcast_ref::<DirNode>().is_some())
+            .count() as u64;
+        let attr = self.meta.read().attr(VfsNodeType::Dir, 4096, 0);
+        Ok(attr.with_nlink(2 + subdirs))
+    }
+
+    fn set_mode(&self, mode: VfsNodePerm) -> VfsResult {
+        let mut meta = self.meta.write();
+        meta.perm = mode;
+        meta.ctime = self.sb.now();
+        Ok(())
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> VfsResult {
+        let mut meta = self.meta.write();
+        (meta.uid, meta.gid) = (uid, gid);
+        meta.ctime = self.sb.now();
+        Ok(())
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        let mut meta = self.meta.write();
+        meta.atime = atime.unwrap_or(meta.atime);
+        meta.mtime = mtime.unwrap_or(meta.mtime);
+        meta.ctime = self.sb.now();
+        Ok(())
+    }
+
+    fn get_xattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.meta.read().get_xattr(name)
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> VfsResult {
+        let now = self.sb.now();
+        self.meta.write().set_xattr(name, value, flags, now)
+    }
+
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        Ok(self.meta.read().list_xattr())
+    }
+
+    fn remove_xattr(&self, name: &str) -> VfsResult {
+        let now = self.sb.now();
+        self.meta.write().remove_xattr(name, now)
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
@@ -172,9 +291,88 @@ impl VfsNodeOps for DirNode {
         }
     }
 
+    /// Renames a node within the filesystem. Both paths are relative to this
+    /// directory. An existing `dst_path` is replaced, if it's a file or an
+    /// empty directory as `src_path` is.
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        log::debug!("rename at ramfs: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.entry_of(src_path)?;
+        let (dst_dir, dst_name) = self.entry_of(dst_path)?;
+        let src_dir = src_dir.as_any().downcast_ref::<DirNode>().unwrap();
+        let dst_dir = dst_dir.as_any().downcast_ref::<DirNode>().unwrap();
+        let node = src_dir
+            .children
+            .read()
+            .get(src_name)
+            .cloned()
+            .ok_or(VfsError::NotFound)?;
+        let moved_dir = node.as_any().downcast_ref::<DirNode>();
+        if moved_dir.is_some() && dst_dir.is_ancestor(&node) {
+            return Err(VfsError::InvalidInput); // move a directory into itself
+        }
+        let replaced = dst_dir.children.read().get(dst_name).cloned();
+        if let Some(replaced) = &replaced {
+            if Arc::ptr_eq(replaced, &node) {
+                return Ok(());
+            }
+            match (moved_dir, replaced.as_any().downcast_ref::<DirNode>()) {
+                (Some(_), Some(dir)) if !dir.children.read().is_empty() => {
+                    return Err(VfsError::DirectoryNotEmpty)
+                }
+                (Some(_), None) => return Err(VfsError::NotADirectory),
+                (None, Some(_)) => return Err(VfsError::IsADirectory),
+                _ => {}
+            }
+        }
+
+        src_dir.children.write().remove(src_name);
+        let replaced = dst_dir
+            .children
+            .write()
+            .insert(dst_name.into(), node.clone());
+        if let Some(file) = replaced
+            .as_ref()
+            .and_then(|node| node.as_any().downcast_ref::<FileNode>())
+        {
+            file.drop_link();
+        }
+        if let Some(dir) = moved_dir {
+            dir.set_parent(dst_dir.this.upgrade().map(|dir| dir as VfsNodeRef).as_ref());
+        }
+        src_dir.modified();
+        dst_dir.modified();
+        Ok(())
+    }
+
+    /// Adds `node`, a file of this filesystem, at `path` as a hard link.
+    fn link(&self, path: &str, node: VfsNodeRef) -> VfsResult {
+        log::debug!("link at ramfs: {}", path);
+        let Some(file) = node.as_any().downcast_ref::<FileNode>() else {
+            return if node.get_attr()?.is_dir() {
+                Err(VfsError::PermissionDenied) // no hard links to directories
+            } else {
+                Err(VfsError::Unsupported)
+            };
+        };
+        if !file.belongs_to(&self.sb) {
+            return Err(VfsError::Unsupported); // in another filesystem
+        }
+        let (dir, name) = self.entry_of(path)?;
+        let dir = dir.as_any().downcast_ref::<DirNode>().unwrap();
+        dir.add_node(name, node.clone())?;
+        file.add_link();
+        Ok(())
+    }
+
     axfs_vfs::impl_vfs_dir_default! {}
 }
 
+impl Drop for DirNode {
+    fn drop(&mut self) {
+        self.sb.free_inode();
+    }
+}
+
 fn split_path(path: &str) -> (&str, Option<&str>) {
     let trimmed_path = path.trim_start_matches('/');
     trimmed_path.find('/').map_or((trimmed_path, None), |n| {
@@ -1,56 +1,209 @@
-use alloc::vec::Vec;
-use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use alloc::collections::btree_map::{BTreeMap, Entry};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use core::time::Duration;
+
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult, XattrFlags};
 use spin::RwLock;
 
+use crate::meta::Meta;
+use crate::super_block::{SuperBlock, PAGE_SIZE};
+
+/// The data of a file, kept in pages. Pages that were never written are
+/// holes, which read as zeros and take no space.
+#[derive(Default)]
+struct Content {
+    pages: BTreeMap<u64, Box<[u8; PAGE_SIZE]>>,
+    size: u64,
+}
+
 /// The file node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct FileNode {
-    content: RwLock<Vec<u8>>,
+    sb: Arc<SuperBlock>,
+    content: RwLock<Content>,
+    meta: RwLock<Meta>,
 }
 
 impl FileNode {
-    /// To get the environment variables of the application
-    pub const fn new() -> Self {
-        Self {
-            content: RwLock::new(Vec::new()),
-        }
+    /// Creates an empty file that is not part of a RAM filesystem, so its
+    /// size is not limited.
+    pub fn new() -> Self {
+        Self::new_in(&SuperBlock::unlimited()).unwrap()
+    }
+
+    /// Creates an empty file, accounted to the filesystem of `sb`.
+    pub(crate) fn new_in(sb: &Arc<SuperBlock>) -> VfsResult<Self> {
+        sb.alloc_inode()?;
+        Ok(Self {
+            sb: sb.clone(),
+            content: RwLock::new(Content::default()),
+            meta: RwLock::new(Meta::new(VfsNodePerm::default_file(), 1, sb.now())),
+        })
+    }
+
+    /// Whether the file is part of the filesystem of `sb`.
+    pub(crate) fn belongs_to(&self, sb: &Arc<SuperBlock>) -> bool {
+        Arc::ptr_eq(&self.sb, sb)
+    }
+
+    /// Records a new hard link to the file.
+    pub(crate) fn add_link(&self) {
+        let mut meta = self.meta.write();
+        meta.nlink += 1;
+        meta.ctime = self.sb.now();
+    }
+
+    /// Records the removal of a hard link to the file. The file itself goes
+    /// away with the last reference to the node, which may be an open file.
+    pub(crate) fn drop_link(&self) {
+        let mut meta = self.meta.write();
+        meta.nlink = meta.nlink.saturating_sub(1);
+        meta.ctime = self.sb.now();
+    }
+}
+
+impl Default for FileNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for FileNode {
+    fn drop(&mut self) {
+        let pages = self.content.get_mut().pages.len() as u64;
+        self.sb.free_pages(pages);
+        self.sb.free_inode();
     }
 }
 
 impl VfsNodeOps for FileNode {
+    fn file_id(&self) -> Option<(usize, u64)> {
+        // a file has one node for all its links, which lives as long as it
+        Some((self as *const Self as usize, 0))
+    }
+
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+        let content = self.content.read();
+        let blocks = (content.pages.len() * PAGE_SIZE / 512) as u64;
+        Ok(self
+            .meta
+            .read()
+            .attr(VfsNodeType::File, content.size, blocks))
+    }
+
+    fn set_mode(&self, mode: VfsNodePerm) -> VfsResult {
+        let mut meta = self.meta.write();
+        meta.perm = mode;
+        meta.ctime = self.sb.now();
+        Ok(())
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> VfsResult {
+        let mut meta = self.meta.write();
+        (meta.uid, meta.gid) = (uid, gid);
+        meta.ctime = self.sb.now();
+        Ok(())
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        let mut meta = self.meta.write();
+        meta.atime = atime.unwrap_or(meta.atime);
+        meta.mtime = mtime.unwrap_or(meta.mtime);
+        meta.ctime = self.sb.now();
+        Ok(())
+    }
+
+    fn get_xattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.meta.read().get_xattr(name)
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> VfsResult {
+        let now = self.sb.now();
+        self.meta.write().set_xattr(name, value, flags, now)
+    }
+
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        Ok(self.meta.read().list_xattr())
+    }
+
+    fn remove_xattr(&self, name: &str) -> VfsResult {
+        let now = self.sb.now();
+        self.meta.write().remove_xattr(name, now)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let mut content = self.content.write();
-        if size < content.len() as u64 {
-            content.truncate(size as _);
-        } else {
-            content.resize(size as _, 0);
+        if size < content.size {
+            let freed = content.pages.split_off(&size.div_ceil(PAGE_SIZE as u64));
+            self.sb.free_pages(freed.len() as u64);
+            // clear the tail of the last page, which reads as zeros if the
+            // file grows again
+            let offset = (size % PAGE_SIZE as u64) as usize;
+            if let Some(page) = content.pages.get_mut(&(size / PAGE_SIZE as u64)) {
+                page[offset..].fill(0);
+            }
         }
+        // growing the file only makes a hole
+        content.size = size;
+        self.meta.write().modified(self.sb.now());
         Ok(())
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         let content = self.content.read();
-        let start = content.len().min(offset as usize);
-        let end = content.len().min(offset as usize + buf.len());
-        let src = &content[start..end];
-        buf[..src.len()].copy_from_slice(src);
-        Ok(src.len())
+        let end = content.size.min(offset.saturating_add(buf.len() as u64));
+        if offset >= end {
+            return Ok(0);
+        }
+        let len = (end - offset) as usize;
+        let mut pos = 0;
+        while pos < len {
+            let offset = offset + pos as u64;
+            let start = (offset % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - start).min(len - pos);
+            match content.pages.get(&(offset / PAGE_SIZE as u64)) {
+                Some(page) => buf[pos..pos + n].copy_from_slice(&page[start..start + n]),
+                None => buf[pos..pos + n].fill(0),
+            }
+            pos += n;
+        }
+        drop(content);
+        self.meta.write().atime = self.sb.now();
+        Ok(len)
     }
 
+    /// Writes as many bytes as the filesystem has room for. Fails with
+    /// [`VfsError::StorageFull`] only if no byte could be written.
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let offset = offset as usize;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if offset.checked_add(buf.len() as u64).is_none() {
+            return Err(VfsError::InvalidInput);
+        }
         let mut content = self.content.write();
-        if offset + buf.len() > content.len() {
-            content.resize(offset + buf.len(), 0);
+        let mut pos = 0;
+        while pos < buf.len() {
+            let offset = offset + pos as u64;
+            let start = (offset % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - start).min(buf.len() - pos);
+            let page = match content.pages.entry(offset / PAGE_SIZE as u64) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => match self.sb.alloc_pages(1) {
+                    Ok(()) => entry.insert(Box::new([0; PAGE_SIZE])),
+                    Err(err) if pos == 0 => return Err(err),
+                    Err(_) => break,
+                },
+            };
+            page[start..start + n].copy_from_slice(&buf[pos..pos + n]);
+            pos += n;
         }
-        let dst = &mut content[offset..offset + buf.len()];
-        dst.copy_from_slice(&buf[..dst.len()]);
-        Ok(buf.len())
+        content.size = content.size.max(offset + pos as u64);
+        drop(content);
+        self.meta.write().modified(self.sb.now());
+        Ok(pos)
     }
 
     impl_vfs_non_dir_default! {}
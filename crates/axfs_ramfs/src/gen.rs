@@ -0,0 +1,44 @@
+use alloc::string::String;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsError, VfsNodeAttr, VfsNodeOps, VfsResult};
+
+/// A read-only file node whose content is generated on every read, e.g.,
+/// `/proc/slabinfo`.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct GenFileNode {
+    gen: fn() -> String,
+}
+
+impl GenFileNode {
+    /// Creates a new file node with the given content generator.
+    pub const fn new(gen: fn() -> String) -> Self {
+        Self { gen }
+    }
+}
+
+impl VfsNodeOps for GenFileNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        // The size is unknown until the content is generated, like procfs.
+        Ok(VfsNodeAttr::new_file(0, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.gen)();
+        let content = content.as_bytes();
+        let start = content.len().min(offset as usize);
+        let end = content.len().min(offset as usize + buf.len());
+        let src = &content[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        Ok(src.len())
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
@@ -1,6 +1,8 @@
 //! RAM filesystem used by [ArceOS](https://github.com/rcore-os/arceos).
 //!
-//! The implementation is based on [`axfs_vfs`].
+//! The implementation is based on [`axfs_vfs`]. With [`RamfsOptions`], it
+//! works as a tmpfs: the file data and the number of nodes can be limited,
+//! files may be sparse and have hard links, and `statfs` reports the usage.
 
 #![cfg_attr(not(test), no_std)]
 
@@ -8,30 +10,55 @@ extern crate alloc;
 
 mod dir;
 mod file;
+mod gen;
 mod interrupts;
+mod meta;
+mod options;
+mod super_block;
 #[cfg(test)]
 mod tests;
 
 pub use self::dir::DirNode;
 pub use self::file::FileNode;
+pub use self::gen::GenFileNode;
 pub use self::interrupts::{Interrupts, INTERRUPT};
+pub use self::options::RamfsOptions;
+use self::super_block::SuperBlock;
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use axfs_vfs::{FileSystemInfo, VfsNodeOps, VfsNodeRef, VfsOps, VfsResult};
+use core::time::Duration;
 use spin::once::Once;
 
 /// A RAM filesystem that implements [`axfs_vfs::VfsOps`].
 pub struct RamFileSystem {
     parent: Once<VfsNodeRef>,
     root: Arc<DirNode>,
+    sb: Arc<SuperBlock>,
 }
 
 impl RamFileSystem {
-    /// Create a new instance.
+    /// Create a new instance, without limits and with all timestamps at the
+    /// epoch.
     pub fn new() -> Self {
-        Self {
-            parent: Once::new(),
-            root: DirNode::new(None),
+        Self::with_options(&RamfsOptions::default(), || Duration::ZERO).unwrap()
+    }
+
+    /// Create a new instance with the given mount options. Timestamps are
+    /// taken from `clock`.
+    pub fn with_options(options: &RamfsOptions, clock: fn() -> Duration) -> VfsResult<Self> {
+        let sb = SuperBlock::new(options.size, options.nr_inodes, clock);
+        let root = DirNode::new(None, &sb)?;
+        if let Some(mode) = options.mode {
+            root.set_mode(mode)?;
         }
+        if options.uid.is_some() || options.gid.is_some() {
+            root.set_owner(options.uid.unwrap_or(0), options.gid.unwrap_or(0))?;
+        }
+        Ok(Self {
+            parent: Once::new(),
+            root,
+            sb,
+        })
     }
 
     /// Returns the root directory node in [`Arc<DirNode>`](DirNode).
@@ -50,6 +77,16 @@ impl VfsOps for RamFileSystem {
         Ok(())
     }
 
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        Ok(self.sb.statfs())
+    }
+
+    /// Only `size=` and `nr_inodes=` can be changed, as on Linux.
+    fn remount(&self, options: &str) -> VfsResult {
+        let options = RamfsOptions::parse(options)?;
+        self.sb.set_limits(options.size, options.nr_inodes)
+    }
+
     fn root_dir(&self) -> VfsNodeRef {
         self.root.clone()
     }
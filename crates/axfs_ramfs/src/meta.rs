@@ -0,0 +1,86 @@
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::time::Duration;
+
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodePerm, VfsNodeType, VfsResult, XattrFlags};
+
+/// The attributes that files and directories of a RAM filesystem keep,
+/// besides their content.
+pub(crate) struct Meta {
+    pub perm: VfsNodePerm,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u64,
+    pub atime: Duration,
+    pub mtime: Duration,
+    pub ctime: Duration,
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl Meta {
+    pub fn new(perm: VfsNodePerm, nlink: u64, now: Duration) -> Self {
+        Self {
+            perm,
+            uid: 0,
+            gid: 0,
+            nlink,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            xattrs: BTreeMap::new(),
+        }
+    }
+
+    pub fn attr(&self, ty: VfsNodeType, size: u64, blocks: u64) -> VfsNodeAttr {
+        VfsNodeAttr::new(self.perm, ty, size, blocks)
+            .with_owner(self.uid, self.gid)
+            .with_nlink(self.nlink)
+            .with_times(self.atime, self.mtime, self.ctime)
+    }
+
+    /// Records a change of the content, which is also a change of the
+    /// attributes.
+    pub fn modified(&mut self, now: Duration) {
+        self.mtime = now;
+        self.ctime = now;
+    }
+
+    pub fn get_xattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        match self.xattrs.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(VfsError::NotFound),
+        }
+    }
+
+    pub fn set_xattr(
+        &mut self,
+        name: &str,
+        value: &[u8],
+        flags: XattrFlags,
+        now: Duration,
+    ) -> VfsResult {
+        let exists = self.xattrs.contains_key(name);
+        if exists && flags.contains(XattrFlags::CREATE) {
+            return Err(VfsError::AlreadyExists);
+        }
+        if !exists && flags.contains(XattrFlags::REPLACE) {
+            return Err(VfsError::NotFound);
+        }
+        self.xattrs.insert(name.into(), value.into());
+        self.ctime = now;
+        Ok(())
+    }
+
+    pub fn list_xattr(&self) -> Vec<String> {
+        self.xattrs.keys().cloned().collect()
+    }
+
+    pub fn remove_xattr(&mut self, name: &str, now: Duration) -> VfsResult {
+        match self.xattrs.remove(name) {
+            Some(_) => {
+                self.ctime = now;
+                Ok(())
+            }
+            None => Err(VfsError::NotFound),
+        }
+    }
+}
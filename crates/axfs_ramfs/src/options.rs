@@ -0,0 +1,67 @@
+use axfs_vfs::{VfsError, VfsNodePerm, VfsResult};
+
+use crate::super_block::PAGE_SIZE;
+
+/// The options of a RAM filesystem, given as the `data` argument of
+/// `mount(2)`, e.g. `size=64m,nr_inodes=1k,mode=1777`.
+///
+/// Sizes may have a `k`, `m` or `g` suffix. As on Linux, a zero `size` or
+/// `nr_inodes` means unlimited, and options that are not given keep their
+/// current values on remount.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RamfsOptions {
+    /// The maximum number of bytes of file data, rounded up to whole pages.
+    pub size: Option<u64>,
+    /// The maximum number of files and directories, including the root.
+    pub nr_inodes: Option<u64>,
+    /// The permission of the root directory.
+    pub mode: Option<VfsNodePerm>,
+    /// The owner of the root directory.
+    pub uid: Option<u32>,
+    /// The group of the root directory.
+    pub gid: Option<u32>,
+}
+
+impl RamfsOptions {
+    /// Parses a comma separated list of options. Unknown options and
+    /// malformed values are rejected with [`VfsError::InvalidInput`].
+    pub fn parse(options: &str) -> VfsResult<Self> {
+        let mut parsed = Self::default();
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let (key, value) = option.split_once('=').ok_or(VfsError::InvalidInput)?;
+            match key {
+                "size" => {
+                    let size = parse_size(value)?;
+                    let size = size
+                        .checked_next_multiple_of(PAGE_SIZE as u64)
+                        .ok_or(VfsError::InvalidInput)?;
+                    parsed.size = Some(size);
+                }
+                "nr_inodes" => parsed.nr_inodes = Some(parse_size(value)?),
+                "mode" => {
+                    let mode = u16::from_str_radix(value, 8).map_err(|_| VfsError::InvalidInput)?;
+                    parsed.mode = Some(VfsNodePerm::from_bits(mode).ok_or(VfsError::InvalidInput)?);
+                }
+                "uid" => parsed.uid = Some(value.parse().map_err(|_| VfsError::InvalidInput)?),
+                "gid" => parsed.gid = Some(value.parse().map_err(|_| VfsError::InvalidInput)?),
+                _ => {
+                    log::warn!("unknown ramfs option: {}", option);
+                    return Err(VfsError::InvalidInput);
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Parses a number with an optional `k`, `m` or `g` suffix.
+fn parse_size(value: &str) -> VfsResult<u64> {
+    let (digits, shift) = match value.as_bytes().last() {
+        Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+        Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+        Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let number: u64 = digits.parse().map_err(|_| VfsError::InvalidInput)?;
+    number.checked_mul(1 << shift).ok_or(VfsError::InvalidInput)
+}
@@ -0,0 +1,130 @@
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axfs_vfs::{FileSystemInfo, VfsError, VfsResult};
+
+/// The unit in which file data is allocated.
+pub(crate) const PAGE_SIZE: usize = 4096;
+
+/// The `f_type` that `statfs` reports for tmpfs on Linux.
+const TMPFS_MAGIC: u64 = 0x0102_1994;
+
+/// The longest file name `statfs` reports.
+const NAME_MAX: u64 = 255;
+
+/// State shared by all nodes of a RAM filesystem: the limits of the
+/// filesystem, the pages and nodes in use, and the clock for timestamps.
+pub(crate) struct SuperBlock {
+    max_pages: AtomicU64,
+    max_inodes: AtomicU64,
+    pages: AtomicU64,
+    inodes: AtomicU64,
+    clock: fn() -> Duration,
+}
+
+impl SuperBlock {
+    pub fn new(size: Option<u64>, nr_inodes: Option<u64>, clock: fn() -> Duration) -> Arc<Self> {
+        Arc::new(Self {
+            max_pages: AtomicU64::new(max_pages(size.unwrap_or(0))),
+            max_inodes: AtomicU64::new(max_inodes(nr_inodes.unwrap_or(0))),
+            pages: AtomicU64::new(0),
+            inodes: AtomicU64::new(0),
+            clock,
+        })
+    }
+
+    /// A superblock without limits and with all timestamps at the epoch,
+    /// for nodes that are not part of a RAM filesystem.
+    pub fn unlimited() -> Arc<Self> {
+        Self::new(None, None, || Duration::ZERO)
+    }
+
+    /// The current time, for timestamps.
+    pub fn now(&self) -> Duration {
+        (self.clock)()
+    }
+
+    /// Accounts `n` more pages of file data, or fails with
+    /// [`VfsError::StorageFull`] if that exceeds `size=`.
+    pub fn alloc_pages(&self, n: u64) -> VfsResult {
+        charge(&self.pages, &self.max_pages, n)
+    }
+
+    pub fn free_pages(&self, n: u64) {
+        self.pages.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    /// Accounts a new node, or fails with [`VfsError::StorageFull`] if that
+    /// exceeds `nr_inodes=`.
+    pub fn alloc_inode(&self) -> VfsResult {
+        charge(&self.inodes, &self.max_inodes, 1)
+    }
+
+    pub fn free_inode(&self) {
+        self.inodes.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Changes the limits of a mounted filesystem, keeping those that are
+    /// `None`. Like Linux, a limit below what is already in use is rejected
+    /// with [`VfsError::InvalidInput`].
+    pub fn set_limits(&self, size: Option<u64>, nr_inodes: Option<u64>) -> VfsResult {
+        let max_pages = size.map_or(self.max_pages.load(Ordering::Relaxed), max_pages);
+        let max_inodes = nr_inodes.map_or(self.max_inodes.load(Ordering::Relaxed), max_inodes);
+        if max_pages < self.pages.load(Ordering::Relaxed)
+            || max_inodes < self.inodes.load(Ordering::Relaxed)
+        {
+            return Err(VfsError::InvalidInput);
+        }
+        self.max_pages.store(max_pages, Ordering::Relaxed);
+        self.max_inodes.store(max_inodes, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// The filesystem attributes for `statfs`. An unlimited filesystem
+    /// reports zero blocks and nodes, like tmpfs mounted with `size=0`.
+    pub fn statfs(&self) -> FileSystemInfo {
+        let limit = |used: &AtomicU64, max: &AtomicU64| match max.load(Ordering::Relaxed) {
+            u64::MAX => (0, 0),
+            max => (max, max.saturating_sub(used.load(Ordering::Relaxed))),
+        };
+        let (blocks, blocks_free) = limit(&self.pages, &self.max_pages);
+        let (files, files_free) = limit(&self.inodes, &self.max_inodes);
+        FileSystemInfo {
+            fs_type: TMPFS_MAGIC,
+            block_size: PAGE_SIZE as u64,
+            blocks,
+            blocks_free,
+            blocks_available: blocks_free,
+            files,
+            files_free,
+            name_len: NAME_MAX,
+        }
+    }
+}
+
+/// The limit on pages for `size=`, where 0 means unlimited.
+fn max_pages(size: u64) -> u64 {
+    match size {
+        0 => u64::MAX,
+        size => size / PAGE_SIZE as u64,
+    }
+}
+
+/// The limit on nodes for `nr_inodes=`, where 0 means unlimited.
+fn max_inodes(nr_inodes: u64) -> u64 {
+    match nr_inodes {
+        0 => u64::MAX,
+        nr_inodes => nr_inodes,
+    }
+}
+
+/// Adds `n` to the counter `used` if the sum stays within `max`.
+fn charge(used: &AtomicU64, max: &AtomicU64, n: u64) -> VfsResult {
+    let max = max.load(Ordering::Relaxed);
+    used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+        used.checked_add(n).filter(|&total| total <= max)
+    })
+    .map(|_| ())
+    .map_err(|_| VfsError::StorageFull)
+}
@@ -1,6 +1,8 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeType, VfsOps, VfsResult, XattrFlags};
 
 use crate::*;
 
@@ -32,6 +34,16 @@ fn test_ramfs_ops(devfs: &RamFileSystem) -> VfsResult {
     assert_eq!(node.read_at(0, &mut buf)?, N);
     assert_eq!(buf[..N_HALF], [0; N_HALF]);
     assert_eq!(buf[N_HALF..], [1; N_HALF]);
+
+    let attr = node.get_attr()?;
+    assert_eq!(attr.perm().mode(), 0o666);
+    assert_eq!((attr.uid(), attr.gid()), (0, 0));
+    node.set_mode(VfsNodePerm::from_bits_truncate(0o4750))?;
+    node.set_owner(1000, 100)?;
+    let attr = node.get_attr()?;
+    assert_eq!(attr.perm().mode(), 0o4750);
+    assert_eq!((attr.uid(), attr.gid()), (1000, 100));
+
     assert_eq!(node.lookup("/").err(), Some(VfsError::NotADirectory));
 
     let foo = devfs.root_dir().lookup(".///.//././/.////foo")?;
@@ -134,3 +146,221 @@ fn test_ramfs() {
     assert_eq!(root.remove("./foo"), Ok(()));
     assert!(ramfs.root_dir_node().get_entries().is_empty());
 }
+
+static NOW: AtomicU64 = AtomicU64::new(0);
+
+fn clock() -> Duration {
+    Duration::from_secs(NOW.load(Ordering::Relaxed))
+}
+
+#[test]
+fn test_tmpfs_options() {
+    let options = RamfsOptions::parse("size=10k,nr_inodes=1k,mode=1777,uid=1000").unwrap();
+    assert_eq!(options.size, Some(12288));
+    assert_eq!(options.nr_inodes, Some(1024));
+    assert_eq!(options.mode.map(|mode| mode.mode()), Some(0o1777));
+    assert_eq!((options.uid, options.gid), (Some(1000), None));
+    assert_eq!(RamfsOptions::parse("").unwrap().size, None);
+    assert!(RamfsOptions::parse("size=1x").is_err());
+    assert!(RamfsOptions::parse("mode=8").is_err());
+    assert!(RamfsOptions::parse("noatime").is_err());
+
+    let tmpfs = RamFileSystem::with_options(&options, clock).unwrap();
+    let attr = tmpfs.root_dir().get_attr().unwrap();
+    assert_eq!(attr.perm().mode(), 0o1777);
+    assert_eq!((attr.uid(), attr.gid()), (1000, 0));
+}
+
+#[test]
+fn test_tmpfs_limits() -> VfsResult {
+    let options = RamfsOptions::parse("size=8k,nr_inodes=3")?;
+    let tmpfs = RamFileSystem::with_options(&options, clock)?;
+    let root = tmpfs.root_dir();
+    let stat = tmpfs.statfs()?;
+    assert_eq!(stat.fs_type, 0x01021994);
+    assert_eq!(
+        (stat.block_size, stat.blocks, stat.blocks_free),
+        (4096, 2, 2)
+    );
+    assert_eq!((stat.files, stat.files_free), (3, 2));
+
+    // the root takes a node, two more are left
+    root.create("f1", VfsNodeType::File)?;
+    root.create("d1", VfsNodeType::Dir)?;
+    assert_eq!(
+        root.create("f2", VfsNodeType::File).err(),
+        Some(VfsError::StorageFull)
+    );
+
+    // a write that does not fit is cut short, then fails
+    let f1 = root.clone().lookup("f1")?;
+    assert_eq!(f1.write_at(100, &[1; 10000])?, 8092);
+    assert_eq!(
+        f1.write_at(8192, &[1; 10]).err(),
+        Some(VfsError::StorageFull)
+    );
+    assert_eq!(tmpfs.statfs()?.blocks_free, 0);
+    assert_eq!(f1.get_attr()?.blocks(), 16);
+
+    // shrinking gives the pages back
+    f1.truncate(4096)?;
+    assert_eq!(tmpfs.statfs()?.blocks_free, 1);
+    tmpfs.remount("size=0")?;
+    assert_eq!(tmpfs.statfs()?.blocks, 0);
+    assert_eq!(
+        tmpfs.remount("nr_inodes=2").err(),
+        Some(VfsError::InvalidInput)
+    );
+
+    // removing a file frees its node and pages
+    root.remove("f1")?;
+    drop(f1);
+    tmpfs.remount("size=4k")?;
+    assert_eq!(tmpfs.statfs()?.blocks_free, 1);
+    assert_eq!(tmpfs.statfs()?.files_free, 1);
+    root.create("f2", VfsNodeType::File)?;
+    Ok(())
+}
+
+#[test]
+fn test_tmpfs_sparse() -> VfsResult {
+    let tmpfs = RamFileSystem::new();
+    let root = tmpfs.root_dir();
+    root.create("f", VfsNodeType::File)?;
+    let file = root.lookup("f")?;
+
+    // a hole in the middle takes no space
+    file.write_at(0, b"head")?;
+    file.write_at(1 << 20, b"tail")?;
+    let attr = file.get_attr()?;
+    assert_eq!((attr.size(), attr.blocks()), ((1 << 20) + 4, 16));
+    let mut buf = [1; 8];
+    assert_eq!(file.read_at(4094, &mut buf)?, 8);
+    assert_eq!(buf, [0; 8]);
+
+    // growing the file only makes a hole
+    file.truncate(1 << 30)?;
+    assert_eq!(file.get_attr()?.blocks(), 16);
+    assert_eq!(file.read_at((1 << 30) - 4, &mut buf)?, 4);
+
+    // bytes cut off by a truncate read as zeros after growing again
+    file.truncate(2)?;
+    file.truncate(8)?;
+    assert_eq!(file.read_at(0, &mut buf)?, 8);
+    assert_eq!(&buf, b"he\0\0\0\0\0\0");
+    assert_eq!(file.get_attr()?.blocks(), 8);
+    Ok(())
+}
+
+#[test]
+fn test_tmpfs_links() -> VfsResult {
+    let tmpfs = RamFileSystem::with_options(&RamfsOptions::default(), clock)?;
+    let root = tmpfs.root_dir();
+    root.create("a", VfsNodeType::File)?;
+    root.create("d", VfsNodeType::Dir)?;
+    root.create("d/sub", VfsNodeType::Dir)?;
+    assert_eq!(root.get_attr()?.nlink(), 3);
+    assert_eq!(root.clone().lookup("d")?.get_attr()?.nlink(), 3);
+
+    let a = root.clone().lookup("a")?;
+    a.write_at(0, b"data")?;
+    root.link("d/b", a.clone())?;
+    assert!(Arc::ptr_eq(&root.clone().lookup("d/b")?, &a));
+    assert_eq!(a.get_attr()?.nlink(), 2);
+    assert_eq!(
+        root.link("a", a.clone()).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    let d = root.clone().lookup("d")?;
+    assert_eq!(root.link("e", d).err(), Some(VfsError::PermissionDenied));
+    let other = RamFileSystem::new();
+    other.root_dir().create("x", VfsNodeType::File)?;
+    let x = other.root_dir().lookup("x")?;
+    assert_eq!(root.link("x", x).err(), Some(VfsError::Unsupported));
+
+    root.remove("a")?;
+    assert_eq!(a.get_attr()?.nlink(), 1);
+    let mut buf = [0; 4];
+    root.clone().lookup("d/b")?.read_at(0, &mut buf)?;
+    assert_eq!(&buf, b"data");
+
+    // renames move nodes and replace files
+    root.create("c", VfsNodeType::File)?;
+    root.rename("d/b", "c")?;
+    assert!(Arc::ptr_eq(&root.clone().lookup("c")?, &a));
+    assert_eq!(root.clone().lookup("d/b").err(), Some(VfsError::NotFound));
+    root.rename("d/sub", "sub")?;
+    let sub = root.clone().lookup("sub")?;
+    assert!(Arc::ptr_eq(&sub.parent().unwrap(), &root));
+    assert_eq!(
+        root.rename("sub", "sub/x").err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert_eq!(root.rename("c", "sub").err(), Some(VfsError::IsADirectory));
+    assert_eq!(root.rename("sub", "c").err(), Some(VfsError::NotADirectory));
+    root.rename("sub", "d")?;
+    assert_eq!(root.clone().lookup("sub").err(), Some(VfsError::NotFound));
+    assert_eq!(root.get_attr()?.nlink(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_tmpfs_times() -> VfsResult {
+    NOW.store(100, Ordering::Relaxed);
+    let tmpfs = RamFileSystem::with_options(&RamfsOptions::default(), clock)?;
+    let root = tmpfs.root_dir();
+    root.create("f", VfsNodeType::File)?;
+    let file = root.clone().lookup("f")?;
+    let attr = file.get_attr()?;
+    assert_eq!(attr.ctime(), Duration::from_secs(100));
+
+    NOW.store(200, Ordering::Relaxed);
+    file.write_at(0, b"x")?;
+    let attr = file.get_attr()?;
+    assert_eq!(attr.atime(), Duration::from_secs(100));
+    assert_eq!(attr.mtime(), Duration::from_secs(200));
+
+    NOW.store(300, Ordering::Relaxed);
+    file.read_at(0, &mut [0; 1])?;
+    file.set_times(None, Some(Duration::from_secs(5)))?;
+    let attr = file.get_attr()?;
+    assert_eq!(attr.atime(), Duration::from_secs(300));
+    assert_eq!(attr.mtime(), Duration::from_secs(5));
+    assert_eq!(attr.ctime(), Duration::from_secs(300));
+
+    root.remove("f")?;
+    assert_eq!(root.get_attr()?.mtime(), Duration::from_secs(300));
+    Ok(())
+}
+
+#[test]
+fn test_xattr() -> VfsResult {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f", VfsNodeType::File)?;
+    let file = root.clone().lookup("f")?;
+    assert_eq!(file.get_xattr("user.a").err(), Some(VfsError::NotFound));
+    assert!(file.list_xattr()?.is_empty());
+
+    file.set_xattr("user.a", b"1", XattrFlags::CREATE)?;
+    file.set_xattr("security.capability", b"", XattrFlags::empty())?;
+    assert_eq!(
+        file.set_xattr("user.a", b"2", XattrFlags::CREATE).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        file.set_xattr("user.b", b"2", XattrFlags::REPLACE).err(),
+        Some(VfsError::NotFound)
+    );
+    file.set_xattr("user.a", b"2", XattrFlags::REPLACE)?;
+    assert_eq!(file.get_xattr("user.a")?, b"2");
+    assert_eq!(file.list_xattr()?, ["security.capability", "user.a"]);
+
+    file.remove_xattr("user.a")?;
+    assert_eq!(file.remove_xattr("user.a").err(), Some(VfsError::NotFound));
+    assert_eq!(file.list_xattr()?, ["security.capability"]);
+
+    root.set_xattr("user.dir", b"d", XattrFlags::empty())?;
+    assert_eq!(root.get_xattr("user.dir")?, b"d");
+    Ok(())
+}
@@ -0,0 +1,274 @@
+//! POSIX access control lists.
+//!
+//! An ACL extends the permission bits of a node with entries for other users
+//! and groups. It's kept in the extended attribute [`ACL_ACCESS`] in the
+//! format of Linux, so filesystems only need to store extended attributes.
+//! A directory can also have a default ACL in [`ACL_DEFAULT`], which is given
+//! to the nodes created in it.
+//!
+//! The permission bits of a node with an ACL are a view of the ACL: the owner
+//! and other bits are those of the owner and other entries, and the group
+//! bits are those of the mask entry, or of the owning group entry without a
+//! mask.
+
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxResult};
+
+use crate::VfsNodePerm;
+
+/// The name of the extended attribute that holds the access ACL.
+pub const ACL_ACCESS: &str = "system.posix_acl_access";
+/// The name of the extended attribute that holds the default ACL of a
+/// directory.
+pub const ACL_DEFAULT: &str = "system.posix_acl_default";
+
+/// The version in the header of an ACL in an extended attribute.
+const ACL_VERSION: u32 = 2;
+
+/// To whom an ACL entry applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AclTag {
+    /// The owner of the node.
+    UserObj,
+    /// The user with the id.
+    User(u32),
+    /// The owning group of the node.
+    GroupObj,
+    /// The group with the id.
+    Group(u32),
+    /// The upper bound of the permissions granted to users and groups
+    /// other than the owner.
+    Mask,
+    /// Everyone else.
+    Other,
+}
+
+/// An entry of an ACL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AclEntry {
+    /// To whom the entry applies.
+    pub tag: AclTag,
+    /// The permissions, a combination of read (4), write (2) and execute (1).
+    pub perm: u32,
+}
+
+/// A POSIX ACL, with the entries sorted by tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PosixAcl {
+    entries: Vec<AclEntry>,
+}
+
+impl AclTag {
+    fn from_raw(tag: u16, id: u32) -> Option<Self> {
+        Some(match tag {
+            0x01 => Self::UserObj,
+            0x02 => Self::User(id),
+            0x04 => Self::GroupObj,
+            0x08 => Self::Group(id),
+            0x10 => Self::Mask,
+            0x20 => Self::Other,
+            _ => return None,
+        })
+    }
+
+    fn to_raw(self) -> (u16, u32) {
+        match self {
+            Self::UserObj => (0x01, u32::MAX),
+            Self::User(id) => (0x02, id),
+            Self::GroupObj => (0x04, u32::MAX),
+            Self::Group(id) => (0x08, id),
+            Self::Mask => (0x10, u32::MAX),
+            Self::Other => (0x20, u32::MAX),
+        }
+    }
+}
+
+impl PosixAcl {
+    /// Returns the ACL equivalent to the permission bits.
+    pub fn from_mode(perm: VfsNodePerm) -> Self {
+        let mode = perm.mode();
+        let entry = |tag, shift: u32| AclEntry {
+            tag,
+            perm: (mode >> shift) & 0o7,
+        };
+        Self {
+            entries: alloc::vec![
+                entry(AclTag::UserObj, 6),
+                entry(AclTag::GroupObj, 3),
+                entry(AclTag::Other, 0),
+            ],
+        }
+    }
+
+    /// Parses an ACL in the format of the extended attribute.
+    ///
+    /// Returns [`InvalidInput`](axerrno::AxError::InvalidInput) if it's
+    /// malformed, or if the entries are not valid: there must be one entry
+    /// for the owner, the owning group and the others, a mask if there are
+    /// entries for other users or groups, and no duplicated entries.
+    pub fn parse(data: &[u8]) -> AxResult<Self> {
+        let (header, data) = data.split_at(4.min(data.len()));
+        if header != ACL_VERSION.to_le_bytes() || data.len() % 8 != 0 {
+            return ax_err!(InvalidInput, "malformed ACL");
+        }
+        let mut entries = Vec::with_capacity(data.len() / 8);
+        for raw in data.chunks_exact(8) {
+            let tag = u16::from_le_bytes([raw[0], raw[1]]);
+            let perm = u16::from_le_bytes([raw[2], raw[3]]) as u32;
+            let id = u32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]);
+            match AclTag::from_raw(tag, id) {
+                Some(tag) if perm & !0o7 == 0 => entries.push(AclEntry { tag, perm }),
+                _ => return ax_err!(InvalidInput, "invalid ACL entry"),
+            }
+        }
+        entries.sort_by_key(|entry| entry.tag);
+        let count = |f: fn(&AclTag) -> bool| entries.iter().filter(|e| f(&e.tag)).count();
+        let named = count(|tag| matches!(tag, AclTag::User(_) | AclTag::Group(_)));
+        let valid = count(|tag| *tag == AclTag::UserObj) == 1
+            && count(|tag| *tag == AclTag::GroupObj) == 1
+            && count(|tag| *tag == AclTag::Other) == 1
+            && count(|tag| *tag == AclTag::Mask) == (named > 0) as usize
+            && entries.windows(2).all(|pair| pair[0].tag != pair[1].tag);
+        if !valid {
+            return ax_err!(InvalidInput, "invalid ACL entries");
+        }
+        Ok(Self { entries })
+    }
+
+    /// Returns the ACL in the format of the extended attribute.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(4 + self.entries.len() * 8);
+        data.extend_from_slice(&ACL_VERSION.to_le_bytes());
+        for entry in &self.entries {
+            let (tag, id) = entry.tag.to_raw();
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&(entry.perm as u16).to_le_bytes());
+            data.extend_from_slice(&id.to_le_bytes());
+        }
+        data
+    }
+
+    /// Returns the entries of the ACL.
+    pub fn entries(&self) -> &[AclEntry] {
+        &self.entries
+    }
+
+    /// Whether the ACL has only the entries that the permission bits can
+    /// hold, so it needs not be kept.
+    pub fn is_minimal(&self) -> bool {
+        self.entries.len() == 3
+    }
+
+    fn find(&self, tag: AclTag) -> Option<&AclEntry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+
+    /// Returns the permission bits described by the ACL, with the special
+    /// bits of `perm`.
+    pub fn mode(&self, perm: VfsNodePerm) -> VfsNodePerm {
+        let bits = |tag| self.find(tag).map(|entry| entry.perm);
+        let group = bits(AclTag::Mask).or(bits(AclTag::GroupObj));
+        let mode = (perm.bits() & 0o7000) as u32
+            | bits(AclTag::UserObj).unwrap_or(0) << 6
+            | group.unwrap_or(0) << 3
+            | bits(AclTag::Other).unwrap_or(0);
+        VfsNodePerm::from_bits_truncate(mode as u16)
+    }
+
+    /// Changes the ACL after the permission bits are changed to `perm`.
+    pub fn chmod(&mut self, perm: VfsNodePerm) {
+        let mode = perm.mode();
+        let has_mask = self.find(AclTag::Mask).is_some();
+        for entry in self.entries.iter_mut() {
+            match entry.tag {
+                AclTag::UserObj => entry.perm = (mode >> 6) & 0o7,
+                AclTag::GroupObj if !has_mask => entry.perm = (mode >> 3) & 0o7,
+                AclTag::Mask => entry.perm = (mode >> 3) & 0o7,
+                AclTag::Other => entry.perm = mode & 0o7,
+                _ => {}
+            }
+        }
+    }
+
+    /// Restricts a default ACL given to a new node by the permission bits
+    /// `perm` it's created with, and returns the resulting permission bits.
+    pub fn create(&mut self, perm: VfsNodePerm) -> VfsNodePerm {
+        let mode = perm.mode();
+        let has_mask = self.find(AclTag::Mask).is_some();
+        for entry in self.entries.iter_mut() {
+            match entry.tag {
+                AclTag::UserObj => entry.perm &= (mode >> 6) & 0o7,
+                AclTag::GroupObj if !has_mask => entry.perm &= (mode >> 3) & 0o7,
+                AclTag::Mask => entry.perm &= (mode >> 3) & 0o7,
+                AclTag::Other => entry.perm &= mode & 0o7,
+                _ => {}
+            }
+        }
+        self.mode(perm)
+    }
+
+    /// Whether the ACL grants `want` (a combination of read (4), write (2)
+    /// and execute (1)) to the user `uid`, on a node owned by `owner` and
+    /// `owner_group`. `in_group` tells if the user is in a group.
+    ///
+    /// The first of the owner, the named users, the groups and the others
+    /// that the user matches decides. The user is in the group class if it's
+    /// in any of the groups, and is granted if any of them grants all of
+    /// `want`. The mask limits all but the owner and the others.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use axfs_vfs::{acl::PosixAcl, VfsNodePerm};
+    ///
+    /// // user::rw-, user:1000:rw-, group::r--, mask::r--, other::---
+    /// let entries = [(1u16, 6u16, 0u32), (2, 6, 1000), (4, 4, 0), (16, 4, 0), (32, 0, 0)];
+    /// let mut data = vec![2, 0, 0, 0];
+    /// for (tag, perm, id) in entries {
+    ///     data.extend_from_slice(&tag.to_le_bytes());
+    ///     data.extend_from_slice(&perm.to_le_bytes());
+    ///     data.extend_from_slice(&id.to_le_bytes());
+    /// }
+    /// let acl = PosixAcl::parse(&data).unwrap();
+    /// assert_eq!(acl.mode(VfsNodePerm::empty()).mode(), 0o640);
+    /// // user 1000 may read, the mask denies writing
+    /// assert!(acl.check(0, 0, 1000, |_| false, 4));
+    /// assert!(!acl.check(0, 0, 1000, |_| false, 2));
+    /// assert!(!acl.check(0, 0, 1001, |_| false, 4));
+    /// ```
+    pub fn check(
+        &self,
+        owner: u32,
+        owner_group: u32,
+        uid: u32,
+        in_group: impl Fn(u32) -> bool,
+        want: u32,
+    ) -> bool {
+        let masked = |perm: u32| {
+            let mask = self.find(AclTag::Mask).map_or(0o7, |entry| entry.perm);
+            perm & mask & want == want
+        };
+        let mut in_group_class = false;
+        for entry in &self.entries {
+            match entry.tag {
+                AclTag::UserObj if uid == owner => return entry.perm & want == want,
+                AclTag::User(id) if uid == id => return masked(entry.perm),
+                AclTag::GroupObj if in_group(owner_group) => {
+                    in_group_class = true;
+                    if entry.perm & want == want {
+                        return masked(entry.perm);
+                    }
+                }
+                AclTag::Group(id) if in_group(id) => {
+                    in_group_class = true;
+                    if entry.perm & want == want {
+                        return masked(entry.perm);
+                    }
+                }
+                AclTag::Other => return !in_group_class && entry.perm & want == want,
+                _ => {}
+            }
+        }
+        false
+    }
+}
@@ -22,6 +22,13 @@
 //! | [`open()`](VfsNodeOps::open) | Do something when the node is opened | both |
 //! | [`release()`](VfsNodeOps::release) | Do something when the node is closed | both |
 //! | [`get_attr()`](VfsNodeOps::get_attr) | Get the attributes of the node | both |
+//! | [`set_mode()`](VfsNodeOps::set_mode) | Change the permission mode of the node | both |
+//! | [`set_owner()`](VfsNodeOps::set_owner) | Change the owner of the node | both |
+//! | [`set_times()`](VfsNodeOps::set_times) | Change the access and modification time of the node | both |
+//! | [`get_xattr()`](VfsNodeOps::get_xattr) | Get an extended attribute of the node | both |
+//! | [`set_xattr()`](VfsNodeOps::set_xattr) | Set an extended attribute of the node | both |
+//! | [`list_xattr()`](VfsNodeOps::list_xattr) | List the extended attributes of the node | both |
+//! | [`remove_xattr()`](VfsNodeOps::remove_xattr) | Remove an extended attribute of the node | both |
 //! | [`read_at()`](VfsNodeOps::read_at) | Read data from the file | file |
 //! | [`write_at()`](VfsNodeOps::write_at) | Write data to the file | file |
 //! | [`fsync()`](VfsNodeOps::fsync) | Synchronize the file data to disk | file |
@@ -31,6 +38,8 @@
 //! | [`create()`](VfsNodeOps::create) | Create a new node with the given path | directory |
 //! | [`remove()`](VfsNodeOps::remove) | Remove the node with the given path | directory |
 //! | [`read_dir()`](VfsNodeOps::read_dir) | Read directory entries | directory |
+//! | [`rename()`](VfsNodeOps::rename) | Rename or move a node | directory |
+//! | [`link()`](VfsNodeOps::link) | Create a hard link to a node | directory |
 //!
 //! [inodes]: https://en.wikipedia.org/wiki/Inode
 
@@ -41,12 +50,16 @@ extern crate alloc;
 mod macros;
 mod structs;
 
+pub mod acl;
 pub mod path;
 
-use alloc::sync::Arc;
+use alloc::{string::String, sync::Arc, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
+use core::time::Duration;
 
-pub use self::structs::{FileSystemInfo, VfsDirEntry, VfsNodeAttr, VfsNodePerm, VfsNodeType};
+pub use self::structs::{
+    FileSystemInfo, VfsDirEntry, VfsNodeAttr, VfsNodePerm, VfsNodeType, XattrFlags,
+};
 
 /// A wrapper of [`Arc<dyn VfsNodeOps>`].
 pub type VfsNodeRef = Arc<dyn VfsNodeOps>;
@@ -79,6 +92,12 @@ pub trait VfsOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Change the filesystem specific mount options of a mounted filesystem,
+    /// e.g. `mount -o remount,size=1g`.
+    fn remount(&self, _options: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     /// Get the root directory of the filesystem.
     fn root_dir(&self) -> VfsNodeRef;
 }
@@ -100,6 +119,57 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Identify the file of the node, e.g. by the filesystem and the inode
+    /// number. It's the same for all the nodes of a file, like those reached
+    /// through its hard links, and unique among the files of all filesystems.
+    ///
+    /// Returns `None` if the filesystem can't tell its files apart.
+    fn file_id(&self) -> Option<(usize, u64)> {
+        None
+    }
+
+    /// Change the permission mode of the node.
+    fn set_mode(&self, _mode: VfsNodePerm) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Change the user and group that own the node.
+    fn set_owner(&self, _uid: u32, _gid: u32) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Change the access and modification time of the node. `None` leaves
+    /// the time unchanged.
+    fn set_times(&self, _atime: Option<Duration>, _mtime: Option<Duration>) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Get the value of the extended attribute `name`, e.g. `user.comment`.
+    ///
+    /// Returns [`NotFound`](AxError::NotFound) if the node does not have it.
+    fn get_xattr(&self, _name: &str) -> VfsResult<Vec<u8>> {
+        ax_err!(Unsupported)
+    }
+
+    /// Set the value of the extended attribute `name`.
+    ///
+    /// Returns [`AlreadyExists`](AxError::AlreadyExists) if it exists with
+    /// [`XattrFlags::CREATE`], and [`NotFound`](AxError::NotFound) if it
+    /// does not with [`XattrFlags::REPLACE`].
+    fn set_xattr(&self, _name: &str, _value: &[u8], _flags: XattrFlags) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// List the names of the extended attributes of the node.
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        ax_err!(Unsupported)
+    }
+
+    /// Remove the extended attribute `name`.
+    fn remove_xattr(&self, _name: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     // file operations:
 
     /// Read data from the file at the given offset.
@@ -160,6 +230,12 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Create a hard link at the given `path` in the directory to `node`,
+    /// which must be a file of the same filesystem.
+    fn link(&self, _path: &str, _node: VfsNodeRef) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     /// Convert `&self` to [`&dyn Any`][1] that can use
     /// [`Any::downcast_ref`][2].
     ///
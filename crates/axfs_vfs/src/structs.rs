@@ -1,8 +1,25 @@
-/// Filesystem attributes.
-///
-/// Currently not used.
-#[non_exhaustive]
-pub struct FileSystemInfo;
+use core::time::Duration;
+
+/// Filesystem attributes, as reported by `statfs`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystemInfo {
+    /// The magic number of the filesystem type, e.g. `0x01021994` for tmpfs.
+    pub fs_type: u64,
+    /// The size of a block, in bytes.
+    pub block_size: u64,
+    /// Total number of blocks.
+    pub blocks: u64,
+    /// Number of free blocks.
+    pub blocks_free: u64,
+    /// Number of free blocks available to unprivileged users.
+    pub blocks_available: u64,
+    /// Total number of nodes.
+    pub files: u64,
+    /// Number of free nodes.
+    pub files_free: u64,
+    /// Maximum length of a file name.
+    pub name_len: u64,
+}
 
 /// Node (file/directory) attributes.
 #[allow(dead_code)]
@@ -16,12 +33,31 @@ pub struct VfsNodeAttr {
     size: u64,
     /// Number of 512B blocks allocated.
     blocks: u64,
+    /// User id of the owner.
+    uid: u32,
+    /// Group id of the owner.
+    gid: u32,
+    /// Number of hard links.
+    nlink: u64,
+    /// Time of last access.
+    atime: Duration,
+    /// Time of last modification of the content.
+    mtime: Duration,
+    /// Time of last change of the attributes.
+    ctime: Duration,
 }
 
 bitflags::bitflags! {
     /// Node (file/directory) permission mode.
     #[derive(Debug, Clone, Copy)]
     pub struct VfsNodePerm: u16 {
+        /// Set user id on execution.
+        const SET_UID = 0o4000;
+        /// Set group id on execution.
+        const SET_GID = 0o2000;
+        /// Only the owner may remove or rename entries in the directory.
+        const STICKY = 0o1000;
+
         /// Owner has read permission.
         const OWNER_READ = 0o400;
         /// Owner has write permission.
@@ -45,6 +81,17 @@ bitflags::bitflags! {
     }
 }
 
+bitflags::bitflags! {
+    /// How to set an extended attribute, the flags of `setxattr`.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct XattrFlags: u32 {
+        /// Fail if the attribute already exists.
+        const CREATE = 0x1;
+        /// Fail if the attribute does not exist.
+        const REPLACE = 0x2;
+    }
+}
+
 /// Node (file/directory) type.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -205,6 +252,12 @@ impl VfsNodeAttr {
             ty,
             size,
             blocks,
+            uid: 0,
+            gid: 0,
+            nlink: 1,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
         }
     }
 
@@ -215,6 +268,12 @@ impl VfsNodeAttr {
             ty: VfsNodeType::File,
             size,
             blocks,
+            uid: 0,
+            gid: 0,
+            nlink: 1,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
         }
     }
 
@@ -226,9 +285,67 @@ impl VfsNodeAttr {
             ty: VfsNodeType::Dir,
             size,
             blocks,
+            uid: 0,
+            gid: 0,
+            nlink: 1,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
         }
     }
 
+    /// Sets the owner of the node, which is root by default.
+    pub const fn with_owner(mut self, uid: u32, gid: u32) -> Self {
+        self.uid = uid;
+        self.gid = gid;
+        self
+    }
+
+    /// Sets the number of hard links to the node, which is 1 by default.
+    pub const fn with_nlink(mut self, nlink: u64) -> Self {
+        self.nlink = nlink;
+        self
+    }
+
+    /// Sets the access, modification and change time of the node, which are
+    /// zero (the epoch) by default.
+    pub const fn with_times(mut self, atime: Duration, mtime: Duration, ctime: Duration) -> Self {
+        self.atime = atime;
+        self.mtime = mtime;
+        self.ctime = ctime;
+        self
+    }
+
+    /// Returns the number of hard links to the node.
+    pub const fn nlink(&self) -> u64 {
+        self.nlink
+    }
+
+    /// Returns the time of last access.
+    pub const fn atime(&self) -> Duration {
+        self.atime
+    }
+
+    /// Returns the time of last modification of the content.
+    pub const fn mtime(&self) -> Duration {
+        self.mtime
+    }
+
+    /// Returns the time of last change of the attributes.
+    pub const fn ctime(&self) -> Duration {
+        self.ctime
+    }
+
+    /// Returns the user id of the owner.
+    pub const fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Returns the group id of the owner.
+    pub const fn gid(&self) -> u32 {
+        self.gid
+    }
+
     /// Returns the size of the node.
     pub const fn size(&self) -> u64 {
         self.size
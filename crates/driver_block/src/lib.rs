@@ -13,6 +13,10 @@ pub mod bcm2835sdhci;
 #[doc(no_inline)]
 pub use driver_common::{BaseDriverOps, DevError, DevResult, DeviceType};
 
+/// The id of a request submitted to a block device without waiting for it,
+/// see [`BlockDriverOps::submit_read_block`].
+pub type BlockRequestId = u16;
+
 /// Operations that require a block storage device driver to implement.
 pub trait BlockDriverOps: BaseDriverOps {
     /// The number of blocks in this storage device.
@@ -36,4 +40,62 @@ pub trait BlockDriverOps: BaseDriverOps {
 
     /// Flushes the device to write all pending data to the storage.
     fn flush(&mut self) -> DevResult;
+
+    /// The IRQ number of the device, or `None` if it is unknown and the
+    /// device has to be polled.
+    fn irq_num(&self) -> Option<usize> {
+        None
+    }
+
+    /// Acknowledges the interrupt of the device, returns whether the device
+    /// had raised it.
+    fn ack_interrupt(&mut self) -> bool {
+        false
+    }
+
+    /// Starts reading blocked data from the given block without waiting for
+    /// it, returns the id of the request.
+    ///
+    /// Like [`BlockDriverOps::read_block`], the buffer may span multiple
+    /// contiguous blocks. The request is finished once its id is returned by
+    /// [`BlockDriverOps::poll_completed`]. Devices that can only do
+    /// synchronous I/O return [`DevError::Unsupported`].
+    ///
+    /// # Safety
+    ///
+    /// The buffer must stay valid and must not be accessed until the request
+    /// is finished. [`BlockDriverOps::read_block`] and
+    /// [`BlockDriverOps::write_block`] must not be called while there are
+    /// unfinished requests.
+    unsafe fn submit_read_block(
+        &mut self,
+        _block_id: u64,
+        _buf: *mut [u8],
+    ) -> DevResult<BlockRequestId> {
+        Err(DevError::Unsupported)
+    }
+
+    /// Starts writing blocked data to the given block without waiting for it,
+    /// returns the id of the request.
+    ///
+    /// See [`BlockDriverOps::submit_read_block`] for details.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`BlockDriverOps::submit_read_block`].
+    unsafe fn submit_write_block(
+        &mut self,
+        _block_id: u64,
+        _buf: *const [u8],
+    ) -> DevResult<BlockRequestId> {
+        Err(DevError::Unsupported)
+    }
+
+    /// Takes a finished request submitted by
+    /// [`BlockDriverOps::submit_read_block`] or
+    /// [`BlockDriverOps::submit_write_block`], returns its id and result, or
+    /// `None` if no request has finished.
+    fn poll_completed(&mut self) -> Option<(BlockRequestId, DevResult)> {
+        None
+    }
 }
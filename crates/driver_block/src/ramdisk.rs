@@ -9,21 +9,28 @@ use driver_common::{BaseDriverOps, DevError, DevResult, DeviceType};
 const BLOCK_SIZE: usize = 512;
 
 /// A RAM disk that stores data in a vector.
-#[derive(Default)]
 pub struct RamDisk {
     size: usize,
+    block_size: usize,
     data: Vec<u8>,
 }
 
+impl Default for RamDisk {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
 impl RamDisk {
     /// Creates a new RAM disk with the given size hint.
     ///
     /// The actual size of the RAM disk will be aligned upwards to the block
     /// size (512 bytes).
     pub fn new(size_hint: usize) -> Self {
-        let size = align_up(size_hint);
+        let size = align_up(size_hint, BLOCK_SIZE);
         Self {
             size,
+            block_size: BLOCK_SIZE,
             data: vec![0; size],
         }
     }
@@ -33,10 +40,31 @@ impl RamDisk {
     /// The actual size of the RAM disk will be aligned upwards to the block
     /// size (512 bytes).
     pub fn from(buf: &[u8]) -> Self {
-        let size = align_up(buf.len());
+        let size = align_up(buf.len(), BLOCK_SIZE);
         let mut data = vec![0; size];
         data[..buf.len()].copy_from_slice(buf);
-        Self { size, data }
+        Self {
+            size,
+            block_size: BLOCK_SIZE,
+            data,
+        }
+    }
+
+    /// Sets the logical block size of the RAM disk, e.g. 4096 bytes to act as
+    /// an advanced format disk.
+    ///
+    /// The size of the RAM disk is aligned upwards to the new block size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block size is not a power of two, or less than 512
+    /// bytes.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        assert!(block_size.is_power_of_two() && block_size >= BLOCK_SIZE);
+        self.size = align_up(self.size, block_size);
+        self.data.resize(self.size, 0);
+        self.block_size = block_size;
+        self
     }
 
     /// Copies the data from the given slice to the RAM disk.
@@ -67,20 +95,20 @@ impl const BaseDriverOps for RamDisk {
 impl BlockDriverOps for RamDisk {
     #[inline]
     fn num_blocks(&self) -> u64 {
-        (self.size / BLOCK_SIZE) as u64
+        (self.size / self.block_size) as u64
     }
 
     #[inline]
     fn block_size(&self) -> usize {
-        BLOCK_SIZE
+        self.block_size
     }
 
     fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
-        let offset = block_id as usize * BLOCK_SIZE;
+        let offset = block_id as usize * self.block_size;
         if offset + buf.len() > self.size {
             return Err(DevError::Io);
         }
-        if buf.len() % BLOCK_SIZE != 0 {
+        if buf.len() % self.block_size != 0 {
             return Err(DevError::InvalidParam);
         }
         buf.copy_from_slice(&self.data[offset..offset + buf.len()]);
@@ -88,11 +116,11 @@ impl BlockDriverOps for RamDisk {
     }
 
     fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
-        let offset = block_id as usize * BLOCK_SIZE;
+        let offset = block_id as usize * self.block_size;
         if offset + buf.len() > self.size {
             return Err(DevError::Io);
         }
-        if buf.len() % BLOCK_SIZE != 0 {
+        if buf.len() % self.block_size != 0 {
             return Err(DevError::InvalidParam);
         }
         self.data[offset..offset + buf.len()].copy_from_slice(buf);
@@ -104,6 +132,6 @@ impl BlockDriverOps for RamDisk {
     }
 }
 
-const fn align_up(val: usize) -> usize {
-    (val + BLOCK_SIZE - 1) & !(BLOCK_SIZE - 1)
+const fn align_up(val: usize, align: usize) -> usize {
+    (val + align - 1) & !(align - 1)
 }
@@ -30,7 +30,7 @@ pub enum DeviceType {
 }
 
 /// The error type for device operation failures.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum DevError {
     /// An entity already exists.
     AlreadyExists,
@@ -64,6 +64,18 @@ pub trait NetDriverOps: BaseDriverOps {
     /// Allocate a memory buffer of a specified size for network transmission,
     /// returns [`DevResult`]
     fn alloc_tx_buffer(&mut self, size: usize) -> DevResult<NetBufPtr>;
+
+    /// The IRQ number of the device, or `None` if it is unknown and the
+    /// device has to be polled.
+    fn irq_num(&self) -> Option<usize> {
+        None
+    }
+
+    /// Acknowledges the interrupt of the device, returns whether the device
+    /// had raised it.
+    fn ack_interrupt(&mut self) -> bool {
+        false
+    }
 }
 
 /// A raw buffer struct for network device.
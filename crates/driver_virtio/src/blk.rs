@@ -1,11 +1,27 @@
+use alloc::{boxed::Box, collections::BTreeMap};
+
 use crate::as_dev_err;
-use driver_block::BlockDriverOps;
+use driver_block::{BlockDriverOps, BlockRequestId};
 use driver_common::{BaseDriverOps, DevResult, DeviceType};
-use virtio_drivers::{device::blk::VirtIOBlk as InnerDev, transport::Transport, Hal};
+use virtio_drivers::device::blk::{BlkReq, BlkResp, VirtIOBlk as InnerDev};
+use virtio_drivers::{transport::Transport, Hal};
+
+extern crate alloc;
+
+/// A request in the virtqueue, boxed so that its header and status stay at
+/// the same address while the device accesses them.
+struct PendingRequest {
+    req: BlkReq,
+    resp: BlkResp,
+    buf: *mut [u8],
+    write: bool,
+}
 
 /// The VirtIO block device driver.
 pub struct VirtIoBlkDev<H: Hal, T: Transport> {
     inner: InnerDev<H, T>,
+    irq_num: Option<usize>,
+    pending: BTreeMap<BlockRequestId, Box<PendingRequest>>,
 }
 
 unsafe impl<H: Hal, T: Transport> Send for VirtIoBlkDev<H, T> {}
@@ -17,8 +33,44 @@ impl<H: Hal, T: Transport> VirtIoBlkDev<H, T> {
     pub fn try_new(transport: T) -> DevResult<Self> {
         Ok(Self {
             inner: InnerDev::new(transport).map_err(as_dev_err)?,
+            irq_num: None,
+            pending: BTreeMap::new(),
         })
     }
+
+    /// Sets the IRQ number of the device, which is known by the bus.
+    pub fn set_irq_num(&mut self, irq_num: Option<usize>) {
+        self.irq_num = irq_num;
+    }
+
+    /// # Safety
+    ///
+    /// The buffer must stay valid until the request is finished.
+    unsafe fn submit(
+        &mut self,
+        block_id: u64,
+        buf: *mut [u8],
+        write: bool,
+    ) -> DevResult<BlockRequestId> {
+        let mut pending = Box::new(PendingRequest {
+            req: BlkReq::default(),
+            resp: BlkResp::default(),
+            buf,
+            write,
+        });
+        let PendingRequest { req, resp, .. } = &mut *pending;
+        let token = unsafe {
+            if write {
+                self.inner.write_blocks_nb(block_id as _, req, &*buf, resp)
+            } else {
+                self.inner
+                    .read_blocks_nb(block_id as _, req, &mut *buf, resp)
+            }
+        }
+        .map_err(as_dev_err)?;
+        self.pending.insert(token, pending);
+        Ok(token)
+    }
 }
 
 impl<H: Hal, T: Transport> const BaseDriverOps for VirtIoBlkDev<H, T> {
@@ -57,4 +109,52 @@ impl<H: Hal, T: Transport> BlockDriverOps for VirtIoBlkDev<H, T> {
     fn flush(&mut self) -> DevResult {
         Ok(())
     }
+
+    #[inline]
+    fn irq_num(&self) -> Option<usize> {
+        self.irq_num
+    }
+
+    #[inline]
+    fn ack_interrupt(&mut self) -> bool {
+        self.inner.ack_interrupt()
+    }
+
+    unsafe fn submit_read_block(
+        &mut self,
+        block_id: u64,
+        buf: *mut [u8],
+    ) -> DevResult<BlockRequestId> {
+        unsafe { self.submit(block_id, buf, false) }
+    }
+
+    unsafe fn submit_write_block(
+        &mut self,
+        block_id: u64,
+        buf: *const [u8],
+    ) -> DevResult<BlockRequestId> {
+        unsafe { self.submit(block_id, buf as *mut [u8], true) }
+    }
+
+    fn poll_completed(&mut self) -> Option<(BlockRequestId, DevResult)> {
+        let token = self.inner.peek_used()?;
+        let mut pending = self.pending.remove(&token)?;
+        let PendingRequest {
+            req,
+            resp,
+            buf,
+            write,
+        } = &mut *pending;
+        // SAFETY: the request was submitted with the same header, buffer and
+        // status, which are still valid.
+        let res = unsafe {
+            if *write {
+                self.inner.complete_write_blocks(token, req, &**buf, resp)
+            } else {
+                self.inner
+                    .complete_read_blocks(token, req, &mut **buf, resp)
+            }
+        };
+        Some((token, res.map_err(as_dev_err)))
+    }
 }
@@ -17,6 +17,7 @@ pub struct VirtIoNetDev<H: Hal, T: Transport, const QS: usize> {
     free_tx_bufs: Vec<NetBufBox>,
     buf_pool: Arc<NetBufPool>,
     inner: InnerDev<H, T, QS>,
+    irq_num: Option<usize>,
 }
 
 unsafe impl<H: Hal, T: Transport, const QS: usize> Send for VirtIoNetDev<H, T, QS> {}
@@ -40,6 +41,7 @@ impl<H: Hal, T: Transport, const QS: usize> VirtIoNetDev<H, T, QS> {
             tx_buffers,
             free_tx_bufs,
             buf_pool,
+            irq_num: None,
         };
 
         // 1. Fill all rx buffers.
@@ -70,6 +72,11 @@ impl<H: Hal, T: Transport, const QS: usize> VirtIoNetDev<H, T, QS> {
         // 3. Return the driver instance.
         Ok(dev)
     }
+
+    /// Sets the IRQ number of the device, which is known by the bus.
+    pub fn set_irq_num(&mut self, irq_num: Option<usize>) {
+        self.irq_num = irq_num;
+    }
 }
 
 impl<H: Hal, T: Transport, const QS: usize> const BaseDriverOps for VirtIoNetDev<H, T, QS> {
@@ -190,4 +197,14 @@ impl<H: Hal, T: Transport, const QS: usize> NetDriverOps for VirtIoNetDev<H, T,
         // 2. Return the buffer.
         Ok(net_buf.into_buf_ptr())
     }
+
+    #[inline]
+    fn irq_num(&self) -> Option<usize> {
+        self.irq_num
+    }
+
+    #[inline]
+    fn ack_interrupt(&mut self) -> bool {
+        self.inner.ack_interrupt()
+    }
 }
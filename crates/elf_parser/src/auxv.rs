@@ -66,6 +66,7 @@ pub fn get_auxv_vector(
 /// * `auxv` - The auxv vector of the app
 /// * `stack_top` - The top address of the stack
 /// * `stack_size` - The size of the stack.
+/// * `random` - The 16 random bytes pointed by `AT_RANDOM`
 ///
 /// # Return
 ///
@@ -86,11 +87,12 @@ pub fn get_app_stack_region(
     auxv: BTreeMap<u8, usize>,
     stack_top: VirtAddr,
     stack_size: usize,
+    random: [usize; 2],
 ) -> (Vec<u8>, usize) {
     let ustack_top = stack_top;
     let ustack_bottom = ustack_top + stack_size;
     // The stack variable is actually the information carried by the stack
-    let stack = init_stack(args, envs, auxv, ustack_bottom.into());
+    let stack = init_stack(args, envs, auxv, ustack_bottom.into(), random);
     let ustack_bottom = stack.get_sp();
     let mut data = [0_u8].repeat(stack_size - stack.get_len());
     data.extend(stack.get_data_front_ref());
@@ -67,6 +67,15 @@ pub fn bootargs() -> Option<&'static str> {
     MY_MACHINE_FDT.0.chosen().bootargs()
 }
 
+/// The random seed the bootloader passes in `/chosen/rng-seed`.
+pub fn rng_seed() -> Option<&'static [u8]> {
+    MY_MACHINE_FDT
+        .0
+        .find_node("/chosen")?
+        .property("rng-seed")
+        .map(|p| p.value)
+}
+
 pub fn fdt_size() -> usize {
     MY_MACHINE_FDT.0.total_size()
 }
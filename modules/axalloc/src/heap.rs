@@ -0,0 +1,181 @@
+//! The byte heap of the global allocator, which is able to give memory back to
+//! the page allocator.
+//!
+//! The memory allocated from the page allocator when the heap grows is managed
+//! as separate chunks, each of them has its own byte allocator and tracks its
+//! usage. Once all the allocations in a chunk are freed, the chunk is removed
+//! from the heap and its pages can be given back to the page allocator.
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
+use core::alloc::Layout;
+use core::mem::size_of;
+use core::ptr::NonNull;
+
+use crate::{DefaultByteAllocator, PAGE_SIZE};
+
+/// The number of pages used to store the header of a [`HeapChunk`].
+pub(crate) const CHUNK_HEADER_PAGES: usize = (size_of::<HeapChunk>() + PAGE_SIZE - 1) / PAGE_SIZE;
+
+/// A chunk of heap memory allocated from the page allocator.
+///
+/// The header is stored in its own pages, so that the whole chunk memory can
+/// be given to the byte allocator.
+struct HeapChunk {
+    start: usize,
+    size: usize,
+    /// Bytes allocated from this chunk.
+    used: usize,
+    balloc: DefaultByteAllocator,
+    next: Option<NonNull<HeapChunk>>,
+}
+
+impl HeapChunk {
+    fn contains(&self, pos: usize) -> bool {
+        self.start <= pos && pos < self.start + self.size
+    }
+}
+
+/// A chunk that has become empty and has been removed from the heap.
+pub(crate) struct EmptyChunk {
+    /// The start address of the header pages.
+    pub header: usize,
+    /// The start address of the chunk memory.
+    pub start: usize,
+    /// The size of the chunk memory in bytes.
+    pub size: usize,
+}
+
+/// A byte heap made of a pinned region and a list of releasable chunks.
+pub(crate) struct Heap {
+    /// The initial heap region, plus the regions added by `add_memory` that
+    /// the page allocator can not manage. They are never released.
+    pinned: DefaultByteAllocator,
+    /// The chunks allocated from the page allocator, newest first.
+    chunks: Option<NonNull<HeapChunk>>,
+    num_chunks: usize,
+}
+
+// The chunks are only accessed with the heap lock held.
+unsafe impl Send for Heap {}
+
+impl Heap {
+    pub const fn new() -> Self {
+        Self {
+            pinned: DefaultByteAllocator::new(),
+            chunks: None,
+            num_chunks: 0,
+        }
+    }
+
+    pub fn init(&mut self, start: usize, size: usize) {
+        self.pinned.init(start, size)
+    }
+
+    pub fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.pinned.add_memory(start, size)
+    }
+
+    /// Adds a chunk of memory allocated from the page allocator to the heap.
+    ///
+    /// # Safety
+    ///
+    /// `[header, header + CHUNK_HEADER_PAGES * PAGE_SIZE)` and
+    /// `[start, start + size)` must be valid, unused memory.
+    pub unsafe fn add_chunk(&mut self, header: usize, start: usize, size: usize) {
+        let mut balloc = DefaultByteAllocator::new();
+        balloc.init(start, size);
+        let chunk = header as *mut HeapChunk;
+        chunk.write(HeapChunk {
+            start,
+            size,
+            used: 0,
+            balloc,
+            next: self.chunks,
+        });
+        self.chunks = NonNull::new(chunk);
+        self.num_chunks += 1;
+    }
+
+    pub fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        if let Ok(ptr) = self.pinned.alloc(layout) {
+            return Ok(ptr);
+        }
+        let mut cur = self.chunks;
+        while let Some(mut chunk) = cur {
+            let chunk = unsafe { chunk.as_mut() };
+            if let Ok(ptr) = chunk.balloc.alloc(layout) {
+                chunk.used += layout.size();
+                return Ok(ptr);
+            }
+            cur = chunk.next;
+        }
+        Err(AllocError::NoMemory)
+    }
+
+    /// Gives back the allocated region. If it was the last allocation of a
+    /// chunk, the chunk is removed from the heap and returned, so that its
+    /// memory can be given back to the page allocator.
+    pub fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) -> Option<EmptyChunk> {
+        let addr = pos.as_ptr() as usize;
+        let mut prev: Option<NonNull<HeapChunk>> = None;
+        let mut cur = self.chunks;
+        while let Some(mut chunk_ptr) = cur {
+            let chunk = unsafe { chunk_ptr.as_mut() };
+            if !chunk.contains(addr) {
+                prev = cur;
+                cur = chunk.next;
+                continue;
+            }
+            chunk.balloc.dealloc(pos, layout);
+            chunk.used -= layout.size();
+            if chunk.used != 0 {
+                return None;
+            }
+            // unlink the empty chunk
+            match prev {
+                Some(mut prev) => unsafe { prev.as_mut().next = chunk.next },
+                None => self.chunks = chunk.next,
+            }
+            self.num_chunks -= 1;
+            let empty = EmptyChunk {
+                header: chunk_ptr.as_ptr() as usize,
+                start: chunk.start,
+                size: chunk.size,
+            };
+            unsafe { core::ptr::drop_in_place(chunk_ptr.as_ptr()) };
+            return Some(empty);
+        }
+        self.pinned.dealloc(pos, layout);
+        None
+    }
+
+    fn chunks(&self) -> impl Iterator<Item = &HeapChunk> + '_ {
+        let mut cur = self.chunks;
+        core::iter::from_fn(move || {
+            let chunk = unsafe { cur?.as_ref() };
+            cur = chunk.next;
+            Some(chunk)
+        })
+    }
+
+    /// Returns the number of chunks allocated from the page allocator.
+    pub fn num_chunks(&self) -> usize {
+        self.num_chunks
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.pinned.total_bytes() + self.chunks().map(|c| c.size).sum::<usize>()
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.pinned.used_bytes() + self.chunks().map(|c| c.balloc.used_bytes()).sum::<usize>()
+    }
+
+    pub fn available_bytes(&self) -> usize {
+        self.pinned.available_bytes()
+            + self
+                .chunks()
+                .map(|c| c.balloc.available_bytes())
+                .sum::<usize>()
+    }
+}
@@ -0,0 +1,793 @@
+//! Linux-like object caches (`kmem_cache`) built on top of the page allocator.
+//!
+//! A [`KmemCache`] hands out objects of a fixed layout. The objects are carved
+//! from *slabs*, runs of pages taken from the page allocator, and each CPU
+//! keeps a magazine of free objects, so that most allocations and
+//! deallocations only touch CPU-local data with IRQs and preemption disabled.
+//! The slabs are shared by all CPUs and protected by a lock, which is only
+//! taken to refill or flush a magazine.
+//!
+//! Small allocations of the global allocator are served by the generic
+//! `kmalloc-*` caches. Hot kernel objects get their own slabs and statistics
+//! from named caches: an allocation site runs its allocation in the
+//! [`KmemCache::scope`] of a named cache, or allocates its `Arc`s from an
+//! [`ArcCache`]. Allocations elsewhere are never served by a named cache,
+//! even if they have the same layout.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+use allocator::{AllocError, AllocResult};
+use kernel_guard::NoPreemptIrqSave;
+use spinlock::SpinNoIrq;
+
+use crate::{global_allocator, PAGE_SIZE};
+
+/// The interface used by the object caches to select the per-CPU magazine.
+#[crate_interface::def_interface]
+pub trait KmemIf {
+    /// Returns the ID of the current CPU, or `None` if it is unknown yet.
+    fn current_cpu_id() -> Option<usize>;
+}
+
+const MAX_CPUS: usize = axconfig::SMP;
+/// The capacity of a per-CPU magazine.
+const MAGAZINE_SIZE: usize = 32;
+const MAX_SLAB_PAGES: usize = 64;
+const MIN_OBJS_PER_SLAB: usize = 8;
+const MAX_NAMED_CACHES: usize = 32;
+
+/// The max allocation size served by the generic `kmalloc-*` caches.
+pub const KMALLOC_MAX_SIZE: usize = 1 << KMALLOC_MAX_SHIFT;
+const KMALLOC_MIN_SHIFT: usize = 3;
+const KMALLOC_MAX_SHIFT: usize = 11;
+const NUM_KMALLOC_CACHES: usize = KMALLOC_MAX_SHIFT - KMALLOC_MIN_SHIFT + 1;
+
+/// The max number of pages covered by the slab page maps (4 GB).
+const MAX_PAGES: usize = 1 << 20;
+
+const fn align_up(val: usize, align: usize) -> usize {
+    (val + align - 1) & !(align - 1)
+}
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// A free object, linked into the free list of its slab.
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// The header of a slab, stored at the beginning of its first page.
+struct Slab {
+    cache: *const KmemCache,
+    /// Free objects in this slab.
+    free: *mut FreeObject,
+    /// Objects taken out of this slab.
+    inuse: usize,
+    prev: *mut Slab,
+    next: *mut Slab,
+}
+
+/// The slabs of a cache, shared by all CPUs.
+struct Node {
+    /// Slabs with at least one free object.
+    partial: *mut Slab,
+    num_slabs: usize,
+    /// Slabs without any object in use. At most one of them is kept.
+    free_slabs: usize,
+    /// Objects taken out of the slabs, including those cached in magazines.
+    inuse: usize,
+}
+
+// The slabs are only accessed with the node lock held.
+unsafe impl Send for Node {}
+
+impl Node {
+    const fn new() -> Self {
+        Self {
+            partial: ptr::null_mut(),
+            num_slabs: 0,
+            free_slabs: 0,
+            inuse: 0,
+        }
+    }
+
+    unsafe fn link(&mut self, slab: *mut Slab) {
+        (*slab).prev = ptr::null_mut();
+        (*slab).next = self.partial;
+        if !self.partial.is_null() {
+            (*self.partial).prev = slab;
+        }
+        self.partial = slab;
+    }
+
+    unsafe fn unlink(&mut self, slab: *mut Slab) {
+        let (prev, next) = ((*slab).prev, (*slab).next);
+        if prev.is_null() {
+            self.partial = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+    }
+
+    /// Allocates a new slab from the page allocator.
+    fn grow(&mut self, cache: &KmemCache) -> AllocResult {
+        let start = global_allocator().alloc_pages(cache.slab_pages, PAGE_SIZE)?;
+        if !SLAB_MAP.mark(start, cache.slab_pages) {
+            global_allocator().dealloc_pages(start, cache.slab_pages);
+            return Err(AllocError::NoMemory);
+        }
+        let slab = start as *mut Slab;
+        unsafe {
+            let mut free = ptr::null_mut();
+            for i in (0..cache.objs_per_slab).rev() {
+                let obj = (start + cache.offset + i * cache.size) as *mut FreeObject;
+                obj.write(FreeObject { next: free });
+                free = obj;
+            }
+            slab.write(Slab {
+                cache,
+                free,
+                inuse: 0,
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+            });
+            self.link(slab);
+        }
+        self.num_slabs += 1;
+        self.free_slabs += 1;
+        Ok(())
+    }
+
+    /// Takes a free object out of the slabs, allocating a new slab if needed.
+    fn take(&mut self, cache: &KmemCache) -> AllocResult<NonNull<u8>> {
+        if self.partial.is_null() {
+            self.grow(cache)?;
+        }
+        unsafe {
+            let slab = self.partial;
+            let obj = (*slab).free;
+            (*slab).free = (*obj).next;
+            if (*slab).inuse == 0 {
+                self.free_slabs -= 1;
+            }
+            (*slab).inuse += 1;
+            self.inuse += 1;
+            if (*slab).free.is_null() {
+                self.unlink(slab);
+            }
+            Ok(NonNull::new_unchecked(obj as *mut u8))
+        }
+    }
+
+    /// Gives an object back to its slab. If there is more than one slab
+    /// without any object in use, the slab is given back to the page
+    /// allocator.
+    unsafe fn put(&mut self, cache: &KmemCache, obj: NonNull<u8>) {
+        let slab = SLAB_MAP
+            .find(obj.as_ptr() as usize)
+            .expect("object not allocated from a slab");
+        debug_assert!(ptr::eq((*slab).cache, cache));
+        if (*slab).free.is_null() {
+            self.link(slab);
+        }
+        let obj = obj.as_ptr() as *mut FreeObject;
+        obj.write(FreeObject { next: (*slab).free });
+        (*slab).free = obj;
+        (*slab).inuse -= 1;
+        self.inuse -= 1;
+        if (*slab).inuse == 0 {
+            self.free_slabs += 1;
+            if self.free_slabs > 1 {
+                self.unlink(slab);
+                self.free_slabs -= 1;
+                self.num_slabs -= 1;
+                let start = slab as usize;
+                SLAB_MAP.unmark(start, cache.slab_pages);
+                global_allocator().dealloc_pages(start, cache.slab_pages);
+            }
+        }
+    }
+}
+
+/// A per-CPU stack of free objects.
+struct Magazine {
+    len: usize,
+    objs: [*mut u8; MAGAZINE_SIZE],
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            objs: [ptr::null_mut(); MAGAZINE_SIZE],
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_MAGAZINE: UnsafeCell<Magazine> = UnsafeCell::new(Magazine::new());
+
+/// A cache of objects with the same layout, like `kmem_cache` in Linux.
+pub struct KmemCache {
+    name: &'static str,
+    /// The layout requested when the cache was created.
+    layout: Layout,
+    /// The size of each object, including padding.
+    size: usize,
+    /// The offset of the first object from the beginning of a slab.
+    offset: usize,
+    slab_pages: usize,
+    objs_per_slab: usize,
+    /// The max number of objects cached in a magazine.
+    limit: usize,
+    /// The number of objects moved between a magazine and the slabs at once.
+    batch: usize,
+    registered: AtomicBool,
+    node: SpinNoIrq<Node>,
+    cpus: [UnsafeCell<Magazine>; MAX_CPUS],
+}
+
+// The magazine of a CPU is only accessed on that CPU, with IRQs and preemption
+// disabled.
+unsafe impl Sync for KmemCache {}
+
+impl KmemCache {
+    /// Creates a new cache for objects of the given layout.
+    ///
+    /// The cache is not used by the global allocator until it is registered
+    /// by [`kmem_cache_register`].
+    pub const fn new(name: &'static str, layout: Layout) -> Self {
+        let align = max(layout.align(), align_of::<FreeObject>());
+        assert!(align <= PAGE_SIZE, "object alignment too large");
+        let size = align_up(max(layout.size(), size_of::<FreeObject>()), align);
+        let offset = align_up(size_of::<Slab>(), align);
+
+        let mut slab_pages = 1;
+        while slab_pages < MAX_SLAB_PAGES
+            && (slab_pages * PAGE_SIZE).saturating_sub(offset) / size < MIN_OBJS_PER_SLAB
+        {
+            slab_pages *= 2;
+        }
+        let objs_per_slab = (slab_pages * PAGE_SIZE).saturating_sub(offset) / size;
+        assert!(objs_per_slab > 0, "object too large for a slab");
+
+        // Cache less objects per CPU if they are large.
+        let limit = if size > PAGE_SIZE {
+            4
+        } else if size > 1024 {
+            8
+        } else if size > 256 {
+            16
+        } else {
+            MAGAZINE_SIZE
+        };
+
+        Self {
+            name,
+            layout,
+            size,
+            offset,
+            slab_pages,
+            objs_per_slab,
+            limit,
+            batch: limit.div_ceil(2),
+            registered: AtomicBool::new(false),
+            node: SpinNoIrq::new(Node::new()),
+            cpus: [EMPTY_MAGAZINE; MAX_CPUS],
+        }
+    }
+
+    /// Returns the name of the cache.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the layout of the objects in the cache.
+    pub const fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Whether an allocation of `layout` fits in the objects of the cache.
+    fn fits(&self, layout: Layout) -> bool {
+        layout.size() <= self.layout.size() && layout.align() <= self.layout.align()
+    }
+
+    /// Runs `f` with the allocations of the global allocator on the current
+    /// CPU served by this cache if they fit in its objects, and registers the
+    /// cache if it's not yet.
+    ///
+    /// This is how an allocation site, e.g., a `Box::new`, gets its objects
+    /// from a named cache, while they are still freed as usual. IRQs and
+    /// preemption are disabled while `f` runs, so it should do nothing but the
+    /// allocation.
+    pub fn scope<R>(&'static self, f: impl FnOnce() -> R) -> R {
+        let _ = kmem_cache_register(self);
+        with_scope(Scope::Cache(self), f).0
+    }
+
+    /// Returns the magazine of the current CPU.
+    ///
+    /// IRQs and preemption must be disabled while the magazine is in use.
+    #[allow(clippy::mut_from_ref)]
+    fn local_magazine(&self) -> Option<&mut Magazine> {
+        let cpu_id = crate_interface::call_interface!(KmemIf::current_cpu_id)?;
+        self.cpus.get(cpu_id).map(|mag| unsafe { &mut *mag.get() })
+    }
+
+    /// Allocates an object from the cache.
+    pub fn alloc(&self) -> AllocResult<NonNull<u8>> {
+        let _guard = NoPreemptIrqSave::new();
+        let Some(mag) = self.local_magazine() else {
+            return self.node.lock().take(self);
+        };
+        if mag.len == 0 {
+            let mut node = self.node.lock();
+            while mag.len < self.batch {
+                match node.take(self) {
+                    Ok(obj) => {
+                        mag.objs[mag.len] = obj.as_ptr();
+                        mag.len += 1;
+                    }
+                    Err(_) if mag.len > 0 => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        mag.len -= 1;
+        Ok(unsafe { NonNull::new_unchecked(mag.objs[mag.len]) })
+    }
+
+    /// Gives an object back to the cache.
+    ///
+    /// # Safety
+    ///
+    /// `obj` must be allocated by [`KmemCache::alloc`] of the same cache.
+    pub unsafe fn free(&self, obj: NonNull<u8>) {
+        let _guard = NoPreemptIrqSave::new();
+        let Some(mag) = self.local_magazine() else {
+            return self.node.lock().put(self, obj);
+        };
+        if mag.len >= self.limit {
+            self.flush(mag, self.batch);
+        }
+        mag.objs[mag.len] = obj.as_ptr();
+        mag.len += 1;
+    }
+
+    /// Moves the oldest `count` objects of the magazine back to the slabs.
+    fn flush(&self, mag: &mut Magazine, count: usize) {
+        let count = count.min(mag.len);
+        let mut node = self.node.lock();
+        for &obj in &mag.objs[..count] {
+            unsafe { node.put(self, NonNull::new_unchecked(obj)) };
+        }
+        mag.objs.copy_within(count..mag.len, 0);
+        mag.len -= count;
+    }
+
+    /// Gives the objects cached by the current CPU back to the slabs, so that
+    /// the empty slabs can be released.
+    pub fn shrink(&self) {
+        let _guard = NoPreemptIrqSave::new();
+        if let Some(mag) = self.local_magazine() {
+            let len = mag.len;
+            self.flush(mag, len);
+        }
+    }
+
+    /// Returns the usage statistics of the cache.
+    pub fn info(&self) -> KmemCacheInfo {
+        let (num_slabs, free_slabs, inuse) = {
+            let node = self.node.lock();
+            (node.num_slabs, node.free_slabs, node.inuse)
+        };
+        // Racy, but good enough for statistics.
+        let cached: usize = self
+            .cpus
+            .iter()
+            .map(|mag| unsafe { ptr::read_volatile(ptr::addr_of!((*mag.get()).len)) })
+            .sum();
+        KmemCacheInfo {
+            name: self.name,
+            active_objs: inuse.saturating_sub(cached),
+            num_objs: num_slabs * self.objs_per_slab,
+            obj_size: self.size,
+            objs_per_slab: self.objs_per_slab,
+            pages_per_slab: self.slab_pages,
+            limit: self.limit,
+            batch: self.batch,
+            active_slabs: num_slabs - free_slabs,
+            num_slabs,
+        }
+    }
+}
+
+/// Usage statistics of a [`KmemCache`], as shown in `/proc/slabinfo`.
+#[derive(Debug, Clone, Copy)]
+pub struct KmemCacheInfo {
+    /// The name of the cache.
+    pub name: &'static str,
+    /// Objects in use.
+    pub active_objs: usize,
+    /// Objects in all the slabs, in use or not.
+    pub num_objs: usize,
+    /// The size of each object in bytes.
+    pub obj_size: usize,
+    /// Objects in each slab.
+    pub objs_per_slab: usize,
+    /// Pages in each slab.
+    pub pages_per_slab: usize,
+    /// The max number of objects cached by each CPU.
+    pub limit: usize,
+    /// The number of objects moved between a CPU and the slabs at once.
+    pub batch: usize,
+    /// Slabs with objects in use.
+    pub active_slabs: usize,
+    /// All the slabs.
+    pub num_slabs: usize,
+}
+
+const fn kmalloc_caches() -> [KmemCache; NUM_KMALLOC_CACHES] {
+    const NAMES: [&str; NUM_KMALLOC_CACHES] = [
+        "kmalloc-8",
+        "kmalloc-16",
+        "kmalloc-32",
+        "kmalloc-64",
+        "kmalloc-128",
+        "kmalloc-256",
+        "kmalloc-512",
+        "kmalloc-1k",
+        "kmalloc-2k",
+    ];
+    macro_rules! kmalloc {
+        ($($i:literal),*) => {
+            [$({
+                let size = 1 << (KMALLOC_MIN_SHIFT + $i);
+                // SAFETY: `size` is a power of two.
+                KmemCache::new(NAMES[$i], unsafe { Layout::from_size_align_unchecked(size, size) })
+            }),*]
+        };
+    }
+    kmalloc!(0, 1, 2, 3, 4, 5, 6, 7, 8)
+}
+
+static KMALLOC_CACHES: [KmemCache; NUM_KMALLOC_CACHES] = kmalloc_caches();
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NULL_CACHE: AtomicPtr<KmemCache> = AtomicPtr::new(ptr::null_mut());
+
+static NAMED_CACHES: [AtomicPtr<KmemCache>; MAX_NAMED_CACHES] = [NULL_CACHE; MAX_NAMED_CACHES];
+static NUM_NAMED_CACHES: AtomicUsize = AtomicUsize::new(0);
+static REGISTER_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+
+fn named_caches() -> impl Iterator<Item = &'static KmemCache> {
+    let num = NUM_NAMED_CACHES.load(Ordering::Acquire);
+    NAMED_CACHES[..num]
+        .iter()
+        .map(|cache| unsafe { &*cache.load(Ordering::Relaxed) })
+}
+
+/// Registers a named cache, so that it shows up in [`slabinfo`].
+///
+/// Registering a cache more than once has no effect.
+pub fn kmem_cache_register(cache: &'static KmemCache) -> AllocResult {
+    if cache.registered.load(Ordering::Acquire) {
+        return Ok(());
+    }
+    let _lock = REGISTER_LOCK.lock();
+    if cache.registered.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let num = NUM_NAMED_CACHES.load(Ordering::Relaxed);
+    if num >= MAX_NAMED_CACHES {
+        return Err(AllocError::NoMemory);
+    }
+    NAMED_CACHES[num].store(cache as *const _ as *mut _, Ordering::Relaxed);
+    NUM_NAMED_CACHES.store(num + 1, Ordering::Release);
+    cache.registered.store(true, Ordering::Release);
+    debug!(
+        "register kmem cache {}: size = {}, align = {}",
+        cache.name,
+        cache.layout.size(),
+        cache.layout.align()
+    );
+    Ok(())
+}
+
+/// Creates and registers a named cache for objects of the given layout.
+///
+/// Named caches live forever, just like most `kmem_cache`s in Linux.
+pub fn kmem_cache_create(name: &'static str, layout: Layout) -> AllocResult<&'static KmemCache> {
+    let cache: &'static KmemCache = Box::leak(Box::new(KmemCache::new(name, layout)));
+    kmem_cache_register(cache)?;
+    Ok(cache)
+}
+
+/// Returns all the registered caches, named caches first.
+pub fn kmem_caches() -> impl Iterator<Item = &'static KmemCache> {
+    named_caches().chain(KMALLOC_CACHES.iter())
+}
+
+/// A named cache of the allocations made by `Arc::new` for a `T`, created on
+/// first use.
+///
+/// The layout of the allocations is the one seen by the global allocator, as
+/// that of `Arc` is private. If the global allocator is not this one, e.g., in
+/// tests on the host, the `Arc`s are allocated as usual.
+pub struct ArcCache<T> {
+    name: &'static str,
+    cache: AtomicPtr<KmemCache>,
+    /// Whether the creation of the cache has been tried.
+    probed: AtomicBool,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+static ARC_CACHE_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+
+impl<T> ArcCache<T> {
+    /// Creates a new cache, which is not created in the allocator until the
+    /// first `Arc` is allocated from it.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            cache: AtomicPtr::new(ptr::null_mut()),
+            probed: AtomicBool::new(false),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the layout of the allocations made by `Arc::<T>::new`.
+    fn arc_layout() -> Option<Layout> {
+        // `MaybeUninit<T>` has the same layout as `T`.
+        #[cfg(target_os = "none")]
+        return alloc_layout(|| drop(Arc::new(core::mem::MaybeUninit::<T>::uninit())));
+        #[cfg(not(target_os = "none"))]
+        None
+    }
+
+    fn cache(&self) -> Option<&'static KmemCache> {
+        if !self.probed.load(Ordering::Acquire) {
+            let _lock = ARC_CACHE_LOCK.lock();
+            if !self.probed.load(Ordering::Relaxed) {
+                if let Some(layout) = Self::arc_layout() {
+                    match kmem_cache_create(self.name, layout) {
+                        Ok(cache) => self
+                            .cache
+                            .store(cache as *const _ as *mut _, Ordering::Relaxed),
+                        Err(e) => warn!("failed to create kmem cache {}: {:?}", self.name, e),
+                    }
+                }
+                self.probed.store(true, Ordering::Release);
+            }
+        }
+        let cache = self.cache.load(Ordering::Relaxed);
+        (!cache.is_null()).then(|| unsafe { &*cache })
+    }
+
+    /// Allocates an `Arc` of `value` from the cache.
+    pub fn new_arc(&self, value: T) -> Arc<T> {
+        match self.cache() {
+            Some(cache) => cache.scope(|| Arc::new(value)),
+            None => Arc::new(value),
+        }
+    }
+}
+
+/// What the global allocator does on a CPU, see [`KmemCache::scope`] and
+/// [`alloc_layout`].
+#[derive(Clone, Copy)]
+enum Scope {
+    None,
+    /// Serve the allocations fitting in the objects of the cache by it.
+    Cache(&'static KmemCache),
+    /// Record the layout of the first allocation.
+    Record(Option<Layout>),
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SCOPE: UnsafeCell<Scope> = UnsafeCell::new(Scope::None);
+
+struct CpuScopes([UnsafeCell<Scope>; MAX_CPUS]);
+
+// The scope of a CPU is only accessed on that CPU, with IRQs and preemption
+// disabled.
+unsafe impl Sync for CpuScopes {}
+
+static SCOPES: CpuScopes = CpuScopes([EMPTY_SCOPE; MAX_CPUS]);
+/// The number of CPUs in a scope, to skip the lookup of the scope otherwise.
+static NUM_SCOPES: AtomicUsize = AtomicUsize::new(0);
+
+fn local_scope() -> Option<*mut Scope> {
+    let cpu_id = crate_interface::call_interface!(KmemIf::current_cpu_id)?;
+    SCOPES.0.get(cpu_id).map(UnsafeCell::get)
+}
+
+/// Runs `f` in `scope` on the current CPU, returns its result and the scope
+/// after it. If the current CPU is unknown, `f` is run as usual.
+fn with_scope<R>(scope: Scope, f: impl FnOnce() -> R) -> (R, Scope) {
+    let _guard = NoPreemptIrqSave::new();
+    let Some(local) = local_scope() else {
+        return (f(), Scope::None);
+    };
+    let prev = unsafe { local.replace(scope) };
+    NUM_SCOPES.fetch_add(1, Ordering::Relaxed);
+    let ret = f();
+    NUM_SCOPES.fetch_sub(1, Ordering::Relaxed);
+    (ret, unsafe { local.replace(prev) })
+}
+
+/// Returns the layout of the first allocation made by `f` through the global
+/// allocator, or `None` if there is none.
+pub fn alloc_layout(f: impl FnOnce()) -> Option<Layout> {
+    match with_scope(Scope::Record(None), f).1 {
+        Scope::Record(layout) => layout,
+        _ => None,
+    }
+}
+
+/// Applies the scope of the current CPU to an allocation of `layout`, returns
+/// the named cache to serve it, if any.
+fn scoped_cache(layout: Layout) -> Option<&'static KmemCache> {
+    if NUM_SCOPES.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let _guard = NoPreemptIrqSave::new();
+    let scope = local_scope()?;
+    unsafe {
+        match *scope {
+            Scope::Cache(cache) => cache.fits(layout).then_some(cache),
+            Scope::Record(None) => {
+                *scope = Scope::Record(Some(layout));
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the cache which serves allocations of the given layout, if any.
+pub(crate) fn cache_for(layout: Layout) -> Option<&'static KmemCache> {
+    if let Some(cache) = scoped_cache(layout) {
+        return Some(cache);
+    }
+    let size = layout.size().max(layout.align());
+    if size > KMALLOC_MAX_SIZE {
+        return None;
+    }
+    let shift = (size.next_power_of_two().trailing_zeros() as usize).max(KMALLOC_MIN_SHIFT);
+    Some(&KMALLOC_CACHES[shift - KMALLOC_MIN_SHIFT])
+}
+
+/// Returns the cache which `ptr` was allocated from, if it is in a slab.
+pub(crate) fn cache_of(ptr: NonNull<u8>) -> Option<&'static KmemCache> {
+    let slab = SLAB_MAP.find(ptr.as_ptr() as usize)?;
+    Some(unsafe { &*(*slab).cache })
+}
+
+/// Sets the lowest address of the memory given to the page allocator.
+pub(crate) fn init(base: usize) {
+    SLAB_MAP
+        .base
+        .store(base & !(PAGE_SIZE - 1), Ordering::Relaxed);
+}
+
+/// Returns the content of `/proc/slabinfo`.
+pub fn slabinfo() -> String {
+    let mut buf = String::from(
+        "slabinfo - version: 2.1\n\
+         # name            <active_objs> <num_objs> <objsize> <objperslab> <pagesperslab> \
+         : tunables <limit> <batchcount> <sharedfactor> : slabdata <active_slabs> <num_slabs> <sharedavail>\n",
+    );
+    for cache in kmem_caches() {
+        let info = cache.info();
+        let _ = writeln!(
+            buf,
+            "{:<17} {:>6} {:>6} {:>6} {:>4} {:>4} : tunables {:>4} {:>4} {:>4} : slabdata {:>6} {:>6} {:>6}",
+            info.name,
+            info.active_objs,
+            info.num_objs,
+            info.obj_size,
+            info.objs_per_slab,
+            info.pages_per_slab,
+            info.limit,
+            info.batch,
+            0,
+            info.active_slabs,
+            info.num_slabs,
+            0,
+        );
+    }
+    buf
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+/// Bitmaps of the pages used by slabs and of the first page of each slab,
+/// indexed by the page number relative to `base`. They are used to find the
+/// slab of an object given only its address, like `PageSlab` in Linux.
+struct SlabMap {
+    base: AtomicUsize,
+    pages: [AtomicU64; MAX_PAGES / 64],
+    heads: [AtomicU64; MAX_PAGES / 64],
+}
+
+static SLAB_MAP: SlabMap = SlabMap {
+    base: AtomicUsize::new(0),
+    pages: [ZERO; MAX_PAGES / 64],
+    heads: [ZERO; MAX_PAGES / 64],
+};
+
+impl SlabMap {
+    fn page_index(&self, addr: usize) -> Option<usize> {
+        let idx = addr.checked_sub(self.base.load(Ordering::Relaxed))? / PAGE_SIZE;
+        (idx < MAX_PAGES).then_some(idx)
+    }
+
+    fn test(map: &[AtomicU64], idx: usize) -> bool {
+        map[idx / 64].load(Ordering::Acquire) & (1 << (idx % 64)) != 0
+    }
+
+    fn set(map: &[AtomicU64], idx: usize, val: bool) {
+        if val {
+            map[idx / 64].fetch_or(1 << (idx % 64), Ordering::Release);
+        } else {
+            map[idx / 64].fetch_and(!(1 << (idx % 64)), Ordering::Release);
+        }
+    }
+
+    /// Marks the pages of a new slab. Returns `false` if they are out of the
+    /// range covered by the maps.
+    fn mark(&self, start: usize, num_pages: usize) -> bool {
+        let Some(first) = self.page_index(start) else {
+            return false;
+        };
+        if first + num_pages > MAX_PAGES {
+            return false;
+        }
+        for idx in first..first + num_pages {
+            Self::set(&self.pages, idx, true);
+        }
+        Self::set(&self.heads, first, true);
+        true
+    }
+
+    fn unmark(&self, start: usize, num_pages: usize) {
+        let first = self.page_index(start).unwrap();
+        Self::set(&self.heads, first, false);
+        for idx in first..first + num_pages {
+            Self::set(&self.pages, idx, false);
+        }
+    }
+
+    /// Finds the slab containing `addr`.
+    fn find(&self, addr: usize) -> Option<*mut Slab> {
+        let idx = self.page_index(addr)?;
+        if !Self::test(&self.pages, idx) {
+            return None;
+        }
+        let base = self.base.load(Ordering::Relaxed);
+        (idx.saturating_sub(MAX_SLAB_PAGES - 1)..=idx)
+            .rev()
+            .find(|&i| Self::test(&self.heads, i))
+            .map(|i| (base + i * PAGE_SIZE) as *mut Slab)
+    }
+}
@@ -11,10 +11,13 @@
 extern crate log;
 extern crate alloc;
 
+mod heap;
+mod kmem;
 mod page;
-use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+use allocator::{AllocError, AllocResult, BaseAllocator, BitmapPageAllocator, PageAllocator};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
+use heap::{Heap, CHUNK_HEADER_PAGES};
 pub use page::PhysPage;
 use spinlock::SpinNoIrq;
 
@@ -23,6 +26,11 @@ const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
 pub use page::GlobalPage;
 
+pub use kmem::{
+    alloc_layout, kmem_cache_create, kmem_cache_register, kmem_caches, slabinfo, ArcCache,
+    KmemCache, KmemCacheInfo, KmemIf, KMALLOC_MAX_SIZE,
+};
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "slab")] {
         use allocator::SlabByteAllocator as DefaultByteAllocator;
@@ -40,12 +48,22 @@ cfg_if::cfg_if! {
 /// there is no memory, asks the page allocator for more memory and adds it to
 /// the byte allocator.
 ///
+/// The memory added to the byte allocator this way is tracked per chunk. When
+/// all the allocations in a chunk are freed, the chunk is given back to the
+/// page allocator, so that the heap shrinks after a burst of allocations.
+///
+/// Allocations no larger than [`KMALLOC_MAX_SIZE`], and those in the scope of
+/// a named [`KmemCache`], bypass the byte allocator and are served
+/// by the object caches, whose per-CPU magazines avoid taking the global heap
+/// lock in the common case.
+///
 /// Currently, [`TlsfByteAllocator`] is used as the byte allocator, while
 /// [`BitmapPageAllocator`] is used as the page allocator.
 ///
 /// [`TlsfByteAllocator`]: allocator::TlsfByteAllocator
+/// [`ByteAllocator`]: allocator::ByteAllocator
 pub struct GlobalAllocator {
-    balloc: SpinNoIrq<DefaultByteAllocator>,
+    balloc: SpinNoIrq<Heap>,
     palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
 }
 
@@ -53,7 +71,7 @@ impl GlobalAllocator {
     /// Creates an empty [`GlobalAllocator`].
     pub const fn new() -> Self {
         Self {
-            balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
+            balloc: SpinNoIrq::new(Heap::new()),
             palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
         }
     }
@@ -80,6 +98,7 @@ impl GlobalAllocator {
         assert!(size > MIN_HEAP_SIZE);
         let init_heap_size = MIN_HEAP_SIZE;
         self.palloc.lock().init(start_vaddr, size);
+        kmem::init(start_vaddr);
         let heap_ptr = self
             .alloc_pages(init_heap_size / PAGE_SIZE, PAGE_SIZE)
             .unwrap();
@@ -88,21 +107,37 @@ impl GlobalAllocator {
 
     /// Add the given region to the allocator.
     ///
-    /// It will add the whole region to the byte allocator.
+    /// It will add the whole region to the page allocator. If the page
+    /// allocator can not manage the region (e.g., it is out of the range
+    /// covered by its bitmap), the region is added to the byte allocator
+    /// instead, and will never be given back.
     pub fn add_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        match self.palloc.lock().add_memory(start_vaddr, size) {
+            Err(AllocError::InvalidParam) => {}
+            res => return res,
+        }
+        warn!(
+            "memory region [{:#x}, {:#x}) is not managed by the page allocator",
+            start_vaddr,
+            start_vaddr + size
+        );
         self.balloc.lock().add_memory(start_vaddr, size)
     }
 
     /// Allocate arbitrary number of bytes. Returns the left bound of the
     /// allocated region.
     ///
-    /// It firstly tries to allocate from the byte allocator. If there is no
-    /// memory, it asks the page allocator for more memory and adds it to the
-    /// byte allocator.
+    /// Small objects are allocated from the object cache for the layout.
+    /// Otherwise, it firstly tries to allocate from the byte allocator. If
+    /// there is no memory, it asks the page allocator for more memory and adds
+    /// it to the byte allocator.
     ///
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     ///  aligned to it.
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        if let Some(cache) = kmem::cache_for(layout) {
+            return cache.alloc();
+        }
         // simple two-level allocator: if no heap memory, allocate from the page allocator.
         let mut balloc = self.balloc.lock();
         loop {
@@ -113,27 +148,51 @@ impl GlobalAllocator {
                 let expand_size = old_size
                     .max(layout.size())
                     .next_power_of_two()
-                    .max(PAGE_SIZE);
+                    .max(MIN_HEAP_SIZE);
                 let heap_ptr = self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
+                let header_ptr = match self.alloc_pages(CHUNK_HEADER_PAGES, PAGE_SIZE) {
+                    Ok(ptr) => ptr,
+                    Err(e) => {
+                        self.dealloc_pages(heap_ptr, expand_size / PAGE_SIZE);
+                        return Err(e);
+                    }
+                };
                 debug!(
                     "expand heap memory: [{:#x}, {:#x})",
                     heap_ptr,
                     heap_ptr + expand_size
                 );
-                balloc.add_memory(heap_ptr, expand_size)?;
+                unsafe { balloc.add_chunk(header_ptr, heap_ptr, expand_size) };
             }
         }
     }
 
-    /// Gives back the allocated region to the byte allocator.
+    /// Gives back the allocated region to the object cache it came from, or to
+    /// the byte allocator.
     ///
     /// The region should be allocated by [`alloc`], and `align_pow2` should be
     /// the same as the one used in [`alloc`]. Otherwise, the behavior is
     /// undefined.
     ///
+    /// If it was the last allocation in a chunk of memory which was taken
+    /// from the page allocator, the chunk is given back to the page allocator.
+    ///
     /// [`alloc`]: GlobalAllocator::alloc
     pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
-        self.balloc.lock().dealloc(pos, layout)
+        if let Some(cache) = kmem::cache_of(pos) {
+            unsafe { cache.free(pos) };
+            return;
+        }
+        let empty = self.balloc.lock().dealloc(pos, layout);
+        if let Some(chunk) = empty {
+            debug!(
+                "shrink heap memory: [{:#x}, {:#x})",
+                chunk.start,
+                chunk.start + chunk.size
+            );
+            self.dealloc_pages(chunk.start, chunk.size / PAGE_SIZE);
+            self.dealloc_pages(chunk.header, CHUNK_HEADER_PAGES);
+        }
     }
 
     /// Allocates contiguous pages.
@@ -157,6 +216,12 @@ impl GlobalAllocator {
         self.palloc.lock().dealloc_pages(pos, num_pages)
     }
 
+    /// Returns the number of memory chunks that the byte allocator currently
+    /// holds from the page allocator.
+    pub fn heap_chunks(&self) -> usize {
+        self.balloc.lock().num_chunks()
+    }
+
     /// Returns the number of allocated bytes in the byte allocator.
     pub fn used_bytes(&self) -> usize {
         self.balloc.lock().used_bytes()
@@ -222,7 +287,8 @@ pub fn global_init(start_vaddr: usize, size: usize) {
 /// Users should ensure that the region is valid and not being used by others,
 /// so that the allocated memory is also valid.
 ///
-/// It's similar to [`global_init`], but can be called multiple times.
+/// It's similar to [`global_init`], but can be called multiple times, e.g.,
+/// for each discontiguous free memory region reported by the platform.
 pub fn global_add_memory(start_vaddr: usize, size: usize) -> AllocResult {
     debug!(
         "add a memory region to global allocator: [{:#x}, {:#x})",
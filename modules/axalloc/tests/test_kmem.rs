@@ -0,0 +1,159 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use axalloc::{alloc_layout, global_allocator, kmem_caches, KmemCache, KMALLOC_MAX_SIZE};
+
+const MEMORY_SIZE: usize = 16 * 1024 * 1024;
+
+struct KmemIfImpl;
+
+#[crate_interface::impl_interface]
+impl axalloc::KmemIf for KmemIfImpl {
+    fn current_cpu_id() -> Option<usize> {
+        Some(0)
+    }
+}
+
+static OBJ_CACHE: KmemCache = KmemCache::new("test_obj", Layout::new::<[u64; 12]>());
+static NAMED_CACHE: KmemCache = KmemCache::new("test_named", Layout::new::<[u64; 5]>());
+
+/// Returns the objects in use of the registered cache `name`.
+fn active_objs(name: &str) -> usize {
+    kmem_caches()
+        .find(|cache| cache.name() == name)
+        .map_or(0, |cache| cache.info().active_objs)
+}
+
+fn alloc(layout: Layout) -> NonNull<u8> {
+    global_allocator().alloc(layout).unwrap()
+}
+
+fn dealloc(ptr: NonNull<u8>, layout: Layout) {
+    global_allocator().dealloc(ptr, layout)
+}
+
+fn test_slab() {
+    println!("test slab:");
+    let per_slab = OBJ_CACHE.info().objs_per_slab;
+    let mut objs: Vec<_> = (0..3 * per_slab)
+        .map(|_| OBJ_CACHE.alloc().unwrap())
+        .collect();
+    for obj in &objs {
+        assert_eq!(obj.as_ptr() as usize % OBJ_CACHE.layout().align(), 0);
+        unsafe { obj.as_ptr().write_bytes(0xaa, OBJ_CACHE.layout().size()) };
+    }
+    let mut addrs: Vec<_> = objs.iter().map(|obj| obj.as_ptr() as usize).collect();
+    addrs.sort_unstable();
+    addrs.dedup();
+    assert_eq!(addrs.len(), objs.len());
+
+    let info = OBJ_CACHE.info();
+    assert_eq!(info.active_objs, objs.len());
+    assert!(info.num_slabs >= 3);
+    assert_eq!(info.active_slabs, info.num_slabs);
+
+    // freed objects are reused
+    let last = objs.pop().unwrap();
+    unsafe { OBJ_CACHE.free(last) };
+    assert_eq!(OBJ_CACHE.alloc().unwrap(), last);
+    objs.push(last);
+
+    // the empty slabs are given back to the page allocator, except one
+    let pages = global_allocator().used_pages();
+    for obj in objs {
+        unsafe { OBJ_CACHE.free(obj) };
+    }
+    OBJ_CACHE.shrink();
+    let info = OBJ_CACHE.info();
+    assert_eq!(info.active_objs, 0);
+    assert_eq!(info.active_slabs, 0);
+    assert!(info.num_slabs <= 1);
+    assert!(global_allocator().used_pages() < pages);
+    println!("test_slab() OK!");
+}
+
+fn test_kmalloc() {
+    println!("test kmalloc:");
+    // served by the cache of the next power of two
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    let active = active_objs("kmalloc-32");
+    let ptr = alloc(layout);
+    assert_eq!(active_objs("kmalloc-32"), active + 1);
+    dealloc(ptr, layout);
+    assert_eq!(active_objs("kmalloc-32"), active);
+
+    // the alignment counts as the size
+    let layout = Layout::from_size_align(8, 64).unwrap();
+    let active = active_objs("kmalloc-64");
+    let ptr = alloc(layout);
+    assert_eq!(ptr.as_ptr() as usize % 64, 0);
+    assert_eq!(active_objs("kmalloc-64"), active + 1);
+    dealloc(ptr, layout);
+    assert_eq!(active_objs("kmalloc-64"), active);
+
+    // larger allocations are not served by the caches
+    let layout = Layout::from_size_align(KMALLOC_MAX_SIZE + 1, 8).unwrap();
+    let active: Vec<_> = kmem_caches()
+        .map(|cache| cache.info().active_objs)
+        .collect();
+    let ptr = alloc(layout);
+    let after: Vec<_> = kmem_caches()
+        .map(|cache| cache.info().active_objs)
+        .collect();
+    assert_eq!(active, after);
+    dealloc(ptr, layout);
+    println!("test_kmalloc() OK!");
+}
+
+fn test_named_cache() {
+    println!("test named cache:");
+    let layout = Layout::new::<[u64; 5]>();
+    // allocations of the same layout elsewhere are not served by the cache
+    let active = active_objs("kmalloc-64");
+    let ptr = alloc(layout);
+    assert_eq!(active_objs("kmalloc-64"), active + 1);
+    assert_eq!(active_objs("test_named"), 0);
+    dealloc(ptr, layout);
+
+    let ptr = NAMED_CACHE.scope(|| alloc(layout));
+    assert!(kmem_caches().any(|cache| cache.name() == "test_named"));
+    assert_eq!(active_objs("test_named"), 1);
+    assert_eq!(active_objs("kmalloc-64"), active);
+    // freed through the global allocator, like a `Box`
+    dealloc(ptr, layout);
+    assert_eq!(active_objs("test_named"), 0);
+
+    // allocations too large for the objects are served as usual
+    let large = Layout::new::<[u64; 16]>();
+    let active = active_objs("kmalloc-128");
+    let ptr = NAMED_CACHE.scope(|| alloc(large));
+    assert_eq!(active_objs("test_named"), 0);
+    assert_eq!(active_objs("kmalloc-128"), active + 1);
+    dealloc(ptr, large);
+    println!("test_named_cache() OK!");
+}
+
+fn test_alloc_layout() {
+    println!("test alloc layout:");
+    let first = Layout::from_size_align(40, 8).unwrap();
+    let second = Layout::from_size_align(100, 4).unwrap();
+    let layout = alloc_layout(|| {
+        let ptr = alloc(first);
+        dealloc(alloc(second), second);
+        dealloc(ptr, first);
+    });
+    assert_eq!(layout, Some(first));
+    assert_eq!(alloc_layout(|| {}), None);
+    println!("test_alloc_layout() OK!");
+}
+
+#[test]
+fn test_kmem() {
+    let memory = unsafe { std::alloc::alloc(Layout::from_size_align(MEMORY_SIZE, 4096).unwrap()) };
+    axalloc::global_init(memory as usize, MEMORY_SIZE);
+
+    test_slab();
+    test_kmalloc();
+    test_named_cache();
+    test_alloc_layout();
+}
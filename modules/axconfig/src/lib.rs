@@ -20,3 +20,5 @@ pub const PHYS_MEMORY_END: usize = PHYS_MEMORY_BASE + PHYS_MEMORY_SIZE;
 
 /// user memory
 pub const USER_MEMORY_START: usize = 0x1000;
+/// End of user memory (exclusive)
+pub const USER_MEMORY_END: usize = 0x1_0000_0000;
@@ -5,9 +5,12 @@ impl AllDevices {
     pub(crate) fn probe_bus_devices(&mut self) {
         // TODO: parse device tree
         #[cfg(feature = "virtio")]
-        for reg in axconfig::VIRTIO_MMIO_REGIONS {
+        for (i, reg) in axconfig::VIRTIO_MMIO_REGIONS.iter().enumerate() {
+            // the IRQs of the devices are numbered in order of their regions
+            let irq =
+                (axconfig::VIRTIO_MMIO_IRQ_BASE != 0).then_some(axconfig::VIRTIO_MMIO_IRQ_BASE + i);
             for_each_drivers!(type Driver, {
-                if let Some(dev) = Driver::probe_mmio(reg.0, reg.1) {
+                if let Some(dev) = Driver::probe_mmio(reg.0, reg.1, irq) {
                     info!(
                         "registered a new {:?} device at [PA:{:#x}, PA:{:#x}): {:?}",
                         dev.device_type(),
@@ -26,5 +26,25 @@ create_test_img() {
 	sudo umount mnt
 }
 
+create_test_img_ext4() {
+	local name=$1
+	local blkcount=$2
+	local src=`mktemp -d`
+	for i in $(seq 1 1000); do
+	  echo "Rust is cool!" >>"$src/long.txt"
+	done
+	echo "Rust is cool!" >>"$src/short.txt"
+	mkdir -p "$src/very/long/path"
+	echo "Rust is cool!" >>"$src/very/long/path/test.txt"
+	mkdir -p "$src/very-long-dir-name"
+	echo "Rust is cool!" >>"$src/very-long-dir-name/very-long-file-name.txt"
+
+	# no mounting needed, the files are copied by mkfs
+	rm -f "$name"
+	mkfs.ext4 -b 1024 -L "Test!" -d "$src" "$name" $blkcount
+	rm -rf "$src"
+}
+
 create_test_img "$CUR_DIR/fat16.img" 2500 16
 create_test_img "$CUR_DIR/fat32.img" 34000 32
+create_test_img_ext4 "$CUR_DIR/ext4.img" 4096
@@ -1,8 +1,10 @@
+use alloc::sync::Arc;
 use axio::{prelude::*, Result, SeekFrom};
 use core::fmt;
 
 use super::FileExt;
 use crate::fops;
+use crate::notify::{OpenFile, WatchMask};
 
 /// A structure representing a type of file with accessors for each file type.
 /// It is returned by [`Metadata::file_type`] method.
@@ -15,6 +17,9 @@ pub type Permissions = fops::FilePerm;
 #[derive(Clone)]
 pub struct File {
     inner: fops::File,
+    /// Shared by the clones, reports the close event when the last one is
+    /// dropped.
+    events: Arc<OpenFile>,
 }
 
 /// Metadata information about a file.
@@ -68,11 +73,17 @@ impl OpenOptions {
 
     /// Opens a file at `path` with the options specified by `self`.
     pub fn open(&self, path: &str) -> Result<File> {
-        fops::File::open(path, &self.0).map(|inner| File { inner })
+        let inner = fops::File::open(path, &self.0)?;
+        let events = Arc::new(OpenFile::new(path, inner.writable()));
+        Ok(File { inner, events })
     }
 }
 
 impl Metadata {
+    pub(crate) const fn new(attr: fops::FileAttr) -> Self {
+        Self(attr)
+    }
+
     /// Returns the file type for this metadata.
     pub const fn file_type(&self) -> FileType {
         self.0.file_type()
@@ -162,7 +173,9 @@ impl File {
     /// Truncates or extends the underlying file, updating the size of
     /// this file to become `size`.
     pub fn set_len(&self, size: u64) -> Result<()> {
-        self.inner.truncate(size)
+        self.inner.truncate(size)?;
+        self.events.notify(WatchMask::MODIFY);
+        Ok(())
     }
 
     /// Queries metadata about the underlying file.
@@ -180,21 +193,34 @@ impl File {
         self.inner.get_attr()
     }
 
+    /// The key of the file to lock, which is opened at `path`.
+    pub fn lock_key(&self, path: &str) -> Result<crate::lock::FileKey> {
+        self.inner.lock_key(path)
+    }
+
     /// To truncate the file to a specified length.
     pub fn truncate(&mut self, len: usize) -> Result<()> {
-        self.inner.truncate(len as u64)
+        self.set_len(len as u64)
     }
 }
 
 impl Read for File {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.inner.read(buf)
+        let len = self.inner.read(buf)?;
+        if len > 0 {
+            self.events.notify(WatchMask::ACCESS);
+        }
+        Ok(len)
     }
 }
 
 impl Write for File {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.inner.write(buf)
+        let len = self.inner.write(buf)?;
+        if len > 0 {
+            self.events.notify(WatchMask::MODIFY);
+        }
+        Ok(len)
     }
 
     fn flush(&mut self) -> Result<()> {
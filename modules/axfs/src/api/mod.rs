@@ -9,12 +9,14 @@ pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
 use axerrno::AxResult;
 use axfs_vfs::VfsNodeRef;
+pub use axfs_vfs::{FileSystemInfo, XattrFlags};
 pub use axio::{Read, Seek, SeekFrom, Write};
 pub use port::*;
 
 use alloc::{string::String, vec::Vec};
 #[allow(unused_imports)]
 use axio::{self as io, prelude::*};
+use core::time::Duration;
 
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
@@ -63,7 +65,9 @@ pub fn write<C: AsRef<[u8]>>(path: &str, contents: C) -> io::Result<()> {
 /// Given a path, query the file system to get information about a file,
 /// directory, etc.
 pub fn metadata(path: &str) -> io::Result<Metadata> {
-    File::open(path)?.metadata()
+    // not opening the file, which would be reported to its watches
+    let attr = crate::root::lookup(None, path)?.get_attr()?;
+    Ok(Metadata::new(attr))
 }
 
 /// Creates a new, empty directory at the provided path.
@@ -95,6 +99,49 @@ pub fn rename(old: &str, new: &str) -> io::Result<()> {
     crate::root::rename(old, new)
 }
 
+/// Creates a new hard link `new` to the file at `old`. Both must be in the
+/// same mounted filesystem, which must support hard links.
+pub fn hard_link(old: &str, new: &str) -> io::Result<()> {
+    crate::root::link(old, new)
+}
+
+/// Changes the access and modification time of a file or directory, `None`
+/// leaves the time unchanged.
+pub fn set_times(path: &str, atime: Option<Duration>, mtime: Option<Duration>) -> io::Result<()> {
+    crate::root::set_times(path, atime, mtime)
+}
+
+/// Returns the value of the extended attribute `name` of a file or
+/// directory.
+pub fn get_xattr(path: &str, name: &str) -> io::Result<Vec<u8>> {
+    crate::xattr::get_xattr(&lookup(path)?, name)
+}
+
+/// Sets the extended attribute `name` of a file or directory, see
+/// [`crate::xattr::set_xattr`].
+pub fn set_xattr(path: &str, name: &str, value: &[u8], flags: XattrFlags) -> io::Result<()> {
+    crate::xattr::set_xattr(&lookup(path)?, name, value, flags)?;
+    crate::notify::notify(path, crate::notify::WatchMask::ATTRIB);
+    Ok(())
+}
+
+/// Returns the names of the extended attributes of a file or directory.
+pub fn list_xattr(path: &str) -> io::Result<Vec<String>> {
+    crate::xattr::list_xattr(&lookup(path)?)
+}
+
+/// Removes the extended attribute `name` of a file or directory.
+pub fn remove_xattr(path: &str, name: &str) -> io::Result<()> {
+    crate::xattr::remove_xattr(&lookup(path)?, name)?;
+    crate::notify::notify(path, crate::notify::WatchMask::ATTRIB);
+    Ok(())
+}
+
+/// Returns the attributes of the filesystem that `path` is in.
+pub fn statfs(path: &str) -> io::Result<FileSystemInfo> {
+    crate::root::statfs(path)
+}
+
 /// Check if a path exists.
 pub fn path_exists(path: &str) -> bool {
     crate::root::lookup(None, path).is_ok()
@@ -104,3 +151,39 @@ pub fn path_exists(path: &str) -> bool {
 pub fn lookup(path: &str) -> AxResult<VfsNodeRef> {
     crate::root::lookup(None, path)
 }
+
+/// Mounts a filesystem of type `fstype` on the directory `target`, creates
+/// the directory if it does not exist.
+///
+/// For disk filesystems, `source` is the block device in `/dev`, and the
+/// type is probed if `fstype` is empty or `"auto"`. It's ignored for others,
+/// which take the filesystem specific `options` instead, e.g. `size=64m` for
+/// `tmpfs`.
+pub fn mount(source: &str, target: &str, fstype: &str, options: &str) -> io::Result<()> {
+    crate::root::mount(source, target, fstype, options)
+}
+
+/// Changes the filesystem specific options of the filesystem mounted on
+/// `target`, e.g. the `size=` of a `tmpfs`.
+pub fn remount(target: &str, options: &str) -> io::Result<()> {
+    crate::root::remount(target, options)
+}
+
+/// Unmounts the filesystem mounted on `target`.
+///
+/// `MNT_EXPIRE` is not supported.
+pub fn umount(target: &str, flags: UmountFlags) -> io::Result<()> {
+    if flags.contains(UmountFlags::MNT_EXPIRE) {
+        return Err(axerrno::AxError::Unsupported);
+    }
+    crate::root::umount(
+        target,
+        flags.contains(UmountFlags::MNT_FORCE),
+        flags.contains(UmountFlags::MNT_DETACH),
+    )
+}
+
+/// Writes the data cached for all block devices to them, like `sync(2)`.
+pub fn sync() -> io::Result<()> {
+    crate::dev::BlockDevice::sync_all().map_err(|_| axerrno::AxError::Io)
+}
@@ -287,6 +287,13 @@ pub trait FileIO: AsAny + Send + Sync {
         debug!("Function get_path not implemented");
         String::from("Function get_path not implemented")
     }
+    /// 加文件锁时用来标识文件，硬链接与重命名后仍是同一个文件
+    ///
+    /// 只有普通文件和目录可以加锁，其余返回 None
+    fn lock_key(&self) -> Option<crate::lock::FileKey> {
+        None
+    }
+
     /// 获取文件信息
     fn get_stat(&self) -> AxResult<Kstat> {
         Err(AxError::Unsupported) // 如果没有实现get_stat, 则返回Unsupported
@@ -396,6 +403,21 @@ impl From<usize> for AccessMode {
     }
 }
 
+bitflags! {
+    /// umount2 的参数
+    #[derive(Clone, Copy, Default, Debug)]
+    pub struct UmountFlags: u32 {
+        /// 即使文件系统卸载出错也强制卸载
+        const MNT_FORCE = 1 << 0;
+        /// 立即从挂载树中摘除，连同挂载在其下的文件系统
+        const MNT_DETACH = 1 << 1;
+        /// 将挂载点标记为过期
+        const MNT_EXPIRE = 1 << 2;
+        /// 不跟随挂载点路径中的符号链接
+        const UMOUNT_NOFOLLOW = 1 << 3;
+    }
+}
+
 /// IOCTL系统调用支持
 #[allow(missing_docs)]
 pub const TCGETS: usize = 0x5401;
@@ -0,0 +1,304 @@
+//! The buffer cache of a block device.
+//!
+//! It's shared by all the users of the device, i.e. the filesystem on it and
+//! its node in `/dev`, and caches the blocks of the device's logical block
+//! size. Missing blocks are read with some following ones ahead. Written
+//! blocks are kept dirty, and written back together when there are too many
+//! of them, or when the device is synced. The least recently used clean
+//! blocks are evicted if the cache is full.
+
+use alloc::{boxed::Box, collections::BTreeMap, vec, vec::Vec};
+use core::ops::Range;
+
+use axdriver::prelude::*;
+use axsync::Mutex;
+
+use super::queue::RequestQueue;
+
+/// The size of the blocks cached for each device.
+const CACHE_SIZE: usize = 2 * 1024 * 1024;
+/// The most data read ahead on a miss.
+const READAHEAD_SIZE: usize = 32 * 1024;
+
+struct Buffer {
+    data: Box<[u8]>,
+    dirty: bool,
+    /// Whether the data is written by users, other than read from the device.
+    modified: bool,
+    /// The writebacks of it in flight, until which it's not evicted.
+    writebacks: usize,
+    /// When it's used last time.
+    stamp: u64,
+}
+
+struct CacheInner {
+    buffers: BTreeMap<u64, Buffer>,
+    /// The cached blocks by the time they are used.
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+    num_dirty: usize,
+    /// Counts the evicted blocks that have been modified, the data read
+    /// from the device before the eviction may be stale.
+    evictions: u64,
+}
+
+impl CacheInner {
+    fn get(&mut self, block: u64) -> Option<&mut Buffer> {
+        let buffer = self.buffers.get_mut(&block)?;
+        self.lru.remove(&buffer.stamp);
+        self.clock += 1;
+        buffer.stamp = self.clock;
+        self.lru.insert(self.clock, block);
+        Some(buffer)
+    }
+
+    fn insert(&mut self, block: u64, data: Box<[u8]>, dirty: bool) {
+        self.clock += 1;
+        self.lru.insert(self.clock, block);
+        self.num_dirty += dirty as usize;
+        self.buffers.insert(
+            block,
+            Buffer {
+                data,
+                dirty,
+                modified: dirty,
+                writebacks: 0,
+                stamp: self.clock,
+            },
+        );
+    }
+
+    fn mark_dirty(&mut self, block: u64) {
+        let buffer = self.buffers.get_mut(&block).unwrap();
+        buffer.modified = true;
+        if !buffer.dirty {
+            buffer.dirty = true;
+            self.num_dirty += 1;
+        }
+    }
+
+    /// Evicts the least recently used clean blocks, until at most `capacity`
+    /// blocks are cached.
+    fn evict(&mut self, capacity: usize) {
+        while self.buffers.len() > capacity {
+            let victim = self.lru.iter().find(|(_, block)| {
+                let buffer = &self.buffers[block];
+                !buffer.dirty && buffer.writebacks == 0
+            });
+            let Some((&stamp, &block)) = victim else {
+                break;
+            };
+            self.lru.remove(&stamp);
+            if self.buffers.remove(&block).unwrap().modified {
+                self.evictions += 1;
+            }
+        }
+    }
+}
+
+/// The buffer cache of a block device.
+pub(crate) struct BufferCache {
+    queue: RequestQueue,
+    block_size: usize,
+    num_blocks: u64,
+    /// The most blocks cached.
+    capacity: usize,
+    inner: Mutex<CacheInner>,
+}
+
+impl BufferCache {
+    pub fn new(dev: AxBlockDevice) -> Self {
+        let block_size = dev.block_size();
+        Self {
+            block_size,
+            num_blocks: dev.num_blocks(),
+            capacity: CACHE_SIZE / block_size,
+            queue: RequestQueue::new(dev),
+            inner: Mutex::new(CacheInner {
+                buffers: BTreeMap::new(),
+                lru: BTreeMap::new(),
+                clock: 0,
+                num_dirty: 0,
+                evictions: 0,
+            }),
+        }
+    }
+
+    /// The IRQ number of the device, if it's used to wait for requests.
+    pub fn irq(&self) -> Option<usize> {
+        self.queue.irq()
+    }
+
+    /// The range of the block in `offset..offset + len`, and where it is in
+    /// the block.
+    fn span(&self, block: u64, offset: u64, len: usize) -> (Range<usize>, Range<usize>) {
+        let block_start = block * self.block_size as u64;
+        let start = offset.max(block_start);
+        let end = (offset + len as u64).min(block_start + self.block_size as u64);
+        let in_buf = (start - offset) as usize..(end - offset) as usize;
+        let in_block = (start - block_start) as usize..(end - block_start) as usize;
+        (in_buf, in_block)
+    }
+
+    /// The blocks in `offset..offset + len`, which must not be empty.
+    fn blocks(&self, offset: u64, len: usize) -> Range<u64> {
+        let block_size = self.block_size as u64;
+        offset / block_size..(offset + len as u64).div_ceil(block_size)
+    }
+
+    /// Reads the data at `offset`, which must be on the device.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> DevResult {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let blocks = self.blocks(offset, buf.len());
+        // copy the cached blocks, and find the runs of the missing ones
+        let mut runs: Vec<Range<u64>> = Vec::new();
+        let evictions = {
+            let mut inner = self.inner.lock();
+            for block in blocks.clone() {
+                if let Some(buffer) = inner.get(block) {
+                    let (in_buf, in_block) = self.span(block, offset, buf.len());
+                    buf[in_buf].copy_from_slice(&buffer.data[in_block]);
+                } else if let Some(run) = runs.last_mut().filter(|run| run.end == block) {
+                    run.end += 1;
+                } else {
+                    runs.push(block..block + 1);
+                }
+            }
+            if let Some(run) = runs.last_mut().filter(|run| run.end == blocks.end) {
+                let limit = self
+                    .num_blocks
+                    .min(blocks.end + (READAHEAD_SIZE / self.block_size) as u64);
+                while run.end < limit && !inner.buffers.contains_key(&run.end) {
+                    run.end += 1;
+                }
+            }
+            inner.evictions
+        };
+        if runs.is_empty() {
+            return Ok(());
+        }
+
+        let tickets: Vec<_> = runs
+            .iter()
+            .map(|run| {
+                let buf = vec![0; (run.end - run.start) as usize * self.block_size];
+                self.queue.submit(run.start, false, buf)
+            })
+            .collect();
+        let results: Vec<_> = tickets.into_iter().map(|t| self.queue.wait(t)).collect();
+
+        let mut inner = self.inner.lock();
+        // blocks in the cache are newer, and the ones read may be stale if
+        // modified blocks are evicted meanwhile
+        let stale = inner.evictions != evictions;
+        let len = buf.len();
+        let mut copy = |block: u64, data: &[u8]| {
+            if block < blocks.end {
+                let (in_buf, in_block) = self.span(block, offset, len);
+                buf[in_buf].copy_from_slice(&data[in_block]);
+            }
+        };
+        for (run, data) in runs.into_iter().zip(&results) {
+            let Ok(data) = data else {
+                continue;
+            };
+            for (block, data) in run.zip(data.chunks_exact(self.block_size)) {
+                if let Some(buffer) = inner.get(block) {
+                    copy(block, &buffer.data);
+                } else {
+                    copy(block, data);
+                    if !stale {
+                        inner.insert(block, data.into(), false);
+                    }
+                }
+            }
+        }
+        inner.evict(self.capacity);
+        results
+            .into_iter()
+            .find_map(Result::err)
+            .map_or(Ok(()), Err)
+    }
+
+    /// Writes the data at `offset`, which must be on the device.
+    ///
+    /// The data is written to the device later, when the dirty blocks are
+    /// written back.
+    pub fn write_at(&self, offset: u64, buf: &[u8]) -> DevResult {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let blocks = self.blocks(offset, buf.len());
+        let too_dirty = loop {
+            let mut inner = self.inner.lock();
+            // the blocks partially written must be read first
+            let missing = [blocks.start, blocks.end - 1].into_iter().find(|&block| {
+                let (in_buf, _) = self.span(block, offset, buf.len());
+                in_buf.len() < self.block_size && !inner.buffers.contains_key(&block)
+            });
+            if let Some(block) = missing {
+                drop(inner);
+                let mut data = vec![0; self.block_size];
+                self.read_at(block * self.block_size as u64, &mut data)?;
+                continue;
+            }
+            for block in blocks.clone() {
+                let (in_buf, in_block) = self.span(block, offset, buf.len());
+                if let Some(buffer) = inner.get(block) {
+                    buffer.data[in_block].copy_from_slice(&buf[in_buf]);
+                    inner.mark_dirty(block);
+                } else {
+                    inner.insert(block, buf[in_buf].into(), true);
+                }
+            }
+            inner.evict(self.capacity);
+            break inner.num_dirty > self.capacity / 2;
+        };
+        if too_dirty {
+            // the data written is in the cache, errors are reported on syncs
+            if let Err(e) = self.writeback() {
+                warn!("failed to write back dirty blocks: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes all the dirty blocks to the device.
+    pub fn writeback(&self) -> DevResult {
+        let mut blocks = Vec::new();
+        let mut tickets = Vec::new();
+        {
+            let mut inner = self.inner.lock();
+            for (&block, buffer) in inner.buffers.iter_mut().filter(|(_, b)| b.dirty) {
+                buffer.dirty = false;
+                buffer.writebacks += 1;
+                blocks.push(block);
+                tickets.push(self.queue.submit(block, true, buffer.data.to_vec()));
+            }
+            inner.num_dirty = 0;
+        }
+        let results: Vec<_> = tickets.into_iter().map(|t| self.queue.wait(t)).collect();
+
+        let mut inner = self.inner.lock();
+        for (block, res) in blocks.into_iter().zip(&results) {
+            inner.buffers.get_mut(&block).unwrap().writebacks -= 1;
+            if res.is_err() {
+                // keep the data to retry later
+                inner.mark_dirty(block);
+            }
+        }
+        inner.evict(self.capacity);
+        results
+            .into_iter()
+            .find_map(Result::err)
+            .map_or(Ok(()), Err)
+    }
+
+    /// Writes all the dirty blocks to the device, and flushes the device.
+    pub fn sync(&self) -> DevResult {
+        self.writeback()?;
+        self.queue.flush()
+    }
+}
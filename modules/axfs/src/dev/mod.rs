@@ -0,0 +1,325 @@
+//! Block devices, shared by the filesystems on them and their nodes in
+//! `/dev`.
+//!
+//! All I/O on a device goes through its buffer cache, which issues the
+//! requests to the request queue of the device.
+//!
+//! The partitions on a disk are block devices too, which share the buffer
+//! cache of the disk and translate the offsets on them.
+
+mod cache;
+mod partition;
+mod queue;
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axdriver::prelude::*;
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axsync::Mutex;
+
+use self::cache::BufferCache;
+
+/// The size of the blocks read and written by [`Disk::read_offset`] and
+/// [`Disk::write_offset`].
+const BLOCK_SIZE: usize = 512;
+
+/// All block devices, the disks named `vda`, `vdb`, ... in the probing
+/// order, and their partitions named `vda1`, `vda2`, ...
+static BLOCK_DEVICES: Mutex<Vec<Arc<BlockDevice>>> = Mutex::new(Vec::new());
+
+/// A block device, shared by the disks opened on it and its node in `/dev`.
+pub struct BlockDevice {
+    name: &'static str,
+    /// The cache of the disk, shared by its partitions.
+    cache: Arc<BufferCache>,
+    /// Where the device starts on the disk in bytes, non-zero for partitions.
+    start: u64,
+    size: u64,
+    /// The logical block size, e.g. 512 or 4096 bytes.
+    block_size: usize,
+    /// The PARTUUID of a partition, `None` for a whole disk.
+    part_uuid: Option<String>,
+    mounted: AtomicBool,
+}
+
+impl BlockDevice {
+    /// Registers a disk and returns it.
+    pub(crate) fn register(dev: AxBlockDevice) -> Arc<Self> {
+        let block_size = dev.block_size();
+        assert!(block_size.is_power_of_two() && block_size >= BLOCK_SIZE);
+        let mut devices = BLOCK_DEVICES.lock();
+        let num_disks = devices.iter().filter(|dev| !dev.is_partition()).count();
+        let name: String = format!("vd{}", (b'a' + num_disks as u8) as char);
+        let num_blocks = dev.num_blocks();
+        let device = Arc::new(Self {
+            name: name.leak(),
+            cache: Arc::new(BufferCache::new(dev)),
+            start: 0,
+            size: num_blocks * block_size as u64,
+            block_size,
+            part_uuid: None,
+            mounted: AtomicBool::new(false),
+        });
+        info!(
+            "  /dev/{}: {} blocks of {} bytes, irq: {:?}",
+            device.name,
+            num_blocks,
+            block_size,
+            device.cache.irq()
+        );
+        devices.push(device.clone());
+        device
+    }
+
+    /// Reads the partition table of a disk, and registers its partitions.
+    pub(crate) fn register_partitions(&self) {
+        let read = |offset, buf: &mut [u8]| self.cache.read_at(offset, buf);
+        let num_blocks = self.size / self.block_size as u64;
+        let partitions = match partition::read_partitions(read, self.block_size, num_blocks) {
+            Ok(partitions) => partitions,
+            Err(e) => {
+                warn!(
+                    "failed to read the partition table of {}: {:?}",
+                    self.name, e
+                );
+                return;
+            }
+        };
+        let mut devices = BLOCK_DEVICES.lock();
+        for part in partitions {
+            let name: String = format!("{}{}", self.name, part.number);
+            info!(
+                "  /dev/{}: {} bytes at {:#x}, PARTUUID={}",
+                name, part.size, part.start, part.uuid
+            );
+            devices.push(Arc::new(Self {
+                name: name.leak(),
+                cache: self.cache.clone(),
+                start: part.start,
+                size: part.size,
+                block_size: self.block_size,
+                part_uuid: Some(part.uuid),
+                mounted: AtomicBool::new(false),
+            }));
+        }
+    }
+
+    /// Returns all registered block devices.
+    pub(crate) fn all() -> Vec<Arc<Self>> {
+        BLOCK_DEVICES.lock().clone()
+    }
+
+    /// Returns the partitions on a disk.
+    pub(crate) fn partitions(&self) -> Vec<Arc<Self>> {
+        BLOCK_DEVICES
+            .lock()
+            .iter()
+            .filter(|dev| dev.is_partition() && Arc::ptr_eq(&dev.cache, &self.cache))
+            .cloned()
+            .collect()
+    }
+
+    /// Finds the block device with the given path, e.g. `/dev/vdb`, or the
+    /// partition with the given PARTUUID, e.g. `PARTUUID=1234abcd-02`.
+    pub(crate) fn find(source: &str) -> AxResult<Arc<Self>> {
+        let devices = BLOCK_DEVICES.lock();
+        let device = if let Some(uuid) = source.strip_prefix("PARTUUID=") {
+            devices.iter().find(|dev| {
+                dev.part_uuid
+                    .as_deref()
+                    .is_some_and(|part_uuid| part_uuid.eq_ignore_ascii_case(uuid))
+            })
+        } else {
+            let name = source.strip_prefix("/dev/").ok_or(AxError::NotFound)?;
+            devices.iter().find(|dev| dev.name == name)
+        };
+        device.cloned().ok_or(AxError::NotFound)
+    }
+
+    /// The name of the device in `/dev`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The size of the device in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the device is a partition on a disk.
+    pub fn is_partition(&self) -> bool {
+        self.part_uuid.is_some()
+    }
+
+    /// Whether the two devices share some blocks on the same disk.
+    fn overlaps(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cache, &other.cache)
+            && self.start < other.start + other.size
+            && other.start < self.start + self.size
+    }
+
+    /// Marks the device as mounted, fails if it or a device overlapping it,
+    /// like the disk of a partition, is already mounted.
+    pub(crate) fn claim(&self) -> AxResult {
+        let devices = BLOCK_DEVICES.lock();
+        let busy = self.mounted.load(Ordering::Acquire)
+            || devices
+                .iter()
+                .any(|dev| dev.mounted.load(Ordering::Acquire) && dev.overlaps(self));
+        if busy {
+            return ax_err!(ResourceBusy, "block device already mounted");
+        }
+        self.mounted.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Marks the device as unmounted.
+    pub(crate) fn release(&self) {
+        self.mounted.store(false, Ordering::Release);
+    }
+
+    /// Writes the dirty blocks in the cache to the disk, and flushes it.
+    pub fn sync(&self) -> DevResult {
+        self.cache.sync()
+    }
+
+    /// Syncs all disks, with their partitions.
+    pub(crate) fn sync_all() -> DevResult {
+        let mut res = Ok(());
+        for dev in Self::all().iter().filter(|dev| !dev.is_partition()) {
+            res = res.and(dev.sync());
+        }
+        res
+    }
+
+    /// The length of the data at `offset` on the device, at most `len`.
+    fn clamp(&self, offset: u64, len: usize) -> usize {
+        len.min(self.size().saturating_sub(offset) as usize)
+    }
+}
+
+impl VfsNodeOps for BlockDevice {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o660),
+            VfsNodeType::BlockDevice,
+            self.size(),
+            self.size() / 512, // in 512-byte units like `st_blocks`
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let len = self.clamp(offset, buf.len());
+        self.cache
+            .read_at(self.start + offset, &mut buf[..len])
+            .map_err(|_| AxError::Io)?;
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let len = self.clamp(offset, buf.len());
+        self.cache
+            .write_at(self.start + offset, &buf[..len])
+            .map_err(|_| AxError::Io)?;
+        Ok(len)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.sync().map_err(|_| AxError::Io)
+    }
+}
+
+/// A disk device with a cursor.
+pub struct Disk {
+    pos: u64,
+    dev: Arc<BlockDevice>,
+}
+
+impl Disk {
+    /// Create a new disk.
+    pub fn new(dev: Arc<BlockDevice>) -> Self {
+        Self { pos: 0, dev }
+    }
+
+    /// Get the size of the disk.
+    pub fn size(&self) -> u64 {
+        self.dev.size()
+    }
+
+    /// Get the position of the cursor.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Set the position of the cursor.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// The length to the end of the block at the cursor, at most `len`.
+    fn len_in_block(&self, len: usize) -> usize {
+        let block_size = self.dev.block_size;
+        len.min(block_size - self.pos as usize % block_size)
+    }
+
+    /// Read within one block, returns the number of bytes read.
+    pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
+        let len = self.len_in_block(buf.len());
+        self.read_at(self.pos, &mut buf[..len])?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    /// Write within one block, returns the number of bytes written.
+    pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
+        let len = self.len_in_block(buf.len());
+        self.write_at(self.pos, &buf[..len])?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    /// Reads the data at `pos` regardless of the cursor, which may span
+    /// multiple blocks.
+    pub fn read_at(&self, pos: u64, buf: &mut [u8]) -> DevResult {
+        if pos + buf.len() as u64 > self.size() {
+            return Err(DevError::InvalidParam);
+        }
+        self.dev.cache.read_at(self.dev.start + pos, buf)
+    }
+
+    /// Writes the data at `pos` regardless of the cursor, which may span
+    /// multiple blocks.
+    pub fn write_at(&self, pos: u64, buf: &[u8]) -> DevResult {
+        if pos + buf.len() as u64 > self.size() {
+            return Err(DevError::InvalidParam);
+        }
+        self.dev.cache.write_at(self.dev.start + pos, buf)
+    }
+
+    /// Waits until the data written is on the device.
+    pub fn flush(&self) -> DevResult {
+        self.dev.sync()
+    }
+
+    /// Read a single block starting from the specified offset.
+    pub fn read_offset(&mut self, offset: usize) -> [u8; BLOCK_SIZE] {
+        let mut block_data = [0u8; BLOCK_SIZE];
+        self.read_at(offset as u64, &mut block_data).unwrap();
+        block_data
+    }
+
+    /// Write single block starting from the specified offset.
+    pub fn write_offset(&mut self, offset: usize, buf: &[u8]) -> DevResult<usize> {
+        assert!(
+            buf.len() == BLOCK_SIZE,
+            "Buffer length must be equal to BLOCK_SIZE"
+        );
+        assert!(offset % BLOCK_SIZE == 0);
+        self.write_at(offset as u64, buf).unwrap();
+        Ok(buf.len())
+    }
+}
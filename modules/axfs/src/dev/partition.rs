@@ -0,0 +1,275 @@
+//! Partition tables, MBR and GPT.
+//!
+//! Like Linux, partitions are numbered from 1 by their slots in the table,
+//! and the logical partitions in an MBR extended partition from 5. Their
+//! PARTUUIDs are the unique GUIDs in GPT, or the disk signature and the
+//! partition number in MBR, like `1234abcd-02`.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use axdriver::prelude::*;
+
+/// The MBR partition type of the protective MBR of GPT.
+const GPT_PROTECTIVE: u8 = 0xee;
+/// The most logical partitions followed in an extended partition, in case
+/// the chain of EBRs loops.
+const MAX_LOGICAL: usize = 64;
+/// The largest partition entry array of GPT read.
+const MAX_GPT_ENTRIES_SIZE: usize = 1024 * 1024;
+
+/// A partition on a disk.
+pub(crate) struct Partition {
+    /// The partition number, from 1.
+    pub number: usize,
+    /// Where the partition starts on the disk in bytes.
+    pub start: u64,
+    /// The size of the partition in bytes.
+    pub size: u64,
+    /// The PARTUUID, in lower case.
+    pub uuid: String,
+}
+
+/// A partition entry in an MBR or EBR.
+#[derive(Clone, Copy)]
+struct MbrEntry {
+    ty: u8,
+    start: u64,
+    count: u64,
+}
+
+impl MbrEntry {
+    fn is_empty(&self) -> bool {
+        self.ty == 0 || self.count == 0
+    }
+
+    fn is_extended(&self) -> bool {
+        matches!(self.ty, 0x05 | 0x0f | 0x85)
+    }
+}
+
+fn le_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn le_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn le_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// The CRC32 of GPT headers and partition entries, the same as zlib's.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Formats a GUID stored in the mixed endian of GPT.
+fn guid_string(guid: &[u8]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        le_u32(guid, 0),
+        le_u16(guid, 4),
+        le_u16(guid, 6),
+        guid[8],
+        guid[9],
+        guid[10],
+        guid[11],
+        guid[12],
+        guid[13],
+        guid[14],
+        guid[15],
+    )
+}
+
+/// Parses the partition entries of an MBR or EBR, returns `None` if it's
+/// not one.
+///
+/// The boot sector of a FAT filesystem also ends with the signature, but
+/// its boot code rarely looks like the boot indicators of 4 entries.
+fn mbr_entries(sector: &[u8]) -> Option<[MbrEntry; 4]> {
+    if sector[510..512] != [0x55, 0xaa] {
+        return None;
+    }
+    let mut entries = [MbrEntry {
+        ty: 0,
+        start: 0,
+        count: 0,
+    }; 4];
+    for (i, entry) in entries.iter_mut().enumerate() {
+        let raw = &sector[446 + i * 16..446 + (i + 1) * 16];
+        if raw[0] != 0 && raw[0] != 0x80 {
+            return None;
+        }
+        *entry = MbrEntry {
+            ty: raw[4],
+            start: le_u32(raw, 8) as u64,
+            count: le_u32(raw, 12) as u64,
+        };
+    }
+    Some(entries)
+}
+
+/// Reads the partition table of a disk, returns no partitions if there is
+/// none.
+///
+/// `read` reads the data at an offset on the disk, which has `num_blocks`
+/// logical blocks of `block_size` bytes, the unit of the addresses in the
+/// tables.
+pub(crate) fn read_partitions(
+    read: impl Fn(u64, &mut [u8]) -> DevResult,
+    block_size: usize,
+    num_blocks: u64,
+) -> DevResult<Vec<Partition>> {
+    // too small for an MBR and a partition
+    if num_blocks < 2 {
+        return Ok(Vec::new());
+    }
+    let mut sector = [0; 512];
+    read(0, &mut sector)?;
+    let Some(entries) = mbr_entries(&sector) else {
+        return Ok(Vec::new());
+    };
+    let mut table = PartitionTable {
+        read,
+        block_size: block_size as u64,
+        num_blocks,
+        partitions: Vec::new(),
+    };
+    if entries.iter().any(|entry| entry.ty == GPT_PROTECTIVE) {
+        table.read_gpt()?;
+    } else {
+        table.read_mbr(le_u32(&sector, 440), &entries)?;
+    }
+    Ok(table.partitions)
+}
+
+struct PartitionTable<F> {
+    read: F,
+    block_size: u64,
+    num_blocks: u64,
+    partitions: Vec<Partition>,
+}
+
+impl<F: Fn(u64, &mut [u8]) -> DevResult> PartitionTable<F> {
+    /// Adds a partition of the blocks `start..start + count`, unless it's
+    /// not on the disk.
+    fn add(&mut self, number: usize, start: u64, count: u64, uuid: String) {
+        if count == 0 || start.saturating_add(count) > self.num_blocks {
+            warn!("partition {} is beyond the end of the disk", number);
+            return;
+        }
+        self.partitions.push(Partition {
+            number,
+            start: start * self.block_size,
+            size: count * self.block_size,
+            uuid,
+        });
+    }
+
+    fn read_mbr(&mut self, signature: u32, entries: &[MbrEntry; 4]) -> DevResult {
+        let uuid = |number: usize| format!("{:08x}-{:02x}", signature, number);
+        let mut logical = 5;
+        for (i, entry) in entries.iter().enumerate() {
+            if entry.is_empty() {
+                continue;
+            }
+            if !entry.is_extended() {
+                self.add(i + 1, entry.start, entry.count, uuid(i + 1));
+                continue;
+            }
+            // the logical partitions are in a chain of EBRs, the first entry
+            // of each is the partition relative to the EBR, and the second
+            // one is the next EBR relative to the extended partition
+            let mut ebr = entry.start;
+            for _ in 0..MAX_LOGICAL {
+                if ebr >= self.num_blocks {
+                    break;
+                }
+                let mut sector = [0; 512];
+                (self.read)(ebr * self.block_size, &mut sector)?;
+                let Some([part, next, ..]) = mbr_entries(&sector) else {
+                    break;
+                };
+                if !part.is_empty() {
+                    self.add(logical, ebr + part.start, part.count, uuid(logical));
+                    logical += 1;
+                }
+                if next.is_empty() || !next.is_extended() {
+                    break;
+                }
+                ebr = entry.start + next.start;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_gpt(&mut self) -> DevResult {
+        if self.read_gpt_at(1)? {
+            return Ok(());
+        }
+        if self.read_gpt_at(self.num_blocks - 1)? {
+            warn!("the primary GPT is corrupted, using the backup one");
+        } else {
+            warn!("no valid GPT on the disk with a protective MBR");
+        }
+        Ok(())
+    }
+
+    /// Reads the partitions in the GPT with the header at `lba`, returns
+    /// whether the header and the entries are valid.
+    fn read_gpt_at(&mut self, lba: u64) -> DevResult<bool> {
+        if lba >= self.num_blocks {
+            return Ok(false);
+        }
+        let block_size = self.block_size as usize;
+        let mut header = vec![0; block_size];
+        (self.read)(lba * self.block_size, &mut header)?;
+        let header_size = le_u32(&header, 12) as usize;
+        if &header[0..8] != b"EFI PART"
+            || !(92..=block_size).contains(&header_size)
+            || le_u64(&header, 24) != lba
+        {
+            return Ok(false);
+        }
+        let header_crc = le_u32(&header, 16);
+        header[16..20].fill(0);
+        if crc32(&header[..header_size]) != header_crc {
+            return Ok(false);
+        }
+
+        let entries_lba = le_u64(&header, 72);
+        let num_entries = le_u32(&header, 80) as usize;
+        let entry_size = le_u32(&header, 84) as usize;
+        let entries_size = num_entries.saturating_mul(entry_size);
+        if entry_size < 128
+            || entries_size > MAX_GPT_ENTRIES_SIZE
+            || entries_lba.saturating_add(entries_size.div_ceil(block_size) as u64)
+                > self.num_blocks
+        {
+            return Ok(false);
+        }
+        let mut entries = vec![0; entries_size];
+        (self.read)(entries_lba * self.block_size, &mut entries)?;
+        if crc32(&entries) != le_u32(&header, 88) {
+            return Ok(false);
+        }
+
+        for (i, entry) in entries.chunks_exact(entry_size).enumerate() {
+            if entry[0..16].iter().all(|&b| b == 0) {
+                continue;
+            }
+            let (first, last) = (le_u64(entry, 32), le_u64(entry, 40));
+            let count = last.saturating_add(1).saturating_sub(first);
+            self.add(i + 1, first, count, guid_string(&entry[16..32]));
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,328 @@
+//! The request queue of a block device.
+//!
+//! Requests are kept in the order of their blocks, and dispatched like an
+//! elevator: it sweeps upwards from the last dispatched block, and wraps to
+//! the lowest one at the end (C-LOOK). Adjacent requests in the same direction
+//! are merged into one transfer on the device.
+//!
+//! Devices that support non-blocking requests have several transfers in
+//! flight. The tasks waiting for their requests poll the device for the
+//! finished transfers, and dispatch the queued requests. With the `irq`
+//! feature, they sleep until the device raises an interrupt, which is handled
+//! in two halves like the NIC interrupts of `axnet`: the handler only masks
+//! the IRQs and wakes the tasks, and the tasks acknowledge the devices and
+//! unmask the IRQs.
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+use axdriver::prelude::*;
+use axsync::Mutex;
+use driver_block::BlockRequestId;
+
+/// The most transfers in flight on a device.
+///
+/// Each transfer takes 3 descriptors in a virtqueue, which has 16 of them.
+const MAX_IN_FLIGHT: usize = 4;
+/// The largest transfer merged from requests.
+const MAX_TRANSFER_SIZE: usize = 128 * 1024;
+
+/// The id of a request in a [`RequestQueue`].
+pub(crate) type Ticket = u64;
+
+struct Request {
+    write: bool,
+    buf: Vec<u8>,
+}
+
+/// A transfer on the device, merged from one or more requests.
+struct Transfer {
+    start: u64,
+    write: bool,
+    buf: Vec<u8>,
+    /// The merged requests and their sizes.
+    parts: Vec<(Ticket, usize)>,
+}
+
+struct QueueInner {
+    dev: AxBlockDevice,
+    block_size: usize,
+    /// Whether the device supports non-blocking requests, until it fails
+    /// with [`DevError::Unsupported`].
+    nonblocking: bool,
+    next_ticket: Ticket,
+    /// The queued requests, keyed by their first blocks and tickets.
+    queued: BTreeMap<(u64, Ticket), Request>,
+    /// The most blocks of a queued request, to find the overlapping ones.
+    max_queued_blocks: u64,
+    in_flight: BTreeMap<BlockRequestId, Transfer>,
+    /// The finished requests, with their buffers given back.
+    finished: BTreeMap<Ticket, (DevResult, Vec<u8>)>,
+    /// Where the elevator is, the block after the last dispatched transfer.
+    head: u64,
+}
+
+impl QueueInner {
+    fn blocks(&self, len: usize) -> u64 {
+        (len / self.block_size) as u64
+    }
+
+    /// Whether the request overlaps a transfer in flight or an earlier
+    /// queued request, one of which writes, so it must wait for them.
+    fn conflicts(&self, (start, ticket): (u64, Ticket), req: &Request) -> bool {
+        let end = start + self.blocks(req.buf.len());
+        let overlaps = |other_start: u64, len: usize, write: bool| {
+            (req.write || write) && other_start < end && start < other_start + self.blocks(len)
+        };
+        let from = (start.saturating_sub(self.max_queued_blocks), 0);
+        self.in_flight
+            .values()
+            .any(|t| overlaps(t.start, t.buf.len(), t.write))
+            || self
+                .queued
+                .range(from..(end, 0))
+                .any(|(&(s, t), r)| t < ticket && overlaps(s, r.buf.len(), r.write))
+    }
+
+    /// The next request to dispatch in the elevator order.
+    fn next_request(&self) -> Option<(u64, Ticket)> {
+        let upper = self.queued.range((self.head, 0)..);
+        let lower = self.queued.range(..(self.head, 0));
+        upper
+            .chain(lower)
+            .find(|&(&key, req)| !self.conflicts(key, req))
+            .map(|(&key, _)| key)
+    }
+
+    /// Dispatches the queued requests until the device is busy.
+    fn dispatch(&mut self) {
+        while !(self.nonblocking && self.in_flight.len() >= MAX_IN_FLIGHT) {
+            let Some(key) = self.next_request() else {
+                break;
+            };
+            let req = self.queued.remove(&key).unwrap();
+            let mut transfer = Transfer {
+                start: key.0,
+                write: req.write,
+                parts: vec![(key.1, req.buf.len())],
+                buf: req.buf,
+            };
+            loop {
+                let end = transfer.start + self.blocks(transfer.buf.len());
+                let Some((&next, req)) = self.queued.range((end, 0)..).next() else {
+                    break;
+                };
+                if next.0 != end
+                    || req.write != transfer.write
+                    || transfer.buf.len() + req.buf.len() > MAX_TRANSFER_SIZE
+                    || self.conflicts(next, req)
+                {
+                    break;
+                }
+                let req = self.queued.remove(&next).unwrap();
+                transfer.buf.extend_from_slice(&req.buf);
+                transfer.parts.push((next.1, req.buf.len()));
+            }
+            if self.queued.is_empty() {
+                self.max_queued_blocks = 0;
+            }
+            self.head = transfer.start + self.blocks(transfer.buf.len());
+            self.start(transfer);
+        }
+    }
+
+    fn start(&mut self, mut transfer: Transfer) {
+        if self.nonblocking {
+            // SAFETY: the buffer is kept in `in_flight` until the transfer is
+            // finished, and moving the vector does not move its data.
+            let res = unsafe {
+                if transfer.write {
+                    self.dev
+                        .submit_write_block(transfer.start, transfer.buf.as_slice())
+                } else {
+                    self.dev
+                        .submit_read_block(transfer.start, transfer.buf.as_mut_slice())
+                }
+            };
+            match res {
+                Ok(id) => {
+                    self.in_flight.insert(id, transfer);
+                    return;
+                }
+                Err(DevError::Unsupported) => self.nonblocking = false,
+                Err(e) => return self.finish(transfer, Err(e)),
+            }
+        }
+        let res = if transfer.write {
+            self.dev.write_block(transfer.start, &transfer.buf)
+        } else {
+            self.dev.read_block(transfer.start, &mut transfer.buf)
+        };
+        self.finish(transfer, res);
+    }
+
+    /// Splits a finished transfer into its requests.
+    fn finish(&mut self, transfer: Transfer, res: DevResult) {
+        if let [(ticket, _)] = transfer.parts[..] {
+            self.finished.insert(ticket, (res, transfer.buf));
+            return;
+        }
+        let mut offset = 0;
+        for (ticket, len) in transfer.parts {
+            let buf = transfer.buf[offset..offset + len].to_vec();
+            self.finished.insert(ticket, (res, buf));
+            offset += len;
+        }
+    }
+
+    /// Takes the finished transfers from the device.
+    fn poll(&mut self) {
+        while let Some((id, res)) = self.dev.poll_completed() {
+            if let Some(transfer) = self.in_flight.remove(&id) {
+                self.finish(transfer, res);
+            }
+        }
+    }
+}
+
+/// The request queue of a block device.
+pub(crate) struct RequestQueue {
+    irq: Option<usize>,
+    inner: Mutex<QueueInner>,
+}
+
+impl RequestQueue {
+    pub fn new(dev: AxBlockDevice) -> Self {
+        #[cfg(feature = "irq")]
+        let irq = dev.irq_num().filter(|&irq| irq::register(irq));
+        #[cfg(not(feature = "irq"))]
+        let irq = None;
+        Self {
+            irq,
+            inner: Mutex::new(QueueInner {
+                block_size: dev.block_size(),
+                dev,
+                nonblocking: true,
+                next_ticket: 0,
+                queued: BTreeMap::new(),
+                max_queued_blocks: 0,
+                in_flight: BTreeMap::new(),
+                finished: BTreeMap::new(),
+                head: 0,
+            }),
+        }
+    }
+
+    /// The IRQ number of the device, if it's used to wait for requests.
+    pub fn irq(&self) -> Option<usize> {
+        self.irq
+    }
+
+    /// Queues a request to read or write the blocks from `start`, returns the
+    /// ticket to wait for it.
+    ///
+    /// The size of the buffer must be a multiple of the block size. The
+    /// request is dispatched when a task waits for a request on the device.
+    pub fn submit(&self, start: u64, write: bool, buf: Vec<u8>) -> Ticket {
+        let mut inner = self.inner.lock();
+        let ticket = inner.next_ticket;
+        inner.next_ticket += 1;
+        inner.max_queued_blocks = inner.max_queued_blocks.max(inner.blocks(buf.len()));
+        inner.queued.insert((start, ticket), Request { write, buf });
+        ticket
+    }
+
+    /// Waits for a request, returns its buffer with the data read.
+    pub fn wait(&self, ticket: Ticket) -> DevResult<Vec<u8>> {
+        loop {
+            #[cfg(feature = "irq")]
+            let events = irq::events();
+            {
+                let mut inner = self.inner.lock();
+                #[cfg(feature = "irq")]
+                if let Some(irq) = self.irq {
+                    inner.dev.ack_interrupt();
+                    axhal::irq::set_enable(irq, true);
+                }
+                inner.poll();
+                inner.dispatch();
+                let res = inner.finished.remove(&ticket);
+                #[cfg(feature = "irq")]
+                if !inner.finished.is_empty() {
+                    // the requests of other tasks are finished by us
+                    irq::wake();
+                }
+                if let Some((res, buf)) = res {
+                    return res.map(|_| buf);
+                }
+            }
+            #[cfg(feature = "irq")]
+            if self.irq.is_some() {
+                irq::wait(events);
+            } else {
+                axtask::yield_now();
+            }
+            #[cfg(not(feature = "irq"))]
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Flushes the device, after the writes waited for are finished.
+    pub fn flush(&self) -> DevResult {
+        self.inner.lock().dev.flush()
+    }
+}
+
+#[cfg(feature = "irq")]
+mod irq {
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::time::Duration;
+
+    use axtask::WaitQueue;
+    use spinlock::SpinNoIrq;
+
+    /// The longest time to sleep, in case an interrupt is missed.
+    const MAX_WAIT: Duration = Duration::from_millis(10);
+
+    /// The IRQs of all block devices, masked by the handler.
+    static IRQS: SpinNoIrq<Vec<usize>> = SpinNoIrq::new(Vec::new());
+    static WAIT_QUEUE: WaitQueue = WaitQueue::new();
+    /// Counts the interrupts, and the times a task finishes the requests of
+    /// others.
+    static EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Registers the IRQ handler of a block device, returns whether it
+    /// succeeds. Devices may share an IRQ.
+    pub fn register(irq: usize) -> bool {
+        let mut irqs = IRQS.lock();
+        if !irqs.contains(&irq) {
+            if !axhal::irq::register_handler(irq, blk_irq_handler) {
+                return false;
+            }
+            irqs.push(irq);
+        }
+        true
+    }
+
+    pub fn events() -> usize {
+        EVENTS.load(Ordering::Acquire)
+    }
+
+    pub fn wake() {
+        EVENTS.fetch_add(1, Ordering::AcqRel);
+        WAIT_QUEUE.notify_all(false);
+    }
+
+    /// Sleeps until something happens after `events` is read.
+    pub fn wait(events: usize) {
+        WAIT_QUEUE.wait_timeout_until(MAX_WAIT, || self::events() != events);
+    }
+
+    /// The IRQ handler of all the block devices.
+    fn blk_irq_handler() {
+        for &irq in IRQS.lock().iter() {
+            axhal::irq::set_enable(irq, false);
+        }
+        wake();
+    }
+}
@@ -1,12 +1,14 @@
 //! Low-level filesystem operations.
 
+use alloc::sync::Arc;
 use axerrno::{ax_err, ax_err_type, AxResult};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use capability::{Cap, WithCap};
 use core::fmt;
 
-#[cfg(feature = "myfs")]
+use crate::root::Mounted;
+
 pub use crate::dev::Disk;
 #[cfg(feature = "myfs")]
 pub use crate::fs::myfs::MyFileSystemIf;
@@ -24,6 +26,8 @@ pub type FilePerm = axfs_vfs::VfsNodePerm;
 #[derive(Clone)]
 pub struct File {
     node: WithCap<VfsNodeRef>,
+    /// Keeps the filesystem that the file is on busy.
+    _mount: Option<Arc<Mounted>>,
     is_append: bool,
     offset: u64,
 }
@@ -32,6 +36,8 @@ pub struct File {
 /// [`read_dir`](Directory::read_dir).
 pub struct Directory {
     node: WithCap<VfsNodeRef>,
+    /// The filesystem that the directory is on, kept busy.
+    mount: Option<Arc<Mounted>>,
     entry_idx: usize,
 }
 
@@ -113,7 +119,12 @@ impl OpenOptions {
 }
 
 impl File {
-    fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_at(
+        dir: Option<&VfsNodeRef>,
+        dir_mount: Option<&Arc<Mounted>>,
+        path: &str,
+        opts: &OpenOptions,
+    ) -> AxResult<Self> {
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
             return ax_err!(InvalidInput);
@@ -153,6 +164,7 @@ impl File {
         }
         Ok(Self {
             node: WithCap::new(node, access_cap),
+            _mount: crate::root::mount_of(dir, dir_mount, path),
             is_append: opts.append,
             offset: 0,
         })
@@ -161,7 +173,7 @@ impl File {
     /// Opens a file at the path relative to the current directory. Returns a
     /// [`File`] object.
     pub fn open(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_at(None, path, opts)
+        Self::_open_at(None, None, path, opts)
     }
 
     /// Truncates the file to the specified size.
@@ -240,6 +252,12 @@ impl File {
         self.node.access(Cap::empty())?.get_attr()
     }
 
+    /// The key of the file to lock, which is opened at `path`.
+    pub fn lock_key(&self, path: &str) -> AxResult<crate::lock::FileKey> {
+        let node = self.node.access(Cap::empty())?;
+        Ok(crate::lock::FileKey::of(node, path))
+    }
+
     #[allow(unused)]
     /// whether the file is readable.
     pub fn readable(&self) -> bool {
@@ -260,7 +278,12 @@ impl File {
 }
 
 impl Directory {
-    fn _open_dir_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_dir_at(
+        dir: Option<&VfsNodeRef>,
+        dir_mount: Option<&Arc<Mounted>>,
+        path: &str,
+        opts: &OpenOptions,
+    ) -> AxResult<Self> {
         debug!("open dir: {}", path);
         if !opts.read {
             return ax_err!(InvalidInput);
@@ -282,6 +305,7 @@ impl Directory {
         node.open()?;
         Ok(Self {
             node: WithCap::new(node, access_cap),
+            mount: crate::root::mount_of(dir, dir_mount, path),
             entry_idx: 0,
         })
     }
@@ -297,19 +321,19 @@ impl Directory {
     /// Opens a directory at the path relative to the current directory.
     /// Returns a [`Directory`] object.
     pub fn open_dir(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(None, path, opts)
+        Self::_open_dir_at(None, None, path, opts)
     }
 
     /// Opens a directory at the path relative to this directory. Returns a
     /// [`Directory`] object.
     pub fn open_dir_at(&self, path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(self.access_at(path)?, path, opts)
+        Self::_open_dir_at(self.access_at(path)?, self.mount.as_ref(), path, opts)
     }
 
     /// Opens a file at the path relative to this directory. Returns a [`File`]
     /// object.
     pub fn open_file_at(&self, path: &str, opts: &OpenOptions) -> AxResult<File> {
-        File::_open_at(self.access_at(path)?, path, opts)
+        File::_open_at(self.access_at(path)?, self.mount.as_ref(), path, opts)
     }
 
     /// Creates an empty file at the path relative to this directory.
@@ -6,9 +6,23 @@ use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
 use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
 
+use super::{FileSystemType, FsKind, BLOCK_SIZE};
 use crate::dev::Disk;
 
-pub const BLOCK_SIZE: usize = 512;
+/// The FAT filesystem type.
+pub const FS_TYPE: FileSystemType = FileSystemType {
+    name: "vfat",
+    kind: FsKind::Disk {
+        probe,
+        new: |disk| Ok(FatFileSystem::open(disk)?.into_vfs()),
+    },
+};
+
+/// The boot sector ends with 0x55 0xaa, and has the type at 0x36, or at 0x52
+/// for FAT32.
+fn probe(data: &[u8]) -> bool {
+    data[510..512] == [0x55, 0xaa] && (data[0x36..0x39] == *b"FAT" || data[0x52..0x57] == *b"FAT32")
+}
 
 pub struct FatFileSystem {
     inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
@@ -40,12 +54,16 @@ impl FatFileSystem {
 
     #[cfg(not(feature = "use-ramdisk"))]
     pub fn new(disk: Disk) -> Self {
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
+        Self::open(disk).expect("failed to initialize FAT filesystem")
+    }
+
+    /// Opens the FAT filesystem on `disk`, fails if it's not valid.
+    pub fn open(disk: Disk) -> VfsResult<Self> {
+        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new()).map_err(as_vfs_err)?;
+        Ok(Self {
             inner,
             root_dir: UnsafeCell::new(None),
-        }
+        })
     }
 
     pub fn init(&'static self) {
@@ -53,6 +71,17 @@ impl FatFileSystem {
         unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
     }
 
+    /// Initializes the filesystem to be mounted.
+    ///
+    /// The nodes of a FAT filesystem borrow it for `'static`, so it is never
+    /// freed, even after it's unmounted.
+    pub fn into_vfs(self) -> Arc<dyn VfsOps> {
+        let fs = Arc::new(self);
+        let fs_ref: &'static Self = unsafe { &*Arc::into_raw(fs.clone()) };
+        fs_ref.init();
+        fs
+    }
+
     fn new_file(file: File<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<FileWrapper> {
         Arc::new(FileWrapper(Mutex::new(file)))
     }
@@ -73,6 +102,20 @@ impl VfsNodeOps for FileWrapper<'static> {
         Ok(VfsNodeAttr::new(perm, VfsNodeType::File, size, blocks))
     }
 
+    fn set_mode(&self, _mode: VfsNodePerm) -> VfsResult {
+        // FAT has no owners or permission bits, accept and drop the change
+        // like Linux does with the `quiet` mount option
+        Ok(())
+    }
+
+    fn set_owner(&self, _uid: u32, _gid: u32) -> VfsResult {
+        Ok(())
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.0.lock().flush().map_err(as_vfs_err)
+    }
+
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         // let mut file = self.0.lock();
         // file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
@@ -146,6 +189,14 @@ impl VfsNodeOps for DirWrapper<'static> {
         ))
     }
 
+    fn set_mode(&self, _mode: VfsNodePerm) -> VfsResult {
+        Ok(())
+    }
+
+    fn set_owner(&self, _uid: u32, _gid: u32) -> VfsResult {
+        Ok(())
+    }
+
     fn parent(&self) -> Option<VfsNodeRef> {
         self.0
             .open_dir("..")
@@ -293,7 +344,7 @@ impl Write for Disk {
         Ok(write_len)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        Disk::flush(self).map_err(|_| ())
     }
 }
 
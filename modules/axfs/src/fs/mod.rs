@@ -1,18 +1,23 @@
-cfg_if::cfg_if! {
-    if #[cfg(feature = "myfs")] {
-        pub mod myfs;
-        /// The block size of the file system.
-        pub const BLOCK_SIZE: usize = 512;
-    } else if #[cfg(feature = "fatfs")] {
-        pub mod fatfs;
-        pub use fatfs::BLOCK_SIZE;
-    } else if #[cfg(feature = "ext4fs")] {
-        pub mod ext4fs;
-        pub use ext4fs::BLOCK_SIZE;
-    }
-}
+//! Filesystem drivers and the registry of filesystem types.
+//!
+//! Each filesystem type is registered by name with its constructor. Disk
+//! filesystems also provide a probe on the superblock, so the type of the
+//! filesystem on a block device can be found without being told.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{VfsOps, VfsResult};
+use axsync::Mutex;
+
+use crate::dev::Disk;
 
-#[cfg(feature = "ext4_rs")]
+#[cfg(feature = "myfs")]
+pub mod myfs;
+
+#[cfg(feature = "fatfs")]
+pub mod fatfs;
+
+#[cfg(feature = "ext4fs")]
 pub mod ext4;
 
 #[cfg(feature = "devfs")]
@@ -20,3 +25,107 @@ pub use axfs_devfs as devfs;
 
 #[cfg(feature = "ramfs")]
 pub use axfs_ramfs as ramfs;
+
+/// The block size of the file system.
+pub const BLOCK_SIZE: usize = 512;
+
+/// The number of bytes at the start of a block device given to the probes,
+/// enough to hold the superblocks of FAT and ext2/3/4.
+pub const PROBE_SIZE: usize = 2048;
+
+/// How to create a filesystem of some type.
+#[derive(Clone, Copy)]
+pub enum FsKind {
+    /// A filesystem on a block device.
+    Disk {
+        /// Checks the first [`PROBE_SIZE`] bytes of the device for the
+        /// superblock of the filesystem.
+        probe: fn(&[u8]) -> bool,
+        /// Opens the filesystem on the disk.
+        new: fn(Disk) -> VfsResult<Arc<dyn VfsOps>>,
+    },
+    /// A filesystem without a device, like `tmpfs`.
+    Nodev {
+        /// Creates a new instance of the filesystem with the filesystem
+        /// specific mount options, e.g. `size=64m` for `tmpfs`.
+        new: fn(&str) -> VfsResult<Arc<dyn VfsOps>>,
+    },
+}
+
+/// A type of filesystem that can be mounted.
+#[derive(Clone, Copy)]
+pub struct FileSystemType {
+    /// The name of the type, e.g. `vfat`.
+    pub name: &'static str,
+    /// How to create a filesystem of the type.
+    pub kind: FsKind,
+}
+
+/// The ext2/3/4 superblock is at offset 1024, with the magic 0xef53 at 0x38.
+#[cfg(feature = "ext4fs")]
+fn probe_ext4(data: &[u8]) -> bool {
+    data[1024 + 0x38..1024 + 0x3a] == [0x53, 0xef]
+}
+
+/// Registered filesystem types, in the order of probing.
+static FILESYSTEMS: Mutex<Vec<FileSystemType>> = Mutex::new(Vec::new());
+
+/// Registers a filesystem type, fails if the name is already taken.
+pub fn register_filesystem(fstype: FileSystemType) -> AxResult {
+    let mut filesystems = FILESYSTEMS.lock();
+    if filesystems.iter().any(|fs| fs.name == fstype.name) {
+        return ax_err!(AlreadyExists, "filesystem type already registered");
+    }
+    filesystems.push(fstype);
+    Ok(())
+}
+
+/// Finds the registered filesystem type with the given name.
+pub(crate) fn find_filesystem(name: &str) -> Option<FileSystemType> {
+    FILESYSTEMS
+        .lock()
+        .iter()
+        .find(|fs| fs.name == name)
+        .copied()
+}
+
+/// Finds the first registered disk filesystem type that recognizes the
+/// superblock in `data`.
+pub(crate) fn probe_filesystem(data: &[u8]) -> Option<FileSystemType> {
+    FILESYSTEMS
+        .lock()
+        .iter()
+        .find(|fs| matches!(fs.kind, FsKind::Disk { probe, .. } if probe(data)))
+        .copied()
+}
+
+/// Lists the registered filesystem types in the format of
+/// `/proc/filesystems`.
+pub(crate) fn filesystems() -> String {
+    let mut buf = String::new();
+    for fs in FILESYSTEMS.lock().iter() {
+        let nodev = match fs.kind {
+            FsKind::Disk { .. } => "",
+            FsKind::Nodev { .. } => "nodev",
+        };
+        buf += &alloc::format!("{}\t{}\n", nodev, fs.name);
+    }
+    buf
+}
+
+/// Registers the filesystem types enabled by cargo features.
+pub(crate) fn register_builtin() {
+    let builtin: &[FileSystemType] = &[
+        #[cfg(feature = "myfs")]
+        myfs::FS_TYPE,
+        #[cfg(feature = "fatfs")]
+        fatfs::FS_TYPE,
+        #[cfg(feature = "ext4fs")]
+        ext4::FS_TYPE,
+    ];
+    for &fstype in builtin.iter().chain(crate::mounts::NODEV_FS_TYPES) {
+        if let Err(e) = register_filesystem(fstype) {
+            warn!("failed to register filesystem {}: {:?}", fstype.name, e);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::{FileSystemType, FsKind};
 use crate::dev::Disk;
 use alloc::sync::Arc;
 use axfs_vfs::VfsOps;
@@ -11,6 +12,16 @@ pub trait MyFileSystemIf {
     fn new_myfs(disk: Disk) -> Arc<dyn VfsOps>;
 }
 
+/// The custom filesystem type, which is never probed but always used as the
+/// root filesystem.
+pub const FS_TYPE: FileSystemType = FileSystemType {
+    name: "myfs",
+    kind: FsKind::Disk {
+        probe: |_| false,
+        new: |disk| Ok(new_myfs(disk)),
+    },
+};
+
 pub(crate) fn new_myfs(disk: Disk) -> Arc<dyn VfsOps> {
     crate_interface::call_interface!(MyFileSystemIf::new_myfs(disk))
 }
@@ -2,20 +2,46 @@
 //!
 //! It provides unified filesystem operations for various filesystems.
 //!
+//! All block devices are in `/dev` as `vda`, `vdb`, ..., with the partitions
+//! in their MBR or GPT partition tables as `vda1`, `vda2`, .... The root
+//! filesystem is on the device given by `AX_ROOT` at build time, by its path
+//! or `PARTUUID=...`, or by default on the first disk, or its first partition
+//! with a known filesystem. Other filesystems can be mounted and unmounted at
+//! runtime by [`api::mount`] and [`api::umount`], on any directory including
+//! those of other mounted filesystems.
+//!
+//! I/O on a block device goes through its buffer cache, and the requests to
+//! the device are merged and sorted in its request queue. The dirty blocks
+//! are written back when there are too many of them, on `fsync`, on
+//! unmounting, and by [`api::sync`].
+//!
 //! # Cargo Features
 //!
-//! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
-//!    is **enabled** by default.
+//! - `fatfs`: Support [FAT] filesystems. This feature is **enabled** by default.
+//! - `ext4fs`: Support ext4 filesystems by `axfs_ext4`, with the journal
+//!    replayed on mounting after a crash. ext2 and ext3 filesystems can also
+//!    be mounted.
 //! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
 //!    **enabled** by default.
-//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
+//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`, and allow mounting
+//!    it as `tmpfs` with options like `size=64m`. This feature is
 //!    **enabled** by default.
+//! - `multitask`: Let the tasks waiting for file locks sleep until the locks
+//!    are dropped, see [`lock::LockWaiter`].
+//! - `irq`: Sleep until block devices raise interrupts when waiting for their
+//!    requests, instead of polling them.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
 //!    by default, but it will override other filesystem selection features if
 //!    both are enabled.
 //!
+//! The filesystem types are kept in a registry, listed in `/proc/filesystems`.
+//! The root filesystem and the ones mounted with the type `auto` are chosen
+//! by probing the superblocks with the registered disk filesystem types, so
+//! one build can have FAT and ext4 roots. More types can be added by
+//! [`register_filesystem`].
+//!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
 
@@ -31,20 +57,38 @@ mod fs;
 mod mounts;
 mod root;
 
-pub use fs::BLOCK_SIZE;
+pub use fs::{register_filesystem, FileSystemType, FsKind, BLOCK_SIZE, PROBE_SIZE};
 pub mod api;
 pub mod fops;
+pub mod lock;
+pub mod notify;
+pub mod xattr;
 
 pub use axfs_devfs;
 pub use axfs_ramfs;
 
+use alloc::vec::Vec;
 use axdriver::{prelude::*, AxDeviceContainer};
 
 /// Initializes filesystems by block devices.
 pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("Initialize filesystems...");
 
-    let dev = blk_devs.take_one().expect("No block device found!");
-    info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let mut disks = Vec::new();
+    while let Some(dev) = blk_devs.take_one() {
+        info!("  block device {}: {:?}", disks.len(), dev.device_name());
+        disks.push(self::dev::BlockDevice::register(dev));
+    }
+    self::fs::register_builtin();
+
+    for disk in &disks {
+        // a filesystem on the whole disk, e.g. FAT, may look like an MBR
+        if mounts::probe_device(disk).is_none() {
+            disk.register_partitions();
+        }
+    }
+    let root = mounts::root_device(&disks);
+    info!("  root device: /dev/{}", root.name());
+    root.claim().unwrap();
+    self::root::init_rootfs(root);
 }
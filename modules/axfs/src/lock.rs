@@ -0,0 +1,430 @@
+//! Advisory file locks.
+//!
+//! Each file has two independent sets of locks, as on Linux:
+//!
+//! - whole-file [`flock`] locks, owned by an open file description;
+//! - byte-range record locks, owned either by a process (POSIX `F_SETLK`) or
+//!   by an open file description (`F_OFD_SETLK`). Both kinds of record locks
+//!   conflict with each other.
+//!
+//! Files are identified by a [`FileKey`], so the locks taken through any
+//! hard link of a file are the same, and stay with it when it's renamed.
+//! Nothing here blocks: a conflicting request fails with
+//! [`LockConflict::WouldBlock`], and the caller waits and retries. With the
+//! `multitask` feature, the caller sleeps on the [`LockWaiter`] of the file,
+//! which is woken whenever locks on the file are dropped. Owners waiting for
+//! a record lock are remembered, so a process whose wait would close a cycle
+//! of waiters gets [`LockConflict::Deadlock`] instead.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use axerrno::AxResult;
+use axfs_vfs::VfsNodeRef;
+use axsync::Mutex;
+
+/// Identifies a file to lock.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FileKey {
+    /// A file identified by its filesystem, see
+    /// [`VfsNodeOps::file_id`](axfs_vfs::VfsNodeOps::file_id).
+    Id(usize, u64),
+    /// A file of a filesystem that can't tell its files apart, by its
+    /// canonical path. Such filesystems, like FAT, have no hard links.
+    Path(String),
+}
+
+impl FileKey {
+    /// The key of the file of `node`, which is at `path`.
+    pub fn of(node: &VfsNodeRef, path: &str) -> Self {
+        match node.file_id() {
+            Some((fs, ino)) => Self::Id(fs, ino),
+            None => Self::Path(axfs_vfs::path::canonicalize(path)),
+        }
+    }
+}
+
+/// Finds the key of the file at `path`.
+pub fn file_key(path: &str) -> AxResult<FileKey> {
+    let path = crate::root::absolute_path(path)?;
+    let node = crate::root::lookup(None, &path)?;
+    Ok(FileKey::of(&node, &path))
+}
+
+/// The type of a lock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockType {
+    /// A shared lock, for reading.
+    Read,
+    /// An exclusive lock, for writing.
+    Write,
+}
+
+impl LockType {
+    fn conflicts(self, other: Self) -> bool {
+        self == Self::Write || other == Self::Write
+    }
+}
+
+/// The holder of a lock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockOwner {
+    /// A process, identified by its pid. Holds POSIX record locks.
+    Process(u64),
+    /// An open file description, identified by its address. Holds `flock`
+    /// and OFD locks.
+    File(usize),
+}
+
+/// A lock on the bytes `start..end` of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordLock {
+    /// The holder of the lock.
+    pub owner: LockOwner,
+    /// Shared or exclusive.
+    pub ty: LockType,
+    /// The first locked byte.
+    pub start: u64,
+    /// One past the last locked byte. `u64::MAX` locks up to the end of the
+    /// file, however far it grows.
+    pub end: u64,
+}
+
+impl RecordLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+
+    fn conflicts(&self, other: &Self) -> bool {
+        self.owner != other.owner
+            && self.overlaps(other.start, other.end)
+            && self.ty.conflicts(other.ty)
+    }
+}
+
+/// Why a lock could not be taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockConflict {
+    /// Another owner holds a conflicting lock.
+    WouldBlock,
+    /// Waiting for the lock would deadlock.
+    Deadlock,
+}
+
+#[derive(Default)]
+struct FileLocks {
+    flocks: Vec<(LockOwner, LockType)>,
+    records: Vec<RecordLock>,
+    #[cfg(feature = "multitask")]
+    waiters: alloc::sync::Arc<waiter::WaitQueue>,
+}
+
+impl FileLocks {
+    /// Whether nothing is locked on the file, and no one waits for it.
+    fn is_empty(&self) -> bool {
+        #[cfg(feature = "multitask")]
+        if alloc::sync::Arc::strong_count(&self.waiters) > 1 {
+            return false;
+        }
+        self.flocks.is_empty() && self.records.is_empty()
+    }
+
+    /// Wakes the owners waiting for the file, after some locks are dropped.
+    fn wake(&self) {
+        #[cfg(feature = "multitask")]
+        self.waiters.wake();
+    }
+
+    /// Replaces whatever `owner` holds on `start..end` with a lock of type
+    /// `ty`, or with nothing. Locks that stick out of the range are split,
+    /// and the new lock is merged with adjacent locks of the same type.
+    fn replace(&mut self, owner: LockOwner, start: u64, end: u64, ty: Option<LockType>) {
+        let mut kept = Vec::with_capacity(self.records.len() + 1);
+        for lock in self.records.drain(..) {
+            if lock.owner != owner || !lock.overlaps(start, end) {
+                kept.push(lock);
+                continue;
+            }
+            if lock.start < start {
+                kept.push(RecordLock { end: start, ..lock });
+            }
+            if lock.end > end {
+                kept.push(RecordLock { start: end, ..lock });
+            }
+        }
+        if let Some(ty) = ty {
+            let mut new = RecordLock {
+                owner,
+                ty,
+                start,
+                end,
+            };
+            kept.retain(|lock| {
+                let adjacent = lock.owner == owner
+                    && lock.ty == ty
+                    && lock.start <= new.end
+                    && new.start <= lock.end;
+                if adjacent {
+                    new.start = new.start.min(lock.start);
+                    new.end = new.end.max(lock.end);
+                }
+                !adjacent
+            });
+            kept.push(new);
+        }
+        self.records = kept;
+    }
+}
+
+struct LockTable {
+    files: BTreeMap<FileKey, FileLocks>,
+    /// Owners waiting in `F_SETLKW`, with the lock each one waits for.
+    waiting: BTreeMap<LockOwner, (FileKey, RecordLock)>,
+}
+
+impl LockTable {
+    /// Drops the entry of `key` once nothing is locked on it.
+    fn prune(&mut self, key: &FileKey) {
+        if self.files.get(key).is_some_and(FileLocks::is_empty) {
+            self.files.remove(key);
+        }
+    }
+
+    /// The owners of the locks that conflict with `lock`.
+    fn blockers(&self, key: &FileKey, lock: &RecordLock) -> Vec<LockOwner> {
+        self.files.get(key).map_or(Vec::new(), |locks| {
+            locks
+                .records
+                .iter()
+                .filter(|held| held.conflicts(lock))
+                .map(|held| held.owner)
+                .collect()
+        })
+    }
+
+    /// Whether `lock.owner` waiting for `lock` closes a cycle in the graph
+    /// of owners waiting for each other.
+    fn would_deadlock(&self, key: &FileKey, lock: &RecordLock) -> bool {
+        let mut stack = self.blockers(key, lock);
+        let mut seen = Vec::new();
+        while let Some(owner) = stack.pop() {
+            if owner == lock.owner {
+                return true;
+            }
+            if seen.contains(&owner) {
+                continue;
+            }
+            seen.push(owner);
+            if let Some((key, lock)) = self.waiting.get(&owner) {
+                stack.extend(self.blockers(key, lock));
+            }
+        }
+        false
+    }
+}
+
+static LOCKS: Mutex<LockTable> = Mutex::new(LockTable {
+    files: BTreeMap::new(),
+    waiting: BTreeMap::new(),
+});
+
+/// Takes or converts the `flock` lock of `owner` on the file, or drops it if
+/// `ty` is `None`.
+///
+/// Converting a lock is not atomic on Linux either: on conflict the old lock
+/// is kept.
+pub fn flock(key: &FileKey, owner: LockOwner, ty: Option<LockType>) -> Result<(), LockConflict> {
+    let mut table = LOCKS.lock();
+    let Some(ty) = ty else {
+        if let Some(locks) = table.files.get_mut(key) {
+            locks.flocks.retain(|&(held, _)| held != owner);
+            locks.wake();
+        }
+        table.prune(key);
+        return Ok(());
+    };
+    let locks = table.files.entry(key.clone()).or_default();
+    if locks
+        .flocks
+        .iter()
+        .any(|&(held, held_ty)| held != owner && held_ty.conflicts(ty))
+    {
+        table.prune(key);
+        return Err(LockConflict::WouldBlock);
+    }
+    let converted = locks.flocks.iter().any(|&(held, _)| held == owner);
+    locks.flocks.retain(|&(held, _)| held != owner);
+    locks.flocks.push((owner, ty));
+    if converted {
+        // a lock converted to a shared one may let others in
+        locks.wake();
+    }
+    Ok(())
+}
+
+/// Returns a lock held by another owner that prevents `lock` from being
+/// taken, as `F_GETLK` reports.
+pub fn test_record(key: &FileKey, lock: &RecordLock) -> Option<RecordLock> {
+    let table = LOCKS.lock();
+    let locks = table.files.get(key)?;
+    locks
+        .records
+        .iter()
+        .find(|held| held.conflicts(lock))
+        .copied()
+}
+
+/// Takes a record lock, replacing whatever its owner holds on the range.
+///
+/// With `wait`, a conflicting request marks the owner as waiting for the lock,
+/// until the lock is taken or [`cancel_wait`] is called. Requests of processes
+/// that would deadlock fail with [`LockConflict::Deadlock`]; like Linux, OFD
+/// locks are not checked for deadlocks.
+pub fn set_record(key: &FileKey, lock: RecordLock, wait: bool) -> Result<(), LockConflict> {
+    let mut table = LOCKS.lock();
+    if !table.blockers(key, &lock).is_empty() {
+        if wait {
+            if matches!(lock.owner, LockOwner::Process(_)) && table.would_deadlock(key, &lock) {
+                table.waiting.remove(&lock.owner);
+                return Err(LockConflict::Deadlock);
+            }
+            table.waiting.insert(lock.owner, (key.clone(), lock));
+        }
+        return Err(LockConflict::WouldBlock);
+    }
+    table.waiting.remove(&lock.owner);
+    let locks = table.files.entry(key.clone()).or_default();
+    locks.replace(lock.owner, lock.start, lock.end, Some(lock.ty));
+    // the owner's locks replaced may be weaker or smaller
+    locks.wake();
+    Ok(())
+}
+
+/// Drops the record locks of `owner` on the bytes `start..end`, splitting
+/// the locks that cover only part of the range.
+pub fn unlock_record(key: &FileKey, owner: LockOwner, start: u64, end: u64) {
+    let mut table = LOCKS.lock();
+    if let Some(locks) = table.files.get_mut(key) {
+        locks.replace(owner, start, end, None);
+        locks.wake();
+    }
+    table.prune(key);
+}
+
+/// Stops waiting for a record lock, e.g. when the wait is interrupted.
+pub fn cancel_wait(owner: LockOwner) {
+    LOCKS.lock().waiting.remove(&owner);
+}
+
+/// Drops all locks of `owner` on the file.
+///
+/// POSIX requires a process to lose its record locks on a file as soon as it
+/// closes any descriptor of that file.
+pub fn release(key: &FileKey, owner: LockOwner) {
+    let mut table = LOCKS.lock();
+    if let Some(locks) = table.files.get_mut(key) {
+        locks.flocks.retain(|&(held, _)| held != owner);
+        locks.records.retain(|held| held.owner != owner);
+        locks.wake();
+    }
+    table.prune(key);
+}
+
+/// Drops all locks of `owner` on every file, when a process exits or an open
+/// file description is closed for the last time.
+pub fn release_owner(owner: LockOwner) {
+    let mut table = LOCKS.lock();
+    table.waiting.remove(&owner);
+    table.files.retain(|_, locks| {
+        let held = locks.flocks.len() + locks.records.len();
+        locks.flocks.retain(|&(held, _)| held != owner);
+        locks.records.retain(|held| held.owner != owner);
+        if locks.flocks.len() + locks.records.len() < held {
+            locks.wake();
+        }
+        !locks.is_empty()
+    });
+}
+
+#[cfg(feature = "multitask")]
+pub use self::waiter::LockWaiter;
+
+#[cfg(feature = "multitask")]
+mod waiter {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use core::time::Duration;
+
+    use super::{FileKey, LOCKS};
+
+    /// The longest time to sleep, after which the caller checks signals.
+    const BLOCK_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// The wait queue of a locked file.
+    pub(super) struct WaitQueue {
+        queue: axtask::WaitQueue,
+        /// Counts the times locks on the file are dropped.
+        changes: AtomicU64,
+    }
+
+    impl Default for WaitQueue {
+        fn default() -> Self {
+            Self {
+                queue: axtask::WaitQueue::new(),
+                changes: AtomicU64::new(0),
+            }
+        }
+    }
+
+    impl WaitQueue {
+        pub fn wake(&self) {
+            self.changes.fetch_add(1, Ordering::AcqRel);
+            self.queue.notify_all(false);
+        }
+    }
+
+    /// Where an owner sleeps until the locks on a file change.
+    ///
+    /// It's taken before trying to lock the file, so the locks dropped in
+    /// between are not missed.
+    pub struct LockWaiter {
+        key: FileKey,
+        queue: Arc<WaitQueue>,
+        changes: u64,
+    }
+
+    impl LockWaiter {
+        /// Starts waiting for the locks on the file.
+        pub fn new(key: &FileKey) -> Self {
+            let mut table = LOCKS.lock();
+            let queue = table.files.entry(key.clone()).or_default().waiters.clone();
+            let changes = queue.changes.load(Ordering::Acquire);
+            Self {
+                key: key.clone(),
+                queue,
+                changes,
+            }
+        }
+
+        /// Sleeps until some locks on the file are dropped after the waiter
+        /// is taken, or for a while, after which the caller checks signals.
+        pub fn wait(&self) {
+            self.queue.queue.wait_timeout_until(BLOCK_INTERVAL, || {
+                self.queue.changes.load(Ordering::Acquire) != self.changes
+            });
+        }
+    }
+
+    impl Drop for LockWaiter {
+        fn drop(&mut self) {
+            let mut table = LOCKS.lock();
+            let unused = table.files.get(&self.key).is_some_and(|locks| {
+                // only the table and this waiter refer to the queue
+                Arc::strong_count(&self.queue) == 2
+                    && locks.flocks.is_empty()
+                    && locks.records.is_empty()
+            });
+            if unused {
+                table.files.remove(&self.key);
+            }
+        }
+    }
+}
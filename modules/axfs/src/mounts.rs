@@ -1,15 +1,118 @@
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{VfsNodeOps, VfsNodeType, VfsOps, VfsResult};
 
-use crate::fs;
+use crate::dev::{BlockDevice, Disk};
+use crate::fs::{self, FileSystemType, FsKind};
+
+/// Filesystem types without a device.
+pub(crate) const NODEV_FS_TYPES: &[FileSystemType] = &[
+    #[cfg(feature = "ramfs")]
+    FileSystemType {
+        name: "ramfs",
+        kind: FsKind::Nodev {
+            new: |options| Ok(ramfs(options)?),
+        },
+    },
+    #[cfg(feature = "ramfs")]
+    FileSystemType {
+        name: "tmpfs",
+        kind: FsKind::Nodev {
+            new: |options| Ok(ramfs(options)?),
+        },
+    },
+    #[cfg(feature = "procfs")]
+    FileSystemType {
+        name: "proc",
+        kind: FsKind::Nodev {
+            new: |_| Ok(procfs()?),
+        },
+    },
+    #[cfg(feature = "sysfs")]
+    FileSystemType {
+        name: "sysfs",
+        kind: FsKind::Nodev {
+            new: |_| Ok(sysfs()?),
+        },
+    },
+    #[cfg(feature = "devfs")]
+    FileSystemType {
+        name: "devtmpfs",
+        kind: FsKind::Nodev {
+            new: |_| Ok(devfs()),
+        },
+    },
+];
+
+/// Creates a filesystem of type `fstype` to be mounted, on the block device
+/// at `source` if it's a disk filesystem, which is claimed by the returned
+/// filesystem. Filesystems without a device are given the mount `options`.
+///
+/// The type of the disk filesystem is probed if `fstype` is empty or `auto`.
+pub(crate) fn new_fs(
+    source: &str,
+    fstype: &str,
+    options: &str,
+) -> AxResult<(Arc<dyn VfsOps>, Option<Arc<BlockDevice>>)> {
+    let new = match fstype {
+        "" | "auto" => None,
+        name => match fs::find_filesystem(name).map(|fs| fs.kind) {
+            Some(FsKind::Nodev { new }) => return Ok((new(options)?, None)),
+            Some(FsKind::Disk { new, .. }) => Some(new),
+            None => return ax_err!(Unsupported, "unknown filesystem type"),
+        },
+    };
+    let device = BlockDevice::find(source)?;
+    device.claim()?;
+    let fs = match new {
+        Some(new) => new(Disk::new(device.clone())),
+        None => open_disk_fs(&device),
+    };
+    if fs.is_err() {
+        device.release();
+    }
+    Ok((fs?, Some(device)))
+}
+
+/// Probes the type of the filesystem on `device` by the superblock.
+pub(crate) fn probe_device(device: &BlockDevice) -> Option<FileSystemType> {
+    let mut buf = [0u8; fs::PROBE_SIZE];
+    device.read_at(0, &mut buf).ok()?;
+    fs::probe_filesystem(&buf)
+}
+
+/// Opens the filesystem on `device`, whose type is probed by the superblock.
+pub(crate) fn open_disk_fs(device: &Arc<BlockDevice>) -> AxResult<Arc<dyn VfsOps>> {
+    match probe_device(device).map(|fs| fs.kind) {
+        Some(FsKind::Disk { new, .. }) => new(Disk::new(device.clone())),
+        _ => ax_err!(InvalidData, "no known filesystem on the block device"),
+    }
+}
+
+/// Chooses the device of the root filesystem.
+///
+/// It's the one given by `AX_ROOT` at build time, like `/dev/vda2` or
+/// `PARTUUID=...`. Otherwise it's the first disk, or the first partition on
+/// it with a known filesystem if it's partitioned.
+pub(crate) fn root_device(disks: &[Arc<BlockDevice>]) -> Arc<BlockDevice> {
+    if let Some(root) = option_env!("AX_ROOT").filter(|root| !root.is_empty()) {
+        return BlockDevice::find(root)
+            .unwrap_or_else(|_| panic!("root device {} not found", root));
+    }
+    let disk = disks.first().expect("No block device found!");
+    disk.partitions()
+        .into_iter()
+        .find(|part| probe_device(part).is_some())
+        .unwrap_or_else(|| disk.clone())
+}
 
 #[cfg(feature = "devfs")]
 pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     let null = fs::devfs::NullDev;
     let zero = fs::devfs::ZeroDev;
     let bar = fs::devfs::ZeroDev;
-    let random = fs::devfs::RandomDev::default();
-    let urandom = fs::devfs::RandomDev::default();
+    let random = fs::devfs::RandomDev::new(axhal::random::fill_bytes);
+    let urandom = fs::devfs::RandomDev::new(axhal::random::fill_bytes);
 
     let devfs = fs::devfs::DeviceFileSystem::new();
     let foo_dir = devfs.mkdir("foo");
@@ -18,6 +121,12 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     foo_dir.add("bar", Arc::new(bar));
     devfs.add("random", Arc::new(random));
     devfs.add("urandom", Arc::new(urandom));
+    for device in BlockDevice::all() {
+        devfs.add(device.name(), device);
+    }
+    // the mount point of tmpfs for POSIX shared memory
+    #[cfg_attr(not(feature = "monolithic"), allow(unused_variables))]
+    let shm_dir = devfs.mkdir("shm");
     #[cfg(feature = "monolithic")]
     {
         // 添加dev文件系统下的配置文件
@@ -25,7 +134,6 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
         // devfs不支持可修改的file，因此取巧直接用了ramfs提供的file实现
         let testshm = fs::ramfs::FileNode::new();
         let testrtc = fs::ramfs::FileNode::new();
-        let shm_dir = devfs.mkdir("shm");
         shm_dir.add("testshm", Arc::new(testshm));
         let rtc_dir = devfs.mkdir("misc");
         rtc_dir.add("rtc", Arc::new(testrtc));
@@ -33,9 +141,13 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     Arc::new(devfs)
 }
 
+/// Creates a tmpfs with the mount options like `size=64m,mode=1777`, whose
+/// timestamps are taken from the system clock.
 #[cfg(feature = "ramfs")]
-pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
-    Arc::new(fs::ramfs::RamFileSystem::new())
+pub(crate) fn ramfs(options: &str) -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
+    let options = fs::ramfs::RamfsOptions::parse(options)?;
+    let ramfs = fs::ramfs::RamFileSystem::with_options(&options, axhal::time::current_time)?;
+    Ok(Arc::new(ramfs))
 }
 
 #[cfg(feature = "procfs")]
@@ -57,11 +169,29 @@ pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
     let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
     file_over.write_at(0, b"0\n")?;
 
+    // Create /proc/sys/kernel/randomize_va_space
+    proc_root.create("sys/kernel", VfsNodeType::Dir)?;
+    proc_root.create("sys/kernel/randomize_va_space", VfsNodeType::File)?;
+    let file_aslr = proc_root.clone().lookup("./sys/kernel/randomize_va_space")?;
+    file_aslr.write_at(0, b"2\n")?;
+
     // Create /proc/self/stat
     proc_root.create("self", VfsNodeType::Dir)?;
     proc_root.create("self/stat", VfsNodeType::File)?;
     proc_root.create("self/exe", VfsNodeType::File)?;
 
+    // Create /proc/slabinfo
+    procfs.root_dir_node().add_node(
+        "slabinfo",
+        Arc::new(fs::ramfs::GenFileNode::new(axalloc::slabinfo)),
+    )?;
+
+    // Create /proc/filesystems
+    procfs.root_dir_node().add_node(
+        "filesystems",
+        Arc::new(fs::ramfs::GenFileNode::new(fs::filesystems)),
+    )?;
+
     #[cfg(feature = "monolithic")]
     {
         // Create other file to pass the testcases
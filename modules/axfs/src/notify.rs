@@ -0,0 +1,416 @@
+//! File change notification, the backend of `inotify`.
+//!
+//! An [`Inotify`] instance watches a set of paths. The filesystem operations
+//! in [`crate::api`] report what they did to the changed path, and each event
+//! is queued on the watches of that path and of its parent directory, the
+//! latter with the name of the child, as on Linux.
+//!
+//! Watches are kept by canonical path, and follow the files they watch when
+//! those are renamed.
+
+use alloc::{
+    collections::VecDeque,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axerrno::{ax_err, AxResult};
+use axsync::Mutex;
+use bitflags::bitflags;
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+
+bitflags! {
+    /// The events of a watch, and the flags of `inotify_add_watch`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct WatchMask: u32 {
+        /// The file was read.
+        const ACCESS = 0x1;
+        /// The file was written or truncated.
+        const MODIFY = 0x2;
+        /// The metadata, e.g. the permissions or the owner, changed.
+        const ATTRIB = 0x4;
+        /// A file opened for writing was closed.
+        const CLOSE_WRITE = 0x8;
+        /// A file not opened for writing was closed.
+        const CLOSE_NOWRITE = 0x10;
+        /// The file was opened.
+        const OPEN = 0x20;
+        /// A file was moved out of the watched directory.
+        const MOVED_FROM = 0x40;
+        /// A file was moved into the watched directory.
+        const MOVED_TO = 0x80;
+        /// A file was created in the watched directory.
+        const CREATE = 0x100;
+        /// A file was deleted from the watched directory.
+        const DELETE = 0x200;
+        /// The watched file was deleted.
+        const DELETE_SELF = 0x400;
+        /// The watched file was moved.
+        const MOVE_SELF = 0x800;
+        /// The filesystem of the watched file was unmounted.
+        const UNMOUNT = 0x2000;
+        /// The event queue overflowed.
+        const Q_OVERFLOW = 0x4000;
+        /// The watch was removed.
+        const IGNORED = 0x8000;
+        /// Only watch the path if it's a directory.
+        const ONLYDIR = 0x0100_0000;
+        /// Don't dereference the path if it's a symbolic link.
+        const DONT_FOLLOW = 0x0200_0000;
+        /// Don't report events of children after they are unlinked.
+        const EXCL_UNLINK = 0x0400_0000;
+        /// Fail if the path is already watched.
+        const MASK_CREATE = 0x1000_0000;
+        /// Add to the events of an existing watch instead of replacing them.
+        const MASK_ADD = 0x2000_0000;
+        /// The subject of the event is a directory.
+        const ISDIR = 0x4000_0000;
+        /// Remove the watch after its first event.
+        const ONESHOT = 0x8000_0000;
+
+        /// Both close events.
+        const CLOSE = Self::CLOSE_WRITE.bits() | Self::CLOSE_NOWRITE.bits();
+        /// Both move events.
+        const MOVE = Self::MOVED_FROM.bits() | Self::MOVED_TO.bits();
+        /// All events that can be watched.
+        const ALL_EVENTS = 0xfff;
+    }
+}
+
+/// The maximum number of events queued on an instance, as Linux's default
+/// `max_queued_events`.
+pub const MAX_QUEUED_EVENTS: usize = 16384;
+
+/// An event queued on an [`Inotify`] instance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotifyEvent {
+    /// The watch descriptor, or -1 for [`WatchMask::Q_OVERFLOW`].
+    pub wd: i32,
+    /// What happened.
+    pub mask: WatchMask,
+    /// Connects the [`WatchMask::MOVED_FROM`] and [`WatchMask::MOVED_TO`]
+    /// events of a rename, 0 for other events.
+    pub cookie: u32,
+    /// The name of the child for events on a watched directory, otherwise
+    /// empty.
+    pub name: String,
+}
+
+/// An inotify instance: the queue of events of its watches.
+pub struct Inotify {
+    queue: Mutex<VecDeque<NotifyEvent>>,
+    next_wd: AtomicI32,
+}
+
+struct Watch {
+    path: String,
+    wd: i32,
+    mask: WatchMask,
+    instance: Weak<Inotify>,
+}
+
+impl Watch {
+    fn belongs_to(&self, instance: &Arc<Inotify>) -> bool {
+        Weak::as_ptr(&self.instance) == Arc::as_ptr(instance)
+    }
+}
+
+/// All watches of all instances. Watches of dropped instances are removed
+/// lazily, so that an instance can be dropped while this is locked.
+static WATCHES: Mutex<Vec<Watch>> = Mutex::new(Vec::new());
+
+/// The last cookie given to a rename.
+static COOKIE: AtomicU32 = AtomicU32::new(0);
+
+impl Inotify {
+    /// Creates an instance without any watch.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            queue: Mutex::new(VecDeque::new()),
+            next_wd: AtomicI32::new(1),
+        })
+    }
+
+    /// Watches `path` for the events in `mask`, returns the watch descriptor.
+    ///
+    /// Watching a path again on the same instance updates the existing watch
+    /// and returns its descriptor.
+    pub fn add_watch(self: &Arc<Self>, path: &str, mask: WatchMask) -> AxResult<i32> {
+        if !mask.intersects(WatchMask::ALL_EVENTS) {
+            return ax_err!(InvalidInput);
+        }
+        if mask.contains(WatchMask::MASK_ADD | WatchMask::MASK_CREATE) {
+            return ax_err!(InvalidInput);
+        }
+        let node = crate::root::lookup(None, path)?;
+        if mask.contains(WatchMask::ONLYDIR) && !node.get_attr()?.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        let path = crate::root::absolute_path(path)?;
+        let events = mask & (WatchMask::ALL_EVENTS | WatchMask::EXCL_UNLINK | WatchMask::ONESHOT);
+
+        let mut watches = WATCHES.lock();
+        watches.retain(|watch| watch.instance.strong_count() > 0);
+        let existing = watches
+            .iter_mut()
+            .find(|watch| watch.path == path && watch.belongs_to(self));
+        if let Some(watch) = existing {
+            if mask.contains(WatchMask::MASK_CREATE) {
+                return ax_err!(AlreadyExists);
+            }
+            if mask.contains(WatchMask::MASK_ADD) {
+                watch.mask |= events;
+            } else {
+                watch.mask = events;
+            }
+            return Ok(watch.wd);
+        }
+        let wd = self.next_wd.fetch_add(1, Ordering::Relaxed);
+        watches.push(Watch {
+            path,
+            wd,
+            mask: events,
+            instance: Arc::downgrade(self),
+        });
+        Ok(wd)
+    }
+
+    /// Removes the watch `wd`, and queues [`WatchMask::IGNORED`] for it.
+    pub fn rm_watch(self: &Arc<Self>, wd: i32) -> AxResult {
+        let mut watches = WATCHES.lock();
+        let Some(index) = watches
+            .iter()
+            .position(|watch| watch.wd == wd && watch.belongs_to(self))
+        else {
+            return ax_err!(InvalidInput);
+        };
+        watches.swap_remove(index);
+        self.push(wd, WatchMask::IGNORED, 0, "");
+        Ok(())
+    }
+
+    /// Whether there are events to read.
+    pub fn has_events(&self) -> bool {
+        !self.queue.lock().is_empty()
+    }
+
+    /// Takes the oldest event if `take` accepts it, e.g. if it fits in the
+    /// buffer of the reader.
+    pub fn pop_event_if(&self, take: impl FnOnce(&NotifyEvent) -> bool) -> Option<NotifyEvent> {
+        let mut queue = self.queue.lock();
+        if take(queue.front()?) {
+            queue.pop_front()
+        } else {
+            None
+        }
+    }
+
+    fn push(&self, wd: i32, mask: WatchMask, cookie: u32, name: &str) {
+        let mut queue = self.queue.lock();
+        // merges an event with the last one if they are identical, as Linux does
+        if queue.back().is_some_and(|last| {
+            last.wd == wd && last.mask == mask && last.cookie == cookie && last.name == name
+        }) {
+            return;
+        }
+        let event = if queue.len() < MAX_QUEUED_EVENTS - 1 {
+            NotifyEvent {
+                wd,
+                mask,
+                cookie,
+                name: name.to_string(),
+            }
+        } else if queue.len() == MAX_QUEUED_EVENTS - 1 {
+            NotifyEvent {
+                wd: -1,
+                mask: WatchMask::Q_OVERFLOW,
+                cookie: 0,
+                name: String::new(),
+            }
+        } else {
+            return;
+        };
+        queue.push_back(event);
+    }
+}
+
+/// Queues `mask` on the watches of `path` itself, and with `name` on the
+/// watches of the parent directory.
+fn dispatch(watches: &mut Vec<Watch>, path: &str, mask: WatchMask, cookie: u32) {
+    let (parent, name) = match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+        None => return,
+    };
+    let events = mask - WatchMask::ISDIR;
+    let self_events = events - (WatchMask::CREATE | WatchMask::DELETE | WatchMask::MOVE);
+    let child_events = events - (WatchMask::DELETE_SELF | WatchMask::MOVE_SELF);
+    watches.retain(|watch| {
+        let Some(instance) = watch.instance.upgrade() else {
+            return false;
+        };
+        let name = if watch.path == path && watch.mask.intersects(self_events) {
+            ""
+        } else if watch.path == parent && watch.mask.intersects(child_events) && !name.is_empty() {
+            name
+        } else {
+            return true;
+        };
+        let reported = if name.is_empty() {
+            mask & (self_events | WatchMask::ISDIR)
+        } else {
+            mask & (child_events | WatchMask::ISDIR)
+        };
+        instance.push(
+            watch.wd,
+            reported & (watch.mask | WatchMask::ISDIR),
+            cookie,
+            name,
+        );
+        if watch.mask.contains(WatchMask::ONESHOT) {
+            instance.push(watch.wd, WatchMask::IGNORED, 0, "");
+            return false;
+        }
+        true
+    });
+}
+
+/// Removes the watches on `path`, and those below it if `recursive`, after
+/// queueing `mask` and [`WatchMask::IGNORED`] on them.
+fn remove_watches(watches: &mut Vec<Watch>, path: &str, mask: WatchMask, recursive: bool) {
+    watches.retain(|watch| {
+        let below = recursive
+            && watch
+                .path
+                .strip_prefix(path)
+                .is_some_and(|rest| rest.starts_with('/') || path == "/");
+        if watch.path != path && !below {
+            return true;
+        }
+        if let Some(instance) = watch.instance.upgrade() {
+            if watch.mask.intersects(mask) || mask.contains(WatchMask::UNMOUNT) {
+                instance.push(watch.wd, mask, 0, "");
+            }
+            instance.push(watch.wd, WatchMask::IGNORED, 0, "");
+        }
+        false
+    });
+}
+
+fn canonical(path: &str) -> Option<String> {
+    crate::root::absolute_path(path).ok()
+}
+
+/// Reports an event on the file itself, e.g. [`WatchMask::MODIFY`] or
+/// [`WatchMask::ATTRIB`]. Add [`WatchMask::ISDIR`] if it's a directory.
+pub fn notify(path: &str, mask: WatchMask) {
+    let mut watches = WATCHES.lock();
+    if watches.is_empty() {
+        return;
+    }
+    if let Some(path) = canonical(path) {
+        dispatch(&mut watches, &path, mask, 0);
+    }
+}
+
+/// Reports that a file or directory was created at `path`.
+pub fn notify_create(path: &str, is_dir: bool) {
+    let mask = if is_dir {
+        WatchMask::CREATE | WatchMask::ISDIR
+    } else {
+        WatchMask::CREATE
+    };
+    notify(path, mask);
+}
+
+/// Reports that the file or directory at `path` was deleted. Its watches
+/// receive [`WatchMask::DELETE_SELF`] and are removed.
+pub fn notify_delete(path: &str, is_dir: bool) {
+    let mut watches = WATCHES.lock();
+    if watches.is_empty() {
+        return;
+    }
+    let Some(path) = canonical(path) else {
+        return;
+    };
+    let isdir = if is_dir {
+        WatchMask::ISDIR
+    } else {
+        WatchMask::empty()
+    };
+    dispatch(&mut watches, &path, WatchMask::DELETE | isdir, 0);
+    remove_watches(&mut watches, &path, WatchMask::DELETE_SELF, false);
+}
+
+/// Reports that `old` was renamed to `new`. The watches on `old` and below
+/// it now watch the new paths.
+pub fn notify_move(old: &str, new: &str, is_dir: bool) {
+    let mut watches = WATCHES.lock();
+    if watches.is_empty() {
+        return;
+    }
+    let (Some(old), Some(new)) = (canonical(old), canonical(new)) else {
+        return;
+    };
+    let isdir = if is_dir {
+        WatchMask::ISDIR
+    } else {
+        WatchMask::empty()
+    };
+    let cookie = COOKIE.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+    dispatch(&mut watches, &old, WatchMask::MOVED_FROM | isdir, cookie);
+    dispatch(&mut watches, &new, WatchMask::MOVED_TO | isdir, cookie);
+    // the replaced file, if any, is gone
+    remove_watches(&mut watches, &new, WatchMask::DELETE_SELF, false);
+    dispatch(&mut watches, &old, WatchMask::MOVE_SELF | isdir, 0);
+    for watch in watches.iter_mut() {
+        if watch.path == old {
+            watch.path = new.clone();
+        } else if let Some(rest) = watch.path.strip_prefix(old.as_str()) {
+            if rest.starts_with('/') {
+                watch.path = new.clone() + rest;
+            }
+        }
+    }
+}
+
+/// Reports that the filesystem mounted on `path` was unmounted. All watches
+/// on it receive [`WatchMask::UNMOUNT`] and are removed.
+pub fn notify_unmount(path: &str) {
+    let mut watches = WATCHES.lock();
+    if let Some(path) = canonical(path) {
+        remove_watches(&mut watches, &path, WatchMask::UNMOUNT, true);
+    }
+}
+
+/// Reports the open and close events of an open file.
+///
+/// [`WatchMask::OPEN`] is reported on creation, and the close event when the
+/// last clone of the file is dropped.
+pub(crate) struct OpenFile {
+    path: String,
+    writable: bool,
+}
+
+impl OpenFile {
+    pub(crate) fn new(path: &str, writable: bool) -> Self {
+        let path = canonical(path).unwrap_or_default();
+        notify(&path, WatchMask::OPEN);
+        Self { path, writable }
+    }
+
+    /// Reports an event of the open file, e.g. [`WatchMask::ACCESS`].
+    pub(crate) fn notify(&self, mask: WatchMask) {
+        notify(&self.path, mask);
+    }
+}
+
+impl Drop for OpenFile {
+    fn drop(&mut self) {
+        if self.writable {
+            notify(&self.path, WatchMask::CLOSE_WRITE);
+        } else {
+            notify(&self.path, WatchMask::CLOSE_NOWRITE);
+        }
+    }
+}
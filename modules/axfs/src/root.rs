@@ -1,43 +1,97 @@
 //! Root directory of the filesystem
 //!
-//! TODO: it doesn't work very well if the mount points have containment relationships.
+//! Filesystems are mounted on directories of the root filesystem or of other
+//! mounted filesystems. A path is resolved in the filesystem mounted at its
+//! longest mount point prefix.
+//!
+//! The files open on a mounted filesystem and the current directory in it keep
+//! it busy, so that it can only be unmounted by force or lazily.
 
-use alloc::{
-    string::{String, ToString},
-    sync::Arc,
-    vec::Vec,
-};
+use alloc::{string::String, sync::Arc, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
-use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+use axfs_vfs::{FileSystemInfo, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult, XattrFlags};
 use axsync::Mutex;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
 use lazy_init::LazyInit;
 
-use crate::{api::FileType, fs, mounts};
+use crate::dev::BlockDevice;
+use crate::{api::FileType, fs, mounts, notify};
 
 static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
 static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
+/// The mounted filesystem that the current directory is in, `None` for the
+/// root filesystem.
+static CURRENT_DIR_MOUNT: Mutex<Option<Arc<Mounted>>> = Mutex::new(None);
 
-struct MountPoint {
-    path: &'static str,
+/// A mounted filesystem, shared by its mount point and the files open on it.
+pub(crate) struct Mounted {
     fs: Arc<dyn VfsOps>,
+    /// The block device that the filesystem is on.
+    device: Option<Arc<BlockDevice>>,
+    /// Set by a lazy unmount, the filesystem is unmounted when the last file
+    /// on it is closed.
+    detached: AtomicBool,
+}
+
+struct MountPoint {
+    /// The absolute path without the trailing '/'.
+    path: String,
+    mounted: Arc<Mounted>,
 }
 
 struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
-    mounts: Vec<MountPoint>,
+    mounts: Mutex<Vec<MountPoint>>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(path: String, fs: Arc<dyn VfsOps>, device: Option<Arc<BlockDevice>>) -> Self {
+        let mounted = Mounted {
+            fs,
+            device,
+            detached: AtomicBool::new(false),
+        };
+        Self {
+            path,
+            mounted: Arc::new(mounted),
+        }
+    }
+
+    /// Whether files are open on the filesystem, or the current directory is
+    /// in it.
+    fn is_busy(&self) -> bool {
+        Arc::strong_count(&self.mounted) > 1
+    }
+
+    /// Whether `path` is the mount point or under it, returns the rest of
+    /// `path` in the filesystem.
+    fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let rest = path.strip_prefix(&self.path[1..])?;
+        if rest.is_empty() || rest.starts_with('/') {
+            Some(rest)
+        } else {
+            None
+        }
     }
 }
 
-impl Drop for MountPoint {
+impl Drop for Mounted {
     fn drop(&mut self) {
-        self.fs.umount().ok();
+        if self.detached.load(Ordering::Acquire) {
+            if let Err(e) = self.fs.umount() {
+                warn!("ignore the error when unmounting lazily: {:?}", e);
+            }
+        }
+        if let Some(device) = &self.device {
+            if let Err(e) = device.sync() {
+                warn!("failed to sync /dev/{}: {:?}", device.name(), e);
+            }
+            device.release();
+        }
     }
 }
 
@@ -45,33 +99,129 @@ impl RootDirectory {
     pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
         Self {
             main_fs,
-            mounts: Vec::new(),
+            mounts: Mutex::new(Vec::new()),
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
-        if path == "/" {
-            return ax_err!(InvalidInput, "cannot mount root filesystem");
-        }
+    /// Mounts `fs` on the directory at `path`, creates the directory if it
+    /// does not exist.
+    pub fn mount(
+        &self,
+        path: &str,
+        fs: Arc<dyn VfsOps>,
+        device: Option<Arc<BlockDevice>>,
+    ) -> AxResult {
         if !path.starts_with('/') {
             return ax_err!(InvalidInput, "mount path must start with '/'");
         }
-        if self.mounts.iter().any(|mp| mp.path == path) {
-            return ax_err!(InvalidInput, "mount point already exists");
+        let path = axfs_vfs::path::canonicalize(path);
+        let path = path.trim_end_matches('/');
+        if path.is_empty() {
+            return ax_err!(InvalidInput, "cannot mount root filesystem");
+        }
+        if self.contains(path) {
+            return ax_err!(ResourceBusy, "mount point already exists");
+        }
+        // create the mount point in the filesystem it is in if it does not exist
+        let mount_point = self.lookup_mounted_fs(path, |parent_fs, rest_path| {
+            let dir = parent_fs.root_dir();
+            match dir.clone().lookup(rest_path) {
+                Err(AxError::NotFound) => dir.create(rest_path, FileType::Dir)?,
+                Err(e) => return Err(e),
+                Ok(_) => {}
+            }
+            let node = dir.lookup(rest_path)?;
+            if !node.get_attr()?.is_dir() {
+                return ax_err!(NotADirectory);
+            }
+            Ok(node)
+        })?;
+        fs.mount(path, mount_point)?;
+        self.mounts
+            .lock()
+            .push(MountPoint::new(path.into(), fs, device));
+        Ok(())
+    }
+
+    /// Unmounts the filesystem at `path`.
+    ///
+    /// It fails if other filesystems are mounted under it, or files are open
+    /// on it, unless `detach` is set. A detached filesystem and the ones
+    /// under it are removed from the tree at once, and each of them is
+    /// unmounted when its last file is closed. `force` unmounts the
+    /// filesystem even if it is busy, and ignores the errors from it.
+    pub fn umount(&self, path: &str, force: bool, detach: bool) -> AxResult {
+        let path = axfs_vfs::path::canonicalize(path);
+        let path = path.trim_end_matches('/');
+        let mut mounts = self.mounts.lock();
+        let Some(idx) = mounts.iter().position(|mp| mp.path == path) else {
+            return ax_err!(InvalidInput, "not a mount point");
+        };
+        let is_child = |mp: &MountPoint| {
+            mp.path.len() > path.len()
+                && mp.path.starts_with(path)
+                && mp.path.as_bytes()[path.len()] == b'/'
+        };
+        if detach {
+            let (mut detached, rest): (Vec<_>, Vec<_>) = mounts
+                .drain(..)
+                .partition(|mp| mp.path == path || is_child(mp));
+            *mounts = rest;
+            // unmount the deepest ones first
+            detached.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+            for mp in detached {
+                mp.mounted.detached.store(true, Ordering::Release);
+            }
+            return Ok(());
+        }
+        if mounts.iter().any(is_child) {
+            return ax_err!(ResourceBusy, "other filesystems are mounted under it");
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
+        if !force && mounts[idx].is_busy() {
+            return ax_err!(ResourceBusy, "the filesystem is in use");
+        }
+        if let Err(e) = mounts[idx].mounted.fs.umount() {
+            if !force {
+                return Err(e);
+            }
+            warn!("ignore the error when unmounting {}: {:?}", path, e);
+        }
+        mounts.remove(idx);
         Ok(())
     }
 
-    pub fn _umount(&mut self, path: &str) {
-        self.mounts.retain(|mp| mp.path != path);
+    /// The mounted filesystem that the absolute `path` is in, `None` for the
+    /// root filesystem.
+    fn mount_of(&self, path: &str) -> Option<Arc<Mounted>> {
+        let path = path
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .collect::<Vec<_>>()
+            .join("/");
+        self.mounts
+            .lock()
+            .iter()
+            .filter_map(|mp| Some((mp.strip(&path)?, mp)))
+            .min_by_key(|(rest, _)| rest.len())
+            .map(|(_, mp)| mp.mounted.clone())
     }
 
     pub fn contains(&self, path: &str) -> bool {
-        self.mounts.iter().any(|mp| mp.path == path)
+        let path = path.trim_end_matches('/');
+        self.mounts.lock().iter().any(|mp| mp.path == path)
+    }
+
+    /// The filesystem mounted exactly at `path`.
+    fn mounted_fs(&self, path: &str) -> AxResult<Arc<dyn VfsOps>> {
+        let path = axfs_vfs::path::canonicalize(path);
+        let path = path.trim_end_matches('/');
+        if path.is_empty() {
+            return Ok(self.main_fs.clone());
+        }
+        match self.mounts.lock().iter().find(|mp| mp.path == path) {
+            Some(mp) => Ok(mp.mounted.fs.clone()),
+            None => ax_err!(InvalidInput, "not a mount point"),
+        }
     }
 
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
@@ -79,35 +229,27 @@ impl RootDirectory {
         F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
     {
         debug!("lookup at root: {}", path);
-        let path = path.trim_matches('/');
-        if let Some(rest) = path.strip_prefix("./") {
-            return self.lookup_mounted_fs(rest, f);
-        }
-
-        let mut idx = 0;
-        let mut max_len = 0;
-
-        // Find the filesystem that has the longest mounted path match
+        // remove the empty and "." components to match the mount points
+        let path = path
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .collect::<Vec<_>>()
+            .join("/");
+        let path = path.as_str();
+
+        // Find the filesystem that has the longest mounted path match, and
+        // call `f` without holding the lock, as it may come back to the root.
         // TODO: more efficient, e.g. trie
-
-        for (i, mp) in self.mounts.iter().enumerate() {
-            // skip the first '/'
-            // two conditions
-            // 1. path == mp.path, e.g. dev
-            // 2. path == mp.path + '/', e.g. dev/
-            let prev = mp.path[1..].to_string() + "/";
-            if path.starts_with(&mp.path[1..])
-                && (path.len() == prev.len() - 1 || path.starts_with(&prev))
-                && prev.len() > max_len
-            {
-                max_len = mp.path.len() - 1;
-                idx = i;
-            }
-        }
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
+        let matched = self
+            .mounts
+            .lock()
+            .iter()
+            .filter_map(|mp| Some((mp.strip(path)?, mp)))
+            .min_by_key(|(rest, _)| rest.len())
+            .map(|(rest, mp)| (mp.mounted.fs.clone(), path.len() - rest.len()));
+        match matched {
+            Some((fs, prefix_len)) => f(fs, &path[prefix_len..]), // matched a mount point
+            None => f(self.main_fs.clone(), path),                // not matched any mount point
         }
     }
 }
@@ -119,6 +261,30 @@ impl VfsNodeOps for RootDirectory {
         self.main_fs.root_dir().get_attr()
     }
 
+    fn set_mode(&self, mode: VfsNodePerm) -> VfsResult {
+        self.main_fs.root_dir().set_mode(mode)
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> VfsResult {
+        self.main_fs.root_dir().set_owner(uid, gid)
+    }
+
+    fn get_xattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.main_fs.root_dir().get_xattr(name)
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> VfsResult {
+        self.main_fs.root_dir().set_xattr(name, value, flags)
+    }
+
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        self.main_fs.root_dir().list_xattr()
+    }
+
+    fn remove_xattr(&self, name: &str) -> VfsResult {
+        self.main_fs.root_dir().remove_xattr(name)
+    }
+
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
         self.lookup_mounted_fs(path, |fs, rest_path| {
             let dir = fs.root_dir();
@@ -147,63 +313,70 @@ impl VfsNodeOps for RootDirectory {
     }
 
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        let (dst_fs, dst_rest) =
+            self.lookup_mounted_fs(dst_path, |fs, rest_path| Ok((fs, String::from(rest_path))))?;
         self.lookup_mounted_fs(src_path, |fs, rest_path| {
-            if rest_path.is_empty() {
+            if rest_path.is_empty() || dst_rest.is_empty() {
                 ax_err!(PermissionDenied) // cannot rename mount points
+            } else if !Arc::ptr_eq(&fs, &dst_fs) {
+                ax_err!(Unsupported, "cannot rename across filesystems")
             } else {
-                fs.root_dir().rename(rest_path, dst_path)
+                fs.root_dir().rename(rest_path, &dst_rest)
+            }
+        })
+    }
+
+    fn link(&self, path: &str, node: VfsNodeRef) -> VfsResult {
+        self.lookup_mounted_fs(path, |fs, rest_path| {
+            if rest_path.is_empty() {
+                ax_err!(AlreadyExists)
+            } else {
+                fs.root_dir().link(rest_path, node)
             }
         })
     }
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
+pub(crate) fn init_rootfs(device: Arc<BlockDevice>) {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
-            let main_fs = fs::myfs::new_myfs(disk);
-        } else if #[cfg(feature = "fatfs")] {
-            static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
-            FAT_FS.init_by(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
-            FAT_FS.init();
-            let main_fs = FAT_FS.clone();
-        } else if #[cfg(feature = "ext4fs")] {
-            static EXT4_FS: LazyInit<Arc<fs::ext4fs::Ext4FileSystem>> = LazyInit::new();
-            EXT4_FS.init_by(Arc::new(fs::ext4fs::Ext4FileSystem::new(disk)));
-            let main_fs = EXT4_FS.clone();
-        } else if #[cfg(feature = "ext4_rs")] {
-            static EXT4_FS: LazyInit<Arc<fs::ext4::Ext4FileSystem>> = LazyInit::new();
-            EXT4_FS.init_by(Arc::new(fs::ext4::Ext4FileSystem::new(disk)));
-            let main_fs = EXT4_FS.clone();
+            let main_fs = fs::myfs::new_myfs(crate::dev::Disk::new(device.clone()));
+        } else if #[cfg(all(feature = "fatfs", feature = "use-ramdisk"))] { // format the ramdisk
+            let main_fs = fs::fatfs::FatFileSystem::new(crate::dev::Disk::new(device.clone()));
+            let main_fs = main_fs.into_vfs();
+        } else {
+            let main_fs = mounts::open_disk_fs(&device).expect("no filesystem on the root device");
         }
     }
+    info!("  root filesystem on {}", device.name());
 
-    let mut root_dir = RootDirectory::new(main_fs);
+    let root_dir = RootDirectory::new(main_fs);
 
     #[cfg(feature = "devfs")]
     root_dir
-        .mount("/dev", mounts::devfs())
+        .mount("/dev", mounts::devfs(), None)
         .expect("failed to mount devfs at /dev");
 
     #[cfg(feature = "ramfs")]
     root_dir
-        .mount("/tmp", mounts::ramfs())
+        .mount("/tmp", mounts::ramfs("").unwrap(), None)
         .expect("failed to mount ramfs at /tmp");
 
     #[cfg(feature = "ramfs")]
     root_dir
-        .mount("/var", mounts::ramfs())
+        .mount("/var", mounts::ramfs("").unwrap(), None)
         .expect("failed to mount ramfs at /tmp");
 
     // Mount another ramfs as procfs
     #[cfg(feature = "procfs")]
     root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
+        .mount("/proc", mounts::procfs().unwrap(), None)
         .expect("fail to mount procfs at /proc");
 
     // Mount another ramfs as sysfs
     #[cfg(feature = "sysfs")]
     root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
+        .mount("/sys", mounts::sysfs().unwrap(), None)
         .expect("fail to mount sysfs at /sys");
 
     ROOT_DIR.init_by(Arc::new(root_dir));
@@ -211,6 +384,57 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     *CURRENT_DIR_PATH.lock() = "/".into();
 }
 
+pub(crate) fn mount(source: &str, target: &str, fstype: &str, options: &str) -> AxResult {
+    let target = absolute_path(target)?;
+    let (fs, device) = mounts::new_fs(source, fstype, options)?;
+    let res = ROOT_DIR.mount(&target, fs, device.clone());
+    if let (Err(_), Some(device)) = (&res, device) {
+        device.release();
+    }
+    res
+}
+
+pub(crate) fn umount(target: &str, force: bool, detach: bool) -> AxResult {
+    let target = absolute_path(target)?;
+    ROOT_DIR.umount(&target, force, detach)?;
+    notify::notify_unmount(&target);
+    Ok(())
+}
+
+pub(crate) fn remount(target: &str, options: &str) -> AxResult {
+    let fs = ROOT_DIR.mounted_fs(&absolute_path(target)?)?;
+    fs.remount(options)
+}
+
+pub(crate) fn statfs(path: &str) -> AxResult<FileSystemInfo> {
+    let path = absolute_path(path)?;
+    lookup(None, &path)?;
+    ROOT_DIR.lookup_mounted_fs(&path, |fs, _| fs.statfs())
+}
+
+/// The mounted filesystem that a file opened at `path` relative to `dir` is
+/// in, `None` for the root filesystem. `dir_mount` is the one `dir` is in.
+///
+/// The file keeps the filesystem busy until it is closed.
+pub(crate) fn mount_of(
+    dir: Option<&VfsNodeRef>,
+    dir_mount: Option<&Arc<Mounted>>,
+    path: &str,
+) -> Option<Arc<Mounted>> {
+    let path = match dir {
+        _ if path.starts_with('/') => String::from(path),
+        None => CURRENT_DIR_PATH.lock().clone() + path,
+        // only the root directory looks up the paths across mount points
+        Some(dir) if is_root(dir) => String::from("/") + path,
+        Some(_) => return dir_mount.cloned(),
+    };
+    ROOT_DIR.mount_of(&path)
+}
+
+fn is_root(node: &VfsNodeRef) -> bool {
+    Arc::as_ptr(node) as *const u8 == Arc::as_ptr(&*ROOT_DIR) as *const u8
+}
+
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
     if path.starts_with('/') {
         ROOT_DIR.clone()
@@ -219,6 +443,12 @@ fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
     }
 }
 
+/// Whether the absolute path of `path` is known, to report changes on it.
+/// It's not for paths relative to a directory other than the current one.
+fn is_known_path(dir: Option<&VfsNodeRef>, path: &str) -> bool {
+    dir.is_none() || path.starts_with('/')
+}
+
 pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
     if path.starts_with('/') {
         Ok(axfs_vfs::path::canonicalize(path))
@@ -248,17 +478,42 @@ pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsN
     }
     let parent = parent_node_of(dir, path);
     parent.create(path, VfsNodeType::File)?;
+    if is_known_path(dir, path) {
+        notify::notify_create(path, false);
+    }
     parent.lookup(path)
 }
 
 pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     match lookup(dir, path) {
         Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+        Err(AxError::NotFound) => {
+            parent_node_of(dir, path).create(path, VfsNodeType::Dir)?;
+            if is_known_path(dir, path) {
+                notify::notify_create(path, true);
+            }
+            Ok(())
+        }
         Err(e) => Err(e),
     }
 }
 
+pub(crate) fn link(old: &str, new: &str) -> AxResult {
+    let node = lookup(None, old)?;
+    if new.ends_with('/') {
+        return ax_err!(NotADirectory);
+    }
+    parent_node_of(None, new).link(new, node)?;
+    notify::notify_create(new, false);
+    Ok(())
+}
+
+pub(crate) fn set_times(path: &str, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+    lookup(None, path)?.set_times(atime, mtime)?;
+    notify::notify(path, notify::WatchMask::ATTRIB);
+    Ok(())
+}
+
 pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     let node = lookup(dir, path)?;
     let attr = node.get_attr()?;
@@ -267,7 +522,11 @@ pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        parent_node_of(dir, path).remove(path)?;
+        if is_known_path(dir, path) {
+            notify::notify_delete(path, false);
+        }
+        Ok(())
     }
 }
 
@@ -296,7 +555,11 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        parent_node_of(dir, path).remove(path)?;
+        if is_known_path(dir, path) {
+            notify::notify_delete(path, true);
+        }
+        Ok(())
     }
 }
 
@@ -312,6 +575,7 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     if abs_path == "/" {
         *CURRENT_DIR.lock() = ROOT_DIR.clone();
         *CURRENT_DIR_PATH.lock() = "/".into();
+        *CURRENT_DIR_MOUNT.lock() = None;
         return Ok(());
     }
 
@@ -322,6 +586,7 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     } else if !attr.perm().owner_executable() {
         ax_err!(PermissionDenied)
     } else {
+        *CURRENT_DIR_MOUNT.lock() = ROOT_DIR.mount_of(&abs_path);
         *CURRENT_DIR.lock() = node;
         *CURRENT_DIR_PATH.lock() = abs_path;
         Ok(())
@@ -333,5 +598,8 @@ pub(crate) fn rename(old: &str, new: &str) -> AxResult {
         warn!("dst file already exist, now remove it");
         remove_file(None, new)?;
     }
-    parent_node_of(None, old).rename(old, new)
+    let is_dir = lookup(None, old)?.get_attr()?.is_dir();
+    parent_node_of(None, old).rename(old, new)?;
+    notify::notify_move(old, new, is_dir);
+    Ok(())
 }
@@ -0,0 +1,160 @@
+//! Extended attributes and POSIX ACLs.
+//!
+//! The names of extended attributes are in namespaces, given by the prefix:
+//! `user.`, `trusted.`, `security.` and `system.`. Who may access which
+//! namespace is up to the caller, e.g. only privileged processes may see
+//! `trusted.` attributes.
+//!
+//! The `system.` namespace only holds the POSIX ACLs, in the attributes
+//! [`ACL_ACCESS`] and [`ACL_DEFAULT`]. They are checked and kept in sync with
+//! the permission bits here, so filesystems store them as any other
+//! attribute.
+
+use alloc::{string::String, vec::Vec};
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::{VfsNodePerm, VfsNodeRef};
+
+pub use axfs_vfs::acl::{AclEntry, AclTag, PosixAcl, ACL_ACCESS, ACL_DEFAULT};
+pub use axfs_vfs::XattrFlags;
+
+/// The maximum length of the name of an extended attribute.
+pub const XATTR_NAME_MAX: usize = 255;
+/// The maximum size of the value of an extended attribute.
+pub const XATTR_SIZE_MAX: usize = 65536;
+/// The maximum size of the list of the names of extended attributes.
+pub const XATTR_LIST_MAX: usize = 65536;
+
+/// The namespace of an extended attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    /// `user.`, for any data of the owner of a regular file or directory.
+    User,
+    /// `trusted.`, only for privileged processes.
+    Trusted,
+    /// `security.`, e.g. `security.capability` and `security.selinux`.
+    Security,
+    /// `system.`, for the POSIX ACLs.
+    System,
+}
+
+impl Namespace {
+    /// Returns the namespace of the attribute `name`.
+    ///
+    /// Returns [`InvalidInput`](AxError::InvalidInput) if the name is empty or
+    /// too long, and [`Unsupported`](AxError::Unsupported) if it's not in a
+    /// known namespace.
+    pub fn of(name: &str) -> AxResult<Self> {
+        if name.is_empty() || name.len() > XATTR_NAME_MAX {
+            return ax_err!(InvalidInput, "invalid xattr name");
+        }
+        let namespace = match name.split_once('.') {
+            Some(("user", _)) => Self::User,
+            Some(("trusted", _)) => Self::Trusted,
+            Some(("security", _)) => Self::Security,
+            Some(("system", _)) if name == ACL_ACCESS || name == ACL_DEFAULT => Self::System,
+            _ => return ax_err!(Unsupported, "unknown xattr namespace"),
+        };
+        Ok(namespace)
+    }
+}
+
+/// Returns the value of the extended attribute `name` of the node.
+pub fn get_xattr(node: &VfsNodeRef, name: &str) -> AxResult<Vec<u8>> {
+    Namespace::of(name)?;
+    node.get_xattr(name)
+}
+
+/// Sets the extended attribute `name` of the node.
+///
+/// An access ACL also changes the permission bits, and is not kept if the
+/// permission bits can hold it. A default ACL can only be set on a directory,
+/// where an empty value removes it.
+pub fn set_xattr(node: &VfsNodeRef, name: &str, value: &[u8], flags: XattrFlags) -> AxResult {
+    Namespace::of(name)?;
+    if value.len() > XATTR_SIZE_MAX {
+        return ax_err!(InvalidInput, "xattr value too large");
+    }
+    match name {
+        ACL_ACCESS => {
+            let acl = PosixAcl::parse(value)?;
+            let attr = node.get_attr()?;
+            if acl.is_minimal() {
+                match node.remove_xattr(name) {
+                    Ok(()) | Err(AxError::NotFound) => {}
+                    Err(e) => return Err(e),
+                }
+            } else {
+                node.set_xattr(name, &acl.to_bytes(), flags)?;
+            }
+            node.set_mode(acl.mode(attr.perm()))
+        }
+        ACL_DEFAULT => {
+            if !node.get_attr()?.is_dir() {
+                return ax_err!(PermissionDenied, "default ACL on a non-directory");
+            }
+            if value.is_empty() {
+                return match node.remove_xattr(name) {
+                    Err(AxError::NotFound) => Ok(()),
+                    res => res,
+                };
+            }
+            node.set_xattr(name, &PosixAcl::parse(value)?.to_bytes(), flags)
+        }
+        _ => node.set_xattr(name, value, flags),
+    }
+}
+
+/// Returns the names of the extended attributes of the node.
+pub fn list_xattr(node: &VfsNodeRef) -> AxResult<Vec<String>> {
+    node.list_xattr()
+}
+
+/// Removes the extended attribute `name` of the node. Removing the access
+/// ACL leaves the permission bits unchanged.
+pub fn remove_xattr(node: &VfsNodeRef, name: &str) -> AxResult {
+    Namespace::of(name)?;
+    node.remove_xattr(name)
+}
+
+/// Returns the access ACL of the node, or `None` if it has none or the
+/// filesystem does not support extended attributes.
+pub fn access_acl(node: &VfsNodeRef) -> Option<PosixAcl> {
+    let value = node.get_xattr(ACL_ACCESS).ok()?;
+    PosixAcl::parse(&value).ok()
+}
+
+/// Changes the permission bits of the node, and its access ACL with them.
+pub fn chmod(node: &VfsNodeRef, perm: VfsNodePerm) -> AxResult {
+    node.set_mode(perm)?;
+    if let Some(mut acl) = access_acl(node) {
+        acl.chmod(perm);
+        node.set_xattr(ACL_ACCESS, &acl.to_bytes(), XattrFlags::REPLACE)?;
+    }
+    Ok(())
+}
+
+/// Gives the default ACL of the directory `dir` to the node just created in
+/// it with the permission bits `perm`.
+///
+/// The node gets an access ACL from the default ACL restricted by `perm`,
+/// and a directory also inherits the default ACL. Returns `false` if `dir`
+/// has no default ACL, then the umask should apply to `perm` instead.
+pub fn inherit_acl(dir: &VfsNodeRef, node: &VfsNodeRef, perm: VfsNodePerm) -> AxResult<bool> {
+    let Some(default) = dir
+        .get_xattr(ACL_DEFAULT)
+        .ok()
+        .and_then(|value| PosixAcl::parse(&value).ok())
+    else {
+        return Ok(false);
+    };
+    if node.get_attr()?.is_dir() {
+        node.set_xattr(ACL_DEFAULT, &default.to_bytes(), XattrFlags::empty())?;
+    }
+    let mut acl = default;
+    let perm = acl.create(perm);
+    if !acl.is_minimal() {
+        node.set_xattr(ACL_ACCESS, &acl.to_bytes(), XattrFlags::empty())?;
+    }
+    node.set_mode(perm)?;
+    Ok(true)
+}
@@ -2,7 +2,7 @@ use axfs::api as fs;
 use axio as io;
 
 use fs::{File, FileType, OpenOptions};
-use io::{prelude::*, Error, Result};
+use io::{prelude::*, Error, Result, SeekFrom};
 
 macro_rules! assert_err {
     ($expr: expr) => {
@@ -93,6 +93,18 @@ fn test_file_permission() -> Result<()> {
     assert_err!(fs::read_to_string("/dev"), IsADirectory);
     assert_err!(fs::write(".", "test"), IsADirectory);
 
+    // owner and mode are kept by the ramfs in /tmp
+    let fname = "/tmp/perm.txt";
+    fs::write(fname, "test")?;
+    let node = fs::lookup(fname)?;
+    assert_eq!(fs::metadata(fname)?.permissions().mode(), 0o666);
+    node.set_mode(fs::Permissions::from_bits_truncate(0o4750))?;
+    node.set_owner(1000, 100)?;
+    let attr = node.get_attr()?;
+    assert_eq!(attr.perm().mode(), 0o4750);
+    assert_eq!((attr.uid(), attr.gid()), (1000, 100));
+    fs::remove_file(fname)?;
+
     println!("test_file_permisson() OK!");
     Ok(())
 }
@@ -252,6 +264,370 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+fn test_mount() -> Result<()> {
+    println!("test mount and umount:");
+    let none = fs::UmountFlags::empty();
+
+    // the root disk is in /dev and can't be mounted again
+    assert!(fs::metadata("/dev/vda")?.file_type().is_block_device());
+    assert_err!(fs::mount("/dev/vda", "/mnt", "auto", ""), ResourceBusy);
+    assert_err!(fs::mount("/dev/vdz", "/mnt", "auto", ""), NotFound);
+    assert_err!(fs::mount("", "/mnt", "nfs", ""), Unsupported);
+    let filesystems = fs::read_to_string("/proc/filesystems")?;
+    assert!(filesystems.contains("nodev\ttmpfs\n"));
+
+    // nested mount points
+    assert_eq!(fs::mount("", "/mnt", "tmpfs", ""), Ok(()));
+    assert_eq!(fs::write("/mnt/a.txt", "outer"), Ok(()));
+    assert_eq!(fs::mount("", "//mnt/./inner/", "tmpfs", ""), Ok(()));
+    assert_eq!(fs::write("/mnt/inner/a.txt", "inner"), Ok(()));
+    assert_eq!(fs::read_to_string("/mnt/a.txt")?, "outer");
+    assert_eq!(fs::read_to_string("/mnt//./inner/a.txt")?, "inner");
+    assert_err!(fs::mount("", "/mnt/inner", "tmpfs", ""), ResourceBusy);
+    assert_err!(fs::remove_dir("/mnt/inner"), PermissionDenied);
+
+    assert_err!(fs::umount("/mnt", none), ResourceBusy);
+    assert_eq!(fs::umount("/mnt/inner", none), Ok(()));
+    assert_err!(fs::metadata("/mnt/inner/a.txt"), NotFound);
+    assert_eq!(fs::read_to_string("/mnt/a.txt")?, "outer");
+
+    // MNT_DETACH unmounts the children together
+    assert_eq!(fs::mount("", "/mnt/inner", "tmpfs", ""), Ok(()));
+    assert_eq!(fs::umount("/mnt", fs::UmountFlags::MNT_DETACH), Ok(()));
+    assert_err!(fs::metadata("/mnt/a.txt"), NotFound);
+    assert_err!(fs::umount("/mnt", none), InvalidInput);
+    assert_err!(fs::umount("/", none), InvalidInput);
+
+    // busy while a file is open on it, unless unmounted lazily
+    assert_eq!(fs::mount("", "/mnt", "tmpfs", ""), Ok(()));
+    let mut file = File::create("/mnt/b.txt")?;
+    assert_err!(fs::umount("/mnt", none), ResourceBusy);
+    assert_eq!(fs::umount("/mnt", fs::UmountFlags::MNT_DETACH), Ok(()));
+    assert_err!(fs::metadata("/mnt/b.txt"), NotFound);
+    assert_eq!(file.write(b"detached")?, 8);
+    drop(file);
+
+    // busy while it is the current directory
+    assert_eq!(fs::mount("", "/mnt", "tmpfs", ""), Ok(()));
+    fs::set_current_dir("/mnt")?;
+    assert_err!(fs::umount("/mnt", none), ResourceBusy);
+    fs::set_current_dir("/")?;
+    assert_eq!(fs::umount("/mnt", none), Ok(()));
+    assert_eq!(fs::remove_dir("/mnt"), Ok(()));
+
+    println!("test_mount() OK!");
+    Ok(())
+}
+
+fn test_tmpfs() -> Result<()> {
+    println!("test tmpfs:");
+    let none = fs::UmountFlags::empty();
+
+    assert_err!(fs::mount("", "/dev/shm", "tmpfs", "size=1x"), InvalidInput);
+    assert_err!(
+        fs::mount("", "/dev/shm", "tmpfs", "huge=always"),
+        InvalidInput
+    );
+    let options = "size=8k,nr_inodes=3,mode=1777";
+    assert_eq!(fs::mount("", "/dev/shm", "tmpfs", options), Ok(()));
+    assert_eq!(fs::metadata("/dev/shm")?.permissions().mode(), 0o1777);
+    let stat = fs::statfs("/dev/shm")?;
+    assert_eq!(stat.fs_type, 0x01021994);
+    assert_eq!(
+        (stat.block_size, stat.blocks, stat.blocks_free),
+        (4096, 2, 2)
+    );
+    assert_eq!((stat.files, stat.files_free), (3, 2));
+
+    // the size and the number of nodes are limited
+    let mut file = File::create("/dev/shm/a")?;
+    assert_eq!(file.write(&[1; 10000])?, 8192);
+    assert_err!(file.write(&[1]), StorageFull);
+    drop(file);
+    assert_eq!(fs::statfs("/dev/shm/a")?.blocks_free, 0);
+    fs::create_dir("/dev/shm/dir")?;
+    assert_err!(fs::write("/dev/shm/b", "b"), StorageFull);
+
+    // a hard link keeps the data after the other name is removed
+    fs::hard_link("/dev/shm/a", "/dev/shm/dir/b")?;
+    assert_eq!(fs::lookup("/dev/shm/a")?.get_attr()?.nlink(), 2);
+    fs::remove_file("/dev/shm/a")?;
+    assert_eq!(fs::metadata("/dev/shm/dir/b")?.len(), 8192);
+    assert_err!(fs::hard_link("/dev/shm/dir/b", "/tmp/b"), Unsupported);
+    assert_err!(
+        fs::hard_link("/dev/shm/dir", "/dev/shm/c"),
+        PermissionDenied
+    );
+
+    // sparse files take space only for the written pages
+    fs::remove_file("/dev/shm/dir/b")?;
+    let mut file = File::create("/dev/shm/dir/sparse")?;
+    file.seek(SeekFrom::Start(1 << 20))?;
+    assert_eq!(file.write(b"end")?, 3);
+    assert_eq!(file.metadata()?.len(), (1 << 20) + 3);
+    assert_eq!(fs::statfs("/dev/shm")?.blocks_free, 1);
+    drop(file);
+
+    // the size can be changed, but not below what is in use
+    assert_err!(fs::remount("/dev/shm", "nr_inodes=2"), InvalidInput);
+    fs::remount("/dev/shm", "size=1m")?;
+    assert_eq!(fs::statfs("/dev/shm")?.blocks_free, 255);
+    assert_err!(fs::remount("/dev/shm/dir", "size=1m"), InvalidInput);
+    assert_eq!(fs::umount("/dev/shm", none), Ok(()));
+
+    // replace the tmpfs on /tmp by a limited one
+    assert_eq!(fs::umount("/tmp", none), Ok(()));
+    assert_eq!(fs::mount("", "/tmp", "tmpfs", "size=1m"), Ok(()));
+    assert_eq!(fs::statfs("/tmp")?.blocks, 256);
+    fs::write("/tmp/a.txt", "hello")?;
+    fs::rename("/tmp/a.txt", "/tmp/b.txt")?;
+    assert_eq!(fs::read_to_string("/tmp/b.txt")?, "hello");
+    fs::remove_file("/tmp/b.txt")?;
+
+    println!("test_tmpfs() OK!");
+    Ok(())
+}
+
+fn test_lock() -> Result<()> {
+    use axfs::lock::{self, LockConflict::*, LockOwner, LockType, RecordLock};
+    println!("test file locks:");
+    let path = "/tmp/lock.txt";
+    fs::write(path, "lock")?;
+    let key = lock::file_key(path)?;
+    let (a, b) = (LockOwner::Process(1), LockOwner::Process(2));
+    let (f, g) = (LockOwner::File(0x1000), LockOwner::File(0x2000));
+    let range = |owner, ty, start, end| RecordLock {
+        owner,
+        ty,
+        start,
+        end,
+    };
+
+    // flock: shared locks coexist, an exclusive one conflicts
+    assert_eq!(lock::flock(&key, f, Some(LockType::Read)), Ok(()));
+    assert_eq!(lock::flock(&key, g, Some(LockType::Read)), Ok(()));
+    assert_eq!(lock::flock(&key, g, Some(LockType::Write)), Err(WouldBlock));
+    assert_eq!(lock::flock(&key, f, None), Ok(()));
+    assert_eq!(lock::flock(&key, g, Some(LockType::Write)), Ok(()));
+    lock::release_owner(g);
+
+    // record locks are split when part of them is unlocked
+    assert_eq!(
+        lock::set_record(&key, range(a, LockType::Write, 0, 100), false),
+        Ok(())
+    );
+    lock::unlock_record(&key, a, 40, 60);
+    assert_eq!(
+        lock::set_record(&key, range(b, LockType::Write, 40, 60), false),
+        Ok(())
+    );
+    let held = lock::test_record(&key, &range(b, LockType::Read, 0, 10));
+    assert_eq!(held, Some(range(a, LockType::Write, 0, 40)));
+    assert_eq!(
+        lock::test_record(&key, &range(a, LockType::Read, 0, 10)),
+        None
+    );
+    // OFD locks conflict with POSIX locks
+    let ofd = range(f, LockType::Read, 90, u64::MAX);
+    assert_eq!(lock::set_record(&key, ofd, false), Err(WouldBlock));
+
+    // a waits for b, so b waiting for a would deadlock
+    assert_eq!(
+        lock::set_record(&key, range(a, LockType::Write, 50, 51), true),
+        Err(WouldBlock)
+    );
+    assert_eq!(
+        lock::set_record(&key, range(b, LockType::Read, 10, 20), true),
+        Err(Deadlock)
+    );
+    lock::cancel_wait(a);
+    assert_eq!(
+        lock::set_record(&key, range(b, LockType::Read, 10, 20), true),
+        Err(WouldBlock)
+    );
+    lock::cancel_wait(b);
+
+    // closing any descriptor drops the locks of the process on the file
+    lock::release(&key, a);
+    assert_eq!(
+        lock::set_record(&key, range(b, LockType::Write, 0, u64::MAX), false),
+        Ok(())
+    );
+    lock::release_owner(b);
+    assert_eq!(lock::set_record(&key, ofd, false), Ok(()));
+    lock::release_owner(f);
+    assert_eq!(
+        lock::test_record(&key, &range(a, LockType::Write, 0, u64::MAX)),
+        None
+    );
+
+    // a file is the same through its hard links and after renames
+    fs::hard_link(path, "/tmp/lock2.txt")?;
+    assert_eq!(lock::file_key("/tmp/lock2.txt")?, key);
+    fs::rename("/tmp/lock2.txt", "/tmp/lock3.txt")?;
+    assert_eq!(lock::file_key("/tmp/lock3.txt")?, key);
+    assert_ne!(lock::file_key("/tmp")?, key);
+    fs::remove_file("/tmp/lock3.txt")?;
+    fs::remove_file(path)?;
+
+    println!("test_lock() OK!");
+    Ok(())
+}
+
+fn test_notify() -> Result<()> {
+    use axfs::notify::{Inotify, WatchMask};
+    println!("test file change notification:");
+    let inotify = Inotify::new();
+    let next = || {
+        let event = inotify.pop_event_if(|_| true)?;
+        Some((event.wd, event.mask, event.name))
+    };
+
+    fs::create_dir("/tmp/watched")?;
+    let dir = inotify.add_watch("/tmp/watched", WatchMask::ALL_EVENTS)?;
+    let mask = WatchMask::OPEN | WatchMask::MASK_CREATE;
+    assert_err!(inotify.add_watch("/tmp/watched", mask), AlreadyExists);
+    assert_err!(inotify.add_watch("/tmp/none", WatchMask::OPEN), NotFound);
+
+    // events of children are reported to the directory with their names
+    fs::write("/tmp/watched/a.txt", "hello")?;
+    let name = || String::from("a.txt");
+    assert_eq!(next(), Some((dir, WatchMask::CREATE, name())));
+    assert_eq!(next(), Some((dir, WatchMask::OPEN, name())));
+    assert_eq!(next(), Some((dir, WatchMask::MODIFY, name())));
+    assert_eq!(next(), Some((dir, WatchMask::CLOSE_WRITE, name())));
+    assert_eq!(next(), None);
+
+    // a rename is a pair of events with the same cookie, the watch follows
+    let mask = WatchMask::MODIFY | WatchMask::MOVE_SELF | WatchMask::DELETE_SELF;
+    let file = inotify.add_watch("/tmp/watched/a.txt", mask)?;
+    fs::rename("/tmp/watched/a.txt", "/tmp/watched/b.txt")?;
+    let from = inotify.pop_event_if(|_| true).unwrap();
+    let to = inotify.pop_event_if(|_| true).unwrap();
+    assert_eq!(
+        (from.mask, from.name.as_str()),
+        (WatchMask::MOVED_FROM, "a.txt")
+    );
+    assert_eq!((to.mask, to.name.as_str()), (WatchMask::MOVED_TO, "b.txt"));
+    assert!(from.cookie != 0 && from.cookie == to.cookie);
+    assert_eq!(next(), Some((file, WatchMask::MOVE_SELF, String::new())));
+    let mut f = File::options().write(true).open("/tmp/watched/b.txt")?;
+    f.write_all(b"x")?;
+    let name = || String::from("b.txt");
+    assert_eq!(next(), Some((dir, WatchMask::OPEN, name())));
+    assert_eq!(next(), Some((dir, WatchMask::MODIFY, name())));
+    assert_eq!(next(), Some((file, WatchMask::MODIFY, String::new())));
+    drop(f);
+    assert_eq!(next(), Some((dir, WatchMask::CLOSE_WRITE, name())));
+
+    // deleting a watched file removes its watch
+    fs::remove_file("/tmp/watched/b.txt")?;
+    assert_eq!(next(), Some((dir, WatchMask::DELETE, name())));
+    assert_eq!(next(), Some((file, WatchMask::DELETE_SELF, String::new())));
+    assert_eq!(next(), Some((file, WatchMask::IGNORED, String::new())));
+    assert_err!(inotify.rm_watch(file), InvalidInput);
+    inotify.rm_watch(dir)?;
+    assert_eq!(next(), Some((dir, WatchMask::IGNORED, String::new())));
+    fs::remove_dir("/tmp/watched")?;
+    assert!(!inotify.has_events());
+
+    println!("test_notify() OK!");
+    Ok(())
+}
+
+fn test_xattr() -> Result<()> {
+    use axfs::xattr::{self, PosixAcl, XattrFlags, ACL_ACCESS, ACL_DEFAULT};
+    println!("test extended attributes and ACLs:");
+    let acl = |entries: &[(u16, u16, u32)]| {
+        let mut data = vec![2, 0, 0, 0];
+        for (tag, perm, id) in entries {
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&perm.to_le_bytes());
+            data.extend_from_slice(&id.to_le_bytes());
+        }
+        data
+    };
+    let mode = |path| fs::metadata(path).map(|m| m.permissions().mode());
+
+    let path = "/tmp/xattr.txt";
+    fs::write(path, "hello")?;
+    fs::set_xattr(path, "user.comment", b"hi", XattrFlags::CREATE)?;
+    fs::set_xattr(path, "security.capability", &[1, 2], XattrFlags::empty())?;
+    assert_eq!(fs::get_xattr(path, "user.comment")?, b"hi");
+    assert_err!(fs::get_xattr(path, "user.none"), NotFound);
+    assert_err!(
+        fs::set_xattr(path, "user.comment", b"", XattrFlags::CREATE),
+        AlreadyExists
+    );
+    assert_err!(
+        fs::set_xattr(path, "foo.bar", b"", XattrFlags::empty()),
+        Unsupported
+    );
+    assert_err!(
+        fs::set_xattr(path, "system.foo", b"", XattrFlags::empty()),
+        Unsupported
+    );
+    assert_err!(
+        fs::set_xattr(path, "", b"", XattrFlags::empty()),
+        InvalidInput
+    );
+    fs::remove_xattr(path, "user.comment")?;
+    assert_eq!(fs::list_xattr(path)?, ["security.capability"]);
+
+    // an access ACL sets the permission bits, the group bits are the mask
+    let user_1000 = acl(&[(1, 6, 0), (2, 6, 1000), (4, 4, 0), (16, 4, 0), (32, 0, 0)]);
+    assert_err!(
+        fs::set_xattr(path, ACL_ACCESS, &user_1000[..12], XattrFlags::empty()),
+        InvalidInput
+    );
+    fs::set_xattr(path, ACL_ACCESS, &user_1000, XattrFlags::empty())?;
+    assert_eq!(mode(path)?, 0o640);
+    let node = fs::lookup(path)?;
+    let access = xattr::access_acl(&node).unwrap();
+    assert!(access.check(0, 0, 1000, |_| false, 4));
+    assert!(!access.check(0, 0, 1000, |_| false, 2));
+
+    // chmod changes the mask, a minimal ACL is only kept in the mode
+    xattr::chmod(&node, fs::Permissions::from_bits_truncate(0o660))?;
+    assert!(xattr::access_acl(&node)
+        .unwrap()
+        .check(0, 0, 1000, |_| false, 6));
+    let minimal = acl(&[(1, 7, 0), (4, 5, 0), (32, 4, 0)]);
+    fs::set_xattr(path, ACL_ACCESS, &minimal, XattrFlags::empty())?;
+    assert_eq!(mode(path)?, 0o754);
+    assert_err!(fs::get_xattr(path, ACL_ACCESS), NotFound);
+
+    // nodes created in a directory with a default ACL inherit it
+    assert_err!(
+        fs::set_xattr(path, ACL_DEFAULT, &user_1000, XattrFlags::empty()),
+        PermissionDenied
+    );
+    fs::create_dir("/tmp/acl")?;
+    fs::set_xattr("/tmp/acl", ACL_DEFAULT, &user_1000, XattrFlags::empty())?;
+    let dir = fs::lookup("/tmp/acl")?;
+    fs::write("/tmp/acl/f", "")?;
+    let file = fs::lookup("/tmp/acl/f")?;
+    assert!(xattr::inherit_acl(
+        &dir,
+        &file,
+        fs::Permissions::from_bits_truncate(0o666)
+    )?);
+    assert_eq!(mode("/tmp/acl/f")?, 0o640);
+    let inherited = PosixAcl::parse(&fs::get_xattr("/tmp/acl/f", ACL_ACCESS)?)?;
+    assert_eq!(inherited, PosixAcl::parse(&user_1000)?);
+    assert!(!xattr::inherit_acl(
+        &file,
+        &dir,
+        fs::Permissions::from_bits_truncate(0o777)
+    )?);
+
+    fs::remove_file("/tmp/acl/f")?;
+    fs::remove_dir("/tmp/acl")?;
+    fs::remove_file(path)?;
+    println!("test_xattr() OK!");
+    Ok(())
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
@@ -259,4 +635,9 @@ pub fn test_all() {
     test_create_file_dir().expect("test_create_file_dir() failed");
     test_remove_file_dir().expect("test_remove_file_dir() failed");
     test_devfs_ramfs().expect("test_devfs_ramfs() failed");
+    test_mount().expect("test_mount() failed");
+    test_tmpfs().expect("test_tmpfs() failed");
+    test_lock().expect("test_lock() failed");
+    test_notify().expect("test_notify() failed");
+    test_xattr().expect("test_xattr() failed");
 }
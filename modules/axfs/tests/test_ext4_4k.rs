@@ -0,0 +1,28 @@
+#![cfg(all(feature = "ext4fs", not(feature = "myfs")))]
+
+mod test_common;
+
+use axdriver::AxDeviceContainer;
+use driver_block::ramdisk::RamDisk;
+
+const IMG_PATH: &str = "resources/ext4.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data).with_block_size(4096))
+}
+
+#[test]
+fn test_ext4_4k() {
+    println!("Testing ext4 with ramdisk of 4K logical blocks ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    test_common::test_all();
+    axfs::api::sync().expect("failed to sync the block devices");
+}
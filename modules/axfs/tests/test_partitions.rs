@@ -0,0 +1,99 @@
+#![cfg(all(feature = "ext4fs", feature = "fatfs", not(feature = "myfs")))]
+
+mod test_common;
+
+use axdriver::AxDeviceContainer;
+use axfs::api as fs;
+use axio::Error;
+use driver_block::ramdisk::RamDisk;
+
+const EXT4_IMG_PATH: &str = "resources/ext4.img";
+const FAT_IMG_PATH: &str = "resources/fat16.img";
+
+const SECTOR_SIZE: usize = 512;
+const DISK_SIGNATURE: u32 = 0x1234_abcd;
+
+/// Writes an MBR partition entry of `count` sectors from `start`.
+fn write_entry(sector: &mut [u8], slot: usize, ty: u8, start: usize, count: usize) {
+    let entry = &mut sector[446 + slot * 16..446 + (slot + 1) * 16];
+    entry[4] = ty;
+    entry[8..12].copy_from_slice(&(start as u32).to_le_bytes());
+    entry[12..16].copy_from_slice(&(count as u32).to_le_bytes());
+    sector[510..512].copy_from_slice(&[0x55, 0xaa]);
+}
+
+/// Makes a disk with an MBR of a boot partition without a filesystem, the
+/// ext4 root partition, and the FAT data partition as a logical partition.
+fn make_disk() -> std::io::Result<RamDisk> {
+    let dir = std::env::current_dir()?;
+    println!("Loading disk images from {:?} ...", dir.join("resources"));
+    let ext4 = std::fs::read(dir.join(EXT4_IMG_PATH))?;
+    let fat = std::fs::read(dir.join(FAT_IMG_PATH))?;
+
+    let (boot_start, boot_sectors) = (2048, 2048);
+    let root_start = boot_start + boot_sectors;
+    let root_sectors = ext4.len() / SECTOR_SIZE;
+    let ext_start = root_start + root_sectors;
+    let data_start = ext_start + 2048;
+    let data_sectors = fat.len() / SECTOR_SIZE;
+    let ext_sectors = data_start + data_sectors - ext_start;
+
+    let mut data = vec![0; (ext_start + ext_sectors) * SECTOR_SIZE];
+    let mbr = &mut data[..SECTOR_SIZE];
+    mbr[440..444].copy_from_slice(&DISK_SIGNATURE.to_le_bytes());
+    write_entry(mbr, 0, 0x83, boot_start, boot_sectors);
+    write_entry(mbr, 1, 0x83, root_start, root_sectors);
+    write_entry(mbr, 2, 0x05, ext_start, ext_sectors);
+    let ebr = &mut data[ext_start * SECTOR_SIZE..(ext_start + 1) * SECTOR_SIZE];
+    write_entry(ebr, 0, 0x0c, data_start - ext_start, data_sectors);
+    data[root_start * SECTOR_SIZE..][..ext4.len()].copy_from_slice(&ext4);
+    data[data_start * SECTOR_SIZE..][..fat.len()].copy_from_slice(&fat);
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+fn test_partitions() -> std::io::Result<()> {
+    println!("test partitions:");
+    for path in ["/dev/vda", "/dev/vda1", "/dev/vda2", "/dev/vda5"] {
+        assert!(fs::metadata(path)?.file_type().is_block_device());
+    }
+    assert_eq!(fs::metadata("/dev/vda1")?.len(), 2048 * SECTOR_SIZE as u64);
+    assert_eq!(fs::metadata("/dev/vda3").err(), Some(Error::NotFound));
+
+    // the root filesystem is on the first partition with one
+    let busy = Some(Error::ResourceBusy);
+    assert_eq!(fs::mount("/dev/vda2", "/mnt", "auto", "").err(), busy);
+    assert_eq!(
+        fs::mount("/dev/vda1", "/mnt", "auto", "").err(),
+        Some(Error::InvalidData)
+    );
+
+    // partitions can be mounted by PARTUUID
+    fs::mount("PARTUUID=1234ABCD-05", "/mnt", "auto", "")?;
+    assert_eq!(fs::read_to_string("/mnt/short.txt")?, "Rust is cool!\n");
+    assert_eq!(fs::mount("/dev/vda5", "/data", "vfat", "").err(), busy);
+    fs::write("/mnt/short.txt", "Rust is still cool!\n")?;
+    fs::umount("/mnt", fs::UmountFlags::empty())?;
+    fs::mount("/dev/vda5", "/mnt", "vfat", "")?;
+    assert_eq!(
+        fs::read_to_string("/mnt/short.txt")?,
+        "Rust is still cool!\n"
+    );
+    fs::umount("/mnt", fs::UmountFlags::empty())?;
+    fs::remove_dir("/mnt")?;
+
+    println!("test_partitions() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_mbr_partitions() {
+    println!("Testing partitions with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk images");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    test_common::test_all();
+    test_partitions().expect("test_partitions() failed");
+}
@@ -78,6 +78,9 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     for m in crate::platform::mem::platform_regions() {
         crate::platform::mem::idmap_kernel(m.paddr.as_usize());
     }
+    if let Some(seed) = of::rng_seed() {
+        crate::random::add_seed(seed);
+    }
     //crate::mem::clear_guest_mem();
     crate::platform::time::init_early();
     // disable low address access
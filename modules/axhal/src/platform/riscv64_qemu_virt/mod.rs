@@ -33,6 +33,9 @@ fn init_board_info(dtb: usize) {
         }
     };
     self::time::init_cpu_freq(freq as u64);
+    if let Some(seed) = of::rng_seed() {
+        crate::random::add_seed(seed);
+    }
 }
 
 unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
@@ -42,6 +42,7 @@ unsafe extern "C" fn rust_entry(magic: usize, _mbi: usize) {
         self::uart16550::init();
         self::dtables::init_primary();
         self::time::init_early();
+        crate::random::seed_from_cpu();
         rust_main(current_cpu_id(), 0);
     }
 }
@@ -0,0 +1,201 @@
+//! Kernel random number generator.
+//!
+//! It is a ChaCha20 based generator with fast key erasure: every request
+//! replaces the global key, so earlier outputs can not be recovered from the
+//! current state. The timings of interrupts are accumulated into an entropy
+//! pool, which is mixed into the key on the next request.
+//!
+//! The key starts from the seeds the platform finds at boot, e.g., the one the
+//! bootloader passes in the device tree, or the hardware generator of the CPU.
+//! Without them, the generator is not ready until enough interrupts arrive,
+//! see [`is_ready`].
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use spinlock::SpinNoIrq;
+
+use crate::time::current_ticks;
+
+/// `"expand 32-byte k"`
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+/// The interrupts to wait for if there is no seed, assuming that each arrival
+/// time has about two bits unpredictable.
+const READY_SAMPLES: usize = 64;
+
+/// A seed shorter than this is not enough to make the generator ready.
+const MIN_SEED_LEN: usize = 16;
+
+static ENTROPY_POOL: AtomicU64 = AtomicU64::new(0);
+
+/// The interrupts mixed into the entropy pool.
+static SAMPLES: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the key is seeded from a random source.
+static SEEDED: AtomicBool = AtomicBool::new(false);
+
+static RNG: SpinNoIrq<ChaChaRng> = SpinNoIrq::new(ChaChaRng::new());
+
+struct ChaChaRng {
+    key: [u32; 8],
+    generation: u64,
+}
+
+impl ChaChaRng {
+    const fn new() -> Self {
+        Self {
+            key: [0; 8],
+            generation: 0,
+        }
+    }
+
+    /// Mixes the entropy pool into the key, and derives an independent key for
+    /// one request.
+    fn fork(&mut self) -> [u32; 8] {
+        let pool = ENTROPY_POOL.swap(0, Ordering::Relaxed);
+        let ticks = current_ticks();
+        self.key[0] ^= pool as u32;
+        self.key[1] ^= (pool >> 32) as u32;
+        self.key[2] ^= ticks as u32;
+        self.key[3] ^= (ticks >> 32) as u32;
+
+        let block = chacha20_block(&self.key, 0, self.generation);
+        self.generation = self.generation.wrapping_add(1);
+        self.key.copy_from_slice(&block[..8]);
+        let mut key = [0; 8];
+        key.copy_from_slice(&block[8..]);
+        key
+    }
+
+    /// Mixes a seed into the key, 32 bytes a time.
+    fn reseed(&mut self, seed: &[u8]) {
+        for chunk in seed.chunks(32) {
+            for (i, byte) in chunk.iter().enumerate() {
+                self.key[i / 4] ^= (*byte as u32) << (i % 4 * 8);
+            }
+            let block = chacha20_block(&self.key, 0, self.generation);
+            self.generation = self.generation.wrapping_add(1);
+            self.key.copy_from_slice(&block[..8]);
+        }
+    }
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64) -> [u32; 16] {
+    let mut state = [0; 16];
+    state[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = nonce as u32;
+    state[15] = (nonce >> 32) as u32;
+
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+    for (word, init) in x.iter_mut().zip(state) {
+        *word = word.wrapping_add(init);
+    }
+    x
+}
+
+/// Mixes a value with some unpredictability, e.g., the time an interrupt
+/// arrives, into the entropy pool.
+pub fn add_entropy(data: u64) {
+    let _ = ENTROPY_POOL.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |pool| {
+        Some(pool.rotate_left(13) ^ data)
+    });
+    SAMPLES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Mixes a seed from a random source, e.g., the bootloader or a hardware
+/// generator, into the key. A seed of at least 16 bytes makes the generator
+/// ready.
+pub fn add_seed(seed: &[u8]) {
+    RNG.lock().reseed(seed);
+    if seed.len() >= MIN_SEED_LEN {
+        SEEDED.store(true, Ordering::Release);
+    }
+}
+
+/// Seeds the key from the hardware generator of the CPU, if it has one.
+#[cfg(all(target_arch = "x86_64", platform_family = "x86-pc"))]
+pub(crate) fn seed_from_cpu() {
+    #[target_feature(enable = "rdrand")]
+    unsafe fn rdrand() -> Option<u64> {
+        let mut value = 0;
+        // a few retries as Intel recommends, it rarely runs out of entropy
+        for _ in 0..10 {
+            if core::arch::x86_64::_rdrand64_step(&mut value) == 1 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    let has_rdrand = raw_cpuid::CpuId::new()
+        .get_feature_info()
+        .map_or(false, |info| info.has_rdrand());
+    if !has_rdrand {
+        return;
+    }
+    let mut seed = [0; 32];
+    for chunk in seed.chunks_mut(8) {
+        // SAFETY: the CPU supports RDRAND.
+        match unsafe { rdrand() } {
+            Some(value) => chunk.copy_from_slice(&value.to_le_bytes()),
+            None => return,
+        }
+    }
+    add_seed(&seed);
+}
+
+/// Whether the outputs are unpredictable, i.e., the key is seeded from a
+/// random source or enough interrupts have been mixed in.
+///
+/// Without interrupts there is nothing more to wait for, so it is always
+/// ready then.
+pub fn is_ready() -> bool {
+    SEEDED.load(Ordering::Acquire)
+        || SAMPLES.load(Ordering::Relaxed) >= READY_SAMPLES
+        || cfg!(not(feature = "irq"))
+}
+
+/// Fills the buffer with random bytes.
+///
+/// The global state is only locked to derive a key for this request, so
+/// filling a large buffer does not block the other CPUs and interrupts.
+pub fn fill_bytes(buf: &mut [u8]) {
+    let key = RNG.lock().fork();
+    for (counter, chunk) in buf.chunks_mut(64).enumerate() {
+        let block = chacha20_block(&key, counter as u64, 0);
+        for (dst, word) in chunk.chunks_mut(4).zip(block) {
+            dst.copy_from_slice(&word.to_le_bytes()[..dst.len()]);
+        }
+    }
+}
+
+/// Returns a random `u64`.
+pub fn random_u64() -> u64 {
+    let mut buf = [0; 8];
+    fill_bytes(&mut buf);
+    u64::from_le_bytes(buf)
+}
@@ -23,6 +23,12 @@ pub struct MapArea {
     pub flags: MappingFlags,
     /// whether the area is backed by a file
     pub backend: Option<MemBackend>,
+    /// whether the changes are written back to the backend (`MAP_SHARED`)
+    pub shared: bool,
+    /// whether the pages of the area are locked in memory (`mlock`)
+    pub locked: bool,
+    /// whether the area should not be inherited by a child process (`MADV_DONTFORK`)
+    pub dont_fork: bool,
 }
 
 impl MapArea {
@@ -48,6 +54,9 @@ impl MapArea {
             vaddr: start,
             flags,
             backend,
+            shared: false,
+            locked: false,
+            dont_fork: false,
         }
     }
 
@@ -82,6 +91,9 @@ impl MapArea {
             vaddr: start,
             flags,
             backend,
+            shared: false,
+            locked: false,
+            dont_fork: false,
         })
     }
 
@@ -128,8 +140,16 @@ impl MapArea {
 
         debug!("page index {}", page_index);
 
+        self.load_page(page_index, page_table).is_ok()
+    }
+
+    /// Allocate a phys page for the page at `page_index`, fill it from the backend (or with 0) and
+    /// map it in the page table, replacing the page fault PTE.
+    fn load_page(&mut self, page_index: usize, page_table: &mut PageTable) -> AxResult<()> {
+        let vaddr = self.vaddr + page_index * PAGE_SIZE_4K;
+
         // Allocate new page
-        let mut page = PhysPage::alloc().expect("Error allocating new phys page for page fault");
+        let mut page = PhysPage::alloc()?;
 
         debug!(
             "new phys page virtual (offset) address {:?}",
@@ -156,16 +176,66 @@ impl MapArea {
         // Map newly allocated page in the page_table
         page_table
             .map_overwrite(
-                addr.align_down_4k(),
+                vaddr,
                 virt_to_phys(page.start_vaddr),
                 axhal::paging::PageSize::Size4K,
                 self.flags,
             )
             .expect("Map in page fault handler failed");
 
-        axhal::arch::flush_tlb(addr.align_down_4k().into());
+        axhal::arch::flush_tlb(vaddr.into());
         self.pages[page_index] = Some(page);
-        true
+        Ok(())
+    }
+
+    /// Allocate and load all the pages in [start, end) which have not been loaded yet.
+    ///
+    /// It is used by `mlock` and `MADV_WILLNEED` to fault the pages in ahead of time.
+    pub fn populate(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        page_table: &mut PageTable,
+    ) -> AxResult<()> {
+        for page_index in self.page_range(start, end) {
+            if self.pages[page_index].is_none() {
+                self.load_page(page_index, page_table)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Free the loaded pages in [start, end) and turn them back into page fault PTEs, so that the
+    /// next access will load them again from the backend (or fill them with 0). The pages of a
+    /// writable shared mapping of a writable file are written back first, like `msync`, while
+    /// the changes to a private mapping are dropped.
+    ///
+    /// It is used by `MADV_DONTNEED` and `MADV_FREE`. You need to flush TLB after this.
+    pub fn free_pages(&mut self, start: VirtAddr, end: VirtAddr, page_table: &mut PageTable) {
+        let write_back = self.shared && self.flags.contains(MappingFlags::WRITE);
+        for page_index in self.page_range(start, end) {
+            if write_back && self.pages[page_index].is_some() {
+                self.sync_page_with_backend(page_index);
+            }
+            if self.pages[page_index].take().is_some() {
+                let vaddr = self.vaddr + page_index * PAGE_SIZE_4K;
+                page_table.unmap(vaddr).unwrap();
+                page_table
+                    .map_fault(vaddr, PageSize::Size4K, self.flags)
+                    .unwrap();
+            }
+        }
+    }
+
+    /// The indexes of the pages of this area which overlap with [start, end).
+    fn page_range(&self, start: VirtAddr, end: VirtAddr) -> core::ops::Range<usize> {
+        let start = start.max(self.vaddr).align_down_4k();
+        let end = end.min(self.end_va()).align_up_4k();
+        if start >= end {
+            return 0..0;
+        }
+        (start.as_usize() - self.vaddr.as_usize()) / PAGE_SIZE_4K
+            ..(end.as_usize() - self.vaddr.as_usize()) / PAGE_SIZE_4K
     }
 
     /// Sync pages in index back to `self.backend` (if there is one).
@@ -254,6 +324,9 @@ impl MapArea {
 
                 backend
             }),
+            shared: self.shared,
+            locked: self.locked,
+            dont_fork: self.dont_fork,
         }
     }
 
@@ -296,6 +369,9 @@ impl MapArea {
 
                 backend
             }),
+            shared: self.shared,
+            locked: self.locked,
+            dont_fork: self.dont_fork,
         };
 
         let right = Self {
@@ -313,6 +389,9 @@ impl MapArea {
 
                 backend
             }),
+            shared: self.shared,
+            locked: self.locked,
+            dont_fork: self.dont_fork,
         };
 
         (mid, right)
@@ -358,6 +437,9 @@ impl MapArea {
 
                 backend
             }),
+            shared: self.shared,
+            locked: self.locked,
+            dont_fork: self.dont_fork,
         };
 
         // remove pages
@@ -433,14 +515,16 @@ impl MapArea {
     pub fn clone_alloc(&self, page_table: &mut PageTable) -> AxResult<Self> {
         // All the pages have been allocated. Allocate a contiguous area in phys memory.
         if self.allocated() {
-            MapArea::new_alloc(
+            let mut area = MapArea::new_alloc(
                 self.vaddr,
                 self.pages.len(),
                 self.flags,
                 Some(unsafe { self.as_slice() }),
                 self.backend.clone(),
                 page_table,
-            )
+            )?;
+            area.shared = self.shared;
+            Ok(area)
         } else {
             let pages: Vec<_> = self
                 .pages
@@ -484,6 +568,9 @@ impl MapArea {
                 vaddr: self.vaddr,
                 flags: self.flags,
                 backend: self.backend.clone(),
+                shared: self.shared,
+                locked: false,
+                dont_fork: self.dont_fork,
             })
         }
     }
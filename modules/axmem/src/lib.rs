@@ -8,10 +8,12 @@ use axerrno::{AxError, AxResult};
 pub use backend::MemBackend;
 
 extern crate alloc;
-use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use axalloc::KmemCache;
+use core::alloc::Layout;
 use core::sync::atomic::{AtomicI32, Ordering};
 use page_table_entry::GenericPTE;
-use shared::SharedMem;
+pub use shared::{SharedMem, SharedMemInfo, SharedMemPermInfo, SHM_DEST};
 use spinlock::SpinNoIrq;
 #[macro_use]
 extern crate log;
@@ -26,7 +28,7 @@ use axhal::{
 static SHMID: AtomicI32 = AtomicI32::new(1);
 
 /// This struct only hold SharedMem that are not IPC_PRIVATE. IPC_PRIVATE SharedMem will be stored
-/// in MemorySet::detached_mem.
+/// in MemorySet::private_mem.
 ///
 /// This is the only place we can query a SharedMem using its shmid.
 ///
@@ -36,13 +38,29 @@ pub static SHARED_MEMS: SpinNoIrq<BTreeMap<i32, Arc<SharedMem>>> = SpinNoIrq::ne
 /// The map from key to shmid. It's used to query shmid from key.
 pub static KEY_TO_SHMID: SpinNoIrq<BTreeMap<i32, i32>> = SpinNoIrq::new(BTreeMap::new());
 
+/// The object cache of the areas in the address spaces.
+static MAP_AREA_CACHE: KmemCache = KmemCache::new("vm_area_struct", Layout::new::<MapArea>());
+
+/// Boxes an area, allocated from its object cache.
+fn new_area_box(area: MapArea) -> Box<MapArea> {
+    MAP_AREA_CACHE.scope(|| Box::new(area))
+}
+
 /// PageTable + MemoryArea for a process (task)
 pub struct MemorySet {
     page_table: PageTable,
-    owned_mem: BTreeMap<usize, MapArea>,
+    /// The areas are boxed, so that they are allocated from their own object cache.
+    owned_mem: BTreeMap<usize, Box<MapArea>>,
 
     private_mem: BTreeMap<i32, Arc<SharedMem>>,
     attached_mem: Vec<(VirtAddr, MappingFlags, Arc<SharedMem>)>,
+
+    /// Whether the regions mapped in the future should be locked (`mlockall(MCL_FUTURE)`).
+    lock_future: bool,
+
+    /// Where the search for a free area starts when no hint is given. It is randomized on exec
+    /// if ASLR is enabled.
+    mmap_base: VirtAddr,
 }
 
 impl MemorySet {
@@ -58,6 +76,8 @@ impl MemorySet {
             owned_mem: BTreeMap::new(),
             private_mem: BTreeMap::new(),
             attached_mem: Vec::new(),
+            lock_future: false,
+            mmap_base: axconfig::USER_MEMORY_START.into(),
         }
     }
 
@@ -90,6 +110,8 @@ impl MemorySet {
             owned_mem: BTreeMap::new(),
             private_mem: BTreeMap::new(),
             attached_mem: Vec::new(),
+            lock_future: false,
+            mmap_base: axconfig::USER_MEMORY_START.into(),
         }
     }
 
@@ -117,7 +139,7 @@ impl MemorySet {
     ) {
         let num_pages = (size + PAGE_SIZE_4K - 1) / PAGE_SIZE_4K;
 
-        let area = match data {
+        let mut area = match data {
             Some(data) => MapArea::new_alloc(
                 vaddr,
                 num_pages,
@@ -148,8 +170,18 @@ impl MemorySet {
             flags
         );
 
+        if self.lock_future {
+            area.locked = true;
+            if let Err(err) = area.populate(area.vaddr, area.end_va(), &mut self.page_table) {
+                warn!("failed to populate locked area: {:?}", err);
+            }
+        }
+
         // self.owned_mem.insert(area.vaddr.into(), area);
-        assert!(self.owned_mem.insert(area.vaddr.into(), area).is_none());
+        assert!(self
+            .owned_mem
+            .insert(area.vaddr.into(), new_area_box(area))
+            .is_none());
     }
 
     /// Make [start, end) unmapped and dealloced. You need to flush TLB after this.
@@ -163,9 +195,9 @@ impl MemorySet {
         // We get all the overlapped areas out first.
 
         // UPDATE: draif_filter is an unstable feature, so we implement it manually.
-        let mut overlapped_area: Vec<(usize, MapArea)> = Vec::new();
+        let mut overlapped_area: Vec<(usize, Box<MapArea>)> = Vec::new();
 
-        let mut prev_area: BTreeMap<usize, MapArea> = BTreeMap::new();
+        let mut prev_area: BTreeMap<usize, Box<MapArea>> = BTreeMap::new();
 
         for _ in 0..self.owned_mem.len() {
             let (idx, area) = self.owned_mem.pop_first().unwrap();
@@ -197,7 +229,7 @@ impl MemorySet {
 
                 assert!(self
                     .owned_mem
-                    .insert(new_area.vaddr.into(), new_area)
+                    .insert(new_area.vaddr.into(), new_area_box(new_area))
                     .is_none());
                 assert!(self.owned_mem.insert(area.vaddr.into(), area).is_none());
             } else if start <= area.vaddr && area.vaddr < end {
@@ -226,10 +258,26 @@ impl MemorySet {
         }
     }
 
+    /// Set where the search for a free area starts when no hint is given.
+    pub fn set_mmap_base(&mut self, base: VirtAddr) {
+        self.mmap_base = base;
+    }
+
     /// Find a free area with given start virtual address and size. Return the start address of the area.
+    ///
+    /// Without a hint, the search starts from the mmap base. If nothing is found above the
+    /// starting point, the whole user address space is searched.
     pub fn find_free_area(&self, hint: VirtAddr, size: usize) -> Option<VirtAddr> {
-        let mut last_end = hint.max(axconfig::USER_MEMORY_START.into()).as_usize();
+        let start = if hint.as_usize() < axconfig::USER_MEMORY_START {
+            self.mmap_base
+        } else {
+            hint
+        };
+        self.find_free_area_from(start.as_usize(), size)
+            .or_else(|| self.find_free_area_from(axconfig::USER_MEMORY_START, size))
+    }
 
+    fn find_free_area_from(&self, start: usize, size: usize) -> Option<VirtAddr> {
         // TODO: performance optimization
         let mut segments: Vec<_> = self
             .owned_mem
@@ -241,37 +289,52 @@ impl MemorySet {
                 .iter()
                 .map(|(start, _, mem)| (start.as_usize(), start.as_usize() + mem.size())),
         );
+        // The signal trampoline is mapped without an area
+        segments.push((
+            axconfig::SIGNAL_TRAMPOLINE,
+            axconfig::SIGNAL_TRAMPOLINE + PAGE_SIZE_4K,
+        ));
 
         segments.sort();
 
+        let mut last_end = start;
         for (start, end) in segments {
             if last_end + size <= start {
                 return Some(last_end.into());
             }
-            last_end = end;
+            last_end = last_end.max(end);
         }
 
-        None
+        if last_end + size <= axconfig::USER_MEMORY_END {
+            Some(last_end.into())
+        } else {
+            None
+        }
     }
 
     /// mmap. You need to flush tlb after this.
+    ///
+    /// The changes to a `shared` mapping are written back to the backend, while those to a
+    /// private one are not.
     pub fn mmap(
         &mut self,
         start: VirtAddr,
         size: usize,
         flags: MappingFlags,
         fixed: bool,
+        shared: bool,
         backend: Option<MemBackend>,
     ) -> isize {
         // align up to 4k
         let size = (size + PAGE_SIZE_4K - 1) / PAGE_SIZE_4K * PAGE_SIZE_4K;
 
         info!(
-            "[mmap] vaddr: [{:?}, {:?}), {:?}, fixed: {}, backend: {}",
+            "[mmap] vaddr: [{:?}, {:?}), {:?}, fixed: {}, shared: {}, backend: {}",
             start,
             start + size,
             flags,
             fixed,
+            shared,
             backend.is_some()
         );
 
@@ -279,6 +342,7 @@ impl MemorySet {
             self.split_for_area(start, size);
 
             self.new_region(start, size, flags, None, backend);
+            self.owned_mem.get_mut(&start.as_usize()).unwrap().shared = shared;
 
             axhal::arch::flush_tlb(None);
 
@@ -291,6 +355,7 @@ impl MemorySet {
                 Some(start) => {
                     info!("found area [{:?}, {:?})", start, start + size);
                     self.new_region(start, size, flags, None, backend);
+                    self.owned_mem.get_mut(&start.as_usize()).unwrap().shared = shared;
                     flush_tlb(None);
                     start.as_usize() as isize
                 }
@@ -351,8 +416,8 @@ impl MemorySet {
         // NOTE: There will be new areas but all old aree's start address won't change. But we
         // can't iterating through `value_mut()` while `insert()` to BTree at the same time, so we
         // `drain_filter()` out the overlapped areas first.
-        let mut overlapped_area: Vec<(usize, MapArea)> = Vec::new();
-        let mut prev_area: BTreeMap<usize, MapArea> = BTreeMap::new();
+        let mut overlapped_area: Vec<(usize, Box<MapArea>)> = Vec::new();
+        let mut prev_area: BTreeMap<usize, Box<MapArea>> = BTreeMap::new();
 
         for _ in 0..self.owned_mem.len() {
             let (idx, area) = self.owned_mem.pop_first().unwrap();
@@ -374,20 +439,32 @@ impl MemorySet {
                 let (mut mid, right) = area.split3(start, end);
                 mid.update_flags(flags, &mut self.page_table);
 
-                assert!(self.owned_mem.insert(mid.vaddr.into(), mid).is_none());
-                assert!(self.owned_mem.insert(right.vaddr.into(), right).is_none());
+                assert!(self
+                    .owned_mem
+                    .insert(mid.vaddr.into(), new_area_box(mid))
+                    .is_none());
+                assert!(self
+                    .owned_mem
+                    .insert(right.vaddr.into(), new_area_box(right))
+                    .is_none());
             } else if start <= area.vaddr && area.vaddr < end {
                 // split into 2 areas, update the left one
                 let right = area.split(end);
                 area.update_flags(flags, &mut self.page_table);
 
-                assert!(self.owned_mem.insert(right.vaddr.into(), right).is_none());
+                assert!(self
+                    .owned_mem
+                    .insert(right.vaddr.into(), new_area_box(right))
+                    .is_none());
             } else {
                 // split into 2 areas, update the right one
                 let mut right = area.split(start);
                 right.update_flags(flags, &mut self.page_table);
 
-                assert!(self.owned_mem.insert(right.vaddr.into(), right).is_none());
+                assert!(self
+                    .owned_mem
+                    .insert(right.vaddr.into(), new_area_box(right))
+                    .is_none());
             }
 
             assert!(self.owned_mem.insert(area.vaddr.into(), area).is_none());
@@ -421,6 +498,10 @@ impl MemorySet {
             area.dealloc(&mut self.page_table);
         }
         self.owned_mem.clear();
+        // System V shared memory is detached on exec and exit
+        self.detach_all_shared_mem();
+        // `mlockall(MCL_FUTURE)` is not preserved across exec
+        self.lock_future = false;
     }
 
     /// Query the page table to get the physical address, flags and page size of the given virtual
@@ -445,9 +526,8 @@ impl MemorySet {
     }
 
     /// Create a new SharedMem with given key.
-    /// You need to add the returned SharedMem to global SHARED_MEMS or process's private_mem.
-    ///
-    /// Panics: SharedMem with the key already exist.
+    /// You need to add the returned SharedMem to global SHARED_MEMS or process's private_mem, and
+    /// record the key in KEY_TO_SHMID if it is not IPC_PRIVATE.
     pub fn create_shared_mem(
         key: i32,
         size: usize,
@@ -456,12 +536,9 @@ impl MemorySet {
         gid: u32,
         mode: u16,
     ) -> AxResult<(i32, SharedMem)> {
-        let mut key_map = KEY_TO_SHMID.lock();
-
         let shmid = SHMID.fetch_add(1, Ordering::Release);
-        key_map.insert(key, shmid);
 
-        let mem = SharedMem::try_new(key, size, pid, uid, gid, mode)?;
+        let mem = SharedMem::try_new(shmid, key, size, pid, uid, gid, mode)?;
 
         Ok((shmid, mem))
     }
@@ -494,14 +571,74 @@ impl MemorySet {
             .map_region(addr, mem.paddr(), mem.size(), flags, false)
             .unwrap();
 
+        mem.attach();
         self.attached_mem.push((addr, flags, mem));
     }
 
-    /// Detach a SharedMem from the memory set.
+    /// Detach the SharedMem attached at `addr` from the memory set, and return it. You need to
+    /// flush TLB after this.
     ///
-    /// TODO: implement this
-    pub fn detach_shared_mem(&mut self, _shmid: i32) {
-        todo!()
+    /// If it is the last attachment of a segment marked by [`MemorySet::remove_shared_mem`], the
+    /// segment is destroyed.
+    pub fn detach_shared_mem(&mut self, addr: VirtAddr) -> AxResult<Arc<SharedMem>> {
+        let idx = self
+            .attached_mem
+            .iter()
+            .position(|(start, _, _)| *start == addr)
+            .ok_or(AxError::InvalidInput)?;
+        let (addr, _, mem) = self.attached_mem.remove(idx);
+        self.page_table
+            .unmap_region(addr, mem.size())
+            .map_err(|_| AxError::InvalidInput)?;
+        if mem.detach() {
+            self.destroy_shared_mem(mem.shmid());
+        }
+        Ok(mem)
+    }
+
+    /// Detach all the attached SharedMems, e.g., on exec and exit.
+    fn detach_all_shared_mem(&mut self) {
+        while let Some((addr, _, _)) = self.attached_mem.last() {
+            let addr = *addr;
+            let _ = self.detach_shared_mem(addr);
+        }
+    }
+
+    /// Mark the SharedMem to be destroyed (`IPC_RMID`).
+    ///
+    /// Its key can not be used to find it any more. It is destroyed at once if it is not attached,
+    /// or after the last process detaches it.
+    pub fn remove_shared_mem(&mut self, shmid: i32) -> AxResult<()> {
+        let mem = self
+            .get_private_shared_mem(shmid)
+            .or_else(|| Self::get_shared_mem(shmid))
+            .ok_or(AxError::InvalidInput)?;
+        let key = {
+            let mut info = mem.info.lock();
+            if info.is_removed() {
+                None
+            } else {
+                info.perm.mode |= SHM_DEST;
+                Some(info.perm.key)
+            }
+        };
+        if let Some(key) = key {
+            let mut key_map = KEY_TO_SHMID.lock();
+            if key_map.get(&key) == Some(&shmid) {
+                key_map.remove(&key);
+            }
+        }
+        if mem.info.lock().nattch == 0 {
+            self.destroy_shared_mem(shmid);
+        }
+        Ok(())
+    }
+
+    /// Drop the references to a SharedMem held by the global map and this memory set. The memory
+    /// is freed once the other processes holding it as private memory drop it as well.
+    fn destroy_shared_mem(&mut self, shmid: i32) {
+        SHARED_MEMS.lock().remove(&shmid);
+        self.private_mem.remove(&shmid);
     }
 
     /// mremap: change the size of a mapping, potentially moving it at the same time.
@@ -577,6 +714,230 @@ impl MemorySet {
     }
 }
 
+/// The end of [start, start + size) aligned up to 4k, or `InvalidInput` if it's beyond the
+/// address space.
+fn range_end(start: VirtAddr, size: usize) -> AxResult<VirtAddr> {
+    start
+        .as_usize()
+        .checked_add(size)
+        .and_then(|end| end.checked_add(PAGE_SIZE_4K - 1))
+        .map(|end| VirtAddr::from(end).align_down_4k())
+        .ok_or(AxError::InvalidInput)
+}
+
+/// `madvise`, `mincore` and `mlock` family.
+impl MemorySet {
+    /// Split the area which strictly contains `addr` into two, so that `addr` becomes a boundary
+    /// between areas.
+    fn split_area_at(&mut self, addr: VirtAddr) {
+        let right = match self.owned_mem.range_mut(..addr.as_usize()).next_back() {
+            Some((_, area)) if addr < area.end_va() => area.split(addr),
+            _ => return,
+        };
+        assert!(self
+            .owned_mem
+            .insert(right.vaddr.into(), new_area_box(right))
+            .is_none());
+    }
+
+    /// Split the areas overlapping with [start, end) at `start` and `end`, so that every area
+    /// overlapping with the range is contained in it.
+    fn isolate_range(&mut self, start: VirtAddr, end: VirtAddr) {
+        self.split_area_at(start);
+        self.split_area_at(end);
+    }
+
+    /// Whether every page in [start, end) is mapped, either by an area or by attached shared
+    /// memory.
+    fn range_mapped(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        let mut segments: Vec<_> = self
+            .owned_mem
+            .iter()
+            .map(|(start, mem)| (*start, *start + mem.size()))
+            .collect();
+        segments.extend(
+            self.attached_mem
+                .iter()
+                .map(|(start, _, mem)| (start.as_usize(), start.as_usize() + mem.size())),
+        );
+        segments.sort();
+
+        let mut covered = start.as_usize();
+        for (seg_start, seg_end) in segments {
+            if covered >= end.as_usize() {
+                break;
+            }
+            if seg_start > covered {
+                return false;
+            }
+            covered = covered.max(seg_end);
+        }
+        covered >= end.as_usize()
+    }
+
+    /// MADV_DONTNEED: free the pages in the range. The next access will see zero-filled pages (or
+    /// the content of the backend file, to which the changes are written back first). You need to
+    /// flush TLB after this.
+    ///
+    /// Returns `InvalidInput` if the range contains locked pages, and `NoMemory` if it contains
+    /// unmapped pages.
+    pub fn madvise_dontneed(&mut self, start: VirtAddr, size: usize) -> AxResult<()> {
+        let end = range_end(start, size)?;
+        if !self.range_mapped(start, end) {
+            return Err(AxError::NoMemory);
+        }
+        let mut areas: Vec<_> = self
+            .owned_mem
+            .values_mut()
+            .filter(|area| area.overlap_with(start, end))
+            .collect();
+        if areas.iter().any(|area| area.locked) {
+            return Err(AxError::InvalidInput);
+        }
+        for area in areas.iter_mut() {
+            area.free_pages(start, end, &mut self.page_table);
+        }
+        Ok(())
+    }
+
+    /// MADV_FREE: the pages in the range can be freed. As there is no memory reclaim, they are
+    /// freed at once, just like MADV_DONTNEED. You need to flush TLB after this.
+    ///
+    /// Returns `InvalidInput` if the range contains locked or file backed pages, and `NoMemory`
+    /// if it contains unmapped pages.
+    pub fn madvise_free(&mut self, start: VirtAddr, size: usize) -> AxResult<()> {
+        let end = range_end(start, size)?;
+        if self
+            .owned_mem
+            .values()
+            .filter(|area| area.overlap_with(start, end))
+            .any(|area| area.backend.is_some())
+        {
+            return Err(AxError::InvalidInput);
+        }
+        self.madvise_dontneed(start, size)
+    }
+
+    /// MADV_WILLNEED: read the pages of file backed areas in the range from their backend ahead
+    /// of time.
+    ///
+    /// Returns `NoMemory` if the range contains unmapped pages.
+    pub fn madvise_willneed(&mut self, start: VirtAddr, size: usize) -> AxResult<()> {
+        let end = range_end(start, size)?;
+        if !self.range_mapped(start, end) {
+            return Err(AxError::NoMemory);
+        }
+        for area in self
+            .owned_mem
+            .values_mut()
+            .filter(|area| area.backend.is_some() && area.overlap_with(start, end))
+        {
+            area.populate(start, end, &mut self.page_table)?;
+        }
+        Ok(())
+    }
+
+    /// MADV_DONTFORK / MADV_DOFORK: set whether the areas in the range will be copied to the
+    /// child process on fork.
+    ///
+    /// Returns `NoMemory` if the range contains unmapped pages.
+    pub fn set_dont_fork(&mut self, start: VirtAddr, size: usize, dont_fork: bool) -> AxResult<()> {
+        let end = range_end(start, size)?;
+        if !self.range_mapped(start, end) {
+            return Err(AxError::NoMemory);
+        }
+        self.isolate_range(start, end);
+        self.owned_mem
+            .values_mut()
+            .filter(|area| area.overlap_with(start, end))
+            .for_each(|area| area.dont_fork = dont_fork);
+        Ok(())
+    }
+
+    /// mincore: return one byte for every page in the range, whose least significant bit is set
+    /// if the page is resident in memory.
+    ///
+    /// Returns `NoMemory` if the range contains unmapped pages.
+    pub fn mincore(&self, start: VirtAddr, size: usize) -> AxResult<Vec<u8>> {
+        let end = range_end(start, size)?;
+        if !self.range_mapped(start, end) {
+            return Err(AxError::NoMemory);
+        }
+        Ok((start.as_usize()..end.as_usize())
+            .step_by(PAGE_SIZE_4K)
+            .map(|addr| {
+                check_page_table_entry_validity(addr.into(), &self.page_table).is_ok() as u8
+            })
+            .collect())
+    }
+
+    /// mlock: lock the pages in the range in memory. If `populate` is set, the pages are loaded
+    /// at once, otherwise they are locked when they are faulted in (`MLOCK_ONFAULT`).
+    ///
+    /// Locked pages can not be freed by `madvise`.
+    ///
+    /// Returns `NoMemory` if the range contains unmapped pages.
+    pub fn mlock(&mut self, start: VirtAddr, size: usize, populate: bool) -> AxResult<()> {
+        let end = range_end(start, size)?;
+        if !self.range_mapped(start, end) {
+            return Err(AxError::NoMemory);
+        }
+        self.isolate_range(start, end);
+        for area in self
+            .owned_mem
+            .values_mut()
+            .filter(|area| area.overlap_with(start, end))
+        {
+            area.locked = true;
+            if populate {
+                area.populate(start, end, &mut self.page_table)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// munlock: unlock the pages in the range.
+    ///
+    /// Returns `NoMemory` if the range contains unmapped pages.
+    pub fn munlock(&mut self, start: VirtAddr, size: usize) -> AxResult<()> {
+        let end = range_end(start, size)?;
+        if !self.range_mapped(start, end) {
+            return Err(AxError::NoMemory);
+        }
+        self.isolate_range(start, end);
+        self.owned_mem
+            .values_mut()
+            .filter(|area| area.overlap_with(start, end))
+            .for_each(|area| area.locked = false);
+        Ok(())
+    }
+
+    /// mlockall: lock all the areas which are currently mapped (`current`) and / or the areas
+    /// which will be mapped in the future (`future`).
+    pub fn mlock_all(&mut self, current: bool, future: bool, populate: bool) -> AxResult<()> {
+        if current {
+            for area in self.owned_mem.values_mut() {
+                area.locked = true;
+                if populate {
+                    area.populate(area.vaddr, area.end_va(), &mut self.page_table)?;
+                }
+            }
+        }
+        if future {
+            self.lock_future = true;
+        }
+        Ok(())
+    }
+
+    /// munlockall: unlock all the areas, and stop locking new areas.
+    pub fn munlock_all(&mut self) {
+        self.owned_mem
+            .values_mut()
+            .for_each(|area| area.locked = false);
+        self.lock_future = false;
+    }
+}
+
 impl MemorySet {
     /// 判断某一个虚拟地址是否在内存集中。
     /// 若当前虚拟地址在内存集中，且对应的是lazy分配，暂未分配物理页的情况下，
@@ -649,13 +1010,17 @@ impl MemorySet {
                 .map_region(phys_to_virt(r.paddr), r.paddr, r.size, r.flags.into(), true)
                 .expect("Error mapping kernel memory");
         }
-        let mut owned_mem: BTreeMap<usize, MapArea> = BTreeMap::new();
+        let mut owned_mem: BTreeMap<usize, Box<MapArea>> = BTreeMap::new();
         for (vaddr, area) in self.owned_mem.iter() {
+            if area.dont_fork {
+                info!("skip MADV_DONTFORK area: {:X?}", area.vaddr);
+                continue;
+            }
             info!("vaddr: {:X?}, new_area: {:X?}", vaddr, area.vaddr);
             match area.clone_alloc(&mut page_table) {
                 Ok(new_area) => {
                     info!("new area: {:X?}", new_area.vaddr);
-                    owned_mem.insert(*vaddr, new_area);
+                    owned_mem.insert(*vaddr, new_area_box(new_area));
                     Ok(())
                 }
                 Err(err) => Err(err),
@@ -668,6 +1033,9 @@ impl MemorySet {
 
             private_mem: self.private_mem.clone(),
             attached_mem: Vec::new(),
+            // memory locks are not inherited by the child
+            lock_future: false,
+            mmap_base: self.mmap_base,
         };
 
         for (addr, flags, mem) in &self.attached_mem {
@@ -1,22 +1,28 @@
 use axalloc::GlobalPage;
-use axerrno::AxResult;
+use axerrno::{AxError, AxResult};
 use axhal::{
     mem::{virt_to_phys, PhysAddr, PAGE_SIZE_4K},
     time::current_time,
 };
+use spinlock::SpinNoIrq;
 
-#[allow(dead_code)]
+/// The segment is marked to be destroyed after the last process detaches it (`SHM_DEST`).
+pub const SHM_DEST: u32 = 0o1000;
+
+/// A System V shared memory segment.
 pub struct SharedMem {
+    shmid: i32,
     pages: GlobalPage,
     /// The information of the shared memory.
-    pub info: SharedMemInfo,
+    pub info: SpinNoIrq<SharedMemInfo>,
 }
 
 impl SharedMem {
     /// Allocate a new shared memory.
     ///
-    /// If the allocation fails, return an error.
+    /// If the size is zero or the allocation fails, return an error.
     pub fn try_new(
+        shmid: i32,
         key: i32,
         size: usize,
         pid: u64,
@@ -24,17 +30,27 @@ impl SharedMem {
         gid: u32,
         mode: u16,
     ) -> AxResult<Self> {
-        let num_pages = (size + PAGE_SIZE_4K - 1) / PAGE_SIZE_4K;
+        if size == 0 {
+            return Err(AxError::InvalidInput);
+        }
+        let num_pages = size.div_ceil(PAGE_SIZE_4K);
 
-        let pages = GlobalPage::alloc_contiguous(num_pages, PAGE_SIZE_4K)?;
-        let size = pages.size();
+        let mut pages = GlobalPage::alloc_contiguous(num_pages, PAGE_SIZE_4K)?;
+        // Shared memory segments are zero-initialized.
+        pages.zero();
 
         Ok(Self {
+            shmid,
             pages,
-            info: SharedMemInfo::new(key, size, pid, uid, gid, mode),
+            info: SpinNoIrq::new(SharedMemInfo::new(key, size, pid, uid, gid, mode)),
         })
     }
 
+    /// Return the identifier of the shared memory.
+    pub fn shmid(&self) -> i32 {
+        self.shmid
+    }
+
     /// Return the size of the shared memory.
     pub fn size(&self) -> usize {
         self.pages.size()
@@ -44,29 +60,59 @@ impl SharedMem {
     pub fn paddr(&self) -> PhysAddr {
         self.pages.start_paddr(virt_to_phys)
     }
+
+    /// Count a new attachment.
+    pub(crate) fn attach(&self) {
+        self.info.lock().nattch += 1;
+    }
+
+    /// Count a detachment. Return `true` if it was the last attachment of a segment marked to be
+    /// destroyed.
+    pub(crate) fn detach(&self) -> bool {
+        let mut info = self.info.lock();
+        info.nattch -= 1;
+        info.nattch == 0 && info.is_removed()
+    }
 }
 
-#[allow(dead_code)]
+/// The information of a shared memory segment, as reported by `shmctl(IPC_STAT)`.
+#[derive(Clone)]
 pub struct SharedMemInfo {
-    perm: SharedMemPermInfo,
-    size: usize,
+    /// The ownership and permissions.
+    pub perm: SharedMemPermInfo,
+    /// The size requested by `shmget`, in bytes.
+    pub size: usize,
 
-    a_time: usize,
-    d_time: usize,
-    c_time: usize,
+    /// Last attach time.
+    pub a_time: usize,
+    /// Last detach time.
+    pub d_time: usize,
+    /// Last change time.
+    pub c_time: usize,
 
-    c_pid: u64,
-    l_pid: u64,
+    /// The pid of the creator.
+    pub c_pid: u64,
+    /// The pid of the last `shmat`/`shmdt`.
+    pub l_pid: u64,
+    /// The number of current attaches.
+    pub nattch: usize,
 }
 
-#[allow(dead_code)]
+/// The ownership and permissions of a shared memory segment.
+#[derive(Clone)]
 pub struct SharedMemPermInfo {
-    key: i32,
-    uid: u32,
-    gid: u32,
-    cuid: u32,
-    cgid: u32,
-    mode: u16,
+    /// The key given to `shmget`.
+    pub key: i32,
+    /// Effective UID of the owner.
+    pub uid: u32,
+    /// Effective GID of the owner.
+    pub gid: u32,
+    /// Effective UID of the creator.
+    pub cuid: u32,
+    /// Effective GID of the creator.
+    pub cgid: u32,
+    /// Permissions, plus the `SHM_DEST` flag.
+    pub mode: u32,
 }
 
 impl SharedMemInfo {
@@ -81,7 +127,7 @@ impl SharedMemInfo {
                 gid,
                 cuid: uid,
                 cgid: gid,
-                mode,
+                mode: mode as u32 & 0o777,
             },
             size,
             a_time: 0,
@@ -90,6 +136,23 @@ impl SharedMemInfo {
 
             c_pid: pid,
             l_pid: 0,
+            nattch: 0,
+        }
+    }
+
+    /// Whether the segment is marked to be destroyed.
+    pub fn is_removed(&self) -> bool {
+        self.perm.mode & SHM_DEST != 0
+    }
+
+    /// Record an attach or detach by the given process.
+    pub fn touch(&mut self, pid: u64, attach: bool) {
+        let now = current_time().as_secs() as usize;
+        if attach {
+            self.a_time = now;
+        } else {
+            self.d_time = now;
         }
+        self.l_pid = pid;
     }
 }
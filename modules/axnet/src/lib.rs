@@ -8,12 +8,31 @@
 //!
 //! - [`TcpSocket`]: A TCP socket that provides POSIX-like APIs.
 //! - [`UdpSocket`]: A UDP socket that provides POSIX-like APIs.
+//! - [`RawSocket`]: A raw IPv4 socket that provides POSIX-like APIs.
+//! - [`IcmpSocket`]: An ICMP datagram socket for echo requests (ping).
+//! - [`PacketSocket`]: A packet socket that captures and injects link-layer
+//!   frames, filtered by classic BPF programs ([`BpfProgram`]).
 //! - [`dns_query`]: Function for DNS query.
+//! - [`add_route`], [`del_route`]: Functions to manage the routing table.
+//! - [`interfaces`], [`add_ip_addr`], [`del_ip_addr`]: Functions to query and
+//!   configure the network interfaces.
+//!
+//! TCP connections scale their windows with the socket buffers, acknowledge
+//! out-of-order data with SACK, and pace sending by a [`CongestionControl`]
+//! algorithm (Reno or CUBIC) chosen per socket.
+//!
+//! Both IPv4 and IPv6 are supported. Ethernet interfaces get an IPv6
+//! link-local address, and global ones by SLAAC from router advertisements.
 //!
 //! # Cargo Features
 //!
 //! - `smoltcp`: Use [smoltcp] as the underlying network stack. This is enabled
 //!   by default.
+//! - `dhcp`: Configure the Ethernet interfaces by DHCP in a kernel task,
+//!   except the first one if `AX_IP` is given. It needs `multitask`.
+//! - `irq`: Run the stack by a kernel task woken by NIC interrupts and the
+//!   timers of the stack, and blocked socket operations sleep until their
+//!   sockets are ready. It needs `multitask` and `irq`.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
@@ -32,22 +51,40 @@ cfg_if::cfg_if! {
     }
 }
 
-pub use self::net_impl::TcpSocket;
+pub use self::net_impl::{CongestionControl, TcpInfo, TcpSocket};
 pub use self::net_impl::UdpSocket;
+pub use self::net_impl::{add_ip_addr, del_ip_addr, interfaces, InterfaceInfo};
 pub use self::net_impl::{
     add_membership, dns_query, from_core_sockaddr, into_core_sockaddr, poll_interfaces,
 };
-pub use self::net_impl::{bench_receive, bench_transmit};
+pub use self::net_impl::{add_route, del_route, interface_name, routes, Route};
+pub use self::net_impl::{bench_receive, bench_tcp_loopback, bench_tcp_transmit, bench_transmit};
+pub use self::net_impl::{BpfContext, BpfInsn, BpfProgram, BPF_MAXINSNS};
+pub use self::net_impl::{IcmpSocket, RawSocket};
+pub use self::net_impl::{PacketAddr, PacketSocket, PacketStats, PacketType, ETH_P_ALL};
+pub use smoltcp::socket::tcp::State as TcpState;
 pub use smoltcp::time::Duration;
-pub use smoltcp::wire::{IpAddress as IpAddr, IpEndpoint as SocketAddr, Ipv4Address as Ipv4Addr};
+pub use smoltcp::wire::{
+    IpAddress as IpAddr, IpCidr, IpEndpoint as SocketAddr, Ipv4Address as Ipv4Addr,
+    Ipv6Address as Ipv6Addr,
+};
 
+use alloc::vec::Vec;
 use axdriver::{prelude::*, AxDeviceContainer};
 
 /// Initializes the network subsystem by NIC devices.
+///
+/// Every NIC becomes an interface besides the loopback interface.
 pub fn init_network(mut net_devs: AxDeviceContainer<AxNetDevice>) {
     info!("Initialize network subsystem...");
 
-    let dev = net_devs.take_one().expect("No NIC device found!");
-    info!("  use NIC 0: {:?}", dev.device_name());
-    net_impl::init(dev);
+    let mut devs = Vec::new();
+    while let Some(dev) = net_devs.take_one() {
+        info!("  use NIC {}: {:?}", devs.len(), dev.device_name());
+        devs.push(dev);
+    }
+    if devs.is_empty() {
+        warn!("No NIC device found, only the loopback interface is available");
+    }
+    net_impl::init(devs);
 }
@@ -1,17 +1,17 @@
 use core::net::{IpAddr, SocketAddr};
-use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
+use smoltcp::wire::{IpAddress, IpEndpoint, IpVersion, Ipv4Address, Ipv6Address};
 
 pub const fn from_core_ipaddr(ip: IpAddr) -> IpAddress {
     match ip {
         IpAddr::V4(ipv4) => IpAddress::Ipv4(Ipv4Address(ipv4.octets())),
-        _ => panic!("IPv6 not supported"),
+        IpAddr::V6(ipv6) => IpAddress::Ipv6(Ipv6Address(ipv6.octets())),
     }
 }
 
 pub const fn into_core_ipaddr(ip: IpAddress) -> IpAddr {
     match ip {
         IpAddress::Ipv4(ipv4) => IpAddr::V4(unsafe { core::mem::transmute(ipv4.0) }),
-        // _ => panic!("IPv6 not supported"),
+        IpAddress::Ipv6(ipv6) => IpAddr::V6(unsafe { core::mem::transmute(ipv6.0) }),
     }
 }
 
@@ -29,7 +29,28 @@ pub const fn into_core_sockaddr(addr: IpEndpoint) -> SocketAddr {
 }
 
 pub fn is_unspecified(ip: IpAddress) -> bool {
-    ip.as_bytes() == [0, 0, 0, 0]
+    ip.is_unspecified()
+}
+
+/// Returns the unspecified endpoint of the same IP version as `addr`.
+pub const fn unspecified_endpoint_of(addr: IpAddress) -> IpEndpoint {
+    match addr {
+        IpAddress::Ipv4(_) => UNSPECIFIED_ENDPOINT,
+        IpAddress::Ipv6(_) => IpEndpoint::new(IpAddress::Ipv6(Ipv6Address::UNSPECIFIED), 0),
+    }
+}
+
+/// Returns the IP version of the packets taken by a socket bound to the
+/// unspecified address `addr`, or `None` if it takes both.
+///
+/// Like Linux, an IPv6 socket also takes IPv4 packets unless it is
+/// `ipv6_only` (`IPV6_V6ONLY`).
+pub const fn accepted_version(addr: IpAddress, ipv6_only: bool) -> Option<IpVersion> {
+    match addr {
+        IpAddress::Ipv4(_) => Some(IpVersion::Ipv4),
+        IpAddress::Ipv6(_) if ipv6_only => Some(IpVersion::Ipv6),
+        IpAddress::Ipv6(_) => None,
+    }
 }
 
 pub const UNSPECIFIED_IP: IpAddress = IpAddress::v4(0, 0, 0, 0);
@@ -1,5 +1,13 @@
+use alloc::vec;
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{AxError, AxResult};
+use axhal::time::current_time;
+
 use super::{AxNetRxToken, AxNetTxToken, STANDARD_MTU};
-use super::{DeviceWrapper, InterfaceWrapper};
+use super::{CongestionControl, TcpSocket};
+use super::{DeviceWrapper, InterfaceWrapper, SOCKET_SET};
 use smoltcp::phy::{Device, RxToken, TxToken};
 
 const GB: usize = 1000 * MB;
@@ -73,3 +81,150 @@ impl DeviceWrapper {
         }
     }
 }
+
+/// Measures the throughput of a TCP connection over the loopback interface.
+///
+/// Both ends are driven by the current task in turn, `total_bytes` are sent
+/// from the client with the given congestion control to the server.
+pub fn bench_tcp_loopback(total_bytes: usize, congestion: CongestionControl) -> AxResult {
+    let server = TcpSocket::new();
+    server.bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))?;
+    server.listen()?;
+    let client = TcpSocket::new();
+    client.set_nonblocking(true);
+    client.set_congestion(congestion);
+    match client.connect(server.local_addr()?) {
+        Ok(()) | Err(AxError::WouldBlock) => {}
+        Err(e) => return Err(e),
+    }
+    let conn = server.accept()?;
+    conn.set_nonblocking(true);
+    while !client.poll()?.writable {
+        SOCKET_SET.poll_interfaces();
+    }
+
+    let mut buf = vec![0u8; 64 * KB];
+    let mut meter = Meter::new("TCP loopback");
+    let mut sent = 0;
+    let mut received = 0;
+    while received < total_bytes {
+        SOCKET_SET.poll_interfaces();
+        if sent < total_bytes {
+            match client.send_with(|tx_buf| {
+                let len = tx_buf.len().min(total_bytes - sent);
+                tx_buf[..len].fill(1);
+                Ok(len)
+            }) {
+                Ok(len) => sent += len,
+                Err(AxError::WouldBlock) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        match conn.recv(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => {
+                received += len;
+                meter.add(len);
+            }
+            Err(AxError::WouldBlock) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    meter.finish();
+    report_window(&client);
+    Ok(())
+}
+
+/// Measures the throughput of sending `total_bytes` to a TCP server at
+/// `addr`, such as `nc -l 5555 > /dev/null` on the host end of a tap device.
+pub fn bench_tcp_transmit(
+    addr: SocketAddr,
+    total_bytes: usize,
+    congestion: CongestionControl,
+) -> AxResult {
+    let client = TcpSocket::new();
+    client.set_congestion(congestion);
+    client.connect(addr)?;
+
+    let mut meter = Meter::new("TCP transmit");
+    let mut sent = 0;
+    while sent < total_bytes {
+        let len = client.send_with(|tx_buf| {
+            let len = tx_buf.len().min(total_bytes - sent);
+            tx_buf[..len].fill(1);
+            Ok(len)
+        })?;
+        sent += len;
+        meter.add(len);
+    }
+    meter.finish();
+    report_window(&client);
+    client.shutdown()
+}
+
+/// Reports the bandwidth every second and in total.
+struct Meter {
+    name: &'static str,
+    start: Duration,
+    bytes: usize,
+    past_time: Duration,
+    past_bytes: usize,
+}
+
+impl Meter {
+    fn new(name: &'static str) -> Self {
+        let now = current_time();
+        Self {
+            name,
+            start: now,
+            bytes: 0,
+            past_time: now,
+            past_bytes: 0,
+        }
+    }
+
+    fn add(&mut self, bytes: usize) {
+        self.bytes += bytes;
+        let now = current_time();
+        if now - self.past_time >= Duration::from_secs(1) {
+            Self::report(
+                self.name,
+                self.bytes - self.past_bytes,
+                now - self.past_time,
+            );
+            self.past_time = now;
+            self.past_bytes = self.bytes;
+        }
+    }
+
+    fn finish(&self) {
+        let name = alloc::format!("{} total", self.name);
+        Self::report(&name, self.bytes, current_time() - self.start);
+    }
+
+    fn report(name: &str, bytes: usize, elapsed: Duration) {
+        let micros = elapsed.as_micros().max(1) as u64;
+        let mbits = bytes as u64 * 8 / micros;
+        let kbits = bytes as u64 * 8 * 1000 / micros % 1000;
+        info!(
+            "{}: {}.{:03}MBytes in {}ms, Bandwidth: {}.{:03}Mbits/sec.",
+            name,
+            bytes / MB,
+            bytes % MB / KB,
+            elapsed.as_millis(),
+            mbits,
+            kbits
+        );
+    }
+}
+
+fn report_window(socket: &TcpSocket) {
+    let info = socket.info();
+    info!(
+        "{}: cwnd {} bytes, ssthresh {}, send buffer {} bytes",
+        socket.congestion().name(),
+        info.cwnd,
+        info.ssthresh,
+        info.send_capacity
+    );
+}
@@ -0,0 +1,272 @@
+//! Classic BPF, the filter language of packet sockets.
+
+use alloc::vec::Vec;
+
+use axerrno::{ax_err, AxResult};
+
+/// The most instructions a program can have.
+pub const BPF_MAXINSNS: usize = 4096;
+/// The number of words in the scratch memory.
+const BPF_MEMWORDS: usize = 16;
+
+// Instruction classes.
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+
+// Sizes of loads.
+const BPF_W: u16 = 0x00;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+
+// Modes of loads.
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+const BPF_MSH: u16 = 0xa0;
+
+// ALU operations.
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_MOD: u16 = 0x90;
+const BPF_XOR: u16 = 0xa0;
+
+// Jumps.
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+
+// Operand sources.
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+/// Return value from the accumulator.
+const BPF_A: u16 = 0x10;
+
+// Register transfers.
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+/// Offset of the ancillary data in absolute loads, as Linux does.
+const SKF_AD_OFF: i32 = -0x1000;
+const SKF_AD_PROTOCOL: i32 = 0;
+const SKF_AD_PKTTYPE: i32 = 4;
+const SKF_AD_IFINDEX: i32 = 8;
+const SKF_AD_HATYPE: i32 = 28;
+
+/// A classic BPF instruction, the same as `struct sock_filter`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BpfInsn {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// What a program knows about a packet besides its content.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BpfContext {
+    /// The ethertype of the packet.
+    pub protocol: u16,
+    /// The packet type, such as `PACKET_HOST`.
+    pub pkttype: u8,
+    /// The index of the interface counted from 1, as Linux does.
+    pub ifindex: u32,
+    /// The hardware type of the interface.
+    pub hatype: u16,
+}
+
+/// A checked classic BPF program.
+#[derive(Clone, Debug)]
+pub struct BpfProgram {
+    insns: Vec<BpfInsn>,
+}
+
+impl BpfProgram {
+    /// Checks the instructions and builds a program.
+    ///
+    /// Like Linux, a program must not be empty, every jump must stay in the
+    /// program and go forward, and the last instruction must be a return, so
+    /// that it always terminates.
+    pub fn new(insns: Vec<BpfInsn>) -> AxResult<Self> {
+        if insns.is_empty() || insns.len() > BPF_MAXINSNS {
+            return ax_err!(InvalidInput, "BPF program of invalid length");
+        }
+        for (pc, insn) in insns.iter().enumerate() {
+            if !Self::check_insn(insn, insns.len() - pc - 1) {
+                return ax_err!(InvalidInput, "invalid BPF instruction");
+            }
+        }
+        if insns.last().unwrap().code & 0x07 != BPF_RET {
+            return ax_err!(InvalidInput, "BPF program does not end with a return");
+        }
+        Ok(Self { insns })
+    }
+
+    /// Checks an instruction, `remaining` is the number of the instructions
+    /// after it.
+    fn check_insn(insn: &BpfInsn, remaining: usize) -> bool {
+        let code = insn.code;
+        match code & 0x07 {
+            BPF_LD | BPF_LDX => {
+                let size_ok = matches!(code & 0x18, BPF_W | BPF_H | BPF_B);
+                match code & 0xe0 {
+                    BPF_MEM => code & 0x18 == BPF_W && (insn.k as usize) < BPF_MEMWORDS,
+                    BPF_IMM | BPF_LEN => code & 0x18 == BPF_W,
+                    BPF_ABS | BPF_IND => code & 0x07 == BPF_LD && size_ok,
+                    BPF_MSH => code == BPF_LDX | BPF_B | BPF_MSH,
+                    _ => false,
+                }
+            }
+            BPF_ST | BPF_STX => code & 0xf8 == 0 && (insn.k as usize) < BPF_MEMWORDS,
+            BPF_ALU => match code & 0xf0 {
+                BPF_DIV | BPF_MOD => code & BPF_X != 0 || insn.k != 0,
+                BPF_LSH | BPF_RSH => code & BPF_X != 0 || insn.k < 32,
+                BPF_ADD | BPF_SUB | BPF_MUL | BPF_OR | BPF_AND | BPF_XOR => true,
+                BPF_NEG => code & BPF_X == 0,
+                _ => false,
+            },
+            BPF_JMP => match code & 0xf0 {
+                BPF_JA => code & BPF_X == 0 && (insn.k as usize) < remaining,
+                BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET => {
+                    (insn.jt as usize) < remaining && (insn.jf as usize) < remaining
+                }
+                _ => false,
+            },
+            BPF_RET => matches!(code & 0x18, BPF_K | BPF_X | BPF_A) && code & 0xe0 == 0,
+            BPF_MISC => code == BPF_MISC | BPF_TAX || code == BPF_MISC | BPF_TXA,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Runs the program on a packet, returns how many bytes of it to accept,
+    /// and 0 means dropping it.
+    ///
+    /// A load out of the packet, or a division by 0 also drops the packet.
+    pub fn run(&self, packet: &[u8], ctx: &BpfContext) -> u32 {
+        self.execute(packet, ctx).unwrap_or(0)
+    }
+
+    fn execute(&self, packet: &[u8], ctx: &BpfContext) -> Option<u32> {
+        let mut a: u32 = 0;
+        let mut x: u32 = 0;
+        let mut mem = [0u32; BPF_MEMWORDS];
+        let mut pc = 0;
+        loop {
+            let insn = self.insns[pc];
+            pc += 1;
+            let k = insn.k;
+            let src = if insn.code & BPF_X != 0 { x } else { k };
+            match insn.code & 0x07 {
+                BPF_LD => {
+                    a = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_LEN => packet.len() as u32,
+                        BPF_MEM => mem[k as usize],
+                        BPF_ABS if (k as i32) < 0 => Self::load_ancillary(k as i32, ctx)?,
+                        BPF_ABS => Self::load(packet, k as usize, insn.code & 0x18)?,
+                        BPF_IND => {
+                            let offset = x.checked_add(k)? as usize;
+                            Self::load(packet, offset, insn.code & 0x18)?
+                        }
+                        _ => unreachable!(),
+                    };
+                }
+                BPF_LDX => {
+                    x = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_LEN => packet.len() as u32,
+                        BPF_MEM => mem[k as usize],
+                        // The length of an IPv4 header.
+                        BPF_MSH => (*packet.get(k as usize)? as u32 & 0xf) << 2,
+                        _ => unreachable!(),
+                    };
+                }
+                BPF_ST => mem[k as usize] = a,
+                BPF_STX => mem[k as usize] = x,
+                BPF_ALU => {
+                    a = match insn.code & 0xf0 {
+                        BPF_ADD => a.wrapping_add(src),
+                        BPF_SUB => a.wrapping_sub(src),
+                        BPF_MUL => a.wrapping_mul(src),
+                        BPF_DIV => a.checked_div(src)?,
+                        BPF_MOD => a.checked_rem(src)?,
+                        BPF_OR => a | src,
+                        BPF_AND => a & src,
+                        BPF_XOR => a ^ src,
+                        BPF_LSH => a.checked_shl(src).unwrap_or(0),
+                        BPF_RSH => a.checked_shr(src).unwrap_or(0),
+                        BPF_NEG => a.wrapping_neg(),
+                        _ => unreachable!(),
+                    };
+                }
+                BPF_JMP => {
+                    let taken = match insn.code & 0xf0 {
+                        BPF_JA => {
+                            pc += k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == src,
+                        BPF_JGT => a > src,
+                        BPF_JGE => a >= src,
+                        BPF_JSET => a & src != 0,
+                        _ => unreachable!(),
+                    };
+                    pc += if taken { insn.jt } else { insn.jf } as usize;
+                }
+                BPF_RET => {
+                    return Some(match insn.code & 0x18 {
+                        BPF_X => x,
+                        BPF_A => a,
+                        _ => k,
+                    });
+                }
+                BPF_MISC => {
+                    if insn.code & 0xf8 == BPF_TXA {
+                        a = x;
+                    } else {
+                        x = a;
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Loads a big-endian word, half word or byte from the packet.
+    fn load(packet: &[u8], offset: usize, size: u16) -> Option<u32> {
+        let len = match size {
+            BPF_W => 4,
+            BPF_H => 2,
+            _ => 1,
+        };
+        let bytes = packet.get(offset..offset.checked_add(len)?)?;
+        Some(bytes.iter().fold(0, |acc, &b| acc << 8 | b as u32))
+    }
+
+    fn load_ancillary(offset: i32, ctx: &BpfContext) -> Option<u32> {
+        match offset.checked_sub(SKF_AD_OFF)? {
+            SKF_AD_PROTOCOL => Some(ctx.protocol as u32),
+            SKF_AD_PKTTYPE => Some(ctx.pkttype as u32),
+            SKF_AD_IFINDEX => Some(ctx.ifindex),
+            SKF_AD_HATYPE => Some(ctx.hatype as u32),
+            _ => None,
+        }
+    }
+}
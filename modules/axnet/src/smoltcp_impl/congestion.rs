@@ -0,0 +1,461 @@
+//! TCP congestion control.
+//!
+//! smoltcp sends as much as the peer's window allows, so the congestion
+//! window is enforced by clamping the window advertised in the ACKs of the
+//! peer before smoltcp sees them. The ACKs and the segments sent are watched
+//! to grow the window, and a retransmission, three duplicate ACKs or enough
+//! data selectively acknowledged (SACK) above the unacknowledged data is taken
+//! as a loss.
+
+use alloc::collections::BTreeMap;
+use core::time::Duration;
+
+use axhal::time::current_time;
+use axsync::Mutex;
+use smoltcp::wire::{IpAddress, IpEndpoint, IpProtocol, IpVersion, Ipv4Packet, Ipv6Packet};
+use smoltcp::wire::{TcpOption, TcpPacket};
+
+/// The congestion control algorithms, selected by `TCP_CONGESTION`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CongestionControl {
+    /// TCP Reno, [RFC 5681](https://www.rfc-editor.org/rfc/rfc5681).
+    Reno,
+    /// CUBIC, [RFC 8312](https://www.rfc-editor.org/rfc/rfc8312), without the
+    /// TCP-friendly region.
+    Cubic,
+}
+
+impl CongestionControl {
+    /// The names of the algorithms as Linux calls them.
+    pub const NAMES: [&'static str; 2] = ["reno", "cubic"];
+
+    /// Returns the algorithm of the given name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "reno" => Some(Self::Reno),
+            "cubic" => Some(Self::Cubic),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the algorithm.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Reno => "reno",
+            Self::Cubic => "cubic",
+        }
+    }
+}
+
+/// The initial window in segments, as in RFC 6928.
+const INITIAL_WINDOW: u32 = 10;
+
+/// The duplicate ACKs or selectively acknowledged segments that indicate a
+/// loss.
+const DUP_THRESH: u32 = 3;
+
+/// The multiplicative decrease factor of CUBIC, in tenths.
+const CUBIC_BETA: u64 = 7;
+
+/// The congestion window of a connection.
+struct Controller {
+    algorithm: CongestionControl,
+    mss: u32,
+    cwnd: u32,
+    ssthresh: u32,
+    /// The bytes acknowledged since the window grew last time.
+    acked: u32,
+    /// The window before the last reduction, for CUBIC.
+    w_max: u32,
+    /// When the current CUBIC epoch started.
+    epoch_start: Option<Duration>,
+    /// The time to reach `origin` in the current CUBIC epoch.
+    k: Duration,
+    /// The window the cubic function centers on.
+    origin: u32,
+}
+
+impl Controller {
+    fn new(algorithm: CongestionControl, mss: u32) -> Self {
+        Self {
+            algorithm,
+            mss,
+            cwnd: INITIAL_WINDOW * mss,
+            ssthresh: u32::MAX,
+            acked: 0,
+            w_max: 0,
+            epoch_start: None,
+            k: Duration::ZERO,
+            origin: 0,
+        }
+    }
+
+    fn set_algorithm(&mut self, algorithm: CongestionControl) {
+        self.algorithm = algorithm;
+        self.epoch_start = None;
+    }
+
+    fn on_ack(&mut self, acked: u32, now: Duration) {
+        if self.cwnd < self.ssthresh {
+            // slow start, at most two segments per ACK as in RFC 3465
+            self.cwnd = self.cwnd.saturating_add(acked.min(2 * self.mss));
+            return;
+        }
+        let target = match self.algorithm {
+            CongestionControl::Reno => self.cwnd.saturating_add(self.mss),
+            CongestionControl::Cubic => self.cubic_target(now),
+        };
+        if target > self.cwnd {
+            // grows by `target - cwnd` in a window of ACKs
+            self.acked += acked;
+            let step = (target - self.cwnd) as u64 * self.acked as u64 / self.cwnd as u64;
+            if step > 0 {
+                self.cwnd = self.cwnd.saturating_add(step.min(self.mss as u64) as u32);
+                self.acked = 0;
+            }
+        }
+    }
+
+    /// W_cubic(t) = C * (t - K)^3 + origin, where C = 0.4 segments per second
+    /// cubed.
+    fn cubic_target(&mut self, now: Duration) -> u32 {
+        if self.epoch_start.is_none() {
+            if self.cwnd < self.w_max {
+                // K = cbrt((w_max - cwnd) / C), in milliseconds
+                let segments = ((self.w_max - self.cwnd) / self.mss) as u64;
+                self.k = Duration::from_millis(cbrt(segments * 10 / 4 * 1_000_000_000));
+                self.origin = self.w_max;
+            } else {
+                self.k = Duration::ZERO;
+                self.origin = self.cwnd;
+            }
+            self.epoch_start = Some(now);
+        }
+        let epoch_start = self.epoch_start.unwrap();
+        let t = (now - epoch_start).as_millis() as i128 - self.k.as_millis() as i128;
+        let delta = 4 * t * t * t * self.mss as i128 / 10 / 1_000_000_000;
+        (self.origin as i128 + delta).clamp(self.mss as i128, u32::MAX as i128) as u32
+    }
+
+    /// A loss detected by duplicate ACKs or SACK.
+    fn on_fast_loss(&mut self, flight: u32) {
+        match self.algorithm {
+            CongestionControl::Reno => {
+                self.ssthresh = (flight / 2).max(2 * self.mss);
+            }
+            CongestionControl::Cubic => {
+                // fast convergence
+                self.w_max = if self.cwnd < self.w_max {
+                    (self.cwnd as u64 * (10 + CUBIC_BETA) / 20) as u32
+                } else {
+                    self.cwnd
+                };
+                self.ssthresh = ((self.cwnd as u64 * CUBIC_BETA / 10) as u32).max(2 * self.mss);
+                self.epoch_start = None;
+            }
+        }
+        self.cwnd = self.ssthresh;
+        self.acked = 0;
+    }
+
+    /// A loss detected by the retransmission timer.
+    fn on_timeout(&mut self, flight: u32) {
+        self.on_fast_loss(flight);
+        self.cwnd = self.mss;
+    }
+}
+
+/// Integer cube root.
+fn cbrt(x: u64) -> u64 {
+    let (mut lo, mut hi) = (0u64, 1 << 21);
+    while lo < hi {
+        let mid = (lo + hi + 1) / 2;
+        if mid * mid * mid <= x {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    lo
+}
+
+/// Whether sequence number `a` comes after `b`.
+fn seq_after(a: i32, b: i32) -> bool {
+    a.wrapping_sub(b) > 0
+}
+
+/// A connection of a local TCP socket.
+struct Flow {
+    local: IpEndpoint,
+    remote: IpEndpoint,
+    controller: Controller,
+    /// Whether our SYN carried the window scale option.
+    wscale_sent: bool,
+    /// The window scale in the SYN of the peer, if any.
+    peer_wscale: Option<u8>,
+    /// The first byte not acknowledged.
+    snd_una: i32,
+    /// The byte after the last one sent.
+    snd_max: i32,
+    dup_acks: u32,
+    /// The loss recovery lasts until the data sent before the loss is
+    /// acknowledged.
+    recover: Option<i32>,
+    fin_sent: bool,
+    fin_received: bool,
+}
+
+impl Flow {
+    /// The shift of the windows advertised by the peer, which are only scaled
+    /// if both SYNs carried the window scale option (RFC 7323).
+    fn peer_shift(&self) -> u8 {
+        match self.peer_wscale {
+            Some(shift) if self.wscale_sent => shift,
+            _ => 0,
+        }
+    }
+
+    fn flight(&self) -> u32 {
+        self.snd_max.wrapping_sub(self.snd_una).max(0) as u32
+    }
+
+    fn in_recovery(&self) -> bool {
+        self.recover.is_some()
+    }
+
+    fn enter_recovery(&mut self, timeout: bool) {
+        let flight = self.flight();
+        if timeout {
+            self.controller.on_timeout(flight);
+        } else {
+            self.controller.on_fast_loss(flight);
+        }
+        self.recover = Some(self.snd_max);
+        trace!(
+            "TCP {} -> {}: loss, cwnd {}",
+            self.local,
+            self.remote,
+            self.controller.cwnd
+        );
+    }
+}
+
+/// The connections controlled, by their local and remote endpoints.
+static FLOWS: Mutex<BTreeMap<(IpEndpoint, IpEndpoint), Flow>> = Mutex::new(BTreeMap::new());
+
+/// Starts controlling the connection between `local` and `remote`.
+pub(crate) fn register(
+    local: IpEndpoint,
+    remote: IpEndpoint,
+    algorithm: CongestionControl,
+    mss: usize,
+) {
+    let flow = Flow {
+        local,
+        remote,
+        controller: Controller::new(algorithm, mss as u32),
+        wscale_sent: false,
+        peer_wscale: None,
+        snd_una: 0,
+        snd_max: 0,
+        dup_acks: 0,
+        recover: None,
+        fin_sent: false,
+        fin_received: false,
+    };
+    FLOWS.lock().insert((local, remote), flow);
+}
+
+/// Stops controlling the connection, it is closed.
+pub(crate) fn unregister(local: IpEndpoint, remote: IpEndpoint) {
+    FLOWS.lock().remove(&(local, remote));
+}
+
+/// Changes the algorithm of a connection, the window is kept.
+pub(crate) fn set_algorithm(local: IpEndpoint, remote: IpEndpoint, algorithm: CongestionControl) {
+    if let Some(flow) = FLOWS.lock().get_mut(&(local, remote)) {
+        flow.controller.set_algorithm(algorithm);
+    }
+}
+
+/// Returns the congestion window and the slow start threshold of a
+/// connection.
+pub(crate) fn window(local: IpEndpoint, remote: IpEndpoint) -> Option<(usize, usize)> {
+    FLOWS.lock().get(&(local, remote)).map(|f| {
+        let c = &f.controller;
+        let ssthresh = match c.ssthresh {
+            u32::MAX => usize::MAX,
+            ssthresh => ssthresh as usize,
+        };
+        (c.cwnd as usize, ssthresh)
+    })
+}
+
+/// Returns the addresses of an IP packet, and the range of its TCP segment.
+fn tcp_segment(packet: &[u8]) -> Option<(IpAddress, IpAddress, core::ops::Range<usize>)> {
+    match IpVersion::of_packet(packet).ok()? {
+        IpVersion::Ipv4 => {
+            let p = Ipv4Packet::new_checked(packet).ok()?;
+            if p.next_header() != IpProtocol::Tcp || p.more_frags() || p.frag_offset() != 0 {
+                return None;
+            }
+            let range = p.header_len() as usize..p.total_len() as usize;
+            Some((p.src_addr().into(), p.dst_addr().into(), range))
+        }
+        IpVersion::Ipv6 => {
+            let p = Ipv6Packet::new_checked(packet).ok()?;
+            if p.next_header() != IpProtocol::Tcp {
+                return None;
+            }
+            let range = p.header_len()..p.total_len();
+            Some((p.src_addr().into(), p.dst_addr().into(), range))
+        }
+    }
+}
+
+/// Calls `f` on every option of a TCP segment.
+fn for_each_option<'a>(mut options: &'a [u8], mut f: impl FnMut(TcpOption<'a>)) {
+    while !options.is_empty() {
+        let Ok((rest, option)) = TcpOption::parse(options) else {
+            break;
+        };
+        if matches!(option, TcpOption::EndOfList) {
+            break;
+        }
+        f(option);
+        options = rest;
+    }
+}
+
+/// Watches an IP packet sent by the stack.
+pub(crate) fn on_transmit(packet: &[u8]) {
+    let Some((src, dst, range)) = tcp_segment(packet) else {
+        return;
+    };
+    let Ok(tcp) = TcpPacket::new_checked(&packet[range]) else {
+        return;
+    };
+    let local = IpEndpoint::new(src, tcp.src_port());
+    let remote = IpEndpoint::new(dst, tcp.dst_port());
+    let key = (local, remote);
+    let mut flows = FLOWS.lock();
+    let Some(flow) = flows.get_mut(&key) else {
+        return;
+    };
+    if tcp.rst() {
+        flows.remove(&key);
+        return;
+    }
+    let seq = tcp.seq_number().0;
+    let end = seq.wrapping_add(tcp.segment_len() as i32);
+    if tcp.syn() {
+        flow.snd_una = seq;
+        flow.snd_max = end;
+        flow.wscale_sent = false;
+        for_each_option(tcp.options(), |option| {
+            if let TcpOption::WindowScale(_) = option {
+                flow.wscale_sent = true;
+            }
+        });
+        return;
+    }
+    if seq_after(end, flow.snd_max) {
+        flow.snd_max = end;
+    } else if !tcp.payload().is_empty() && seq_after(flow.snd_max, seq) && !flow.in_recovery() {
+        // sent again without duplicate ACKs, the retransmission timer expired
+        flow.enter_recovery(true);
+    }
+    if tcp.fin() {
+        flow.fin_sent = true;
+    }
+    if flow.fin_sent && flow.fin_received {
+        flows.remove(&key);
+    }
+}
+
+/// Watches an IP packet received, before it is handed to the stack.
+///
+/// The window advertised by the peer is clamped to the congestion window.
+pub(crate) fn on_receive(packet: &mut [u8]) {
+    let Some((src, dst, range)) = tcp_segment(packet) else {
+        return;
+    };
+    let Ok(tcp) = TcpPacket::new_checked(&packet[range.clone()]) else {
+        return;
+    };
+    let local = IpEndpoint::new(dst, tcp.dst_port());
+    let remote = IpEndpoint::new(src, tcp.src_port());
+    let key = (local, remote);
+    let mut flows = FLOWS.lock();
+    let Some(flow) = flows.get_mut(&key) else {
+        return;
+    };
+    if tcp.rst() {
+        flows.remove(&key);
+        return;
+    }
+    if tcp.syn() {
+        // the window in a SYN is never scaled
+        flow.peer_wscale = None;
+        for_each_option(tcp.options(), |option| {
+            if let TcpOption::WindowScale(shift) = option {
+                flow.peer_wscale = Some(shift.min(14));
+            }
+        });
+    }
+    if tcp.fin() {
+        flow.fin_received = true;
+    }
+
+    let mut clamped_window = None;
+    if tcp.ack() && !tcp.syn() {
+        let ack = tcp.ack_number().0;
+        if seq_after(ack, flow.snd_una) && !seq_after(ack, flow.snd_max) {
+            let acked = ack.wrapping_sub(flow.snd_una) as u32;
+            flow.snd_una = ack;
+            flow.dup_acks = 0;
+            match flow.recover {
+                Some(recover) if seq_after(recover, ack) => {}
+                _ => {
+                    flow.recover = None;
+                    flow.controller.on_ack(acked, current_time());
+                }
+            }
+        } else if ack == flow.snd_una && tcp.segment_len() == 0 && flow.flight() > 0 {
+            flow.dup_acks += 1;
+        }
+
+        // the bytes above the unacknowledged data that the peer has received
+        let mut sacked = 0u32;
+        for_each_option(tcp.options(), |option| {
+            if let TcpOption::SackRange(ranges) = option {
+                for &(left, right) in ranges.iter().flatten() {
+                    if seq_after(left as i32, flow.snd_una) {
+                        sacked += (right as i32).wrapping_sub(left as i32).max(0) as u32;
+                    }
+                }
+            }
+        });
+        let mss = flow.controller.mss;
+        if !flow.in_recovery() && (flow.dup_acks >= DUP_THRESH || sacked >= DUP_THRESH * mss) {
+            flow.enter_recovery(false);
+        }
+
+        let shift = flow.peer_shift();
+        let cwnd = flow.controller.cwnd;
+        if cwnd < (tcp.window_len() as u32) << shift {
+            clamped_window = Some(cwnd.div_ceil(1 << shift).max(1) as u16);
+        }
+    }
+
+    if flow.fin_sent && flow.fin_received {
+        flows.remove(&key);
+    }
+    drop(flows);
+
+    if let Some(window) = clamped_window {
+        let mut tcp = TcpPacket::new_unchecked(&mut packet[range]);
+        tcp.set_window_len(window);
+        tcp.fill_checksum(&src, &dst);
+    }
+}
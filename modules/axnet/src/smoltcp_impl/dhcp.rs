@@ -0,0 +1,291 @@
+//! DHCPv4 clients (RFC 2131) of Ethernet interfaces.
+//!
+//! The DHCP socket of smoltcp needs an Ethernet interface, while the IP stack
+//! of the kernel works on IP packets routed across all the interfaces. So
+//! every client runs its own small smoltcp interface on the NIC: the router
+//! hands it the DHCP replies and the unicast ARP frames, and the frames it
+//! sends go to the NIC directly. The leased address, default gateway and DNS
+//! servers are applied to the kernel interface, and withdrawn when the lease
+//! is lost.
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
+use smoltcp::socket::dhcpv4;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, HardwareAddress, IpAddress, IpCidr, IpProtocol, Ipv4Address,
+    Ipv4Cidr, Ipv4Packet, UdpPacket, ETHERNET_HEADER_LEN,
+};
+
+use super::route::ROUTE_TABLE;
+use super::{InterfaceWrapper, STANDARD_MTU};
+
+const DHCP_CLIENT_PORT: u16 = 68;
+/// Frames beyond it are dropped if the client has not taken them yet.
+const RX_QUEUE_LEN: usize = 16;
+/// Replies are only received when the interfaces are polled, so they are
+/// polled often until a lease is acquired.
+const DISCOVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The longest interval between two polls of the clients.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The configuration leased from a DHCP server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Lease {
+    address: Ipv4Cidr,
+    router: Option<Ipv4Address>,
+    dns_servers: Vec<IpAddress>,
+}
+
+/// The DHCP client of an interface.
+pub(crate) struct DhcpClient {
+    iface: Interface,
+    sockets: SocketSet<'static>,
+    handle: SocketHandle,
+    rx_queue: VecDeque<Vec<u8>>,
+    lease: Option<Lease>,
+}
+
+impl DhcpClient {
+    fn new(iw: &InterfaceWrapper) -> Self {
+        let mut rx_queue = VecDeque::new();
+        let mut config = Config::new(HardwareAddress::Ethernet(iw.ether_addr));
+        config.random_seed = axhal::random::random_u64();
+        let iface = Interface::new(
+            config,
+            &mut DhcpDevice {
+                iface: iw,
+                rx_queue: &mut rx_queue,
+            },
+            InterfaceWrapper::current_time(),
+        );
+        let mut sockets = SocketSet::new(vec![]);
+        let handle = sockets.add(dhcpv4::Socket::new());
+        Self {
+            iface,
+            sockets,
+            handle,
+            rx_queue,
+            lease: None,
+        }
+    }
+
+    /// Polls the client, returns the previous and the new lease if it has
+    /// changed.
+    fn poll(
+        &mut self,
+        iw: &InterfaceWrapper,
+        now: Instant,
+    ) -> Option<(Option<Lease>, Option<Lease>)> {
+        let mut device = DhcpDevice {
+            iface: iw,
+            rx_queue: &mut self.rx_queue,
+        };
+        self.iface.poll(now, &mut device, &mut self.sockets);
+        let lease = match self.sockets.get_mut::<dhcpv4::Socket>(self.handle).poll()? {
+            dhcpv4::Event::Configured(config) => Some(Lease {
+                address: config.address,
+                router: config.router,
+                dns_servers: config.dns_servers.iter().map(|&s| s.into()).collect(),
+            }),
+            dhcpv4::Event::Deconfigured => None,
+        };
+
+        // The client renews the lease by unicast, so it needs the address and
+        // the route to the server as well.
+        self.iface.update_ip_addrs(|ip_addrs| {
+            ip_addrs.clear();
+            if let Some(lease) = &lease {
+                ip_addrs.push(IpCidr::Ipv4(lease.address)).ok();
+            }
+        });
+        match lease.as_ref().and_then(|lease| lease.router) {
+            Some(router) => {
+                self.iface.routes_mut().add_default_ipv4_route(router).ok();
+            }
+            None => {
+                self.iface.routes_mut().remove_default_ipv4_route();
+            }
+        }
+        let old = core::mem::replace(&mut self.lease, lease.clone());
+        Some((old, lease))
+    }
+
+    /// Returns how long the client can wait before it is polled again.
+    fn poll_delay(&mut self, now: Instant) -> Duration {
+        let max = if self.lease.is_some() {
+            MAX_POLL_INTERVAL
+        } else {
+            DISCOVER_POLL_INTERVAL
+        };
+        match self.iface.poll_delay(now, &self.sockets) {
+            Some(delay) if delay < max => delay,
+            _ => max,
+        }
+    }
+}
+
+/// Returns whether `packet` is an IPv4 packet to the DHCP client port.
+fn is_dhcp_reply(packet: &[u8]) -> bool {
+    let Ok(ip_packet) = Ipv4Packet::new_checked(packet) else {
+        return false;
+    };
+    ip_packet.next_header() == IpProtocol::Udp
+        && UdpPacket::new_checked(ip_packet.payload())
+            .is_ok_and(|udp_packet| udp_packet.dst_port() == DHCP_CLIENT_PORT)
+}
+
+/// DHCP operations of interfaces.
+impl InterfaceWrapper {
+    /// Starts a DHCP client on the interface, it is polled by the DHCP task,
+    /// or the net worker with the `irq` feature.
+    pub fn setup_dhcp(&self) {
+        *self.dhcp.lock() = Some(DhcpClient::new(self));
+    }
+
+    /// Hands a received Ethernet frame to the DHCP client if it takes the
+    /// frame, returns whether the frame is taken.
+    ///
+    /// DHCP replies are only taken by the client, while unicast ARP frames
+    /// are also handled by the interface.
+    pub(crate) fn dhcp_input(&self, frame: &EthernetFrame<&[u8]>) -> bool {
+        let take = match frame.ethertype() {
+            EthernetProtocol::Ipv4 => is_dhcp_reply(frame.payload()),
+            EthernetProtocol::Arp => frame.dst_addr() == self.ether_addr,
+            _ => false,
+        };
+        if !take {
+            return false;
+        }
+        let mut dhcp = self.dhcp.lock();
+        let Some(client) = dhcp.as_mut() else {
+            return false;
+        };
+        if client.rx_queue.len() < RX_QUEUE_LEN {
+            client.rx_queue.push_back(frame.as_ref().to_vec());
+        }
+        frame.ethertype() == EthernetProtocol::Ipv4
+    }
+
+    /// Polls the DHCP client and applies the lease, returns how long it can
+    /// wait before the next poll, or `None` if there is no client.
+    pub(crate) fn poll_dhcp(&self, now: Instant) -> Option<Duration> {
+        let mut dhcp = self.dhcp.lock();
+        let client = dhcp.as_mut()?;
+        let changed = client.poll(self, now);
+        let delay = client.poll_delay(now);
+        drop(dhcp);
+        if let Some((old, new)) = changed {
+            self.update_lease(old, new);
+        }
+        Some(delay)
+    }
+
+    fn update_lease(&self, old: Option<Lease>, new: Option<Lease>) {
+        if let Some(old) = &old {
+            let new_address = new.as_ref().map(|lease| lease.address);
+            if new_address != Some(old.address) {
+                self.remove_ip_addr(IpCidr::Ipv4(old.address));
+            }
+            if old.router.is_some() && new.as_ref().and_then(|lease| lease.router) != old.router {
+                ROUTE_TABLE
+                    .lock()
+                    .remove(IpCidr::new(IpAddress::v4(0, 0, 0, 0), 0), Some(self.index))
+                    .ok();
+            }
+        }
+        let Some(lease) = new else {
+            info!("{}: DHCP lease lost", self.name);
+            self.dns_servers.lock().clear();
+            return;
+        };
+        info!("{}: DHCP leased {}", self.name, lease.address);
+        let address = IpAddress::Ipv4(lease.address.address());
+        if !self.has_ip_addr(address) {
+            self.setup_ip_addr(address, lease.address.prefix_len());
+        }
+        if let Some(router) = lease.router {
+            info!("{}: DHCP router {}", self.name, router);
+            self.setup_gateway(router.into());
+        }
+        *self.dns_servers.lock() = lease.dns_servers;
+    }
+}
+
+/// Starts the kernel task that runs the DHCP clients.
+///
+/// It also polls the interfaces, so that the replies are received without
+/// any socket in use.
+#[cfg(not(feature = "irq"))]
+pub(crate) fn spawn_dhcp_task() {
+    use super::{INTERFACES, SOCKET_SET};
+
+    axtask::spawn(|| loop {
+        SOCKET_SET.poll_interfaces();
+        let now = InterfaceWrapper::current_time();
+        let delay = INTERFACES
+            .iter()
+            .filter_map(|iface| iface.poll_dhcp(now))
+            .min()
+            .unwrap_or(MAX_POLL_INTERVAL);
+        axtask::sleep(delay.into());
+    });
+}
+
+/// The NIC of an interface seen by its DHCP client.
+struct DhcpDevice<'a> {
+    iface: &'a InterfaceWrapper,
+    rx_queue: &'a mut VecDeque<Vec<u8>>,
+}
+
+impl<'a> Device for DhcpDevice<'a> {
+    type RxToken<'b> = DhcpRxToken where Self: 'b;
+    type TxToken<'b> = DhcpTxToken<'b> where Self: 'b;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let frame = self.rx_queue.pop_front()?;
+        Some((DhcpRxToken(frame), DhcpTxToken(self.iface)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(DhcpTxToken(self.iface))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = ETHERNET_HEADER_LEN + STANDARD_MTU;
+        caps.max_burst_size = None;
+        caps.medium = Medium::Ethernet;
+        caps
+    }
+}
+
+struct DhcpRxToken(Vec<u8>);
+struct DhcpTxToken<'a>(&'a InterfaceWrapper);
+
+impl RxToken for DhcpRxToken {
+    fn preprocess(&self, _sockets: &mut SocketSet<'_>) {}
+
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.0)
+    }
+}
+
+impl<'a> TxToken for DhcpTxToken<'a> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buf = vec![0; len];
+        let result = f(&mut buf);
+        if let Ok(frame) = EthernetFrame::new_checked(&buf[..]) {
+            self.0
+                .send_frame(frame.dst_addr(), frame.ethertype(), frame.payload());
+        }
+        result
+    }
+}
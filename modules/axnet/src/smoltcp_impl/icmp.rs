@@ -0,0 +1,390 @@
+use alloc::collections::BTreeSet;
+use core::net::SocketAddr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use axerrno::{ax_err, ax_err_type, AxError, AxResult};
+use axhal::time::current_ticks;
+use axio::PollState;
+use axsync::Mutex;
+use spin::RwLock;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::icmp::{self, BindError, Endpoint, SendError};
+use smoltcp::wire::{IpAddress, IpVersion};
+
+use super::addr::{from_core_ipaddr, into_core_ipaddr};
+use super::{router, SocketSetWrapper, SocketWaiter, INTERFACES, SOCKET_SET};
+use super::{RAW_RX_BUF_LEN, RAW_TX_BUF_LEN};
+
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+/// Length of the header of echo requests.
+const ECHO_HEADER_LEN: usize = 8;
+
+/// Identifiers of the bound ICMP sockets.
+static IDENTS: Mutex<BTreeSet<u16>> = Mutex::new(BTreeSet::new());
+
+/// An ICMP datagram socket ("ping socket") that provides POSIX-like APIs.
+///
+/// Only echo requests can be sent, their identifier is replaced by the one
+/// of the socket, which is like the port of a UDP socket. Only the echo
+/// replies of the same identifier are received.
+pub struct IcmpSocket {
+    handle: SocketHandle,
+    version: IpVersion,
+    ident: RwLock<Option<u16>>,
+    local_addr: RwLock<Option<IpAddress>>,
+    peer_addr: RwLock<Option<IpAddress>>,
+    nonblock: AtomicBool,
+    recv_buf_size: AtomicUsize,
+    send_buf_size: AtomicUsize,
+    waiter: SocketWaiter,
+}
+
+impl IcmpSocket {
+    /// Creates a new ICMP socket, of ICMPv6 if `ipv6` is true, or of ICMPv4.
+    pub fn new(ipv6: bool) -> Self {
+        let socket = SocketSetWrapper::new_icmp_socket(RAW_RX_BUF_LEN, RAW_TX_BUF_LEN);
+        let handle = SOCKET_SET.add(socket);
+        Self {
+            handle,
+            version: if ipv6 {
+                IpVersion::Ipv6
+            } else {
+                IpVersion::Ipv4
+            },
+            ident: RwLock::new(None),
+            local_addr: RwLock::new(None),
+            peer_addr: RwLock::new(None),
+            nonblock: AtomicBool::new(false),
+            recv_buf_size: AtomicUsize::new(RAW_RX_BUF_LEN),
+            send_buf_size: AtomicUsize::new(RAW_TX_BUF_LEN),
+            waiter: SocketWaiter::new(),
+        }
+    }
+
+    /// Returns the bound address, the port is the identifier.
+    pub fn local_addr(&self) -> AxResult<SocketAddr> {
+        let addr = self.local_addr.read().unwrap_or(self.unspecified_addr());
+        let ident = self.ident.read().unwrap_or(0);
+        Ok(SocketAddr::new(into_core_ipaddr(addr), ident))
+    }
+
+    /// Returns the connected address, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not connected.
+    pub fn peer_addr(&self) -> AxResult<SocketAddr> {
+        let addr = self.peer_addr.read().ok_or(AxError::NotConnected)?;
+        Ok(SocketAddr::new(into_core_ipaddr(addr), 0))
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this ICMP socket into or out of nonblocking mode.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Set the TTL (time-to-live) option for this socket.
+    pub fn set_socket_ttl(&self, ttl: u8) {
+        SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+            socket.set_hop_limit(Some(ttl))
+        });
+    }
+
+    /// Returns the size of the receive buffer.
+    #[inline]
+    pub fn recv_buffer_size(&self) -> usize {
+        self.recv_buf_size.load(Ordering::Acquire)
+    }
+
+    /// Sets the size of the receive buffer (`SO_RCVBUF`).
+    ///
+    /// The packets queued in the socket are dropped.
+    pub fn set_recv_buffer_size(&self, size: usize) {
+        self.recv_buf_size.store(size, Ordering::Release);
+        self.rebuild();
+    }
+
+    /// Returns the size of the send buffer.
+    #[inline]
+    pub fn send_buffer_size(&self) -> usize {
+        self.send_buf_size.load(Ordering::Acquire)
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`).
+    ///
+    /// The packets queued in the socket are dropped.
+    pub fn set_send_buffer_size(&self, size: usize) {
+        self.send_buf_size.store(size, Ordering::Release);
+        self.rebuild();
+    }
+
+    /// Binds the socket to the given address, the port is taken as the
+    /// identifier, and a free one is chosen if it is 0.
+    pub fn bind(&self, local_addr: SocketAddr) -> AxResult {
+        let mut self_ident = self.ident.write();
+        if self_ident.is_some() {
+            return ax_err!(InvalidInput, "socket bind() failed: already bound");
+        }
+        let addr = self.check_version(from_core_ipaddr(local_addr.ip()))?;
+        if !addr.is_unspecified() && !router::is_local_addr(&INTERFACES, addr) {
+            return ax_err!(InvalidInput, "socket bind() failed: not a local address");
+        }
+
+        let mut idents = IDENTS.lock();
+        let ident = match local_addr.port() {
+            0 => get_ephemeral_ident(&idents)?,
+            ident if idents.contains(&ident) => return Err(AxError::AddrInUse),
+            ident => ident,
+        };
+        SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+            socket.bind(Endpoint::Ident(ident)).or_else(|e| match e {
+                BindError::InvalidState => ax_err!(AlreadyExists, "socket bind() failed"),
+                BindError::Unaddressable => ax_err!(InvalidInput, "socket bind() failed"),
+            })
+        })?;
+        idents.insert(ident);
+
+        *self_ident = Some(ident);
+        *self.local_addr.write() = (!addr.is_unspecified()).then_some(addr);
+        debug!(
+            "ICMP socket {}: bound on {} ident {}",
+            self.handle, addr, ident
+        );
+        Ok(())
+    }
+
+    /// Connects the socket to a remote address, only the replies from it are
+    /// received, and it is the destination of [`send`](Self::send).
+    ///
+    /// The identifier is chosen if the socket is not bound.
+    pub fn connect(&self, addr: SocketAddr) -> AxResult {
+        let peer_addr = self.check_version(from_core_ipaddr(addr.ip()))?;
+        self.bind_if_unbound()?;
+        *self.peer_addr.write() = Some(peer_addr);
+        debug!("ICMP socket {}: connected to {}", self.handle, addr);
+        Ok(())
+    }
+
+    /// Sends an echo request (with the ICMP header) to the given address, the
+    /// port is ignored. On success, returns the number of bytes written.
+    pub fn send_to(&self, buf: &[u8], remote_addr: SocketAddr) -> AxResult<usize> {
+        let remote_addr = self.check_version(from_core_ipaddr(remote_addr.ip()))?;
+        self.send_impl(buf, remote_addr)
+    }
+
+    /// Sends an echo request to the connected address.
+    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+        let remote_addr = self.peer_addr.read().ok_or(AxError::NotConnected)?;
+        self.send_impl(buf, remote_addr)
+    }
+
+    /// Receives an echo reply with the ICMP header. On success, returns the
+    /// number of bytes read and the source address.
+    ///
+    /// The reply is truncated if `buf` is too small.
+    pub fn recv_from(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
+        self.recv_impl(buf, None)
+    }
+
+    /// Receives an echo reply like [`recv_from`](Self::recv_from).
+    ///
+    /// It will return [`Err(Timeout)`](AxError::Timeout) if expired.
+    pub fn recv_from_timeout(&self, buf: &mut [u8], ticks: u64) -> AxResult<(usize, SocketAddr)> {
+        self.recv_impl(buf, Some(current_ticks() + ticks))
+    }
+
+    /// Receives an echo reply from the connected address.
+    pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        self.recv_from(buf).map(|(len, _)| len)
+    }
+
+    /// Close the socket.
+    pub fn shutdown(&self) -> AxResult {
+        Ok(())
+    }
+
+    /// Whether the socket is readable or writable.
+    pub fn poll(&self) -> AxResult<PollState> {
+        if self.ident.read().is_none() {
+            return Ok(PollState {
+                readable: false,
+                writable: true,
+            });
+        }
+        SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+            Ok(PollState {
+                readable: socket.can_recv(),
+                writable: socket.can_send(),
+            })
+        })
+    }
+}
+
+/// Private methods
+impl IcmpSocket {
+    /// Replaces the smoltcp socket with one of the new buffer sizes, which
+    /// is bound to the same identifier.
+    fn rebuild(&self) {
+        // keep the socket from being bound meanwhile
+        let ident = self.ident.write();
+        let mut socket =
+            SocketSetWrapper::new_icmp_socket(self.recv_buffer_size(), self.send_buffer_size());
+        SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |old| {
+            socket.set_hop_limit(old.hop_limit());
+            if let Some(ident) = *ident {
+                socket.bind(Endpoint::Ident(ident)).ok();
+            }
+            *old = socket;
+        });
+    }
+
+    fn unspecified_addr(&self) -> IpAddress {
+        match self.version {
+            IpVersion::Ipv4 => IpAddress::v4(0, 0, 0, 0),
+            IpVersion::Ipv6 => IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 0),
+        }
+    }
+
+    fn check_version(&self, addr: IpAddress) -> AxResult<IpAddress> {
+        if addr.version() != self.version {
+            return ax_err!(InvalidInput, "address family mismatched");
+        }
+        Ok(addr)
+    }
+
+    fn bind_if_unbound(&self) -> AxResult {
+        if self.ident.read().is_some() {
+            return Ok(());
+        }
+        self.bind(SocketAddr::new(
+            into_core_ipaddr(self.unspecified_addr()),
+            0,
+        ))
+    }
+
+    fn recv_impl(&self, buf: &mut [u8], expire_at: Option<u64>) -> AxResult<(usize, SocketAddr)> {
+        if self.ident.read().is_none() {
+            return ax_err!(NotConnected, "socket recv() failed");
+        }
+        let peer_addr = *self.peer_addr.read();
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| loop {
+                let Ok((packet, src_addr)) = socket.recv() else {
+                    return match expire_at {
+                        Some(expire_at) if current_ticks() > expire_at => Err(AxError::Timeout),
+                        _ => Err(AxError::WouldBlock),
+                    };
+                };
+                if peer_addr.is_some_and(|addr| addr != src_addr) {
+                    continue;
+                }
+                let len = packet.len().min(buf.len());
+                buf[..len].copy_from_slice(&packet[..len]);
+                return Ok((len, SocketAddr::new(into_core_ipaddr(src_addr), 0)));
+            })
+        })
+    }
+
+    fn send_impl(&self, buf: &[u8], remote_addr: IpAddress) -> AxResult<usize> {
+        let echo_request = match self.version {
+            IpVersion::Ipv4 => ICMPV4_ECHO_REQUEST,
+            IpVersion::Ipv6 => ICMPV6_ECHO_REQUEST,
+        };
+        if buf.len() < ECHO_HEADER_LEN || buf[0] != echo_request || buf[1] != 0 {
+            return ax_err!(InvalidInput, "socket send() failed: not an echo request");
+        }
+        self.bind_if_unbound()?;
+        let ident = self.ident.read().unwrap();
+
+        // The checksum is computed again when the packet is sent.
+        let mut packet = buf.to_vec();
+        packet[2..4].fill(0);
+        packet[4..6].copy_from_slice(&ident.to_be_bytes());
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+                if !socket.can_send() {
+                    return Err(AxError::WouldBlock);
+                }
+                socket
+                    .send_slice(&packet, remote_addr)
+                    .map_err(|e| match e {
+                        SendError::BufferFull => AxError::WouldBlock,
+                        SendError::Unaddressable => {
+                            ax_err_type!(ConnectionRefused, "socket send() failed")
+                        }
+                    })?;
+                Ok(buf.len())
+            })
+        })
+    }
+
+    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        if self.is_nonblocking() {
+            f()
+        } else {
+            loop {
+                #[cfg(feature = "signal")]
+                unsafe {
+                    extern "Rust" {
+                        fn current_have_signal() -> bool;
+                    }
+                    if current_have_signal() {
+                        return Err(AxError::Interrupted);
+                    }
+                }
+                self.register_waker();
+                SOCKET_SET.poll_interfaces();
+                match f() {
+                    Ok(t) => return Ok(t),
+                    Err(AxError::WouldBlock) => self.waiter.wait(),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    /// Registers the waiter to be woken when the socket may be ready.
+    fn register_waker(&self) {
+        self.waiter.arm();
+        #[cfg(feature = "irq")]
+        SOCKET_SET.register_waker::<icmp::Socket, _>(self.handle, |socket| {
+            let waker = self.waiter.waker();
+            socket.register_recv_waker(&waker);
+            socket.register_send_waker(&waker);
+        });
+    }
+}
+
+impl Drop for IcmpSocket {
+    fn drop(&mut self) {
+        if let Some(ident) = *self.ident.read() {
+            IDENTS.lock().remove(&ident);
+        }
+        SOCKET_SET.remove(self.handle);
+    }
+}
+
+/// Chooses an identifier that is not used by any socket.
+fn get_ephemeral_ident(idents: &BTreeSet<u16>) -> AxResult<u16> {
+    const IDENT_START: u16 = 0xc000;
+    static CURR: Mutex<u16> = Mutex::new(IDENT_START);
+    let mut curr = CURR.lock();
+
+    for _ in 0..u16::MAX {
+        let ident = *curr;
+        *curr = curr.checked_add(1).unwrap_or(IDENT_START);
+        if !idents.contains(&ident) {
+            return Ok(ident);
+        }
+    }
+    ax_err!(AddrInUse, "no free ICMP identifier")
+}
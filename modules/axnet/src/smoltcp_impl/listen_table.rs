@@ -1,26 +1,56 @@
-use alloc::{boxed::Box, collections::VecDeque};
-use core::ops::{Deref, DerefMut};
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "irq")]
+use core::task::Waker;
 
 use axerrno::{ax_err, AxError, AxResult};
 use axsync::Mutex;
 use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{self, State};
-use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
+use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint, IpVersion};
 
-use super::{SocketSetWrapper, LISTEN_QUEUE_SIZE, SOCKET_SET};
+use super::congestion;
+use super::tcp::TcpConfig;
+use super::{LISTEN_QUEUE_SIZE, SOCKET_SET};
 
 const PORT_NUM: usize = 65536;
 
 struct ListenTableEntry {
+    /// Tells the listening sockets sharing a port apart.
+    id: usize,
     listen_endpoint: IpListenEndpoint,
-    syn_queue: VecDeque<SocketHandle>,
+    /// The IP version of connections to accept on the unspecified address,
+    /// `None` for both.
+    version: Option<IpVersion>,
+    /// Whether the port can be shared by other listening sockets with
+    /// `SO_REUSEPORT`.
+    reuse_port: bool,
+    /// The options that the new connections take.
+    config: TcpConfig,
+    /// The connections not accepted yet, with the local and remote endpoints
+    /// their flows are registered with.
+    syn_queue: VecDeque<(SocketHandle, (IpEndpoint, IpEndpoint))>,
+    /// Wakes the listening socket when a connection is established.
+    #[cfg(feature = "irq")]
+    waker: Option<Waker>,
 }
 
 impl ListenTableEntry {
-    pub fn new(listen_endpoint: IpListenEndpoint) -> Self {
+    pub fn new(
+        listen_endpoint: IpListenEndpoint,
+        version: Option<IpVersion>,
+        reuse_port: bool,
+        config: TcpConfig,
+    ) -> Self {
         Self {
+            id: NEXT_LISTENER_ID.fetch_add(1, Ordering::Relaxed),
             listen_endpoint,
+            version,
+            reuse_port,
+            config,
             syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            #[cfg(feature = "irq")]
+            waker: None,
         }
     }
 
@@ -28,21 +58,42 @@ impl ListenTableEntry {
     fn can_accept(&self, dst: IpAddress) -> bool {
         match self.listen_endpoint.addr {
             Some(addr) => addr == dst,
-            None => true,
+            None => self
+                .version
+                .map_or(true, |version| version == dst.version()),
         }
     }
+
+    /// Whether it conflicts with a new listening socket on the same port.
+    fn conflicts_with(&self, addr: Option<IpAddress>, reuse_port: bool) -> bool {
+        let overlapped = match (self.listen_endpoint.addr, addr) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+        overlapped && !(self.reuse_port && reuse_port)
+    }
 }
 
 impl Drop for ListenTableEntry {
     fn drop(&mut self) {
-        for &handle in &self.syn_queue {
+        for &(handle, (local, remote)) in &self.syn_queue {
             SOCKET_SET.remove(handle);
+            congestion::unregister(local, remote);
         }
     }
 }
 
+/// The next ID of listening sockets.
+static NEXT_LISTENER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The listening sockets on every port.
+///
+/// Several sockets with `SO_REUSEPORT` can listen on the same port, and the
+/// new connections are distributed among them in turn.
 pub struct ListenTable {
-    tcp: Box<[Mutex<Option<Box<ListenTableEntry>>>]>,
+    tcp: Box<[Mutex<Vec<ListenTableEntry>>]>,
+    /// Counts the connections to choose a socket among the ones sharing a port.
+    next_choice: AtomicUsize,
 }
 
 impl ListenTable {
@@ -50,49 +101,75 @@ impl ListenTable {
         let tcp = unsafe {
             let mut buf = Box::new_uninit_slice(PORT_NUM);
             for i in 0..PORT_NUM {
-                buf[i].write(Mutex::new(None));
+                buf[i].write(Mutex::new(Vec::new()));
             }
             buf.assume_init()
         };
-        Self { tcp }
+        Self {
+            tcp,
+            next_choice: AtomicUsize::new(0),
+        }
     }
 
     pub fn can_listen(&self, port: u16) -> bool {
-        self.tcp[port as usize].lock().is_none()
+        self.tcp[port as usize].lock().is_empty()
     }
 
-    pub fn listen(&self, listen_endpoint: IpListenEndpoint) -> AxResult {
+    /// Starts listening, returns the ID of the listening socket.
+    pub fn listen(
+        &self,
+        listen_endpoint: IpListenEndpoint,
+        version: Option<IpVersion>,
+        reuse_port: bool,
+        config: TcpConfig,
+    ) -> AxResult<usize> {
         let port = listen_endpoint.port;
         assert_ne!(port, 0);
-        let mut entry = self.tcp[port as usize].lock();
-        if entry.is_none() {
-            *entry = Some(Box::new(ListenTableEntry::new(listen_endpoint)));
-            Ok(())
-        } else {
-            ax_err!(AddrInUse, "socket listen() failed")
+        let mut entries = self.tcp[port as usize].lock();
+        if entries
+            .iter()
+            .any(|entry| entry.conflicts_with(listen_endpoint.addr, reuse_port))
+        {
+            return ax_err!(AddrInUse, "socket listen() failed");
         }
+        let entry = ListenTableEntry::new(listen_endpoint, version, reuse_port, config);
+        let id = entry.id;
+        entries.push(entry);
+        Ok(id)
     }
 
-    pub fn unlisten(&self, port: u16) {
+    pub fn unlisten(&self, port: u16, id: usize) {
         debug!("TCP socket unlisten on {}", port);
-        *self.tcp[port as usize].lock() = None;
+        let mut entries = self.tcp[port as usize].lock();
+        let entry = entries
+            .iter()
+            .position(|entry| entry.id == id)
+            .map(|idx| entries.swap_remove(idx));
+        // the sockets in the SYN queue are removed without the lock held
+        drop(entries);
+        drop(entry);
     }
 
-    pub fn can_accept(&self, port: u16) -> AxResult<bool> {
-        if let Some(entry) = self.tcp[port as usize].lock().deref() {
-            Ok(entry.syn_queue.iter().any(|&handle| is_connected(handle)))
-        } else {
-            ax_err!(InvalidInput, "socket accept() failed: not listen")
-        }
+    pub fn can_accept(&self, port: u16, id: usize) -> AxResult<bool> {
+        self.with_entry(port, id, |entry| {
+            Ok(entry
+                .syn_queue
+                .iter()
+                .any(|&(handle, _)| is_connected(handle)))
+        })
     }
 
-    pub fn accept(&self, port: u16) -> AxResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
-        if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
+    pub fn accept(
+        &self,
+        port: u16,
+        id: usize,
+    ) -> AxResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
+        self.with_entry(port, id, |entry| {
             let syn_queue = &mut entry.syn_queue;
             let (idx, addr_tuple) = syn_queue
                 .iter()
                 .enumerate()
-                .find_map(|(idx, &handle)| {
+                .find_map(|(idx, &(handle, _))| {
                     is_connected(handle).then(|| (idx, get_addr_tuple(handle)))
                 })
                 .ok_or(AxError::WouldBlock)?; // wait for connection
@@ -103,11 +180,35 @@ impl ListenTable {
                     syn_queue.len()
                 );
             }
-            let handle = syn_queue.swap_remove_front(idx).unwrap();
+            let (handle, (local, remote)) = syn_queue.swap_remove_front(idx).unwrap();
+            if (local, remote) != addr_tuple {
+                // the half-open connection was reset, and the socket took
+                // another one
+                congestion::unregister(local, remote);
+                entry.config.register_flow(addr_tuple.0, addr_tuple.1);
+            }
             Ok((handle, addr_tuple))
-        } else {
-            ax_err!(InvalidInput, "socket accept() failed: not listen")
-        }
+        })
+    }
+
+    /// Updates the options that the new connections take.
+    pub fn set_config(&self, port: u16, id: usize, config: TcpConfig) {
+        self.with_entry(port, id, |entry| {
+            entry.config = config;
+            Ok(())
+        })
+        .ok();
+    }
+
+    /// Registers the waker of the listening socket, it is woken when any new
+    /// connection to it is established.
+    #[cfg(feature = "irq")]
+    pub fn register_waker(&self, port: u16, id: usize, waker: Waker) {
+        self.with_entry(port, id, |entry| {
+            entry.waker = Some(waker);
+            Ok(())
+        })
+        .ok();
     }
 
     pub fn incoming_tcp_packet(
@@ -116,25 +217,58 @@ impl ListenTable {
         dst: IpEndpoint,
         sockets: &mut SocketSet<'_>,
     ) {
-        if let Some(entry) = self.tcp[dst.port as usize].lock().deref_mut() {
-            if !entry.can_accept(dst.addr) {
-                // not listening on this address
-                return;
-            }
-            if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
-                // SYN queue is full, drop the packet
-                warn!("SYN queue overflow!");
-                return;
-            }
-            let mut socket = SocketSetWrapper::new_tcp_socket();
-            if socket.listen(entry.listen_endpoint).is_ok() {
-                let handle = sockets.add(socket);
-                debug!(
-                    "TCP socket {}: prepare for connection {} -> {}",
-                    handle, src, entry.listen_endpoint
-                );
-                entry.syn_queue.push_back(handle);
-            }
+        let mut entries = self.tcp[dst.port as usize].lock();
+        // the sockets listening on the exact address take precedence over the
+        // ones on the unspecified address
+        let exact = entries
+            .iter()
+            .any(|entry| entry.listen_endpoint.addr == Some(dst.addr));
+        let takes = |entry: &ListenTableEntry| {
+            entry.can_accept(dst.addr) && (!exact || entry.listen_endpoint.addr.is_some())
+        };
+        let count = entries.iter().filter(|entry| takes(entry)).count();
+        if count == 0 {
+            // not listening on this address
+            return;
+        }
+        let nth = self.next_choice.fetch_add(1, Ordering::Relaxed) % count;
+        let entry = entries
+            .iter_mut()
+            .filter(|entry| takes(entry))
+            .nth(nth)
+            .unwrap();
+        if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
+            // SYN queue is full, drop the packet
+            warn!("SYN queue overflow!");
+            return;
+        }
+        let mut socket = entry.config.new_socket();
+        // the socket wakes its wakers when its state changes
+        #[cfg(feature = "irq")]
+        if let Some(waker) = &entry.waker {
+            socket.register_recv_waker(waker);
+        }
+        if socket.listen(entry.listen_endpoint).is_ok() {
+            let handle = sockets.add(socket);
+            debug!(
+                "TCP socket {}: prepare for connection {} -> {}",
+                handle, src, entry.listen_endpoint
+            );
+            entry.syn_queue.push_back((handle, (dst, src)));
+            entry.config.register_flow(dst, src);
+        }
+    }
+
+    fn with_entry<R>(
+        &self,
+        port: u16,
+        id: usize,
+        f: impl FnOnce(&mut ListenTableEntry) -> AxResult<R>,
+    ) -> AxResult<R> {
+        let mut entries = self.tcp[port as usize].lock();
+        match entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => f(entry),
+            None => ax_err!(InvalidInput, "socket accept() failed: not listen"),
         }
     }
 }
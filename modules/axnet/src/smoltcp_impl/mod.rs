@@ -1,15 +1,33 @@
 mod addr;
 mod bench;
+mod bpf;
+mod congestion;
+#[cfg(feature = "dhcp")]
+mod dhcp;
 mod dns;
+mod icmp;
 mod listen_table;
+mod loopback;
+mod ndp;
+mod neighbor;
+mod packet;
+mod raw;
+mod route;
+mod router;
 
 mod tcp;
 mod udp;
-use alloc::vec;
-use axerrno::{AxError, AxResult};
+mod waiter;
+#[cfg(feature = "irq")]
+mod worker;
+use alloc::{format, string::String, vec, vec::Vec};
+use axerrno::{ax_err_type, AxError, AxResult};
+use core::alloc::Layout;
 use core::cell::RefCell;
 use core::ops::DerefMut;
+use core::sync::atomic::{AtomicBool, Ordering};
 
+use axalloc::KmemCache;
 use axdriver::prelude::*;
 use axhal::time::{current_time_nanos, NANOS_PER_MICROS};
 use axsync::Mutex;
@@ -19,12 +37,26 @@ use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
 use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
 use smoltcp::socket::{self, AnySocket, Socket};
 use smoltcp::time::Instant;
-use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
+use smoltcp::wire::{
+    EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpProtocol, IpVersion, Ipv6Address,
+};
 
 use self::listen_table::ListenTable;
-
+use self::loopback::LoopbackDev;
+use self::neighbor::NeighborCache;
+use self::route::ROUTE_TABLE;
+use self::router::Router;
+use self::waiter::SocketWaiter;
+
+pub use self::bench::{bench_tcp_loopback, bench_tcp_transmit};
+pub use self::bpf::{BpfContext, BpfInsn, BpfProgram, BPF_MAXINSNS};
+pub use self::congestion::CongestionControl;
 pub use self::dns::dns_query;
-pub use self::tcp::TcpSocket;
+pub use self::icmp::IcmpSocket;
+pub use self::packet::{PacketAddr, PacketSocket, PacketStats, PacketType, ETH_P_ALL};
+pub use self::raw::RawSocket;
+pub use self::route::Route;
+pub use self::tcp::{TcpInfo, TcpSocket};
 pub use self::udp::UdpSocket;
 pub use addr::{from_core_sockaddr, into_core_sockaddr};
 #[allow(unused)]
@@ -37,34 +69,44 @@ macro_rules! env_or_default {
     };
 }
 
-const DNS_SEVER: &str = "8.8.8.8";
+/// Used when no DNS server is leased by DHCP.
+const DEFAULT_DNS_SERVER: &str = "8.8.8.8";
+/// The most DNS servers a DNS socket takes.
+const DNS_MAX_SERVER_COUNT: usize = 4;
+
+const IP: &str = env_or_default!("AX_IP");
+const GATEWAY: &str = env_or_default!("AX_GW");
+const IP_PREFIX: u8 = 24;
+const IP6: &str = env_or_default!("AX_IP6");
+const GATEWAY6: &str = env_or_default!("AX_GW6");
+const IP6_PREFIX: u8 = 64;
 
 const RANDOM_SEED: u64 = 0xA2CE_05A2_CE05_A2CE;
 const STANDARD_MTU: usize = 1500;
+/// The default sizes of the socket buffers, which are changed by `SO_RCVBUF`
+/// and `SO_SNDBUF`.
 const TCP_RX_BUF_LEN: usize = 64 * 1024;
 const TCP_TX_BUF_LEN: usize = 64 * 1024;
 const UDP_RX_BUF_LEN: usize = 64 * 1024;
 const UDP_TX_BUF_LEN: usize = 64 * 1024;
+const RAW_RX_BUF_LEN: usize = 64 * 1024;
+const RAW_TX_BUF_LEN: usize = 64 * 1024;
 const LISTEN_QUEUE_SIZE: usize = 512;
 
+/// The object cache of the TCP and UDP socket buffers of the default size.
+static SOCK_BUFFER_CACHE: KmemCache =
+    KmemCache::new("sock_buffer", Layout::new::<[u8; TCP_RX_BUF_LEN]>());
+
 static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
 static SOCKET_SET: LazyInit<SocketSetWrapper> = LazyInit::new();
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "ip")] {
-        mod loopback;
-        static LOOPBACK_DEV: LazyInit<Mutex<LoopbackDev>> = LazyInit::new();
-        static LOOPBACK: LazyInit<Mutex<Interface>> = LazyInit::new();
-        use self::loopback::LoopbackDev;
-    }
-    else {
-        const IP: &str = env_or_default!("AX_IP");
-        const GATEWAY: &str = env_or_default!("AX_GW");
-        const IP_PREFIX: u8 = 24;
-
-        static ETH0: LazyInit<InterfaceWrapper> = LazyInit::new();
-    }
-}
+/// All the interfaces, the loopback interface is always the first one.
+static INTERFACES: LazyInit<Vec<InterfaceWrapper>> = LazyInit::new();
+/// The IP stack over all the interfaces.
+static IFACE: LazyInit<Mutex<Interface>> = LazyInit::new();
+/// Whether the addresses of the interfaces have changed since they were
+/// copied to the IP stack.
+static IP_ADDRS_CHANGED: AtomicBool = AtomicBool::new(false);
 
 struct SocketSetWrapper<'a>(Mutex<SocketSet<'a>>);
 
@@ -72,11 +114,37 @@ struct DeviceWrapper {
     inner: RefCell<AxNetDevice>, // use `RefCell` is enough since it's wrapped in `Mutex` in `InterfaceWrapper`.
 }
 
+enum InterfaceDevice {
+    Loopback(LoopbackDev),
+    Ethernet(DeviceWrapper),
+}
+
 struct InterfaceWrapper {
-    name: &'static str,
+    name: String,
+    index: usize,
     ether_addr: EthernetAddress,
-    dev: Mutex<DeviceWrapper>,
-    iface: Mutex<Interface>,
+    ip_addrs: Mutex<Vec<IpCidr>>,
+    dev: Mutex<InterfaceDevice>,
+    neighbors: Mutex<NeighborCache>,
+    /// The DNS servers leased by DHCP.
+    dns_servers: Mutex<Vec<IpAddress>>,
+    #[cfg(feature = "dhcp")]
+    dhcp: Mutex<Option<self::dhcp::DhcpClient>>,
+    /// The IRQ of the NIC, if its handler is registered.
+    #[cfg(feature = "irq")]
+    irq: Option<usize>,
+}
+
+/// Allocates a zeroed socket buffer, from its object cache if it's of the
+/// default size.
+fn socket_buffer(len: usize) -> Vec<u8> {
+    let mut buf = if len == SOCK_BUFFER_CACHE.layout().size() {
+        SOCK_BUFFER_CACHE.scope(|| Vec::with_capacity(len))
+    } else {
+        Vec::with_capacity(len)
+    };
+    buf.resize(len, 0);
+    buf
 }
 
 impl<'a> SocketSetWrapper<'a> {
@@ -84,27 +152,67 @@ impl<'a> SocketSetWrapper<'a> {
         Self(Mutex::new(SocketSet::new(vec![])))
     }
 
-    pub fn new_tcp_socket() -> socket::tcp::Socket<'a> {
-        let tcp_rx_buffer = socket::tcp::SocketBuffer::new(vec![0; TCP_RX_BUF_LEN]);
-        let tcp_tx_buffer = socket::tcp::SocketBuffer::new(vec![0; TCP_TX_BUF_LEN]);
+    pub fn new_tcp_socket(rx_buf_len: usize, tx_buf_len: usize) -> socket::tcp::Socket<'a> {
+        let tcp_rx_buffer = socket::tcp::SocketBuffer::new(socket_buffer(rx_buf_len));
+        let tcp_tx_buffer = socket::tcp::SocketBuffer::new(socket_buffer(tx_buf_len));
         socket::tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer)
     }
 
-    pub fn new_udp_socket() -> socket::udp::Socket<'a> {
+    pub fn new_udp_socket(rx_buf_len: usize, tx_buf_len: usize) -> socket::udp::Socket<'a> {
         let udp_rx_buffer = socket::udp::PacketBuffer::new(
             vec![socket::udp::PacketMetadata::EMPTY; 256],
-            vec![0; UDP_RX_BUF_LEN],
+            socket_buffer(rx_buf_len),
         );
         let udp_tx_buffer = socket::udp::PacketBuffer::new(
             vec![socket::udp::PacketMetadata::EMPTY; 256],
-            vec![0; UDP_TX_BUF_LEN],
+            socket_buffer(tx_buf_len),
         );
         socket::udp::Socket::new(udp_rx_buffer, udp_tx_buffer)
     }
 
+    pub fn new_raw_socket(
+        ip_version: IpVersion,
+        ip_protocol: IpProtocol,
+        rx_buf_len: usize,
+        tx_buf_len: usize,
+    ) -> socket::raw::Socket<'a> {
+        let raw_rx_buffer = socket::raw::PacketBuffer::new(
+            vec![socket::raw::PacketMetadata::EMPTY; 64],
+            vec![0; rx_buf_len],
+        );
+        let raw_tx_buffer = socket::raw::PacketBuffer::new(
+            vec![socket::raw::PacketMetadata::EMPTY; 64],
+            vec![0; tx_buf_len],
+        );
+        socket::raw::Socket::new(ip_version, ip_protocol, raw_rx_buffer, raw_tx_buffer)
+    }
+
+    pub fn new_icmp_socket(rx_buf_len: usize, tx_buf_len: usize) -> socket::icmp::Socket<'a> {
+        let icmp_rx_buffer = socket::icmp::PacketBuffer::new(
+            vec![socket::icmp::PacketMetadata::EMPTY; 64],
+            vec![0; rx_buf_len],
+        );
+        let icmp_tx_buffer = socket::icmp::PacketBuffer::new(
+            vec![socket::icmp::PacketMetadata::EMPTY; 64],
+            vec![0; tx_buf_len],
+        );
+        socket::icmp::Socket::new(icmp_rx_buffer, icmp_tx_buffer)
+    }
+
     pub fn new_dns_socket() -> socket::dns::Socket<'a> {
-        let server_addr = DNS_SEVER.parse().expect("invalid DNS server address");
-        socket::dns::Socket::new(&[server_addr], vec![])
+        let mut servers: Vec<IpAddress> = INTERFACES
+            .iter()
+            .flat_map(|iface| iface.dns_servers.lock().clone())
+            .take(DNS_MAX_SERVER_COUNT)
+            .collect();
+        if servers.is_empty() {
+            servers.push(
+                DEFAULT_DNS_SERVER
+                    .parse()
+                    .expect("invalid DNS server address"),
+            );
+        }
+        socket::dns::Socket::new(&servers, vec![])
     }
 
     pub fn add<T: AnySocket<'a>>(&self, socket: T) -> SocketHandle {
@@ -128,7 +236,22 @@ impl<'a> SocketSetWrapper<'a> {
     {
         let mut set = self.0.lock();
         let socket = set.get_mut(handle);
-        f(socket)
+        let ret = f(socket);
+        drop(set);
+        // the socket may have packets to send
+        #[cfg(feature = "irq")]
+        worker::kick();
+        ret
+    }
+
+    /// Registers the wakers of a socket by `f`, unlike
+    /// [`with_socket_mut`](Self::with_socket_mut) it does not wake the worker.
+    #[cfg(feature = "irq")]
+    pub fn register_waker<T: AnySocket<'a>, F>(&self, handle: SocketHandle, f: F)
+    where
+        F: FnOnce(&mut T),
+    {
+        f(self.0.lock().get_mut(handle))
     }
 
     pub fn bind_check(&self, addr: IpAddress, _port: u16) -> AxResult {
@@ -147,17 +270,25 @@ impl<'a> SocketSetWrapper<'a> {
     }
 
     pub fn poll_interfaces(&self) {
-        #[cfg(feature = "ip")]
-        {
-            let timestamp =
-                Instant::from_micros_const((current_time_nanos() / NANOS_PER_MICROS) as i64);
-            let mut sockets = self.0.lock();
-            LOOPBACK
-                .lock()
-                .poll(timestamp, LOOPBACK_DEV.lock().deref_mut(), &mut sockets);
+        let mut sockets = self.0.lock();
+        let mut iface = IFACE.lock();
+        if IP_ADDRS_CHANGED.swap(false, Ordering::AcqRel) {
+            update_ip_addrs(&mut iface);
         }
-        #[cfg(not(feature = "ip"))]
-        ETH0.poll(&self.0);
+        iface.poll(
+            InterfaceWrapper::current_time(),
+            &mut Router::new(&INTERFACES),
+            &mut sockets,
+        );
+        tcp::reap_orphans(&mut sockets);
+    }
+
+    /// Returns how long the stack can wait before it is polled again, or
+    /// `None` if it has nothing to do.
+    #[cfg(feature = "irq")]
+    pub fn poll_delay(&self, now: Instant) -> Option<smoltcp::time::Duration> {
+        let sockets = self.0.lock();
+        IFACE.lock().poll_delay(now, &sockets)
     }
 
     pub fn remove(&self, handle: SocketHandle) {
@@ -168,17 +299,19 @@ impl<'a> SocketSetWrapper<'a> {
 
 #[allow(unused)]
 impl InterfaceWrapper {
-    fn new(name: &'static str, dev: AxNetDevice, ether_addr: EthernetAddress) -> Self {
-        let mut config = Config::new(HardwareAddress::Ethernet(ether_addr));
-        config.random_seed = RANDOM_SEED;
-
-        let mut dev = DeviceWrapper::new(dev);
-        let iface = Mutex::new(Interface::new(config, &mut dev, Self::current_time()));
+    fn new(name: String, index: usize, dev: InterfaceDevice, ether_addr: EthernetAddress) -> Self {
         Self {
             name,
+            index,
             ether_addr,
+            ip_addrs: Mutex::new(Vec::new()),
             dev: Mutex::new(dev),
-            iface,
+            neighbors: Mutex::new(NeighborCache::new()),
+            dns_servers: Mutex::new(Vec::new()),
+            #[cfg(feature = "dhcp")]
+            dhcp: Mutex::new(None),
+            #[cfg(feature = "irq")]
+            irq: None,
         }
     }
 
@@ -187,36 +320,125 @@ impl InterfaceWrapper {
     }
 
     pub fn name(&self) -> &str {
-        self.name
+        &self.name
     }
 
     pub fn ethernet_address(&self) -> EthernetAddress {
         self.ether_addr
     }
 
+    pub fn ip_addrs(&self) -> Vec<IpCidr> {
+        self.ip_addrs.lock().clone()
+    }
+
+    /// Assigns an address to the interface, and adds the route to its network.
+    ///
+    /// The IP stack takes the new address at its next poll, so it can be
+    /// called while receiving packets.
     pub fn setup_ip_addr(&self, ip: IpAddress, prefix_len: u8) {
-        let mut iface = self.iface.lock();
-        iface.update_ip_addrs(|ip_addrs| {
-            ip_addrs.push(IpCidr::new(ip, prefix_len)).unwrap();
-        });
+        let cidr = IpCidr::new(ip, prefix_len);
+        self.ip_addrs.lock().push(cidr);
+        ROUTE_TABLE
+            .lock()
+            .add(Route {
+                dest: cidr,
+                gateway: None,
+                iface: self.index,
+                metric: 0,
+            })
+            .ok();
+        IP_ADDRS_CHANGED.store(true, Ordering::Release);
+    }
+
+    /// Removes an address from the interface, and the route to its network.
+    pub fn remove_ip_addr(&self, cidr: IpCidr) {
+        self.ip_addrs.lock().retain(|&c| c != cidr);
+        ROUTE_TABLE.lock().remove(cidr, Some(self.index)).ok();
+        IP_ADDRS_CHANGED.store(true, Ordering::Release);
     }
 
+    /// Adds a default route through `gateway` on this interface.
     pub fn setup_gateway(&self, gateway: IpAddress) {
-        let mut iface = self.iface.lock();
-        match gateway {
-            IpAddress::Ipv4(v4) => iface.routes_mut().add_default_ipv4_route(v4).unwrap(),
+        let dest = match gateway {
+            IpAddress::Ipv4(_) => IpCidr::new(IpAddress::v4(0, 0, 0, 0), 0),
+            IpAddress::Ipv6(_) => IpCidr::new(IpAddress::Ipv6(Ipv6Address::UNSPECIFIED), 0),
         };
+        ROUTE_TABLE
+            .lock()
+            .add(Route {
+                dest,
+                gateway: Some(gateway),
+                iface: self.index,
+                metric: 0,
+            })
+            .ok();
     }
+}
 
-    pub fn poll(&self, sockets: &Mutex<SocketSet>) {
-        let mut dev = self.dev.lock();
-        let mut iface = self.iface.lock();
-        let mut sockets = sockets.lock();
-        let timestamp = Self::current_time();
-        iface.poll(timestamp, dev.deref_mut(), &mut sockets);
+/// Copies the addresses of all the interfaces to the IP stack, the loopback
+/// addresses go last, so that smoltcp does not choose them as the source
+/// address of packets to other hosts.
+fn update_ip_addrs(iface: &mut Interface) {
+    iface.update_ip_addrs(|ip_addrs| {
+        ip_addrs.clear();
+        for iw in INTERFACES.iter().skip(1).chain(INTERFACES.first()) {
+            for &cidr in iw.ip_addrs.lock().iter() {
+                if ip_addrs.push(cidr).is_err() {
+                    warn!("too many addresses, {} is ignored", cidr);
+                }
+            }
+        }
+    });
+}
+
+/// Finds the interface by name.
+fn interface_by_name(name: &str) -> AxResult<&'static InterfaceWrapper> {
+    INTERFACES
+        .iter()
+        .find(|iface| iface.name == name)
+        .ok_or(AxError::NotFound)
+}
+
+/// Chooses the source address to connect to `addr`, according to the routing
+/// table.
+fn source_address(addr: IpAddress) -> AxResult<IpAddress> {
+    if router::is_local_addr(&INTERFACES, addr) {
+        return Ok(addr);
+    }
+    let route = ROUTE_TABLE
+        .lock()
+        .lookup(addr)
+        .ok_or_else(|| ax_err_type!(ConnectionRefused, "no route to host"))?;
+    INTERFACES[route.iface]
+        .source_address(addr, route.gateway.unwrap_or(addr))
+        .ok_or_else(|| ax_err_type!(ConnectionRefused, "no address on the egress interface"))
+}
+
+/// Checks that `addr` is reachable without a gateway, for the sockets with
+/// `SO_DONTROUTE`.
+fn check_on_link(addr: IpAddress) -> AxResult {
+    if router::is_local_addr(&INTERFACES, addr) {
+        return Ok(());
+    }
+    match ROUTE_TABLE.lock().lookup(addr) {
+        Some(route) if route.gateway.is_none() => Ok(()),
+        _ => Err(ax_err_type!(
+            ConnectionRefused,
+            "destination is not on link"
+        )),
     }
 }
 
+/// Returns the largest TCP segment sent to `addr`.
+fn tcp_mss(addr: IpAddress) -> usize {
+    const TCP_HEADER_LEN: usize = 20;
+    let ip_header_len = match addr.version() {
+        IpVersion::Ipv4 => 20,
+        IpVersion::Ipv6 => 40,
+    };
+    STANDARD_MTU - ip_header_len - TCP_HEADER_LEN
+}
+
 impl DeviceWrapper {
     fn new(inner: AxNetDevice) -> Self {
         Self {
@@ -338,69 +560,218 @@ pub fn poll_interfaces() {
     SOCKET_SET.poll_interfaces();
 }
 
-/// Benchmark raw socket transmit bandwidth.
-pub fn bench_transmit() {
-    #[cfg(not(feature = "ip"))]
-    ETH0.dev.lock().bench_transmit_bandwidth();
+/// Adds a route to the kernel routing table.
+///
+/// The egress interface is given by name, `gateway` is `None` if the
+/// destination network is directly reachable through the interface.
+pub fn add_route(
+    dest: IpAddress,
+    prefix_len: u8,
+    gateway: Option<IpAddress>,
+    iface: &str,
+) -> AxResult {
+    let iface = interface_by_name(iface)?;
+    ROUTE_TABLE.lock().add(Route {
+        dest: IpCidr::new(dest, prefix_len),
+        gateway,
+        iface: iface.index,
+        metric: 0,
+    })
 }
 
-/// Benchmark raw socket receive bandwidth.
-pub fn bench_receive() {
-    #[cfg(not(feature = "ip"))]
-    ETH0.dev.lock().bench_receive_bandwidth();
+/// Removes the routes to the given network from the kernel routing table.
+pub fn del_route(dest: IpAddress, prefix_len: u8) -> AxResult {
+    ROUTE_TABLE
+        .lock()
+        .remove(IpCidr::new(dest, prefix_len), None)
 }
 
-/// Add multicast_addr to the loopback device.
-pub fn add_membership(_multicast_addr: IpAddress, _interface_addr: IpAddress) {
-    #[cfg(feature = "ip")]
-    {
-        let timestamp =
-            Instant::from_micros_const((current_time_nanos() / NANOS_PER_MICROS) as i64);
-        let _ = LOOPBACK.lock().join_multicast_group(
-            LOOPBACK_DEV.lock().deref_mut(),
-            _multicast_addr,
-            timestamp,
-        );
+/// Returns all the entries of the kernel routing table.
+pub fn routes() -> Vec<Route> {
+    ROUTE_TABLE.lock().routes().to_vec()
+}
+
+/// Returns the name of the interface with the given index.
+pub fn interface_name(index: usize) -> Option<String> {
+    INTERFACES.get(index).map(|iface| iface.name.clone())
+}
+
+/// Information of a network interface.
+#[derive(Clone, Debug)]
+pub struct InterfaceInfo {
+    /// Index of the interface, the loopback interface is always 0.
+    pub index: usize,
+    /// Name of the interface, e.g. `lo` and `eth0`.
+    pub name: String,
+    /// The hardware address, all zeros for the loopback interface.
+    pub ether_addr: [u8; 6],
+    /// The addresses assigned to the interface.
+    pub ip_addrs: Vec<IpCidr>,
+    /// The largest IP packet that the interface sends.
+    pub mtu: usize,
+    /// Whether it is the loopback interface.
+    pub is_loopback: bool,
+}
+
+/// Returns the information of all the interfaces, ordered by index.
+pub fn interfaces() -> Vec<InterfaceInfo> {
+    INTERFACES
+        .iter()
+        .map(|iface| InterfaceInfo {
+            index: iface.index,
+            name: iface.name.clone(),
+            ether_addr: iface.ether_addr.0,
+            ip_addrs: iface.ip_addrs(),
+            mtu: STANDARD_MTU,
+            is_loopback: iface.index == 0,
+        })
+        .collect()
+}
+
+/// Assigns an address to the interface, the route to its network is added as
+/// well.
+pub fn add_ip_addr(iface: &str, ip: IpAddress, prefix_len: u8) -> AxResult {
+    let iface = interface_by_name(iface)?;
+    if iface.has_ip_addr(ip) {
+        return Err(ax_err_type!(AlreadyExists, "address already assigned"));
     }
+    iface.setup_ip_addr(ip, prefix_len);
+    Ok(())
 }
 
-pub(crate) fn init(_net_dev: AxNetDevice) {
-    #[cfg(feature = "ip")]
-    {
-        let mut device = LoopbackDev::new(Medium::Ip);
-        let config = Config::new(smoltcp::wire::HardwareAddress::Ip);
+/// Removes an address from the interface, with the route to its network.
+pub fn del_ip_addr(iface: &str, ip: IpAddress, prefix_len: u8) -> AxResult {
+    let iface = interface_by_name(iface)?;
+    let cidr = IpCidr::new(ip, prefix_len);
+    if !iface.ip_addrs.lock().contains(&cidr) {
+        return Err(ax_err_type!(NotFound, "address not assigned"));
+    }
+    iface.remove_ip_addr(cidr);
+    Ok(())
+}
 
-        let mut iface = Interface::new(
-            config,
-            &mut device,
-            Instant::from_micros_const((current_time_nanos() / NANOS_PER_MICROS) as i64),
-        );
-        iface.update_ip_addrs(|ip_addrs| {
-            ip_addrs
-                .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
-                .unwrap();
-        });
-        LOOPBACK.init_by(Mutex::new(iface));
-        LOOPBACK_DEV.init_by(Mutex::new(device));
+fn first_ethernet_device() -> Option<&'static InterfaceWrapper> {
+    INTERFACES
+        .iter()
+        .find(|iface| matches!(*iface.dev.lock(), InterfaceDevice::Ethernet(_)))
+}
+
+/// Benchmark raw socket transmit bandwidth.
+pub fn bench_transmit() {
+    if let Some(iface) = first_ethernet_device() {
+        if let InterfaceDevice::Ethernet(dev) = iface.dev.lock().deref_mut() {
+            dev.bench_transmit_bandwidth();
+        }
     }
+}
 
-    #[cfg(not(feature = "ip"))]
-    {
-        let ether_addr = EthernetAddress(_net_dev.mac_address().0);
-        let eth0 = InterfaceWrapper::new("eth0", _net_dev, ether_addr);
+/// Benchmark raw socket receive bandwidth.
+pub fn bench_receive() {
+    if let Some(iface) = first_ethernet_device() {
+        if let InterfaceDevice::Ethernet(dev) = iface.dev.lock().deref_mut() {
+            dev.bench_receive_bandwidth();
+        }
+    }
+}
 
-        let ip = IP.parse().expect("invalid IP address");
-        let gateway = GATEWAY.parse().expect("invalid gateway IP address");
-        eth0.setup_ip_addr(ip, IP_PREFIX);
-        eth0.setup_gateway(gateway);
+/// Joins the multicast group, reports are sent through the interface chosen
+/// by the routing table.
+pub fn add_membership(multicast_addr: IpAddress, _interface_addr: IpAddress) {
+    let _ = IFACE.lock().join_multicast_group(
+        &mut Router::new(&INTERFACES),
+        multicast_addr,
+        InterfaceWrapper::current_time(),
+    );
+}
 
-        ETH0.init_by(eth0);
-        info!("created net interface {:?}:", ETH0.name());
-        info!("  ether:    {}", ETH0.ethernet_address());
-        info!("  ip:       {}/{}", ip, IP_PREFIX);
-        info!("  gateway:  {}", gateway);
+pub(crate) fn init(net_devs: Vec<AxNetDevice>) {
+    let mut interfaces = vec![InterfaceWrapper::new(
+        String::from("lo"),
+        0,
+        InterfaceDevice::Loopback(LoopbackDev::new()),
+        EthernetAddress([0; 6]),
+    )];
+    for dev in net_devs {
+        let index = interfaces.len();
+        let ether_addr = EthernetAddress(dev.mac_address().0);
+        #[cfg(feature = "irq")]
+        let irq = dev.irq_num().filter(|&irq| worker::register_irq(irq));
+        let dev = InterfaceDevice::Ethernet(DeviceWrapper::new(dev));
+        let iface = InterfaceWrapper::new(format!("eth{}", index - 1), index, dev, ether_addr);
+        #[cfg(feature = "irq")]
+        let iface = InterfaceWrapper { irq, ..iface };
+        interfaces.push(iface);
+    }
+    INTERFACES.init_by(interfaces);
+
+    let mut config = Config::new(HardwareAddress::Ip);
+    config.random_seed = RANDOM_SEED;
+    let iface = Interface::new(
+        config,
+        &mut Router::new(&INTERFACES),
+        InterfaceWrapper::current_time(),
+    );
+    IFACE.init_by(Mutex::new(iface));
+
+    INTERFACES[0].setup_ip_addr(IpAddress::v4(127, 0, 0, 1), 8);
+    INTERFACES[0].setup_ip_addr(IpAddress::Ipv6(Ipv6Address::LOOPBACK), 128);
+    for iface in INTERFACES.iter().skip(1) {
+        iface.setup_ipv6();
+    }
+    // The addresses of the first NIC may be given at compile time.
+    if let Some(eth0) = INTERFACES.get(1) {
+        if let Ok(ip) = IP.parse() {
+            eth0.setup_ip_addr(ip, IP_PREFIX);
+        }
+        if let Ok(gateway) = GATEWAY.parse() {
+            eth0.setup_gateway(gateway);
+        }
+        if let Ok(ip) = IP6.parse() {
+            eth0.setup_ip_addr(ip, IP6_PREFIX);
+        }
+        if let Ok(gateway) = GATEWAY6.parse() {
+            eth0.setup_gateway(gateway);
+        }
+    }
+    for iface in INTERFACES.iter() {
+        info!("created net interface {:?}:", iface.name());
+        if iface.index != 0 {
+            info!("  ether:    {}", iface.ethernet_address());
+        }
+        #[cfg(feature = "irq")]
+        if let Some(irq) = iface.irq {
+            info!("  irq:      {}", irq);
+        }
+        for cidr in iface.ip_addrs() {
+            info!("  ip:       {}", cidr);
+        }
+    }
+    for route in ROUTE_TABLE.lock().routes() {
+        if let Some(gateway) = route.gateway {
+            info!(
+                "  gateway:  {} via {}",
+                gateway,
+                INTERFACES[route.iface].name()
+            );
+        }
     }
 
     SOCKET_SET.init_by(SocketSetWrapper::new());
     LISTEN_TABLE.init_by(ListenTable::new());
+
+    // The NICs without a static address are configured by DHCP.
+    #[cfg(feature = "dhcp")]
+    if INTERFACES.len() > 1 {
+        let static_ip = IP.parse::<IpAddress>().is_ok();
+        for iface in INTERFACES.iter().skip(1 + static_ip as usize) {
+            info!("{}: starting DHCP", iface.name());
+            iface.setup_dhcp();
+        }
+        #[cfg(not(feature = "irq"))]
+        dhcp::spawn_dhcp_task();
+    }
+
+    // The worker also runs the DHCP clients.
+    #[cfg(feature = "irq")]
+    worker::spawn_worker();
 }
@@ -0,0 +1,354 @@
+//! IPv6 neighbor discovery (RFC 4861) and stateless address
+//! autoconfiguration (RFC 4862) of Ethernet interfaces.
+//!
+//! The other ICMPv6 messages are left to smoltcp. Lifetimes in router
+//! advertisements are not tracked, the learned addresses and routes stay until
+//! a router withdraws itself.
+
+use alloc::{vec, vec::Vec};
+use smoltcp::wire::{
+    EthernetAddress, EthernetProtocol, IpAddress, IpCidr, IpProtocol, Ipv6Address, Ipv6Packet,
+};
+
+use super::route::{Route, ROUTE_TABLE};
+use super::router::multicast_ether_addr;
+use super::InterfaceWrapper;
+
+const ROUTER_SOLICIT: u8 = 133;
+const ROUTER_ADVERT: u8 = 134;
+const NEIGHBOR_SOLICIT: u8 = 135;
+const NEIGHBOR_ADVERT: u8 = 136;
+const REDIRECT: u8 = 137;
+
+const OPT_SOURCE_LLADDR: u8 = 1;
+const OPT_TARGET_LLADDR: u8 = 2;
+const OPT_PREFIX_INFO: u8 = 3;
+
+const ADVERT_FLAG_SOLICITED: u8 = 0x40;
+const ADVERT_FLAG_OVERRIDE: u8 = 0x20;
+const PREFIX_FLAG_ON_LINK: u8 = 0x80;
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+
+/// Messages with a smaller hop limit have been forwarded, and are invalid.
+const NDP_HOP_LIMIT: u8 = 255;
+/// The prefix length of addresses made from an interface identifier.
+const SLAAC_PREFIX_LEN: u8 = 64;
+
+/// Returns whether `packet` is an NDP message to be handled by the interface.
+pub(crate) fn is_ndp_packet(packet: &[u8]) -> bool {
+    let Ok(ip_packet) = Ipv6Packet::new_checked(packet) else {
+        return false;
+    };
+    ip_packet.next_header() == IpProtocol::Icmpv6
+        && packet
+            .get(ip_packet.header_len())
+            .is_some_and(|ty| (ROUTER_SOLICIT..=REDIRECT).contains(ty))
+}
+
+/// Returns whether `addr` is an IPv6 link-local address.
+pub(crate) fn is_link_local(addr: IpAddress) -> bool {
+    matches!(addr, IpAddress::Ipv6(addr) if addr.is_link_local())
+}
+
+/// Iterates the options of an NDP message as `(type, option)`.
+struct NdpOptions<'a>(&'a [u8]);
+
+impl<'a> Iterator for NdpOptions<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The length is in units of 8 bytes, and a zero length is invalid.
+        let len = *self.0.get(1)? as usize * 8;
+        if len == 0 || len > self.0.len() {
+            return None;
+        }
+        let (option, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some((option[0], option))
+    }
+}
+
+fn lladdr_option(options: &[u8], ty: u8) -> Option<EthernetAddress> {
+    NdpOptions(options)
+        .find(|&(option_ty, _)| option_ty == ty)
+        .map(|(_, option)| EthernetAddress::from_bytes(&option[2..8]))
+}
+
+/// Computes the ICMPv6 checksum, including the IPv6 pseudo header.
+fn icmpv6_checksum(src_addr: &Ipv6Address, dst_addr: &Ipv6Address, message: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    let mut add = |data: &[u8]| {
+        for chunk in data.chunks(2) {
+            sum += u16::from_be_bytes([chunk[0], chunk.get(1).copied().unwrap_or(0)]) as u32;
+        }
+    };
+    add(src_addr.as_bytes());
+    add(dst_addr.as_bytes());
+    add(&(message.len() as u32).to_be_bytes());
+    add(&[0, 0, 0, IpProtocol::Icmpv6.into()]);
+    add(message);
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Neighbor discovery of Ethernet interfaces.
+impl InterfaceWrapper {
+    /// The modified EUI-64 interface identifier made from the MAC address.
+    fn interface_id(&self) -> [u8; 8] {
+        let mac = self.ether_addr.as_bytes();
+        let mut id = [0xff; 8];
+        id[..3].copy_from_slice(&mac[..3]);
+        id[4] = 0xfe;
+        id[5..].copy_from_slice(&mac[3..]);
+        // flip the universal/local bit
+        id[0] ^= 0x02;
+        id
+    }
+
+    /// Returns the address made from `prefix` and the interface identifier.
+    fn address_with_prefix(&self, prefix: &[u8]) -> Ipv6Address {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&prefix[..8]);
+        bytes[8..].copy_from_slice(&self.interface_id());
+        Ipv6Address(bytes)
+    }
+
+    /// Brings up IPv6 on the interface: assigns the link-local address, routes
+    /// the multicast packets to the link, and asks the routers for prefixes.
+    pub fn setup_ipv6(&self) {
+        let link_local = self.address_with_prefix(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0]);
+        self.setup_ip_addr(IpAddress::Ipv6(link_local), SLAAC_PREFIX_LEN);
+        ROUTE_TABLE
+            .lock()
+            .add(Route {
+                dest: IpCidr::new(IpAddress::v6(0xff00, 0, 0, 0, 0, 0, 0, 0), 8),
+                gateway: None,
+                iface: self.index,
+                metric: 0,
+            })
+            .ok();
+        self.send_router_solicit(link_local);
+    }
+
+    /// Handles an NDP message in an IPv6 packet from `src_ether_addr`.
+    pub(crate) fn process_ndp(&self, packet: &[u8], src_ether_addr: EthernetAddress) {
+        let Ok(ip_packet) = Ipv6Packet::new_checked(packet) else {
+            return;
+        };
+        if ip_packet.hop_limit() != NDP_HOP_LIMIT {
+            return;
+        }
+        let src_addr = ip_packet.src_addr();
+        let dst_addr = ip_packet.dst_addr();
+        let message = &packet[ip_packet.header_len()..ip_packet.total_len()];
+        if message.len() < 8 || icmpv6_checksum(&src_addr, &dst_addr, message) != 0 {
+            return;
+        }
+        match message[0] {
+            NEIGHBOR_SOLICIT => self.process_neighbor_solicit(src_addr, message, src_ether_addr),
+            NEIGHBOR_ADVERT => self.process_neighbor_advert(message, src_ether_addr),
+            ROUTER_ADVERT => self.process_router_advert(src_addr, message),
+            // We are not a router, and redirects are not supported.
+            _ => {}
+        }
+    }
+
+    fn process_neighbor_solicit(
+        &self,
+        src_addr: Ipv6Address,
+        message: &[u8],
+        src_ether_addr: EthernetAddress,
+    ) {
+        if message.len() < 24 {
+            return;
+        }
+        let target = Ipv6Address::from_bytes(&message[8..24]);
+        if !self.has_ip_addr(IpAddress::Ipv6(target)) {
+            return;
+        }
+        if src_addr.is_unspecified() {
+            // Duplicate address detection of another host, defend our address.
+            let dst_addr = Ipv6Address::LINK_LOCAL_ALL_NODES;
+            let dst_ether_addr = multicast_ether_addr(IpAddress::Ipv6(dst_addr));
+            self.send_neighbor_advert(target, dst_addr, dst_ether_addr, false);
+            return;
+        }
+        let src_lladdr = lladdr_option(&message[24..], OPT_SOURCE_LLADDR);
+        if let Some(lladdr) = src_lladdr {
+            self.learn_neighbor(src_addr, lladdr, true);
+        }
+        self.send_neighbor_advert(target, src_addr, src_lladdr.unwrap_or(src_ether_addr), true);
+    }
+
+    fn process_neighbor_advert(&self, message: &[u8], src_ether_addr: EthernetAddress) {
+        if message.len() < 24 {
+            return;
+        }
+        let target = Ipv6Address::from_bytes(&message[8..24]);
+        if target.is_multicast() {
+            return;
+        }
+        if self.has_ip_addr(IpAddress::Ipv6(target)) {
+            warn!(
+                "{}: {} is also used by {}",
+                self.name, target, src_ether_addr
+            );
+            return;
+        }
+        let lladdr = lladdr_option(&message[24..], OPT_TARGET_LLADDR).unwrap_or(src_ether_addr);
+        self.learn_neighbor(target, lladdr, false);
+    }
+
+    fn process_router_advert(&self, src_addr: Ipv6Address, message: &[u8]) {
+        if message.len() < 16 || !src_addr.is_link_local() {
+            return;
+        }
+        let router_lifetime = u16::from_be_bytes([message[6], message[7]]);
+        let options = &message[16..];
+        if let Some(lladdr) = lladdr_option(options, OPT_SOURCE_LLADDR) {
+            self.learn_neighbor(src_addr, lladdr, true);
+        }
+        for (_, option) in NdpOptions(options).filter(|&(ty, _)| ty == OPT_PREFIX_INFO) {
+            if option.len() != 32 {
+                continue;
+            }
+            let prefix_len = option[2];
+            let flags = option[3];
+            let valid_lifetime = u32::from_be_bytes([option[4], option[5], option[6], option[7]]);
+            let prefix = Ipv6Address::from_bytes(&option[16..32]);
+            if prefix.is_link_local() || prefix_len > 128 || valid_lifetime == 0 {
+                continue;
+            }
+            if flags & PREFIX_FLAG_ON_LINK != 0 {
+                ROUTE_TABLE
+                    .lock()
+                    .add(Route {
+                        dest: IpCidr::new(IpAddress::Ipv6(prefix), prefix_len),
+                        gateway: None,
+                        iface: self.index,
+                        metric: 0,
+                    })
+                    .ok();
+            }
+            if flags & PREFIX_FLAG_AUTONOMOUS != 0 && prefix_len == SLAAC_PREFIX_LEN {
+                let addr = IpAddress::Ipv6(self.address_with_prefix(prefix.as_bytes()));
+                if !self.has_ip_addr(addr) {
+                    info!("{}: autoconfigured {}/{}", self.name, addr, prefix_len);
+                    self.setup_ip_addr(addr, prefix_len);
+                }
+            }
+        }
+
+        let default_route = Route {
+            dest: IpCidr::new(IpAddress::Ipv6(Ipv6Address::UNSPECIFIED), 0),
+            gateway: Some(IpAddress::Ipv6(src_addr)),
+            iface: self.index,
+            metric: 0,
+        };
+        let mut route_table = ROUTE_TABLE.lock();
+        if router_lifetime > 0 {
+            if route_table.add(default_route).is_ok() {
+                info!("{}: default router {}", self.name, src_addr);
+            }
+        } else if route_table.routes().contains(&default_route) {
+            info!("{}: router {} withdrawn", self.name, src_addr);
+            route_table
+                .remove(default_route.dest, Some(self.index))
+                .ok();
+        }
+    }
+
+    /// Records the hardware address of a neighbor, and sends out the packets
+    /// waiting for it.
+    fn learn_neighbor(&self, addr: Ipv6Address, lladdr: EthernetAddress, create: bool) {
+        if !lladdr.is_unicast() {
+            return;
+        }
+        let pending =
+            self.neighbors
+                .lock()
+                .fill(IpAddress::Ipv6(addr), lladdr, Self::current_time(), create);
+        for packet in pending {
+            self.send_frame(lladdr, EthernetProtocol::Ipv6, &packet);
+        }
+    }
+
+    /// Sends a neighbor solicitation to resolve `target`.
+    pub(crate) fn send_neighbor_solicit(&self, target: IpAddress) {
+        let (IpAddress::Ipv6(target), Some(IpAddress::Ipv6(src_addr))) =
+            (target, self.source_address(target, target))
+        else {
+            return;
+        };
+        debug!("{}: neighbor solicitation for {}", self.name, target);
+        // The solicited-node multicast address of the target.
+        let mut dst_addr = [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xff, 0, 0, 0];
+        dst_addr[13..].copy_from_slice(&target.as_bytes()[13..]);
+        let dst_addr = Ipv6Address(dst_addr);
+
+        let mut message = vec![0; 32];
+        message[0] = NEIGHBOR_SOLICIT;
+        message[8..24].copy_from_slice(target.as_bytes());
+        self.write_lladdr_option(&mut message[24..], OPT_SOURCE_LLADDR);
+        let dst_ether_addr = multicast_ether_addr(IpAddress::Ipv6(dst_addr));
+        self.send_ndp(src_addr, dst_addr, dst_ether_addr, message);
+    }
+
+    fn send_neighbor_advert(
+        &self,
+        target: Ipv6Address,
+        dst_addr: Ipv6Address,
+        dst_ether_addr: EthernetAddress,
+        solicited: bool,
+    ) {
+        let mut message = vec![0; 32];
+        message[0] = NEIGHBOR_ADVERT;
+        message[4] = if solicited {
+            ADVERT_FLAG_SOLICITED | ADVERT_FLAG_OVERRIDE
+        } else {
+            ADVERT_FLAG_OVERRIDE
+        };
+        message[8..24].copy_from_slice(target.as_bytes());
+        self.write_lladdr_option(&mut message[24..], OPT_TARGET_LLADDR);
+        self.send_ndp(target, dst_addr, dst_ether_addr, message);
+    }
+
+    fn send_router_solicit(&self, src_addr: Ipv6Address) {
+        let mut message = vec![0; 16];
+        message[0] = ROUTER_SOLICIT;
+        self.write_lladdr_option(&mut message[8..], OPT_SOURCE_LLADDR);
+        let dst_addr = Ipv6Address::LINK_LOCAL_ALL_ROUTERS;
+        let dst_ether_addr = multicast_ether_addr(IpAddress::Ipv6(dst_addr));
+        self.send_ndp(src_addr, dst_addr, dst_ether_addr, message);
+    }
+
+    fn write_lladdr_option(&self, buf: &mut [u8], ty: u8) {
+        buf[0] = ty;
+        buf[1] = 1;
+        buf[2..8].copy_from_slice(self.ether_addr.as_bytes());
+    }
+
+    fn send_ndp(
+        &self,
+        src_addr: Ipv6Address,
+        dst_addr: Ipv6Address,
+        dst_ether_addr: EthernetAddress,
+        mut message: Vec<u8>,
+    ) {
+        let checksum = icmpv6_checksum(&src_addr, &dst_addr, &message);
+        message[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+        let mut packet = vec![0; 40 + message.len()];
+        let mut ip_packet = Ipv6Packet::new_unchecked(&mut packet[..]);
+        ip_packet.set_version(6);
+        ip_packet.set_payload_len(message.len() as u16);
+        ip_packet.set_next_header(IpProtocol::Icmpv6);
+        ip_packet.set_hop_limit(NDP_HOP_LIMIT);
+        ip_packet.set_src_addr(src_addr);
+        ip_packet.set_dst_addr(dst_addr);
+        ip_packet.payload_mut().copy_from_slice(&message);
+        self.send_frame(dst_ether_addr, EthernetProtocol::Ipv6, &packet);
+    }
+}
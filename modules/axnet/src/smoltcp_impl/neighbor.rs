@@ -0,0 +1,116 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{EthernetAddress, IpAddress};
+
+/// How long a resolved entry stays valid.
+const ENTRY_LIFETIME: Duration = Duration::from_secs(60);
+/// The minimal interval between two requests for the same address.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+/// Packets queued for an unresolved address, the oldest ones are dropped.
+const MAX_PENDING_PACKETS: usize = 16;
+
+enum Neighbor {
+    Reachable {
+        hardware_addr: EthernetAddress,
+        expires_at: Instant,
+    },
+    Incomplete {
+        pending: VecDeque<Vec<u8>>,
+        requested_at: Option<Instant>,
+    },
+}
+
+/// The neighbor (ARP) cache of an Ethernet interface.
+///
+/// Unlike the one in smoltcp, it holds the packets sent to an address being
+/// resolved, and sends them out once the address is resolved.
+pub(crate) struct NeighborCache {
+    entries: BTreeMap<IpAddress, Neighbor>,
+}
+
+impl NeighborCache {
+    pub const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the hardware address of `addr` if it is resolved.
+    pub fn lookup(&mut self, addr: IpAddress, now: Instant) -> Option<EthernetAddress> {
+        match self.entries.get(&addr) {
+            Some(&Neighbor::Reachable {
+                hardware_addr,
+                expires_at,
+            }) => {
+                if expires_at > now {
+                    return Some(hardware_addr);
+                }
+                self.entries.remove(&addr);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Queues a packet until `addr` is resolved.
+    ///
+    /// Returns whether a request for `addr` should be sent.
+    pub fn enqueue(&mut self, addr: IpAddress, packet: Vec<u8>, now: Instant) -> bool {
+        let entry = self.entries.entry(addr).or_insert(Neighbor::Incomplete {
+            pending: VecDeque::new(),
+            requested_at: None,
+        });
+        if let Neighbor::Reachable { .. } = entry {
+            *entry = Neighbor::Incomplete {
+                pending: VecDeque::new(),
+                requested_at: None,
+            };
+        }
+        let Neighbor::Incomplete {
+            pending,
+            requested_at,
+        } = entry
+        else {
+            unreachable!()
+        };
+        if pending.len() >= MAX_PENDING_PACKETS {
+            pending.pop_front();
+        }
+        pending.push_back(packet);
+        if requested_at.is_some_and(|at| at + REQUEST_INTERVAL > now) {
+            false
+        } else {
+            *requested_at = Some(now);
+            true
+        }
+    }
+
+    /// Records the hardware address of `addr`, and returns the packets waiting
+    /// for it.
+    ///
+    /// A new entry is only created if `create` is true, otherwise only the
+    /// existing entries are refreshed.
+    pub fn fill(
+        &mut self,
+        addr: IpAddress,
+        hardware_addr: EthernetAddress,
+        now: Instant,
+        create: bool,
+    ) -> VecDeque<Vec<u8>> {
+        let reachable = Neighbor::Reachable {
+            hardware_addr,
+            expires_at: now + ENTRY_LIFETIME,
+        };
+        match self.entries.insert(addr, reachable) {
+            Some(Neighbor::Incomplete { pending, .. }) => pending,
+            Some(Neighbor::Reachable { .. }) => VecDeque::new(),
+            None => {
+                if !create {
+                    self.entries.remove(&addr);
+                }
+                VecDeque::new()
+            }
+        }
+    }
+}
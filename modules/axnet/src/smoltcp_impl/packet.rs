@@ -0,0 +1,477 @@
+use alloc::{collections::VecDeque, sync::Arc, vec, vec::Vec};
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicUsize, Ordering};
+
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::current_ticks;
+use axio::PollState;
+use axsync::Mutex;
+
+use smoltcp::wire::{EthernetAddress, EthernetFrame, EthernetProtocol, ETHERNET_HEADER_LEN};
+
+use super::bpf::{BpfContext, BpfProgram};
+use super::{
+    InterfaceDevice, InterfaceWrapper, SocketWaiter, INTERFACES, SOCKET_SET, STANDARD_MTU,
+};
+
+/// The protocol that matches the frames of all ethertypes.
+pub const ETH_P_ALL: u16 = 0x0003;
+
+/// The hardware type of Ethernet interfaces.
+const ARPHRD_ETHER: u16 = 1;
+/// The hardware type of the loopback interface, as Linux uses.
+const ARPHRD_LOOPBACK: u16 = 772;
+
+/// The most frames waiting in the receive queue of a packet socket.
+const PACKET_RX_QUEUE_LEN: usize = 256;
+
+/// The open packet sockets, which see every frame on the interfaces.
+static TAPS: Mutex<Vec<Arc<PacketTap>>> = Mutex::new(Vec::new());
+
+/// To whom a frame is sent, as `sll_pkttype`.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketType {
+    /// To this host.
+    Host = 0,
+    /// To all hosts on the link.
+    Broadcast = 1,
+    /// To a multicast group.
+    Multicast = 2,
+    /// To another host, only seen in promiscuous mode.
+    OtherHost = 3,
+    /// Sent by this host.
+    Outgoing = 4,
+}
+
+/// The link-layer address of a frame, like `struct sockaddr_ll`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PacketAddr {
+    /// The ethertype.
+    pub protocol: u16,
+    /// The index of the interface in axnet, 0 is the loopback interface.
+    pub iface: usize,
+    /// The hardware type of the interface.
+    pub hatype: u16,
+    pub pkttype: PacketType,
+    /// The source address of received frames, or the destination of sent
+    /// ones.
+    pub hw_addr: [u8; 6],
+}
+
+impl PacketAddr {
+    /// An address to send frames of `protocol` to `hw_addr` through the
+    /// interface `iface`.
+    pub fn new(protocol: u16, iface: usize, hw_addr: [u8; 6]) -> Self {
+        Self {
+            protocol,
+            iface,
+            hatype: hardware_type(iface),
+            pkttype: PacketType::Host,
+            hw_addr,
+        }
+    }
+}
+
+/// Counters of the frames that matched a packet socket.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PacketStats {
+    /// Frames queued or dropped.
+    pub packets: u32,
+    /// Frames dropped as the receive queue is full.
+    pub drops: u32,
+}
+
+/// The part of a packet socket that the interfaces deliver frames to.
+struct PacketTap {
+    /// Whether the link-layer header is removed (`SOCK_DGRAM`).
+    cooked: bool,
+    /// The ethertype to receive, 0 means receiving nothing.
+    protocol: AtomicU16,
+    /// The bound interface, `usize::MAX` means all.
+    iface: AtomicUsize,
+    filter: Mutex<Option<BpfProgram>>,
+    rx_queue: Mutex<VecDeque<(Vec<u8>, PacketAddr)>>,
+    packets: AtomicU32,
+    drops: AtomicU32,
+    waiter: SocketWaiter,
+}
+
+impl PacketTap {
+    fn deliver(&self, iface: usize, frame: &EthernetFrame<&[u8]>, pkttype: PacketType) {
+        let protocol = self.protocol.load(Ordering::Acquire);
+        let ethertype = u16::from(frame.ethertype());
+        if protocol == 0 || (protocol != ETH_P_ALL && protocol != ethertype) {
+            return;
+        }
+        // Frames sent by the host are only seen by the sockets of all ethertypes.
+        if pkttype == PacketType::Outgoing && protocol != ETH_P_ALL {
+            return;
+        }
+        let bound = self.iface.load(Ordering::Acquire);
+        if bound != usize::MAX && bound != iface {
+            return;
+        }
+
+        let data = if self.cooked {
+            frame.payload()
+        } else {
+            frame.as_ref()
+        };
+        let hatype = hardware_type(iface);
+        let len = match self.filter.lock().as_ref() {
+            Some(filter) => {
+                let ctx = BpfContext {
+                    protocol: ethertype,
+                    pkttype: pkttype as u8,
+                    ifindex: iface as u32 + 1,
+                    hatype,
+                };
+                filter.run(data, &ctx) as usize
+            }
+            None => data.len(),
+        };
+        if len == 0 {
+            return;
+        }
+        let hw_addr = if pkttype == PacketType::Outgoing {
+            frame.dst_addr()
+        } else {
+            frame.src_addr()
+        };
+        let addr = PacketAddr {
+            protocol: ethertype,
+            iface,
+            hatype,
+            pkttype,
+            hw_addr: hw_addr.0,
+        };
+
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        let mut rx_queue = self.rx_queue.lock();
+        if rx_queue.len() >= PACKET_RX_QUEUE_LEN {
+            self.drops.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        rx_queue.push_back((data[..len.min(data.len())].to_vec(), addr));
+        drop(rx_queue);
+        self.waiter.wake();
+    }
+}
+
+fn hardware_type(iface: usize) -> u16 {
+    if iface == 0 {
+        ARPHRD_LOOPBACK
+    } else {
+        ARPHRD_ETHER
+    }
+}
+
+/// Gives a frame on the interface `iface` to the packet sockets, except the
+/// one sending it.
+pub(crate) fn tap_frame(iface: usize, frame: &[u8], pkttype: PacketType, origin: Option<usize>) {
+    let taps = TAPS.lock();
+    if taps.is_empty() {
+        return;
+    }
+    let Ok(frame) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    for tap in taps.iter() {
+        if origin != Some(Arc::as_ptr(tap) as usize) {
+            tap.deliver(iface, &frame, pkttype);
+        }
+    }
+}
+
+/// Like [`tap_frame`], for an IP packet on the loopback interface, which is
+/// seen with an Ethernet header of zero addresses as on Linux.
+pub(crate) fn tap_loopback_packet(packet: &[u8], pkttype: PacketType) {
+    if TAPS.lock().is_empty() {
+        return;
+    }
+    let ethertype = match packet.first().map(|b| b >> 4) {
+        Some(4) => EthernetProtocol::Ipv4,
+        Some(6) => EthernetProtocol::Ipv6,
+        _ => return,
+    };
+    let mut frame = vec![0; ETHERNET_HEADER_LEN + packet.len()];
+    EthernetFrame::new_unchecked(&mut frame[..]).set_ethertype(ethertype);
+    frame[ETHERNET_HEADER_LEN..].copy_from_slice(packet);
+    tap_frame(0, &frame, pkttype, None);
+}
+
+/// A packet socket that receives and sends link-layer frames, like
+/// `AF_PACKET` sockets of Linux.
+///
+/// A raw socket sees the whole Ethernet frames, and a cooked one only the
+/// payload, with the link-layer information in [`PacketAddr`]. The frames
+/// are copied before the IP stack processes them, so the socket does not
+/// take them from the stack.
+pub struct PacketSocket {
+    tap: Arc<PacketTap>,
+    nonblock: AtomicBool,
+}
+
+impl PacketSocket {
+    /// Creates a packet socket receiving the frames of `protocol` on all the
+    /// interfaces. It is cooked (`SOCK_DGRAM`) if `cooked` is true, or raw
+    /// (`SOCK_RAW`).
+    pub fn new(cooked: bool, protocol: u16) -> Self {
+        let tap = Arc::new(PacketTap {
+            cooked,
+            protocol: AtomicU16::new(protocol),
+            iface: AtomicUsize::new(usize::MAX),
+            filter: Mutex::new(None),
+            rx_queue: Mutex::new(VecDeque::new()),
+            packets: AtomicU32::new(0),
+            drops: AtomicU32::new(0),
+            waiter: SocketWaiter::new(),
+        });
+        TAPS.lock().push(tap.clone());
+        Self {
+            tap,
+            nonblock: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns whether the link-layer header is removed.
+    pub fn is_cooked(&self) -> bool {
+        self.tap.cooked
+    }
+
+    /// Returns the bound protocol and interface. The interface is `None` if
+    /// not bound to any.
+    pub fn local_addr(&self) -> (u16, Option<usize>) {
+        let iface = self.tap.iface.load(Ordering::Acquire);
+        (
+            self.tap.protocol.load(Ordering::Acquire),
+            (iface != usize::MAX).then_some(iface),
+        )
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this packet socket into or out of nonblocking mode.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Binds the socket to the interface `iface`, or all if it is `None`, and
+    /// receives the frames of `protocol` from then on. The protocol is not
+    /// changed if it is 0.
+    pub fn bind(&self, protocol: u16, iface: Option<usize>) -> AxResult {
+        if iface.is_some_and(|iface| iface >= INTERFACES.len()) {
+            return ax_err!(NotFound, "socket bind() failed: no such interface");
+        }
+        if protocol != 0 {
+            self.tap.protocol.store(protocol, Ordering::Release);
+        }
+        self.tap
+            .iface
+            .store(iface.unwrap_or(usize::MAX), Ordering::Release);
+        Ok(())
+    }
+
+    /// Filters the received frames by the program, replacing the old one.
+    pub fn attach_filter(&self, filter: BpfProgram) {
+        *self.tap.filter.lock() = Some(filter);
+    }
+
+    /// Removes the filter, or returns [`Err(NotFound)`](AxError::NotFound) if
+    /// there is none.
+    pub fn detach_filter(&self) -> AxResult {
+        match self.tap.filter.lock().take() {
+            Some(_) => Ok(()),
+            None => ax_err!(NotFound, "no filter attached"),
+        }
+    }
+
+    /// Returns the counters and resets them.
+    pub fn take_stats(&self) -> PacketStats {
+        PacketStats {
+            packets: self.tap.packets.swap(0, Ordering::Relaxed),
+            drops: self.tap.drops.swap(0, Ordering::Relaxed),
+        }
+    }
+
+    /// Sends a frame through the interface in `addr`.
+    ///
+    /// A raw socket sends `buf` as the whole frame, and a cooked one sends it
+    /// as the payload to the hardware address and of the protocol in `addr`.
+    pub fn send_to(&self, buf: &[u8], addr: &PacketAddr) -> AxResult<usize> {
+        let Some(iface) = INTERFACES.get(addr.iface) else {
+            return ax_err!(NotFound, "socket send() failed: no such interface");
+        };
+        let frame = if self.tap.cooked {
+            let mut frame = vec![0; ETHERNET_HEADER_LEN + buf.len()];
+            let mut header = EthernetFrame::new_unchecked(&mut frame[..]);
+            header.set_dst_addr(EthernetAddress(addr.hw_addr));
+            header.set_src_addr(iface.ether_addr);
+            header.set_ethertype(EthernetProtocol::from(addr.protocol));
+            frame[ETHERNET_HEADER_LEN..].copy_from_slice(buf);
+            frame
+        } else {
+            if buf.len() < ETHERNET_HEADER_LEN {
+                return ax_err!(InvalidInput, "socket send() failed: frame too short");
+            }
+            buf.to_vec()
+        };
+        if frame.len() > ETHERNET_HEADER_LEN + STANDARD_MTU {
+            return ax_err!(InvalidInput, "socket send() failed: frame too long");
+        }
+        let origin = Arc::as_ptr(&self.tap) as usize;
+        self.block_on(|| iface.send_raw_frame(&frame, origin))?;
+        Ok(buf.len())
+    }
+
+    /// Sends a frame through the bound interface, only for raw sockets, as
+    /// the frame has the destination.
+    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+        let (protocol, iface) = self.local_addr();
+        let Some(iface) = iface else {
+            return ax_err!(NotConnected, "socket send() failed: not bound");
+        };
+        if self.tap.cooked {
+            return ax_err!(NotConnected, "socket send() failed: no destination");
+        }
+        self.send_to(buf, &PacketAddr::new(protocol, iface, [0; 6]))
+    }
+
+    /// Receives a frame. On success, returns the length of the frame, which
+    /// may be larger than `buf` and then the frame is truncated, and the
+    /// link-layer address of it. The frame is not removed if `peek` is true.
+    pub fn recv_from(&self, buf: &mut [u8], peek: bool) -> AxResult<(usize, PacketAddr)> {
+        self.recv_impl(buf, peek, None)
+    }
+
+    /// Receives a frame like [`recv_from`](Self::recv_from).
+    ///
+    /// It will return [`Err(Timeout)`](AxError::Timeout) if expired.
+    pub fn recv_from_timeout(
+        &self,
+        buf: &mut [u8],
+        peek: bool,
+        ticks: u64,
+    ) -> AxResult<(usize, PacketAddr)> {
+        self.recv_impl(buf, peek, Some(current_ticks() + ticks))
+    }
+
+    /// Close the socket.
+    pub fn shutdown(&self) -> AxResult {
+        Ok(())
+    }
+
+    /// Whether the socket is readable or writable.
+    pub fn poll(&self) -> AxResult<PollState> {
+        SOCKET_SET.poll_interfaces();
+        Ok(PollState {
+            readable: !self.tap.rx_queue.lock().is_empty(),
+            writable: true,
+        })
+    }
+}
+
+/// Private methods
+impl PacketSocket {
+    fn recv_impl(
+        &self,
+        buf: &mut [u8],
+        peek: bool,
+        expire_at: Option<u64>,
+    ) -> AxResult<(usize, PacketAddr)> {
+        self.block_on(|| {
+            let mut rx_queue = self.tap.rx_queue.lock();
+            let Some((data, addr)) = rx_queue.front() else {
+                return match expire_at {
+                    Some(expire_at) if current_ticks() > expire_at => Err(AxError::Timeout),
+                    _ => Err(AxError::WouldBlock),
+                };
+            };
+            let (len, addr) = (data.len(), *addr);
+            let copy_len = len.min(buf.len());
+            buf[..copy_len].copy_from_slice(&data[..copy_len]);
+            if !peek {
+                rx_queue.pop_front();
+            }
+            Ok((len, addr))
+        })
+    }
+
+    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        if self.is_nonblocking() {
+            f()
+        } else {
+            loop {
+                #[cfg(feature = "signal")]
+                unsafe {
+                    extern "Rust" {
+                        fn current_have_signal() -> bool;
+                    }
+                    if current_have_signal() {
+                        return Err(AxError::Interrupted);
+                    }
+                }
+                self.tap.waiter.arm();
+                SOCKET_SET.poll_interfaces();
+                match f() {
+                    Ok(t) => return Ok(t),
+                    Err(AxError::WouldBlock) => self.tap.waiter.wait(),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        TAPS.lock().retain(|tap| !Arc::ptr_eq(tap, &self.tap));
+    }
+}
+
+/// Frame level operations of packet sockets.
+impl InterfaceWrapper {
+    /// Sends a whole frame built by a packet socket. Frames to the loopback
+    /// interface are looped back if they carry IP packets.
+    fn send_raw_frame(&self, frame: &[u8], origin: usize) -> AxResult {
+        let mut dev = self.dev.lock();
+        match dev.deref_mut() {
+            InterfaceDevice::Loopback(dev) => {
+                tap_frame(self.index, frame, PacketType::Outgoing, Some(origin));
+                let header = EthernetFrame::new_unchecked(frame);
+                if matches!(
+                    header.ethertype(),
+                    EthernetProtocol::Ipv4 | EthernetProtocol::Ipv6
+                ) {
+                    dev.transmit(frame[ETHERNET_HEADER_LEN..].to_vec());
+                }
+            }
+            InterfaceDevice::Ethernet(dev) => {
+                let mut dev = dev.inner.borrow_mut();
+                if !dev.can_transmit() {
+                    return Err(AxError::WouldBlock);
+                }
+                let mut tx_buf = dev.alloc_tx_buffer(frame.len()).map_err(|e| {
+                    warn!("{}: alloc_tx_buffer failed: {:?}", self.name, e);
+                    AxError::NoMemory
+                })?;
+                tx_buf.packet_mut()[..frame.len()].copy_from_slice(frame);
+                tap_frame(self.index, frame, PacketType::Outgoing, Some(origin));
+                dev.transmit(tx_buf).map_err(|e| {
+                    warn!("{}: transmit failed: {:?}", self.name, e);
+                    AxError::Io
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
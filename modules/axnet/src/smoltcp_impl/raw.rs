@@ -0,0 +1,420 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::net::SocketAddr;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use axerrno::{ax_err, ax_err_type, AxError, AxResult};
+use axhal::time::current_ticks;
+use axio::PollState;
+use spin::RwLock;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::raw;
+use smoltcp::wire::{IpAddress, IpProtocol, IpVersion, Ipv4Address, Ipv4Packet, Ipv4Repr};
+
+use super::addr::{from_core_ipaddr, into_core_ipaddr};
+use super::{
+    router, source_address, SocketSetWrapper, SocketWaiter, INTERFACES, SOCKET_SET, STANDARD_MTU,
+};
+use super::{RAW_RX_BUF_LEN, RAW_TX_BUF_LEN};
+
+/// The TTL of the packets whose IP header is built by the kernel.
+const DEFAULT_HOP_LIMIT: u8 = 64;
+
+/// A raw IPv4 socket that provides POSIX-like APIs.
+///
+/// It receives every IPv4 packet of its protocol with the IP header, which
+/// the stack also handles as usual. The IP header of the sent packets is
+/// built by the kernel, unless [`set_header_included`] is set
+/// (`IP_HDRINCL`).
+///
+/// [`set_header_included`]: RawSocket::set_header_included
+pub struct RawSocket {
+    handle: SocketHandle,
+    protocol: IpProtocol,
+    local_addr: RwLock<Option<Ipv4Address>>,
+    peer_addr: RwLock<Option<Ipv4Address>>,
+    nonblock: AtomicBool,
+    header_included: AtomicBool,
+    icmp_filter: AtomicU32,
+    recv_buf_size: AtomicUsize,
+    send_buf_size: AtomicUsize,
+    waiter: SocketWaiter,
+}
+
+impl RawSocket {
+    /// Creates a new raw socket of the given IP protocol number.
+    pub fn new(protocol: u8) -> Self {
+        let protocol = IpProtocol::from(protocol);
+        let socket = SocketSetWrapper::new_raw_socket(
+            IpVersion::Ipv4,
+            protocol,
+            RAW_RX_BUF_LEN,
+            RAW_TX_BUF_LEN,
+        );
+        let handle = SOCKET_SET.add(socket);
+        Self {
+            handle,
+            protocol,
+            local_addr: RwLock::new(None),
+            peer_addr: RwLock::new(None),
+            nonblock: AtomicBool::new(false),
+            header_included: AtomicBool::new(false),
+            icmp_filter: AtomicU32::new(0),
+            recv_buf_size: AtomicUsize::new(RAW_RX_BUF_LEN),
+            send_buf_size: AtomicUsize::new(RAW_TX_BUF_LEN),
+            waiter: SocketWaiter::new(),
+        }
+    }
+
+    /// Returns the bound address, the port is always 0.
+    pub fn local_addr(&self) -> AxResult<SocketAddr> {
+        let addr = self.local_addr.read().unwrap_or(Ipv4Address::UNSPECIFIED);
+        Ok(SocketAddr::new(into_core_ipaddr(addr.into()), 0))
+    }
+
+    /// Returns the connected address, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not connected.
+    pub fn peer_addr(&self) -> AxResult<SocketAddr> {
+        let addr = self.peer_addr.read().ok_or(AxError::NotConnected)?;
+        Ok(SocketAddr::new(into_core_ipaddr(addr.into()), 0))
+    }
+
+    /// Returns the IP protocol number of the socket.
+    #[inline]
+    pub fn protocol(&self) -> u8 {
+        self.protocol.into()
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this raw socket into or out of nonblocking mode.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Returns whether the data sent includes the IP header.
+    #[inline]
+    pub fn is_header_included(&self) -> bool {
+        self.header_included.load(Ordering::Acquire)
+    }
+
+    /// Sets whether the data sent includes the IP header (`IP_HDRINCL`).
+    ///
+    /// The checksum and the total length of the header are always filled by
+    /// the kernel, and so is the source address if it is unspecified.
+    #[inline]
+    pub fn set_header_included(&self, included: bool) {
+        self.header_included.store(included, Ordering::Release);
+    }
+
+    /// Returns the ICMP types that are not received.
+    #[inline]
+    pub fn icmp_filter(&self) -> u32 {
+        self.icmp_filter.load(Ordering::Acquire)
+    }
+
+    /// Sets the ICMP types that are not received (`ICMP_FILTER`), bit `n` of
+    /// `filter` stands for type `n`.
+    ///
+    /// It only applies to sockets of ICMP.
+    #[inline]
+    pub fn set_icmp_filter(&self, filter: u32) {
+        self.icmp_filter.store(filter, Ordering::Release);
+    }
+
+    /// Returns the size of the receive buffer.
+    #[inline]
+    pub fn recv_buffer_size(&self) -> usize {
+        self.recv_buf_size.load(Ordering::Acquire)
+    }
+
+    /// Sets the size of the receive buffer (`SO_RCVBUF`).
+    ///
+    /// The packets queued in the socket are dropped.
+    pub fn set_recv_buffer_size(&self, size: usize) {
+        self.recv_buf_size.store(size, Ordering::Release);
+        self.rebuild();
+    }
+
+    /// Returns the size of the send buffer.
+    #[inline]
+    pub fn send_buffer_size(&self) -> usize {
+        self.send_buf_size.load(Ordering::Acquire)
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`).
+    ///
+    /// The packets queued in the socket are dropped.
+    pub fn set_send_buffer_size(&self, size: usize) {
+        self.send_buf_size.store(size, Ordering::Release);
+        self.rebuild();
+    }
+
+    /// Binds the socket to a local address, only the packets to it are
+    /// received, and it is the source address of the packets sent.
+    pub fn bind(&self, local_addr: SocketAddr) -> AxResult {
+        let addr = ipv4_of(local_addr)?;
+        if !addr.is_unspecified() && !router::is_local_addr(&INTERFACES, addr.into()) {
+            return ax_err!(InvalidInput, "socket bind() failed: not a local address");
+        }
+        *self.local_addr.write() = (!addr.is_unspecified()).then_some(addr);
+        debug!("raw socket {}: bound on {}", self.handle, addr);
+        Ok(())
+    }
+
+    /// Connects the socket to a remote address, only the packets from it are
+    /// received, and it is the destination of [`send`](Self::send).
+    pub fn connect(&self, addr: SocketAddr) -> AxResult {
+        *self.peer_addr.write() = Some(ipv4_of(addr)?);
+        debug!("raw socket {}: connected to {}", self.handle, addr);
+        Ok(())
+    }
+
+    /// Sends a packet to the given address, the port is ignored. On success,
+    /// returns the number of bytes written.
+    pub fn send_to(&self, buf: &[u8], remote_addr: SocketAddr) -> AxResult<usize> {
+        self.send_impl(buf, ipv4_of(remote_addr)?)
+    }
+
+    /// Sends a packet to the connected address.
+    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+        let remote_addr = self.peer_addr.read().ok_or(AxError::NotConnected)?;
+        self.send_impl(buf, remote_addr)
+    }
+
+    /// Receives a packet with the IP header. On success, returns the number
+    /// of bytes read and the source address.
+    ///
+    /// The packet is truncated if `buf` is too small.
+    pub fn recv_from(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
+        self.recv_impl(buf, None)
+    }
+
+    /// Receives a packet like [`recv_from`](Self::recv_from).
+    ///
+    /// It will return [`Err(Timeout)`](AxError::Timeout) if expired.
+    pub fn recv_from_timeout(&self, buf: &mut [u8], ticks: u64) -> AxResult<(usize, SocketAddr)> {
+        self.recv_impl(buf, Some(current_ticks() + ticks))
+    }
+
+    /// Receives a packet from the connected address.
+    pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        self.recv_from(buf).map(|(len, _)| len)
+    }
+
+    /// Close the socket.
+    pub fn shutdown(&self) -> AxResult {
+        Ok(())
+    }
+
+    /// Whether the socket is readable or writable.
+    pub fn poll(&self) -> AxResult<PollState> {
+        SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+            self.drop_unaccepted(socket);
+            Ok(PollState {
+                readable: socket.can_recv(),
+                writable: socket.can_send(),
+            })
+        })
+    }
+}
+
+/// Private methods
+impl RawSocket {
+    /// Replaces the smoltcp socket with one of the new buffer sizes.
+    fn rebuild(&self) {
+        let socket = SocketSetWrapper::new_raw_socket(
+            IpVersion::Ipv4,
+            self.protocol,
+            self.recv_buffer_size(),
+            self.send_buffer_size(),
+        );
+        SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(self.handle, |old| *old = socket);
+    }
+
+    /// Drops the received packets that are filtered out, so that the first
+    /// one in the queue is accepted.
+    fn drop_unaccepted(&self, socket: &mut raw::Socket<'_>) {
+        while let Ok(packet) = socket.peek() {
+            if self.accepts(packet) {
+                break;
+            }
+            socket.recv().ok();
+        }
+    }
+
+    fn accepts(&self, packet: &[u8]) -> bool {
+        let Ok(packet) = Ipv4Packet::new_checked(packet) else {
+            return false;
+        };
+        if self
+            .local_addr
+            .read()
+            .is_some_and(|addr| addr != packet.dst_addr())
+        {
+            return false;
+        }
+        if self
+            .peer_addr
+            .read()
+            .is_some_and(|addr| addr != packet.src_addr())
+        {
+            return false;
+        }
+        if self.protocol == IpProtocol::Icmp {
+            if let Some(&ty) = packet.payload().first() {
+                return ty >= 32 || self.icmp_filter() & (1 << ty) == 0;
+            }
+        }
+        true
+    }
+
+    fn recv_impl(&self, buf: &mut [u8], expire_at: Option<u64>) -> AxResult<(usize, SocketAddr)> {
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+                self.drop_unaccepted(socket);
+                let Ok(packet) = socket.recv() else {
+                    return match expire_at {
+                        Some(expire_at) if current_ticks() > expire_at => Err(AxError::Timeout),
+                        _ => Err(AxError::WouldBlock),
+                    };
+                };
+                let len = packet.len().min(buf.len());
+                buf[..len].copy_from_slice(&packet[..len]);
+                let src_addr = Ipv4Packet::new_unchecked(packet).src_addr();
+                Ok((len, SocketAddr::new(into_core_ipaddr(src_addr.into()), 0)))
+            })
+        })
+    }
+
+    fn send_impl(&self, buf: &[u8], remote_addr: Ipv4Address) -> AxResult<usize> {
+        let packet = if self.is_header_included() {
+            self.complete_header(buf)?
+        } else {
+            self.build_packet(buf, remote_addr)?
+        };
+        if packet.len() > STANDARD_MTU {
+            return ax_err!(InvalidInput, "socket send() failed: message too long");
+        }
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+                socket
+                    .send_slice(&packet)
+                    .map_err(|_| AxError::WouldBlock)?;
+                Ok(buf.len())
+            })
+        })
+    }
+
+    /// Builds the IP header before the payload.
+    fn build_packet(&self, payload: &[u8], remote_addr: Ipv4Address) -> AxResult<Vec<u8>> {
+        let src_addr = match *self.local_addr.read() {
+            Some(addr) => addr,
+            None => ipv4_source_address(remote_addr)?,
+        };
+        let repr = Ipv4Repr {
+            src_addr,
+            dst_addr: remote_addr,
+            next_header: self.protocol,
+            payload_len: payload.len(),
+            hop_limit: DEFAULT_HOP_LIMIT,
+        };
+        let mut buf = vec![0; repr.buffer_len() + payload.len()];
+        let mut packet = Ipv4Packet::new_unchecked(&mut buf[..]);
+        repr.emit(&mut packet, &ChecksumCapabilities::default());
+        packet.payload_mut().copy_from_slice(payload);
+        Ok(buf)
+    }
+
+    /// Fills the IP header given by the user (`IP_HDRINCL`).
+    fn complete_header(&self, buf: &[u8]) -> AxResult<Vec<u8>> {
+        let mut buf = buf.to_vec();
+        let len = buf.len();
+        let mut packet = Ipv4Packet::new_unchecked(&mut buf[..]);
+        if len < 20 || packet.version() != 4 || (packet.header_len() as usize) > len {
+            return ax_err!(InvalidInput, "socket send() failed: invalid IP header");
+        }
+        // The packet is dropped by smoltcp if the protocol is not the one
+        // of the socket.
+        if packet.next_header() != self.protocol {
+            return ax_err!(InvalidInput, "socket send() failed: protocol mismatched");
+        }
+        packet.set_total_len(len as u16);
+        if packet.src_addr().is_unspecified() {
+            let src_addr = ipv4_source_address(packet.dst_addr())?;
+            packet.set_src_addr(src_addr);
+        }
+        packet.fill_checksum();
+        Ok(buf)
+    }
+
+    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        if self.is_nonblocking() {
+            f()
+        } else {
+            loop {
+                #[cfg(feature = "signal")]
+                unsafe {
+                    extern "Rust" {
+                        fn current_have_signal() -> bool;
+                    }
+                    if current_have_signal() {
+                        return Err(AxError::Interrupted);
+                    }
+                }
+                self.register_waker();
+                SOCKET_SET.poll_interfaces();
+                match f() {
+                    Ok(t) => return Ok(t),
+                    Err(AxError::WouldBlock) => self.waiter.wait(),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    /// Registers the waiter to be woken when the socket may be ready.
+    fn register_waker(&self) {
+        self.waiter.arm();
+        #[cfg(feature = "irq")]
+        SOCKET_SET.register_waker::<raw::Socket, _>(self.handle, |socket| {
+            let waker = self.waiter.waker();
+            socket.register_recv_waker(&waker);
+            socket.register_send_waker(&waker);
+        });
+    }
+}
+
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        SOCKET_SET.remove(self.handle);
+    }
+}
+
+/// Raw sockets only support IPv4.
+fn ipv4_of(addr: SocketAddr) -> AxResult<Ipv4Address> {
+    match from_core_ipaddr(addr.ip()) {
+        IpAddress::Ipv4(addr) => Ok(addr),
+        IpAddress::Ipv6(_) => Err(ax_err_type!(InvalidInput, "not an IPv4 address")),
+    }
+}
+
+fn ipv4_source_address(remote_addr: Ipv4Address) -> AxResult<Ipv4Address> {
+    match source_address(remote_addr.into())? {
+        IpAddress::Ipv4(addr) if !addr.is_unspecified() => Ok(addr),
+        _ => Err(ax_err_type!(
+            ConnectionRefused,
+            "no IPv4 address to send from"
+        )),
+    }
+}
@@ -0,0 +1,95 @@
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxResult};
+use axsync::Mutex;
+use smoltcp::wire::{IpAddress, IpCidr, Ipv6Address, Ipv6Cidr};
+
+pub(crate) static ROUTE_TABLE: Mutex<RouteTable> = Mutex::new(RouteTable::new());
+
+/// An entry of the kernel routing table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Route {
+    /// The destination network.
+    pub dest: IpCidr,
+    /// The next hop, or `None` if the destination is directly reachable.
+    pub gateway: Option<IpAddress>,
+    /// Index of the egress interface.
+    pub iface: usize,
+    /// Preference among routes of the same prefix length, the lower the better.
+    pub metric: u32,
+}
+
+/// The kernel routing table.
+///
+/// The egress interface of every outgoing packet is decided here by
+/// longest-prefix match, so a default route is just a route to `0.0.0.0/0`.
+pub(crate) struct RouteTable {
+    routes: Vec<Route>,
+}
+
+impl RouteTable {
+    pub const fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    /// Adds a route, the host bits of the destination are cleared.
+    pub fn add(&mut self, mut route: Route) -> AxResult {
+        route.dest = network_of(route.dest);
+        if self
+            .routes
+            .iter()
+            .any(|r| r.dest == route.dest && r.metric == route.metric && r.iface == route.iface)
+        {
+            return ax_err!(AlreadyExists, "route already exists");
+        }
+        debug!("add route {:?}", route);
+        self.routes.push(route);
+        Ok(())
+    }
+
+    /// Removes the routes to `dest`, or only those through `iface` if given.
+    pub fn remove(&mut self, dest: IpCidr, iface: Option<usize>) -> AxResult {
+        let dest = network_of(dest);
+        let len = self.routes.len();
+        self.routes
+            .retain(|r| r.dest != dest || iface.is_some_and(|iface| r.iface != iface));
+        if self.routes.len() == len {
+            return ax_err!(NotFound, "route not found");
+        }
+        Ok(())
+    }
+
+    /// Finds the most specific route to `addr`, the earliest added one wins a
+    /// tie, e.g. the link-local routes of several interfaces.
+    pub fn lookup(&self, addr: IpAddress) -> Option<Route> {
+        self.routes
+            .iter()
+            .rev()
+            .filter(|r| r.dest.contains_addr(&addr))
+            .max_by(|a, b| {
+                a.dest
+                    .prefix_len()
+                    .cmp(&b.dest.prefix_len())
+                    .then(b.metric.cmp(&a.metric))
+            })
+            .copied()
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+}
+
+fn network_of(cidr: IpCidr) -> IpCidr {
+    match cidr {
+        IpCidr::Ipv4(cidr) => IpCidr::Ipv4(cidr.network()),
+        IpCidr::Ipv6(cidr) => {
+            let prefix_len = cidr.prefix_len() as usize;
+            let mut bytes = cidr.address().0;
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                let bits = prefix_len.saturating_sub(i * 8).min(8) as u32;
+                *byte &= !0xffu8.checked_shr(bits).unwrap_or(0);
+            }
+            IpCidr::Ipv6(Ipv6Cidr::new(Ipv6Address(bytes), cidr.prefix_len()))
+        }
+    }
+}
@@ -0,0 +1,512 @@
+use alloc::{vec, vec::Vec};
+use core::ops::DerefMut;
+
+use driver_net::{DevError, NetBufPtr};
+use smoltcp::iface::SocketSet;
+use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
+use smoltcp::time::Instant;
+use smoltcp::wire::{
+    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol, IpAddress,
+    IpCidr, IpVersion, Ipv4Packet, Ipv6Packet, ETHERNET_HEADER_LEN,
+};
+
+use super::congestion;
+use super::ndp::{is_link_local, is_ndp_packet};
+use super::packet::{self, PacketType};
+use super::route::ROUTE_TABLE;
+use super::{InterfaceDevice, InterfaceWrapper, LISTEN_TABLE, STANDARD_MTU};
+
+/// The device seen by the IP stack.
+///
+/// It receives packets from all the interfaces, and sends every packet through
+/// the interface chosen by the routing table.
+pub(crate) struct Router<'a> {
+    interfaces: &'a [InterfaceWrapper],
+    next_rx: usize,
+}
+
+impl<'a> Router<'a> {
+    pub fn new(interfaces: &'a [InterfaceWrapper]) -> Self {
+        Self {
+            interfaces,
+            next_rx: 0,
+        }
+    }
+}
+
+impl<'a> Device for Router<'a> {
+    type RxToken<'b> = RouterRxToken<'b> where Self: 'b;
+    type TxToken<'b> = RouterTxToken<'b> where Self: 'b;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        // Take turns so that a busy interface does not starve the others.
+        let count = self.interfaces.len();
+        for i in 0..count {
+            let index = (self.next_rx + i) % count;
+            let iface = &self.interfaces[index];
+            if let Some(buf) = iface.receive() {
+                self.next_rx = (index + 1) % count;
+                return Some((RouterRxToken { iface, buf }, RouterTxToken(self.interfaces)));
+            }
+        }
+        None
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        // The egress interface is only known once the packet is built, so a
+        // full interface is checked in `route_packet`, and does not hold up
+        // the packets to the others.
+        Some(RouterTxToken(self.interfaces))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = STANDARD_MTU;
+        caps.max_burst_size = None;
+        caps.medium = Medium::Ip;
+        caps
+    }
+}
+
+/// A packet received by an interface.
+pub(crate) enum RxBuf {
+    Loopback(Vec<u8>),
+    /// An Ethernet frame carrying an IP packet.
+    Ethernet(NetBufPtr),
+}
+
+impl RxBuf {
+    fn packet(&self) -> &[u8] {
+        match self {
+            Self::Loopback(buf) => buf,
+            Self::Ethernet(buf) => &buf.packet()[ETHERNET_HEADER_LEN..],
+        }
+    }
+}
+
+pub(crate) struct RouterRxToken<'a> {
+    iface: &'a InterfaceWrapper,
+    buf: RxBuf,
+}
+
+pub(crate) struct RouterTxToken<'a>(&'a [InterfaceWrapper]);
+
+impl<'a> RxToken for RouterRxToken<'a> {
+    fn preprocess(&self, sockets: &mut SocketSet<'_>) {
+        snoop_tcp_packet(self.buf.packet(), sockets).ok();
+    }
+
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        match self.buf {
+            RxBuf::Loopback(mut buf) => {
+                congestion::on_receive(&mut buf);
+                f(&mut buf)
+            }
+            RxBuf::Ethernet(mut buf) => {
+                trace!(
+                    "{}: RECV {} bytes: {:02X?}",
+                    self.iface.name(),
+                    buf.packet_len(),
+                    buf.packet()
+                );
+                let packet = &mut buf.packet_mut()[ETHERNET_HEADER_LEN..];
+                congestion::on_receive(packet);
+                let result = f(packet);
+                self.iface.recycle_rx_buffer(buf);
+                result
+            }
+        }
+    }
+}
+
+impl<'a> TxToken for RouterTxToken<'a> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut packet = vec![0; len];
+        let result = f(&mut packet);
+        congestion::on_transmit(&packet);
+        route_packet(self.0, packet);
+        result
+    }
+}
+
+fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) -> Result<(), smoltcp::wire::Error> {
+    use smoltcp::wire::{IpProtocol, TcpPacket};
+
+    let (src_addr, dst_addr, protocol, payload): (IpAddress, IpAddress, _, _) =
+        match IpVersion::of_packet(buf)? {
+            IpVersion::Ipv4 => {
+                let packet = Ipv4Packet::new_checked(buf)?;
+                let payload = &buf[packet.header_len() as usize..packet.total_len() as usize];
+                let (src_addr, dst_addr) = (packet.src_addr(), packet.dst_addr());
+                (
+                    src_addr.into(),
+                    dst_addr.into(),
+                    packet.next_header(),
+                    payload,
+                )
+            }
+            IpVersion::Ipv6 => {
+                let packet = Ipv6Packet::new_checked(buf)?;
+                let payload = &buf[packet.header_len()..packet.total_len()];
+                let (src_addr, dst_addr) = (packet.src_addr(), packet.dst_addr());
+                (
+                    src_addr.into(),
+                    dst_addr.into(),
+                    packet.next_header(),
+                    payload,
+                )
+            }
+        };
+
+    if protocol == IpProtocol::Tcp {
+        let tcp_packet = TcpPacket::new_checked(payload)?;
+        let src_addr = (src_addr, tcp_packet.src_port()).into();
+        let dst_addr = (dst_addr, tcp_packet.dst_port()).into();
+        let is_first = tcp_packet.syn() && !tcp_packet.ack();
+        if is_first {
+            // create a socket for the first incoming TCP packet, as the later accept() returns.
+            LISTEN_TABLE.incoming_tcp_packet(src_addr, dst_addr, sockets);
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether `addr` is assigned to one of the interfaces.
+pub(crate) fn is_local_addr(interfaces: &[InterfaceWrapper], addr: IpAddress) -> bool {
+    interfaces.iter().any(|iface| iface.has_ip_addr(addr))
+}
+
+/// Returns the Ethernet multicast address that a multicast IP address maps to.
+pub(crate) fn multicast_ether_addr(addr: IpAddress) -> EthernetAddress {
+    let b = addr.as_bytes();
+    match addr {
+        IpAddress::Ipv4(_) => EthernetAddress([0x01, 0x00, 0x5e, b[1] & 0x7f, b[2], b[3]]),
+        IpAddress::Ipv6(_) => EthernetAddress([0x33, 0x33, b[12], b[13], b[14], b[15]]),
+    }
+}
+
+fn dst_addr_of(packet: &[u8]) -> Option<IpAddress> {
+    match IpVersion::of_packet(packet).ok()? {
+        IpVersion::Ipv4 => Ipv4Packet::new_checked(packet)
+            .ok()
+            .map(|p| IpAddress::Ipv4(p.dst_addr())),
+        IpVersion::Ipv6 => Ipv6Packet::new_checked(packet)
+            .ok()
+            .map(|p| IpAddress::Ipv6(p.dst_addr())),
+    }
+}
+
+/// Sends an IP packet generated by the IP stack through the egress interface.
+fn route_packet(interfaces: &[InterfaceWrapper], packet: Vec<u8>) {
+    let Some(dst_addr) = dst_addr_of(&packet) else {
+        return;
+    };
+    let loopback = &interfaces[0];
+    // Packets to the host itself never leave it.
+    if is_local_addr(interfaces, dst_addr) {
+        loopback.send_ip(packet, dst_addr);
+        return;
+    }
+    let Some(route) = ROUTE_TABLE.lock().lookup(dst_addr) else {
+        trace!("no route to {}, packet dropped", dst_addr);
+        if dst_addr.is_multicast() {
+            loopback.send_ip(packet, dst_addr);
+        }
+        return;
+    };
+    if dst_addr.is_multicast() && route.iface != loopback.index {
+        // Like IP_MULTICAST_LOOP, local members also receive the packet.
+        loopback.send_ip(packet.clone(), dst_addr);
+    }
+    let iface = &interfaces[route.iface];
+    if !iface.can_transmit() {
+        trace!("{}: transmit queue is full, packet dropped", iface.name());
+        return;
+    }
+    iface.send_ip(packet, route.gateway.unwrap_or(dst_addr));
+}
+
+/// Packet level operations of interfaces.
+impl InterfaceWrapper {
+    pub fn has_ip_addr(&self, addr: IpAddress) -> bool {
+        self.ip_addrs
+            .lock()
+            .iter()
+            .any(|cidr| cidr.address() == addr)
+    }
+
+    /// Chooses the source address to reach `dst` via `next_hop` through this
+    /// interface.
+    ///
+    /// An address on the network of `dst` is preferred, then one on the
+    /// network of `next_hop`. IPv6 link-local addresses are only chosen for
+    /// link-local destinations, unless there is no other address.
+    pub fn source_address(&self, dst: IpAddress, next_hop: IpAddress) -> Option<IpAddress> {
+        let ip_addrs = self.ip_addrs.lock();
+        let ip_addrs: &[IpCidr] = &ip_addrs;
+        let same_version = move || {
+            ip_addrs
+                .iter()
+                .filter(move |cidr| cidr.address().version() == dst.version())
+        };
+        let candidates = move || {
+            same_version().filter(move |cidr| is_link_local(dst) || !is_link_local(cidr.address()))
+        };
+        candidates()
+            .find(|cidr| cidr.contains_addr(&dst))
+            .or_else(|| candidates().find(|cidr| cidr.contains_addr(&next_hop)))
+            .or_else(|| candidates().next())
+            .or_else(|| same_version().next())
+            .map(IpCidr::address)
+    }
+
+    fn is_broadcast(&self, addr: IpAddress) -> bool {
+        match addr {
+            IpAddress::Ipv4(addr) => {
+                addr.is_broadcast()
+                    || self.ip_addrs.lock().iter().any(|cidr| match cidr {
+                        IpCidr::Ipv4(cidr) => cidr.broadcast() == Some(addr),
+                        IpCidr::Ipv6(_) => false,
+                    })
+            }
+            // IPv6 has no broadcast, all-nodes multicast is used instead.
+            IpAddress::Ipv6(_) => false,
+        }
+    }
+
+    fn can_transmit(&self) -> bool {
+        match self.dev.lock().deref_mut() {
+            InterfaceDevice::Loopback(_) => true,
+            InterfaceDevice::Ethernet(dev) => {
+                let mut dev = dev.inner.borrow_mut();
+                if let Err(e) = dev.recycle_tx_buffers() {
+                    warn!("recycle_tx_buffers failed: {:?}", e);
+                    return false;
+                }
+                dev.can_transmit()
+            }
+        }
+    }
+
+    /// Receives an IP packet, the other frames and NDP messages are handled
+    /// here, and DHCP replies go to the DHCP client.
+    fn receive(&self) -> Option<RxBuf> {
+        loop {
+            let rx_buf = match self.dev.lock().deref_mut() {
+                InterfaceDevice::Loopback(dev) => {
+                    let packet = dev.receive()?;
+                    packet::tap_loopback_packet(&packet, PacketType::Host);
+                    return Some(RxBuf::Loopback(packet));
+                }
+                InterfaceDevice::Ethernet(dev) => match dev.inner.borrow_mut().receive() {
+                    Ok(buf) => buf,
+                    Err(err) => {
+                        if !matches!(err, DevError::Again) {
+                            warn!("{}: receive failed: {:?}", self.name, err);
+                        }
+                        return None;
+                    }
+                },
+            };
+            let Ok(frame) = EthernetFrame::new_checked(rx_buf.packet()) else {
+                self.recycle_rx_buffer(rx_buf);
+                continue;
+            };
+            let dst_addr = frame.dst_addr();
+            let pkttype = if dst_addr == self.ether_addr {
+                PacketType::Host
+            } else if dst_addr.is_broadcast() {
+                PacketType::Broadcast
+            } else if dst_addr.is_multicast() {
+                PacketType::Multicast
+            } else {
+                PacketType::OtherHost
+            };
+            packet::tap_frame(self.index, rx_buf.packet(), pkttype, None);
+            if dst_addr != self.ether_addr && !dst_addr.is_broadcast() && !dst_addr.is_multicast() {
+                self.recycle_rx_buffer(rx_buf);
+                continue;
+            }
+            #[cfg(feature = "dhcp")]
+            if self.dhcp_input(&frame) {
+                self.recycle_rx_buffer(rx_buf);
+                continue;
+            }
+            match frame.ethertype() {
+                EthernetProtocol::Ipv4 => return Some(RxBuf::Ethernet(rx_buf)),
+                EthernetProtocol::Ipv6 if !is_ndp_packet(frame.payload()) => {
+                    return Some(RxBuf::Ethernet(rx_buf))
+                }
+                EthernetProtocol::Ipv6 => {
+                    let src_addr = frame.src_addr();
+                    let payload = frame.payload().to_vec();
+                    self.recycle_rx_buffer(rx_buf);
+                    self.process_ndp(&payload, src_addr);
+                }
+                EthernetProtocol::Arp => {
+                    let payload = frame.payload().to_vec();
+                    self.recycle_rx_buffer(rx_buf);
+                    self.process_arp(&payload);
+                }
+                _ => self.recycle_rx_buffer(rx_buf),
+            }
+        }
+    }
+
+    fn recycle_rx_buffer(&self, rx_buf: NetBufPtr) {
+        if let InterfaceDevice::Ethernet(dev) = self.dev.lock().deref_mut() {
+            dev.inner.borrow_mut().recycle_rx_buffer(rx_buf).unwrap();
+        }
+    }
+
+    /// Sends an IP packet to `next_hop` on the link.
+    fn send_ip(&self, packet: Vec<u8>, next_hop: IpAddress) {
+        if let InterfaceDevice::Loopback(dev) = self.dev.lock().deref_mut() {
+            packet::tap_loopback_packet(&packet, PacketType::Outgoing);
+            dev.transmit(packet);
+            return;
+        }
+        let dst_addr = if self.is_broadcast(next_hop) {
+            EthernetAddress::BROADCAST
+        } else if next_hop.is_multicast() {
+            multicast_ether_addr(next_hop)
+        } else {
+            let now = Self::current_time();
+            let mut neighbors = self.neighbors.lock();
+            match neighbors.lookup(next_hop, now) {
+                Some(addr) => addr,
+                None => {
+                    let need_request = neighbors.enqueue(next_hop, packet, now);
+                    drop(neighbors);
+                    if need_request {
+                        match next_hop {
+                            IpAddress::Ipv4(_) => self.send_arp_request(next_hop),
+                            IpAddress::Ipv6(_) => self.send_neighbor_solicit(next_hop),
+                        }
+                    }
+                    return;
+                }
+            }
+        };
+        let ethertype = match next_hop {
+            IpAddress::Ipv4(_) => EthernetProtocol::Ipv4,
+            IpAddress::Ipv6(_) => EthernetProtocol::Ipv6,
+        };
+        self.send_frame(dst_addr, ethertype, &packet);
+    }
+
+    pub(crate) fn send_frame(
+        &self,
+        dst_addr: EthernetAddress,
+        ethertype: EthernetProtocol,
+        payload: &[u8],
+    ) {
+        let mut dev = self.dev.lock();
+        let InterfaceDevice::Ethernet(dev) = dev.deref_mut() else {
+            return;
+        };
+        let mut dev = dev.inner.borrow_mut();
+        if !dev.can_transmit() {
+            trace!("{}: transmit queue is full, frame dropped", self.name);
+            return;
+        }
+        let len = ETHERNET_HEADER_LEN + payload.len();
+        let mut tx_buf = match dev.alloc_tx_buffer(len) {
+            Ok(buf) => buf,
+            Err(e) => {
+                warn!("{}: alloc_tx_buffer failed: {:?}", self.name, e);
+                return;
+            }
+        };
+        let mut frame = EthernetFrame::new_unchecked(tx_buf.packet_mut());
+        frame.set_src_addr(self.ether_addr);
+        frame.set_dst_addr(dst_addr);
+        frame.set_ethertype(ethertype);
+        frame.payload_mut()[..payload.len()].copy_from_slice(payload);
+        trace!(
+            "{}: SEND {} bytes: {:02X?}",
+            self.name,
+            len,
+            tx_buf.packet()
+        );
+        packet::tap_frame(self.index, tx_buf.packet(), PacketType::Outgoing, None);
+        if let Err(e) = dev.transmit(tx_buf) {
+            warn!("{}: transmit failed: {:?}", self.name, e);
+        }
+    }
+
+    fn process_arp(&self, payload: &[u8]) {
+        let Ok(ArpRepr::EthernetIpv4 {
+            operation,
+            source_hardware_addr,
+            source_protocol_addr,
+            target_protocol_addr,
+            ..
+        }) = ArpPacket::new_checked(payload).and_then(|packet| ArpRepr::parse(&packet))
+        else {
+            return;
+        };
+        if !source_hardware_addr.is_unicast() || !source_protocol_addr.is_unicast() {
+            return;
+        }
+        let for_us = self.has_ip_addr(target_protocol_addr.into());
+        let pending = self.neighbors.lock().fill(
+            source_protocol_addr.into(),
+            source_hardware_addr,
+            Self::current_time(),
+            for_us,
+        );
+        for packet in pending {
+            self.send_frame(source_hardware_addr, EthernetProtocol::Ipv4, &packet);
+        }
+        if operation == ArpOperation::Request && for_us {
+            self.send_arp(ArpRepr::EthernetIpv4 {
+                operation: ArpOperation::Reply,
+                source_hardware_addr: self.ether_addr,
+                source_protocol_addr: target_protocol_addr,
+                target_hardware_addr: source_hardware_addr,
+                target_protocol_addr: source_protocol_addr,
+            });
+        }
+    }
+
+    fn send_arp_request(&self, target: IpAddress) {
+        let (IpAddress::Ipv4(target), Some(IpAddress::Ipv4(source))) =
+            (target, self.source_address(target, target))
+        else {
+            return;
+        };
+        debug!("{}: ARP request for {}", self.name, target);
+        self.send_arp(ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Request,
+            source_hardware_addr: self.ether_addr,
+            source_protocol_addr: source,
+            target_hardware_addr: EthernetAddress([0; 6]),
+            target_protocol_addr: target,
+        });
+    }
+
+    fn send_arp(&self, repr: ArpRepr) {
+        let ArpRepr::EthernetIpv4 {
+            target_hardware_addr,
+            ..
+        } = repr
+        else {
+            return;
+        };
+        let dst_addr = if target_hardware_addr == EthernetAddress([0; 6]) {
+            EthernetAddress::BROADCAST
+        } else {
+            target_hardware_addr
+        };
+        let mut buf = vec![0; repr.buffer_len()];
+        repr.emit(&mut ArpPacket::new_unchecked(&mut buf[..]));
+        self.send_frame(dst_addr, EthernetProtocol::Arp, &buf);
+    }
+}
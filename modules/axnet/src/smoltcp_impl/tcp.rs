@@ -1,19 +1,25 @@
+use alloc::vec::Vec;
 use core::cell::UnsafeCell;
 use core::net::SocketAddr;
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::time::Duration;
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
-use axhal::time::current_ticks;
+use axhal::time::{current_ticks, current_time};
 use axio::{PollState, Read, Write};
 use axsync::Mutex;
 
 use axtask::yield_now;
-use smoltcp::iface::SocketHandle;
+use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{self, ConnectError, State};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
-use super::addr::{from_core_sockaddr, into_core_sockaddr, is_unspecified, UNSPECIFIED_ENDPOINT};
-use super::{SocketSetWrapper, LISTEN_TABLE, SOCKET_SET};
+use super::addr::{
+    accepted_version, from_core_sockaddr, into_core_sockaddr, is_unspecified, UNSPECIFIED_ENDPOINT,
+};
+use super::congestion::{self, CongestionControl};
+use super::{check_on_link, source_address, tcp_mss, SocketSetWrapper, SocketWaiter};
+use super::{LISTEN_TABLE, SOCKET_SET, TCP_RX_BUF_LEN, TCP_TX_BUF_LEN};
 
 // State transitions:
 // CLOSED -(connect)-> BUSY -> CONNECTING -> CONNECTED -(shutdown)-> BUSY -> CLOSED
@@ -27,6 +33,105 @@ const STATE_CONNECTING: u8 = 2;
 const STATE_CONNECTED: u8 = 3;
 const STATE_LISTENING: u8 = 4;
 
+/// How long a closed connection may take to shut down before it is aborted.
+const ORPHAN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The connections still shutting down after their sockets are closed, they
+/// are removed from the socket set once they are closed. Each one keeps the
+/// endpoints of its flow, to stop its congestion control then.
+static ORPHANS: Mutex<Vec<(SocketHandle, Option<(IpEndpoint, IpEndpoint)>)>> =
+    Mutex::new(Vec::new());
+
+/// The options of a TCP socket that its connection takes.
+///
+/// The socket keeps them before the connection is created, and a listening
+/// socket passes them to the connections it accepts.
+#[derive(Clone, Copy)]
+pub(crate) struct TcpConfig {
+    recv_buf_size: usize,
+    send_buf_size: usize,
+    nagle: bool,
+    keep_alive: bool,
+    keep_idle: Duration,
+    keep_interval: Duration,
+    keep_count: u32,
+    linger: Option<Duration>,
+    congestion: CongestionControl,
+}
+
+impl TcpConfig {
+    const fn new() -> Self {
+        // the same defaults as Linux
+        Self {
+            recv_buf_size: TCP_RX_BUF_LEN,
+            send_buf_size: TCP_TX_BUF_LEN,
+            nagle: true,
+            keep_alive: false,
+            keep_idle: Duration::from_secs(7200),
+            keep_interval: Duration::from_secs(75),
+            keep_count: 9,
+            linger: None,
+            congestion: CongestionControl::Cubic,
+        }
+    }
+
+    /// Creates a smoltcp socket with these options.
+    pub fn new_socket<'a>(&self) -> tcp::Socket<'a> {
+        let mut socket = SocketSetWrapper::new_tcp_socket(self.recv_buf_size, self.send_buf_size);
+        self.apply(&mut socket);
+        socket
+    }
+
+    /// Applies the options except the buffer sizes to a smoltcp socket.
+    ///
+    /// smoltcp sends a keepalive every time the connection has been idle for
+    /// the interval, so the probes are sent every `keep_idle`, and the
+    /// connection is aborted if no reply arrives in the time that Linux takes
+    /// to give up.
+    fn apply(&self, socket: &mut tcp::Socket) {
+        socket.set_nagle_enabled(self.nagle);
+        if self.keep_alive {
+            let timeout = self.keep_idle + self.keep_interval * self.keep_count;
+            socket.set_keep_alive(Some(self.keep_idle.into()));
+            socket.set_timeout(Some(timeout.into()));
+        } else {
+            socket.set_keep_alive(None);
+            socket.set_timeout(None);
+        }
+    }
+
+    /// Starts the congestion control of the connection between `local` and
+    /// `remote`.
+    pub fn register_flow(&self, local: IpEndpoint, remote: IpEndpoint) {
+        congestion::register(local, remote, self.congestion, tcp_mss(remote.addr));
+    }
+}
+
+/// The state of a TCP connection, reported by `TCP_INFO`.
+#[derive(Debug, Clone, Copy)]
+pub struct TcpInfo {
+    /// The state of the connection.
+    pub state: State,
+    /// The largest segment sent to the peer.
+    pub mss: usize,
+    /// The bytes received and not read yet.
+    pub recv_queue: usize,
+    /// The bytes written and not acknowledged yet.
+    pub send_queue: usize,
+    /// The size of the receive buffer.
+    pub recv_capacity: usize,
+    /// The size of the send buffer.
+    pub send_capacity: usize,
+    /// How long the ACKs are delayed.
+    pub ack_delay: Option<Duration>,
+    /// Whether the Nagle algorithm is enabled.
+    pub nagle: bool,
+    /// The congestion window in bytes.
+    pub cwnd: usize,
+    /// The slow start threshold in bytes, `usize::MAX` before any loss.
+    pub ssthresh: usize,
+}
+
 /// A TCP socket that provides POSIX-like APIs.
 ///
 /// - [`connect`] is for TCP clients.
@@ -43,24 +148,43 @@ pub struct TcpSocket {
     local_addr: UnsafeCell<IpEndpoint>,
     peer_addr: UnsafeCell<IpEndpoint>,
     nonblock: AtomicBool,
+    ipv6_only: AtomicBool,
+    reuse_addr: AtomicBool,
+    reuse_port: AtomicBool,
+    dont_route: AtomicBool,
+    /// The ID of the listening socket in the listen table.
+    listener_id: UnsafeCell<usize>,
+    config: Mutex<TcpConfig>,
+    waiter: SocketWaiter,
 }
 
 unsafe impl Sync for TcpSocket {}
 
 impl TcpSocket {
     /// Creates a new TCP socket.
-    pub const fn new() -> Self {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
         Self {
             state: AtomicU8::new(STATE_CLOSED),
             handle: UnsafeCell::new(None),
             local_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             peer_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             nonblock: AtomicBool::new(false),
+            ipv6_only: AtomicBool::new(false),
+            reuse_addr: AtomicBool::new(false),
+            reuse_port: AtomicBool::new(false),
+            dont_route: AtomicBool::new(false),
+            listener_id: UnsafeCell::new(0),
+            config: Mutex::new(TcpConfig::new()),
+            waiter: SocketWaiter::new(),
         }
     }
 
     /// Creates a new TCP socket that is already connected.
-    const fn new_connected(
+    ///
+    /// It takes the options of the listening socket `listener`.
+    fn new_connected(
+        listener: &TcpSocket,
         handle: SocketHandle,
         local_addr: IpEndpoint,
         peer_addr: IpEndpoint,
@@ -71,6 +195,13 @@ impl TcpSocket {
             local_addr: UnsafeCell::new(local_addr),
             peer_addr: UnsafeCell::new(peer_addr),
             nonblock: AtomicBool::new(false),
+            ipv6_only: AtomicBool::new(false),
+            reuse_addr: AtomicBool::new(listener.is_reuse_addr()),
+            reuse_port: AtomicBool::new(false),
+            dont_route: AtomicBool::new(listener.is_dont_route()),
+            listener_id: UnsafeCell::new(0),
+            config: Mutex::new(*listener.config.lock()),
+            waiter: SocketWaiter::new(),
         }
     }
 
@@ -125,16 +256,20 @@ impl TcpSocket {
         self.update_state(STATE_CLOSED, STATE_CONNECTING, || {
             // SAFETY: no other threads can read or write these fields.
             let handle = unsafe { self.handle.get().read() }
-                .unwrap_or_else(|| SOCKET_SET.add(SocketSetWrapper::new_tcp_socket()));
+                .unwrap_or_else(|| SOCKET_SET.add(self.config.lock().new_socket()));
+            // keep the socket even if the connect fails, the options are on it
+            unsafe { self.handle.get().write(Some(handle)) };
 
-            // TODO: check remote addr unreachable
             let remote_endpoint = from_core_sockaddr(remote_addr);
-            let bound_endpoint = self.bound_endpoint()?;
-            #[cfg(not(feature = "ip"))]
-            let iface = &super::ETH0.iface;
-
-            #[cfg(feature = "ip")]
-            let iface = super::LOOPBACK.try_get().unwrap();
+            if self.is_dont_route() {
+                check_on_link(remote_endpoint.addr)?;
+            }
+            let mut bound_endpoint = self.bound_endpoint()?;
+            if bound_endpoint.addr.is_none() {
+                // choose the source address by the egress interface
+                bound_endpoint.addr = Some(source_address(remote_endpoint.addr)?);
+            }
+            let iface = &super::IFACE;
             let (local_endpoint, remote_endpoint) = SOCKET_SET
                 .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
                     socket
@@ -152,12 +287,14 @@ impl TcpSocket {
                         socket.remote_endpoint().unwrap(),
                     ))
                 })?;
+            self.config
+                .lock()
+                .register_flow(local_endpoint, remote_endpoint);
             unsafe {
                 // SAFETY: no other threads can read or write these fields as we
                 // have changed the state to `BUSY`.
                 self.local_addr.get().write(local_endpoint);
                 self.peer_addr.get().write(remote_endpoint);
-                self.handle.get().write(Some(handle));
             }
             Ok(())
         })
@@ -216,10 +353,18 @@ impl TcpSocket {
     pub fn listen(&self) -> AxResult {
         self.update_state(STATE_CLOSED, STATE_LISTENING, || {
             let bound_endpoint = self.bound_endpoint()?;
-            unsafe {
+            let bound_addr = unsafe {
                 (*self.local_addr.get()).port = bound_endpoint.port;
-            }
-            LISTEN_TABLE.listen(bound_endpoint)?;
+                (*self.local_addr.get()).addr
+            };
+            let config = *self.config.lock();
+            let id = LISTEN_TABLE.listen(
+                bound_endpoint,
+                accepted_version(bound_addr, self.is_ipv6_only()),
+                self.is_reuse_port(),
+                config,
+            )?;
+            unsafe { self.listener_id.get().write(id) };
             debug!("TCP socket listening on {}", bound_endpoint);
             Ok(())
         })
@@ -237,28 +382,45 @@ impl TcpSocket {
             return ax_err!(InvalidInput, "socket accept() failed: not listen");
         }
 
-        // SAFETY: `self.local_addr` should be initialized after `bind()`.
+        // SAFETY: `self.local_addr` and `self.listener_id` should be initialized
+        // after `listen()`.
         let local_port = unsafe { self.local_addr.get().read().port };
+        let id = unsafe { self.listener_id.get().read() };
         self.block_on(|| {
-            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
+            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port, id)?;
             debug!("TCP socket accepted a new connection {}", peer_addr);
-            Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
+            Ok(TcpSocket::new_connected(
+                self, handle, local_addr, peer_addr,
+            ))
         })
     }
 
     /// Close the connection.
+    ///
+    /// With a zero linger timeout the connection is reset. With a nonzero one
+    /// it blocks until the peer acknowledges all the data and the FIN, or the
+    /// timeout expires.
     pub fn shutdown(&self) -> AxResult {
         // stream
         self.update_state(STATE_CONNECTED, STATE_CLOSED, || {
             // SAFETY: `self.handle` should be initialized in a connected socket, and
             // no other threads can read or write it.
             let handle = unsafe { self.handle.get().read().unwrap() };
+            let linger = self.config.lock().linger;
             SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                debug!("TCP socket {}: shutting down", handle);
-                socket.close();
+                if linger == Some(Duration::ZERO) {
+                    debug!("TCP socket {}: aborting", handle);
+                    socket.abort();
+                } else {
+                    debug!("TCP socket {}: shutting down", handle);
+                    socket.close();
+                }
             });
             unsafe { self.local_addr.get().write(UNSPECIFIED_ENDPOINT) }; // clear bound address
             SOCKET_SET.poll_interfaces();
+            if let Some(timeout) = linger.filter(|timeout| !timeout.is_zero()) {
+                self.wait_linger(handle, timeout);
+            }
             Ok(())
         })
         .unwrap_or(Ok(()))?;
@@ -268,8 +430,9 @@ impl TcpSocket {
             // SAFETY: `self.local_addr` should be initialized in a listening socket,
             // and no other threads can read or write it.
             let local_port = unsafe { self.local_addr.get().read().port };
+            let id = unsafe { self.listener_id.get().read() };
             unsafe { self.local_addr.get().write(UNSPECIFIED_ENDPOINT) }; // clear bound address
-            LISTEN_TABLE.unlisten(local_port);
+            LISTEN_TABLE.unlisten(local_port, id);
             SOCKET_SET.poll_interfaces();
             Ok(())
         })
@@ -398,6 +561,43 @@ impl TcpSocket {
         })
     }
 
+    /// Transmits data by letting `f` fill the free space of the send buffer in
+    /// place, so the data does not go through an intermediate buffer.
+    ///
+    /// `f` may be called with a slice shorter than the free space when the
+    /// buffer wraps around, it returns the number of bytes it has filled.
+    pub fn send_with<F>(&self, mut f: F) -> AxResult<usize>
+    where
+        F: FnMut(&mut [u8]) -> AxResult<usize>,
+    {
+        if self.is_connecting() {
+            return Err(AxError::WouldBlock);
+        } else if !self.is_connected() {
+            return ax_err!(NotConnected, "socket send() failed");
+        }
+
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                if !socket.is_active() || !socket.may_send() {
+                    // closed by remote
+                    ax_err!(ConnectionReset, "socket send() failed")
+                } else if socket.can_send() {
+                    socket
+                        .send(|buf| match f(buf) {
+                            Ok(len) => (len, Ok(len)),
+                            Err(e) => (0, Err(e)),
+                        })
+                        .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?
+                } else {
+                    // tx buffer is full
+                    Err(AxError::WouldBlock)
+                }
+            })
+        })
+    }
+
     /// Whether the socket is readable or writable.
     pub fn poll(&self) -> AxResult<PollState> {
         match self.get_state() {
@@ -411,34 +611,218 @@ impl TcpSocket {
         }
     }
 
-    /// To set the nagle algorithm enabled or not.
-    pub fn set_nagle_enabled(&self, enabled: bool) -> AxResult {
-        let handle = unsafe { self.handle.get().read() };
+    /// Returns whether this socket only takes IPv6 connections.
+    #[inline]
+    pub fn is_ipv6_only(&self) -> bool {
+        self.ipv6_only.load(Ordering::Acquire)
+    }
 
-        let Some(handle) = handle else {
-            return Err(AxError::NotConnected);
-        };
+    /// Sets whether a socket listening on the IPv6 unspecified address only
+    /// takes IPv6 connections (`IPV6_V6ONLY`), or IPv4 ones as well.
+    ///
+    /// It must be called before [`listen`](Self::listen).
+    #[inline]
+    pub fn set_ipv6_only(&self, ipv6_only: bool) {
+        self.ipv6_only.store(ipv6_only, Ordering::Release);
+    }
+
+    /// Returns whether the local address can be reused (`SO_REUSEADDR`).
+    ///
+    /// The connections in `TIME_WAIT` never keep the port from listening, so
+    /// the address can always be reused, it's only kept for `getsockopt`.
+    #[inline]
+    pub fn is_reuse_addr(&self) -> bool {
+        self.reuse_addr.load(Ordering::Acquire)
+    }
 
-        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-            socket.set_nagle_enabled(enabled)
-        });
+    /// Sets whether the local address can be reused (`SO_REUSEADDR`).
+    #[inline]
+    pub fn set_reuse_addr(&self, reuse_addr: bool) {
+        self.reuse_addr.store(reuse_addr, Ordering::Release);
+    }
+
+    /// Returns whether the port can be shared by other listening sockets
+    /// (`SO_REUSEPORT`).
+    #[inline]
+    pub fn is_reuse_port(&self) -> bool {
+        self.reuse_port.load(Ordering::Acquire)
+    }
 
+    /// Sets whether the port can be shared by other listening sockets with
+    /// the option as well (`SO_REUSEPORT`), then the new connections are
+    /// distributed among them.
+    ///
+    /// It must be called before [`listen`](Self::listen).
+    #[inline]
+    pub fn set_reuse_port(&self, reuse_port: bool) {
+        self.reuse_port.store(reuse_port, Ordering::Release);
+    }
+
+    /// Returns whether the socket only connects to directly reachable hosts
+    /// (`SO_DONTROUTE`).
+    #[inline]
+    pub fn is_dont_route(&self) -> bool {
+        self.dont_route.load(Ordering::Acquire)
+    }
+
+    /// Sets whether the socket only connects to the hosts reachable without
+    /// a gateway (`SO_DONTROUTE`).
+    ///
+    /// It must be called before [`connect`](Self::connect).
+    #[inline]
+    pub fn set_dont_route(&self, dont_route: bool) {
+        self.dont_route.store(dont_route, Ordering::Release);
+    }
+
+    /// Returns the size of the receive buffer.
+    pub fn recv_buffer_size(&self) -> usize {
+        self.config.lock().recv_buf_size
+    }
+
+    /// Sets the size of the receive buffer (`SO_RCVBUF`).
+    ///
+    /// smoltcp can't resize the buffers of a connection, so it takes effect
+    /// on the connections created after it.
+    pub fn set_recv_buffer_size(&self, size: usize) {
+        self.update_config(|config| config.recv_buf_size = size);
+    }
+
+    /// Returns the size of the send buffer.
+    pub fn send_buffer_size(&self) -> usize {
+        self.config.lock().send_buf_size
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`).
+    ///
+    /// Like [`set_recv_buffer_size`](Self::set_recv_buffer_size), it takes
+    /// effect on the connections created after it.
+    pub fn set_send_buffer_size(&self, size: usize) {
+        self.update_config(|config| config.send_buf_size = size);
+    }
+
+    /// To set the nagle algorithm enabled or not.
+    pub fn set_nagle_enabled(&self, enabled: bool) -> AxResult {
+        self.update_config(|config| config.nagle = enabled);
         Ok(())
     }
 
     /// To get the nagle algorithm enabled or not.
     pub fn nagle_enabled(&self) -> bool {
-        let handle = unsafe { self.handle.get().read() };
+        self.config.lock().nagle
+    }
 
-        match handle {
-            Some(handle) => {
-                SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| socket.nagle_enabled())
-            }
-            // Nagle algorithm will be enabled by default once the socket is created
-            None => true,
+    /// Returns whether keepalives are sent (`SO_KEEPALIVE`).
+    pub fn keep_alive(&self) -> bool {
+        self.config.lock().keep_alive
+    }
+
+    /// Sets whether keepalives are sent on the idle connection, which is
+    /// aborted if the peer doesn't reply (`SO_KEEPALIVE`).
+    pub fn set_keep_alive(&self, enabled: bool) {
+        self.update_config(|config| config.keep_alive = enabled);
+    }
+
+    /// Returns how long the connection is idle before keepalives are sent.
+    pub fn keep_alive_idle(&self) -> Duration {
+        self.config.lock().keep_idle
+    }
+
+    /// Sets how long the connection is idle before keepalives are sent
+    /// (`TCP_KEEPIDLE`).
+    pub fn set_keep_alive_idle(&self, idle: Duration) {
+        self.update_config(|config| config.keep_idle = idle);
+    }
+
+    /// Returns the interval between the unanswered keepalives.
+    pub fn keep_alive_interval(&self) -> Duration {
+        self.config.lock().keep_interval
+    }
+
+    /// Sets the interval between the unanswered keepalives
+    /// (`TCP_KEEPINTVL`).
+    pub fn set_keep_alive_interval(&self, interval: Duration) {
+        self.update_config(|config| config.keep_interval = interval);
+    }
+
+    /// Returns how many keepalives are unanswered before the connection is
+    /// aborted.
+    pub fn keep_alive_count(&self) -> u32 {
+        self.config.lock().keep_count
+    }
+
+    /// Sets how many keepalives are unanswered before the connection is
+    /// aborted (`TCP_KEEPCNT`).
+    pub fn set_keep_alive_count(&self, count: u32) {
+        self.update_config(|config| config.keep_count = count);
+    }
+
+    /// Returns the linger timeout of [`shutdown`](Self::shutdown), `None` if
+    /// it doesn't linger.
+    pub fn linger(&self) -> Option<Duration> {
+        self.config.lock().linger
+    }
+
+    /// Sets the linger timeout of [`shutdown`](Self::shutdown) (`SO_LINGER`).
+    pub fn set_linger(&self, linger: Option<Duration>) {
+        self.update_config(|config| config.linger = linger);
+    }
+
+    /// Returns the congestion control algorithm (`TCP_CONGESTION`).
+    pub fn congestion(&self) -> CongestionControl {
+        self.config.lock().congestion
+    }
+
+    /// Sets the congestion control algorithm (`TCP_CONGESTION`), it also
+    /// takes effect on a connection in progress.
+    pub fn set_congestion(&self, algorithm: CongestionControl) {
+        self.update_config(|config| config.congestion = algorithm);
+        if let Some((local, remote)) = self.flow() {
+            congestion::set_algorithm(local, remote, algorithm);
         }
     }
 
+    /// Returns the state of the connection.
+    pub fn info(&self) -> TcpInfo {
+        let config = *self.config.lock();
+        let default = TcpInfo {
+            state: if self.is_listening() {
+                State::Listen
+            } else {
+                State::Closed
+            },
+            mss: 0,
+            recv_queue: 0,
+            send_queue: 0,
+            recv_capacity: config.recv_buf_size,
+            send_capacity: config.send_buf_size,
+            ack_delay: None,
+            nagle: config.nagle,
+            cwnd: 0,
+            ssthresh: usize::MAX,
+        };
+        let (cwnd, ssthresh) = self
+            .flow()
+            .and_then(|(local, remote)| congestion::window(local, remote))
+            .unwrap_or((0, usize::MAX));
+        self.with_socket(|socket| match socket {
+            Some(socket) => TcpInfo {
+                state: socket.state(),
+                mss: socket
+                    .remote_endpoint()
+                    .map_or(0, |endpoint| tcp_mss(endpoint.addr)),
+                recv_queue: socket.recv_queue(),
+                send_queue: socket.send_queue(),
+                recv_capacity: socket.recv_capacity(),
+                send_capacity: socket.send_capacity(),
+                ack_delay: socket.ack_delay().map(Into::into),
+                nagle: socket.nagle_enabled(),
+                cwnd,
+                ssthresh,
+            },
+            None => default,
+        })
+    }
+
     /// To get the socket and call the given function.
     ///
     /// If the socket is not connected, it will return None.
@@ -513,6 +897,60 @@ impl TcpSocket {
         }
     }
 
+    /// Returns the local and remote endpoints of the connection, if it is
+    /// connecting or connected.
+    fn flow(&self) -> Option<(IpEndpoint, IpEndpoint)> {
+        match self.get_state() {
+            // SAFETY: they are initialized in a connecting or connected socket.
+            STATE_CONNECTING | STATE_CONNECTED => unsafe {
+                Some((self.local_addr.get().read(), self.peer_addr.get().read()))
+            },
+            _ => None,
+        }
+    }
+
+    /// Changes the options, and applies them to the connection or the
+    /// listening socket.
+    fn update_config(&self, f: impl FnOnce(&mut TcpConfig)) {
+        let mut config = self.config.lock();
+        f(&mut config);
+        if let Some(handle) = unsafe { self.handle.get().read() } {
+            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                if socket.state() == State::Closed {
+                    // not connected yet, so the buffers can be replaced
+                    *socket = config.new_socket();
+                } else {
+                    config.apply(socket);
+                }
+            });
+        } else if self.is_listening() {
+            // SAFETY: they should be initialized in a listening socket.
+            let local_port = unsafe { self.local_addr.get().read().port };
+            let id = unsafe { self.listener_id.get().read() };
+            LISTEN_TABLE.set_config(local_port, id, *config);
+        }
+    }
+
+    /// Blocks until the peer acknowledges all the data and the FIN after the
+    /// connection is closed, or the timeout expires.
+    fn wait_linger(&self, handle: SocketHandle, timeout: Duration) {
+        let expire_at = current_time() + timeout;
+        loop {
+            self.register_waker();
+            SOCKET_SET.poll_interfaces();
+            let done = SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
+                matches!(
+                    socket.state(),
+                    State::FinWait2 | State::TimeWait | State::Closed
+                )
+            });
+            if done || current_time() >= expire_at {
+                break;
+            }
+            self.waiter.wait();
+        }
+    }
+
     #[inline]
     fn is_connecting(&self) -> bool {
         self.get_state() == STATE_CONNECTING
@@ -588,10 +1026,11 @@ impl TcpSocket {
     }
 
     fn poll_listener(&self) -> AxResult<PollState> {
-        // SAFETY: `self.local_addr` should be initialized in a listening socket.
+        // SAFETY: they should be initialized in a listening socket.
         let local_addr = unsafe { self.local_addr.get().read() };
+        let id = unsafe { self.listener_id.get().read() };
         Ok(PollState {
-            readable: LISTEN_TABLE.can_accept(local_addr.port)?,
+            readable: LISTEN_TABLE.can_accept(local_addr.port, id)?,
             writable: false,
         })
     }
@@ -618,15 +1057,37 @@ impl TcpSocket {
                         return Err(AxError::Interrupted);
                     }
                 }
+                self.register_waker();
                 SOCKET_SET.poll_interfaces();
                 match f() {
                     Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => axtask::yield_now(),
+                    Err(AxError::WouldBlock) => self.waiter.wait(),
                     Err(e) => return Err(e),
                 }
             }
         }
     }
+
+    /// Registers the waiter to be woken when the socket may be ready, or a
+    /// connection to the listening socket is established.
+    fn register_waker(&self) {
+        self.waiter.arm();
+        #[cfg(feature = "irq")]
+        match unsafe { self.handle.get().read() } {
+            Some(handle) => SOCKET_SET.register_waker::<tcp::Socket, _>(handle, |socket| {
+                let waker = self.waiter.waker();
+                socket.register_recv_waker(&waker);
+                socket.register_send_waker(&waker);
+            }),
+            None if self.is_listening() => {
+                // SAFETY: they should be initialized in a listening socket.
+                let local_port = unsafe { self.local_addr.get().read().port };
+                let id = unsafe { self.listener_id.get().read() };
+                LISTEN_TABLE.register_waker(local_port, id, self.waiter.waker());
+            }
+            None => {}
+        }
+    }
 }
 
 impl Read for TcpSocket {
@@ -647,14 +1108,44 @@ impl Write for TcpSocket {
 
 impl Drop for TcpSocket {
     fn drop(&mut self) {
+        let flow = self.flow();
         self.shutdown().ok();
         // Safe because we have mut reference to `self`.
         if let Some(handle) = unsafe { self.handle.get().read() } {
-            SOCKET_SET.remove(handle);
+            let closed = SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                // stop connecting if it's not connected yet
+                socket.close();
+                socket.set_timeout(Some(ORPHAN_TIMEOUT.into()));
+                socket.state() == State::Closed
+            });
+            if closed {
+                SOCKET_SET.remove(handle);
+                if let Some((local, remote)) = flow {
+                    congestion::unregister(local, remote);
+                }
+            } else {
+                // the connection goes on shutting down in the background
+                ORPHANS.lock().push((handle, flow));
+            }
         }
     }
 }
 
+/// Removes the orphaned connections that are closed.
+pub(crate) fn reap_orphans(sockets: &mut SocketSet<'_>) {
+    ORPHANS.lock().retain(|&(handle, flow)| {
+        let closed = sockets.get::<tcp::Socket>(handle).state() == State::Closed;
+        if closed {
+            sockets.remove(handle);
+            if let Some((local, remote)) = flow {
+                congestion::unregister(local, remote);
+            }
+            debug!("socket {}: destroyed", handle);
+        }
+        !closed
+    });
+}
+
 fn get_ephemeral_port() -> AxResult<u16> {
     const PORT_START: u16 = 0xc000;
     const PORT_END: u16 = 0xffff;
@@ -1,5 +1,5 @@
 use core::net::SocketAddr;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axhal::time::current_ticks;
@@ -11,8 +11,12 @@ use smoltcp::iface::SocketHandle;
 use smoltcp::socket::udp::{self, BindError, SendError};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
-use super::addr::{from_core_sockaddr, into_core_sockaddr, is_unspecified, UNSPECIFIED_ENDPOINT};
-use super::{SocketSetWrapper, SOCKET_SET};
+use super::addr::{
+    accepted_version, from_core_sockaddr, into_core_sockaddr, is_unspecified,
+    unspecified_endpoint_of,
+};
+use super::{check_on_link, SocketSetWrapper, SocketWaiter, SOCKET_SET};
+use super::{UDP_RX_BUF_LEN, UDP_TX_BUF_LEN};
 
 /// A UDP socket that provides POSIX-like APIs.
 pub struct UdpSocket {
@@ -21,13 +25,19 @@ pub struct UdpSocket {
     peer_addr: RwLock<Option<IpEndpoint>>,
     nonblock: AtomicBool,
     reuse_addr: AtomicBool,
+    reuse_port: AtomicBool,
+    ipv6_only: AtomicBool,
+    dont_route: AtomicBool,
+    recv_buf_size: AtomicUsize,
+    send_buf_size: AtomicUsize,
+    waiter: SocketWaiter,
 }
 
 impl UdpSocket {
     /// Creates a new UDP socket.
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let socket = SocketSetWrapper::new_udp_socket();
+        let socket = SocketSetWrapper::new_udp_socket(UDP_RX_BUF_LEN, UDP_TX_BUF_LEN);
         let handle = SOCKET_SET.add(socket);
         Self {
             handle,
@@ -35,6 +45,12 @@ impl UdpSocket {
             peer_addr: RwLock::new(None),
             nonblock: AtomicBool::new(false),
             reuse_addr: AtomicBool::new(false),
+            reuse_port: AtomicBool::new(false),
+            ipv6_only: AtomicBool::new(false),
+            dont_route: AtomicBool::new(false),
+            recv_buf_size: AtomicUsize::new(UDP_RX_BUF_LEN),
+            send_buf_size: AtomicUsize::new(UDP_TX_BUF_LEN),
+            waiter: SocketWaiter::new(),
         }
     }
 
@@ -97,6 +113,77 @@ impl UdpSocket {
         self.reuse_addr.store(reuse_addr, Ordering::Release);
     }
 
+    /// Returns whether the port can be shared by other sockets
+    /// (`SO_REUSEPORT`).
+    #[inline]
+    pub fn is_reuse_port(&self) -> bool {
+        self.reuse_port.load(Ordering::Acquire)
+    }
+
+    /// Sets whether the port can be shared by other sockets (`SO_REUSEPORT`).
+    ///
+    /// Like [`set_reuse_addr`](Self::set_reuse_addr) it skips the check of
+    /// `bind`, and a datagram is delivered to one of the sockets.
+    #[inline]
+    pub fn set_reuse_port(&self, reuse_port: bool) {
+        self.reuse_port.store(reuse_port, Ordering::Release);
+    }
+
+    /// Returns whether the socket only sends to directly reachable hosts
+    /// (`SO_DONTROUTE`).
+    #[inline]
+    pub fn is_dont_route(&self) -> bool {
+        self.dont_route.load(Ordering::Acquire)
+    }
+
+    /// Sets whether the socket only sends to the hosts reachable without a
+    /// gateway (`SO_DONTROUTE`).
+    #[inline]
+    pub fn set_dont_route(&self, dont_route: bool) {
+        self.dont_route.store(dont_route, Ordering::Release);
+    }
+
+    /// Returns the size of the receive buffer.
+    #[inline]
+    pub fn recv_buffer_size(&self) -> usize {
+        self.recv_buf_size.load(Ordering::Acquire)
+    }
+
+    /// Sets the size of the receive buffer (`SO_RCVBUF`).
+    ///
+    /// The datagrams queued in the socket are dropped.
+    pub fn set_recv_buffer_size(&self, size: usize) {
+        self.recv_buf_size.store(size, Ordering::Release);
+        self.rebuild();
+    }
+
+    /// Returns the size of the send buffer.
+    #[inline]
+    pub fn send_buffer_size(&self) -> usize {
+        self.send_buf_size.load(Ordering::Acquire)
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`).
+    ///
+    /// The datagrams queued in the socket are dropped.
+    pub fn set_send_buffer_size(&self, size: usize) {
+        self.send_buf_size.store(size, Ordering::Release);
+        self.rebuild();
+    }
+
+    /// Returns whether this socket only takes IPv6 datagrams.
+    #[inline]
+    pub fn is_ipv6_only(&self) -> bool {
+        self.ipv6_only.load(Ordering::Acquire)
+    }
+
+    /// Sets whether a socket bound to the IPv6 unspecified address only takes
+    /// IPv6 datagrams (`IPV6_V6ONLY`), or IPv4 ones as well.
+    #[inline]
+    pub fn set_ipv6_only(&self, ipv6_only: bool) {
+        self.ipv6_only.store(ipv6_only, Ordering::Release);
+    }
+
     /// Binds an unbound socket to the given address and port.
     ///
     /// It's must be called before [`send_to`](Self::send_to) and
@@ -117,7 +204,7 @@ impl UdpSocket {
             port: local_endpoint.port,
         };
 
-        if !self.is_reuse_addr() {
+        if !self.is_reuse_addr() && !self.is_reuse_port() {
             // Check if the address is already in use
             SOCKET_SET.bind_check(local_endpoint.addr, local_endpoint.port)?;
         }
@@ -188,11 +275,14 @@ impl UdpSocket {
     pub fn connect(&self, addr: SocketAddr) -> AxResult {
         let mut self_peer_addr = self.peer_addr.write();
 
+        let peer_endpoint = from_core_sockaddr(addr);
         if self.local_addr.read().is_none() {
-            self.bind(into_core_sockaddr(UNSPECIFIED_ENDPOINT))?;
+            self.bind(into_core_sockaddr(unspecified_endpoint_of(
+                peer_endpoint.addr,
+            )))?;
         }
 
-        *self_peer_addr = Some(from_core_sockaddr(addr));
+        *self_peer_addr = Some(peer_endpoint);
         debug!("UDP socket {}: connected to {}", self.handle, addr);
         Ok(())
     }
@@ -257,10 +347,29 @@ impl UdpSocket {
         }
     }
 
+    /// Replaces the smoltcp socket with one of the new buffer sizes, which
+    /// is bound to the same address.
+    fn rebuild(&self) {
+        // keep the socket from being bound meanwhile
+        let _local_addr = self.local_addr.write();
+        let mut socket =
+            SocketSetWrapper::new_udp_socket(self.recv_buffer_size(), self.send_buffer_size());
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |old| {
+            socket.set_hop_limit(old.hop_limit());
+            if old.is_open() {
+                socket.bind(old.endpoint()).ok();
+            }
+            *old = socket;
+        });
+    }
+
     fn send_impl(&self, buf: &[u8], remote_endpoint: IpEndpoint) -> AxResult<usize> {
         if self.local_addr.read().is_none() {
             return ax_err!(NotConnected, "socket send() failed");
         }
+        if self.is_dont_route() {
+            check_on_link(remote_endpoint.addr)?;
+        }
         // info!("send to addr: {:?}", remote_endpoint);
         self.block_on(|| {
             SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
@@ -289,11 +398,23 @@ impl UdpSocket {
     where
         F: FnMut(&mut udp::Socket) -> AxResult<T>,
     {
-        if self.local_addr.read().is_none() {
+        let Some(local_endpoint) = *self.local_addr.read() else {
             return ax_err!(NotConnected, "socket send() failed");
-        }
+        };
+        let version = if is_unspecified(local_endpoint.addr) {
+            accepted_version(local_endpoint.addr, self.is_ipv6_only())
+        } else {
+            None
+        };
         self.block_on(|| {
             SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                // drop the datagrams of the other IP version
+                while let Ok((_, meta)) = socket.peek() {
+                    if version.map_or(true, |version| version == meta.endpoint.addr.version()) {
+                        break;
+                    }
+                    socket.recv().ok();
+                }
                 if !socket.is_open() {
                     // not bound
                     ax_err!(NotConnected, "socket recv() failed")
@@ -325,16 +446,28 @@ impl UdpSocket {
                         return Err(AxError::Interrupted);
                     }
                 }
+                self.register_waker();
                 SOCKET_SET.poll_interfaces();
                 match f() {
                     Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => axtask::yield_now(),
+                    Err(AxError::WouldBlock) => self.waiter.wait(),
                     Err(e) => return Err(e),
                 }
             }
         }
     }
 
+    /// Registers the waiter to be woken when the socket may be ready.
+    fn register_waker(&self) {
+        self.waiter.arm();
+        #[cfg(feature = "irq")]
+        SOCKET_SET.register_waker::<udp::Socket, _>(self.handle, |socket| {
+            let waker = self.waiter.waker();
+            socket.register_recv_waker(&waker);
+            socket.register_send_waker(&waker);
+        });
+    }
+
     /// To get the socket and call the given function.
     ///
     /// If the socket is not connected, it will return None.
@@ -0,0 +1,87 @@
+//! Wait queues of sockets, where the blocked operations on them sleep.
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "irq")] {
+        use alloc::sync::Arc;
+        use alloc::task::Wake;
+        use core::sync::atomic::{AtomicBool, Ordering};
+        use core::task::Waker;
+        use core::time::Duration;
+
+        use axtask::WaitQueue;
+
+        /// The longest time a blocked operation sleeps, after which it checks
+        /// signals and its deadline again.
+        const BLOCK_INTERVAL: Duration = Duration::from_millis(10);
+
+        /// The wait queue of a socket, woken by smoltcp or the packet taps
+        /// when the socket may be ready.
+        pub(crate) struct SocketWaiter(Arc<WaiterInner>);
+
+        struct WaiterInner {
+            queue: WaitQueue,
+            woken: AtomicBool,
+        }
+
+        impl Wake for WaiterInner {
+            fn wake(self: Arc<Self>) {
+                self.wake_by_ref();
+            }
+
+            fn wake_by_ref(self: &Arc<Self>) {
+                self.woken.store(true, Ordering::Release);
+                self.queue.notify_all(false);
+            }
+        }
+
+        impl SocketWaiter {
+            pub fn new() -> Self {
+                Self(Arc::new(WaiterInner {
+                    queue: WaitQueue::new(),
+                    woken: AtomicBool::new(false),
+                }))
+            }
+
+            /// Forgets the wakeups so far, the socket must be checked after it.
+            pub fn arm(&self) {
+                self.0.woken.store(false, Ordering::Release);
+            }
+
+            /// Returns a waker to register to a smoltcp socket.
+            pub fn waker(&self) -> Waker {
+                Waker::from(self.0.clone())
+            }
+
+            /// Wakes the tasks sleeping on it.
+            pub fn wake(&self) {
+                self.0.wake_by_ref();
+            }
+
+            /// Sleeps until woken after the last [`arm`](Self::arm), or
+            /// [`BLOCK_INTERVAL`] has elapsed.
+            pub fn wait(&self) {
+                self.0
+                    .queue
+                    .wait_timeout_until(BLOCK_INTERVAL, || self.0.woken.load(Ordering::Acquire));
+            }
+        }
+    } else {
+        /// Without interrupts nothing runs the stack in the background, so
+        /// the blocked operations keep polling it by themselves.
+        pub(crate) struct SocketWaiter;
+
+        impl SocketWaiter {
+            pub fn new() -> Self {
+                Self
+            }
+
+            pub fn arm(&self) {}
+
+            pub fn wake(&self) {}
+
+            pub fn wait(&self) {
+                axtask::yield_now();
+            }
+        }
+    }
+}
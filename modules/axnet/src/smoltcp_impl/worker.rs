@@ -0,0 +1,100 @@
+//! The net worker, a kernel task that runs the stack in the background.
+//!
+//! Otherwise packets are only processed while some task is blocked in a
+//! socket call, and the timers of the stack, such as TCP retransmits and
+//! keepalives, stall when no task calls into it. The worker polls the
+//! interfaces when a NIC raises an interrupt, when a socket is changed, and
+//! when the timers expire.
+//!
+//! NIC interrupts are handled in two halves like NAPI of Linux: the handler
+//! only masks the IRQs and wakes the worker, and the worker acknowledges the
+//! devices, unmasks the IRQs and then receives all the pending packets.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axtask::WaitQueue;
+
+use super::{InterfaceDevice, InterfaceWrapper, INTERFACES, SOCKET_SET};
+
+/// The longest time the worker sleeps if all the NICs raise interrupts.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the NICs without IRQs are polled.
+const NO_IRQ_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+static WORKER_QUEUE: WaitQueue = WaitQueue::new();
+/// Whether a NIC has raised an interrupt since the worker acknowledged them.
+static IRQ_PENDING: AtomicBool = AtomicBool::new(false);
+/// Whether the worker should poll the interfaces at once.
+static POLL_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Registers the IRQ handler of a NIC, returns whether it succeeds.
+pub(crate) fn register_irq(irq: usize) -> bool {
+    axhal::irq::register_handler(irq, net_irq_handler)
+}
+
+/// Wakes the worker to poll the interfaces, as a changed socket may have
+/// packets to send.
+pub(crate) fn kick() {
+    POLL_PENDING.store(true, Ordering::Release);
+    WORKER_QUEUE.notify_one(false);
+}
+
+/// Starts the worker.
+pub(crate) fn spawn_worker() {
+    axtask::spawn(worker);
+}
+
+/// The IRQ handler of all the NICs.
+fn net_irq_handler() {
+    for irq in INTERFACES.iter().filter_map(|iface| iface.irq) {
+        axhal::irq::set_enable(irq, false);
+    }
+    IRQ_PENDING.store(true, Ordering::Release);
+    WORKER_QUEUE.notify_one(false);
+}
+
+/// Acknowledges the interrupts of the NICs, and unmasks their IRQs.
+///
+/// The packets arriving after it raise interrupts again, so they are not
+/// missed by the following poll.
+fn ack_interrupts() {
+    for iface in INTERFACES.iter() {
+        let Some(irq) = iface.irq else {
+            continue;
+        };
+        if let InterfaceDevice::Ethernet(dev) = &*iface.dev.lock() {
+            dev.inner.borrow_mut().ack_interrupt();
+        }
+        axhal::irq::set_enable(irq, true);
+    }
+}
+
+fn worker() {
+    let max_interval = if INTERFACES.iter().skip(1).all(|iface| iface.irq.is_some()) {
+        MAX_POLL_INTERVAL
+    } else {
+        NO_IRQ_POLL_INTERVAL
+    };
+    loop {
+        if IRQ_PENDING.swap(false, Ordering::AcqRel) {
+            ack_interrupts();
+        }
+        POLL_PENDING.store(false, Ordering::Release);
+        SOCKET_SET.poll_interfaces();
+
+        let now = InterfaceWrapper::current_time();
+        let delay = SOCKET_SET
+            .poll_delay(now)
+            .map_or(max_interval, |delay| delay.into())
+            .min(max_interval);
+        #[cfg(feature = "dhcp")]
+        let delay = INTERFACES
+            .iter()
+            .filter_map(|iface| iface.poll_dhcp(now))
+            .fold(delay, |delay, dhcp_delay| delay.min(dhcp_delay.into()));
+        WORKER_QUEUE.wait_timeout_until(delay, || {
+            IRQ_PENDING.load(Ordering::Acquire) || POLL_PENDING.load(Ordering::Acquire)
+        });
+    }
+}
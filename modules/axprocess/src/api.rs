@@ -10,7 +10,7 @@ use alloc::{
 };
 use axconfig::{MAX_USER_HEAP_SIZE, MAX_USER_STACK_SIZE, USER_HEAP_BASE, USER_STACK_TOP};
 use axerrno::{AxError, AxResult};
-use axhal::mem::VirtAddr;
+use axhal::mem::{VirtAddr, PAGE_SIZE_4K};
 use axhal::paging::MappingFlags;
 use axhal::KERNEL_PROCESS_ID;
 use axlog::{debug, info};
@@ -24,7 +24,7 @@ use elf_parser::{
 };
 use xmas_elf::program::SegmentData;
 
-use crate::flags::WaitStatus;
+use crate::flags::{WaitStatus, ADDR_NO_RANDOMIZE};
 use crate::futex::clear_wait;
 use crate::link::real_path;
 use crate::process::{Process, PID2PC, TID2TASK};
@@ -118,6 +118,10 @@ pub fn exit_current_task(exit_code: i32) -> ! {
 
         process.tasks.lock().clear();
         process.fd_manager.fd_table.lock().clear();
+        // 撤销该进程通过 SEM_UNDO 对信号量所做的修改
+        crate::ipc::sem::exit_sem(process.pid());
+        // 释放该进程持有的 POSIX 记录锁
+        axfs::lock::release_owner(axfs::lock::LockOwner::Process(process.pid()));
         #[cfg(feature = "signal")]
         process.signal_modules.lock().clear();
 
@@ -154,16 +158,62 @@ pub fn exit_current_task(exit_code: i32) -> ! {
     RUN_QUEUE.lock().exit_current(exit_code);
 }
 
+/// 开启地址空间布局随机化时，PIE 基址的最大偏移
+const PIE_RANDOM_RANGE: usize = 0x1000_0000;
+/// 开启地址空间布局随机化时，用户栈向下的最大偏移
+const STACK_RANDOM_RANGE: usize = 0x1000_0000;
+/// randomize_va_space 为 2 时，用户堆在栈之下的最大额外偏移
+const HEAP_RANDOM_RANGE: usize = 0x200_0000;
+/// 开启地址空间布局随机化时 mmap 区域的最低基址，位于用户栈与信号跳板之上
+const MMAP_RANDOM_BASE: usize = 0x4100_0000;
+/// 开启地址空间布局随机化时，mmap 基址的最大偏移
+const MMAP_RANDOM_RANGE: usize = 0x4000_0000;
+
+/// 地址空间布局随机化的级别，由 /proc/sys/kernel/randomize_va_space 控制
+///
+/// - 0：关闭随机化
+/// - 1：随机化 PIE 基址、用户栈与 mmap 区域
+/// - 2：在 1 的基础上随机化用户堆
+pub fn randomize_va_space() -> usize {
+    axfs::api::read_to_string("/proc/sys/kernel/randomize_va_space")
+        .ok()
+        .and_then(|level| level.trim().parse().ok())
+        .unwrap_or(2)
+}
+
+/// 返回 [0, range] 中一个随机的页对齐的偏移
+fn random_offset(range: usize) -> usize {
+    let pages = range / PAGE_SIZE_4K + 1;
+    (axhal::random::random_u64() as usize % pages) * PAGE_SIZE_4K
+}
+
+/// 等待内核随机数生成器就绪，在此之前生成的地址空间布局与 AT_RANDOM 都是可预测的
+///
+/// 启动后最早运行的程序需要等待时钟中断积累足够的熵；关中断时等待不会结束，因此不等待
+fn wait_random_ready() {
+    while !axhal::random::is_ready() && axhal::arch::irqs_enabled() {
+        yield_now_task();
+    }
+}
+
+/// 文件是否为交给 busybox sh 解释执行的脚本
+pub fn is_script(name: &str) -> bool {
+    name.ends_with(".sh")
+}
+
 /// 返回应用程序入口，用户栈底，用户堆底
+///
+/// 若 personality 中没有 ADDR_NO_RANDOMIZE，则按照 randomize_va_space 的级别随机化地址空间布局
 pub fn load_app(
     name: String,
     mut args: Vec<String>,
     envs: &Vec<String>,
     memory_set: &mut MemorySet,
+    personality: u32,
 ) -> AxResult<(VirtAddr, VirtAddr, VirtAddr)> {
-    if name.ends_with(".sh") {
+    if is_script(&name) {
         args = [vec![String::from("busybox"), String::from("sh")], args].concat();
-        return load_app("busybox".to_string(), args, envs, memory_set);
+        return load_app("busybox".to_string(), args, envs, memory_set, personality);
     }
     let elf_data = if let Ok(ans) = axfs::api::read(name.as_str()) {
         ans
@@ -187,10 +237,20 @@ pub fn load_app(
         let interp_path = interp_path.trim_matches(char::from(0)).to_string();
         let real_interp_path = real_path(&interp_path);
         args = [vec![real_interp_path.clone()], args].concat();
-        return load_app(real_interp_path, args, envs, memory_set);
+        return load_app(real_interp_path, args, envs, memory_set, personality);
     }
     info!("args: {:?}", args);
-    let elf_base_addr = Some(0x400_0000);
+    wait_random_ready();
+    let aslr = if personality & ADDR_NO_RANDOMIZE != 0 {
+        0
+    } else {
+        randomize_va_space()
+    };
+    let elf_base_addr = if aslr >= 1 {
+        Some(0x400_0000 + random_offset(PIE_RANDOM_RANGE))
+    } else {
+        Some(0x400_0000)
+    };
     axlog::warn!("The elf base addr may be different in different arch!");
     // let (entry, segments, relocate_pairs) = parse_elf(&elf, elf_base_addr);
     let entry = get_elf_entry(&elf, elf_base_addr);
@@ -214,7 +274,22 @@ pub fn load_app(
     }
 
     // Now map the stack and the heap
-    let heap_start = VirtAddr::from(USER_HEAP_BASE);
+    // 随机化时堆紧挨在栈之下，级别为 2 时二者之间再留出随机的间隔
+    let stack_top = if aslr >= 1 {
+        VirtAddr::from(USER_STACK_TOP - random_offset(STACK_RANDOM_RANGE))
+    } else {
+        VirtAddr::from(USER_STACK_TOP)
+    };
+    let heap_start = match aslr {
+        0 => VirtAddr::from(USER_HEAP_BASE),
+        1 => stack_top - MAX_USER_HEAP_SIZE,
+        _ => stack_top - MAX_USER_HEAP_SIZE - random_offset(HEAP_RANDOM_RANGE),
+    };
+    memory_set.set_mmap_base(if aslr >= 1 {
+        VirtAddr::from(MMAP_RANDOM_BASE + random_offset(MMAP_RANDOM_RANGE))
+    } else {
+        VirtAddr::from(axconfig::USER_MEMORY_START)
+    });
     let heap_data = [0_u8].repeat(MAX_USER_HEAP_SIZE);
     memory_set.new_region(
         heap_start,
@@ -231,10 +306,14 @@ pub fn load_app(
 
     let auxv = get_auxv_vector(&elf, elf_base_addr);
 
-    let stack_top = VirtAddr::from(USER_STACK_TOP);
     let stack_size = MAX_USER_STACK_SIZE;
 
-    let (stack_data, stack_bottom) = get_app_stack_region(args, envs, auxv, stack_top, stack_size);
+    let random = [
+        axhal::random::random_u64() as usize,
+        axhal::random::random_u64() as usize,
+    ];
+    let (stack_data, stack_bottom) =
+        get_app_stack_region(args, envs, auxv, stack_top, stack_size, random);
     memory_set.new_region(
         stack_top,
         stack_size,
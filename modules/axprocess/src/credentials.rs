@@ -0,0 +1,266 @@
+//! 进程的用户身份，决定进程能否访问文件、能否修改自己的身份
+//!
+//! 规则与 Linux 相同：有效用户 id 为 0 的进程拥有特权，
+//! 其余进程只能在真实、有效、保存的 id 之间切换
+extern crate alloc;
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxResult};
+use axfs::{api::Permissions, fops::FileAttr, xattr::PosixAcl};
+
+/// 附加组数量的上限，与 Linux 的 `NGROUPS_MAX` 相同
+pub const NGROUPS_MAX: usize = 65536;
+
+/// 请求读权限，与 `access` 的 `R_OK` 相同
+pub const MAY_READ: u32 = 4;
+/// 请求写权限，与 `access` 的 `W_OK` 相同
+pub const MAY_WRITE: u32 = 2;
+/// 请求执行（对目录来说是搜索）权限，与 `access` 的 `X_OK` 相同
+pub const MAY_EXEC: u32 = 1;
+
+/// 进程的用户身份，fork 时复制给子进程
+///
+/// 默认所有 id 都为 0，即 root
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    /// 真实用户 id
+    pub ruid: u32,
+    /// 有效用户 id，用于权限检查
+    pub euid: u32,
+    /// 保存的用户 id
+    pub suid: u32,
+    /// 真实组 id
+    pub rgid: u32,
+    /// 有效组 id，用于权限检查
+    pub egid: u32,
+    /// 保存的组 id
+    pub sgid: u32,
+    /// 附加组
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    /// 有效用户为 root 时拥有特权
+    pub fn is_privileged(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// 进程的有效组或附加组中是否包含 `gid`
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.egid == gid || self.groups.contains(&gid)
+    }
+
+    /// 用真实 id 代替有效 id，`access` 系列调用按真实用户检查权限
+    pub fn as_real(&self) -> Self {
+        Self {
+            euid: self.ruid,
+            egid: self.rgid,
+            ..self.clone()
+        }
+    }
+
+    /// 判断是否允许以 `want`（[`MAY_READ`]、[`MAY_WRITE`]、[`MAY_EXEC`] 的组合）访问文件
+    ///
+    /// 特权进程不受读写位限制，但执行普通文件仍要求至少有一个执行位。
+    /// 文件有访问 ACL 时按 ACL 检查，否则按权限位检查
+    pub fn may_access(&self, attr: &FileAttr, acl: Option<&PosixAcl>, want: u32) -> bool {
+        let mode = attr.perm().mode();
+        if self.is_privileged() {
+            return want & MAY_EXEC == 0 || attr.is_dir() || mode & 0o111 != 0;
+        }
+        if let Some(acl) = acl {
+            let in_group = |gid| self.in_group(gid);
+            return acl.check(attr.uid(), attr.gid(), self.euid, in_group, want);
+        }
+        let bits = if self.euid == attr.uid() {
+            mode >> 6
+        } else if self.in_group(attr.gid()) {
+            mode >> 3
+        } else {
+            mode
+        };
+        bits & want == want
+    }
+
+    /// 是否可以修改文件的权限位与时间等属性，要求是文件的属主或特权进程
+    pub fn owns(&self, attr: &FileAttr) -> bool {
+        self.is_privileged() || self.euid == attr.uid()
+    }
+
+    /// 是否可以删除或重命名目录 `dir` 中属性为 `attr` 的项，`dir_acl` 为目录的访问 ACL
+    ///
+    /// 需要对目录有写和搜索权限，若目录设置了 sticky 位，还要求是目录或文件的属主
+    pub fn may_delete(&self, dir: &FileAttr, dir_acl: Option<&PosixAcl>, attr: &FileAttr) -> bool {
+        if !self.may_access(dir, dir_acl, MAY_WRITE | MAY_EXEC) {
+            return false;
+        }
+        !dir.perm().contains(Permissions::STICKY) || self.owns(dir) || self.owns(attr)
+    }
+
+    /// `setuid`：特权进程同时设置三个用户 id，否则只能把有效用户 id 设为真实或保存的用户 id
+    pub fn setuid(&mut self, uid: u32) -> AxResult {
+        if self.is_privileged() {
+            self.ruid = uid;
+            self.suid = uid;
+        } else if uid != self.ruid && uid != self.suid {
+            return ax_err!(PermissionDenied);
+        }
+        self.euid = uid;
+        Ok(())
+    }
+
+    /// `setgid`：与 [`Credentials::setuid`] 相同，作用于组 id
+    pub fn setgid(&mut self, gid: u32) -> AxResult {
+        if self.is_privileged() {
+            self.rgid = gid;
+            self.sgid = gid;
+        } else if gid != self.rgid && gid != self.sgid {
+            return ax_err!(PermissionDenied);
+        }
+        self.egid = gid;
+        Ok(())
+    }
+
+    /// `setreuid`：`None` 表示不修改
+    ///
+    /// 非特权进程只能把真实用户 id 设为真实或有效用户 id，把有效用户 id 设为三者之一。
+    /// 若修改了真实用户 id，或有效用户 id 被设为与原真实用户 id 不同的值，
+    /// 保存的用户 id 随之变为新的有效用户 id
+    pub fn setreuid(&mut self, ruid: Option<u32>, euid: Option<u32>) -> AxResult {
+        if !self.is_privileged() {
+            let ruid_ok = ruid.map_or(true, |id| id == self.ruid || id == self.euid);
+            let euid_ok = euid.map_or(true, |id| {
+                id == self.ruid || id == self.euid || id == self.suid
+            });
+            if !ruid_ok || !euid_ok {
+                return ax_err!(PermissionDenied);
+            }
+        }
+        let old_ruid = self.ruid;
+        if let Some(id) = ruid {
+            self.ruid = id;
+        }
+        if let Some(id) = euid {
+            self.euid = id;
+        }
+        if ruid.is_some() || euid.is_some_and(|id| id != old_ruid) {
+            self.suid = self.euid;
+        }
+        Ok(())
+    }
+
+    /// `setregid`：与 [`Credentials::setreuid`] 相同，作用于组 id
+    pub fn setregid(&mut self, rgid: Option<u32>, egid: Option<u32>) -> AxResult {
+        if !self.is_privileged() {
+            let rgid_ok = rgid.map_or(true, |id| id == self.rgid || id == self.egid);
+            let egid_ok = egid.map_or(true, |id| {
+                id == self.rgid || id == self.egid || id == self.sgid
+            });
+            if !rgid_ok || !egid_ok {
+                return ax_err!(PermissionDenied);
+            }
+        }
+        let old_rgid = self.rgid;
+        if let Some(id) = rgid {
+            self.rgid = id;
+        }
+        if let Some(id) = egid {
+            self.egid = id;
+        }
+        if rgid.is_some() || egid.is_some_and(|id| id != old_rgid) {
+            self.sgid = self.egid;
+        }
+        Ok(())
+    }
+
+    /// `setresuid`：`None` 表示不修改，非特权进程的每个新值都必须是当前三个用户 id 之一
+    pub fn setresuid(
+        &mut self,
+        ruid: Option<u32>,
+        euid: Option<u32>,
+        suid: Option<u32>,
+    ) -> AxResult {
+        if !self.is_privileged() {
+            let current = [self.ruid, self.euid, self.suid];
+            if [ruid, euid, suid]
+                .iter()
+                .flatten()
+                .any(|id| !current.contains(id))
+            {
+                return ax_err!(PermissionDenied);
+            }
+        }
+        if let Some(id) = ruid {
+            self.ruid = id;
+        }
+        if let Some(id) = euid {
+            self.euid = id;
+        }
+        if let Some(id) = suid {
+            self.suid = id;
+        }
+        Ok(())
+    }
+
+    /// `setresgid`：与 [`Credentials::setresuid`] 相同，作用于组 id
+    pub fn setresgid(
+        &mut self,
+        rgid: Option<u32>,
+        egid: Option<u32>,
+        sgid: Option<u32>,
+    ) -> AxResult {
+        if !self.is_privileged() {
+            let current = [self.rgid, self.egid, self.sgid];
+            if [rgid, egid, sgid]
+                .iter()
+                .flatten()
+                .any(|id| !current.contains(id))
+            {
+                return ax_err!(PermissionDenied);
+            }
+        }
+        if let Some(id) = rgid {
+            self.rgid = id;
+        }
+        if let Some(id) = egid {
+            self.egid = id;
+        }
+        if let Some(id) = sgid {
+            self.sgid = id;
+        }
+        Ok(())
+    }
+
+    /// `setgroups`：只有特权进程可以设置附加组
+    pub fn setgroups(&mut self, groups: Vec<u32>) -> AxResult {
+        if !self.is_privileged() {
+            return ax_err!(PermissionDenied);
+        }
+        if groups.len() > NGROUPS_MAX {
+            return ax_err!(InvalidInput);
+        }
+        self.groups = groups;
+        Ok(())
+    }
+
+    /// exec 一个属性为 `attr` 的文件后更新身份，返回有效 id 是否改变
+    ///
+    /// 文件设置了 set-user-ID 或 set-group-ID 位时，有效 id 变为文件的属主或属组，
+    /// 之后保存的 id 都更新为有效 id。
+    /// `attr` 为 `None` 表示 exec 的是解释执行的脚本，同 Linux 忽略这两个位
+    pub fn exec(&mut self, attr: Option<&FileAttr>) -> bool {
+        let (euid, egid) = (self.euid, self.egid);
+        if let Some(attr) = attr {
+            let perm = attr.perm();
+            if perm.contains(Permissions::SET_UID) {
+                self.euid = attr.uid();
+            }
+            // 没有组执行位时 set-group-ID 表示强制锁，不改变身份
+            if perm.contains(Permissions::SET_GID | Permissions::GROUP_EXEC) {
+                self.egid = attr.gid();
+            }
+        }
+        self.suid = self.euid;
+        self.sgid = self.egid;
+        self.euid != euid || self.egid != egid
+    }
+}
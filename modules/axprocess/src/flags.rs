@@ -45,6 +45,12 @@ bitflags! {
     }
 }
 
+/// personality 中的标志，禁用地址空间布局随机化
+pub const ADDR_NO_RANDOMIZE: u32 = 0x0040000;
+
+/// exec 改变了有效 id 时，从 personality 中清除的标志，同 Linux 的 `PER_CLEAR_ON_SETID`
+pub const PER_CLEAR_ON_SETID: u32 = ADDR_NO_RANDOMIZE;
+
 /// sys_wait4 的返回值
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WaitStatus {
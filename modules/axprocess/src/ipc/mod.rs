@@ -0,0 +1,238 @@
+//! System V 进程间通信中的消息队列与信号量
+//!
+//! 共享内存由 `axmem` 实现
+extern crate alloc;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axerrno::LinuxError;
+use axhal::time::current_time;
+use core::time::Duration;
+
+use crate::credentials::Credentials;
+use crate::{current_process, yield_now_task};
+
+pub mod msg;
+pub mod sem;
+
+/// 私有的 IPC 对象，每次 get 都会创建一个新的对象
+pub const IPC_PRIVATE: i32 = 0;
+/// 若 key 对应的对象不存在则创建
+pub const IPC_CREAT: i32 = 0o1000;
+/// 与 IPC_CREAT 一同使用，若对象已经存在则失败
+pub const IPC_EXCL: i32 = 0o2000;
+/// 操作无法立即完成时不阻塞，直接返回错误
+pub const IPC_NOWAIT: i32 = 0o4000;
+
+/// 每种 IPC 对象的最大数目
+const IPC_MNI: usize = 32000;
+/// 与 Linux 相同，对象的 id 为 `seq * IPC_SEQ_MULTIPLIER + index`，其中 index 为对象在表中的下标
+const IPC_SEQ_MULTIPLIER: i32 = 32768;
+
+/// IPC 对象的所有者与权限信息
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IpcPerm {
+    /// 创建时使用的 key
+    pub key: i32,
+    /// 所有者的 uid
+    pub uid: u32,
+    /// 所有者的 gid
+    pub gid: u32,
+    /// 创建者的 uid
+    pub cuid: u32,
+    /// 创建者的 gid
+    pub cgid: u32,
+    /// 权限位
+    pub mode: u32,
+}
+
+impl IpcPerm {
+    /// 当前进程创建的对象的权限，所有者与创建者均为进程的有效用户与有效组
+    fn new(key: i32, mode: u32) -> Self {
+        let process = current_process();
+        let credentials = process.credentials.lock();
+        Self {
+            key,
+            uid: credentials.euid,
+            gid: credentials.egid,
+            cuid: credentials.euid,
+            cgid: credentials.egid,
+            mode: mode & 0o777,
+        }
+    }
+
+    /// 检查进程能否以 `want`（`MAY_READ`、`MAY_WRITE` 的组合）访问对象，否则返回 `EACCES`
+    ///
+    /// 与 Linux 相同，所有者或创建者使用属主权限位，所有组或创建者的组使用属组权限位，
+    /// 特权进程不受限制
+    pub fn check(&self, credentials: &Credentials, want: u32) -> Result<(), LinuxError> {
+        if credentials.is_privileged() {
+            return Ok(());
+        }
+        let bits = if credentials.euid == self.uid || credentials.euid == self.cuid {
+            self.mode >> 6
+        } else if credentials.in_group(self.gid) || credentials.in_group(self.cgid) {
+            self.mode >> 3
+        } else {
+            self.mode
+        };
+        if bits & want != want {
+            return Err(LinuxError::EACCES);
+        }
+        Ok(())
+    }
+
+    /// 检查进程是否为对象的所有者或创建者，`IPC_SET` 与 `IPC_RMID` 要求如此，否则返回 `EPERM`
+    pub fn check_owner(&self, credentials: &Credentials) -> Result<(), LinuxError> {
+        if credentials.is_privileged()
+            || credentials.euid == self.uid
+            || credentials.euid == self.cuid
+        {
+            return Ok(());
+        }
+        Err(LinuxError::EPERM)
+    }
+
+    /// 用当前进程的身份执行 [`IpcPerm::check`]
+    fn check_current(&self, want: u32) -> Result<(), LinuxError> {
+        self.check(&current_process().credentials.lock(), want)
+    }
+
+    /// 用当前进程的身份执行 [`IpcPerm::check_owner`]
+    fn check_current_owner(&self) -> Result<(), LinuxError> {
+        self.check_owner(&current_process().credentials.lock())
+    }
+
+    /// 对应 `IPC_SET`，修改所有者与权限位
+    pub fn set(&mut self, uid: u32, gid: u32, mode: u32) {
+        self.uid = uid;
+        self.gid = gid;
+        self.mode = (self.mode & !0o777) | (mode & 0o777);
+    }
+}
+
+/// `get` 已有的对象时 `flags` 中请求的权限，与 Linux 相同，任一类用户的权限位都视为请求
+fn requested_access(flags: i32) -> u32 {
+    let mode = flags as u32;
+    (mode >> 6 | mode >> 3 | mode) & 0o7
+}
+
+/// 当前时间，以秒为单位
+fn now_secs() -> usize {
+    current_time().as_secs() as usize
+}
+
+/// 按照下标和 key 索引的 IPC 对象表
+struct IpcTable<T> {
+    /// 下标到对象的 id 与对象本身
+    objects: BTreeMap<usize, (i32, Arc<T>)>,
+    keys: BTreeMap<i32, i32>,
+    /// 下一个对象 id 的序号，使得复用同一个下标的对象的 id 各不相同
+    seq: i32,
+}
+
+impl<T> IpcTable<T> {
+    const fn new() -> Self {
+        Self {
+            objects: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            seq: 0,
+        }
+    }
+
+    /// `msgget`、`semget` 等的公共逻辑
+    ///
+    /// 根据 key 查找已有的对象并用 `check` 检查，或者在需要时用 `create` 创建新的对象，返回对象的 id
+    fn get_or_create(
+        &mut self,
+        key: i32,
+        flags: i32,
+        check: impl FnOnce(&T) -> Result<(), LinuxError>,
+        create: impl FnOnce() -> Result<T, LinuxError>,
+    ) -> Result<i32, LinuxError> {
+        if key != IPC_PRIVATE {
+            if let Some(id) = self.keys.get(&key) {
+                if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                    return Err(LinuxError::EEXIST);
+                }
+                check(&*self.get(*id)?)?;
+                return Ok(*id);
+            }
+            if flags & IPC_CREAT == 0 {
+                return Err(LinuxError::ENOENT);
+            }
+        }
+        if self.objects.len() >= IPC_MNI {
+            return Err(LinuxError::ENOSPC);
+        }
+        let obj = create()?;
+        // 使用最小的空闲下标
+        let index = (0..)
+            .find(|index| !self.objects.contains_key(index))
+            .unwrap();
+        let id = self.seq * IPC_SEQ_MULTIPLIER + index as i32;
+        self.seq = (self.seq + 1) % (i32::MAX / IPC_SEQ_MULTIPLIER);
+        self.objects.insert(index, (id, Arc::new(obj)));
+        if key != IPC_PRIVATE {
+            self.keys.insert(key, id);
+        }
+        Ok(id)
+    }
+
+    /// id 对应的对象在表中的下标，id 不合法时返回 `None`
+    fn index_of(&self, id: i32) -> Option<usize> {
+        if id < 0 {
+            return None;
+        }
+        let index = (id % IPC_SEQ_MULTIPLIER) as usize;
+        match self.objects.get(&index) {
+            Some((obj_id, _)) if *obj_id == id => Some(index),
+            _ => None,
+        }
+    }
+
+    fn get(&self, id: i32) -> Result<Arc<T>, LinuxError> {
+        let index = self.index_of(id).ok_or(LinuxError::EINVAL)?;
+        Ok(self.objects[&index].1.clone())
+    }
+
+    /// 根据下标获取对象及其 id，对应 `MSG_STAT` 与 `SEM_STAT`
+    fn get_at(&self, index: usize) -> Result<(i32, Arc<T>), LinuxError> {
+        self.objects.get(&index).cloned().ok_or(LinuxError::EINVAL)
+    }
+
+    fn remove(&mut self, id: i32, key: i32) -> Result<Arc<T>, LinuxError> {
+        let index = self.index_of(id).ok_or(LinuxError::EINVAL)?;
+        let (_, obj) = self.objects.remove(&index).unwrap();
+        if self.keys.get(&key) == Some(&id) {
+            self.keys.remove(&key);
+        }
+        Ok(obj)
+    }
+
+    /// 已使用的最大下标，对应 `MSG_INFO` 等的返回值
+    fn max_index(&self) -> usize {
+        self.objects.keys().next_back().copied().unwrap_or(0)
+    }
+
+    /// 表中的所有对象
+    fn values(&self) -> impl Iterator<Item = &Arc<T>> {
+        self.objects.values().map(|(_, obj)| obj)
+    }
+}
+
+/// 等待 IPC 对象的状态发生变化
+///
+/// 若当前进程有待处理的信号则返回 `EINTR`，若已经超过了截止时间则返回 `EAGAIN`
+fn wait_for_change(deadline: Option<Duration>) -> Result<(), LinuxError> {
+    #[cfg(feature = "signal")]
+    if current_process().have_signals().is_some() {
+        return Err(LinuxError::EINTR);
+    }
+    if let Some(deadline) = deadline {
+        if current_time() >= deadline {
+            return Err(LinuxError::EAGAIN);
+        }
+    }
+    yield_now_task();
+    Ok(())
+}
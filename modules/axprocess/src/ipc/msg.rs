@@ -0,0 +1,255 @@
+//! System V 消息队列
+extern crate alloc;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::LinuxError;
+use axsync::Mutex;
+
+use super::{now_secs, requested_access, wait_for_change, IpcPerm, IpcTable, IPC_NOWAIT};
+use crate::credentials::{MAY_READ, MAY_WRITE};
+use crate::current_process;
+
+/// 单条消息的最大长度
+pub const MSGMAX: usize = 8192;
+/// 一个消息队列默认的最大字节数
+pub const MSGMNB: usize = 16384;
+/// 消息过长时将其截断，而不是返回错误
+pub const MSG_NOERROR: i32 = 0o10000;
+/// 接收第一条类型不等于 msgtyp 的消息
+pub const MSG_EXCEPT: i32 = 0o20000;
+/// 复制队列中第 msgtyp 条消息，而不将其移出队列
+pub const MSG_COPY: i32 = 0o40000;
+
+static MSG_QUEUES: Mutex<IpcTable<MsgQueue>> = Mutex::new(IpcTable::new());
+
+/// 消息队列的状态，对应 `struct msqid_ds`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MsgQueueInfo {
+    /// 所有者与权限
+    pub perm: IpcPerm,
+    /// 最后一次发送消息的时间
+    pub stime: usize,
+    /// 最后一次接收消息的时间
+    pub rtime: usize,
+    /// 最后一次修改的时间
+    pub ctime: usize,
+    /// 队列中消息的总字节数
+    pub cbytes: usize,
+    /// 队列中消息的数目
+    pub qnum: usize,
+    /// 队列允许的最大字节数
+    pub qbytes: usize,
+    /// 最后一次发送消息的进程
+    pub lspid: u64,
+    /// 最后一次接收消息的进程
+    pub lrpid: u64,
+}
+
+struct Message {
+    mtype: isize,
+    data: Vec<u8>,
+}
+
+struct MsgQueueInner {
+    info: MsgQueueInfo,
+    messages: VecDeque<Message>,
+    removed: bool,
+}
+
+/// 一个消息队列
+pub struct MsgQueue {
+    inner: Mutex<MsgQueueInner>,
+}
+
+impl MsgQueue {
+    fn new(key: i32, mode: u32) -> Self {
+        Self {
+            inner: Mutex::new(MsgQueueInner {
+                info: MsgQueueInfo {
+                    perm: IpcPerm::new(key, mode),
+                    ctime: now_secs(),
+                    qbytes: MSGMNB,
+                    ..Default::default()
+                },
+                messages: VecDeque::new(),
+                removed: false,
+            }),
+        }
+    }
+
+    /// 队列的状态，对应 `IPC_STAT`，需要读权限
+    pub fn stat(&self) -> Result<MsgQueueInfo, LinuxError> {
+        let inner = self.inner.lock();
+        inner.info.perm.check_current(MAY_READ)?;
+        Ok(inner.info)
+    }
+
+    /// 修改队列的所有者、权限与最大字节数，对应 `IPC_SET`
+    ///
+    /// 只有所有者、创建者与特权进程可以修改，最大字节数超过 [`MSGMNB`] 时要求特权
+    pub fn set(&self, uid: u32, gid: u32, mode: u32, qbytes: usize) -> Result<(), LinuxError> {
+        let mut inner = self.inner.lock();
+        let process = current_process();
+        let credentials = process.credentials.lock();
+        inner.info.perm.check_owner(&credentials)?;
+        if qbytes > MSGMNB && !credentials.is_privileged() {
+            return Err(LinuxError::EPERM);
+        }
+        inner.info.perm.set(uid, gid, mode);
+        inner.info.qbytes = qbytes;
+        inner.info.ctime = now_secs();
+        Ok(())
+    }
+
+    /// 向队列发送一条消息，队列已满时阻塞，需要写权限
+    pub fn send(&self, mtype: isize, data: &[u8], flags: i32) -> Result<(), LinuxError> {
+        if mtype < 1 || data.len() > MSGMAX {
+            return Err(LinuxError::EINVAL);
+        }
+        self.inner.lock().info.perm.check_current(MAY_WRITE)?;
+        let pid = current_process().pid();
+        loop {
+            let mut inner = self.inner.lock();
+            if inner.removed {
+                return Err(LinuxError::EIDRM);
+            }
+            let info = &mut inner.info;
+            if info.cbytes + data.len() <= info.qbytes && info.qnum < info.qbytes {
+                info.cbytes += data.len();
+                info.qnum += 1;
+                info.lspid = pid;
+                info.stime = now_secs();
+                inner.messages.push_back(Message {
+                    mtype,
+                    data: data.to_vec(),
+                });
+                return Ok(());
+            }
+            drop(inner);
+            if flags & IPC_NOWAIT != 0 {
+                return Err(LinuxError::EAGAIN);
+            }
+            wait_for_change(None)?;
+        }
+    }
+
+    /// 从队列中接收一条消息，返回消息的类型与内容，没有符合条件的消息时阻塞，需要读权限
+    ///
+    /// - `msgtyp` 为 0 时，接收第一条消息
+    /// - `msgtyp` 大于 0 时，接收第一条类型为 `msgtyp` 的消息，若指定了 `MSG_EXCEPT` 则接收第一条类型不为 `msgtyp` 的消息
+    /// - `msgtyp` 小于 0 时，接收类型不大于 `msgtyp` 绝对值的消息中类型最小的一条
+    pub fn receive(
+        &self,
+        max_size: usize,
+        msgtyp: isize,
+        flags: i32,
+    ) -> Result<(isize, Vec<u8>), LinuxError> {
+        if flags & MSG_COPY != 0 && (flags & IPC_NOWAIT == 0 || flags & MSG_EXCEPT != 0) {
+            return Err(LinuxError::EINVAL);
+        }
+        self.inner.lock().info.perm.check_current(MAY_READ)?;
+        let pid = current_process().pid();
+        loop {
+            let mut inner = self.inner.lock();
+            if inner.removed {
+                return Err(LinuxError::EIDRM);
+            }
+            if let Some(index) = Self::find(&inner.messages, msgtyp, flags) {
+                let msg = &inner.messages[index];
+                if msg.data.len() > max_size && flags & MSG_NOERROR == 0 {
+                    return Err(LinuxError::E2BIG);
+                }
+                let len = msg.data.len().min(max_size);
+                if flags & MSG_COPY != 0 {
+                    return Ok((msg.mtype, msg.data[..len].to_vec()));
+                }
+                let mut msg = inner.messages.remove(index).unwrap();
+                let info = &mut inner.info;
+                info.cbytes -= msg.data.len();
+                info.qnum -= 1;
+                info.lrpid = pid;
+                info.rtime = now_secs();
+                msg.data.truncate(len);
+                return Ok((msg.mtype, msg.data));
+            }
+            drop(inner);
+            if flags & IPC_NOWAIT != 0 {
+                return Err(LinuxError::ENOMSG);
+            }
+            wait_for_change(None)?;
+        }
+    }
+
+    fn find(messages: &VecDeque<Message>, msgtyp: isize, flags: i32) -> Option<usize> {
+        if flags & MSG_COPY != 0 {
+            return usize::try_from(msgtyp)
+                .ok()
+                .filter(|&index| index < messages.len());
+        }
+        let mut iter = messages.iter().enumerate();
+        if msgtyp == 0 {
+            iter.next().map(|(index, _)| index)
+        } else if msgtyp > 0 {
+            let except = flags & MSG_EXCEPT != 0;
+            iter.find(|(_, msg)| (msg.mtype == msgtyp) != except)
+                .map(|(index, _)| index)
+        } else {
+            iter.filter(|(_, msg)| msg.mtype <= -msgtyp)
+                .min_by_key(|(_, msg)| msg.mtype)
+                .map(|(index, _)| index)
+        }
+    }
+}
+
+/// 获取 key 对应的消息队列，必要时创建，返回队列的 id
+///
+/// 获取已有的队列时，要求拥有 `flags` 中请求的权限
+pub fn msgget(key: i32, flags: i32) -> Result<i32, LinuxError> {
+    MSG_QUEUES.lock().get_or_create(
+        key,
+        flags,
+        |queue| {
+            let perm = queue.inner.lock().info.perm;
+            perm.check_current(requested_access(flags))
+        },
+        || Ok(MsgQueue::new(key, flags as u32)),
+    )
+}
+
+/// 根据 id 获取消息队列
+pub fn msg_queue(msqid: i32) -> Result<Arc<MsgQueue>, LinuxError> {
+    MSG_QUEUES.lock().get(msqid)
+}
+
+/// 根据在表中的下标获取消息队列及其 id
+pub fn msg_queue_at(index: usize) -> Result<(i32, Arc<MsgQueue>), LinuxError> {
+    MSG_QUEUES.lock().get_at(index)
+}
+
+/// 删除消息队列，正在等待该队列的任务会返回 `EIDRM`
+///
+/// 只有所有者、创建者与特权进程可以删除
+pub fn msg_queue_remove(msqid: i32) -> Result<(), LinuxError> {
+    let mut queues = MSG_QUEUES.lock();
+    let perm = queues.get(msqid)?.inner.lock().info.perm;
+    perm.check_current_owner()?;
+    let key = perm.key;
+    let queue = queues.remove(msqid, key)?;
+    let mut inner = queue.inner.lock();
+    inner.removed = true;
+    inner.messages.clear();
+    Ok(())
+}
+
+/// 消息队列的使用情况，依次为队列数目、消息总数、消息总字节数与已使用的最大下标
+pub fn msg_usage() -> (usize, usize, usize, usize) {
+    let queues = MSG_QUEUES.lock();
+    let (mut msgs, mut bytes) = (0, 0);
+    for queue in queues.values() {
+        let inner = queue.inner.lock();
+        msgs += inner.info.qnum;
+        bytes += inner.info.cbytes;
+    }
+    (queues.objects.len(), msgs, bytes, queues.max_index())
+}
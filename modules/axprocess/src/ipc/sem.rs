@@ -0,0 +1,392 @@
+//! System V 信号量集
+extern crate alloc;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::LinuxError;
+use axsync::Mutex;
+use core::time::Duration;
+
+use super::{now_secs, requested_access, wait_for_change, IpcPerm, IpcTable, IPC_NOWAIT};
+use crate::credentials::{MAY_READ, MAY_WRITE};
+use crate::current_process;
+
+/// 一个信号量集中信号量的最大数目
+pub const SEMMSL: usize = 32000;
+/// 一次 semop 中操作的最大数目
+pub const SEMOPM: usize = 500;
+/// 信号量的最大值
+pub const SEMVMX: i32 = 32767;
+/// 进程退出时撤销该操作
+pub const SEM_UNDO: i16 = 0x1000;
+
+static SEM_SETS: Mutex<IpcTable<SemSet>> = Mutex::new(IpcTable::new());
+
+/// 各进程的 SEM_UNDO 调整值，按照 (semid, sem_num) 索引
+///
+/// 加锁顺序为先锁信号量集，再锁该表
+static SEM_UNDO_LIST: Mutex<BTreeMap<u64, BTreeMap<(i32, u16), i32>>> = Mutex::new(BTreeMap::new());
+
+/// semop 中的一个操作，对应 `struct sembuf`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SemBuf {
+    /// 信号量在集合中的下标
+    pub sem_num: u16,
+    /// 操作值，正数表示增加，负数表示减少，0 表示等待其变为 0
+    pub sem_op: i16,
+    /// IPC_NOWAIT 与 SEM_UNDO
+    pub sem_flg: i16,
+}
+
+/// 信号量集的状态，对应 `struct semid_ds`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SemSetInfo {
+    /// 所有者与权限
+    pub perm: IpcPerm,
+    /// 最后一次 semop 的时间
+    pub otime: usize,
+    /// 最后一次修改的时间
+    pub ctime: usize,
+    /// 信号量的数目
+    pub nsems: usize,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Sem {
+    val: i32,
+    pid: u64,
+    ncnt: usize,
+    zcnt: usize,
+}
+
+struct SemSetInner {
+    info: SemSetInfo,
+    sems: Vec<Sem>,
+    removed: bool,
+}
+
+/// 一个信号量集
+pub struct SemSet {
+    inner: Mutex<SemSetInner>,
+}
+
+impl SemSet {
+    fn new(key: i32, nsems: usize, mode: u32) -> Self {
+        Self {
+            inner: Mutex::new(SemSetInner {
+                info: SemSetInfo {
+                    perm: IpcPerm::new(key, mode),
+                    ctime: now_secs(),
+                    nsems,
+                    ..Default::default()
+                },
+                sems: alloc::vec![Sem::default(); nsems],
+                removed: false,
+            }),
+        }
+    }
+
+    /// 信号量集的状态，对应 `IPC_STAT`，需要读权限
+    pub fn stat(&self) -> Result<SemSetInfo, LinuxError> {
+        let inner = self.inner.lock();
+        inner.info.perm.check_current(MAY_READ)?;
+        Ok(inner.info)
+    }
+
+    /// 修改信号量集的所有者与权限，对应 `IPC_SET`，只有所有者、创建者与特权进程可以修改
+    pub fn set(&self, uid: u32, gid: u32, mode: u32) -> Result<(), LinuxError> {
+        let mut inner = self.inner.lock();
+        inner.info.perm.check_current_owner()?;
+        inner.info.perm.set(uid, gid, mode);
+        inner.info.ctime = now_secs();
+        Ok(())
+    }
+
+    /// 信号量的数目
+    pub fn nsems(&self) -> usize {
+        self.inner.lock().sems.len()
+    }
+
+    /// 检查读权限与信号量的下标
+    fn check_read(&self, sem_num: usize) -> Result<(), LinuxError> {
+        let inner = self.inner.lock();
+        inner.info.perm.check_current(MAY_READ)?;
+        if sem_num >= inner.sems.len() {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(())
+    }
+
+    /// 对应 `GETVAL`
+    pub fn get_val(&self, sem_num: usize) -> Result<i32, LinuxError> {
+        self.check_read(sem_num)?;
+        Ok(self.inner.lock().sems[sem_num].val)
+    }
+
+    /// 对应 `GETPID`
+    pub fn get_pid(&self, sem_num: usize) -> Result<u64, LinuxError> {
+        self.check_read(sem_num)?;
+        Ok(self.inner.lock().sems[sem_num].pid)
+    }
+
+    /// 对应 `GETNCNT`，返回等待信号量增加的任务数
+    pub fn get_ncnt(&self, sem_num: usize) -> Result<usize, LinuxError> {
+        self.check_read(sem_num)?;
+        Ok(self.inner.lock().sems[sem_num].ncnt)
+    }
+
+    /// 对应 `GETZCNT`，返回等待信号量变为 0 的任务数
+    pub fn get_zcnt(&self, sem_num: usize) -> Result<usize, LinuxError> {
+        self.check_read(sem_num)?;
+        Ok(self.inner.lock().sems[sem_num].zcnt)
+    }
+
+    /// 对应 `GETALL`，需要读权限
+    pub fn get_all(&self) -> Result<Vec<u16>, LinuxError> {
+        let inner = self.inner.lock();
+        inner.info.perm.check_current(MAY_READ)?;
+        Ok(inner.sems.iter().map(|sem| sem.val as u16).collect())
+    }
+
+    /// 对应 `SETVAL`，需要写权限，同时清除所有进程对该信号量的 SEM_UNDO 调整值
+    pub fn set_val(&self, semid: i32, sem_num: usize, val: i32) -> Result<(), LinuxError> {
+        let mut inner = self.inner.lock();
+        inner.info.perm.check_current(MAY_WRITE)?;
+        if !(0..=SEMVMX).contains(&val) {
+            return Err(LinuxError::ERANGE);
+        }
+        let sem = inner.sems.get_mut(sem_num).ok_or(LinuxError::EINVAL)?;
+        sem.val = val;
+        sem.pid = current_process().pid();
+        inner.info.ctime = now_secs();
+        for undo in SEM_UNDO_LIST.lock().values_mut() {
+            undo.remove(&(semid, sem_num as u16));
+        }
+        Ok(())
+    }
+
+    /// 对应 `SETALL`，需要写权限，同时清除所有进程对该信号量集的 SEM_UNDO 调整值
+    pub fn set_all(&self, semid: i32, vals: &[u16]) -> Result<(), LinuxError> {
+        let pid = current_process().pid();
+        let mut inner = self.inner.lock();
+        inner.info.perm.check_current(MAY_WRITE)?;
+        if vals.iter().any(|&val| val as i32 > SEMVMX) {
+            return Err(LinuxError::ERANGE);
+        }
+        for (sem, &val) in inner.sems.iter_mut().zip(vals) {
+            sem.val = val as i32;
+            sem.pid = pid;
+        }
+        inner.info.ctime = now_secs();
+        for undo in SEM_UNDO_LIST.lock().values_mut() {
+            undo.retain(|&(id, _), _| id != semid);
+        }
+        Ok(())
+    }
+
+    /// 原子地执行一组操作，无法全部完成时阻塞，直到超过截止时间
+    ///
+    /// 修改信号量的操作需要写权限，只等待信号量变为 0 的操作需要读权限
+    pub fn semop(
+        &self,
+        semid: i32,
+        ops: &[SemBuf],
+        deadline: Option<Duration>,
+    ) -> Result<(), LinuxError> {
+        if ops.is_empty() {
+            return Err(LinuxError::EINVAL);
+        }
+        if ops.len() > SEMOPM {
+            return Err(LinuxError::E2BIG);
+        }
+        let want = if ops.iter().any(|op| op.sem_op != 0) {
+            MAY_WRITE
+        } else {
+            MAY_READ
+        };
+        self.inner.lock().info.perm.check_current(want)?;
+        let pid = current_process().pid();
+        // 当前正在等待的信号量与等待的类型，true 表示等待其变为 0
+        let mut waiting: Option<(usize, bool)> = None;
+        let result = loop {
+            let mut inner = self.inner.lock();
+            if let Some((num, zero)) = waiting.take() {
+                if let Some(sem) = inner.sems.get_mut(num) {
+                    if zero {
+                        sem.zcnt -= 1;
+                    } else {
+                        sem.ncnt -= 1;
+                    }
+                }
+            }
+            if inner.removed {
+                break Err(LinuxError::EIDRM);
+            }
+            if ops.iter().any(|op| op.sem_num as usize >= inner.sems.len()) {
+                break Err(LinuxError::EFBIG);
+            }
+            match Self::try_apply(&inner.sems, ops) {
+                Ok(vals) => {
+                    for op in ops {
+                        inner.sems[op.sem_num as usize].pid = pid;
+                    }
+                    for (sem, val) in inner.sems.iter_mut().zip(vals) {
+                        sem.val = val;
+                    }
+                    inner.info.otime = now_secs();
+                    Self::record_undo(pid, semid, ops);
+                    break Ok(());
+                }
+                Err(Some((index, zero))) => {
+                    if ops[index].sem_flg & IPC_NOWAIT as i16 != 0 {
+                        break Err(LinuxError::EAGAIN);
+                    }
+                    let sem = &mut inner.sems[ops[index].sem_num as usize];
+                    if zero {
+                        sem.zcnt += 1;
+                    } else {
+                        sem.ncnt += 1;
+                    }
+                    waiting = Some((ops[index].sem_num as usize, zero));
+                }
+                Err(None) => break Err(LinuxError::ERANGE),
+            }
+            drop(inner);
+            if let Err(err) = wait_for_change(deadline) {
+                break Err(err);
+            }
+        };
+        if let Some((num, zero)) = waiting {
+            let mut inner = self.inner.lock();
+            if let Some(sem) = inner.sems.get_mut(num) {
+                if zero {
+                    sem.zcnt -= 1;
+                } else {
+                    sem.ncnt -= 1;
+                }
+            }
+        }
+        result
+    }
+
+    /// 在信号量的副本上依次执行各个操作
+    ///
+    /// 成功时返回执行后的值；需要阻塞时返回阻塞的操作的下标以及是否在等待信号量变为 0；
+    /// 结果超出范围时返回 `Err(None)`
+    fn try_apply(sems: &[Sem], ops: &[SemBuf]) -> Result<Vec<i32>, Option<(usize, bool)>> {
+        let mut vals: Vec<i32> = sems.iter().map(|sem| sem.val).collect();
+        for (index, op) in ops.iter().enumerate() {
+            let val = &mut vals[op.sem_num as usize];
+            let op_val = op.sem_op as i32;
+            if op_val == 0 {
+                if *val != 0 {
+                    return Err(Some((index, true)));
+                }
+            } else if *val + op_val < 0 {
+                return Err(Some((index, false)));
+            } else if *val + op_val > SEMVMX {
+                return Err(None);
+            } else {
+                *val += op_val;
+            }
+        }
+        Ok(vals)
+    }
+
+    fn record_undo(pid: u64, semid: i32, ops: &[SemBuf]) {
+        if ops.iter().all(|op| op.sem_flg & SEM_UNDO == 0) {
+            return;
+        }
+        let mut undo_list = SEM_UNDO_LIST.lock();
+        let undo = undo_list.entry(pid).or_default();
+        for op in ops.iter().filter(|op| op.sem_flg & SEM_UNDO != 0) {
+            let adj = undo.entry((semid, op.sem_num)).or_insert(0);
+            *adj -= op.sem_op as i32;
+            if *adj == 0 {
+                undo.remove(&(semid, op.sem_num));
+            }
+        }
+    }
+}
+
+/// 获取 key 对应的信号量集，必要时创建，返回信号量集的 id
+///
+/// 获取已有的信号量集时，要求拥有 `flags` 中请求的权限
+pub fn semget(key: i32, nsems: usize, flags: i32) -> Result<i32, LinuxError> {
+    if nsems > SEMMSL {
+        return Err(LinuxError::EINVAL);
+    }
+    SEM_SETS.lock().get_or_create(
+        key,
+        flags,
+        |set| {
+            set.inner
+                .lock()
+                .info
+                .perm
+                .check_current(requested_access(flags))?;
+            if nsems > set.nsems() {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(())
+        },
+        || {
+            if nsems == 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(SemSet::new(key, nsems, flags as u32))
+        },
+    )
+}
+
+/// 根据 id 获取信号量集
+pub fn sem_set(semid: i32) -> Result<Arc<SemSet>, LinuxError> {
+    SEM_SETS.lock().get(semid)
+}
+
+/// 根据在表中的下标获取信号量集及其 id
+pub fn sem_set_at(index: usize) -> Result<(i32, Arc<SemSet>), LinuxError> {
+    SEM_SETS.lock().get_at(index)
+}
+
+/// 删除信号量集，正在等待的任务会返回 `EIDRM`
+///
+/// 只有所有者、创建者与特权进程可以删除
+pub fn sem_set_remove(semid: i32) -> Result<(), LinuxError> {
+    let mut sets = SEM_SETS.lock();
+    let perm = sets.get(semid)?.inner.lock().info.perm;
+    perm.check_current_owner()?;
+    let key = perm.key;
+    let set = sets.remove(semid, key)?;
+    set.inner.lock().removed = true;
+    drop(sets);
+    for undo in SEM_UNDO_LIST.lock().values_mut() {
+        undo.retain(|&(id, _), _| id != semid);
+    }
+    Ok(())
+}
+
+/// 信号量集的使用情况，依次为信号量集数目、信号量总数与已使用的最大下标
+pub fn sem_usage() -> (usize, usize, usize) {
+    let sets = SEM_SETS.lock();
+    let sems = sets.values().map(|set| set.nsems()).sum();
+    (sets.objects.len(), sems, sets.max_index())
+}
+
+/// 进程退出时执行其所有的 SEM_UNDO 调整
+pub fn exit_sem(pid: u64) {
+    let Some(undo) = SEM_UNDO_LIST.lock().remove(&pid) else {
+        return;
+    };
+    for ((semid, sem_num), adj) in undo {
+        let Ok(set) = sem_set(semid) else {
+            continue;
+        };
+        let mut inner = set.inner.lock();
+        if let Some(sem) = inner.sems.get_mut(sem_num as usize) {
+            sem.val = (sem.val + adj).clamp(0, SEMVMX);
+            sem.pid = pid;
+        }
+    }
+}
@@ -6,8 +6,10 @@ pub use api::*;
 mod process;
 pub use process::{Process, PID2PC, TID2TASK};
 
+pub mod credentials;
 pub mod flags;
 pub mod futex;
+pub mod ipc;
 pub mod link;
 mod stdio;
 
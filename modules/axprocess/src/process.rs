@@ -14,8 +14,9 @@ use axlog::{debug, error};
 use axmem::MemorySet;
 use axsync::Mutex;
 use axtask::{current, AxTaskRef, TaskId, TaskInner, RUN_QUEUE};
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, Ordering};
 
+use crate::credentials::Credentials;
 use crate::fd_manager::FdManager;
 use crate::flags::CloneFlags;
 use crate::futex::FutexRobustList;
@@ -83,6 +84,12 @@ pub struct Process {
 
     /// 该进程可执行文件所在的路径
     pub file_path: Mutex<String>,
+
+    /// 进程的执行域，由 personality 系统调用设置，在 fork 与 exec 时保留
+    pub personality: AtomicU32,
+
+    /// 进程的用户身份，在 fork 时继承，在 exec 时根据 set-user-ID 位更新
+    pub credentials: Mutex<Credentials>,
 }
 
 impl Process {
@@ -157,6 +164,16 @@ impl Process {
         (*self.file_path.lock()).clone()
     }
 
+    /// get the personality of the process
+    pub fn get_personality(&self) -> u32 {
+        self.personality.load(Ordering::Acquire)
+    }
+
+    /// set the personality of the process
+    pub fn set_personality(&self, personality: u32) {
+        self.personality.store(personality, Ordering::Release)
+    }
+
     /// 若进程运行完成，则获取其返回码
     /// 若正在运行（可能上锁或没有上锁），则返回None
     pub fn get_code_if_exit(&self) -> Option<i32> {
@@ -192,6 +209,8 @@ impl Process {
             robust_list: Mutex::new(BTreeMap::new()),
             blocked_by_vfork: Mutex::new(false),
             file_path: Mutex::new(String::new()),
+            personality: AtomicU32::new(0),
+            credentials: Mutex::new(Credentials::default()),
         }
     }
     /// 根据给定参数创建一个新的进程，作为应用程序初始进程
@@ -224,7 +243,7 @@ impl Process {
         }
 
         let (entry, user_stack_bottom, heap_bottom) =
-            if let Ok(ans) = load_app(path.clone(), args, envs, &mut memory_set) {
+            if let Ok(ans) = load_app(path.clone(), args, envs, &mut memory_set, 0) {
                 ans
             } else {
                 error!("Failed to load app {}", path);
@@ -387,9 +406,13 @@ impl Process {
         } else {
             args
         };
-        let (entry, user_stack_bottom, heap_bottom) = if let Ok(ans) =
-            load_app(name.clone(), args, envs, &mut self.memory_set.lock().lock())
-        {
+        let (entry, user_stack_bottom, heap_bottom) = if let Ok(ans) = load_app(
+            name.clone(),
+            args,
+            envs,
+            &mut self.memory_set.lock().lock(),
+            self.get_personality(),
+        ) {
             ans
         } else {
             error!("Failed to load app {}", name);
@@ -640,6 +663,8 @@ impl Process {
                 self.get_heap_bottom(),
                 self.fd_manager.fd_table.lock().clone(),
             ));
+            new_process.set_personality(self.get_personality());
+            *new_process.credentials.lock() = self.credentials.lock().clone();
             // 记录该进程，防止被回收
             PID2PC.lock().insert(process_id, Arc::clone(&new_process));
             new_process.tasks.lock().push(Arc::clone(&new_task));
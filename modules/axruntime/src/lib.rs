@@ -113,6 +113,20 @@ impl axlog::LogIf for LogIfImpl {
     }
 }
 
+#[cfg(feature = "alloc")]
+struct KmemIfImpl;
+
+#[cfg(feature = "alloc")]
+#[crate_interface::impl_interface]
+impl axalloc::KmemIf for KmemIfImpl {
+    fn current_cpu_id() -> Option<usize> {
+        #[cfg(feature = "smp")]
+        return Some(axhal::cpu::this_cpu_id());
+        #[cfg(not(feature = "smp"))]
+        Some(0)
+    }
+}
+
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 static INITED_CPUS: AtomicUsize = AtomicUsize::new(0);
@@ -24,10 +24,14 @@ use axhal::arch::TrapFrame;
 use crate::stat::TimeStat;
 
 use crate::{AxRunQueue, AxTask, AxTaskRef, WaitQueue};
+use axalloc::ArcCache;
 
 #[allow(unused)]
 use crate_interface::call_interface;
 
+/// The object cache of the tasks.
+static TASK_CACHE: ArcCache<AxTask> = ArcCache::new("task_struct");
+
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct TaskId(u64);
@@ -578,7 +582,7 @@ impl TaskInner {
             // FIXME: name 现已被用作 prctl 使用的程序名，应另选方式判断 idle 进程
             t.is_idle = true;
         }
-        Arc::new(AxTask::new(t))
+        TASK_CACHE.new_arc(AxTask::new(t))
     }
 
     /// Creates an "init task" using the current CPU states, to use as the
@@ -596,7 +600,7 @@ impl TaskInner {
             // FIXME: name 现已被用作 prctl 使用的程序名，应另选方式判断 idle 进程
             t.is_idle = true;
         }
-        Arc::new(AxTask::new(t))
+        TASK_CACHE.new_arc(AxTask::new(t))
     }
 
     #[inline]
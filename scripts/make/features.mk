@@ -43,12 +43,7 @@ else ifneq ($(findstring monolithic,$(APP)),)
   ifneq ($(filter fs net pipe select epoll,$(FEATURES)),)
     override FEATURES += fd
   endif
-  ifeq ($(filter ext4fs,$(FEATURES)),)
-    override FEATURES += fatfs
-  endif
-  ifeq ($(filter ext4_rs,$(FEATURES)),)
-    override FEATURES += ext4_rs
-  endif
+  override FEATURES += fatfs ext4fs
 endif
 
 ifeq ($(GIC_V3), y)
@@ -0,0 +1,22 @@
+#!/bin/bash
+#
+# Run a DHCP server on the virtual bridge, to test the DHCP client of the
+# kernel without a real network (run with `IP= NET_DEV=bridge`).
+#
+# sudo ./dhcp-server.sh [virbr0]
+
+BR=$1
+RANGE=10.0.2.100,10.0.2.200
+GW=10.0.2.2
+DNS=10.0.2.2
+
+if [ -z "$BR" ]; then
+    BR=virbr0
+fi
+
+echo "Serving DHCP on $BR ($RANGE) ..."
+
+dnsmasq --no-daemon --port=0 --interface=$BR --bind-interfaces \
+    --dhcp-range=$RANGE,2m \
+    --dhcp-option=option:router,$GW \
+    --dhcp-option=option:dns-server,$DNS
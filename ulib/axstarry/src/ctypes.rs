@@ -185,6 +185,69 @@ bitflags! {
     }
 }
 
+numeric_enum_macro::numeric_enum! {
+    #[repr(usize)]
+    #[allow(non_camel_case_types)]
+    #[derive(Eq, PartialEq, Debug, Copy, Clone)]
+    /// sys_madvise 指定的建议
+    pub enum MadviseAdvice {
+        /// 无特殊处理
+        MADV_NORMAL = 0,
+        /// 页面将被随机访问
+        MADV_RANDOM = 1,
+        /// 页面将被顺序访问
+        MADV_SEQUENTIAL = 2,
+        /// 页面即将被访问，可以预读
+        MADV_WILLNEED = 3,
+        /// 页面不再需要，释放后再次访问时重新填零
+        MADV_DONTNEED = 4,
+        /// 页面可以被释放
+        MADV_FREE = 8,
+        /// 释放页面及其后端存储
+        MADV_REMOVE = 9,
+        /// fork 时子进程不继承该区域
+        MADV_DONTFORK = 10,
+        /// 撤销 MADV_DONTFORK
+        MADV_DOFORK = 11,
+        /// 允许合并相同页面
+        MADV_MERGEABLE = 12,
+        /// 撤销 MADV_MERGEABLE
+        MADV_UNMERGEABLE = 13,
+        /// 使用透明大页
+        MADV_HUGEPAGE = 14,
+        /// 不使用透明大页
+        MADV_NOHUGEPAGE = 15,
+        /// core dump 时不包含该区域
+        MADV_DONTDUMP = 16,
+        /// 撤销 MADV_DONTDUMP
+        MADV_DODUMP = 17,
+        /// fork 时子进程中该区域填零
+        MADV_WIPEONFORK = 18,
+        /// 撤销 MADV_WIPEONFORK
+        MADV_KEEPONFORK = 19,
+        /// 页面较冷，可以优先回收
+        MADV_COLD = 20,
+        /// 立即回收页面
+        MADV_PAGEOUT = 21,
+    }
+}
+
+bitflags! {
+    #[derive(Debug)]
+    /// 指定 mlockall 的选项
+    pub struct MlockAllFlags: u32 {
+        /// 锁定当前已映射的所有页面
+        const MCL_CURRENT = 1 << 0;
+        /// 锁定之后映射的所有页面
+        const MCL_FUTURE = 1 << 1;
+        /// 页面在被访问时才锁定
+        const MCL_ONFAULT = 1 << 2;
+    }
+}
+
+/// mlock2 的选项：页面在被访问时才锁定
+pub const MLOCK_ONFAULT: usize = 1;
+
 /// sys_uname 中指定的结构体类型
 #[repr(C)]
 pub struct UtsName {
@@ -280,6 +343,26 @@ pub struct IoVec {
     /// length of the buffer
     pub len: usize,
 }
+
+/// sendmsg/recvmsg使用的结构体
+#[repr(C)]
+pub struct MsgHdr {
+    /// 对端地址，可以为空
+    pub name: *mut u8,
+    /// 对端地址的长度
+    pub name_len: u32,
+    /// 数据所在的缓冲区
+    pub iov: *mut IoVec,
+    /// 缓冲区的个数
+    pub iov_len: usize,
+    /// 辅助数据，目前不支持
+    pub control: *mut u8,
+    /// 辅助数据的长度
+    pub control_len: usize,
+    /// 接收到的消息的标志
+    pub flags: i32,
+}
+
 /// 对 futex 的操作
 pub enum FutexFlags {
     /// 检查用户地址 uaddr 处的值。如果不是要求的值则等待 wake
@@ -320,11 +403,46 @@ numeric_enum_macro::numeric_enum! {
         F_GETFL = 3,
         /// 设置 flags 信息
         F_SETFL = 4,
+        /// 查询会阻止给定记录锁的锁
+        F_GETLK = 5,
+        /// 加记录锁或解锁，冲突时立即返回
+        F_SETLK = 6,
+        /// 加记录锁或解锁，冲突时等待
+        F_SETLKW = 7,
+        /// 与 F_GETLK 相同，但锁属于打开的文件而不是进程
+        F_OFD_GETLK = 36,
+        /// 与 F_SETLK 相同，但锁属于打开的文件而不是进程
+        F_OFD_SETLK = 37,
+        /// 与 F_SETLKW 相同，但锁属于打开的文件而不是进程
+        F_OFD_SETLKW = 38,
         /// 复制 fd，然后设置 cloexec 信息，即 exec 成功时删除该 fd
         F_DUPFD_CLOEXEC = 1030,
     }
 }
 
+/// 读锁（共享锁）
+pub const F_RDLCK: i16 = 0;
+/// 写锁（互斥锁）
+pub const F_WRLCK: i16 = 1;
+/// 解锁
+pub const F_UNLCK: i16 = 2;
+
+/// fcntl 记录锁命令使用的结构体
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Flock {
+    /// 锁的类型，为 F_RDLCK、F_WRLCK 或 F_UNLCK
+    pub l_type: i16,
+    /// l_start 的起点，与 lseek 的 whence 相同
+    pub l_whence: i16,
+    /// 锁的起始偏移
+    pub l_start: i64,
+    /// 锁的长度，0 表示到文件末尾，负数表示向前锁定
+    pub l_len: i64,
+    /// F_GETLK 返回持有冲突锁的进程，OFD 锁为 -1
+    pub l_pid: i32,
+}
+
 /// syscall_info 用到的 结构体
 #[repr(C)]
 #[derive(Debug)]
@@ -470,6 +588,21 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// splice、tee 与 vmsplice 用到的选项
+    #[derive(Debug)]
+    pub struct SpliceFlags: u32 {
+        /// 尝试移动页面而非复制，这里的实现本就不经过中间缓冲区，忽略
+        const MOVE = 1 << 0;
+        /// 不阻塞地操作管道
+        const NONBLOCK = 1 << 1;
+        /// 后续还有更多数据，忽略
+        const MORE = 1 << 2;
+        /// vmsplice 时将用户页面交给内核，这里直接复制，忽略
+        const GIFT = 1 << 3;
+    }
+}
+
 /// 文件系统的属性
 /// 具体参数定义信息来自 `https://man7.org/linux/man-pages/man2/statfs64.2.html`
 #[repr(C)]
@@ -519,6 +652,25 @@ pub fn get_fs_stat() -> FsStat {
     }
 }
 
+impl From<axfs::api::FileSystemInfo> for FsStat {
+    fn from(info: axfs::api::FileSystemInfo) -> Self {
+        FsStat {
+            f_type: info.fs_type as i64,
+            f_bsize: info.block_size as i64,
+            f_blocks: info.blocks,
+            f_bfree: info.blocks_free,
+            f_bavail: info.blocks_available,
+            f_files: info.files,
+            f_ffree: info.files_free,
+            f_fsid: [0, 0],
+            f_namelen: info.name_len as isize,
+            f_frsize: info.block_size as isize,
+            f_flags: 0,
+            f_spare: [0, 0, 0, 0],
+        }
+    }
+}
+
 bitflags! {
     /// 指定 st_mode 的选项
     pub struct StMode: u32 {
@@ -528,6 +680,8 @@ bitflags! {
         const S_IFDIR = 1 << 14;
         /// character device
         const S_IFCHR = 1 << 13;
+        /// block device
+        const S_IFBLK = 3 << 13;
         /// 是否设置 uid/gid/sticky
         //const S_ISUID = 1 << 14;
         //const S_ISGID = 1 << 13;
@@ -607,3 +761,230 @@ pub struct CloneArgs {
     /// 该信息 Starry 暂未支持
     pub cgroup: u64,
 }
+
+/// 删除 IPC 对象
+pub const IPC_RMID: usize = 0;
+/// 修改 IPC 对象的所有者与权限
+pub const IPC_SET: usize = 1;
+/// 获取 IPC 对象的状态
+pub const IPC_STAT: usize = 2;
+/// 获取系统对该类 IPC 对象的限制
+pub const IPC_INFO: usize = 3;
+/// 用户程序在 cmd 中加入该位表示使用 64 位的结构体，与不加时相同
+pub const IPC_64: usize = 0x100;
+/// 同 IPC_STAT，参数为下标而非 id
+pub const SHM_STAT: usize = 13;
+/// 获取共享内存的使用情况
+pub const SHM_INFO: usize = 14;
+/// 同 IPC_STAT，参数为下标而非 id
+pub const MSG_STAT: usize = 11;
+/// 获取消息队列的使用情况
+pub const MSG_INFO: usize = 12;
+/// 获取最后一次操作信号量的进程
+pub const GETPID: usize = 11;
+/// 获取信号量的值
+pub const GETVAL: usize = 12;
+/// 获取所有信号量的值
+pub const GETALL: usize = 13;
+/// 获取等待信号量增加的任务数
+pub const GETNCNT: usize = 14;
+/// 获取等待信号量变为 0 的任务数
+pub const GETZCNT: usize = 15;
+/// 设置信号量的值
+pub const SETVAL: usize = 16;
+/// 设置所有信号量的值
+pub const SETALL: usize = 17;
+/// 同 IPC_STAT，参数为下标而非 id
+pub const SEM_STAT: usize = 18;
+/// 获取信号量的使用情况
+pub const SEM_INFO: usize = 19;
+
+/// IPC 对象的所有者与权限，对应 `struct ipc64_perm`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IpcPerm {
+    /// 创建时使用的 key
+    pub key: i32,
+    /// 所有者的 uid
+    pub uid: u32,
+    /// 所有者的 gid
+    pub gid: u32,
+    /// 创建者的 uid
+    pub cuid: u32,
+    /// 创建者的 gid
+    pub cgid: u32,
+    /// 权限位
+    pub mode: u32,
+    /// 序列号
+    pub seq: u16,
+    pad: u16,
+    unused: [usize; 2],
+}
+
+impl IpcPerm {
+    /// 构造一个新的权限信息
+    pub fn new(key: i32, uid: u32, gid: u32, cuid: u32, cgid: u32, mode: u32) -> Self {
+        Self {
+            key,
+            uid,
+            gid,
+            cuid,
+            cgid,
+            mode,
+            ..Default::default()
+        }
+    }
+}
+
+/// shmctl 中使用的结构体，对应 `struct shmid64_ds`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShmidDs {
+    /// 所有者与权限
+    pub shm_perm: IpcPerm,
+    /// 共享内存的大小
+    pub shm_segsz: usize,
+    /// 最后一次 attach 的时间
+    pub shm_atime: usize,
+    /// 最后一次 detach 的时间
+    pub shm_dtime: usize,
+    /// 最后一次修改的时间
+    pub shm_ctime: usize,
+    /// 创建者的 pid
+    pub shm_cpid: i32,
+    /// 最后一次 attach 或 detach 的进程
+    pub shm_lpid: i32,
+    /// 当前 attach 的数目
+    pub shm_nattch: usize,
+    unused: [usize; 2],
+}
+
+/// shmctl(IPC_INFO) 使用的结构体，对应 `struct shminfo64`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShmInfo {
+    /// 共享内存的最大字节数
+    pub shmmax: usize,
+    /// 共享内存的最小字节数
+    pub shmmin: usize,
+    /// 共享内存的最大数目
+    pub shmmni: usize,
+    /// 每个进程 attach 的最大数目
+    pub shmseg: usize,
+    /// 共享内存的总页数上限
+    pub shmall: usize,
+    unused: [usize; 4],
+}
+
+/// shmctl(SHM_INFO) 使用的结构体，对应 `struct shm_info`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShmUsage {
+    /// 当前共享内存的数目
+    pub used_ids: i32,
+    /// 共享内存的总页数
+    pub shm_tot: usize,
+    /// 驻留在内存中的页数
+    pub shm_rss: usize,
+    /// 换出的页数
+    pub shm_swp: usize,
+    /// 未使用
+    pub swap_attempts: usize,
+    /// 未使用
+    pub swap_successes: usize,
+}
+
+/// msgctl 中使用的结构体，对应 `struct msqid64_ds`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MsqidDs {
+    /// 所有者与权限
+    pub msg_perm: IpcPerm,
+    /// 最后一次发送消息的时间
+    pub msg_stime: usize,
+    /// 最后一次接收消息的时间
+    pub msg_rtime: usize,
+    /// 最后一次修改的时间
+    pub msg_ctime: usize,
+    /// 队列中消息的总字节数
+    pub msg_cbytes: usize,
+    /// 队列中消息的数目
+    pub msg_qnum: usize,
+    /// 队列允许的最大字节数
+    pub msg_qbytes: usize,
+    /// 最后一次发送消息的进程
+    pub msg_lspid: i32,
+    /// 最后一次接收消息的进程
+    pub msg_lrpid: i32,
+    unused: [usize; 2],
+}
+
+/// msgctl(IPC_INFO) 与 msgctl(MSG_INFO) 使用的结构体，对应 `struct msginfo`
+///
+/// MSG_INFO 时 msgpool、msgmap 与 msgtql 分别为队列数目、消息总数与消息总字节数
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+#[allow(missing_docs)]
+pub struct MsgInfo {
+    pub msgpool: i32,
+    pub msgmap: i32,
+    pub msgmax: i32,
+    pub msgmnb: i32,
+    pub msgmni: i32,
+    pub msgssz: i32,
+    pub msgtql: i32,
+    pub msgseg: u16,
+}
+
+/// semctl 中使用的结构体，对应 `struct semid64_ds`
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SemidDs {
+    /// 所有者与权限
+    pub sem_perm: IpcPerm,
+    /// 最后一次 semop 的时间
+    pub sem_otime: usize,
+    /// 最后一次修改的时间
+    pub sem_ctime: usize,
+    /// 信号量的数目
+    pub sem_nsems: usize,
+    unused: [usize; 2],
+}
+
+/// semctl 中使用的结构体，对应 `struct semid64_ds`
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SemidDs {
+    /// 所有者与权限
+    pub sem_perm: IpcPerm,
+    /// 最后一次 semop 的时间
+    pub sem_otime: usize,
+    unused1: usize,
+    /// 最后一次修改的时间
+    pub sem_ctime: usize,
+    unused2: usize,
+    /// 信号量的数目
+    pub sem_nsems: usize,
+    unused: [usize; 2],
+}
+
+/// semctl(IPC_INFO) 与 semctl(SEM_INFO) 使用的结构体，对应 `struct seminfo`
+///
+/// SEM_INFO 时 semusz 与 semaem 分别为信号量集数目与信号量总数
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+#[allow(missing_docs)]
+pub struct SemInfo {
+    pub semmap: i32,
+    pub semmni: i32,
+    pub semmns: i32,
+    pub semmnu: i32,
+    pub semmsl: i32,
+    pub semopm: i32,
+    pub semume: i32,
+    pub semusz: i32,
+    pub semvmx: i32,
+    pub semaem: i32,
+}
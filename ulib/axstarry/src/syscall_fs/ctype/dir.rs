@@ -1,10 +1,13 @@
-use crate::{normal_file_mode, StMode};
+use crate::StMode;
 extern crate alloc;
 use alloc::string::{String, ToString};
 use axerrno::{AxError, AxResult};
 use axfs::api::{self, FileIO, FileIOType, Kstat, OpenFlags, SeekFrom};
+use axfs::lock::FileKey;
 use axlog::debug;
 
+use super::file::with_fs_times;
+
 /// 目录描述符
 pub struct DirDesc {
     /// 目录
@@ -19,6 +22,13 @@ impl DirDesc {
     }
 }
 
+/// 关闭打开目录的最后一个 fd 时，释放它持有的 flock 锁与 OFD 锁
+impl Drop for DirDesc {
+    fn drop(&mut self) {
+        axfs::lock::release_owner(axfs::lock::LockOwner::File(self as *const Self as usize));
+    }
+}
+
 /// 为DirDesc实现FileIO trait
 impl FileIO for DirDesc {
     fn read(&self, _: &mut [u8]) -> AxResult<usize> {
@@ -49,14 +59,19 @@ impl FileIO for DirDesc {
         self.dir_path.to_string().clone()
     }
 
+    fn lock_key(&self) -> Option<FileKey> {
+        axfs::lock::file_key(&self.dir_path).ok()
+    }
+
     fn get_stat(&self) -> AxResult<Kstat> {
+        let attr = api::lookup(&self.dir_path)?.get_attr()?;
         let kstat = Kstat {
             st_dev: 1,
             st_ino: 0,
-            st_mode: normal_file_mode(StMode::S_IFDIR).bits(),
-            st_nlink: 1,
-            st_uid: 0,
-            st_gid: 0,
+            st_mode: StMode::S_IFDIR.bits() | attr.perm().mode(),
+            st_nlink: attr.nlink() as _,
+            st_uid: attr.uid(),
+            st_gid: attr.gid(),
             st_rdev: 0,
             _pad0: 0,
             st_size: 0,
@@ -70,7 +85,7 @@ impl FileIO for DirDesc {
             st_ctime_sec: 0,
             st_ctime_nsec: 0,
         };
-        Ok(kstat)
+        Ok(with_fs_times(kstat, &attr))
     }
 }
 
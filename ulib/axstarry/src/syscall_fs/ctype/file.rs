@@ -5,17 +5,23 @@ use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
+use axalloc::ArcCache;
 use axerrno::AxResult;
 use axfs::api::{File, FileIO, FileIOType, Kstat, OpenFlags, Read, Seek, SeekFrom, Write};
+use axfs::fops::FileAttr;
+use axfs::lock::FileKey;
 
 use axlog::debug;
 
-use crate::{new_file, normal_file_mode, StMode, TimeSecs};
+use crate::{new_file, StMode, TimeSecs};
 use axprocess::link::get_link_count;
 use axsync::Mutex;
 
 pub static INODE_NAME_MAP: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
 
+/// 文件描述符的对象缓存
+pub static FILE_DESC_CACHE: ArcCache<FileDesc> = ArcCache::new("filp");
+
 /// 文件描述符
 pub struct FileDesc {
     /// 文件路径
@@ -86,6 +92,10 @@ impl FileIO for FileDesc {
         self.path.clone()
     }
 
+    fn lock_key(&self) -> Option<FileKey> {
+        self.file.lock().lock_key(&self.path).ok()
+    }
+
     fn truncate(&self, len: usize) -> AxResult<()> {
         self.file.lock().truncate(len)
     }
@@ -111,10 +121,10 @@ impl FileIO for FileDesc {
         let kstat = Kstat {
             st_dev: 1,
             st_ino: inode_number,
-            st_mode: normal_file_mode(StMode::S_IFREG).bits() | 0o644,
-            st_nlink: get_link_count(&(self.path.as_str().to_string())) as _,
-            st_uid: 0,
-            st_gid: 0,
+            st_mode: StMode::S_IFREG.bits() | attr.perm().mode(),
+            st_nlink: get_link_count(&self.path).max(attr.nlink() as usize) as _,
+            st_uid: attr.uid(),
+            st_gid: attr.gid(),
             st_rdev: 0,
             _pad0: 0,
             st_size: attr.size(),
@@ -128,7 +138,7 @@ impl FileIO for FileDesc {
             st_ctime_sec: stat.ctime.tv_sec as isize,
             st_ctime_nsec: stat.ctime.tv_nsec as isize,
         };
-        Ok(kstat)
+        Ok(with_fs_times(kstat, &attr))
     }
 
     fn set_status(&self, flags: OpenFlags) -> bool {
@@ -177,6 +187,13 @@ impl FileIO for FileDesc {
     }
 }
 
+/// 关闭打开文件的最后一个 fd 时，释放它持有的 flock 锁与 OFD 锁
+impl Drop for FileDesc {
+    fn drop(&mut self) {
+        axfs::lock::release_owner(axfs::lock::LockOwner::File(self as *const Self as usize));
+    }
+}
+
 impl FileDesc {
     /// debug
 
@@ -216,3 +233,17 @@ pub fn new_inode(path: String) -> AxResult<()> {
     inode_name_map.insert(path, inode_number);
     Ok(())
 }
+
+/// 文件系统记录了时间戳时（如 tmpfs），用它们代替打开文件时记录的时间
+pub fn with_fs_times(mut kstat: Kstat, attr: &FileAttr) -> Kstat {
+    if attr.ctime().is_zero() {
+        return kstat;
+    }
+    kstat.st_atime_sec = attr.atime().as_secs() as isize;
+    kstat.st_atime_nsec = attr.atime().subsec_nanos() as isize;
+    kstat.st_mtime_sec = attr.mtime().as_secs() as isize;
+    kstat.st_mtime_nsec = attr.mtime().subsec_nanos() as isize;
+    kstat.st_ctime_sec = attr.ctime().as_secs() as isize;
+    kstat.st_ctime_nsec = attr.ctime().subsec_nanos() as isize;
+    kstat
+}
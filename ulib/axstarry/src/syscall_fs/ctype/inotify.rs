@@ -0,0 +1,122 @@
+//! inotify 实例对应的文件，事件由 [`axfs::notify`] 产生
+extern crate alloc;
+use alloc::sync::Arc;
+use axerrno::{AxError, AxResult};
+use axfs::api::{FileIO, FileIOType, OpenFlags};
+use axfs::notify::{Inotify, NotifyEvent};
+use axsync::Mutex;
+use axtask::yield_now;
+
+/// `struct inotify_event` 中 name 之前的部分的长度
+const EVENT_HEADER_LEN: usize = 16;
+
+/// inotify 实例的文件描述符
+pub struct InotifyFile {
+    /// 监视项与事件队列
+    pub inotify: Arc<Inotify>,
+    /// 只包含 NON_BLOCK 与 CLOEXEC
+    flags: Mutex<OpenFlags>,
+}
+
+impl InotifyFile {
+    /// 创建一个没有监视项的实例
+    pub fn new(flags: OpenFlags) -> Self {
+        Self {
+            inotify: Inotify::new(),
+            flags: Mutex::new(flags & (OpenFlags::NON_BLOCK | OpenFlags::CLOEXEC)),
+        }
+    }
+}
+
+/// 事件序列化为 `struct inotify_event` 后的长度
+///
+/// name 以 0 结尾，并补齐到与 Linux 相同的 16 字节边界
+fn event_len(event: &NotifyEvent) -> usize {
+    EVENT_HEADER_LEN + name_len(event)
+}
+
+fn name_len(event: &NotifyEvent) -> usize {
+    if event.name.is_empty() {
+        0
+    } else {
+        (event.name.len() + 1).next_multiple_of(EVENT_HEADER_LEN)
+    }
+}
+
+impl FileIO for InotifyFile {
+    /// 读出尽可能多的完整事件，缓冲区放不下第一个事件时返回 InvalidInput
+    fn read(&self, buf: &mut [u8]) -> AxResult<usize> {
+        loop {
+            if self.inotify.has_events() {
+                break;
+            }
+            if self.flags.lock().contains(OpenFlags::NON_BLOCK) {
+                return Err(AxError::WouldBlock);
+            }
+            #[cfg(feature = "signal")]
+            if axprocess::current_process().have_signals().is_some() {
+                return Err(AxError::Interrupted);
+            }
+            yield_now();
+        }
+        let mut len = 0;
+        while let Some(event) = self
+            .inotify
+            .pop_event_if(|event| len + event_len(event) <= buf.len())
+        {
+            let name_len = name_len(&event);
+            let out = &mut buf[len..len + EVENT_HEADER_LEN + name_len];
+            out[0..4].copy_from_slice(&event.wd.to_ne_bytes());
+            out[4..8].copy_from_slice(&event.mask.bits().to_ne_bytes());
+            out[8..12].copy_from_slice(&event.cookie.to_ne_bytes());
+            out[12..16].copy_from_slice(&(name_len as u32).to_ne_bytes());
+            let name = &mut out[EVENT_HEADER_LEN..];
+            name.fill(0);
+            name[..event.name.len()].copy_from_slice(event.name.as_bytes());
+            len += EVENT_HEADER_LEN + name_len;
+        }
+        if len == 0 {
+            return Err(AxError::InvalidInput);
+        }
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8]) -> AxResult<usize> {
+        Err(AxError::InvalidInput)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn executable(&self) -> bool {
+        false
+    }
+
+    fn get_type(&self) -> FileIOType {
+        FileIOType::Other
+    }
+
+    fn ready_to_read(&self) -> bool {
+        self.inotify.has_events()
+    }
+
+    fn get_status(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        let nonblock = flags.contains(OpenFlags::NON_BLOCK);
+        self.flags.lock().set(OpenFlags::NON_BLOCK, nonblock);
+        true
+    }
+
+    fn set_close_on_exec(&self, is_set: bool) -> bool {
+        self.flags.lock().set(OpenFlags::CLOEXEC, is_set);
+        true
+    }
+}
@@ -1,10 +1,10 @@
 use axfs::api::{FileIO, FileIOType, OpenFlags};
 extern crate alloc;
 use alloc::sync::{Arc, Weak};
-use axerrno::AxResult;
+use axerrno::{AxError, AxResult};
 use axlog::{info, trace};
 
-use axsync::Mutex;
+use axsync::{Mutex, MutexGuard};
 use axtask::yield_now;
 
 /// IPC pipe
@@ -41,6 +41,124 @@ impl Pipe {
     pub fn is_non_block(&self) -> bool {
         self.flags.lock().contains(OpenFlags::NON_BLOCK)
     }
+
+    /// 是否为同一个管道的两端
+    pub fn same_pipe(&self, other: &Pipe) -> bool {
+        Arc::ptr_eq(&self.buffer, &other.buffer)
+    }
+
+    /// 将管道中至多 `len` 字节的数据直接交给 `f` 处理，不经过中间缓冲区
+    ///
+    /// `f` 返回其实际取走的字节数，被取走的数据从管道中移除。写入端均已关闭且管道为空时返回 0
+    pub fn splice_out<F>(&self, len: usize, nonblock: bool, f: F) -> AxResult<usize>
+    where
+        F: FnMut(&[u8]) -> AxResult<usize>,
+    {
+        assert!(self.readable());
+        self.lock_readable(nonblock)?.read_with(len, f)
+    }
+
+    /// 由 `f` 直接向管道的空闲空间中填入至多 `len` 字节的数据，不经过中间缓冲区
+    ///
+    /// `f` 返回其实际填入的字节数。读入端已关闭时返回 `ConnectionReset`，对应 EPIPE
+    pub fn splice_in<F>(&self, len: usize, nonblock: bool, f: F) -> AxResult<usize>
+    where
+        F: FnMut(&mut [u8]) -> AxResult<usize>,
+    {
+        assert!(self.writable());
+        self.lock_writable(nonblock)?.write_with(len, f)
+    }
+
+    /// 将本管道中至多 `len` 字节的数据复制到管道 `out` 中
+    ///
+    /// `consume` 为 false 时不移除本管道中的数据，即 tee 的语义
+    pub fn splice_to_pipe(
+        &self,
+        out: &Pipe,
+        len: usize,
+        consume: bool,
+        nonblock: bool,
+    ) -> AxResult<usize> {
+        assert!(self.readable() && out.writable());
+        if self.same_pipe(out) {
+            return Err(AxError::InvalidInput);
+        }
+        loop {
+            drop(self.lock_readable(nonblock)?);
+            drop(out.lock_writable(nonblock)?);
+            // 按地址顺序加锁，避免两个方向同时 splice 时死锁
+            let (mut src, mut dst) = if Arc::as_ptr(&self.buffer) < Arc::as_ptr(&out.buffer) {
+                let src = self.buffer.lock();
+                (src, out.buffer.lock())
+            } else {
+                let dst = out.buffer.lock();
+                (self.buffer.lock(), dst)
+            };
+            if src.available_read() == 0 {
+                if self.write_closed(&src) {
+                    return Ok(0);
+                }
+                // 数据被其他读者取走了，重新等待
+                continue;
+            }
+            if dst.available_write() == 0 {
+                continue;
+            }
+            let copy = |data: &[u8]| Ok(dst.write_slice(data));
+            return if consume {
+                src.read_with(len, copy)
+            } else {
+                src.peek_with(len, copy)
+            };
+        }
+    }
+
+    /// 写入端是否均已关闭
+    fn write_closed(&self, ring_buffer: &PipeRingBuffer) -> bool {
+        Arc::strong_count(&self.buffer) < 2 || ring_buffer.all_write_ends_closed()
+    }
+
+    /// 等待管道中有数据可读或写入端均已关闭，返回持有的缓冲区锁
+    fn lock_readable(&self, nonblock: bool) -> AxResult<MutexGuard<'_, PipeRingBuffer>> {
+        loop {
+            let ring_buffer = self.buffer.lock();
+            if ring_buffer.available_read() != 0 || self.write_closed(&ring_buffer) {
+                return Ok(ring_buffer);
+            }
+            drop(ring_buffer);
+            if nonblock || self.is_non_block() {
+                return Err(AxError::WouldBlock);
+            }
+            #[cfg(feature = "signal")]
+            if axprocess::current_process().have_signals().is_some() {
+                return Err(AxError::Interrupted);
+            }
+            yield_now();
+        }
+    }
+
+    /// 等待管道中有空闲空间，返回持有的缓冲区锁
+    fn lock_writable(&self, nonblock: bool) -> AxResult<MutexGuard<'_, PipeRingBuffer>> {
+        loop {
+            let ring_buffer = self.buffer.lock();
+            if Arc::strong_count(&self.buffer) < 2 {
+                // 读入端关闭
+                return Err(AxError::ConnectionReset);
+            }
+            if ring_buffer.available_write() != 0 {
+                return Ok(ring_buffer);
+            }
+            drop(ring_buffer);
+            if nonblock || self.is_non_block() {
+                return Err(AxError::WouldBlock);
+            }
+            #[cfg(feature = "signal")]
+            if axprocess::current_process().have_signals().is_some() {
+                return Err(AxError::Interrupted);
+            }
+            yield_now();
+        }
+    }
 }
 
 const RING_BUFFER_SIZE: usize = 0x4000;
@@ -111,6 +229,95 @@ impl PipeRingBuffer {
     pub fn all_write_ends_closed(&self) -> bool {
         self.write_end.as_ref().unwrap().upgrade().is_none()
     }
+
+    /// 将至多 `len` 字节的可读数据按连续的片段依次交给 `f`，但不移除它们
+    ///
+    /// `f` 返回其取走的字节数，取走的比给出的少时停止
+    fn peek_with<F>(&self, len: usize, mut f: F) -> AxResult<usize>
+    where
+        F: FnMut(&[u8]) -> AxResult<usize>,
+    {
+        let mut pos = self.head;
+        let mut left = self.available_read().min(len);
+        let mut done = 0;
+        while left > 0 {
+            let chunk = left.min(RING_BUFFER_SIZE - pos);
+            let n = match f(&self.arr[pos..pos + chunk]) {
+                Ok(n) => n.min(chunk),
+                Err(e) if done == 0 => return Err(e),
+                Err(_) => break,
+            };
+            done += n;
+            left -= n;
+            pos = (pos + n) % RING_BUFFER_SIZE;
+            if n < chunk {
+                break;
+            }
+        }
+        Ok(done)
+    }
+
+    /// 同 [`peek_with`](Self::peek_with)，但会移除被取走的数据
+    fn read_with<F>(&mut self, len: usize, f: F) -> AxResult<usize>
+    where
+        F: FnMut(&[u8]) -> AxResult<usize>,
+    {
+        let n = self.peek_with(len, f)?;
+        if n > 0 {
+            self.head = (self.head + n) % RING_BUFFER_SIZE;
+            self.status = if self.head == self.tail {
+                RingBufferStatus::Empty
+            } else {
+                RingBufferStatus::Normal
+            };
+        }
+        Ok(n)
+    }
+
+    /// 将至多 `len` 字节的空闲空间按连续的片段依次交给 `f` 填写
+    ///
+    /// `f` 返回其填入的字节数，填入的比给出的少时停止
+    fn write_with<F>(&mut self, len: usize, mut f: F) -> AxResult<usize>
+    where
+        F: FnMut(&mut [u8]) -> AxResult<usize>,
+    {
+        let mut left = self.available_write().min(len);
+        let mut done = 0;
+        while left > 0 {
+            let chunk = left.min(RING_BUFFER_SIZE - self.tail);
+            let n = match f(&mut self.arr[self.tail..self.tail + chunk]) {
+                Ok(n) => n.min(chunk),
+                Err(e) if done == 0 => return Err(e),
+                Err(_) => break,
+            };
+            if n == 0 {
+                break;
+            }
+            done += n;
+            left -= n;
+            self.tail = (self.tail + n) % RING_BUFFER_SIZE;
+            self.status = if self.tail == self.head {
+                RingBufferStatus::Full
+            } else {
+                RingBufferStatus::Normal
+            };
+            if n < chunk {
+                break;
+            }
+        }
+        Ok(done)
+    }
+
+    /// 写入尽可能多的数据，返回写入的字节数
+    fn write_slice(&mut self, data: &[u8]) -> usize {
+        let mut copied = 0;
+        let _ = self.write_with(data.len(), |space| {
+            space.copy_from_slice(&data[copied..copied + space.len()]);
+            copied += space.len();
+            Ok(space.len())
+        });
+        copied
+    }
 }
 
 /// Return (read_end, write_end)
@@ -14,6 +14,18 @@ numeric_enum_macro::numeric_enum! {
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub enum FsSyscallId {
     // fs
+    SETXATTR = 5,
+    LSETXATTR = 6,
+    FSETXATTR = 7,
+    GETXATTR = 8,
+    LGETXATTR = 9,
+    FGETXATTR = 10,
+    LISTXATTR = 11,
+    LLISTXATTR = 12,
+    FLISTXATTR = 13,
+    REMOVEXATTR = 14,
+    LREMOVEXATTR = 15,
+    FREMOVEXATTR = 16,
     GETCWD = 17,
     EVENTFD = 19,
     EPOLL_CREATE = 20,
@@ -22,7 +34,11 @@ pub enum FsSyscallId {
     DUP = 23,
     DUP3 = 24,
     FCNTL64 = 25,
+    INOTIFY_INIT1 = 26,
+    INOTIFY_ADD_WATCH = 27,
+    INOTIFY_RM_WATCH = 28,
     IOCTL = 29,
+    FLOCK = 32,
     MKDIRAT = 34,
     SYMLINKAT = 36,
     UNLINKAT = 35,
@@ -34,7 +50,10 @@ pub enum FsSyscallId {
     FTRUNCATE64 = 46,
     FACCESSAT = 48,
     CHDIR = 49,
+    FCHMOD = 52,
     FCHMODAT = 53,
+    FCHOWNAT = 54,
+    FCHOWN = 55,
     OPENAT = 56,
     CLOSE = 57,
     PIPE2 = 59,
@@ -50,6 +69,9 @@ pub enum FsSyscallId {
     PWRITE64 = 68,
     SENDFILE64 = 71,
     PSELECT6 = 72,
+    VMSPLICE = 75,
+    SPLICE = 76,
+    TEE = 77,
     PREADLINKAT = 78,
     FSTAT = 80,
     SYNC = 81,
@@ -81,6 +103,11 @@ numeric_enum_macro::numeric_enum! {
         DUP2 = 33,
         DUP3 = 292,
         FCNTL64 = 72,
+        FLOCK = 73,
+        INOTIFY_INIT = 253,
+        INOTIFY_ADD_WATCH = 254,
+        INOTIFY_RM_WATCH = 255,
+        INOTIFY_INIT1 = 294,
         IOCTL = 16,
         MKDIRAT = 258,
         SYMLINKAT = 266,
@@ -114,10 +141,18 @@ numeric_enum_macro::numeric_enum! {
         PREAD64 = 17,
         PWRITE64 = 18,
         SENDFILE64 = 40,
+        SPLICE = 275,
+        TEE = 276,
+        VMSPLICE = 278,
         SELECT = 23,
         PSELECT6 = 270,
         READLINK = 89,
         CHMOD = 90,
+        FCHMOD = 91,
+        CHOWN = 92,
+        FCHOWN = 93,
+        LCHOWN = 94,
+        FCHOWNAT = 260,
         PREADLINKAT = 267,
         FSTAT = 5,
         LSTAT = 6,
@@ -129,5 +164,17 @@ numeric_enum_macro::numeric_enum! {
         COPYFILERANGE = 326,
         EPOLL_CREATE1 = 291,
         EPOLL_PWAIT = 281,
+        SETXATTR = 188,
+        LSETXATTR = 189,
+        FSETXATTR = 190,
+        GETXATTR = 191,
+        LGETXATTR = 192,
+        FGETXATTR = 193,
+        LISTXATTR = 194,
+        LLISTXATTR = 195,
+        FLISTXATTR = 196,
+        REMOVEXATTR = 197,
+        LREMOVEXATTR = 198,
+        FREMOVEXATTR = 199,
     }
 }
@@ -5,16 +5,28 @@ use core::ptr::copy_nonoverlapping;
 
 use crate::{
     syscall_fs::ctype::{file::new_fd, FileDesc},
-    DirEnt, DirEntType, Fcntl64Cmd, RenameFlags, SyscallError, SyscallResult, TimeSecs,
+    DirEnt, DirEntType, Fcntl64Cmd, RenameFlags, SyscallError, SyscallResult, TimeSecs, UTIME_NOW,
+    UTIME_OMIT,
 };
+use axerrno::AxError;
+use axfs::fops::FileAttr;
+use axfs::notify::WatchMask;
+use axfs::xattr::PosixAcl;
 use axhal::mem::VirtAddr;
 use axprocess::{
+    credentials::{Credentials, MAY_EXEC, MAY_WRITE},
     current_process,
-    link::{deal_with_path, FilePath, AT_FDCWD},
+    link::{deal_with_path, raw_ptr_to_ref_str, FilePath, AT_FDCWD},
 };
 
 extern crate alloc;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
+use core::time::Duration;
+
+/// faccessat 按有效用户而不是真实用户检查权限
+pub const AT_EACCESS: usize = 0x200;
+/// path 为空时操作 dir_fd 本身
+pub const AT_EMPTY_PATH: usize = 0x1000;
 
 /// 功能:获取当前工作目录；
 /// # Arguments
@@ -85,9 +97,11 @@ pub fn syscall_mkdirat(args: [usize; 6]) -> SyscallResult {
         // 文件已存在
         return Err(SyscallError::EEXIST);
     }
+    check_access(path.dir()?, MAY_WRITE | MAY_EXEC)?;
     let _ = axfs::api::create_dir(path.path());
     // 只要文件夹存在就返回0
     if axfs::api::path_exists(path.path()) {
+        init_new_node(&path, mode);
         Ok(0)
     } else {
         Err(SyscallError::EPERM)
@@ -418,6 +432,18 @@ pub fn syscall_fcntl64(args: [usize; 6]) -> SyscallResult {
                 Err(SyscallError::EINVAL)
             }
         }
+        Ok(
+            cmd @ (Fcntl64Cmd::F_GETLK
+            | Fcntl64Cmd::F_SETLK
+            | Fcntl64Cmd::F_SETLKW
+            | Fcntl64Cmd::F_OFD_GETLK
+            | Fcntl64Cmd::F_OFD_SETLK
+            | Fcntl64Cmd::F_OFD_SETLKW),
+        ) => {
+            // F_SETLKW 可能等待很久，不能一直持有 fd 表
+            drop(fd_table);
+            super::fcntl_lock(file, cmd, arg)
+        }
         _ => Err(SyscallError::EINVAL),
     }
 }
@@ -449,15 +475,188 @@ pub fn syscall_ioctl(args: [usize; 6]) -> SyscallResult {
     }
 
     let file = fd_table[fd].clone().unwrap();
+    // socket 上的网络接口请求
+    #[cfg(feature = "net")]
+    if file.get_type() == axfs::api::FileIOType::Socket {
+        if let Ok(request) = crate::syscall_net::InterfaceIoctl::try_from(request) {
+            drop(fd_table);
+            return request.handle(argp);
+        }
+    }
     match file.ioctl(request, argp) {
         Ok(ret) => Ok(ret),
         Err(_) => Ok(0),
     }
 }
 
+/// 获取 `path` 对应文件的属性
+pub(crate) fn path_attr(path: &str) -> Result<FileAttr, SyscallError> {
+    axfs::api::lookup(path)
+        .and_then(|node| node.get_attr())
+        .map_err(|_| SyscallError::ENOENT)
+}
+
+/// 获取 `path` 对应文件的访问 ACL，没有 ACL 或文件系统不支持时返回 `None`
+pub(crate) fn path_acl(path: &str) -> Option<PosixAcl> {
+    axfs::api::lookup(path)
+        .ok()
+        .and_then(|node| axfs::xattr::access_acl(&node))
+}
+
+/// 检查 `credentials` 能否搜索 `path` 经过的每一级目录
+///
+/// 同 Linux 的路径解析，任何一级目录没有搜索权限时返回 EACCES，不存在时返回 ENOENT
+pub(crate) fn check_search(credentials: &Credentials, path: &str) -> Result<(), SyscallError> {
+    let dirs = path
+        .trim_end_matches('/')
+        .match_indices('/')
+        .map(|(pos, _)| &path[..=pos]);
+    for dir in dirs {
+        let attr = path_attr(dir)?;
+        let acl = path_acl(dir);
+        if !credentials.may_access(&attr, acl.as_ref(), MAY_EXEC) {
+            return Err(SyscallError::EACCES);
+        }
+    }
+    Ok(())
+}
+
+/// 按当前进程的有效用户检查能否以 `want` 访问 `path`，成功时返回文件属性
+///
+/// 同时检查对 `path` 经过的各级目录的搜索权限
+pub(crate) fn check_access(path: &str, want: u32) -> Result<FileAttr, SyscallError> {
+    let process = current_process();
+    let credentials = process.credentials.lock();
+    check_search(&credentials, path)?;
+    let attr = path_attr(path)?;
+    let acl = path_acl(path);
+    if credentials.may_access(&attr, acl.as_ref(), want) {
+        Ok(attr)
+    } else {
+        Err(SyscallError::EACCES)
+    }
+}
+
+/// 检查当前进程能否删除 `path`
+///
+/// 对所在目录没有写和搜索权限时返回 EACCES，被目录的 sticky 位阻止时返回 EPERM
+pub(crate) fn check_delete(path: &FilePath) -> Result<(), SyscallError> {
+    let dir = check_access(path.dir()?, MAY_WRITE | MAY_EXEC)?;
+    let dir_acl = path_acl(path.dir()?);
+    let attr = path_attr(path.path())?;
+    if current_process()
+        .credentials
+        .lock()
+        .may_delete(&dir, dir_acl.as_ref(), &attr)
+    {
+        Ok(())
+    } else {
+        Err(SyscallError::EPERM)
+    }
+}
+
+/// 新建的文件或目录属于当前进程的有效用户与组
+///
+/// 所在目录有默认 ACL 时继承该 ACL，权限由 ACL 与 `mode` 决定，否则权限为去掉 umask 后的 `mode`
+pub(crate) fn init_new_node(path: &FilePath, mode: u32) {
+    let process = current_process();
+    let Ok(node) = axfs::api::lookup(path.path()) else {
+        return;
+    };
+    {
+        let credentials = process.credentials.lock();
+        let _ = node.set_owner(credentials.euid, credentials.egid);
+    }
+    let perm = Permissions::from_bits_truncate((mode & 0o7777) as u16);
+    let inherited = path
+        .dir()
+        .and_then(axfs::api::lookup)
+        .and_then(|dir| axfs::xattr::inherit_acl(&dir, &node, perm))
+        .unwrap_or(false);
+    if !inherited {
+        let mode = mode & !(process.fd_manager.get_mask() as u32) & 0o7777;
+        let _ = node.set_mode(Permissions::from_bits_truncate(mode as u16));
+    }
+}
+
+/// 修改 `path` 的权限位，要求是文件的属主或特权进程
+///
+/// 非特权进程不属于文件的属组时，set-group-ID 位被清除
+fn chmod(path: &str, mode: usize) -> SyscallResult {
+    let node = axfs::api::lookup(path).map_err(|_| SyscallError::ENOENT)?;
+    let attr = node.get_attr()?;
+    let process = current_process();
+    let credentials = process.credentials.lock();
+    if !credentials.owns(&attr) {
+        return Err(SyscallError::EPERM);
+    }
+    let mut perm = Permissions::from_bits_truncate(mode as u16 & 0o7777);
+    if !credentials.is_privileged() && !credentials.in_group(attr.gid()) {
+        perm.remove(Permissions::SET_GID);
+    }
+    axfs::xattr::chmod(&node, perm).map_err(|_| SyscallError::EPERM)?;
+    notify_attrib(path, &attr);
+    Ok(0)
+}
+
+/// 修改 `path` 的属主与属组，-1 表示不修改
+///
+/// 只有特权进程可以修改属主，属主可以把属组改为自己所在的组。
+/// 修改后普通文件的 set-user-ID 与 set-group-ID 位被清除
+fn chown(path: &str, uid: usize, gid: usize) -> SyscallResult {
+    let node = axfs::api::lookup(path).map_err(|_| SyscallError::ENOENT)?;
+    let attr = node.get_attr()?;
+    let uid = (uid as u32 != u32::MAX).then_some(uid as u32);
+    let gid = (gid as u32 != u32::MAX).then_some(gid as u32);
+    let process = current_process();
+    let credentials = process.credentials.lock();
+    if !credentials.is_privileged() {
+        let uid_ok = uid.map_or(true, |uid| uid == attr.uid());
+        let gid_ok = gid.map_or(true, |gid| gid == attr.gid() || credentials.in_group(gid));
+        if !credentials.owns(&attr) || !uid_ok || !gid_ok {
+            return Err(SyscallError::EPERM);
+        }
+    }
+    if uid.is_none() && gid.is_none() {
+        return Ok(0);
+    }
+    node.set_owner(uid.unwrap_or(attr.uid()), gid.unwrap_or(attr.gid()))
+        .map_err(|_| SyscallError::EPERM)?;
+    let mut perm = attr.perm();
+    if !attr.is_dir() && perm.intersects(Permissions::SET_UID | Permissions::SET_GID) {
+        perm.remove(Permissions::SET_UID);
+        if perm.contains(Permissions::GROUP_EXEC) {
+            perm.remove(Permissions::SET_GID);
+        }
+        let _ = node.set_mode(perm);
+    }
+    notify_attrib(path, &attr);
+    Ok(0)
+}
+
+/// 通知 inotify 文件的属性被修改
+fn notify_attrib(path: &str, attr: &FileAttr) {
+    let mask = if attr.is_dir() {
+        WatchMask::ATTRIB | WatchMask::ISDIR
+    } else {
+        WatchMask::ATTRIB
+    };
+    axfs::notify::notify(path, mask);
+}
+
+/// 获取文件描述符对应文件的路径
+pub(crate) fn fd_path(fd: usize) -> Result<String, SyscallError> {
+    let process = current_process();
+    let fd_table = process.fd_manager.fd_table.lock();
+    match fd_table.get(fd) {
+        Some(Some(file)) => Ok(file.get_path()),
+        _ => Err(SyscallError::EBADF),
+    }
+}
+
 /// 53
 /// 修改文件权限
-/// mode: 0o777, 3位八进制数字
+/// mode: 0o7777, 4位八进制数字，包括 set-user-ID、set-group-ID 与 sticky 位
 /// path为相对路径:
 ///     1. 若dir_fd为AT_FDCWD,则相对于当前工作目录
 ///     2. 若dir_fd为AT_FDCWD以外的值,则相对于dir_fd所指的目录
@@ -471,18 +670,80 @@ pub fn syscall_fchmodat(args: [usize; 6]) -> SyscallResult {
     let dir_fd = args[0];
     let path = args[1] as *const u8;
     let mode = args[2];
-    let file_path = deal_with_path(dir_fd, Some(path), false).unwrap();
-    axfs::api::metadata(file_path.path())
-        .map(|mut metadata| {
-            metadata.set_permissions(Permissions::from_bits_truncate(mode as u16));
-            Ok(0)
-        })
-        .unwrap_or_else(|_| Err(SyscallError::ENOENT))
+    let file_path = deal_with_path(dir_fd, Some(path), false).ok_or(SyscallError::EINVAL)?;
+    chmod(file_path.path(), mode)
+}
+
+/// 52
+/// 修改文件描述符对应文件的权限
+/// # Arguments
+/// * `fd`: usize, 文件描述符
+/// * `mode`: usize, 文件的权限
+pub fn syscall_fchmod(args: [usize; 6]) -> SyscallResult {
+    let fd = args[0];
+    let mode = args[1];
+    chmod(&fd_path(fd)?, mode)
+}
+
+/// 修改文件权限
+/// # Arguments
+/// * `path`: *const u8, 文件的路径
+/// * `mode`: usize, 文件的权限
+#[cfg(target_arch = "x86_64")]
+pub fn syscall_chmod(args: [usize; 6]) -> SyscallResult {
+    let temp_args = [AT_FDCWD, args[0], args[1], 0, 0, 0];
+    syscall_fchmodat(temp_args)
+}
+
+/// 54
+/// 修改文件的属主与属组，路径的解析方式同 fchmodat
+///
+/// 若 flags 包含 AT_EMPTY_PATH 且 path 为空，则修改 dir_fd 对应的文件
+/// # Arguments
+/// * `dir_fd`: usize, 目录的文件描述符
+/// * `path`: *const u8, 文件的路径
+/// * `uid`: u32, 新的属主，-1 表示不修改
+/// * `gid`: u32, 新的属组，-1 表示不修改
+/// * `flags`: usize, AT_EMPTY_PATH 或 AT_SYMLINK_NOFOLLOW
+pub fn syscall_fchownat(args: [usize; 6]) -> SyscallResult {
+    let dir_fd = args[0];
+    let path = args[1] as *const u8;
+    let uid = args[2];
+    let gid = args[3];
+    let flags = args[4];
+    if flags & AT_EMPTY_PATH != 0 && unsafe { raw_ptr_to_ref_str(path) }.is_empty() {
+        return chown(&fd_path(dir_fd)?, uid, gid);
+    }
+    let file_path = deal_with_path(dir_fd, Some(path), false).ok_or(SyscallError::EINVAL)?;
+    chown(file_path.path(), uid, gid)
+}
+
+/// 55
+/// 修改文件描述符对应文件的属主与属组
+/// # Arguments
+/// * `fd`: usize, 文件描述符
+/// * `uid`: u32, 新的属主，-1 表示不修改
+/// * `gid`: u32, 新的属组，-1 表示不修改
+pub fn syscall_fchown(args: [usize; 6]) -> SyscallResult {
+    let fd = args[0];
+    let uid = args[1];
+    let gid = args[2];
+    chown(&fd_path(fd)?, uid, gid)
+}
+
+/// 修改文件的属主与属组，lchown 与 chown 的区别在于不跟随符号链接
+/// # Arguments
+/// * `path`: *const u8, 文件的路径
+/// * `uid`: u32, 新的属主，-1 表示不修改
+/// * `gid`: u32, 新的属组，-1 表示不修改
+#[cfg(target_arch = "x86_64")]
+pub fn syscall_chown(args: [usize; 6]) -> SyscallResult {
+    let temp_args = [AT_FDCWD, args[0], args[1], args[2], 0, 0];
+    syscall_fchownat(temp_args)
 }
 
 /// 48
-/// 获取文件权限
-/// 类似上面的fchmodat
+/// 检查文件的访问权限
 ///        The mode specifies the accessibility check(s) to be performed,
 ///        and is either the value F_OK, or a mask consisting of the bitwise
 ///        OR of one or more of R_OK, W_OK, and X_OK.  F_OK tests for the
@@ -490,50 +751,45 @@ pub fn syscall_fchmodat(args: [usize; 6]) -> SyscallResult {
 ///        file exists and grants read, write, and execute permissions,
 ///        respectively.
 /// 0: F_OK, 1: X_OK, 2: W_OK, 4: R_OK
+///
+/// 默认按真实用户检查，flags 包含 AT_EACCESS 时按有效用户检查
 /// # Arguments
 /// * `dir_fd`: usize, 目录的文件描述符
 /// * `path`: *const u8, 文件的路径
-/// * `mode`: usize, 文件的权限
+/// * `mode`: usize, 要检查的权限
+/// * `flags`: usize, AT_EACCESS
 pub fn syscall_faccessat(args: [usize; 6]) -> SyscallResult {
     let dir_fd = args[0];
     let path = args[1] as *const u8;
     let mode = args[2];
-    // todo: 有问题,实际上需要考虑当前进程对应的用户UID和文件拥有者之间的关系
-    // 现在一律当作root用户处理
-    let file_path = deal_with_path(dir_fd, Some(path), false).unwrap();
-    axfs::api::metadata(file_path.path())
-        .map(|metadata| {
-            if mode == 0 {
-                //F_OK
-                // 文件存在返回0,不存在返回-1
-                if axfs::api::path_exists(file_path.path()) {
-                    Ok(0)
-                } else {
-                    Err(SyscallError::ENOENT)
-                }
-            } else {
-                // 逐位对比
-                let mut ret = true;
-                if mode & 1 != 0 {
-                    // X_OK
-                    ret &= metadata.permissions().contains(Permissions::OWNER_EXEC)
-                }
-                if mode & 2 != 0 {
-                    // W_OK
-                    ret &= metadata.permissions().contains(Permissions::OWNER_WRITE)
-                }
-                if mode & 4 != 0 {
-                    // R_OK
-                    ret &= metadata.permissions().contains(Permissions::OWNER_READ)
-                }
-                Ok(ret as isize - 1)
-            }
-        })
-        .unwrap_or_else(|_| Err(SyscallError::ENOENT))
+    let flags = args[3];
+    if mode & !7 != 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    let file_path = deal_with_path(dir_fd, Some(path), false).ok_or(SyscallError::EINVAL)?;
+    let process = current_process();
+    let credentials = process.credentials.lock();
+    let credentials = if flags & AT_EACCESS != 0 {
+        credentials.clone()
+    } else {
+        credentials.as_real()
+    };
+    check_search(&credentials, file_path.path())?;
+    let attr = path_attr(file_path.path())?;
+    let acl = path_acl(file_path.path());
+    if mode == 0 {
+        // F_OK
+        return Ok(0);
+    }
+    if credentials.may_access(&attr, acl.as_ref(), mode as u32) {
+        Ok(0)
+    } else {
+        Err(SyscallError::EACCES)
+    }
 }
 
 /// 48
-/// 获取文件权限
+/// 检查文件的访问权限，按真实用户检查
 /// 0: F_OK, 1: X_OK, 2: W_OK, 4: R_OK
 /// # Arguments
 /// * `path`: *const u8, 文件的路径
@@ -543,8 +799,6 @@ pub fn syscall_access(args: [usize; 6]) -> SyscallResult {
     let path = args[0];
     let mode = args[1];
     let temp_args = [AT_FDCWD, path, mode, 0, 0, 0];
-    // todo: 有问题,实际上需要考虑当前进程对应的用户UID和文件拥有者之间的关系
-    // 现在一律当作root用户处理
     syscall_faccessat(temp_args)
 }
 
@@ -615,6 +869,7 @@ pub fn syscall_utimensat(args: [usize; 6]) -> SyscallResult {
                 // }
                 fat_file.stat.lock().atime.set_as_utime(&new_atime);
                 fat_file.stat.lock().mtime.set_as_utime(&new_mtime);
+                set_fs_times(&fat_file.path, &new_atime, &new_mtime)?;
             } else {
                 return Err(SyscallError::EPERM);
             }
@@ -633,6 +888,20 @@ pub fn syscall_utimensat(args: [usize; 6]) -> SyscallResult {
         let file = new_fd(file_path.path().to_string(), 0.into()).unwrap();
         file.stat.lock().atime.set_as_utime(&new_atime);
         file.stat.lock().mtime.set_as_utime(&new_mtime);
+        set_fs_times(file_path.path(), &new_atime, &new_mtime)?;
         Ok(0)
     }
 }
+
+/// 把时间写入记录时间戳的文件系统（如 tmpfs），其他文件系统忽略
+fn set_fs_times(path: &str, atime: &TimeSecs, mtime: &TimeSecs) -> Result<(), SyscallError> {
+    let to_duration = |time: &TimeSecs| match time.tv_nsec {
+        UTIME_OMIT => None,
+        UTIME_NOW => Some(axhal::time::current_time()),
+        _ => Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32)),
+    };
+    match axfs::api::set_times(path, to_duration(atime), to_duration(mtime)) {
+        Ok(()) | Err(AxError::Unsupported) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
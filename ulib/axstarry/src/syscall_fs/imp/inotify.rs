@@ -0,0 +1,87 @@
+//! inotify 相关的系统调用
+use alloc::sync::Arc;
+use axfs::api::{FileIO, OpenFlags};
+use axfs::notify::WatchMask;
+use axlog::info;
+use axprocess::{
+    credentials::MAY_READ,
+    current_process,
+    link::{deal_with_path, AT_FDCWD},
+};
+
+use super::ctl::check_access;
+use crate::syscall_fs::ctype::inotify::InotifyFile;
+use crate::{SyscallError, SyscallResult};
+
+/// 取出 fd 对应的 inotify 实例，fd 不是 inotify 实例时返回 EINVAL
+fn inotify_of(fd: usize) -> Result<Arc<dyn FileIO>, SyscallError> {
+    let process = current_process();
+    let fd_table = process.fd_manager.fd_table.lock();
+    let Some(Some(file)) = fd_table.get(fd) else {
+        return Err(SyscallError::EBADF);
+    };
+    if file.as_any().downcast_ref::<InotifyFile>().is_none() {
+        return Err(SyscallError::EINVAL);
+    }
+    Ok(file.clone())
+}
+
+/// 26
+/// 创建一个 inotify 实例
+/// # Arguments
+/// * `flags`: usize, 可以包含 IN_NONBLOCK 与 IN_CLOEXEC，取值与 O_NONBLOCK、O_CLOEXEC 相同
+pub fn syscall_inotify_init1(args: [usize; 6]) -> SyscallResult {
+    let Some(flags) = OpenFlags::from_bits(args[0] as u32) else {
+        return Err(SyscallError::EINVAL);
+    };
+    if !(OpenFlags::NON_BLOCK | OpenFlags::CLOEXEC).contains(flags) {
+        return Err(SyscallError::EINVAL);
+    }
+    let process = current_process();
+    let mut fd_table = process.fd_manager.fd_table.lock();
+    let Ok(fd) = process.alloc_fd(&mut fd_table) else {
+        return Err(SyscallError::EMFILE);
+    };
+    fd_table[fd] = Some(Arc::new(InotifyFile::new(flags)));
+    Ok(fd as isize)
+}
+
+/// x86 下的 inotify_init，相当于 flags 为 0 的 inotify_init1
+#[cfg(target_arch = "x86_64")]
+pub fn syscall_inotify_init(_args: [usize; 6]) -> SyscallResult {
+    syscall_inotify_init1([0; 6])
+}
+
+/// 27
+/// 监视一个文件或目录，返回监视描述符
+/// # Arguments
+/// * `fd`: usize, inotify 实例
+/// * `path`: *const u8, 被监视的路径，要求有读权限
+/// * `mask`: usize, 监视的事件与 IN_ONLYDIR、IN_MASK_ADD 等选项
+pub fn syscall_inotify_add_watch(args: [usize; 6]) -> SyscallResult {
+    let fd = args[0];
+    let path = args[1] as *const u8;
+    let mask = WatchMask::from_bits_truncate(args[2] as u32);
+    let file = inotify_of(fd)?;
+    let inotify = &file.as_any().downcast_ref::<InotifyFile>().unwrap().inotify;
+    let Some(path) = deal_with_path(AT_FDCWD, Some(path), false) else {
+        return Err(SyscallError::EINVAL);
+    };
+    check_access(path.path(), MAY_READ)?;
+    info!("inotify_add_watch {} {:?}", path.path(), mask);
+    Ok(inotify.add_watch(path.path(), mask)? as isize)
+}
+
+/// 28
+/// 移除一个监视项，实例会收到该监视项的 IN_IGNORED 事件
+/// # Arguments
+/// * `fd`: usize, inotify 实例
+/// * `wd`: usize, 监视描述符
+pub fn syscall_inotify_rm_watch(args: [usize; 6]) -> SyscallResult {
+    let fd = args[0];
+    let wd = args[1] as i32;
+    let file = inotify_of(fd)?;
+    let inotify = &file.as_any().downcast_ref::<InotifyFile>().unwrap().inotify;
+    inotify.rm_watch(wd)?;
+    Ok(0)
+}
@@ -8,15 +8,19 @@ use alloc::sync::Arc;
 use alloc::vec;
 use axerrno::AxError;
 use axfs::api::{FileIOType, OpenFlags, SeekFrom};
+use axfs::lock::LockOwner;
 
 use axlog::{debug, info};
+use axprocess::credentials::{MAY_EXEC, MAY_READ, MAY_WRITE};
 use axprocess::current_process;
 use axprocess::link::{create_link, deal_with_path, real_path};
 
+use super::ctl::{check_access, init_new_node};
+
 use crate::syscall_fs::ctype::{
     dir::new_dir,
     epoll::{EpollCtl, EpollEvent, EpollEventType, EpollFile},
-    file::{new_fd, new_inode},
+    file::{new_fd, new_inode, FILE_DESC_CACHE},
     pipe::make_pipe,
 };
 /// 功能:从一个文件描述符中读取；
@@ -386,13 +390,28 @@ pub fn syscall_openat(args: [usize; 6]) -> SyscallResult {
     let fd = args[0];
     let path = args[1] as *const u8;
     let flags = args[2];
-    let _mode = args[3] as u8;
-    let force_dir = OpenFlags::from(flags).is_dir();
+    let mode = args[3] as u32;
+    let open_flags = OpenFlags::from(flags);
+    let force_dir = open_flags.is_dir();
     let path = if let Some(path) = deal_with_path(fd, Some(path), force_dir) {
         path
     } else {
         return Err(SyscallError::EINVAL);
     };
+    // 已存在的文件按打开方式检查读写权限，新建文件则需要对所在目录有写和搜索权限
+    let created = !axfs::api::path_exists(path.path());
+    if !created {
+        let mut want = 0;
+        if open_flags.readable() {
+            want |= MAY_READ;
+        }
+        if open_flags.writable() {
+            want |= MAY_WRITE;
+        }
+        check_access(path.path(), want)?;
+    } else if open_flags.creatable() {
+        check_access(path.dir()?, MAY_WRITE | MAY_EXEC)?;
+    }
     let process = current_process();
     let mut fd_table = process.fd_manager.fd_table.lock();
     let fd_num: usize = if let Ok(fd) = process.alloc_fd(&mut fd_table) {
@@ -421,7 +440,10 @@ pub fn syscall_openat(args: [usize; 6]) -> SyscallResult {
         debug!("open file");
         if let Ok(file) = new_fd(path.path().to_string(), flags.into()) {
             debug!("new file_desc successfully allocated");
-            fd_table[fd_num] = Some(Arc::new(file));
+            fd_table[fd_num] = Some(FILE_DESC_CACHE.new_arc(file));
+            if created {
+                init_new_node(&path, mode);
+            }
             let _ = create_link(&path, &path); // 不需要检查是否成功,因为如果成功,说明是新建的文件,如果失败,说明已经存在了
             Ok(fd_num as isize)
         } else {
@@ -485,7 +507,11 @@ pub fn syscall_close(args: [usize; 6]) -> SyscallResult {
         }
     }
 
-    fd_table[fd] = None;
+    // POSIX 记录锁属于进程，关闭文件的任意一个 fd 都会释放进程在该文件上的记录锁
+    let file = fd_table[fd].take().unwrap();
+    if let Some(key) = file.lock_key() {
+        axfs::lock::release(&key, LockOwner::Process(process.pid()));
+    }
     // for i in 0..process_inner.fd_table.len() {
     //     if let Some(file) = process_inner.fd_table[i].as_ref() {
     //         debug!("fd: {} has file", i);
@@ -552,48 +578,6 @@ pub fn syscall_pwrite64(args: [usize; 6]) -> SyscallResult {
         .unwrap_or_else(|_| Err(SyscallError::EINVAL))
 }
 
-/// 71
-/// sendfile64
-/// 将一个文件的内容发送到另一个文件中
-/// 如果offset为NULL,则从当前读写指针开始读取,读取完毕后会更新读写指针
-/// 如果offset不为NULL,则从offset指定的位置开始读取,读取完毕后不会更新读写指针,但是会更新offset的值
-/// # Arguments
-/// * `out_fd`: usize
-/// * `in_fd`: usize
-/// * `offset`: *mut usize
-/// * `count`: usize
-pub fn syscall_sendfile64(args: [usize; 6]) -> SyscallResult {
-    let out_fd = args[0];
-    let in_fd = args[1];
-    let offset = args[2] as *mut usize;
-    let count = args[3];
-    info!("send from {} to {}, count: {}", in_fd, out_fd, count);
-    let process = current_process();
-    let out_file = process.fd_manager.fd_table.lock()[out_fd].clone().unwrap();
-    let in_file = process.fd_manager.fd_table.lock()[in_fd].clone().unwrap();
-    let old_in_offset = in_file.seek(SeekFrom::Current(0)).unwrap();
-
-    let mut buf = vec![0u8; count];
-    if !offset.is_null() {
-        // 如果offset不为NULL,则从offset指定的位置开始读取
-        let in_offset = unsafe { *offset };
-        in_file.seek(SeekFrom::Start(in_offset as u64)).unwrap();
-        let ret = in_file.read(buf.as_mut_slice());
-        unsafe { *offset = in_offset + ret.unwrap() };
-        in_file.seek(SeekFrom::Start(old_in_offset)).unwrap();
-        let buf = buf[..ret.unwrap()].to_vec();
-        Ok(out_file.write(buf.as_slice()).unwrap() as isize)
-    } else {
-        // 如果offset为NULL,则从当前读写指针开始读取
-        let ret = in_file.read(buf.as_mut_slice());
-        info!("in fd: {}, count: {}", in_fd, count);
-        let buf = buf[..ret.unwrap()].to_vec();
-        info!("read len: {}", buf.len());
-        info!("write len: {}", buf.as_slice().len());
-        Ok(out_file.write(buf.as_slice()).unwrap() as isize)
-    }
-}
-
 /// 78
 /// readlinkat
 /// 读取符号链接文件的内容
@@ -724,6 +708,15 @@ pub fn syscall_lseek(args: [usize; 6]) -> SyscallResult {
     }
 }
 
+/// 81
+/// 将所有块设备缓存中的脏数据写回硬盘，与 Linux 一样总是成功
+pub fn syscall_sync() -> SyscallResult {
+    if let Err(e) = axfs::api::sync() {
+        axlog::warn!("sync failed: {:?}", e);
+    }
+    Ok(0)
+}
+
 /// 82
 /// 写回硬盘
 #[allow(unused)]
@@ -4,9 +4,12 @@
 extern crate alloc;
 
 use crate::{SyscallError, SyscallResult};
+use axerrno::AxError;
 use axlog::debug;
 use axprocess::link::{create_link, deal_with_path, remove_link, FilePath};
 
+use super::ctl::check_delete;
+
 /// Special value used to indicate openat should use the current working directory.
 pub const AT_REMOVEDIR: usize = 0x200; // Remove directory instead of unlinking file.
 
@@ -19,6 +22,8 @@ pub const AT_REMOVEDIR: usize = 0x200; // Remove directory instead of unlinking
 /// * `flags`: usize, 在2.6.18内核之前,应置为0。其它的值详见`man 2 linkat`。
 /// # Return
 /// 成功执行,返回0。失败,返回-1。
+///
+/// 文件系统支持硬链接时（如 tmpfs）直接在文件系统中创建，否则记录在链接表中
 #[allow(dead_code)]
 pub fn sys_linkat(args: [usize; 6]) -> SyscallResult {
     let old_dir_fd = args[0];
@@ -37,6 +42,11 @@ pub fn sys_linkat(args: [usize; 6]) -> SyscallResult {
     } else {
         return Err(SyscallError::EINVAL);
     };
+    match axfs::api::hard_link(old_path.path(), new_path.path()) {
+        Ok(()) => return Ok(0),
+        Err(AxError::Unsupported) => {}
+        Err(e) => return Err(e.into()),
+    }
     if create_link(&old_path, &new_path) {
         Ok(0)
     } else {
@@ -72,12 +82,16 @@ pub fn syscall_unlinkat(args: [usize; 6]) -> SyscallResult {
     if path.start_with(&FilePath::new("/proc").unwrap()) {
         return Ok(-1);
     }
+    check_delete(&path)?;
 
     // unlink file
     if flags == 0 {
         if remove_link(&path).is_none() {
-            debug!("unlink file error");
-            return Err(SyscallError::EINVAL);
+            // 不在链接表中，可能是文件系统中的硬链接
+            if let Err(e) = axfs::api::remove_file(path.path()) {
+                debug!("unlink file error: {:?}", e);
+                return Err(SyscallError::EINVAL);
+            }
         }
     }
     // remove dir
@@ -0,0 +1,200 @@
+//! 文件锁：flock 的整文件锁与 fcntl 的记录锁
+//!
+//! 锁本身由 [`axfs::lock`] 管理，这里负责解析参数，以及在 F_SETLKW 时等待：
+//! 等待者睡在文件的等待队列上，锁被释放时被唤醒，收到信号时返回 EINTR
+use alloc::sync::Arc;
+use axfs::api::{FileIO, SeekFrom};
+use axfs::lock::{self, FileKey, LockConflict, LockOwner, LockType, LockWaiter, RecordLock};
+use axlog::{debug, info};
+use axprocess::current_process;
+
+use crate::{Fcntl64Cmd, Flock, SyscallError, SyscallResult, F_RDLCK, F_UNLCK, F_WRLCK};
+
+/// 加共享锁
+pub const LOCK_SH: usize = 1;
+/// 加互斥锁
+pub const LOCK_EX: usize = 2;
+/// 不等待，冲突时返回 EWOULDBLOCK
+pub const LOCK_NB: usize = 4;
+/// 解锁
+pub const LOCK_UN: usize = 8;
+
+/// 打开的文件作为锁的持有者，用文件描述符对象的地址区分
+///
+/// 同一打开文件 dup 出来的 fd 共享这一地址，对应的描述符对象析构时释放它持有的锁
+pub fn file_lock_owner(file: &Arc<dyn FileIO>) -> LockOwner {
+    LockOwner::File(Arc::as_ptr(file) as *const () as usize)
+}
+
+/// 可以加锁的文件的标识，只有普通文件和目录可以加锁
+fn lock_key(file: &Arc<dyn FileIO>) -> Result<FileKey, SyscallError> {
+    file.lock_key().ok_or(SyscallError::ENOLCK)
+}
+
+/// 32
+/// 对整个文件加锁或解锁
+/// # Arguments
+/// * `fd`: usize, 文件描述符
+/// * `operation`: usize, LOCK_SH、LOCK_EX 或 LOCK_UN，可以与 LOCK_NB 组合
+pub fn syscall_flock(args: [usize; 6]) -> SyscallResult {
+    let fd = args[0];
+    let operation = args[1];
+    let process = current_process();
+    let file = match process.fd_manager.fd_table.lock().get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return Err(SyscallError::EBADF),
+    };
+    let ty = match operation & !LOCK_NB {
+        LOCK_SH => Some(LockType::Read),
+        LOCK_EX => Some(LockType::Write),
+        LOCK_UN => None,
+        _ => return Err(SyscallError::EINVAL),
+    };
+    let key = lock_key(&file)?;
+    let owner = file_lock_owner(&file);
+    info!("flock {} on {:?}", operation, key);
+    loop {
+        // 先取得等待者，以免错过尝试加锁之后的解锁
+        let waiter = LockWaiter::new(&key);
+        match lock::flock(&key, owner, ty) {
+            Ok(()) => return Ok(0),
+            Err(_) if operation & LOCK_NB != 0 => return Err(SyscallError::EAGAIN),
+            Err(_) => {}
+        }
+        #[cfg(feature = "signal")]
+        if process.have_signals().is_some() {
+            return Err(SyscallError::EINTR);
+        }
+        waiter.wait();
+    }
+}
+
+/// 把 flock 结构体描述的区域换算为 `start..end` 的绝对偏移
+fn lock_range(file: &Arc<dyn FileIO>, flock: &Flock) -> Result<(u64, u64), SyscallError> {
+    let base = match flock.l_whence {
+        // SEEK_SET
+        0 => 0,
+        // SEEK_CUR
+        1 => file.seek(SeekFrom::Current(0))? as i64,
+        // SEEK_END
+        2 => file.get_stat()?.st_size as i64,
+        _ => return Err(SyscallError::EINVAL),
+    };
+    let start = base
+        .checked_add(flock.l_start)
+        .ok_or(SyscallError::EOVERFLOW)?;
+    let (start, end) = match flock.l_len {
+        0 => (start, u64::MAX as i128),
+        len if len > 0 => (start, start as i128 + len as i128),
+        // 负数长度锁定 start 之前的 |l_len| 个字节
+        len => (
+            start.checked_add(len).ok_or(SyscallError::EINVAL)?,
+            start as i128,
+        ),
+    };
+    if start < 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    Ok((start as u64, end.min(u64::MAX as i128) as u64))
+}
+
+/// 处理 fcntl 的 F_GETLK、F_SETLK、F_SETLKW 及对应的 OFD 命令
+///
+/// 调用时不能持有 fd 表的锁，F_SETLKW 可能一直等待
+pub fn fcntl_lock(file: Arc<dyn FileIO>, cmd: Fcntl64Cmd, arg: usize) -> SyscallResult {
+    let process = current_process();
+    let flock = arg as *mut Flock;
+    if flock.is_null() || process.manual_alloc_type_for_lazy(flock).is_err() {
+        return Err(SyscallError::EFAULT);
+    }
+    let flock = unsafe { &mut *flock };
+    let key = lock_key(&file)?;
+    let ofd = matches!(
+        cmd,
+        Fcntl64Cmd::F_OFD_GETLK | Fcntl64Cmd::F_OFD_SETLK | Fcntl64Cmd::F_OFD_SETLKW
+    );
+    if ofd && flock.l_pid != 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    let owner = if ofd {
+        file_lock_owner(&file)
+    } else {
+        LockOwner::Process(process.pid())
+    };
+    let ty = match flock.l_type {
+        F_RDLCK => Some(LockType::Read),
+        F_WRLCK => Some(LockType::Write),
+        F_UNLCK => None,
+        _ => return Err(SyscallError::EINVAL),
+    };
+    let (start, end) = lock_range(&file, flock)?;
+    debug!("fcntl {:?} {:?} [{}, {}) on {:?}", cmd, ty, start, end, key);
+
+    if matches!(cmd, Fcntl64Cmd::F_GETLK | Fcntl64Cmd::F_OFD_GETLK) {
+        let Some(ty) = ty else {
+            return Err(SyscallError::EINVAL);
+        };
+        let request = RecordLock {
+            owner,
+            ty,
+            start,
+            end,
+        };
+        match lock::test_record(&key, &request) {
+            Some(held) => {
+                flock.l_type = match held.ty {
+                    LockType::Read => F_RDLCK,
+                    LockType::Write => F_WRLCK,
+                };
+                flock.l_whence = 0;
+                flock.l_start = held.start as i64;
+                flock.l_len = if held.end == u64::MAX {
+                    0
+                } else {
+                    (held.end - held.start) as i64
+                };
+                flock.l_pid = match held.owner {
+                    LockOwner::Process(pid) => pid as i32,
+                    LockOwner::File(_) => -1,
+                };
+            }
+            None => flock.l_type = F_UNLCK,
+        }
+        return Ok(0);
+    }
+
+    let Some(ty) = ty else {
+        lock::unlock_record(&key, owner, start, end);
+        return Ok(0);
+    };
+    // 读锁要求文件以可读方式打开，写锁要求可写
+    let permitted = match ty {
+        LockType::Read => file.readable(),
+        LockType::Write => file.writable(),
+    };
+    if !permitted {
+        return Err(SyscallError::EBADF);
+    }
+    let wait = matches!(cmd, Fcntl64Cmd::F_SETLKW | Fcntl64Cmd::F_OFD_SETLKW);
+    let request = RecordLock {
+        owner,
+        ty,
+        start,
+        end,
+    };
+    loop {
+        let waiter = LockWaiter::new(&key);
+        match lock::set_record(&key, request, wait) {
+            Ok(()) => return Ok(0),
+            Err(LockConflict::Deadlock) => return Err(SyscallError::EDEADLK),
+            Err(LockConflict::WouldBlock) if !wait => return Err(SyscallError::EAGAIN),
+            Err(LockConflict::WouldBlock) => {}
+        }
+        #[cfg(feature = "signal")]
+        if process.have_signals().is_some() {
+            lock::cancel_wait(owner);
+            return Err(SyscallError::EINTR);
+        }
+        waiter.wait();
+    }
+}
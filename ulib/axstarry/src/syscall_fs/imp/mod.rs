@@ -4,16 +4,24 @@ extern crate alloc;
 mod ctl;
 mod epoll;
 mod eventfd;
+mod inotify;
 mod io;
 mod link;
+mod lock;
 mod mount;
 mod poll;
+mod splice;
 mod stat;
+mod xattr;
 pub use ctl::*;
 pub use epoll::*;
 pub use eventfd::*;
+pub use inotify::*;
 pub use io::*;
 pub use link::*;
+pub use lock::*;
 pub use mount::*;
 pub use poll::*;
+pub use splice::*;
 pub use stat::*;
+pub use xattr::*;
@@ -1,14 +1,18 @@
 use crate::{SyscallError, SyscallResult};
+use axfs::api::UmountFlags;
 use axprocess::{
     current_process,
     link::{deal_with_path, raw_ptr_to_ref_str, AT_FDCWD},
 };
 
-// use super::{deal_with_path, AT_FDCWD};
-use crate::syscall_fs::ctype::mount::{check_mounted, mount_fat_fs, umount_fat_fs};
 extern crate alloc;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
+use axerrno::AxError;
 use axlog::debug;
+
+/// 修改已挂载的文件系统的参数，而不是挂载新的文件系统
+const MS_REMOUNT: usize = 0x20;
+
 /// 功能:挂载文件系统；
 /// # Arguments
 /// * `special`: *const u8, 挂载设备
@@ -17,74 +21,71 @@ use axlog::debug;
 /// * `flags`: usize, 挂载参数
 /// * `data`: *const u8, 传递给文件系统的字符串参数,可为NULL
 /// 返回值:成功返回0,失败返回-1
+///
+/// 磁盘文件系统的 `special` 为 /dev 下的块设备，fs_type 为 "auto" 时根据超级块探测类型；
+/// 其他文件系统忽略 `special`，`data` 为文件系统自己的参数，如 tmpfs 的 "size=64m,mode=1777"。
+/// 挂载参数中只支持 MS_REMOUNT，此时只修改 `dir` 上已挂载的文件系统的 `data` 参数。
+/// 只有特权进程可以挂载，否则返回 EPERM
 pub fn syscall_mount(args: [usize; 6]) -> SyscallResult {
+    if !current_process().credentials.lock().is_privileged() {
+        return Err(SyscallError::EPERM);
+    }
     let special = args[0] as *const u8;
     let dir = args[1] as *const u8;
     let fs_type = args[2] as *const u8;
-    let _flags = args[3];
-    let _data = args[4] as *const u8;
-    let device_path = deal_with_path(AT_FDCWD, Some(special), false).unwrap();
+    let flags = args[3];
+    let data = args[4] as *const u8;
+    // 非磁盘文件系统的 special 可以为 NULL
+    let device_path = if special.is_null() {
+        String::new()
+    } else {
+        let path = deal_with_path(AT_FDCWD, Some(special), false).ok_or(SyscallError::EFAULT)?;
+        path.path().to_string()
+    };
     // 这里dir必须以"/"结尾,但在shell中输入时,不需要以"/"结尾
-    let mount_path = deal_with_path(AT_FDCWD, Some(dir), true).unwrap();
+    let mount_path = deal_with_path(AT_FDCWD, Some(dir), true).ok_or(SyscallError::EFAULT)?;
 
     let process = current_process();
-    if process
-        .manual_alloc_for_lazy((fs_type as usize).into())
+    let data = if data.is_null() {
+        ""
+    } else if process
+        .manual_alloc_for_lazy((data as usize).into())
         .is_err()
     {
-        return Err(SyscallError::EINVAL);
+        return Err(SyscallError::EFAULT);
+    } else {
+        unsafe { raw_ptr_to_ref_str(data) }
+    };
+    if flags & MS_REMOUNT != 0 {
+        debug!("remount {} with {}", mount_path.path(), data);
+        return match axfs::api::remount(mount_path.path(), data) {
+            Ok(()) => Ok(0),
+            Err(AxError::Unsupported) => Err(SyscallError::EINVAL),
+            Err(e) => Err(e.into()),
+        };
     }
-
-    let fs_type = unsafe { raw_ptr_to_ref_str(fs_type).to_string() };
-    let mut _data_str = "".to_string();
-    if !_data.is_null() {
-        if process
-            .manual_alloc_for_lazy((_data as usize).into())
+    if fs_type.is_null()
+        || process
+            .manual_alloc_for_lazy((fs_type as usize).into())
             .is_err()
-        {
-            return Err(SyscallError::EINVAL);
-        }
-        // data可以为NULL, 必须判断, 否则会panic, 发生LoadPageFault
-        _data_str = unsafe { raw_ptr_to_ref_str(_data) }.to_string();
-    }
-    if device_path.is_dir() {
-        debug!("device_path should not be a dir");
-        return Err(SyscallError::EPERM);
-    }
-    if !mount_path.is_dir() {
-        debug!("mount_path should be a dir");
-        return Err(SyscallError::EPERM);
-    }
-
-    // 如果mount_path不存在,则创建
-    if !axfs::api::path_exists(mount_path.path()) {
-        if let Err(e) = axfs::api::create_dir(mount_path.path()) {
-            debug!("create mount path error: {:?}", e);
-            return Err(SyscallError::EPERM);
-        }
+    {
+        return Err(SyscallError::EFAULT);
     }
+    let fs_type = unsafe { raw_ptr_to_ref_str(fs_type) };
 
-    if fs_type != "vfat" {
-        debug!("fs_type can only be vfat.");
-        return Err(SyscallError::EPERM);
-    }
-    // 检查挂载点路径是否存在
-    if !axfs::api::path_exists(mount_path.path()) {
-        debug!("mount path not exist");
-        return Err(SyscallError::EPERM);
+    debug!(
+        "mount {} on {} as {} with {}",
+        device_path,
+        mount_path.path(),
+        fs_type,
+        data
+    );
+    match axfs::api::mount(&device_path, mount_path.path(), fs_type, data) {
+        Ok(()) => Ok(0),
+        // 不支持的文件系统类型
+        Err(AxError::Unsupported) => Err(SyscallError::ENODEV),
+        Err(e) => Err(e.into()),
     }
-    // 查挂载点是否已经被挂载
-    if check_mounted(&mount_path) {
-        debug!("mount path includes mounted fs");
-        return Err(SyscallError::EPERM);
-    }
-    // 挂载
-    if !mount_fat_fs(&device_path, &mount_path) {
-        debug!("mount error");
-        return Err(SyscallError::EPERM);
-    }
-
-    Ok(0)
 }
 
 /// 功能:卸载文件系统；
@@ -92,27 +93,31 @@ pub fn syscall_mount(args: [usize; 6]) -> SyscallResult {
 /// 返回值:成功返回0,失败返回-1
 /// # Arguments
 /// * `dir`: *const u8, 指定卸载目录
-/// * `flags`: usize, 卸载参数
+/// * `flags`: usize, 卸载参数，支持 MNT_FORCE、MNT_DETACH 与 UMOUNT_NOFOLLOW
+///
+/// 只有特权进程可以卸载，否则返回 EPERM
 pub fn syscall_umount(args: [usize; 6]) -> SyscallResult {
+    if !current_process().credentials.lock().is_privileged() {
+        return Err(SyscallError::EPERM);
+    }
     let dir = args[0] as *const u8;
     let flags = args[1];
-    let mount_path = deal_with_path(AT_FDCWD, Some(dir), true).unwrap();
+    let mount_path = deal_with_path(AT_FDCWD, Some(dir), true).ok_or(SyscallError::EFAULT)?;
 
-    if flags != 0 {
-        debug!("flags unimplemented");
-        return Err(SyscallError::EPERM);
+    let Some(flags) = UmountFlags::from_bits(flags as u32) else {
+        debug!("unknown umount flags: {:#x}", flags);
+        return Err(SyscallError::EINVAL);
+    };
+    // MNT_EXPIRE 不能与 MNT_FORCE 或 MNT_DETACH 同时使用
+    if flags.contains(UmountFlags::MNT_EXPIRE)
+        && flags.intersects(UmountFlags::MNT_FORCE | UmountFlags::MNT_DETACH)
+    {
+        return Err(SyscallError::EINVAL);
     }
 
-    // 检查挂载点路径是否存在
-    if !axfs::api::path_exists(mount_path.path()) {
-        debug!("mount path not exist");
-        return Err(SyscallError::EPERM);
+    match axfs::api::umount(mount_path.path(), flags) {
+        Ok(()) => Ok(0),
+        Err(AxError::Unsupported) => Err(SyscallError::EINVAL),
+        Err(e) => Err(e.into()),
     }
-    // 从挂载点中删除
-    if !umount_fat_fs(&mount_path) {
-        debug!("umount error");
-        return Err(SyscallError::EPERM);
-    }
-
-    Ok(0)
 }
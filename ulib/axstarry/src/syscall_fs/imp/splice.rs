@@ -0,0 +1,400 @@
+//! 在文件、管道与 socket 之间直接传输数据的系统调用
+//!
+//! 数据在内核中从一端直接读入另一端的缓冲区，不经过用户态，也不经过中间缓冲区
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use axerrno::{AxError, AxResult};
+use axfs::api::{FileIO, FileIOType, SeekFrom};
+use axlog::info;
+use axprocess::current_process;
+use core::slice::{from_raw_parts, from_raw_parts_mut};
+
+use crate::syscall_fs::ctype::pipe::Pipe;
+use crate::{IoVec, SpliceFlags, SyscallError, SyscallResult};
+
+/// 无法直接传输时经由缓冲区复制，每次复制的最大长度
+const COPY_CHUNK_SIZE: usize = 0x10000;
+
+/// vmsplice 允许的最多缓冲区个数，同 Linux 的 UIO_MAXIOV
+const MAX_IOVECS: usize = 1024;
+
+fn get_file(fd: usize) -> Result<Arc<dyn FileIO>, SyscallError> {
+    match current_process().fd_manager.fd_table.lock().get(fd) {
+        Some(Some(file)) => Ok(file.clone()),
+        _ => Err(SyscallError::EBADF),
+    }
+}
+
+fn as_pipe(file: &Arc<dyn FileIO>) -> Option<&Pipe> {
+    file.as_any().downcast_ref::<Pipe>()
+}
+
+/// 将传输过程中的错误转为对应的错误码
+fn transfer_error(err: AxError) -> SyscallError {
+    match err {
+        AxError::WouldBlock => SyscallError::EAGAIN,
+        // 管道读入端或 socket 发送端已关闭，同 Linux 向当前线程发送 SIGPIPE
+        AxError::ConnectionReset => {
+            #[cfg(feature = "signal")]
+            let _ = axprocess::signal::send_signal_to_thread(
+                axprocess::current_task().id().as_u64() as isize,
+                axsignal::signal_no::SignalNo::SIGPIPE as isize,
+            );
+            SyscallError::EPIPE
+        }
+        AxError::Interrupted => SyscallError::EINTR,
+        AxError::InvalidInput => SyscallError::EINVAL,
+        err => SyscallError::from(err),
+    }
+}
+
+/// 检查用户传入的偏移量指针
+fn check_offset(offset: *mut i64) -> Result<(), SyscallError> {
+    if !offset.is_null()
+        && current_process()
+            .manual_alloc_type_for_lazy(offset)
+            .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    Ok(())
+}
+
+/// 在文件的 `offset` 处执行传输 `f`，之后恢复文件的读写指针，并将 `offset` 后移传输的字节数
+///
+/// `offset` 为 NULL 时直接使用并更新文件的读写指针
+fn transfer_at<F>(file: &dyn FileIO, offset: *mut i64, f: F) -> Result<usize, SyscallError>
+where
+    F: FnOnce() -> AxResult<usize>,
+{
+    if offset.is_null() {
+        return f().map_err(transfer_error);
+    }
+    let start = unsafe { *offset };
+    if start < 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    let old_offset = file
+        .seek(SeekFrom::Current(0))
+        .map_err(|_| SyscallError::ESPIPE)?;
+    file.seek(SeekFrom::Start(start as u64))
+        .map_err(|_| SyscallError::ESPIPE)?;
+    let ret = f();
+    file.seek(SeekFrom::Start(old_offset)).unwrap();
+    let len = ret.map_err(transfer_error)?;
+    unsafe { *offset = start + len as i64 };
+    Ok(len)
+}
+
+/// 从 `in_file` 读取至多 `len` 字节，直接填入 TCP socket 的发送缓冲区
+///
+/// 读取时持有全局 socket 集合的锁，因此 `in_file` 只能是普通文件：
+/// 读取其他类型的文件可能阻塞而卡住整个网络栈，读取 socket 则会再次获取该锁而死锁
+///
+/// 返回 `Unsupported` 表示 `out_file` 不是 TCP socket
+#[cfg(feature = "net")]
+fn send_to_socket(in_file: &dyn FileIO, out_file: &dyn FileIO, len: usize) -> AxResult<usize> {
+    let Some(socket) = out_file
+        .as_any()
+        .downcast_ref::<crate::syscall_net::Socket>()
+    else {
+        return Err(AxError::Unsupported);
+    };
+    let mut sent = 0;
+    while sent < len {
+        let mut eof = false;
+        let ret = socket.send_with(|space| {
+            let want = space.len().min(len - sent);
+            let read_len = in_file.read(&mut space[..want])?;
+            eof = read_len < want;
+            Ok(read_len)
+        });
+        match ret {
+            Ok(send_len) => sent += send_len,
+            Err(err) if sent == 0 => return Err(err),
+            // 已经发送了部分数据，返回已发送的长度
+            Err(_) => break,
+        }
+        if eof {
+            break;
+        }
+    }
+    Ok(sent)
+}
+
+/// 经由缓冲区从 `in_file` 复制至多 `len` 字节到 `out_file`
+fn copy_through_buffer(in_file: &dyn FileIO, out_file: &dyn FileIO, len: usize) -> AxResult<usize> {
+    let mut buf = vec![0u8; len.min(COPY_CHUNK_SIZE)];
+    let mut copied = 0;
+    while copied < len {
+        let want = buf.len().min(len - copied);
+        let read_len = in_file.read(&mut buf[..want])?;
+        if read_len == 0 {
+            break;
+        }
+        let write_len = match out_file.write(&buf[..read_len]) {
+            Ok(write_len) => write_len,
+            Err(err) if copied == 0 => return Err(err),
+            Err(_) => 0,
+        };
+        copied += write_len;
+        if write_len < read_len {
+            // 没写出去的数据放回输入文件中
+            let _ = in_file.seek(SeekFrom::Current(write_len as i64 - read_len as i64));
+            break;
+        }
+        if read_len < want {
+            break;
+        }
+    }
+    Ok(copied)
+}
+
+/// 71
+/// sendfile64
+/// 将一个文件的内容发送到另一个文件中
+/// 如果offset为NULL,则从当前读写指针开始读取,读取完毕后会更新读写指针
+/// 如果offset不为NULL,则从offset指定的位置开始读取,读取完毕后不会更新读写指针,但是会更新offset的值
+///
+/// 输入端为普通文件且输出端为 TCP socket 时，输入文件的内容直接读入 socket 的发送缓冲区；
+/// 输出端为管道时，直接读入管道的缓冲区
+/// # Arguments
+/// * `out_fd`: usize
+/// * `in_fd`: usize
+/// * `offset`: *mut i64
+/// * `count`: usize
+pub fn syscall_sendfile64(args: [usize; 6]) -> SyscallResult {
+    let out_fd = args[0];
+    let in_fd = args[1];
+    let offset = args[2] as *mut i64;
+    let count = args[3];
+    info!("send from {} to {}, count: {}", in_fd, out_fd, count);
+    let out_file = get_file(out_fd)?;
+    let in_file = get_file(in_fd)?;
+    if in_file.get_type() == FileIOType::DirDesc || out_file.get_type() == FileIOType::DirDesc {
+        return Err(SyscallError::EINVAL);
+    }
+    check_offset(offset)?;
+    if count == 0 {
+        return Ok(0);
+    }
+
+    let len = transfer_at(in_file.as_ref(), offset, || {
+        if let Some(pipe) = as_pipe(&out_file) {
+            if !pipe.writable() {
+                return Err(AxError::InvalidInput);
+            }
+            return pipe.splice_in(count, false, |space| in_file.read(space));
+        }
+        #[cfg(feature = "net")]
+        if in_file.get_type() == FileIOType::FileDesc {
+            match send_to_socket(in_file.as_ref(), out_file.as_ref(), count) {
+                Err(AxError::Unsupported) => {}
+                ret => return ret,
+            }
+        }
+        copy_through_buffer(in_file.as_ref(), out_file.as_ref(), count)
+    })?;
+    Ok(len as isize)
+}
+
+/// 76
+/// splice
+/// 在管道与另一个文件描述符之间移动数据，两端至少有一端为管道
+///
+/// 数据直接从管道的缓冲区写出，或者直接读入管道的缓冲区
+/// # Arguments
+/// * `fd_in`: usize
+/// * `off_in`: *mut i64, 为NULL时使用并更新 fd_in 的读写指针，否则从该位置读取并更新它
+/// * `fd_out`: usize
+/// * `off_out`: *mut i64, 同 `off_in`
+/// * `len`: usize
+/// * `flags`: u32, 见 [`SpliceFlags`]
+pub fn syscall_splice(args: [usize; 6]) -> SyscallResult {
+    let fd_in = args[0];
+    let off_in = args[1] as *mut i64;
+    let fd_out = args[2];
+    let off_out = args[3] as *mut i64;
+    let len = args[4];
+    let flags = SpliceFlags::from_bits_truncate(args[5] as u32);
+    info!(
+        "splice: fd_in: {}, fd_out: {}, len: {}, flags: {:?}",
+        fd_in, fd_out, len, flags
+    );
+    let in_file = get_file(fd_in)?;
+    let out_file = get_file(fd_out)?;
+    if in_file.get_type() == FileIOType::DirDesc || out_file.get_type() == FileIOType::DirDesc {
+        return Err(SyscallError::EINVAL);
+    }
+    check_offset(off_in)?;
+    check_offset(off_out)?;
+    let nonblock = flags.contains(SpliceFlags::NONBLOCK);
+
+    let len = match (as_pipe(&in_file), as_pipe(&out_file)) {
+        (Some(pipe_in), Some(pipe_out)) => {
+            if !off_in.is_null() || !off_out.is_null() {
+                return Err(SyscallError::ESPIPE);
+            }
+            if !pipe_in.readable() || !pipe_out.writable() {
+                return Err(SyscallError::EBADF);
+            }
+            if len == 0 {
+                return Ok(0);
+            }
+            pipe_in
+                .splice_to_pipe(pipe_out, len, true, nonblock)
+                .map_err(transfer_error)?
+        }
+        (Some(pipe_in), None) => {
+            if !off_in.is_null() {
+                return Err(SyscallError::ESPIPE);
+            }
+            if !pipe_in.readable() {
+                return Err(SyscallError::EBADF);
+            }
+            if len == 0 {
+                return Ok(0);
+            }
+            transfer_at(out_file.as_ref(), off_out, || {
+                pipe_in.splice_out(len, nonblock, |data| out_file.write(data))
+            })?
+        }
+        (None, Some(pipe_out)) => {
+            if !off_out.is_null() {
+                return Err(SyscallError::ESPIPE);
+            }
+            if !pipe_out.writable() {
+                return Err(SyscallError::EBADF);
+            }
+            if len == 0 {
+                return Ok(0);
+            }
+            transfer_at(in_file.as_ref(), off_in, || {
+                pipe_out.splice_in(len, nonblock, |space| in_file.read(space))
+            })?
+        }
+        (None, None) => return Err(SyscallError::EINVAL),
+    };
+    Ok(len as isize)
+}
+
+/// 77
+/// tee
+/// 将一个管道中的数据复制到另一个管道中，但不取走原管道中的数据
+/// # Arguments
+/// * `fd_in`: usize, 管道的读出端
+/// * `fd_out`: usize, 管道的写入端
+/// * `len`: usize
+/// * `flags`: u32, 见 [`SpliceFlags`]
+pub fn syscall_tee(args: [usize; 6]) -> SyscallResult {
+    let fd_in = args[0];
+    let fd_out = args[1];
+    let len = args[2];
+    let flags = SpliceFlags::from_bits_truncate(args[3] as u32);
+    info!(
+        "tee: fd_in: {}, fd_out: {}, len: {}, flags: {:?}",
+        fd_in, fd_out, len, flags
+    );
+    let in_file = get_file(fd_in)?;
+    let out_file = get_file(fd_out)?;
+    let (Some(pipe_in), Some(pipe_out)) = (as_pipe(&in_file), as_pipe(&out_file)) else {
+        return Err(SyscallError::EINVAL);
+    };
+    if !pipe_in.readable() || !pipe_out.writable() {
+        return Err(SyscallError::EBADF);
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    let nonblock = flags.contains(SpliceFlags::NONBLOCK);
+    pipe_in
+        .splice_to_pipe(pipe_out, len, false, nonblock)
+        .map(|len| len as isize)
+        .map_err(transfer_error)
+}
+
+/// 75
+/// vmsplice
+/// 管道为写入端时，将用户的各个缓冲区中的数据写入管道；为读出端时，将管道中的数据读到各个缓冲区中
+///
+/// 与 Linux 不同，数据总是被复制，SPLICE_F_GIFT 被忽略
+/// # Arguments
+/// * `fd`: usize, 管道的一端
+/// * `iov`: *const IoVec
+/// * `nr_segs`: usize, 缓冲区的个数
+/// * `flags`: u32, 见 [`SpliceFlags`]
+pub fn syscall_vmsplice(args: [usize; 6]) -> SyscallResult {
+    let fd = args[0];
+    let iov = args[1] as *const IoVec;
+    let nr_segs = args[2];
+    let flags = SpliceFlags::from_bits_truncate(args[3] as u32);
+    info!(
+        "vmsplice: fd: {}, nr_segs: {}, flags: {:?}",
+        fd, nr_segs, flags
+    );
+    let file = get_file(fd)?;
+    let Some(pipe) = as_pipe(&file) else {
+        return Err(SyscallError::EBADF);
+    };
+    if nr_segs > MAX_IOVECS {
+        return Err(SyscallError::EINVAL);
+    }
+    if nr_segs == 0 {
+        return Ok(0);
+    }
+    let curr = current_process();
+    let iov_start = iov as usize;
+    if curr
+        .manual_alloc_range_for_lazy(
+            iov_start.into(),
+            (iov_start + nr_segs * core::mem::size_of::<IoVec>()).into(),
+        )
+        .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    let iovecs = unsafe { from_raw_parts(iov, nr_segs) };
+    for io in iovecs.iter().filter(|io| io.len > 0) {
+        let start = io.base as usize;
+        if curr
+            .manual_alloc_range_for_lazy(start.into(), (start + io.len).into())
+            .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+    }
+
+    let mut total = 0;
+    for io in iovecs.iter().filter(|io| io.len > 0) {
+        // 已经传输了部分数据后不再阻塞
+        let nonblock = flags.contains(SpliceFlags::NONBLOCK) || total > 0;
+        let mut done = 0;
+        let ret = if pipe.writable() {
+            let data = unsafe { from_raw_parts(io.base, io.len) };
+            pipe.splice_in(io.len, nonblock, |space| {
+                space.copy_from_slice(&data[done..done + space.len()]);
+                done += space.len();
+                Ok(space.len())
+            })
+        } else {
+            let buf = unsafe { from_raw_parts_mut(io.base, io.len) };
+            pipe.splice_out(io.len, nonblock, |data| {
+                buf[done..done + data.len()].copy_from_slice(data);
+                done += data.len();
+                Ok(data.len())
+            })
+        };
+        match ret {
+            Ok(len) => {
+                total += len;
+                if len < io.len {
+                    break;
+                }
+            }
+            Err(AxError::WouldBlock) if total > 0 => break,
+            Err(err) => return Err(transfer_error(err)),
+        }
+    }
+    Ok(total as isize)
+}
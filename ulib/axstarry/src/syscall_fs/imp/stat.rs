@@ -1,12 +1,14 @@
 //! 获取文件系统状态信息
 //!
 
+use super::ctl::check_search;
 use crate::{get_fs_stat, FsStat, SyscallError, SyscallResult};
+use axerrno::AxError;
 use axfs::api::{FileIOType, Kstat};
-use axlog::{debug, error, info};
+use axlog::{debug, info};
 use axprocess::{
     current_process,
-    link::{deal_with_path, raw_ptr_to_ref_str, FilePath, AT_FDCWD},
+    link::{deal_with_path, raw_ptr_to_ref_str, AT_FDCWD},
 };
 
 use crate::syscall_fs::ctype::mount::get_stat_in_fs;
@@ -76,6 +78,7 @@ pub fn syscall_fstatat(args: [usize; 6]) -> SyscallResult {
         panic!("Wrong path at syscall_fstatat: {}(dir_fd={})", path, dir_fd);
     };
     info!("path : {}", file_path.path());
+    check_search(&current_process().credentials.lock(), file_path.path())?;
     if !axfs::api::path_exists(file_path.path()) {
         return Err(SyscallError::ENOENT);
     }
@@ -119,19 +122,28 @@ pub fn syscall_stat(args: [usize; 6]) -> SyscallResult {
 /// # Arguments
 /// * `path` - *const u8
 /// * `stat` - *mut FsStat
+///
+/// 给出 `path` 所在的已挂载文件系统的信息，文件系统没有提供时给出一个基础的 fsstat
 pub fn syscall_statfs(args: [usize; 6]) -> SyscallResult {
     let path = args[0] as *const u8;
     let stat = args[1] as *mut FsStat;
-    let file_path = deal_with_path(AT_FDCWD, Some(path), false).unwrap();
-    if file_path.equal_to(&FilePath::new("/").unwrap()) {
-        // 目前只支持访问根目录文件系统的信息
-        unsafe {
-            *stat = get_fs_stat();
+    let process = current_process();
+    if process.manual_alloc_type_for_lazy(stat).is_err() {
+        return Err(SyscallError::EFAULT);
+    }
+    let Some(file_path) = deal_with_path(AT_FDCWD, Some(path), false) else {
+        return Err(SyscallError::EINVAL);
+    };
+    let fs_stat = match axfs::api::statfs(file_path.path()) {
+        Ok(info) => info.into(),
+        Err(AxError::Unsupported) => get_fs_stat(),
+        Err(e) => {
+            debug!("statfs {} error: {:?}", file_path.path(), e);
+            return Err(e.into());
         }
-
-        Ok(0)
-    } else {
-        error!("Only support fs_stat for root");
-        Err(SyscallError::EINVAL)
+    };
+    unsafe {
+        *stat = fs_stat;
     }
+    Ok(0)
 }
@@ -0,0 +1,244 @@
+//! 扩展属性相关的系统调用
+//!
+//! l 开头的版本不跟随符号链接，目前不支持符号链接，与普通版本相同；
+//! f 开头的版本操作文件描述符对应的文件。
+//! `system.posix_acl_access` 中的 ACL 在权限检查时生效，见 [`super::ctl::check_access`]
+extern crate alloc;
+use alloc::{string::String, vec::Vec};
+use axerrno::AxError;
+use axfs::fops::FileAttr;
+use axfs::xattr::{Namespace, XattrFlags, XATTR_LIST_MAX, XATTR_NAME_MAX, XATTR_SIZE_MAX};
+use axprocess::{
+    credentials::{MAY_READ, MAY_WRITE},
+    current_process,
+    link::{deal_with_path, raw_ptr_to_ref_str, AT_FDCWD},
+};
+
+use super::ctl::{check_access, fd_path, path_attr};
+use crate::{SyscallError, SyscallResult};
+
+/// 转换扩展属性操作的错误，属性不存在时为 ENODATA，不支持时为 EOPNOTSUPP
+fn xattr_error(err: AxError) -> SyscallError {
+    match err {
+        AxError::NotFound => SyscallError::ENODATA,
+        AxError::Unsupported => SyscallError::EOPNOTSUPP,
+        err => err.into(),
+    }
+}
+
+/// 解析 `path` 参数指向的路径
+fn user_path(path: usize) -> Result<String, SyscallError> {
+    let path =
+        deal_with_path(AT_FDCWD, Some(path as *const u8), false).ok_or(SyscallError::EINVAL)?;
+    Ok(path.path().into())
+}
+
+/// 读取属性名，名字为空或过长时返回 ERANGE
+fn user_name(name: usize) -> Result<&'static str, SyscallError> {
+    if current_process()
+        .manual_alloc_for_lazy(name.into())
+        .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    let name = unsafe { raw_ptr_to_ref_str(name as *const u8) };
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(SyscallError::ERANGE);
+    }
+    Ok(name)
+}
+
+/// 获取用户空间中从 `buf` 开始、长度为 `size` 的缓冲区
+fn user_buf(buf: usize, size: usize) -> Result<&'static mut [u8], SyscallError> {
+    if size == 0 {
+        return Ok(&mut []);
+    }
+    if current_process()
+        .manual_alloc_range_for_lazy(buf.into(), (buf + size - 1).into())
+        .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    Ok(unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, size) })
+}
+
+/// 把 `data` 写入大小为 `size` 的用户缓冲区，返回数据的长度
+///
+/// `size` 为 0 时只返回长度，缓冲区不够大时返回 ERANGE
+fn copy_out(buf: usize, size: usize, data: &[u8]) -> SyscallResult {
+    if size == 0 {
+        return Ok(data.len() as isize);
+    }
+    if size < data.len() {
+        return Err(SyscallError::ERANGE);
+    }
+    user_buf(buf, data.len())?.copy_from_slice(data);
+    Ok(data.len() as isize)
+}
+
+/// 检查当前进程能否读取（`write` 为 false）或修改文件 `path` 的扩展属性 `name`
+///
+/// - `trusted.` 只有特权进程可以访问，其余进程读取时如同属性不存在
+/// - `security.` 只有特权进程可以修改
+/// - `system.` 中的 ACL 只有文件的属主或特权进程可以修改
+/// - `user.` 只能用于普通文件与目录，需要对文件有读或写权限，
+///   设置了 sticky 位的目录只有属主可以修改
+fn check_xattr_access(
+    path: &str,
+    attr: &FileAttr,
+    name: &str,
+    write: bool,
+) -> Result<(), SyscallError> {
+    let namespace = Namespace::of(name).map_err(xattr_error)?;
+    let denied = if write {
+        SyscallError::EPERM
+    } else {
+        SyscallError::ENODATA
+    };
+    {
+        let process = current_process();
+        let credentials = process.credentials.lock();
+        if credentials.is_privileged() {
+            return Ok(());
+        }
+        match namespace {
+            Namespace::Trusted => return Err(denied),
+            Namespace::Security | Namespace::System if !write => return Ok(()),
+            Namespace::Security => return Err(SyscallError::EPERM),
+            Namespace::System if !credentials.owns(attr) => return Err(SyscallError::EPERM),
+            Namespace::System => return Ok(()),
+            Namespace::User => {
+                if !attr.is_file() && !attr.is_dir() {
+                    return Err(denied);
+                }
+                let sticky = attr.perm().contains(axfs::api::Permissions::STICKY);
+                if write && attr.is_dir() && sticky && !credentials.owns(attr) {
+                    return Err(SyscallError::EPERM);
+                }
+            }
+        }
+    }
+    check_access(path, if write { MAY_WRITE } else { MAY_READ })?;
+    Ok(())
+}
+
+fn getxattr(path: &str, name: usize, value: usize, size: usize) -> SyscallResult {
+    let name = user_name(name)?;
+    let attr = path_attr(path)?;
+    check_xattr_access(path, &attr, name, false)?;
+    let data = axfs::api::get_xattr(path, name).map_err(xattr_error)?;
+    copy_out(value, size, &data)
+}
+
+fn setxattr(path: &str, name: usize, value: usize, size: usize, flags: usize) -> SyscallResult {
+    let name = user_name(name)?;
+    let flags = XattrFlags::from_bits(flags as u32).ok_or(SyscallError::EINVAL)?;
+    if size > XATTR_SIZE_MAX {
+        return Err(SyscallError::E2BIG);
+    }
+    let attr = path_attr(path)?;
+    check_xattr_access(path, &attr, name, true)?;
+    let value = user_buf(value, size)?;
+    axfs::api::set_xattr(path, name, value, flags).map_err(xattr_error)?;
+    Ok(0)
+}
+
+fn listxattr(path: &str, list: usize, size: usize) -> SyscallResult {
+    path_attr(path)?;
+    let names = match axfs::api::list_xattr(path) {
+        Ok(names) => names,
+        // 不支持扩展属性的文件系统没有任何属性
+        Err(AxError::Unsupported) => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let privileged = current_process().credentials.lock().is_privileged();
+    let mut data = Vec::new();
+    for name in names {
+        if privileged || Namespace::of(&name).is_ok_and(|ns| ns != Namespace::Trusted) {
+            data.extend_from_slice(name.as_bytes());
+            data.push(0);
+        }
+    }
+    if data.len() > XATTR_LIST_MAX {
+        return Err(SyscallError::E2BIG);
+    }
+    copy_out(list, size, &data)
+}
+
+fn removexattr(path: &str, name: usize) -> SyscallResult {
+    let name = user_name(name)?;
+    let attr = path_attr(path)?;
+    check_xattr_access(path, &attr, name, true)?;
+    axfs::api::remove_xattr(path, name).map_err(xattr_error)?;
+    Ok(0)
+}
+
+/// 5
+/// 设置文件的扩展属性
+/// # Arguments
+/// * `path`: *const u8, 文件的路径
+/// * `name`: *const u8, 属性名，如 `user.comment`
+/// * `value`: *const u8, 属性值
+/// * `size`: usize, 属性值的长度
+/// * `flags`: usize, XATTR_CREATE 要求属性不存在，XATTR_REPLACE 要求属性已存在
+pub fn syscall_setxattr(args: [usize; 6]) -> SyscallResult {
+    setxattr(&user_path(args[0])?, args[1], args[2], args[3], args[4])
+}
+
+/// 7
+/// 设置文件描述符对应文件的扩展属性，参数同 setxattr
+pub fn syscall_fsetxattr(args: [usize; 6]) -> SyscallResult {
+    setxattr(&fd_path(args[0])?, args[1], args[2], args[3], args[4])
+}
+
+/// 8
+/// 获取文件的扩展属性，返回属性值的长度
+///
+/// size 为 0 时只返回长度，缓冲区不够大时返回 ERANGE
+/// # Arguments
+/// * `path`: *const u8, 文件的路径
+/// * `name`: *const u8, 属性名
+/// * `value`: *mut u8, 存放属性值的缓冲区
+/// * `size`: usize, 缓冲区的大小
+pub fn syscall_getxattr(args: [usize; 6]) -> SyscallResult {
+    getxattr(&user_path(args[0])?, args[1], args[2], args[3])
+}
+
+/// 10
+/// 获取文件描述符对应文件的扩展属性，参数同 getxattr
+pub fn syscall_fgetxattr(args: [usize; 6]) -> SyscallResult {
+    getxattr(&fd_path(args[0])?, args[1], args[2], args[3])
+}
+
+/// 11
+/// 列出文件的扩展属性名，每个名字以 '\0' 结尾，返回列表的长度
+///
+/// 非特权进程看不到 `trusted.` 中的属性
+/// # Arguments
+/// * `path`: *const u8, 文件的路径
+/// * `list`: *mut u8, 存放属性名的缓冲区
+/// * `size`: usize, 缓冲区的大小，为 0 时只返回长度
+pub fn syscall_listxattr(args: [usize; 6]) -> SyscallResult {
+    listxattr(&user_path(args[0])?, args[1], args[2])
+}
+
+/// 13
+/// 列出文件描述符对应文件的扩展属性名，参数同 listxattr
+pub fn syscall_flistxattr(args: [usize; 6]) -> SyscallResult {
+    listxattr(&fd_path(args[0])?, args[1], args[2])
+}
+
+/// 14
+/// 删除文件的扩展属性
+/// # Arguments
+/// * `path`: *const u8, 文件的路径
+/// * `name`: *const u8, 属性名
+pub fn syscall_removexattr(args: [usize; 6]) -> SyscallResult {
+    removexattr(&user_path(args[0])?, args[1])
+}
+
+/// 16
+/// 删除文件描述符对应文件的扩展属性，参数同 removexattr
+pub fn syscall_fremovexattr(args: [usize; 6]) -> SyscallResult {
+    removexattr(&fd_path(args[0])?, args[1])
+}
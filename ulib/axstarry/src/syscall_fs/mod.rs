@@ -31,23 +31,41 @@ pub fn fs_syscall(syscall_id: fs_syscall_id::FsSyscallId, args: [usize; 6]) -> S
         READV => syscall_readv(args),
         WRITEV => syscall_writev(args),
         FCNTL64 => syscall_fcntl64(args),
+        FLOCK => syscall_flock(args),
+        INOTIFY_INIT1 => syscall_inotify_init1(args),
+        INOTIFY_ADD_WATCH => syscall_inotify_add_watch(args),
+        INOTIFY_RM_WATCH => syscall_inotify_rm_watch(args),
         FSTATAT => syscall_fstatat(args),
         STATFS => syscall_statfs(args),
+        FCHMOD => syscall_fchmod(args),
         FCHMODAT => syscall_fchmodat(args),
+        FCHOWN => syscall_fchown(args),
+        FCHOWNAT => syscall_fchownat(args),
+        // 目前不支持符号链接，l 开头的版本与普通版本相同
+        SETXATTR | LSETXATTR => syscall_setxattr(args),
+        FSETXATTR => syscall_fsetxattr(args),
+        GETXATTR | LGETXATTR => syscall_getxattr(args),
+        FGETXATTR => syscall_fgetxattr(args),
+        LISTXATTR | LLISTXATTR => syscall_listxattr(args),
+        FLISTXATTR => syscall_flistxattr(args),
+        REMOVEXATTR | LREMOVEXATTR => syscall_removexattr(args),
+        FREMOVEXATTR => syscall_fremovexattr(args),
         FACCESSAT => syscall_faccessat(args),
         LSEEK => syscall_lseek(args),
         PREAD64 => syscall_pread64(args),
         PREADLINKAT => syscall_readlinkat(args),
         PWRITE64 => syscall_pwrite64(args),
         SENDFILE64 => syscall_sendfile64(args),
+        SPLICE => syscall_splice(args),
+        TEE => syscall_tee(args),
+        VMSPLICE => syscall_vmsplice(args),
         FSYNC => Ok(0),
         FTRUNCATE64 => {
             syscall_ftruncate64(args)
             // 0
         }
         IOCTL => syscall_ioctl(args),
-        // 不做处理即可
-        SYNC => Ok(0),
+        SYNC => syscall_sync(),
         COPYFILERANGE => syscall_copyfilerange(args),
         LINKAT => sys_linkat(args),
         UNLINKAT => syscall_unlinkat(args),
@@ -98,6 +116,13 @@ pub fn fs_syscall(syscall_id: fs_syscall_id::FsSyscallId, args: [usize; 6]) -> S
         #[cfg(target_arch = "x86_64")]
         EPOLL_PWAIT => unimplemented!("epoll_ctl"),
         #[cfg(target_arch = "x86_64")]
-        CHMOD => Ok(0),
+        CHMOD => syscall_chmod(args),
+        #[cfg(target_arch = "x86_64")]
+        CHOWN => syscall_chown(args),
+        // 目前不支持符号链接，lchown 与 chown 相同
+        #[cfg(target_arch = "x86_64")]
+        LCHOWN => syscall_chown(args),
+        #[cfg(target_arch = "x86_64")]
+        INOTIFY_INIT => syscall_inotify_init(args),
     }
 }
@@ -1,10 +1,19 @@
-use crate::{syscall_fs::FileDesc, MMAPFlags, MREMAPFlags, SyscallError, SyscallResult, MMAPPROT};
+use crate::{
+    syscall_fs::FileDesc, IpcPerm, MMAPFlags, MREMAPFlags, MadviseAdvice, MlockAllFlags, ShmInfo,
+    ShmUsage, ShmidDs, SyscallError, SyscallResult, IPC_64, IPC_INFO, IPC_RMID, IPC_SET, IPC_STAT,
+    MLOCK_ONFAULT, MMAPPROT, SHM_INFO, SHM_STAT,
+};
 extern crate alloc;
 
-use axhal::{arch::flush_tlb, mem::VirtAddr, paging::MappingFlags};
-use axmem::MemorySet;
+use axhal::{
+    arch::flush_tlb,
+    mem::{VirtAddr, PAGE_SIZE_4K},
+    paging::MappingFlags,
+};
+use axmem::{MemorySet, SharedMem};
 
-use axprocess::current_process;
+use axprocess::credentials::{MAY_EXEC, MAY_READ, MAY_WRITE};
+use axprocess::{current_process, ipc};
 use bitflags::bitflags;
 
 const MAX_HEAP_SIZE: usize = 0x20000;
@@ -50,6 +59,7 @@ pub fn syscall_mmap(args: [usize; 6]) -> SyscallResult {
     use axmem::MemBackend;
 
     let fixed = flags.contains(MMAPFlags::MAP_FIXED);
+    let shared = flags.contains(MMAPFlags::MAP_SHARED);
     // try to map to NULL
     if fixed && start == 0 {
         return Err(SyscallError::EINVAL);
@@ -66,7 +76,7 @@ pub fn syscall_mmap(args: [usize; 6]) -> SyscallResult {
             .memory_set
             .lock()
             .lock()
-            .mmap(start.into(), len, prot.into(), fixed, None)
+            .mmap(start.into(), len, prot.into(), fixed, shared, None)
     } else {
         // file backend
         debug!("[mmap] fd: {}, offset: 0x{:x}", fd, offset);
@@ -88,11 +98,14 @@ pub fn syscall_mmap(args: [usize; 6]) -> SyscallResult {
         };
 
         let backend = MemBackend::new(file, offset as u64);
-        process
-            .memory_set
-            .lock()
-            .lock()
-            .mmap(start.into(), len, prot.into(), fixed, Some(backend))
+        process.memory_set.lock().lock().mmap(
+            start.into(),
+            len,
+            prot.into(),
+            fixed,
+            shared,
+            Some(backend),
+        )
     };
 
     flush_tlb(None);
@@ -231,6 +244,11 @@ pub fn syscall_mremap(args: [usize; 6]) -> SyscallResult {
 }
 const IPC_PRIVATE: i32 = 0;
 
+/// 共享内存的最大字节数，与 Linux 的默认值相同
+const SHMMAX: usize = usize::MAX - (1 << 24);
+/// 共享内存的最大数目
+const SHMMNI: usize = 4096;
+
 bitflags! {
     #[derive(Debug)]
     struct ShmFlags: i32 {
@@ -242,7 +260,21 @@ bitflags! {
     }
 }
 
-// TODO: uid and gid support
+/// 共享内存的所有者与权限，用于检查进程能否访问
+fn shm_perm(mem: &SharedMem) -> ipc::IpcPerm {
+    let perm = mem.info.lock().perm.clone();
+    ipc::IpcPerm {
+        key: perm.key,
+        uid: perm.uid,
+        gid: perm.gid,
+        cuid: perm.cuid,
+        cgid: perm.cgid,
+        mode: perm.mode,
+    }
+}
+
+/// 新的共享内存的所有者与创建者为当前进程的有效用户与有效组；
+/// 获取已有的共享内存时，要求拥有 `flags` 中请求的权限
 /// # Arguments
 /// * `key` - i32
 /// * `size` - usize
@@ -252,7 +284,12 @@ pub fn syscall_shmget(args: [usize; 6]) -> SyscallResult {
     let size = args[1];
     let flags = args[2] as i32;
 
-    let pid = current_process().pid();
+    let process = current_process();
+    let pid = process.pid();
+    let (uid, gid) = {
+        let credentials = process.credentials.lock();
+        (credentials.euid, credentials.egid)
+    };
 
     // 9 bits for permission
     let mode: u16 = (flags as u16) & ((1 << 10) - 1);
@@ -263,8 +300,11 @@ pub fn syscall_shmget(args: [usize; 6]) -> SyscallResult {
     };
 
     if key == IPC_PRIVATE {
-        let Ok((shmid, mem)) = MemorySet::create_shared_mem(key, size, pid, 0, 0, mode) else {
+        if size == 0 || size > SHMMAX {
             return Err(SyscallError::EINVAL);
+        }
+        let Ok((shmid, mem)) = MemorySet::create_shared_mem(key, size, pid, uid, gid, mode) else {
+            return Err(SyscallError::ENOMEM);
         };
 
         current_process()
@@ -280,16 +320,31 @@ pub fn syscall_shmget(args: [usize; 6]) -> SyscallResult {
         match key_map.get(&key) {
             Some(shmid) => {
                 if flags.contains(ShmFlags::IPC_CREAT) && flags.contains(ShmFlags::IPC_EXCL) {
-                    Err(SyscallError::EEXIST)
-                } else {
-                    Ok(*shmid as isize)
+                    return Err(SyscallError::EEXIST);
+                }
+                let Some(mem) = MemorySet::get_shared_mem(*shmid) else {
+                    return Err(SyscallError::EINVAL);
+                };
+                let mode = mode as u32;
+                shm_perm(&mem).check(
+                    &process.credentials.lock(),
+                    (mode >> 6 | mode >> 3 | mode) & 0o7,
+                )?;
+                // 已有的共享内存不能满足要求的大小
+                if size > mem.info.lock().size {
+                    return Err(SyscallError::EINVAL);
                 }
+                Ok(*shmid as isize)
             }
             None => {
                 if flags.contains(ShmFlags::IPC_CREAT) {
-                    let Ok((shmid, mem)) = MemorySet::create_shared_mem(key, size, pid, 0, 0, mode)
-                    else {
+                    if size == 0 || size > SHMMAX {
                         return Err(SyscallError::EINVAL);
+                    }
+                    let Ok((shmid, mem)) =
+                        MemorySet::create_shared_mem(key, size, pid, uid, gid, mode)
+                    else {
+                        return Err(SyscallError::ENOMEM);
                     };
 
                     key_map.insert(key, shmid);
@@ -326,7 +381,9 @@ pub fn syscall_shmat(args: [usize; 6]) -> SyscallResult {
     let memory_set_wrapper = process.memory_set.lock();
     let mut memory = memory_set_wrapper.lock();
 
-    let flags = ShmAtFlags::from_bits(flags).unwrap();
+    let Some(flags) = ShmAtFlags::from_bits(flags) else {
+        return Err(SyscallError::EINVAL);
+    };
 
     let Some(mem) = memory
         .get_private_shared_mem(shmid)
@@ -334,6 +391,18 @@ pub fn syscall_shmat(args: [usize; 6]) -> SyscallResult {
     else {
         return Err(SyscallError::EINVAL);
     };
+    // 已经被 IPC_RMID 标记删除的共享内存不能再被 attach
+    if mem.info.lock().is_removed() {
+        return Err(SyscallError::EIDRM);
+    }
+    let mut want = MAY_READ;
+    if !flags.contains(ShmAtFlags::SHM_RDONLY) {
+        want |= MAY_WRITE;
+    }
+    if flags.contains(ShmAtFlags::SHM_EXEC) {
+        want |= MAY_EXEC;
+    }
+    shm_perm(&mem).check(&process.credentials.lock(), want)?;
     let size = mem.size();
 
     let addr = if addr == 0 {
@@ -346,7 +415,7 @@ pub fn syscall_shmat(args: [usize; 6]) -> SyscallResult {
         let addr = if addr.is_aligned_4k() {
             addr
         } else if flags.contains(ShmAtFlags::SHM_RND) {
-            addr.align_up_4k()
+            addr.align_down_4k()
         } else {
             return Err(SyscallError::EINVAL);
         };
@@ -354,8 +423,9 @@ pub fn syscall_shmat(args: [usize; 6]) -> SyscallResult {
         if flags.contains(ShmAtFlags::SHM_REMAP) {
             memory.split_for_area(addr, size);
             flush_tlb(None);
-        } else {
-            unimplemented!()
+        } else if memory.find_free_area(addr, size) != Some(addr) {
+            // 未指定 SHM_REMAP 时不能覆盖已有的映射
+            return Err(SyscallError::EINVAL);
         }
 
         addr
@@ -371,13 +441,309 @@ pub fn syscall_shmat(args: [usize; 6]) -> SyscallResult {
         map_flags |= MappingFlags::EXECUTE;
     }
 
+    mem.info.lock().touch(process.pid(), true);
     memory.attach_shared_mem(mem, addr, map_flags);
     flush_tlb(None);
 
     Ok(addr.as_usize() as isize)
 }
 
-/// # mlock
+/// 将 attach 在 addr 处的共享内存从当前进程的地址空间中分离
+///
+/// 若该共享内存已经被 IPC_RMID 标记删除，且这是最后一次 detach，则将其释放
+/// # Arguments
+/// * `addr` - usize
+pub fn syscall_shmdt(args: [usize; 6]) -> SyscallResult {
+    let addr = VirtAddr::from(args[0]);
+    if !addr.is_aligned_4k() {
+        return Err(SyscallError::EINVAL);
+    }
+    let process = current_process();
+    let memory_set_wrapper = process.memory_set.lock();
+    let mut memory = memory_set_wrapper.lock();
+    let Ok(mem) = memory.detach_shared_mem(addr) else {
+        return Err(SyscallError::EINVAL);
+    };
+    flush_tlb(None);
+    mem.info.lock().touch(process.pid(), false);
+    Ok(0)
+}
+
+/// 对共享内存进行控制
+///
+/// # Arguments
+/// * `shmid` - i32
+/// * `cmd` - usize
+/// * `buf` - *mut ShmidDs
+pub fn syscall_shmctl(args: [usize; 6]) -> SyscallResult {
+    let shmid = args[0] as i32;
+    let cmd = args[1] & !IPC_64;
+    let buf = args[2];
+    let process = current_process();
+
+    match cmd {
+        IPC_INFO => {
+            let buf = buf as *mut ShmInfo;
+            if process.manual_alloc_type_for_lazy(buf).is_err() {
+                return Err(SyscallError::EFAULT);
+            }
+            unsafe {
+                *buf = ShmInfo {
+                    shmmax: SHMMAX,
+                    shmmin: 1,
+                    shmmni: SHMMNI,
+                    shmseg: SHMMNI,
+                    shmall: SHMMAX,
+                    ..Default::default()
+                };
+            }
+            return Ok(shm_max_id() as isize);
+        }
+        SHM_INFO => {
+            let buf = buf as *mut ShmUsage;
+            if process.manual_alloc_type_for_lazy(buf).is_err() {
+                return Err(SyscallError::EFAULT);
+            }
+            let mems = axmem::SHARED_MEMS.lock();
+            let pages = mems.values().map(|mem| mem.size() / PAGE_SIZE_4K).sum();
+            unsafe {
+                *buf = ShmUsage {
+                    used_ids: mems.len() as i32,
+                    shm_tot: pages,
+                    shm_rss: pages,
+                    ..Default::default()
+                };
+            }
+            return Ok(shm_max_id() as isize);
+        }
+        IPC_STAT | SHM_STAT | IPC_SET => {
+            if process
+                .manual_alloc_type_for_lazy(buf as *const ShmidDs)
+                .is_err()
+            {
+                return Err(SyscallError::EFAULT);
+            }
+        }
+        IPC_RMID => {}
+        _ => return Err(SyscallError::EINVAL),
+    }
+
+    let memory_set_wrapper = process.memory_set.lock();
+    let mut memory = memory_set_wrapper.lock();
+    let Some(mem) = memory
+        .get_private_shared_mem(shmid)
+        .or_else(|| MemorySet::get_shared_mem(shmid))
+    else {
+        return Err(SyscallError::EINVAL);
+    };
+    // 读取状态需要读权限，修改与删除只有所有者、创建者与特权进程可以进行
+    let perm = shm_perm(&mem);
+    let credentials = process.credentials.lock().clone();
+    match cmd {
+        IPC_STAT | SHM_STAT => perm.check(&credentials, MAY_READ)?,
+        _ => perm.check_owner(&credentials)?,
+    }
+    match cmd {
+        IPC_STAT | SHM_STAT => {
+            let info = mem.info.lock().clone();
+            let perm = info.perm;
+            unsafe {
+                *(buf as *mut ShmidDs) = ShmidDs {
+                    shm_perm: IpcPerm::new(
+                        perm.key, perm.uid, perm.gid, perm.cuid, perm.cgid, perm.mode,
+                    ),
+                    shm_segsz: info.size,
+                    shm_atime: info.a_time,
+                    shm_dtime: info.d_time,
+                    shm_ctime: info.c_time,
+                    shm_cpid: info.c_pid as i32,
+                    shm_lpid: info.l_pid as i32,
+                    shm_nattch: info.nattch,
+                    ..Default::default()
+                };
+            }
+            Ok(if cmd == SHM_STAT { shmid as isize } else { 0 })
+        }
+        IPC_SET => {
+            let ds = unsafe { *(buf as *const ShmidDs) };
+            let mut info = mem.info.lock();
+            info.perm.uid = ds.shm_perm.uid;
+            info.perm.gid = ds.shm_perm.gid;
+            info.perm.mode = (info.perm.mode & !0o777) | (ds.shm_perm.mode & 0o777);
+            info.c_time = axhal::time::current_time().as_secs() as usize;
+            Ok(0)
+        }
+        _ => {
+            if memory.remove_shared_mem(shmid).is_err() {
+                return Err(SyscallError::EINVAL);
+            }
+            Ok(0)
+        }
+    }
+}
+
+/// 当前最大的共享内存 id
+fn shm_max_id() -> i32 {
+    axmem::SHARED_MEMS
+        .lock()
+        .keys()
+        .next_back()
+        .copied()
+        .unwrap_or(0)
+}
+
+/// 向内核提供关于一段内存的使用建议
+///
+/// # Arguments
+/// * `start` - usize
+/// * `len` - usize
+/// * `advice` - MadviseAdvice
+pub fn syscall_madvise(args: [usize; 6]) -> SyscallResult {
+    let start = VirtAddr::from(args[0]);
+    let len = args[1];
+    let Ok(advice) = MadviseAdvice::try_from(args[2]) else {
+        return Err(SyscallError::EINVAL);
+    };
+    if !start.is_aligned_4k() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let process = current_process();
+    let memory_set_wrapper = process.memory_set.lock();
+    let mut memory = memory_set_wrapper.lock();
+    match advice {
+        MadviseAdvice::MADV_DONTNEED => {
+            memory.madvise_dontneed(start, len)?;
+            flush_tlb(None);
+        }
+        MadviseAdvice::MADV_FREE => {
+            memory.madvise_free(start, len)?;
+            flush_tlb(None);
+        }
+        MadviseAdvice::MADV_WILLNEED => memory.madvise_willneed(start, len)?,
+        MadviseAdvice::MADV_DONTFORK => memory.set_dont_fork(start, len, true)?,
+        MadviseAdvice::MADV_DOFORK => memory.set_dont_fork(start, len, false)?,
+        // 所有映射都是私有的，无法释放后端存储
+        MadviseAdvice::MADV_REMOVE => return Err(SyscallError::EINVAL),
+        // 其余建议仅为提示，不做处理即可
+        _ => {}
+    }
+    Ok(0)
+}
+
+/// 查询一段内存中的页面是否驻留在内存中
+///
+/// # Arguments
+/// * `start` - usize
+/// * `len` - usize
+/// * `vec` - *mut u8，每个页面对应一个字节
+pub fn syscall_mincore(args: [usize; 6]) -> SyscallResult {
+    let start = VirtAddr::from(args[0]);
+    let len = args[1];
+    let vec = args[2] as *mut u8;
+    if !start.is_aligned_4k() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let process = current_process();
+    let residency = process.memory_set.lock().lock().mincore(start, len)?;
+    if residency.is_empty() {
+        return Ok(0);
+    }
+    if process
+        .manual_alloc_range_for_lazy(
+            (vec as usize).into(),
+            (vec as usize + residency.len() - 1).into(),
+        )
+        .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    unsafe { core::slice::from_raw_parts_mut(vec, residency.len()) }.copy_from_slice(&residency);
+    Ok(0)
+}
+
+/// 将一段内存锁定在内存中
+///
+/// # Arguments
+/// * `start` - usize
+/// * `len` - usize
 pub fn syscall_mlock(args: [usize; 6]) -> SyscallResult {
+    let start = VirtAddr::from(args[0]);
+    let len = args[1]
+        .checked_add(start.align_offset_4k())
+        .ok_or(SyscallError::ENOMEM)?;
+    current_process()
+        .memory_set
+        .lock()
+        .lock()
+        .mlock(start.align_down_4k(), len, true)?;
+    Ok(0)
+}
+
+/// 将一段内存锁定在内存中
+///
+/// # Arguments
+/// * `start` - usize
+/// * `len` - usize
+/// * `flags` - usize，MLOCK_ONFAULT 表示页面在被访问时才锁定
+pub fn syscall_mlock2(args: [usize; 6]) -> SyscallResult {
+    let start = VirtAddr::from(args[0]);
+    let len = args[1]
+        .checked_add(start.align_offset_4k())
+        .ok_or(SyscallError::ENOMEM)?;
+    let flags = args[2];
+    if flags & !MLOCK_ONFAULT != 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    current_process().memory_set.lock().lock().mlock(
+        start.align_down_4k(),
+        len,
+        flags & MLOCK_ONFAULT == 0,
+    )?;
+    Ok(0)
+}
+
+/// 解除一段内存的锁定
+///
+/// # Arguments
+/// * `start` - usize
+/// * `len` - usize
+pub fn syscall_munlock(args: [usize; 6]) -> SyscallResult {
+    let start = VirtAddr::from(args[0]);
+    let len = args[1]
+        .checked_add(start.align_offset_4k())
+        .ok_or(SyscallError::ENOMEM)?;
+    current_process()
+        .memory_set
+        .lock()
+        .lock()
+        .munlock(start.align_down_4k(), len)?;
+    Ok(0)
+}
+
+/// 锁定进程的全部内存
+///
+/// # Arguments
+/// * `flags` - MlockAllFlags
+pub fn syscall_mlockall(args: [usize; 6]) -> SyscallResult {
+    let Some(flags) = MlockAllFlags::from_bits(args[0] as u32) else {
+        return Err(SyscallError::EINVAL);
+    };
+    // MCL_ONFAULT 必须和 MCL_CURRENT 或 MCL_FUTURE 一起使用
+    if !flags.intersects(MlockAllFlags::MCL_CURRENT | MlockAllFlags::MCL_FUTURE) {
+        return Err(SyscallError::EINVAL);
+    }
+    current_process().memory_set.lock().lock().mlock_all(
+        flags.contains(MlockAllFlags::MCL_CURRENT),
+        flags.contains(MlockAllFlags::MCL_FUTURE),
+        !flags.contains(MlockAllFlags::MCL_ONFAULT),
+    )?;
+    Ok(0)
+}
+
+/// 解除进程全部内存的锁定
+pub fn syscall_munlockall() -> SyscallResult {
+    current_process().memory_set.lock().lock().munlock_all();
     Ok(0)
 }
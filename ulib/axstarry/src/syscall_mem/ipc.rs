@@ -0,0 +1,357 @@
+//! System V 消息队列与信号量相关的系统调用
+extern crate alloc;
+use alloc::vec::Vec;
+use axhal::time::current_time;
+use axprocess::{
+    current_process,
+    ipc::{
+        self,
+        msg::{self, MSGMAX, MSGMNB},
+        sem::{self, SemBuf, SEMMSL, SEMOPM, SEMVMX},
+    },
+};
+use core::time::Duration;
+
+use crate::{
+    IpcPerm, MsgInfo, MsqidDs, SemInfo, SemidDs, SyscallError, SyscallResult, TimeSecs, GETALL,
+    GETNCNT, GETPID, GETVAL, GETZCNT, IPC_64, IPC_INFO, IPC_RMID, IPC_SET, IPC_STAT, MSG_INFO,
+    MSG_STAT, SEM_INFO, SEM_STAT, SETALL, SETVAL,
+};
+
+/// 消息队列与信号量集的最大数目
+const IPC_MNI: i32 = 32000;
+
+fn to_user_perm(perm: &ipc::IpcPerm) -> IpcPerm {
+    IpcPerm::new(
+        perm.key, perm.uid, perm.gid, perm.cuid, perm.cgid, perm.mode,
+    )
+}
+
+/// 检查用户传入的结构体指针是否可以访问
+fn check_user_ptr<T>(ptr: *const T) -> Result<(), SyscallError> {
+    if ptr.is_null() || current_process().manual_alloc_type_for_lazy(ptr).is_err() {
+        return Err(SyscallError::EFAULT);
+    }
+    Ok(())
+}
+
+/// 检查用户传入的缓冲区是否可以访问，缓冲区超出地址空间时返回 EINVAL
+fn check_user_buf(start: usize, len: usize) -> Result<(), SyscallError> {
+    let end = start.checked_add(len).ok_or(SyscallError::EINVAL)?;
+    if current_process()
+        .manual_alloc_range_for_lazy(start.into(), end.into())
+        .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    Ok(())
+}
+
+/// 获取 key 对应的消息队列，必要时创建，返回队列的 id
+/// # Arguments
+/// * `key` - i32
+/// * `msgflg` - i32
+pub fn syscall_msgget(args: [usize; 6]) -> SyscallResult {
+    let key = args[0] as i32;
+    let flags = args[1] as i32;
+    Ok(msg::msgget(key, flags)? as isize)
+}
+
+/// 向消息队列发送一条消息
+///
+/// msgp 指向的结构体为 `struct msgbuf { long mtype; char mtext[msgsz]; }`
+/// # Arguments
+/// * `msqid` - i32
+/// * `msgp` - *const u8
+/// * `msgsz` - usize
+/// * `msgflg` - i32
+pub fn syscall_msgsnd(args: [usize; 6]) -> SyscallResult {
+    let msqid = args[0] as i32;
+    let msgp = args[1];
+    let msgsz = args[2];
+    let flags = args[3] as i32;
+    if msgsz > MSGMAX {
+        return Err(SyscallError::EINVAL);
+    }
+    let queue = msg::msg_queue(msqid)?;
+    let len = core::mem::size_of::<isize>()
+        .checked_add(msgsz)
+        .ok_or(SyscallError::EINVAL)?;
+    check_user_buf(msgp, len)?;
+    let mtype = unsafe { *(msgp as *const isize) };
+    let data = unsafe {
+        core::slice::from_raw_parts((msgp + core::mem::size_of::<isize>()) as *const u8, msgsz)
+    };
+    queue.send(mtype, data, flags)?;
+    Ok(0)
+}
+
+/// 从消息队列中接收一条消息，返回消息内容的长度
+///
+/// 消息不会超过 MSGMAX，所以更大的 msgsz 按 MSGMAX 处理
+/// # Arguments
+/// * `msqid` - i32
+/// * `msgp` - *mut u8
+/// * `msgsz` - usize
+/// * `msgtyp` - isize
+/// * `msgflg` - i32
+pub fn syscall_msgrcv(args: [usize; 6]) -> SyscallResult {
+    let msqid = args[0] as i32;
+    let msgp = args[1];
+    let msgsz = args[2];
+    let msgtyp = args[3] as isize;
+    let flags = args[4] as i32;
+    if (msgsz as isize) < 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    let msgsz = msgsz.min(MSGMAX);
+    let queue = msg::msg_queue(msqid)?;
+    let len = core::mem::size_of::<isize>()
+        .checked_add(msgsz)
+        .ok_or(SyscallError::EINVAL)?;
+    check_user_buf(msgp, len)?;
+    let (mtype, data) = queue.receive(msgsz, msgtyp, flags)?;
+    unsafe {
+        *(msgp as *mut isize) = mtype;
+        core::slice::from_raw_parts_mut(
+            (msgp + core::mem::size_of::<isize>()) as *mut u8,
+            data.len(),
+        )
+        .copy_from_slice(&data);
+    }
+    Ok(data.len() as isize)
+}
+
+/// 对消息队列进行控制
+/// # Arguments
+/// * `msqid` - i32
+/// * `cmd` - usize
+/// * `buf` - *mut MsqidDs
+pub fn syscall_msgctl(args: [usize; 6]) -> SyscallResult {
+    let msqid = args[0] as i32;
+    let cmd = args[1] & !IPC_64;
+    let buf = args[2];
+    match cmd {
+        IPC_INFO | MSG_INFO => {
+            let buf = buf as *mut MsgInfo;
+            check_user_ptr(buf)?;
+            let (queues, msgs, bytes, max_index) = msg::msg_usage();
+            let mut info = MsgInfo {
+                msgmax: MSGMAX as i32,
+                msgmnb: MSGMNB as i32,
+                msgmni: IPC_MNI,
+                msgssz: 16,
+                msgseg: u16::MAX,
+                ..Default::default()
+            };
+            if cmd == MSG_INFO {
+                info.msgpool = queues as i32;
+                info.msgmap = msgs as i32;
+                info.msgtql = bytes as i32;
+            } else {
+                info.msgpool = (MSGMNB / 1024) as i32 * IPC_MNI;
+                info.msgmap = MSGMNB as i32;
+                info.msgtql = MSGMNB as i32;
+            }
+            unsafe { *buf = info };
+            Ok(max_index as isize)
+        }
+        IPC_STAT | MSG_STAT => {
+            let buf = buf as *mut MsqidDs;
+            check_user_ptr(buf)?;
+            // MSG_STAT 的参数为表中的下标，返回该处的队列的 id
+            let (msqid, queue) = if cmd == MSG_STAT {
+                msg::msg_queue_at(msqid as usize)?
+            } else {
+                (msqid, msg::msg_queue(msqid)?)
+            };
+            let info = queue.stat()?;
+            unsafe {
+                *buf = MsqidDs {
+                    msg_perm: to_user_perm(&info.perm),
+                    msg_stime: info.stime,
+                    msg_rtime: info.rtime,
+                    msg_ctime: info.ctime,
+                    msg_cbytes: info.cbytes,
+                    msg_qnum: info.qnum,
+                    msg_qbytes: info.qbytes,
+                    msg_lspid: info.lspid as i32,
+                    msg_lrpid: info.lrpid as i32,
+                    ..Default::default()
+                };
+            }
+            Ok(if cmd == MSG_STAT { msqid as isize } else { 0 })
+        }
+        IPC_SET => {
+            let buf = buf as *const MsqidDs;
+            check_user_ptr(buf)?;
+            let ds = unsafe { *buf };
+            msg::msg_queue(msqid)?.set(
+                ds.msg_perm.uid,
+                ds.msg_perm.gid,
+                ds.msg_perm.mode,
+                ds.msg_qbytes,
+            )?;
+            Ok(0)
+        }
+        IPC_RMID => {
+            msg::msg_queue_remove(msqid)?;
+            Ok(0)
+        }
+        _ => Err(SyscallError::EINVAL),
+    }
+}
+
+/// 获取 key 对应的信号量集，必要时创建，返回信号量集的 id
+/// # Arguments
+/// * `key` - i32
+/// * `nsems` - usize
+/// * `semflg` - i32
+pub fn syscall_semget(args: [usize; 6]) -> SyscallResult {
+    let key = args[0] as i32;
+    let nsems = args[1] as i32;
+    let flags = args[2] as i32;
+    if nsems < 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    Ok(sem::semget(key, nsems as usize, flags)? as isize)
+}
+
+/// 对信号量集原子地执行一组操作
+/// # Arguments
+/// * `semid` - i32
+/// * `sops` - *const SemBuf
+/// * `nsops` - usize
+pub fn syscall_semop(args: [usize; 6]) -> SyscallResult {
+    syscall_semtimedop([args[0], args[1], args[2], 0, 0, 0])
+}
+
+/// 对信号量集原子地执行一组操作，阻塞时间超过 timeout 时返回 EAGAIN
+/// # Arguments
+/// * `semid` - i32
+/// * `sops` - *const SemBuf
+/// * `nsops` - usize
+/// * `timeout` - *const TimeSecs，为空时表示一直阻塞
+pub fn syscall_semtimedop(args: [usize; 6]) -> SyscallResult {
+    let semid = args[0] as i32;
+    let sops = args[1] as *const SemBuf;
+    let nsops = args[2];
+    let timeout = args[3] as *const TimeSecs;
+    if nsops == 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    if nsops > SEMOPM {
+        return Err(SyscallError::E2BIG);
+    }
+    check_user_buf(sops as usize, nsops * core::mem::size_of::<SemBuf>())?;
+    let ops: Vec<SemBuf> = unsafe { core::slice::from_raw_parts(sops, nsops) }.to_vec();
+    let deadline = if timeout.is_null() {
+        None
+    } else {
+        check_user_ptr(timeout)?;
+        let timeout = unsafe { *timeout };
+        if timeout.tv_nsec >= 1_000_000_000 {
+            return Err(SyscallError::EINVAL);
+        }
+        Some(current_time() + Duration::new(timeout.tv_sec as u64, timeout.tv_nsec as u32))
+    };
+    sem::sem_set(semid)?.semop(semid, &ops, deadline)?;
+    Ok(0)
+}
+
+/// 对信号量集进行控制
+///
+/// 第四个参数为 `union semun`，根据 cmd 的不同为整数值或者指针
+/// # Arguments
+/// * `semid` - i32
+/// * `semnum` - usize
+/// * `cmd` - usize
+/// * `arg` - union semun
+pub fn syscall_semctl(args: [usize; 6]) -> SyscallResult {
+    let semid = args[0] as i32;
+    let semnum = args[1];
+    let cmd = args[2] & !IPC_64;
+    let arg = args[3];
+    match cmd {
+        IPC_INFO | SEM_INFO => {
+            let buf = arg as *mut SemInfo;
+            check_user_ptr(buf)?;
+            let (sets, sems, max_index) = sem::sem_usage();
+            let mut info = SemInfo {
+                semmap: IPC_MNI * SEMMSL as i32,
+                semmni: IPC_MNI,
+                semmns: IPC_MNI * SEMMSL as i32,
+                semmnu: IPC_MNI * SEMMSL as i32,
+                semmsl: SEMMSL as i32,
+                semopm: SEMOPM as i32,
+                semume: SEMOPM as i32,
+                semusz: 20,
+                semvmx: SEMVMX,
+                semaem: SEMVMX,
+            };
+            if cmd == SEM_INFO {
+                info.semusz = sets as i32;
+                info.semaem = sems as i32;
+            }
+            unsafe { *buf = info };
+            Ok(max_index as isize)
+        }
+        IPC_STAT | SEM_STAT => {
+            let buf = arg as *mut SemidDs;
+            check_user_ptr(buf)?;
+            // SEM_STAT 的参数为表中的下标，返回该处的信号量集的 id
+            let (semid, set) = if cmd == SEM_STAT {
+                sem::sem_set_at(semid as usize)?
+            } else {
+                (semid, sem::sem_set(semid)?)
+            };
+            let info = set.stat()?;
+            unsafe {
+                *buf = SemidDs {
+                    sem_perm: to_user_perm(&info.perm),
+                    sem_otime: info.otime,
+                    sem_ctime: info.ctime,
+                    sem_nsems: info.nsems,
+                    ..Default::default()
+                };
+            }
+            Ok(if cmd == SEM_STAT { semid as isize } else { 0 })
+        }
+        IPC_SET => {
+            let buf = arg as *const SemidDs;
+            check_user_ptr(buf)?;
+            let ds = unsafe { *buf };
+            sem::sem_set(semid)?.set(ds.sem_perm.uid, ds.sem_perm.gid, ds.sem_perm.mode)?;
+            Ok(0)
+        }
+        IPC_RMID => {
+            sem::sem_set_remove(semid)?;
+            Ok(0)
+        }
+        GETVAL => Ok(sem::sem_set(semid)?.get_val(semnum)? as isize),
+        GETPID => Ok(sem::sem_set(semid)?.get_pid(semnum)? as isize),
+        GETNCNT => Ok(sem::sem_set(semid)?.get_ncnt(semnum)? as isize),
+        GETZCNT => Ok(sem::sem_set(semid)?.get_zcnt(semnum)? as isize),
+        SETVAL => {
+            sem::sem_set(semid)?.set_val(semid, semnum, arg as i32)?;
+            Ok(0)
+        }
+        GETALL => {
+            let set = sem::sem_set(semid)?;
+            let vals = set.get_all()?;
+            check_user_buf(arg, vals.len() * core::mem::size_of::<u16>())?;
+            unsafe { core::slice::from_raw_parts_mut(arg as *mut u16, vals.len()) }
+                .copy_from_slice(&vals);
+            Ok(0)
+        }
+        SETALL => {
+            let set = sem::sem_set(semid)?;
+            let nsems = set.nsems();
+            check_user_buf(arg, nsems * core::mem::size_of::<u16>())?;
+            let vals = unsafe { core::slice::from_raw_parts(arg as *const u16, nsems) };
+            set.set_all(semid, vals)?;
+            Ok(0)
+        }
+        _ => Err(SyscallError::EINVAL),
+    }
+}
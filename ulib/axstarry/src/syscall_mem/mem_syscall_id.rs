@@ -13,16 +13,32 @@ numeric_enum_macro::numeric_enum! {
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub enum MemSyscallId {
     // mem
+    MSGGET = 186,
+    MSGCTL = 187,
+    MSGRCV = 188,
+    MSGSND = 189,
+    SEMGET = 190,
+    SEMCTL = 191,
+    SEMTIMEDOP = 192,
+    SEMOP = 193,
     SHMGET = 194,
     SHMCTL = 195,
     SHMAT = 196,
+    SHMDT = 197,
     BRK = 214,
     MUNMAP = 215,
     MREMAP = 216,
     MMAP = 222,
     MSYNC = 227,
     MPROTECT = 226,
+    MLOCK = 228,
+    MUNLOCK = 229,
+    MLOCKALL = 230,
+    MUNLOCKALL = 231,
+    MINCORE = 232,
+    MADVISE = 233,
     MEMBARRIER = 283,
+    MLOCK2 = 284,
 }
 }
 
@@ -38,12 +54,27 @@ numeric_enum_macro::numeric_enum! {
         SHMGET = 29,
         SHMCTL = 31,
         SHMAT = 30,
+        SEMGET = 64,
+        SEMOP = 65,
+        SEMCTL = 66,
+        SHMDT = 67,
+        MSGGET = 68,
+        MSGSND = 69,
+        MSGRCV = 70,
+        MSGCTL = 71,
+        SEMTIMEDOP = 220,
         BRK = 12,
         MUNMAP = 11,
         MMAP = 9,
         MSYNC = 26,
         MPROTECT = 10,
         MEMBARRIER = 324,
+        MINCORE = 27,
+        MADVISE = 28,
         MLOCK = 149,
+        MUNLOCK = 150,
+        MLOCKALL = 151,
+        MUNLOCKALL = 152,
+        MLOCK2 = 325,
     }
 }
@@ -4,10 +4,13 @@ use crate::SyscallResult;
 
 mod imp;
 
+mod ipc;
+
 mod mem_syscall_id;
 pub use mem_syscall_id::MemSyscallId::{self, *};
 
 use imp::*;
+use ipc::*;
 /// 与内存相关的系统调用
 pub fn mem_syscall(syscall_id: mem_syscall_id::MemSyscallId, args: [usize; 6]) -> SyscallResult {
     match syscall_id {
@@ -20,10 +23,24 @@ pub fn mem_syscall(syscall_id: mem_syscall_id::MemSyscallId, args: [usize; 6]) -
         MPROTECT => syscall_mprotect(args),
         MEMBARRIER => Ok(0),
         SHMGET => syscall_shmget(args),
-        SHMCTL => Ok(0),
+        SHMCTL => syscall_shmctl(args),
         SHMAT => syscall_shmat(args),
-        #[cfg(target_arch = "x86_64")]
+        SHMDT => syscall_shmdt(args),
+        MSGGET => syscall_msgget(args),
+        MSGSND => syscall_msgsnd(args),
+        MSGRCV => syscall_msgrcv(args),
+        MSGCTL => syscall_msgctl(args),
+        SEMGET => syscall_semget(args),
+        SEMOP => syscall_semop(args),
+        SEMTIMEDOP => syscall_semtimedop(args),
+        SEMCTL => syscall_semctl(args),
+        MADVISE => syscall_madvise(args),
+        MINCORE => syscall_mincore(args),
         MLOCK => syscall_mlock(args),
+        MLOCK2 => syscall_mlock2(args),
+        MUNLOCK => syscall_munlock(args),
+        MLOCKALL => syscall_mlockall(args),
+        MUNLOCKALL => syscall_munlockall(),
         #[allow(unused)]
         _ => {
             panic!("Invalid Syscall Id: {:?}!", syscall_id);
@@ -0,0 +1,304 @@
+//! 网络接口的查询与配置，即 socket 上的 SIOC* ioctl
+extern crate alloc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use axlog::{info, warn};
+use axnet::{add_ip_addr, del_ip_addr, interfaces, InterfaceInfo, IpAddr, IpCidr, Ipv4Addr};
+use axprocess::current_process;
+use num_enum::TryFromPrimitive;
+
+use super::socket::Domain;
+use crate::{SyscallError, SyscallResult};
+
+/// 接口名的最大长度（含结尾的 0）
+pub const IFNAMSIZ: usize = 16;
+
+pub const IFF_UP: u32 = 0x1;
+pub const IFF_BROADCAST: u32 = 0x2;
+pub const IFF_LOOPBACK: u32 = 0x8;
+pub const IFF_RUNNING: u32 = 0x40;
+pub const IFF_MULTICAST: u32 = 0x1000;
+pub const IFF_LOWER_UP: u32 = 0x10000;
+
+/// 以太网的硬件地址类型
+pub const ARPHRD_ETHER: u16 = 1;
+/// 回环接口的硬件地址类型
+pub const ARPHRD_LOOPBACK: u16 = 772;
+
+/// socket 上的网络接口 ioctl
+#[derive(TryFromPrimitive, Debug)]
+#[repr(usize)]
+#[allow(non_camel_case_types)]
+pub enum InterfaceIoctl {
+    SIOCGIFNAME = 0x8910,
+    SIOCGIFCONF = 0x8912,
+    SIOCGIFFLAGS = 0x8913,
+    SIOCSIFFLAGS = 0x8914,
+    SIOCGIFADDR = 0x8915,
+    SIOCSIFADDR = 0x8916,
+    SIOCGIFBRDADDR = 0x8919,
+    SIOCGIFNETMASK = 0x891b,
+    SIOCSIFNETMASK = 0x891c,
+    SIOCGIFMTU = 0x8921,
+    SIOCGIFHWADDR = 0x8927,
+    SIOCGIFINDEX = 0x8933,
+}
+
+/// struct ifreq
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IfReq {
+    /// 接口名
+    pub name: [u8; IFNAMSIZ],
+    /// 地址、标志、编号等，由请求决定
+    pub data: [u8; 24],
+}
+
+/// struct ifconf
+#[repr(C)]
+pub struct IfConf {
+    /// 缓冲区的长度，返回时为写入的长度
+    pub len: i32,
+    /// 存放 ifreq 数组的缓冲区，为空时只返回所需的长度
+    pub buf: *mut IfReq,
+}
+
+/// Linux 的接口编号从 1 开始，axnet 的回环接口编号为 0
+pub fn if_index(info: &InterfaceInfo) -> u32 {
+    info.index as u32 + 1
+}
+
+/// 按 Linux 的接口编号查找接口
+pub fn interface_by_index(if_index: u32) -> Option<InterfaceInfo> {
+    let index = (if_index as usize).checked_sub(1)?;
+    interfaces().into_iter().nth(index)
+}
+
+/// 接口的 IFF_* 标志，接口总是处于开启状态
+pub fn interface_flags(info: &InterfaceInfo) -> u32 {
+    let flags = IFF_UP | IFF_RUNNING | IFF_LOWER_UP;
+    if info.is_loopback {
+        flags | IFF_LOOPBACK
+    } else {
+        flags | IFF_BROADCAST | IFF_MULTICAST
+    }
+}
+
+/// 接口的硬件地址类型
+pub fn interface_type(info: &InterfaceInfo) -> u16 {
+    if info.is_loopback {
+        ARPHRD_LOOPBACK
+    } else {
+        ARPHRD_ETHER
+    }
+}
+
+/// 接口的第一个 IPv4 地址，ifreq 相关的请求只涉及 IPv4
+fn ipv4_cidr(info: &InterfaceInfo) -> Option<IpCidr> {
+    info.ip_addrs
+        .iter()
+        .find(|cidr| matches!(cidr.address(), IpAddr::Ipv4(_)))
+        .copied()
+}
+
+/// 由子网掩码得到前缀长度，掩码不连续时返回 None
+fn prefix_len_of(mask: [u8; 4]) -> Option<u8> {
+    let mask = u32::from_be_bytes(mask);
+    let prefix_len = mask.leading_ones();
+    if mask.checked_shl(prefix_len).unwrap_or(0) != 0 {
+        return None;
+    }
+    Some(prefix_len as u8)
+}
+
+/// 有类地址的默认前缀长度，SIOCSIFADDR 在没有旧地址时使用
+fn classful_prefix_len(addr: [u8; 4]) -> u8 {
+    match addr[0] {
+        0..=127 => 8,
+        128..=191 => 16,
+        _ => 24,
+    }
+}
+
+/// 前缀长度对应的 IPv4 子网掩码
+fn netmask_of(prefix_len: u8) -> [u8; 4] {
+    let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+    mask.to_be_bytes()
+}
+
+/// 将 IPv4 地址以 sockaddr_in 的形式写入 ifreq
+fn write_sockaddr_in(data: &mut [u8; 24], addr: [u8; 4]) {
+    data.fill(0);
+    data[..2].copy_from_slice(&(Domain::AF_INET as u16).to_ne_bytes());
+    data[4..8].copy_from_slice(&addr);
+}
+
+/// 从 ifreq 中读取 sockaddr_in 的地址
+fn read_sockaddr_in(data: &[u8; 24]) -> Result<[u8; 4], SyscallError> {
+    let family = u16::from_ne_bytes([data[0], data[1]]);
+    if family != Domain::AF_INET as u16 {
+        return Err(SyscallError::EINVAL);
+    }
+    Ok([data[4], data[5], data[6], data[7]])
+}
+
+fn name_of(ifreq: &IfReq) -> &str {
+    let len = ifreq.name.iter().position(|&c| c == 0).unwrap_or(IFNAMSIZ);
+    core::str::from_utf8(&ifreq.name[..len]).unwrap_or("")
+}
+
+fn write_name(ifreq: &mut IfReq, name: &str) {
+    let len = name.len().min(IFNAMSIZ - 1);
+    ifreq.name.fill(0);
+    ifreq.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+}
+
+/// 修改接口与路由需要特权
+pub(super) fn check_net_admin() -> Result<(), SyscallError> {
+    if !current_process().credentials.lock().is_privileged() {
+        return Err(SyscallError::EPERM);
+    }
+    Ok(())
+}
+
+/// 重新设置接口的 IPv4 地址，原有的 IPv4 地址被删除
+fn set_ipv4_addr(info: &InterfaceInfo, addr: [u8; 4], prefix_len: u8) -> SyscallResult {
+    for cidr in info.ip_addrs.iter() {
+        if let IpAddr::Ipv4(_) = cidr.address() {
+            let _ = del_ip_addr(&info.name, cidr.address(), cidr.prefix_len());
+        }
+    }
+    let ip = IpAddr::Ipv4(Ipv4Addr(addr));
+    info!("[ioctl()] set {} address {}/{}", info.name, ip, prefix_len);
+    add_ip_addr(&info.name, ip, prefix_len).map_err(|_| SyscallError::EINVAL)?;
+    Ok(0)
+}
+
+impl InterfaceIoctl {
+    /// 执行 ioctl，`argp` 指向 ifreq 或 ifconf
+    pub fn handle(&self, argp: usize) -> SyscallResult {
+        if argp == 0 {
+            return Err(SyscallError::EFAULT);
+        }
+        if let InterfaceIoctl::SIOCGIFCONF = self {
+            return Self::get_conf(unsafe { &mut *(argp as *mut IfConf) });
+        }
+        if matches!(
+            self,
+            InterfaceIoctl::SIOCSIFFLAGS
+                | InterfaceIoctl::SIOCSIFADDR
+                | InterfaceIoctl::SIOCSIFNETMASK
+        ) {
+            check_net_admin()?;
+        }
+        let ifreq = unsafe { &mut *(argp as *mut IfReq) };
+        if let InterfaceIoctl::SIOCGIFNAME = self {
+            let if_index = u32::from_ne_bytes(ifreq.data[..4].try_into().unwrap());
+            let info = interface_by_index(if_index).ok_or(SyscallError::ENODEV)?;
+            write_name(ifreq, &info.name);
+            return Ok(0);
+        }
+
+        let name = name_of(ifreq);
+        let Some(info) = interfaces().into_iter().find(|info| info.name == name) else {
+            return Err(SyscallError::ENODEV);
+        };
+        match self {
+            InterfaceIoctl::SIOCGIFFLAGS => {
+                // ifr_flags 为 short
+                let flags = interface_flags(&info) as u16;
+                ifreq.data[..2].copy_from_slice(&flags.to_ne_bytes());
+            }
+            InterfaceIoctl::SIOCSIFFLAGS => {
+                warn!("[ioctl()] SIOCSIFFLAGS on {} ignored", info.name);
+            }
+            InterfaceIoctl::SIOCGIFADDR => {
+                let cidr = ipv4_cidr(&info).ok_or(SyscallError::EADDRNOTAVAIL)?;
+                let addr = cidr.address().as_bytes().try_into().unwrap();
+                write_sockaddr_in(&mut ifreq.data, addr);
+            }
+            InterfaceIoctl::SIOCSIFADDR => {
+                let addr = read_sockaddr_in(&ifreq.data)?;
+                let prefix_len = match ipv4_cidr(&info) {
+                    Some(cidr) => cidr.prefix_len(),
+                    None => classful_prefix_len(addr),
+                };
+                return set_ipv4_addr(&info, addr, prefix_len);
+            }
+            InterfaceIoctl::SIOCGIFBRDADDR => {
+                let Some(IpCidr::Ipv4(cidr)) = ipv4_cidr(&info) else {
+                    return Err(SyscallError::EADDRNOTAVAIL);
+                };
+                let broadcast = cidr.broadcast().unwrap_or(cidr.address());
+                write_sockaddr_in(&mut ifreq.data, broadcast.0);
+            }
+            InterfaceIoctl::SIOCGIFNETMASK => {
+                let cidr = ipv4_cidr(&info).ok_or(SyscallError::EADDRNOTAVAIL)?;
+                write_sockaddr_in(&mut ifreq.data, netmask_of(cidr.prefix_len()));
+            }
+            InterfaceIoctl::SIOCSIFNETMASK => {
+                let mask = read_sockaddr_in(&ifreq.data)?;
+                let prefix_len = prefix_len_of(mask).ok_or(SyscallError::EINVAL)?;
+                let cidr = ipv4_cidr(&info).ok_or(SyscallError::EADDRNOTAVAIL)?;
+                let addr = cidr.address().as_bytes().try_into().unwrap();
+                return set_ipv4_addr(&info, addr, prefix_len);
+            }
+            InterfaceIoctl::SIOCGIFMTU => {
+                ifreq.data[..4].copy_from_slice(&(info.mtu as i32).to_ne_bytes());
+            }
+            InterfaceIoctl::SIOCGIFHWADDR => {
+                // struct sockaddr 的 sa_family 为硬件地址类型，sa_data 为硬件地址
+                ifreq.data.fill(0);
+                ifreq.data[..2].copy_from_slice(&interface_type(&info).to_ne_bytes());
+                ifreq.data[2..8].copy_from_slice(&info.ether_addr);
+            }
+            InterfaceIoctl::SIOCGIFINDEX => {
+                ifreq.data[..4].copy_from_slice(&if_index(&info).to_ne_bytes());
+            }
+            InterfaceIoctl::SIOCGIFNAME | InterfaceIoctl::SIOCGIFCONF => unreachable!(),
+        }
+        Ok(0)
+    }
+
+    /// SIOCGIFCONF：每个 IPv4 地址对应一个 ifreq
+    fn get_conf(ifconf: &mut IfConf) -> SyscallResult {
+        let reqs: Vec<IfReq> = interfaces()
+            .iter()
+            .filter_map(|info| Some((info, ipv4_cidr(info)?)))
+            .map(|(info, cidr)| {
+                let mut ifreq = IfReq {
+                    name: [0; IFNAMSIZ],
+                    data: [0; 24],
+                };
+                write_name(&mut ifreq, &info.name);
+                write_sockaddr_in(
+                    &mut ifreq.data,
+                    cidr.address().as_bytes().try_into().unwrap(),
+                );
+                ifreq
+            })
+            .collect();
+        if ifconf.buf.is_null() {
+            ifconf.len = (reqs.len() * size_of::<IfReq>()) as i32;
+            return Ok(0);
+        }
+        let count = reqs
+            .len()
+            .min(ifconf.len.max(0) as usize / size_of::<IfReq>());
+        if current_process()
+            .manual_alloc_range_for_lazy(
+                (ifconf.buf as usize).into(),
+                (ifconf.buf as usize + count * size_of::<IfReq>()).into(),
+            )
+            .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+        unsafe {
+            core::ptr::copy_nonoverlapping(reqs.as_ptr(), ifconf.buf, count);
+        }
+        ifconf.len = (count * size_of::<IfReq>()) as i32;
+        Ok(0)
+    }
+}
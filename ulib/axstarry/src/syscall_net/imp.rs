@@ -1,14 +1,17 @@
 //! 相关系统调用的具体实现
 extern crate alloc;
+use super::netlink::*;
+use super::packet::PacketSocket;
 use super::socket::*;
 use core::slice::{from_raw_parts, from_raw_parts_mut};
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec, vec::Vec};
 
-use crate::{SyscallError, SyscallResult};
+use crate::{IoVec, MsgHdr, SyscallError, SyscallResult};
 use axerrno::AxError;
+use axfs::api::FileIO;
 use axlog::{debug, error, info, warn};
-use axnet::{into_core_sockaddr, IpAddr, SocketAddr};
+use axnet::into_core_sockaddr;
 use axprocess::current_process;
 use num_enum::TryFromPrimitive;
 
@@ -21,7 +24,7 @@ pub const SOCKET_TYPE_MASK: usize = 0xFF;
 pub fn syscall_socket(args: [usize; 6]) -> SyscallResult {
     let domain = args[0];
     let s_type = args[1];
-    let _protocol = args[2];
+    let protocol = args[2];
     let Ok(domain) = Domain::try_from(domain) else {
         error!("[socket()] Address Family not supported: {domain}");
         // return ErrorNo::EAFNOSUPPORT as isize;
@@ -31,20 +34,44 @@ pub fn syscall_socket(args: [usize; 6]) -> SyscallResult {
         // return ErrorNo::EINVAL as isize;
         return Err(SyscallError::EINVAL);
     };
-    let mut socket = Socket::new(domain, socket_type);
-    if s_type & SOCK_NONBLOCK != 0 {
-        socket.set_nonblocking(true)
-    }
-    if s_type & SOCK_CLOEXEC != 0 {
-        socket.close_exec = true;
-    }
+    let socket: Arc<dyn FileIO> = if let Domain::AF_NETLINK = domain {
+        if !matches!(socket_type, SocketType::SOCK_RAW | SocketType::SOCK_DGRAM) {
+            return Err(SyscallError::ESOCKTNOSUPPORT);
+        }
+        let mut socket = NetlinkSocket::new(protocol)?;
+        if s_type & SOCK_NONBLOCK != 0 {
+            socket.set_nonblocking(true)
+        }
+        if s_type & SOCK_CLOEXEC != 0 {
+            socket.close_exec = true;
+        }
+        Arc::new(socket)
+    } else if let Domain::AF_PACKET = domain {
+        let mut socket = PacketSocket::new(socket_type, protocol)?;
+        if s_type & SOCK_NONBLOCK != 0 {
+            socket.set_nonblocking(true)
+        }
+        if s_type & SOCK_CLOEXEC != 0 {
+            socket.close_exec = true;
+        }
+        Arc::new(socket)
+    } else {
+        let mut socket = Socket::new(domain, socket_type, protocol)?;
+        if s_type & SOCK_NONBLOCK != 0 {
+            socket.set_nonblocking(true)
+        }
+        if s_type & SOCK_CLOEXEC != 0 {
+            socket.close_exec = true;
+        }
+        Arc::new(socket)
+    };
     let curr = current_process();
     let mut fd_table = curr.fd_manager.fd_table.lock();
     let Ok(fd) = curr.alloc_fd(&mut fd_table) else {
         return Err(SyscallError::EMFILE);
     };
 
-    fd_table[fd] = Some(Arc::new(socket));
+    fd_table[fd] = Some(socket);
 
     debug!("[socket()] create socket {fd}");
 
@@ -58,7 +85,7 @@ pub fn syscall_socket(args: [usize; 6]) -> SyscallResult {
 pub fn syscall_bind(args: [usize; 6]) -> SyscallResult {
     let fd = args[0];
     let addr = args[1] as *const u8;
-    let _addr_len = args[2];
+    let addr_len = args[2];
     let curr = current_process();
 
     let file = match curr.fd_manager.fd_table.lock().get(fd) {
@@ -66,7 +93,26 @@ pub fn syscall_bind(args: [usize; 6]) -> SyscallResult {
         _ => return Err(SyscallError::EBADF),
     };
 
-    let addr = unsafe { socket_address_from(addr) };
+    if let Some(socket) = file.as_any().downcast_ref::<NetlinkSocket>() {
+        let (port_id, groups) = unsafe { netlink_address_from(addr) };
+        info!("[bind()] binding netlink socket {} to port {}", fd, port_id);
+        return socket
+            .bind(port_id, groups)
+            .map(|_| 0)
+            .map_err(SyscallError::from);
+    }
+
+    if let Some(socket) = file.as_any().downcast_ref::<PacketSocket>() {
+        if curr
+            .manual_alloc_range_for_lazy((addr as usize).into(), (addr as usize + addr_len).into())
+            .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+        return unsafe { socket.bind(addr, addr_len) };
+    }
+
+    let addr = unsafe { socket_address_from(addr) }?;
 
     let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
         return Err(SyscallError::ENOTSOCK);
@@ -167,16 +213,29 @@ pub fn syscall_connect(args: [usize; 6]) -> SyscallResult {
         _ => return Err(SyscallError::EBADF),
     };
 
+    // netlink socket 只与内核通信
+    if file.as_any().is::<NetlinkSocket>() {
+        return Ok(0);
+    }
+    if file.as_any().is::<PacketSocket>() {
+        return Err(SyscallError::EOPNOTSUPP);
+    }
+
     let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
         return Err(SyscallError::ENOTSOCK);
     };
 
-    let addr = unsafe { socket_address_from(addr_buf) };
+    let addr = unsafe { socket_address_from(addr_buf) }?;
 
     debug!("[connect()] socket {fd} connecting to {addr:?}");
 
+    // IPV6_V6ONLY 的 socket 连接 IPv4 映射地址
+    if socket.check_address(addr).is_err() {
+        return Err(SyscallError::ENETUNREACH);
+    }
     match socket.connect(addr) {
         Ok(_) => Ok(0),
+        Err(AxError::InvalidInput) => Err(SyscallError::EINVAL),
         Err(AxError::WouldBlock) => Err(SyscallError::EINPROGRESS),
         Err(AxError::Interrupted) => Err(SyscallError::EINTR),
         Err(AxError::AlreadyExists) => Err(SyscallError::EISCONN),
@@ -200,6 +259,15 @@ pub fn syscall_get_sock_name(args: [usize; 6]) -> SyscallResult {
         _ => return Err(SyscallError::EBADF),
     };
 
+    if let Some(socket) = file.as_any().downcast_ref::<NetlinkSocket>() {
+        unsafe { socket.name(addr, addr_len) };
+        return Ok(0);
+    }
+    if let Some(socket) = file.as_any().downcast_ref::<PacketSocket>() {
+        unsafe { socket.name(addr, addr_len) };
+        return Ok(0);
+    }
+
     let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
         return Err(SyscallError::ENOTSOCK);
     };
@@ -284,10 +352,6 @@ pub fn syscall_sendto(args: [usize; 6]) -> SyscallResult {
         _ => return Err(SyscallError::EBADF),
     };
 
-    let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
-        return Err(SyscallError::ENOTSOCK);
-    };
-
     if buf.is_null() {
         return Err(SyscallError::EFAULT);
     }
@@ -302,12 +366,50 @@ pub fn syscall_sendto(args: [usize; 6]) -> SyscallResult {
         return Err(SyscallError::EFAULT);
     };
 
+    send_to(fd, file.as_ref(), buf, addr, addr_len)
+}
+
+/// sendto 与 sendmsg 的共同部分，`buf` 已经过检查
+fn send_to(
+    fd: usize,
+    file: &dyn FileIO,
+    buf: &[u8],
+    addr: *const u8,
+    addr_len: usize,
+) -> SyscallResult {
+    // netlink socket 的目的地址只能是内核，因此被忽略
+    if let Some(socket) = file.as_any().downcast_ref::<NetlinkSocket>() {
+        return socket
+            .send(buf)
+            .map(|len| len as isize)
+            .map_err(SyscallError::from);
+    }
+
+    let curr = current_process();
+    if let Some(socket) = file.as_any().downcast_ref::<PacketSocket>() {
+        if !addr.is_null()
+            && curr
+                .manual_alloc_range_for_lazy(
+                    (addr as usize).into(),
+                    (addr as usize + addr_len).into(),
+                )
+                .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+        return unsafe { socket.send_to(buf, addr, addr_len) };
+    }
+
+    let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
+        return Err(SyscallError::ENOTSOCK);
+    };
+
     let addr = if !addr.is_null() && addr_len != 0 {
         match curr.manual_alloc_range_for_lazy(
             (addr as usize).into(),
             unsafe { addr.add(addr_len) as usize }.into(),
         ) {
-            Ok(_) => Some(unsafe { socket_address_from(addr) }),
+            Ok(_) => Some(unsafe { socket_address_from(addr) }?),
             Err(_) => {
                 error!("[sendto()] addr address {addr:?} invalid");
                 return Err(SyscallError::EFAULT);
@@ -316,16 +418,18 @@ pub fn syscall_sendto(args: [usize; 6]) -> SyscallResult {
     } else {
         None
     };
+    if let Some(addr) = addr {
+        if socket.check_address(addr).is_err() {
+            return Err(SyscallError::ENETUNREACH);
+        }
+    }
+    let unspecified_address = socket.unspecified_address();
     let inner = socket.inner.lock();
     let send_result = match &*inner {
         SocketInner::Udp(s) => {
             // udp socket not bound
             if s.local_addr().is_err() {
-                s.bind(into_core_sockaddr(SocketAddr::new(
-                    IpAddr::v4(0, 0, 0, 0),
-                    0,
-                )))
-                .unwrap();
+                s.bind(into_core_sockaddr(unspecified_address)).unwrap();
             }
             match addr {
                 Some(addr) => s.send_to(buf, into_core_sockaddr(addr)),
@@ -338,6 +442,24 @@ pub fn syscall_sendto(args: [usize; 6]) -> SyscallResult {
                 }
             }
         }
+        SocketInner::Raw(s) => match addr {
+            Some(addr) => s.send_to(buf, into_core_sockaddr(addr)),
+            None => {
+                if s.peer_addr().is_err() {
+                    return Err(SyscallError::EDESTADDRREQ);
+                }
+                s.send(buf)
+            }
+        },
+        SocketInner::Icmp(s) => match addr {
+            Some(addr) => s.send_to(buf, into_core_sockaddr(addr)),
+            None => {
+                if s.peer_addr().is_err() {
+                    return Err(SyscallError::EDESTADDRREQ);
+                }
+                s.send(buf)
+            }
+        },
         SocketInner::Tcp(s) => {
             if addr.is_some() {
                 return Err(SyscallError::EISCONN);
@@ -357,6 +479,8 @@ pub fn syscall_sendto(args: [usize; 6]) -> SyscallResult {
             Ok(len as isize)
         }
         Err(AxError::Interrupted) => Err(SyscallError::EINTR),
+        Err(AxError::WouldBlock) => Err(SyscallError::EAGAIN),
+        Err(AxError::InvalidInput) => Err(SyscallError::EINVAL),
         Err(_) => Err(SyscallError::EPERM),
     }
 }
@@ -372,7 +496,7 @@ pub fn syscall_recvfrom(args: [usize; 6]) -> SyscallResult {
     let fd = args[0];
     let buf = args[1] as *mut u8;
     let len = args[2];
-    let _flags = args[3];
+    let flags = args[3];
     let addr_buf = args[4] as *mut u8;
     let addr_len = args[5] as *mut u32;
     let curr = current_process();
@@ -381,9 +505,6 @@ pub fn syscall_recvfrom(args: [usize; 6]) -> SyscallResult {
         Some(Some(file)) => file.clone(),
         _ => return Err(SyscallError::EBADF),
     };
-    let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
-        return Err(SyscallError::ENOTSOCK);
-    };
 
     if !addr_len.is_null()
         && curr
@@ -409,6 +530,43 @@ pub fn syscall_recvfrom(args: [usize; 6]) -> SyscallResult {
         return Err(SyscallError::EFAULT);
     }
     let buf = unsafe { from_raw_parts_mut(buf, len) };
+    recv_from(fd, file.as_ref(), buf, flags, addr_buf, addr_len)
+}
+
+/// recvfrom 与 recvmsg 的共同部分，`buf` 与地址已经过检查
+fn recv_from(
+    fd: usize,
+    file: &dyn FileIO,
+    buf: &mut [u8],
+    flags: usize,
+    addr_buf: *mut u8,
+    addr_len: *mut u32,
+) -> SyscallResult {
+    if let Some(socket) = file.as_any().downcast_ref::<NetlinkSocket>() {
+        let len = match socket.recv(buf, flags) {
+            Ok(len) => len,
+            Err(AxError::Interrupted) => return Err(SyscallError::EINTR),
+            Err(_) => return Err(SyscallError::EAGAIN),
+        };
+        if !addr_buf.is_null() && !addr_len.is_null() {
+            unsafe { netlink_kernel_address_to(addr_buf, addr_len) };
+        }
+        // 指定 MSG_TRUNC 时返回数据报的实际长度
+        let len = if flags & MSG_TRUNC != 0 {
+            len
+        } else {
+            len.min(buf.len())
+        };
+        return Ok(len as isize);
+    }
+
+    if let Some(socket) = file.as_any().downcast_ref::<PacketSocket>() {
+        return unsafe { socket.recv_from(buf, flags, addr_buf, addr_len) };
+    }
+
+    let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
+        return Err(SyscallError::ENOTSOCK);
+    };
     info!("recv addr: {:?}", socket.name().unwrap());
     match socket.recv_from(buf) {
         Ok((len, addr)) => {
@@ -440,10 +598,6 @@ pub fn syscall_set_sock_opt(args: [usize; 6]) -> SyscallResult {
     let opt_name = args[2];
     let opt_value = args[3] as *const u8;
     let opt_len = args[4] as u32;
-    let Ok(level) = SocketOptionLevel::try_from(level) else {
-        error!("[setsockopt()] level {level} not supported");
-        unimplemented!();
-    };
 
     let curr = current_process();
 
@@ -452,6 +606,30 @@ pub fn syscall_set_sock_opt(args: [usize; 6]) -> SyscallResult {
         _ => return Err(SyscallError::EBADF),
     };
 
+    if file.as_any().is::<NetlinkSocket>() {
+        warn!("[setsockopt()] option {opt_name} of netlink socket ignored");
+        return Ok(0);
+    }
+
+    if let Some(socket) = file.as_any().downcast_ref::<PacketSocket>() {
+        if curr
+            .manual_alloc_range_for_lazy(
+                (opt_value as usize).into(),
+                (opt_value as usize + opt_len as usize).into(),
+            )
+            .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+        let opt = unsafe { from_raw_parts(opt_value, opt_len as usize) };
+        return socket.set_option(level, opt_name, opt);
+    }
+
+    let Ok(level) = SocketOptionLevel::try_from(level) else {
+        error!("[setsockopt()] level {level} not supported");
+        unimplemented!();
+    };
+
     let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
         return Err(SyscallError::ENOTSOCK);
     };
@@ -483,6 +661,22 @@ pub fn syscall_set_sock_opt(args: [usize; 6]) -> SyscallResult {
 
             option.set(socket, opt)
         }
+        SocketOptionLevel::IPv6 => {
+            let Ok(option) = Ipv6Option::try_from(opt_name) else {
+                warn!("[setsockopt()] option {opt_name} not supported in ipv6 level");
+                return Ok(0);
+            };
+
+            option.set(socket, opt)
+        }
+        SocketOptionLevel::Raw => {
+            let Ok(option) = RawOption::try_from(opt_name) else {
+                return Err(SyscallError::ENOPROTOOPT);
+            };
+
+            option.set(socket, opt)
+        }
+        SocketOptionLevel::Packet => Err(SyscallError::ENOPROTOOPT),
     }
 }
 
@@ -498,10 +692,6 @@ pub fn syscall_get_sock_opt(args: [usize; 6]) -> SyscallResult {
     let opt_name = args[2];
     let opt_value = args[3] as *mut u8;
     let opt_len = args[4] as *mut u32;
-    let Ok(level) = SocketOptionLevel::try_from(level) else {
-        error!("[setsockopt()] level {level} not supported");
-        unimplemented!();
-    };
 
     if opt_value.is_null() || opt_len.is_null() {
         return Err(SyscallError::EFAULT);
@@ -514,9 +704,9 @@ pub fn syscall_get_sock_opt(args: [usize; 6]) -> SyscallResult {
         _ => return Err(SyscallError::EBADF),
     };
 
-    let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
-        return Err(SyscallError::ENOTSOCK);
-    };
+    if file.as_any().is::<NetlinkSocket>() {
+        return Err(SyscallError::ENOPROTOOPT);
+    }
 
     if curr
         .manual_alloc_type_for_lazy(opt_len as *const u32)
@@ -539,26 +729,56 @@ pub fn syscall_get_sock_opt(args: [usize; 6]) -> SyscallResult {
         return Err(SyscallError::EFAULT);
     }
 
+    if let Some(socket) = file.as_any().downcast_ref::<PacketSocket>() {
+        return unsafe { socket.get_option(level, opt_name, opt_value, opt_len) };
+    }
+
+    let Ok(level) = SocketOptionLevel::try_from(level) else {
+        error!("[setsockopt()] level {level} not supported");
+        unimplemented!();
+    };
+
+    let Some(socket) = file.as_any().downcast_ref::<Socket>() else {
+        return Err(SyscallError::ENOTSOCK);
+    };
+
     match level {
-        SocketOptionLevel::IP => {}
+        SocketOptionLevel::IP => {
+            if let Ok(option) = IpOption::try_from(opt_name) {
+                return option.get(socket, opt_value, opt_len);
+            }
+        }
         SocketOptionLevel::Socket => {
             let Ok(option) = SocketOption::try_from(opt_name) else {
-                panic!("[setsockopt()] option {opt_name} not supported in socket level");
+                warn!("[getsockopt()] option {opt_name} not supported in socket level");
+                return Err(SyscallError::ENOPROTOOPT);
             };
 
-            option.get(socket, opt_value, opt_len);
+            return option.get(socket, opt_value, opt_len);
         }
         SocketOptionLevel::Tcp => {
             let Ok(option) = TcpSocketOption::try_from(opt_name) else {
-                panic!("[setsockopt()] option {opt_name} not supported in tcp level");
+                warn!("[getsockopt()] option {opt_name} not supported in tcp level");
+                return Err(SyscallError::ENOPROTOOPT);
             };
 
-            if option == TcpSocketOption::TCP_INFO {
+            return option.get(socket, opt_value, opt_len);
+        }
+        SocketOptionLevel::IPv6 => {
+            let Ok(option) = Ipv6Option::try_from(opt_name) else {
                 return Err(SyscallError::ENOPROTOOPT);
-            }
+            };
 
-            option.get(socket, opt_value, opt_len);
+            return option.get(socket, opt_value, opt_len);
         }
+        SocketOptionLevel::Raw => {
+            let Ok(option) = RawOption::try_from(opt_name) else {
+                return Err(SyscallError::ENOPROTOOPT);
+            };
+
+            return option.get(socket, opt_value, opt_len);
+        }
+        SocketOptionLevel::Packet => return Err(SyscallError::ENOPROTOOPT),
     }
 
     Ok(0)
@@ -609,3 +829,108 @@ pub fn syscall_shutdown(args: [usize; 6]) -> SyscallResult {
 pub fn syscall_socketpair() -> SyscallResult {
     Err(SyscallError::EAFNOSUPPORT)
 }
+
+/// 检查 msghdr 中的各个缓冲区是否合法
+fn msg_iovecs<'a>(msg: &MsgHdr) -> Result<&'a [IoVec], SyscallError> {
+    if msg.iov_len == 0 {
+        return Ok(&[]);
+    }
+    let curr = current_process();
+    let iov_start = msg.iov as usize;
+    if curr
+        .manual_alloc_range_for_lazy(
+            iov_start.into(),
+            (iov_start + msg.iov_len * core::mem::size_of::<IoVec>()).into(),
+        )
+        .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    let iovecs = unsafe { from_raw_parts(msg.iov, msg.iov_len) };
+    for io in iovecs.iter().filter(|io| io.len > 0) {
+        let start = io.base as usize;
+        if curr
+            .manual_alloc_range_for_lazy(start.into(), (start + io.len).into())
+            .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+    }
+    Ok(iovecs)
+}
+
+/// 各个缓冲区中的数据合并后发送
+/// # Arguments
+/// * `fd` - usize
+/// * `msg` - *const MsgHdr
+/// * `flags` - usize
+pub fn syscall_sendmsg(args: [usize; 6]) -> SyscallResult {
+    let fd = args[0];
+    let msg = args[1] as *const MsgHdr;
+    let _flags = args[2];
+    let curr = current_process();
+
+    let file = match curr.fd_manager.fd_table.lock().get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return Err(SyscallError::EBADF),
+    };
+
+    if msg.is_null() || curr.manual_alloc_type_for_lazy(msg).is_err() {
+        return Err(SyscallError::EFAULT);
+    }
+    let msg = unsafe { &*msg };
+    let mut buf = Vec::new();
+    for io in msg_iovecs(msg)?.iter().filter(|io| io.len > 0) {
+        buf.extend_from_slice(unsafe { from_raw_parts(io.base, io.len) });
+    }
+    send_to(fd, file.as_ref(), &buf, msg.name, msg.name_len as usize)
+}
+
+/// 接收的数据依次写入各个缓冲区，不支持辅助数据
+/// # Arguments
+/// * `fd` - usize
+/// * `msg` - *mut MsgHdr
+/// * `flags` - usize
+pub fn syscall_recvmsg(args: [usize; 6]) -> SyscallResult {
+    let fd = args[0];
+    let msg = args[1] as *mut MsgHdr;
+    let flags = args[2];
+    let curr = current_process();
+
+    let file = match curr.fd_manager.fd_table.lock().get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return Err(SyscallError::EBADF),
+    };
+
+    if msg.is_null() || curr.manual_alloc_type_for_lazy(msg).is_err() {
+        return Err(SyscallError::EFAULT);
+    }
+    let msg = unsafe { &mut *msg };
+    let iovecs = msg_iovecs(msg)?;
+    let addr_len = if msg.name.is_null() {
+        core::ptr::null_mut()
+    } else {
+        let start = msg.name as usize;
+        if curr
+            .manual_alloc_range_for_lazy(start.into(), (start + msg.name_len as usize).into())
+            .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+        &mut msg.name_len as *mut u32
+    };
+
+    let total_len = iovecs.iter().map(|io| io.len).sum();
+    let mut buf = vec![0u8; total_len];
+    let len = recv_from(fd, file.as_ref(), &mut buf, flags, msg.name, addr_len)? as usize;
+
+    let mut copied = 0;
+    for io in iovecs {
+        let n = io.len.min(len.min(total_len) - copied);
+        unsafe { from_raw_parts_mut(io.base, n) }.copy_from_slice(&buf[copied..copied + n]);
+        copied += n;
+    }
+    msg.control_len = 0;
+    msg.flags = if len > total_len { MSG_TRUNC as i32 } else { 0 };
+    Ok(len as isize)
+}
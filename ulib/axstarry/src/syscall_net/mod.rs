@@ -1,11 +1,17 @@
 //! 提供与 net work 相关的 syscall
 
 use crate::SyscallResult;
+mod iface;
 mod imp;
+mod netlink;
+mod packet;
 
 #[allow(unused)]
 mod socket;
 use imp::*;
+pub use iface::InterfaceIoctl;
+pub use netlink::NetlinkSocket;
+pub use packet::PacketSocket;
 pub use socket::Socket;
 mod net_syscall_id;
 pub use net_syscall_id::NetSyscallId::{self, *};
@@ -23,6 +29,8 @@ pub fn net_syscall(syscall_id: net_syscall_id::NetSyscallId, args: [usize; 6]) -
         // GETPEERNAME => 0,
         SENDTO => syscall_sendto(args),
         RECVFROM => syscall_recvfrom(args),
+        SENDMSG => syscall_sendmsg(args),
+        RECVMSG => syscall_recvmsg(args),
         SETSOCKOPT => syscall_set_sock_opt(args),
         // SETSOCKOPT => 0,
         GETSOCKOPT => syscall_get_sock_opt(args),
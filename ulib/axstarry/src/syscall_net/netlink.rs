@@ -0,0 +1,670 @@
+//! AF_NETLINK socket，目前只支持 NETLINK_ROUTE
+//!
+//! 请求在发送时即由内核处理，回复放入接收队列。接口、地址与路由均来自 axnet。
+extern crate alloc;
+use alloc::{collections::BTreeSet, collections::VecDeque, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use axerrno::{AxError, AxResult};
+use axfs::api::{FileIO, FileIOType, OpenFlags};
+use axlog::{info, warn};
+use axnet::{
+    add_ip_addr, add_route, del_ip_addr, del_route, interfaces, routes, InterfaceInfo, IpAddr,
+    IpCidr, Ipv4Addr, Ipv6Addr,
+};
+use axprocess::current_process;
+use axsync::Mutex;
+use num_enum::TryFromPrimitive;
+
+use super::iface::{
+    check_net_admin, if_index, interface_by_index, interface_flags, interface_type,
+};
+use super::socket::Domain;
+use crate::SyscallError;
+
+/// 路由、接口与地址的管理
+pub const NETLINK_ROUTE: usize = 0;
+
+/// sockaddr_nl 的长度
+pub const SOCKADDR_NL_LEN: usize = 12;
+
+const NLMSG_HDR_LEN: usize = 16;
+const NLMSG_ALIGNTO: usize = 4;
+
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_MULTI: u16 = 0x2;
+const NLM_F_ACK: u16 = 0x4;
+const NLM_F_ROOT: u16 = 0x100;
+const NLM_F_MATCH: u16 = 0x200;
+const NLM_F_DUMP: u16 = NLM_F_ROOT | NLM_F_MATCH;
+
+const IFLA_ADDRESS: u16 = 1;
+const IFLA_BROADCAST: u16 = 2;
+const IFLA_IFNAME: u16 = 3;
+const IFLA_MTU: u16 = 4;
+const IFLA_OPERSTATE: u16 = 16;
+
+/// IFLA_OPERSTATE 的 IF_OPER_UP
+const IF_OPER_UP: u8 = 6;
+
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+const IFA_LABEL: u16 = 3;
+const IFA_BROADCAST: u16 = 4;
+
+const IFA_F_PERMANENT: u8 = 0x80;
+
+const RTA_DST: u16 = 1;
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_PRIORITY: u16 = 6;
+const RTA_TABLE: u16 = 15;
+
+const RT_TABLE_MAIN: u8 = 254;
+const RTPROT_KERNEL: u8 = 2;
+const RTPROT_BOOT: u8 = 3;
+const RT_SCOPE_UNIVERSE: u8 = 0;
+const RT_SCOPE_LINK: u8 = 253;
+const RT_SCOPE_HOST: u8 = 254;
+const RTN_UNICAST: u8 = 1;
+
+/// recvfrom 的 flags
+pub const MSG_PEEK: usize = 0x2;
+pub const MSG_TRUNC: usize = 0x20;
+
+/// NETLINK_ROUTE 的消息类型
+#[derive(TryFromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+#[allow(non_camel_case_types)]
+enum RouteMessageType {
+    RTM_NEWLINK = 16,
+    RTM_GETLINK = 18,
+    RTM_NEWADDR = 20,
+    RTM_DELADDR = 21,
+    RTM_GETADDR = 22,
+    RTM_NEWROUTE = 24,
+    RTM_DELROUTE = 25,
+    RTM_GETROUTE = 26,
+}
+
+/// 已被使用的端口号
+static PORT_IDS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// struct nlmsghdr
+#[derive(Clone, Copy, Debug)]
+struct NlMsgHdr {
+    len: u32,
+    ty: u16,
+    flags: u16,
+    seq: u32,
+}
+
+impl NlMsgHdr {
+    fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < NLMSG_HDR_LEN {
+            return None;
+        }
+        Some(Self {
+            len: u32::from_ne_bytes(buf[0..4].try_into().unwrap()),
+            ty: u16::from_ne_bytes(buf[4..6].try_into().unwrap()),
+            flags: u16::from_ne_bytes(buf[6..8].try_into().unwrap()),
+            seq: u32::from_ne_bytes(buf[8..12].try_into().unwrap()),
+        })
+    }
+}
+
+const fn align(len: usize) -> usize {
+    (len + NLMSG_ALIGNTO - 1) & !(NLMSG_ALIGNTO - 1)
+}
+
+/// 构造一条消息，`body` 为消息头之后的内容
+fn new_message(ty: u16, flags: u16, seq: u32, pid: u32, body: &[u8]) -> Vec<u8> {
+    let len = NLMSG_HDR_LEN + body.len();
+    let mut msg = Vec::with_capacity(align(len));
+    msg.extend_from_slice(&(len as u32).to_ne_bytes());
+    msg.extend_from_slice(&ty.to_ne_bytes());
+    msg.extend_from_slice(&flags.to_ne_bytes());
+    msg.extend_from_slice(&seq.to_ne_bytes());
+    msg.extend_from_slice(&pid.to_ne_bytes());
+    msg.extend_from_slice(body);
+    msg.resize(align(len), 0);
+    msg
+}
+
+/// 在消息体后追加一个属性（struct rtattr）
+fn push_attr(body: &mut Vec<u8>, ty: u16, data: &[u8]) {
+    let len = 4 + data.len();
+    body.extend_from_slice(&(len as u16).to_ne_bytes());
+    body.extend_from_slice(&ty.to_ne_bytes());
+    body.extend_from_slice(data);
+    body.resize(align(body.len()), 0);
+}
+
+/// 遍历属性，得到 (类型, 内容)
+fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    core::iter::from_fn(move || {
+        if buf.len() < 4 {
+            return None;
+        }
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        let ty = u16::from_ne_bytes([buf[2], buf[3]]);
+        if len < 4 || len > buf.len() {
+            return None;
+        }
+        let data = &buf[4..len];
+        buf = &buf[align(len).min(buf.len())..];
+        Some((ty, data))
+    })
+}
+
+fn family_of(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::Ipv4(_) => Domain::AF_INET as u8,
+        IpAddr::Ipv6(_) => Domain::AF_INET6 as u8,
+    }
+}
+
+/// 由属性中的地址与地址族得到 IP 地址
+fn parse_addr(family: u8, data: &[u8]) -> Result<IpAddr, SyscallError> {
+    if family == Domain::AF_INET as u8 && data.len() == 4 {
+        Ok(IpAddr::Ipv4(Ipv4Addr::from_bytes(data)))
+    } else if family == Domain::AF_INET6 as u8 && data.len() == 16 {
+        Ok(IpAddr::Ipv6(Ipv6Addr::from_bytes(data)))
+    } else {
+        Err(SyscallError::EINVAL)
+    }
+}
+
+/// 请求中指定的地址族，AF_UNSPEC 表示不限
+fn family_matches(family: u8, addr: IpAddr) -> bool {
+    family == 0 || family == family_of(addr)
+}
+
+fn link_message(info: &InterfaceInfo) -> Vec<u8> {
+    // struct ifinfomsg
+    let mut body = vec![0u8; 16];
+    body[2..4].copy_from_slice(&interface_type(info).to_ne_bytes());
+    body[4..8].copy_from_slice(&if_index(info).to_ne_bytes());
+    body[8..12].copy_from_slice(&interface_flags(info).to_ne_bytes());
+    let mut name = info.name.as_bytes().to_vec();
+    name.push(0);
+    push_attr(&mut body, IFLA_IFNAME, &name);
+    push_attr(&mut body, IFLA_ADDRESS, &info.ether_addr);
+    let broadcast = if info.is_loopback { [0; 6] } else { [0xff; 6] };
+    push_attr(&mut body, IFLA_BROADCAST, &broadcast);
+    push_attr(&mut body, IFLA_MTU, &(info.mtu as u32).to_ne_bytes());
+    push_attr(&mut body, IFLA_OPERSTATE, &[IF_OPER_UP]);
+    body
+}
+
+fn address_message(info: &InterfaceInfo, cidr: &IpCidr) -> Vec<u8> {
+    let addr = cidr.address();
+    let scope = if info.is_loopback {
+        RT_SCOPE_HOST
+    } else if addr.as_bytes()[..2] == [0xfe, 0x80] {
+        RT_SCOPE_LINK
+    } else {
+        RT_SCOPE_UNIVERSE
+    };
+    // struct ifaddrmsg
+    let mut body = vec![family_of(addr), cidr.prefix_len(), IFA_F_PERMANENT, scope];
+    body.extend_from_slice(&if_index(info).to_ne_bytes());
+    push_attr(&mut body, IFA_ADDRESS, addr.as_bytes());
+    if let IpCidr::Ipv4(cidr) = cidr {
+        push_attr(&mut body, IFA_LOCAL, addr.as_bytes());
+        if let Some(broadcast) = cidr.broadcast() {
+            push_attr(&mut body, IFA_BROADCAST, broadcast.as_bytes());
+        }
+        let mut label = info.name.as_bytes().to_vec();
+        label.push(0);
+        push_attr(&mut body, IFA_LABEL, &label);
+    }
+    body
+}
+
+fn route_message(route: &axnet::Route) -> Vec<u8> {
+    let dest = route.dest;
+    let (protocol, scope) = match route.gateway {
+        Some(_) => (RTPROT_BOOT, RT_SCOPE_UNIVERSE),
+        None => (RTPROT_KERNEL, RT_SCOPE_LINK),
+    };
+    // struct rtmsg
+    let mut body = vec![
+        family_of(dest.address()),
+        dest.prefix_len(),
+        0,
+        0,
+        RT_TABLE_MAIN,
+        protocol,
+        scope,
+        RTN_UNICAST,
+        0,
+        0,
+        0,
+        0,
+    ];
+    push_attr(&mut body, RTA_TABLE, &(RT_TABLE_MAIN as u32).to_ne_bytes());
+    if dest.prefix_len() > 0 {
+        push_attr(&mut body, RTA_DST, dest.address().as_bytes());
+    }
+    if let Some(gateway) = route.gateway {
+        push_attr(&mut body, RTA_GATEWAY, gateway.as_bytes());
+    }
+    push_attr(&mut body, RTA_PRIORITY, &route.metric.to_ne_bytes());
+    push_attr(&mut body, RTA_OIF, &(route.iface as u32 + 1).to_ne_bytes());
+    body
+}
+
+/// 处理 RTM_NEWADDR 与 RTM_DELADDR
+fn change_address(ty: RouteMessageType, body: &[u8]) -> Result<(), SyscallError> {
+    check_net_admin()?;
+    // struct ifaddrmsg
+    if body.len() < 8 {
+        return Err(SyscallError::EINVAL);
+    }
+    let (family, prefix_len) = (body[0], body[1]);
+    let index = u32::from_ne_bytes(body[4..8].try_into().unwrap());
+    let info = interface_by_index(index).ok_or(SyscallError::ENODEV)?;
+    let mut addr = None;
+    for (attr, data) in attrs(&body[8..]) {
+        match attr {
+            IFA_LOCAL => addr = Some(parse_addr(family, data)?),
+            IFA_ADDRESS if addr.is_none() => addr = Some(parse_addr(family, data)?),
+            _ => {}
+        }
+    }
+    let addr = addr.ok_or(SyscallError::EINVAL)?;
+    info!(
+        "[netlink] {:?} {}/{} on {}",
+        ty, addr, prefix_len, info.name
+    );
+    if ty == RouteMessageType::RTM_NEWADDR {
+        add_ip_addr(&info.name, addr, prefix_len).map_err(|e| match e {
+            AxError::AlreadyExists => SyscallError::EEXIST,
+            _ => SyscallError::EINVAL,
+        })
+    } else {
+        del_ip_addr(&info.name, addr, prefix_len).map_err(|_| SyscallError::EADDRNOTAVAIL)
+    }
+}
+
+/// 处理 RTM_NEWROUTE 与 RTM_DELROUTE
+fn change_route(ty: RouteMessageType, body: &[u8]) -> Result<(), SyscallError> {
+    check_net_admin()?;
+    // struct rtmsg
+    if body.len() < 12 {
+        return Err(SyscallError::EINVAL);
+    }
+    let (family, dst_len) = (body[0], body[1]);
+    let mut dest = None;
+    let mut gateway = None;
+    let mut oif = None;
+    for (attr, data) in attrs(&body[12..]) {
+        match attr {
+            RTA_DST => dest = Some(parse_addr(family, data)?),
+            RTA_GATEWAY => gateway = Some(parse_addr(family, data)?),
+            RTA_OIF if data.len() == 4 => {
+                oif = Some(u32::from_ne_bytes(data.try_into().unwrap()));
+            }
+            _ => {}
+        }
+    }
+    let dest = match (dest, family) {
+        (Some(dest), _) => dest,
+        (None, family) if family == Domain::AF_INET as u8 => IpAddr::v4(0, 0, 0, 0),
+        (None, family) if family == Domain::AF_INET6 as u8 => IpAddr::Ipv6(Ipv6Addr::UNSPECIFIED),
+        _ => return Err(SyscallError::EINVAL),
+    };
+    info!(
+        "[netlink] {:?} {}/{} via {:?} dev {:?}",
+        ty, dest, dst_len, gateway, oif
+    );
+    if ty == RouteMessageType::RTM_DELROUTE {
+        return del_route(dest, dst_len).map_err(|_| SyscallError::ESRCH);
+    }
+    // 未指定出口接口时，选择与网关在同一网络的接口
+    let name = match oif {
+        Some(oif) => interface_by_index(oif).ok_or(SyscallError::ENODEV)?.name,
+        None => {
+            let gateway = gateway.ok_or(SyscallError::EINVAL)?;
+            interfaces()
+                .into_iter()
+                .find(|info| {
+                    info.ip_addrs
+                        .iter()
+                        .any(|cidr| cidr.contains_addr(&gateway))
+                })
+                .ok_or(SyscallError::ENETUNREACH)?
+                .name
+        }
+    };
+    add_route(dest, dst_len, gateway, &name).map_err(|e| match e {
+        AxError::AlreadyExists => SyscallError::EEXIST,
+        _ => SyscallError::EINVAL,
+    })
+}
+
+/// 从 sockaddr_nl 中读取端口号与多播组
+///
+/// # Safety
+///
+/// `addr` 必须指向合法的 sockaddr_nl
+pub unsafe fn netlink_address_from(addr: *const u8) -> (u32, u32) {
+    let port_id = *(addr.add(4) as *const u32);
+    let groups = *(addr.add(8) as *const u32);
+    (port_id, groups)
+}
+
+/// 写入内核的 sockaddr_nl，即消息的来源
+///
+/// # Safety
+///
+/// `buf` 与 `buf_len` 必须是合法的用户地址
+pub unsafe fn netlink_kernel_address_to(buf: *mut u8, buf_len: *mut u32) {
+    write_address(buf, buf_len, 0, 0);
+}
+
+unsafe fn write_address(buf: *mut u8, buf_len: *mut u32, port_id: u32, groups: u32) {
+    let mut raw = [0u8; SOCKADDR_NL_LEN];
+    raw[..2].copy_from_slice(&(Domain::AF_NETLINK as u16).to_ne_bytes());
+    raw[4..8].copy_from_slice(&port_id.to_ne_bytes());
+    raw[8..12].copy_from_slice(&groups.to_ne_bytes());
+    let len = (*buf_len as usize).min(SOCKADDR_NL_LEN);
+    core::ptr::copy_nonoverlapping(raw.as_ptr(), buf, len);
+    *buf_len = SOCKADDR_NL_LEN as u32;
+}
+
+/// NETLINK_ROUTE socket
+pub struct NetlinkSocket {
+    /// 绑定的端口号，0 表示未绑定
+    port_id: AtomicU32,
+    /// 加入的多播组，目前不会产生多播消息
+    groups: AtomicU32,
+    recv_queue: Mutex<VecDeque<Vec<u8>>>,
+    nonblocking: AtomicBool,
+    /// Whether the socket is set to close on exec
+    pub close_exec: bool,
+}
+
+impl NetlinkSocket {
+    /// 创建 socket，只支持 NETLINK_ROUTE
+    pub fn new(protocol: usize) -> Result<Self, SyscallError> {
+        if protocol != NETLINK_ROUTE {
+            return Err(SyscallError::EPROTONOSUPPORT);
+        }
+        Ok(Self {
+            port_id: AtomicU32::new(0),
+            groups: AtomicU32::new(0),
+            recv_queue: Mutex::new(VecDeque::new()),
+            nonblocking: AtomicBool::new(false),
+            close_exec: false,
+        })
+    }
+
+    /// 设置非阻塞模式
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+    }
+
+    /// 绑定端口号，为 0 时由内核分配，优先使用进程号
+    pub fn bind(&self, port_id: u32, groups: u32) -> AxResult {
+        self.groups.store(groups, Ordering::Release);
+        let mut port_ids = PORT_IDS.lock();
+        let old = self.port_id.load(Ordering::Acquire);
+        if old != 0 {
+            return if port_id == 0 || port_id == old {
+                Ok(())
+            } else {
+                Err(AxError::InvalidInput)
+            };
+        }
+        let port_id = if port_id != 0 {
+            if port_ids.contains(&port_id) {
+                return Err(AxError::AddrInUse);
+            }
+            port_id
+        } else {
+            let pid = current_process().pid() as u32;
+            // 进程号已被使用时，像 Linux 一样分配负数的端口号
+            let mut candidate = pid;
+            let mut next = u32::MAX;
+            while port_ids.contains(&candidate) {
+                candidate = next;
+                next -= 1;
+            }
+            candidate
+        };
+        port_ids.insert(port_id);
+        self.port_id.store(port_id, Ordering::Release);
+        Ok(())
+    }
+
+    /// 写入 sockaddr_nl
+    ///
+    /// # Safety
+    ///
+    /// `buf` 与 `buf_len` 必须是合法的用户地址
+    pub unsafe fn name(&self, buf: *mut u8, buf_len: *mut u32) {
+        write_address(
+            buf,
+            buf_len,
+            self.port_id.load(Ordering::Acquire),
+            self.groups.load(Ordering::Acquire),
+        );
+    }
+
+    /// 发送消息到内核，回复进入接收队列
+    pub fn send(&self, mut buf: &[u8]) -> AxResult<usize> {
+        let len = buf.len();
+        // 未绑定的 socket 在发送时自动绑定
+        self.bind(0, self.groups.load(Ordering::Acquire))?;
+        while let Some(hdr) = NlMsgHdr::parse(buf) {
+            let msg_len = hdr.len as usize;
+            if msg_len < NLMSG_HDR_LEN || msg_len > buf.len() {
+                break;
+            }
+            self.handle_message(hdr, &buf[..msg_len]);
+            buf = &buf[align(msg_len).min(buf.len())..];
+        }
+        Ok(len)
+    }
+
+    /// 处理一条请求
+    fn handle_message(&self, hdr: NlMsgHdr, msg: &[u8]) {
+        if hdr.flags & NLM_F_REQUEST == 0 {
+            return;
+        }
+        let body = &msg[NLMSG_HDR_LEN..];
+        let Ok(ty) = RouteMessageType::try_from(hdr.ty) else {
+            warn!("[netlink] message type {} not supported", hdr.ty);
+            self.reply_error(hdr, msg, SyscallError::EOPNOTSUPP);
+            return;
+        };
+        let family = body.first().copied().unwrap_or(0);
+        let result = match ty {
+            RouteMessageType::RTM_GETLINK => {
+                let index = body
+                    .get(4..8)
+                    .map_or(0, |index| u32::from_ne_bytes(index.try_into().unwrap()));
+                let links: Vec<_> = interfaces()
+                    .iter()
+                    .filter(|info| hdr.flags & NLM_F_DUMP != 0 || if_index(info) == index)
+                    .map(link_message)
+                    .collect();
+                if links.is_empty() {
+                    Err(SyscallError::ENODEV)
+                } else {
+                    self.reply(hdr, RouteMessageType::RTM_NEWLINK, links);
+                    return;
+                }
+            }
+            RouteMessageType::RTM_GETADDR => {
+                let mut addrs = Vec::new();
+                for info in interfaces() {
+                    for cidr in info.ip_addrs.iter() {
+                        if family_matches(family, cidr.address()) {
+                            addrs.push(address_message(&info, cidr));
+                        }
+                    }
+                }
+                self.reply(hdr, RouteMessageType::RTM_NEWADDR, addrs);
+                return;
+            }
+            RouteMessageType::RTM_GETROUTE => {
+                let routes = routes()
+                    .iter()
+                    .filter(|route| family_matches(family, route.dest.address()))
+                    .map(route_message)
+                    .collect();
+                self.reply(hdr, RouteMessageType::RTM_NEWROUTE, routes);
+                return;
+            }
+            RouteMessageType::RTM_NEWADDR | RouteMessageType::RTM_DELADDR => {
+                change_address(ty, body)
+            }
+            RouteMessageType::RTM_NEWROUTE | RouteMessageType::RTM_DELROUTE => {
+                change_route(ty, body)
+            }
+            RouteMessageType::RTM_NEWLINK => Err(SyscallError::EOPNOTSUPP),
+        };
+        match result {
+            Ok(()) if hdr.flags & NLM_F_ACK != 0 => self.reply_ack(hdr, msg),
+            Ok(()) => {}
+            Err(e) => self.reply_error(hdr, msg, e),
+        }
+    }
+
+    /// 回复请求的结果，dump 请求以 NLMSG_DONE 结束
+    fn reply(&self, hdr: NlMsgHdr, ty: RouteMessageType, bodies: Vec<Vec<u8>>) {
+        let pid = self.port_id.load(Ordering::Acquire);
+        let mut queue = self.recv_queue.lock();
+        if hdr.flags & NLM_F_DUMP == 0 {
+            for body in bodies {
+                queue.push_back(new_message(ty as u16, 0, hdr.seq, pid, &body));
+            }
+            return;
+        }
+        let mut datagram = Vec::new();
+        for body in bodies {
+            datagram.extend(new_message(ty as u16, NLM_F_MULTI, hdr.seq, pid, &body));
+        }
+        datagram.extend(new_message(
+            NLMSG_DONE,
+            NLM_F_MULTI,
+            hdr.seq,
+            pid,
+            &0i32.to_ne_bytes(),
+        ));
+        queue.push_back(datagram);
+    }
+
+    /// 回复确认，即错误码为 0 的 NLMSG_ERROR，附带原请求的消息头
+    fn reply_ack(&self, hdr: NlMsgHdr, msg: &[u8]) {
+        let mut body = 0i32.to_ne_bytes().to_vec();
+        body.extend_from_slice(&msg[..NLMSG_HDR_LEN]);
+        self.push_error(hdr, body);
+    }
+
+    /// 回复错误，附带原请求
+    fn reply_error(&self, hdr: NlMsgHdr, msg: &[u8], error: SyscallError) {
+        let mut body = (-error.code()).to_ne_bytes().to_vec();
+        body.extend_from_slice(msg);
+        self.push_error(hdr, body);
+    }
+
+    fn push_error(&self, hdr: NlMsgHdr, body: Vec<u8>) {
+        let pid = self.port_id.load(Ordering::Acquire);
+        self.recv_queue
+            .lock()
+            .push_back(new_message(NLMSG_ERROR, 0, hdr.seq, pid, &body));
+    }
+
+    /// 接收一个数据报，返回数据报的实际长度
+    ///
+    /// 缓冲区不足时数据报被截断，指定 MSG_PEEK 时数据报仍留在队列中
+    pub fn recv(&self, buf: &mut [u8], flags: usize) -> AxResult<usize> {
+        loop {
+            let mut queue = self.recv_queue.lock();
+            if let Some(datagram) = queue.front() {
+                let len = datagram.len();
+                let copy_len = len.min(buf.len());
+                buf[..copy_len].copy_from_slice(&datagram[..copy_len]);
+                if flags & MSG_PEEK == 0 {
+                    queue.pop_front();
+                }
+                return Ok(len);
+            }
+            drop(queue);
+            if self.nonblocking.load(Ordering::Acquire) {
+                return Err(AxError::WouldBlock);
+            }
+            #[cfg(feature = "signal")]
+            if current_process().have_signals().is_some() {
+                return Err(AxError::Interrupted);
+            }
+            axtask::yield_now();
+        }
+    }
+}
+
+impl Drop for NetlinkSocket {
+    fn drop(&mut self) {
+        let port_id = self.port_id.load(Ordering::Acquire);
+        if port_id != 0 {
+            PORT_IDS.lock().remove(&port_id);
+        }
+    }
+}
+
+impl FileIO for NetlinkSocket {
+    fn read(&self, buf: &mut [u8]) -> AxResult<usize> {
+        self.recv(buf, 0).map(|len| len.min(buf.len()))
+    }
+
+    fn write(&self, buf: &[u8]) -> AxResult<usize> {
+        self.send(buf)
+    }
+
+    fn readable(&self) -> bool {
+        !self.recv_queue.lock().is_empty()
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn executable(&self) -> bool {
+        false
+    }
+
+    fn get_type(&self) -> FileIOType {
+        FileIOType::Socket
+    }
+
+    fn get_status(&self) -> OpenFlags {
+        let mut flags = OpenFlags::default();
+        if self.close_exec {
+            flags |= OpenFlags::CLOEXEC;
+        }
+        if self.nonblocking.load(Ordering::Acquire) {
+            flags |= OpenFlags::NON_BLOCK;
+        }
+        flags
+    }
+
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        self.set_nonblocking(flags.contains(OpenFlags::NON_BLOCK));
+        true
+    }
+
+    fn ready_to_read(&self) -> bool {
+        self.readable()
+    }
+
+    fn ready_to_write(&self) -> bool {
+        self.writable()
+    }
+}
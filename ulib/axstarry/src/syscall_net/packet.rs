@@ -0,0 +1,412 @@
+//! AF_PACKET socket，收发链路层的帧
+//!
+//! SOCK_RAW 收发包含以太网头部的完整帧，SOCK_DGRAM 只收发负载，链路层的信息在 sockaddr_ll 中。
+//! 帧在协议栈处理之前被复制，因此不影响协议栈。
+extern crate alloc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::copy_nonoverlapping;
+
+use axerrno::{AxError, AxResult};
+use axfs::api::{FileIO, FileIOType, OpenFlags};
+use axlog::{info, warn};
+use axnet::{interfaces, BpfInsn, BpfProgram, PacketAddr, BPF_MAXINSNS};
+use axprocess::current_process;
+use axsync::Mutex;
+use num_enum::TryFromPrimitive;
+
+use super::iface::interface_by_index;
+use super::netlink::{MSG_PEEK, MSG_TRUNC};
+use super::socket::{Domain, SocketOption, SocketOptionLevel, SocketType};
+use crate::{SyscallError, SyscallResult, TimeVal};
+
+/// sockaddr_ll 的长度
+pub const SOCKADDR_LL_LEN: usize = 20;
+/// 以太网头部的长度
+const ETH_HLEN: usize = 14;
+/// 以太网地址的长度
+const ETH_ALEN: usize = 6;
+
+/// 附加 BPF 过滤程序，选项值为 struct sock_fprog
+pub const SO_ATTACH_FILTER: usize = 26;
+/// 移除 BPF 过滤程序
+pub const SO_DETACH_FILTER: usize = 27;
+
+/// SOL_PACKET 层的选项
+#[derive(TryFromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+#[allow(non_camel_case_types)]
+pub enum PacketOption {
+    PACKET_ADD_MEMBERSHIP = 1,
+    PACKET_DROP_MEMBERSHIP = 2,
+    PACKET_STATISTICS = 6,
+}
+
+/// struct sock_fprog
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SockFprog {
+    len: u16,
+    filter: *const BpfInsn,
+}
+
+/// struct tpacket_stats
+#[repr(C)]
+struct TpacketStats {
+    packets: u32,
+    drops: u32,
+}
+
+/// sockaddr_ll 中的地址
+struct LinkAddress {
+    /// 以太网协议号，已转换为主机字节序
+    protocol: u16,
+    /// Linux 的接口编号，0 表示不限
+    if_index: u32,
+    /// 硬件地址，长度不足 6 时为 None
+    hw_addr: Option<[u8; ETH_ALEN]>,
+}
+
+/// 读取 sockaddr_ll
+///
+/// # Safety
+///
+/// `addr` 必须指向长度为 `addr_len` 的合法内存
+unsafe fn link_address_from(addr: *const u8, addr_len: usize) -> Result<LinkAddress, SyscallError> {
+    if addr.is_null() || addr_len < 12 {
+        return Err(SyscallError::EINVAL);
+    }
+    let raw = core::slice::from_raw_parts(addr, addr_len.min(SOCKADDR_LL_LEN));
+    if u16::from_ne_bytes([raw[0], raw[1]]) != Domain::AF_PACKET as u16 {
+        return Err(SyscallError::EINVAL);
+    }
+    let halen = raw[11] as usize;
+    let hw_addr = if halen >= ETH_ALEN && raw.len() >= 12 + ETH_ALEN {
+        Some(raw[12..12 + ETH_ALEN].try_into().unwrap())
+    } else {
+        None
+    };
+    Ok(LinkAddress {
+        protocol: u16::from_be_bytes([raw[2], raw[3]]),
+        if_index: u32::from_ne_bytes(raw[4..8].try_into().unwrap()),
+        hw_addr,
+    })
+}
+
+/// 写入 sockaddr_ll
+///
+/// # Safety
+///
+/// `buf` 与 `buf_len` 必须是合法的用户地址
+unsafe fn link_address_to(addr: &PacketAddr, if_index: u32, buf: *mut u8, buf_len: *mut u32) {
+    let mut raw = [0u8; SOCKADDR_LL_LEN];
+    raw[..2].copy_from_slice(&(Domain::AF_PACKET as u16).to_ne_bytes());
+    raw[2..4].copy_from_slice(&addr.protocol.to_be_bytes());
+    raw[4..8].copy_from_slice(&if_index.to_ne_bytes());
+    raw[8..10].copy_from_slice(&addr.hatype.to_ne_bytes());
+    raw[10] = addr.pkttype as u8;
+    raw[11] = ETH_ALEN as u8;
+    raw[12..12 + ETH_ALEN].copy_from_slice(&addr.hw_addr);
+    let len = (*buf_len as usize).min(SOCKADDR_LL_LEN);
+    copy_nonoverlapping(raw.as_ptr(), buf, len);
+    *buf_len = SOCKADDR_LL_LEN as u32;
+}
+
+/// 由 Linux 的接口编号得到 axnet 的接口编号与 MTU
+fn interface_of(if_index: u32) -> Result<(usize, usize), SyscallError> {
+    let info = interface_by_index(if_index).ok_or(SyscallError::ENXIO)?;
+    Ok((info.index, info.mtu))
+}
+
+/// AF_PACKET socket
+pub struct PacketSocket {
+    inner: axnet::PacketSocket,
+    recv_timeout: Mutex<Option<TimeVal>>,
+    /// Whether the socket is set to close on exec
+    pub close_exec: bool,
+}
+
+impl PacketSocket {
+    /// 创建 socket，`protocol` 为网络字节序的以太网协议号，0 表示在绑定前不接收
+    pub fn new(socket_type: SocketType, protocol: usize) -> Result<Self, SyscallError> {
+        let cooked = match socket_type {
+            SocketType::SOCK_RAW => false,
+            SocketType::SOCK_DGRAM => true,
+            _ => return Err(SyscallError::ESOCKTNOSUPPORT),
+        };
+        let protocol = u16::from_be(protocol as u16);
+        Ok(Self {
+            inner: axnet::PacketSocket::new(cooked, protocol),
+            recv_timeout: Mutex::new(None),
+            close_exec: false,
+        })
+    }
+
+    /// 设置非阻塞模式
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.inner.set_nonblocking(nonblocking);
+    }
+
+    /// 绑定到 sockaddr_ll 中的接口与协议，接口编号为 0 时接收所有接口的帧
+    ///
+    /// # Safety
+    ///
+    /// `addr` 必须指向长度为 `addr_len` 的合法内存
+    pub unsafe fn bind(&self, addr: *const u8, addr_len: usize) -> SyscallResult {
+        let addr = link_address_from(addr, addr_len)?;
+        let iface = match addr.if_index {
+            0 => None,
+            if_index => Some(interface_of(if_index).map_err(|_| SyscallError::ENODEV)?.0),
+        };
+        info!(
+            "[bind()] packet socket bound to protocol {:#06x} on {:?}",
+            addr.protocol, iface
+        );
+        self.inner
+            .bind(addr.protocol, iface)
+            .map(|_| 0)
+            .map_err(|_| SyscallError::ENODEV)
+    }
+
+    /// 写入绑定的 sockaddr_ll
+    ///
+    /// # Safety
+    ///
+    /// `buf` 与 `buf_len` 必须是合法的用户地址
+    pub unsafe fn name(&self, buf: *mut u8, buf_len: *mut u32) {
+        let (protocol, iface) = self.inner.local_addr();
+        let mut addr = PacketAddr::new(protocol, iface.unwrap_or(0), [0; ETH_ALEN]);
+        if let Some(info) = iface.and_then(|iface| interfaces().into_iter().nth(iface)) {
+            addr.hw_addr = info.ether_addr;
+        }
+        // 未绑定接口时 sll_ifindex 为 0
+        let if_index = iface.map_or(0, |iface| iface as u32 + 1);
+        link_address_to(&addr, if_index, buf, buf_len);
+    }
+
+    /// 发送一个帧，未指定地址时使用绑定的接口，此时只能发送完整的帧
+    ///
+    /// # Safety
+    ///
+    /// `addr` 为空或指向长度为 `addr_len` 的合法内存
+    pub unsafe fn send_to(&self, buf: &[u8], addr: *const u8, addr_len: usize) -> SyscallResult {
+        let cooked = self.inner.is_cooked();
+        let (bound_protocol, bound_iface) = self.inner.local_addr();
+        let (protocol, iface, hw_addr) = if !addr.is_null() && addr_len != 0 {
+            let addr = link_address_from(addr, addr_len)?;
+            let iface = match addr.if_index {
+                0 => bound_iface.map(|iface| iface as u32 + 1).unwrap_or(0),
+                if_index => if_index,
+            };
+            (addr.protocol, iface, addr.hw_addr)
+        } else {
+            if cooked {
+                return Err(SyscallError::EDESTADDRREQ);
+            }
+            let iface = bound_iface.map(|iface| iface as u32 + 1).unwrap_or(0);
+            (bound_protocol, iface, None)
+        };
+        let (iface, mtu) = interface_of(iface)?;
+        let hw_addr = match (cooked, hw_addr) {
+            (true, None) => return Err(SyscallError::EINVAL),
+            (_, hw_addr) => hw_addr.unwrap_or([0; ETH_ALEN]),
+        };
+        let max_len = if cooked { mtu } else { ETH_HLEN + mtu };
+        if buf.len() > max_len {
+            return Err(SyscallError::EMSGSIZE);
+        }
+        match self
+            .inner
+            .send_to(buf, &PacketAddr::new(protocol, iface, hw_addr))
+        {
+            Ok(len) => Ok(len as isize),
+            Err(AxError::Interrupted) => Err(SyscallError::EINTR),
+            Err(AxError::WouldBlock) => Err(SyscallError::EAGAIN),
+            Err(AxError::InvalidInput) => Err(SyscallError::EINVAL),
+            Err(AxError::NotFound) => Err(SyscallError::ENXIO),
+            Err(_) => Err(SyscallError::ENOBUFS),
+        }
+    }
+
+    /// 接收一个帧，返回帧的实际长度，缓冲区不足时帧被截断
+    fn recv_impl(&self, buf: &mut [u8], peek: bool) -> AxResult<(usize, PacketAddr)> {
+        match *self.recv_timeout.lock() {
+            Some(time) => self
+                .inner
+                .recv_from_timeout(buf, peek, time.turn_to_ticks()),
+            None => self.inner.recv_from(buf, peek),
+        }
+    }
+
+    /// 接收一个帧并写入来源的 sockaddr_ll，指定 MSG_TRUNC 时返回帧的实际长度
+    ///
+    /// # Safety
+    ///
+    /// `addr_buf` 与 `addr_len` 为空或是合法的用户地址
+    pub unsafe fn recv_from(
+        &self,
+        buf: &mut [u8],
+        flags: usize,
+        addr_buf: *mut u8,
+        addr_len: *mut u32,
+    ) -> SyscallResult {
+        let (len, addr) = match self.recv_impl(buf, flags & MSG_PEEK != 0) {
+            Ok(result) => result,
+            Err(AxError::Interrupted) => return Err(SyscallError::EINTR),
+            Err(_) => return Err(SyscallError::EAGAIN),
+        };
+        if !addr_buf.is_null() && !addr_len.is_null() {
+            link_address_to(&addr, addr.iface as u32 + 1, addr_buf, addr_len);
+        }
+        let len = if flags & MSG_TRUNC != 0 {
+            len
+        } else {
+            len.min(buf.len())
+        };
+        Ok(len as isize)
+    }
+
+    /// setsockopt，支持 BPF 过滤程序与接收超时
+    pub fn set_option(&self, level: usize, opt_name: usize, opt: &[u8]) -> SyscallResult {
+        match SocketOptionLevel::try_from(level) {
+            Ok(SocketOptionLevel::Socket) => match opt_name {
+                SO_ATTACH_FILTER => self.attach_filter(opt),
+                SO_DETACH_FILTER => self
+                    .inner
+                    .detach_filter()
+                    .map(|_| 0)
+                    .map_err(|_| SyscallError::ENOENT),
+                _ if opt_name == SocketOption::SO_RCVTIMEO as usize => {
+                    if opt.len() < size_of::<TimeVal>() {
+                        return Err(SyscallError::EINVAL);
+                    }
+                    let timeout = unsafe { *(opt.as_ptr() as *const TimeVal) };
+                    *self.recv_timeout.lock() = if timeout.sec == 0 && timeout.usec == 0 {
+                        None
+                    } else {
+                        Some(timeout)
+                    };
+                    Ok(0)
+                }
+                _ => {
+                    warn!("[setsockopt()] option {opt_name} of packet socket ignored");
+                    Ok(0)
+                }
+            },
+            Ok(SocketOptionLevel::Packet) => match PacketOption::try_from(opt_name) {
+                // 网卡总是收到发往本机、广播与多播地址的帧，不支持混杂模式
+                Ok(PacketOption::PACKET_ADD_MEMBERSHIP | PacketOption::PACKET_DROP_MEMBERSHIP) => {
+                    warn!("[setsockopt()] packet membership ignored");
+                    Ok(0)
+                }
+                _ => Err(SyscallError::ENOPROTOOPT),
+            },
+            _ => Err(SyscallError::ENOPROTOOPT),
+        }
+    }
+
+    /// getsockopt，支持 PACKET_STATISTICS，读取后计数被清零
+    ///
+    /// # Safety
+    ///
+    /// `opt_value` 与 `opt_len` 必须是合法的用户地址
+    pub unsafe fn get_option(
+        &self,
+        level: usize,
+        opt_name: usize,
+        opt_value: *mut u8,
+        opt_len: *mut u32,
+    ) -> SyscallResult {
+        let (Ok(SocketOptionLevel::Packet), Ok(PacketOption::PACKET_STATISTICS)) = (
+            SocketOptionLevel::try_from(level),
+            PacketOption::try_from(opt_name),
+        ) else {
+            return Err(SyscallError::ENOPROTOOPT);
+        };
+        let stats = self.inner.take_stats();
+        let stats = TpacketStats {
+            packets: stats.packets,
+            drops: stats.drops,
+        };
+        let len = (*opt_len as usize).min(size_of::<TpacketStats>());
+        copy_nonoverlapping(&stats as *const TpacketStats as *const u8, opt_value, len);
+        *opt_len = len as u32;
+        Ok(0)
+    }
+
+    /// SO_ATTACH_FILTER，检查并替换过滤程序
+    fn attach_filter(&self, opt: &[u8]) -> SyscallResult {
+        if opt.len() < size_of::<SockFprog>() {
+            return Err(SyscallError::EINVAL);
+        }
+        let fprog = unsafe { *(opt.as_ptr() as *const SockFprog) };
+        let len = fprog.len as usize;
+        if len == 0 || len > BPF_MAXINSNS {
+            return Err(SyscallError::EINVAL);
+        }
+        let start = fprog.filter as usize;
+        if current_process()
+            .manual_alloc_range_for_lazy(start.into(), (start + len * size_of::<BpfInsn>()).into())
+            .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+        let insns: Vec<BpfInsn> =
+            unsafe { core::slice::from_raw_parts(fprog.filter, len) }.to_vec();
+        let program = BpfProgram::new(insns).map_err(|_| SyscallError::EINVAL)?;
+        info!("[setsockopt()] attach a BPF program of {len} instructions");
+        self.inner.attach_filter(program);
+        Ok(0)
+    }
+}
+
+impl FileIO for PacketSocket {
+    fn read(&self, buf: &mut [u8]) -> AxResult<usize> {
+        self.recv_impl(buf, false)
+            .map(|(len, _)| len.min(buf.len()))
+    }
+
+    fn write(&self, buf: &[u8]) -> AxResult<usize> {
+        self.inner.send(buf)
+    }
+
+    fn readable(&self) -> bool {
+        self.inner.poll().map_or(false, |state| state.readable)
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn executable(&self) -> bool {
+        false
+    }
+
+    fn get_type(&self) -> FileIOType {
+        FileIOType::Socket
+    }
+
+    fn get_status(&self) -> OpenFlags {
+        let mut flags = OpenFlags::default();
+        if self.close_exec {
+            flags |= OpenFlags::CLOEXEC;
+        }
+        if self.inner.is_nonblocking() {
+            flags |= OpenFlags::NON_BLOCK;
+        }
+        flags
+    }
+
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        self.set_nonblocking(flags.contains(OpenFlags::NON_BLOCK));
+        true
+    }
+
+    fn ready_to_read(&self) -> bool {
+        self.readable()
+    }
+
+    fn ready_to_write(&self) -> bool {
+        self.writable()
+    }
+}
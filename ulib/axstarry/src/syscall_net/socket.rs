@@ -1,19 +1,13 @@
 extern crate alloc;
-use alloc::vec::Vec;
-use core::{
-    mem::size_of,
-    ptr::copy_nonoverlapping,
-    sync::atomic::{AtomicBool, AtomicU64},
-};
+use core::{mem::size_of, ptr::copy_nonoverlapping, slice::from_raw_parts, time::Duration};
 
-use alloc::string::String;
 use axerrno::{AxError, AxResult};
 use axfs::api::{FileIO, FileIOType, OpenFlags, Read, Write};
 
 use axlog::warn;
 use axnet::{
-    add_membership, from_core_sockaddr, into_core_sockaddr, poll_interfaces, IpAddr, SocketAddr,
-    TcpSocket, UdpSocket,
+    add_membership, from_core_sockaddr, into_core_sockaddr, poll_interfaces, CongestionControl,
+    IcmpSocket, IpAddr, Ipv6Addr, RawSocket, SocketAddr, TcpSocket, TcpState, UdpSocket,
 };
 use axsync::Mutex;
 use num_enum::TryFromPrimitive;
@@ -28,6 +22,9 @@ pub const SOCKET_TYPE_MASK: usize = 0xFF;
 pub enum Domain {
     AF_UNIX = 1,
     AF_INET = 2,
+    AF_INET6 = 10,
+    AF_NETLINK = 16,
+    AF_PACKET = 17,
 }
 
 #[derive(TryFromPrimitive, PartialEq, Eq, Clone, Debug)]
@@ -58,6 +55,28 @@ pub const SOCK_NONBLOCK: usize = 0x800;
 /// Set FD_CLOEXEC flag on the new fd
 pub const SOCK_CLOEXEC: usize = 0x80000;
 
+pub const IPPROTO_ICMP: usize = 1;
+pub const IPPROTO_ICMPV6: usize = 58;
+pub const IPPROTO_RAW: usize = 255;
+
+/// SO_SNDBUF 与 SO_RCVBUF 的上限，即 Linux 默认的 `net.core.wmem_max` 与 `net.core.rmem_max`
+const SYSCTL_MEM_MAX: u32 = 212992;
+/// 发送缓冲区的下限
+const SOCK_MIN_SNDBUF: usize = 4608;
+/// 接收缓冲区的下限
+const SOCK_MIN_RCVBUF: usize = 2304;
+
+/// TCP_KEEPIDLE 的上限（秒）
+const MAX_TCP_KEEPIDLE: i32 = 32767;
+/// TCP_KEEPINTVL 的上限（秒）
+const MAX_TCP_KEEPINTVL: i32 = 32767;
+/// TCP_KEEPCNT 的上限
+const MAX_TCP_KEEPCNT: i32 = 127;
+/// 未连接时 TCP_MAXSEG 返回的默认 MSS
+const TCP_MSS_DEFAULT: i32 = 536;
+/// 拥塞控制算法名称的最大长度
+const TCP_CA_NAME_MAX: usize = 16;
+
 #[derive(TryFromPrimitive, Debug)]
 #[repr(usize)]
 #[allow(non_camel_case_types)]
@@ -65,18 +84,37 @@ pub enum SocketOptionLevel {
     IP = 0,
     Socket = 1,
     Tcp = 6,
+    IPv6 = 41,
+    Raw = 255,
+    Packet = 263,
 }
 
 #[derive(TryFromPrimitive, Debug)]
 #[repr(usize)]
 #[allow(non_camel_case_types)]
 pub enum IpOption {
+    IP_HDRINCL = 3,
     IP_MULTICAST_IF = 32,
     IP_MULTICAST_TTL = 33,
     IP_MULTICAST_LOOP = 34,
     IP_ADD_MEMBERSHIP = 35,
 }
 
+#[derive(TryFromPrimitive, Debug)]
+#[repr(usize)]
+#[allow(non_camel_case_types)]
+pub enum RawOption {
+    /// ICMP socket 不接收的 ICMP 类型
+    ICMP_FILTER = 1,
+}
+
+#[derive(TryFromPrimitive, Debug)]
+#[repr(usize)]
+#[allow(non_camel_case_types)]
+pub enum Ipv6Option {
+    IPV6_V6ONLY = 26,
+}
+
 #[derive(TryFromPrimitive, Debug)]
 #[repr(usize)]
 #[allow(non_camel_case_types)]
@@ -87,6 +125,8 @@ pub enum SocketOption {
     SO_SNDBUF = 7,
     SO_RCVBUF = 8,
     SO_KEEPALIVE = 9,
+    SO_LINGER = 13,
+    SO_REUSEPORT = 15,
     SO_RCVTIMEO = 20,
     SO_SNDTIMEO = 21,
 }
@@ -97,6 +137,9 @@ pub enum SocketOption {
 pub enum TcpSocketOption {
     TCP_NODELAY = 1, // disable nagle algorithm and flush
     TCP_MAXSEG = 2,
+    TCP_KEEPIDLE = 4,
+    TCP_KEEPINTVL = 5,
+    TCP_KEEPCNT = 6,
     TCP_INFO = 11,
     TCP_CONGESTION = 13,
 }
@@ -104,6 +147,17 @@ pub enum TcpSocketOption {
 impl IpOption {
     pub fn set(&self, socket: &Socket, opt: &[u8]) -> SyscallResult {
         match self {
+            IpOption::IP_HDRINCL => {
+                if opt.len() < 4 {
+                    return Err(SyscallError::EINVAL);
+                }
+                let opt_value = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                match &*socket.inner.lock() {
+                    SocketInner::Raw(s) => s.set_header_included(opt_value != 0),
+                    _ => return Err(SyscallError::ENOPROTOOPT),
+                }
+                Ok(0)
+            }
             IpOption::IP_MULTICAST_IF => {
                 // 我们只会使用LOOPBACK作为多播接口
                 Ok(0)
@@ -116,6 +170,10 @@ impl IpOption {
                         s.set_socket_ttl(ttl);
                         Ok(0)
                     }
+                    SocketInner::Icmp(s) => {
+                        s.set_socket_ttl(opt[0]);
+                        Ok(0)
+                    }
                     _ => panic!("setsockopt IP_MULTICAST_TTL on a non-udp socket"),
                 }
             }
@@ -129,81 +187,193 @@ impl IpOption {
             }
         }
     }
+
+    /// 目前只支持 IP_HDRINCL，其余选项不写入任何值
+    pub fn get(&self, socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) -> SyscallResult {
+        let buf_len = unsafe { *opt_len } as usize;
+        match self {
+            IpOption::IP_HDRINCL => {
+                if buf_len < 4 {
+                    return Err(SyscallError::EINVAL);
+                }
+                let value: i32 = match &*socket.inner.lock() {
+                    SocketInner::Raw(s) => s.is_header_included() as i32,
+                    _ => return Err(SyscallError::ENOPROTOOPT),
+                };
+                unsafe {
+                    copy_nonoverlapping(&value.to_ne_bytes() as *const u8, opt_value, 4);
+                    *opt_len = 4;
+                }
+                Ok(0)
+            }
+            _ => Ok(0),
+        }
+    }
 }
 
-impl SocketOption {
+impl RawOption {
     pub fn set(&self, socket: &Socket, opt: &[u8]) -> SyscallResult {
         match self {
-            SocketOption::SO_REUSEADDR => {
+            RawOption::ICMP_FILTER => {
                 if opt.len() < 4 {
-                    panic!("can't read a int from socket opt value");
+                    return Err(SyscallError::EINVAL);
+                }
+                let filter = u32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                match &*socket.inner.lock() {
+                    SocketInner::Raw(s) if s.protocol() == IPPROTO_ICMP as u8 => {
+                        s.set_icmp_filter(filter)
+                    }
+                    _ => return Err(SyscallError::EOPNOTSUPP),
                 }
-                let opt_value = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
-                socket.set_reuse_addr(opt_value != 0);
                 Ok(0)
             }
-            SocketOption::SO_DONTROUTE => {
-                if opt.len() < 4 {
-                    panic!("can't read a int from socket opt value");
+        }
+    }
+
+    pub fn get(&self, socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) -> SyscallResult {
+        let buf_len = unsafe { *opt_len } as usize;
+        match self {
+            RawOption::ICMP_FILTER => {
+                let filter = match &*socket.inner.lock() {
+                    SocketInner::Raw(s) if s.protocol() == IPPROTO_ICMP as u8 => s.icmp_filter(),
+                    _ => return Err(SyscallError::EOPNOTSUPP),
+                };
+                let len = buf_len.min(4);
+                unsafe {
+                    copy_nonoverlapping(&filter.to_ne_bytes() as *const u8, opt_value, len);
+                    *opt_len = len as u32;
                 }
+                Ok(0)
+            }
+        }
+    }
+}
 
+impl Ipv6Option {
+    pub fn set(&self, socket: &Socket, opt: &[u8]) -> SyscallResult {
+        if !matches!(socket.domain, Domain::AF_INET6) {
+            return Err(SyscallError::ENOPROTOOPT);
+        }
+        match self {
+            Ipv6Option::IPV6_V6ONLY => {
+                if opt.len() < 4 {
+                    return Err(SyscallError::EINVAL);
+                }
                 let opt_value = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                let inner = socket.inner.lock();
+                match &*inner {
+                    SocketInner::Tcp(s) => s.set_ipv6_only(opt_value != 0),
+                    SocketInner::Udp(s) => s.set_ipv6_only(opt_value != 0),
+                    SocketInner::Raw(_) | SocketInner::Icmp(_) => {
+                        return Err(SyscallError::ENOPROTOOPT)
+                    }
+                }
+                Ok(0)
+            }
+        }
+    }
 
+    pub fn get(&self, socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) -> SyscallResult {
+        if !matches!(socket.domain, Domain::AF_INET6) {
+            return Err(SyscallError::ENOPROTOOPT);
+        }
+        let buf_len = unsafe { *opt_len } as usize;
+        match self {
+            Ipv6Option::IPV6_V6ONLY => {
+                if buf_len < 4 {
+                    return Err(SyscallError::EINVAL);
+                }
+                let inner = socket.inner.lock();
+                let value: i32 = match &*inner {
+                    SocketInner::Tcp(s) => s.is_ipv6_only() as i32,
+                    SocketInner::Udp(s) => s.is_ipv6_only() as i32,
+                    SocketInner::Raw(_) | SocketInner::Icmp(_) => {
+                        return Err(SyscallError::ENOPROTOOPT)
+                    }
+                };
+                unsafe {
+                    copy_nonoverlapping(&value.to_ne_bytes() as *const u8, opt_value, 4);
+                    *opt_len = 4;
+                }
+                Ok(0)
+            }
+        }
+    }
+}
+
+impl SocketOption {
+    pub fn set(&self, socket: &Socket, opt: &[u8]) -> SyscallResult {
+        match self {
+            SocketOption::SO_REUSEADDR => {
+                let opt_value = read_int(opt)?;
                 socket.set_reuse_addr(opt_value != 0);
-                // socket.reuse_addr = opt_value != 0;
+                Ok(0)
+            }
+            SocketOption::SO_REUSEPORT => {
+                let opt_value = read_int(opt)?;
+                match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.set_reuse_port(opt_value != 0),
+                    SocketInner::Udp(s) => s.set_reuse_port(opt_value != 0),
+                    SocketInner::Raw(_) | SocketInner::Icmp(_) => {
+                        warn!("[setsockopt()] set SO_REUSEPORT on a socket without ports, ignored")
+                    }
+                }
+                Ok(0)
+            }
+            SocketOption::SO_DONTROUTE => {
+                let opt_value = read_int(opt)?;
+                match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.set_dont_route(opt_value != 0),
+                    SocketInner::Udp(s) => s.set_dont_route(opt_value != 0),
+                    SocketInner::Raw(_) | SocketInner::Icmp(_) => {
+                        warn!("[setsockopt()] set SO_DONTROUTE on a raw socket, ignored")
+                    }
+                }
                 Ok(0)
             }
             SocketOption::SO_SNDBUF => {
-                if opt.len() < 4 {
-                    panic!("can't read a int from socket opt value");
+                let size = buffer_size(read_int(opt)?, SOCK_MIN_SNDBUF);
+                match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.set_send_buffer_size(size),
+                    SocketInner::Udp(s) => s.set_send_buffer_size(size),
+                    SocketInner::Raw(s) => s.set_send_buffer_size(size),
+                    SocketInner::Icmp(s) => s.set_send_buffer_size(size),
                 }
-
-                let opt_value = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
-
-                socket.set_send_buf_size(opt_value as u64);
-                // socket.send_buf_size = opt_value as usize;
                 Ok(0)
             }
             SocketOption::SO_RCVBUF => {
-                if opt.len() < 4 {
-                    panic!("can't read a int from socket opt value");
+                let size = buffer_size(read_int(opt)?, SOCK_MIN_RCVBUF);
+                match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.set_recv_buffer_size(size),
+                    SocketInner::Udp(s) => s.set_recv_buffer_size(size),
+                    SocketInner::Raw(s) => s.set_recv_buffer_size(size),
+                    SocketInner::Icmp(s) => s.set_recv_buffer_size(size),
                 }
-
-                let opt_value = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
-
-                socket.set_recv_buf_size(opt_value as u64);
-                // socket.recv_buf_size = opt_value as usize;
                 Ok(0)
             }
             SocketOption::SO_KEEPALIVE => {
-                if opt.len() < 4 {
-                    panic!("can't read a int from socket opt value");
+                let opt_value = read_int(opt)?;
+                match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.set_keep_alive(opt_value != 0),
+                    SocketInner::Udp(_) | SocketInner::Raw(_) | SocketInner::Icmp(_) => {
+                        warn!("[setsockopt()] set SO_KEEPALIVE on non-tcp socket, ignored")
+                    }
                 }
-
-                let opt_value = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
-
-                let interval = if opt_value != 0 {
-                    Some(axnet::Duration::from_secs(45))
-                } else {
-                    None
-                };
-
-                let mut inner = socket.inner.lock();
-
-                match &mut (*inner) {
-                    SocketInner::Udp(_) => {
-                        warn!("[setsockopt()] set SO_KEEPALIVE on udp socket, ignored")
+                Ok(0)
+            }
+            SocketOption::SO_LINGER => {
+                if opt.len() < size_of::<Linger>() {
+                    return Err(SyscallError::EINVAL);
+                }
+                let linger = unsafe { *(opt.as_ptr() as *const Linger) };
+                let timeout = (linger.l_onoff != 0)
+                    .then(|| Duration::from_secs(linger.l_linger.max(0) as u64));
+                match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.set_linger(timeout),
+                    SocketInner::Udp(_) | SocketInner::Raw(_) | SocketInner::Icmp(_) => {
+                        warn!("[setsockopt()] set SO_LINGER on non-tcp socket, ignored")
                     }
-                    SocketInner::Tcp(s) => s.with_socket_mut(|s| match s {
-                        Some(s) => s.set_keep_alive(interval),
-                        None => warn!(
-                            "[setsockopt()] set keep-alive for tcp socket not created, ignored"
-                        ),
-                    }),
-                };
-                drop(inner);
-                socket.set_recv_buf_size(opt_value as u64);
-                // socket.recv_buf_size = opt_value as usize;
+                }
                 Ok(0)
             }
             SocketOption::SO_RCVTIMEO => {
@@ -226,82 +396,68 @@ impl SocketOption {
         }
     }
 
-    pub fn get(&self, socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) {
+    pub fn get(&self, socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) -> SyscallResult {
         let buf_len = unsafe { *opt_len } as usize;
 
         match self {
             SocketOption::SO_REUSEADDR => {
-                let value: i32 = if socket.get_reuse_addr() { 1 } else { 0 };
-
-                if buf_len < 4 {
-                    panic!("can't write a int to socket opt value");
-                }
-
-                unsafe {
-                    copy_nonoverlapping(&value.to_ne_bytes() as *const u8, opt_value, 4);
-                    *opt_len = 4;
-                }
+                let value = socket.get_reuse_addr() as i32;
+                unsafe { write_opt(&value.to_ne_bytes(), opt_value, opt_len) };
+            }
+            SocketOption::SO_REUSEPORT => {
+                let value = match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.is_reuse_port(),
+                    SocketInner::Udp(s) => s.is_reuse_port(),
+                    SocketInner::Raw(_) | SocketInner::Icmp(_) => false,
+                } as i32;
+                unsafe { write_opt(&value.to_ne_bytes(), opt_value, opt_len) };
             }
             SocketOption::SO_DONTROUTE => {
-                if buf_len < 4 {
-                    panic!("can't write a int to socket opt value");
-                }
-
-                let size: i32 = if socket.dont_route { 1 } else { 0 };
-
-                unsafe {
-                    copy_nonoverlapping(&size.to_ne_bytes() as *const u8, opt_value, 4);
-                    *opt_len = 4;
-                }
+                let value = match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.is_dont_route(),
+                    SocketInner::Udp(s) => s.is_dont_route(),
+                    SocketInner::Raw(_) | SocketInner::Icmp(_) => false,
+                } as i32;
+                unsafe { write_opt(&value.to_ne_bytes(), opt_value, opt_len) };
             }
             SocketOption::SO_SNDBUF => {
-                if buf_len < 4 {
-                    panic!("can't write a int to socket opt value");
-                }
-
-                let size: i32 = socket.get_send_buf_size() as i32;
-
-                unsafe {
-                    copy_nonoverlapping(&size.to_ne_bytes() as *const u8, opt_value, 4);
-                    *opt_len = 4;
-                }
+                let size = match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.send_buffer_size(),
+                    SocketInner::Udp(s) => s.send_buffer_size(),
+                    SocketInner::Raw(s) => s.send_buffer_size(),
+                    SocketInner::Icmp(s) => s.send_buffer_size(),
+                } as i32;
+                unsafe { write_opt(&size.to_ne_bytes(), opt_value, opt_len) };
             }
             SocketOption::SO_RCVBUF => {
-                if buf_len < 4 {
-                    panic!("can't write a int to socket opt value");
-                }
-
-                let size: i32 = socket.get_recv_buf_size() as i32;
-
-                unsafe {
-                    copy_nonoverlapping(&size.to_ne_bytes() as *const u8, opt_value, 4);
-                    *opt_len = 4;
-                }
+                let size = match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.recv_buffer_size(),
+                    SocketInner::Udp(s) => s.recv_buffer_size(),
+                    SocketInner::Raw(s) => s.recv_buffer_size(),
+                    SocketInner::Icmp(s) => s.recv_buffer_size(),
+                } as i32;
+                unsafe { write_opt(&size.to_ne_bytes(), opt_value, opt_len) };
             }
             SocketOption::SO_KEEPALIVE => {
-                if buf_len < 4 {
-                    panic!("can't write a int to socket opt value");
-                }
-
-                let mut inner = socket.inner.lock();
-                let keep_alive: i32 = match &mut *inner {
-                    SocketInner::Udp(_) => {
-                        warn!("[getsockopt()] get SO_KEEPALIVE on udp socket, returning false");
-                        0
-                    }
-                    SocketInner::Tcp(s) => s.with_socket(|s| match s {
-                        Some(s) => if s.keep_alive().is_some() { 1 } else { 0 },
-                        None => {warn!(
-                            "[setsockopt()] set keep-alive for tcp socket not created, returning false"
-                        );
-                            0},
-                    }),
+                let value = match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.keep_alive(),
+                    SocketInner::Udp(_) | SocketInner::Raw(_) | SocketInner::Icmp(_) => false,
+                } as i32;
+                unsafe { write_opt(&value.to_ne_bytes(), opt_value, opt_len) };
+            }
+            SocketOption::SO_LINGER => {
+                let timeout = match &*socket.inner.lock() {
+                    SocketInner::Tcp(s) => s.linger(),
+                    SocketInner::Udp(_) | SocketInner::Raw(_) | SocketInner::Icmp(_) => None,
+                };
+                let linger = Linger {
+                    l_onoff: timeout.is_some() as i32,
+                    l_linger: timeout.map_or(0, |timeout| timeout.as_secs() as i32),
                 };
-                drop(inner);
-
                 unsafe {
-                    copy_nonoverlapping(&keep_alive.to_ne_bytes() as *const u8, opt_value, 4);
-                    *opt_len = 4;
+                    let bytes =
+                        from_raw_parts(&linger as *const Linger as *const u8, size_of::<Linger>());
+                    write_opt(bytes, opt_value, opt_len);
                 }
             }
             SocketOption::SO_RCVTIMEO => {
@@ -331,6 +487,7 @@ impl SocketOption {
                 panic!("unimplemented!")
             }
         }
+        Ok(0)
     }
 }
 
@@ -339,82 +496,218 @@ impl TcpSocketOption {
         let mut inner = raw_socket.inner.lock();
         let socket = match &mut *inner {
             SocketInner::Tcp(ref mut s) => s,
-            _ => panic!("calling tcp option on a wrong type of socket"),
+            _ => return Err(SyscallError::ENOPROTOOPT),
         };
 
         match self {
             TcpSocketOption::TCP_NODELAY => {
-                if opt.len() < 4 {
-                    panic!("can't read a int from socket opt value");
-                }
-                let opt_value = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
-
+                let opt_value = read_int(opt)?;
                 let _ = socket.set_nagle_enabled(opt_value == 0);
                 let _ = socket.flush();
                 Ok(0)
             }
-            TcpSocketOption::TCP_INFO => {
-                // TODO: support the protocal
+            TcpSocketOption::TCP_MAXSEG => {
+                // smoltcp 总是按对端通告的 MSS 发送
+                warn!("[setsockopt()] set TCP_MAXSEG, ignored");
                 Ok(0)
             }
-            TcpSocketOption::TCP_CONGESTION => {
-                raw_socket.set_congestion(String::from_utf8(Vec::from(opt)).unwrap());
+            TcpSocketOption::TCP_KEEPIDLE => {
+                let secs = read_int(opt)?;
+                if !(1..=MAX_TCP_KEEPIDLE).contains(&secs) {
+                    return Err(SyscallError::EINVAL);
+                }
+                socket.set_keep_alive_idle(Duration::from_secs(secs as u64));
+                Ok(0)
+            }
+            TcpSocketOption::TCP_KEEPINTVL => {
+                let secs = read_int(opt)?;
+                if !(1..=MAX_TCP_KEEPINTVL).contains(&secs) {
+                    return Err(SyscallError::EINVAL);
+                }
+                socket.set_keep_alive_interval(Duration::from_secs(secs as u64));
                 Ok(0)
             }
-            _ => {
-                unimplemented!()
+            TcpSocketOption::TCP_KEEPCNT => {
+                let count = read_int(opt)?;
+                if !(1..=MAX_TCP_KEEPCNT).contains(&count) {
+                    return Err(SyscallError::EINVAL);
+                }
+                socket.set_keep_alive_count(count as u32);
+                Ok(0)
+            }
+            // TCP_INFO 只能读取
+            TcpSocketOption::TCP_INFO => Err(SyscallError::ENOPROTOOPT),
+            TcpSocketOption::TCP_CONGESTION => {
+                // 名称可能以 '\0' 结尾
+                let len = opt.iter().position(|&b| b == 0).unwrap_or(opt.len());
+                let congestion = core::str::from_utf8(&opt[..len])
+                    .ok()
+                    .and_then(CongestionControl::from_name)
+                    .ok_or(SyscallError::ENOENT)?;
+                socket.set_congestion(congestion);
+                Ok(0)
             }
         }
     }
 
-    pub fn get(&self, raw_socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) {
+    pub fn get(&self, raw_socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) -> SyscallResult {
         let inner = raw_socket.inner.lock();
         let socket = match &*inner {
             SocketInner::Tcp(ref s) => s,
-            _ => panic!("calling tcp option on a wrong type of socket"),
+            _ => return Err(SyscallError::ENOPROTOOPT),
         };
 
-        let buf_len = unsafe { *opt_len };
-
         match self {
             TcpSocketOption::TCP_NODELAY => {
-                if buf_len < 4 {
-                    panic!("can't write a int to socket opt value");
-                }
-
-                let value: i32 = if socket.nagle_enabled() { 0 } else { 1 };
-
-                let value = value.to_ne_bytes();
-
-                unsafe {
-                    copy_nonoverlapping(&value as *const u8, opt_value, 4);
-                    *opt_len = 4;
-                }
+                let value = !socket.nagle_enabled() as i32;
+                unsafe { write_opt(&value.to_ne_bytes(), opt_value, opt_len) };
             }
             TcpSocketOption::TCP_MAXSEG => {
-                let len = size_of::<usize>();
-
-                let value: usize = 1500;
-
-                unsafe {
-                    copy_nonoverlapping(&value as *const usize as *const u8, opt_value, len);
-                    *opt_len = len as u32;
+                let mss = match socket.info().mss {
+                    0 => TCP_MSS_DEFAULT,
+                    mss => mss as i32,
                 };
+                unsafe { write_opt(&mss.to_ne_bytes(), opt_value, opt_len) };
             }
-            TcpSocketOption::TCP_INFO => {}
-            TcpSocketOption::TCP_CONGESTION => {
-                let bytes = raw_socket.get_congestion();
-                let bytes = bytes.as_bytes();
-
+            TcpSocketOption::TCP_KEEPIDLE => {
+                let secs = socket.keep_alive_idle().as_secs() as i32;
+                unsafe { write_opt(&secs.to_ne_bytes(), opt_value, opt_len) };
+            }
+            TcpSocketOption::TCP_KEEPINTVL => {
+                let secs = socket.keep_alive_interval().as_secs() as i32;
+                unsafe { write_opt(&secs.to_ne_bytes(), opt_value, opt_len) };
+            }
+            TcpSocketOption::TCP_KEEPCNT => {
+                let count = socket.keep_alive_count() as i32;
+                unsafe { write_opt(&count.to_ne_bytes(), opt_value, opt_len) };
+            }
+            TcpSocketOption::TCP_INFO => {
+                let info = TcpInfo::from(socket.info());
                 unsafe {
-                    copy_nonoverlapping(bytes.as_ptr(), opt_value, bytes.len());
-                    *opt_len = bytes.len() as u32;
-                };
+                    let bytes =
+                        from_raw_parts(&info as *const TcpInfo as *const u8, size_of::<TcpInfo>());
+                    write_opt(bytes, opt_value, opt_len);
+                }
+            }
+            TcpSocketOption::TCP_CONGESTION => {
+                // 与 Linux 相同，返回以 '\0' 填充的 TCP_CA_NAME_MAX 字节
+                let mut name = [0u8; TCP_CA_NAME_MAX];
+                let congestion = socket.congestion().name().as_bytes();
+                name[..congestion.len()].copy_from_slice(congestion);
+                unsafe { write_opt(&name, opt_value, opt_len) };
             }
         }
+        Ok(0)
     }
 }
 
+/// Linux 的 `struct linger`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Linger {
+    /// 是否在关闭时等待数据发送完毕
+    l_onoff: i32,
+    /// 等待的秒数，为 0 时关闭会重置连接
+    l_linger: i32,
+}
+
+/// Linux 的 `struct tcp_info`，只包含 glibc 定义的部分
+///
+/// smoltcp 不提供 RTT 与重传次数等信息，这些字段总是 0
+#[repr(C)]
+#[derive(Default)]
+pub struct TcpInfo {
+    pub tcpi_state: u8,
+    pub tcpi_ca_state: u8,
+    pub tcpi_retransmits: u8,
+    pub tcpi_probes: u8,
+    pub tcpi_backoff: u8,
+    pub tcpi_options: u8,
+    /// 低 4 位为 tcpi_snd_wscale，高 4 位为 tcpi_rcv_wscale
+    pub tcpi_wscale: u8,
+    pub tcpi_flags: u8,
+
+    pub tcpi_rto: u32,
+    pub tcpi_ato: u32,
+    pub tcpi_snd_mss: u32,
+    pub tcpi_rcv_mss: u32,
+
+    pub tcpi_unacked: u32,
+    pub tcpi_sacked: u32,
+    pub tcpi_lost: u32,
+    pub tcpi_retrans: u32,
+    pub tcpi_fackets: u32,
+
+    pub tcpi_last_data_sent: u32,
+    pub tcpi_last_ack_sent: u32,
+    pub tcpi_last_data_recv: u32,
+    pub tcpi_last_ack_recv: u32,
+
+    pub tcpi_pmtu: u32,
+    pub tcpi_rcv_ssthresh: u32,
+    pub tcpi_rtt: u32,
+    pub tcpi_rttvar: u32,
+    pub tcpi_snd_ssthresh: u32,
+    pub tcpi_snd_cwnd: u32,
+    pub tcpi_advmss: u32,
+    pub tcpi_reordering: u32,
+
+    pub tcpi_rcv_rtt: u32,
+    pub tcpi_rcv_space: u32,
+
+    pub tcpi_total_retrans: u32,
+}
+
+impl From<axnet::TcpInfo> for TcpInfo {
+    fn from(info: axnet::TcpInfo) -> Self {
+        // Linux 的 TCP 状态编号
+        let state = match info.state {
+            TcpState::Established => 1,
+            TcpState::SynSent => 2,
+            TcpState::SynReceived => 3,
+            TcpState::FinWait1 => 4,
+            TcpState::FinWait2 => 5,
+            TcpState::TimeWait => 6,
+            TcpState::Closed => 7,
+            TcpState::CloseWait => 8,
+            TcpState::LastAck => 9,
+            TcpState::Listen => 10,
+            TcpState::Closing => 11,
+        };
+        Self {
+            tcpi_state: state,
+            tcpi_ato: info.ack_delay.map_or(0, |delay| delay.as_micros() as u32),
+            tcpi_snd_mss: info.mss as u32,
+            tcpi_rcv_mss: info.mss as u32,
+            tcpi_advmss: info.mss as u32,
+            tcpi_rcv_space: info.recv_capacity as u32,
+            tcpi_snd_ssthresh: info.ssthresh.min(u32::MAX as usize) as u32,
+            tcpi_snd_cwnd: info.cwnd.div_ceil(info.mss.max(1)) as u32,
+            ..Default::default()
+        }
+    }
+}
+
+/// 从选项值中读取一个 int
+fn read_int(opt: &[u8]) -> Result<i32, SyscallError> {
+    match opt.get(..size_of::<i32>()) {
+        Some(bytes) => Ok(i32::from_ne_bytes(bytes.try_into().unwrap())),
+        None => Err(SyscallError::EINVAL),
+    }
+}
+
+/// 写入选项值，与 Linux 相同，缓冲区不足时截断
+unsafe fn write_opt(value: &[u8], opt_value: *mut u8, opt_len: *mut u32) {
+    let len = value.len().min(*opt_len as usize);
+    copy_nonoverlapping(value.as_ptr(), opt_value, len);
+    *opt_len = len as u32;
+}
+
+/// 与 Linux 相同，SO_SNDBUF 与 SO_RCVBUF 设置的大小加倍，并限制在一定范围内
+fn buffer_size(size: i32, min: usize) -> usize {
+    ((size as u32).min(SYSCTL_MEM_MAX) as usize * 2).max(min)
+}
+
 /// 包装内部的不同协议 Socket
 /// 类似 FileDesc，impl FileIO 后加入fd_list
 #[allow(dead_code)]
@@ -427,12 +720,6 @@ pub struct Socket {
     /// Whether the socket is set to close on exec
     pub close_exec: bool,
     recv_timeout: Mutex<Option<TimeVal>>,
-
-    // fake options
-    dont_route: bool,
-    send_buf_size: AtomicU64,
-    recv_buf_size: AtomicU64,
-    congestion: Mutex<String>,
 }
 
 /// The transport protocol used by the socket
@@ -441,6 +728,10 @@ pub enum SocketInner {
     Tcp(TcpSocket),
     /// UDP socket
     Udp(UdpSocket),
+    /// Raw IPv4 socket
+    Raw(RawSocket),
+    /// ICMP datagram socket, for echo requests only
+    Icmp(IcmpSocket),
 }
 
 impl Socket {
@@ -450,25 +741,12 @@ impl Socket {
     fn get_reuse_addr(&self) -> bool {
         let inner = self.inner.lock();
         match &*inner {
-            SocketInner::Tcp(s) => unimplemented!("get_reuse_addr on other socket"),
+            SocketInner::Tcp(s) => s.is_reuse_addr(),
             SocketInner::Udp(s) => s.is_reuse_addr(),
+            SocketInner::Raw(_) | SocketInner::Icmp(_) => false,
         }
     }
 
-    fn get_send_buf_size(&self) -> u64 {
-        self.send_buf_size
-            .load(core::sync::atomic::Ordering::Acquire)
-    }
-
-    fn get_recv_buf_size(&self) -> u64 {
-        self.recv_buf_size
-            .load(core::sync::atomic::Ordering::Acquire)
-    }
-
-    fn get_congestion(&self) -> String {
-        self.congestion.lock().clone()
-    }
-
     fn set_recv_timeout(&self, val: Option<TimeVal>) {
         *self.recv_timeout.lock() = val;
     }
@@ -476,45 +754,47 @@ impl Socket {
     fn set_reuse_addr(&self, flag: bool) {
         let inner = self.inner.lock();
         match &*inner {
-            SocketInner::Tcp(s) => (),
+            SocketInner::Tcp(s) => s.set_reuse_addr(flag),
             SocketInner::Udp(s) => s.set_reuse_addr(flag),
+            _ => (),
         }
     }
 
-    fn set_send_buf_size(&self, size: u64) {
-        self.send_buf_size
-            .store(size, core::sync::atomic::Ordering::Release)
-    }
-
-    fn set_recv_buf_size(&self, size: u64) {
-        self.recv_buf_size
-            .store(size, core::sync::atomic::Ordering::Release)
-    }
-
-    fn set_congestion(&self, congestion: String) {
-        *self.congestion.lock() = congestion;
-    }
-
-    /// Create a new socket with the given domain and socket type.
-    pub fn new(domain: Domain, socket_type: SocketType) -> Self {
-        let inner = match socket_type {
-            SocketType::SOCK_STREAM | SocketType::SOCK_SEQPACKET => {
+    /// Create a new socket with the given domain, socket type and protocol.
+    ///
+    /// SOCK_RAW 只支持 AF_INET，SOCK_DGRAM 的 IPPROTO_ICMP(V6) 为 ICMP socket
+    pub fn new(
+        domain: Domain,
+        socket_type: SocketType,
+        protocol: usize,
+    ) -> Result<Self, SyscallError> {
+        let inner = match (&socket_type, &domain, protocol) {
+            (SocketType::SOCK_STREAM | SocketType::SOCK_SEQPACKET, _, _) => {
                 SocketInner::Tcp(TcpSocket::new())
             }
-            SocketType::SOCK_DGRAM => SocketInner::Udp(UdpSocket::new()),
-            _ => unimplemented!(),
+            (SocketType::SOCK_DGRAM, Domain::AF_INET, IPPROTO_ICMP) => {
+                SocketInner::Icmp(IcmpSocket::new(false))
+            }
+            (SocketType::SOCK_DGRAM, Domain::AF_INET6, IPPROTO_ICMPV6) => {
+                SocketInner::Icmp(IcmpSocket::new(true))
+            }
+            (SocketType::SOCK_DGRAM, _, _) => SocketInner::Udp(UdpSocket::new()),
+            // IPPROTO_RAW 的 socket 可以发送任意协议的报文，smoltcp 不支持
+            (SocketType::SOCK_RAW, Domain::AF_INET, 1..=254) => {
+                SocketInner::Raw(RawSocket::new(protocol as u8))
+            }
+            (SocketType::SOCK_RAW, Domain::AF_INET, _) => {
+                return Err(SyscallError::EPROTONOSUPPORT)
+            }
+            _ => return Err(SyscallError::ESOCKTNOSUPPORT),
         };
-        Self {
+        Ok(Self {
             domain,
             socket_type,
             inner: Mutex::new(inner),
             close_exec: false,
             recv_timeout: Mutex::new(None),
-            dont_route: false,
-            send_buf_size: AtomicU64::new(64 * 1024),
-            recv_buf_size: AtomicU64::new(64 * 1024),
-            congestion: Mutex::new(String::from("reno")),
-        }
+        })
     }
 
     /// set the socket to non-blocking mode
@@ -524,6 +804,8 @@ impl Socket {
         match &*inner {
             SocketInner::Tcp(s) => s.set_nonblocking(nonblocking),
             SocketInner::Udp(s) => s.set_nonblocking(nonblocking),
+            SocketInner::Raw(s) => s.set_nonblocking(nonblocking),
+            SocketInner::Icmp(s) => s.set_nonblocking(nonblocking),
         }
     }
 
@@ -533,6 +815,8 @@ impl Socket {
         match &*inner {
             SocketInner::Tcp(s) => s.is_nonblocking(),
             SocketInner::Udp(s) => s.is_nonblocking(),
+            SocketInner::Raw(s) => s.is_nonblocking(),
+            SocketInner::Icmp(s) => s.is_nonblocking(),
         }
     }
 
@@ -542,6 +826,37 @@ impl Socket {
         match &*inner {
             SocketInner::Tcp(s) => s.is_connected(),
             SocketInner::Udp(s) => s.with_socket(|s| s.is_open()),
+            // raw socket 与 ICMP socket 总是可以收发
+            SocketInner::Raw(_) | SocketInner::Icmp(_) => true,
+        }
+    }
+
+    /// AF_INET6 的 socket 以 IPv4 映射地址表示 IPv4 地址
+    fn user_address(&self, addr: SocketAddr) -> SocketAddr {
+        match self.domain {
+            Domain::AF_INET6 => ipv4_mapped(addr),
+            _ => addr,
+        }
+    }
+
+    /// 设置了 IPV6_V6ONLY 的 socket 不能使用 IPv4 映射地址
+    pub fn check_address(&self, addr: SocketAddr) -> AxResult {
+        let ipv6_only = match &*self.inner.lock() {
+            SocketInner::Tcp(s) => s.is_ipv6_only(),
+            SocketInner::Udp(s) => s.is_ipv6_only(),
+            SocketInner::Raw(_) | SocketInner::Icmp(_) => false,
+        };
+        match addr.addr {
+            IpAddr::Ipv4(_) if ipv6_only => Err(AxError::InvalidInput),
+            _ => Ok(()),
+        }
+    }
+
+    /// 未绑定的 socket 自动绑定时使用的地址
+    pub fn unspecified_address(&self) -> SocketAddr {
+        match self.domain {
+            Domain::AF_INET6 => SocketAddr::new(IpAddr::Ipv6(Ipv6Addr::UNSPECIFIED), 0),
+            _ => SocketAddr::new(IpAddr::v4(0, 0, 0, 0), 0),
         }
     }
 
@@ -551,8 +866,10 @@ impl Socket {
         match &*inner {
             SocketInner::Tcp(s) => s.local_addr(),
             SocketInner::Udp(s) => s.local_addr(),
+            SocketInner::Raw(s) => s.local_addr(),
+            SocketInner::Icmp(s) => s.local_addr(),
         }
-        .map(from_core_sockaddr)
+        .map(|addr| self.user_address(from_core_sockaddr(addr)))
     }
 
     /// Return peer address.
@@ -561,16 +878,21 @@ impl Socket {
         match &*inner {
             SocketInner::Tcp(s) => s.peer_addr(),
             SocketInner::Udp(s) => s.peer_addr(),
+            SocketInner::Raw(s) => s.peer_addr(),
+            SocketInner::Icmp(s) => s.peer_addr(),
         }
-        .map(from_core_sockaddr)
+        .map(|addr| self.user_address(from_core_sockaddr(addr)))
     }
 
     /// Bind the socket to the given address.
     pub fn bind(&self, addr: SocketAddr) -> AxResult {
+        self.check_address(addr)?;
         let inner = self.inner.lock();
         match &*inner {
             SocketInner::Tcp(s) => s.bind(into_core_sockaddr(addr)),
             SocketInner::Udp(s) => s.bind(into_core_sockaddr(addr)),
+            SocketInner::Raw(s) => s.bind(into_core_sockaddr(addr)),
+            SocketInner::Icmp(s) => s.bind(into_core_sockaddr(addr)),
         }
     }
 
@@ -588,7 +910,7 @@ impl Socket {
         let inner = self.inner.lock();
         match &*inner {
             SocketInner::Tcp(s) => s.listen(),
-            SocketInner::Udp(_) => Err(AxError::Unsupported),
+            _ => Err(AxError::Unsupported),
         }
     }
 
@@ -602,7 +924,7 @@ impl Socket {
         let inner = self.inner.lock();
         let new_socket = match &*inner {
             SocketInner::Tcp(s) => s.accept()?,
-            SocketInner::Udp(_) => Err(AxError::Unsupported)?,
+            _ => Err(AxError::Unsupported)?,
         };
         let addr = new_socket.peer_addr()?;
 
@@ -613,21 +935,20 @@ impl Socket {
                 inner: Mutex::new(SocketInner::Tcp(new_socket)),
                 close_exec: false,
                 recv_timeout: Mutex::new(None),
-                dont_route: false,
-                send_buf_size: AtomicU64::new(64 * 1024),
-                recv_buf_size: AtomicU64::new(64 * 1024),
-                congestion: Mutex::new(String::from("reno")),
             },
-            from_core_sockaddr(addr),
+            self.user_address(from_core_sockaddr(addr)),
         ))
     }
 
     /// Connect to the given address.
     pub fn connect(&self, addr: SocketAddr) -> AxResult {
+        self.check_address(addr)?;
         let inner = self.inner.lock();
         match &*inner {
             SocketInner::Tcp(s) => s.connect(into_core_sockaddr(addr)),
             SocketInner::Udp(s) => s.connect(into_core_sockaddr(addr)),
+            SocketInner::Raw(s) => s.connect(into_core_sockaddr(addr)),
+            SocketInner::Icmp(s) => s.connect(into_core_sockaddr(addr)),
         }
     }
 
@@ -638,6 +959,8 @@ impl Socket {
         match &*inner {
             SocketInner::Tcp(s) => s.local_addr().is_ok(),
             SocketInner::Udp(s) => s.local_addr().is_ok(),
+            SocketInner::Raw(s) => s.local_addr().is_ok(),
+            SocketInner::Icmp(s) => s.local_addr().is_ok(),
         }
     }
     #[allow(unused)]
@@ -647,6 +970,22 @@ impl Socket {
         match &*inner {
             SocketInner::Tcp(s) => s.send(buf),
             SocketInner::Udp(s) => s.send_to(buf, into_core_sockaddr(addr)),
+            SocketInner::Raw(s) => s.send_to(buf, into_core_sockaddr(addr)),
+            SocketInner::Icmp(s) => s.send_to(buf, into_core_sockaddr(addr)),
+        }
+    }
+
+    /// 由 `f` 直接向 TCP 的发送缓冲区中填入数据，用于 sendfile 和 splice 的零拷贝发送
+    ///
+    /// 非 TCP socket 返回 `Unsupported`，调用者应退回到普通的写入
+    pub fn send_with<F>(&self, f: F) -> AxResult<usize>
+    where
+        F: FnMut(&mut [u8]) -> AxResult<usize>,
+    {
+        let inner = self.inner.lock();
+        match &*inner {
+            SocketInner::Tcp(s) => s.send_with(f),
+            _ => Err(AxError::Unsupported),
         }
     }
 
@@ -671,7 +1010,18 @@ impl Socket {
                     .recv_from(buf)
                     .map(|(val, addr)| (val, from_core_sockaddr(addr))),
             },
+            SocketInner::Raw(s) => match self.get_recv_timeout() {
+                Some(time) => s.recv_from_timeout(buf, time.turn_to_ticks()),
+                None => s.recv_from(buf),
+            }
+            .map(|(val, addr)| (val, from_core_sockaddr(addr))),
+            SocketInner::Icmp(s) => match self.get_recv_timeout() {
+                Some(time) => s.recv_from_timeout(buf, time.turn_to_ticks()),
+                None => s.recv_from(buf),
+            }
+            .map(|(val, addr)| (val, from_core_sockaddr(addr))),
         }
+        .map(|(len, addr)| (len, self.user_address(addr)))
     }
 
     /// For shutdown(fd, SHUT_WR)
@@ -681,6 +1031,12 @@ impl Socket {
             SocketInner::Udp(s) => {
                 s.shutdown();
             }
+            SocketInner::Raw(s) => {
+                s.shutdown();
+            }
+            SocketInner::Icmp(s) => {
+                s.shutdown();
+            }
             SocketInner::Tcp(s) => s.close(),
         };
     }
@@ -692,6 +1048,12 @@ impl Socket {
             SocketInner::Udp(s) => {
                 let _ = s.shutdown();
             }
+            SocketInner::Raw(s) => {
+                let _ = s.shutdown();
+            }
+            SocketInner::Icmp(s) => {
+                let _ = s.shutdown();
+            }
             SocketInner::Tcp(s) => s.with_socket_mut(|s| {
                 if let Some(s) = s {
                     s.abort();
@@ -707,6 +1069,8 @@ impl FileIO for Socket {
         match &mut *inner {
             SocketInner::Tcp(s) => s.read(buf),
             SocketInner::Udp(s) => s.read(buf),
+            SocketInner::Raw(s) => s.recv(buf),
+            SocketInner::Icmp(s) => s.recv(buf),
         }
     }
 
@@ -715,6 +1079,8 @@ impl FileIO for Socket {
         match &mut *inner {
             SocketInner::Tcp(s) => s.write(buf),
             SocketInner::Udp(s) => s.write(buf),
+            SocketInner::Raw(s) => s.send(buf),
+            SocketInner::Icmp(s) => s.send(buf),
         }
     }
 
@@ -728,6 +1094,8 @@ impl FileIO for Socket {
         match &*inner {
             SocketInner::Tcp(s) => s.poll().map_or(false, |p| p.readable),
             SocketInner::Udp(s) => s.poll().map_or(false, |p| p.readable),
+            SocketInner::Raw(s) => s.poll().map_or(false, |p| p.readable),
+            SocketInner::Icmp(s) => s.poll().map_or(false, |p| p.readable),
         }
     }
 
@@ -737,6 +1105,8 @@ impl FileIO for Socket {
         match &*inner {
             SocketInner::Tcp(s) => s.poll().map_or(false, |p| p.writable),
             SocketInner::Udp(s) => s.poll().map_or(false, |p| p.writable),
+            SocketInner::Raw(s) => s.poll().map_or(false, |p| p.writable),
+            SocketInner::Icmp(s) => s.poll().map_or(false, |p| p.writable),
         }
     }
 
@@ -777,14 +1147,35 @@ impl FileIO for Socket {
     }
 }
 
+/// IPv4 映射地址（::ffff:a.b.c.d）的前 12 字节
+const IPV4_MAPPED_PREFIX: [u8; 12] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff];
+
+/// 将 IPv4 地址转换为 IPv4 映射的 IPv6 地址，其余地址不变
+fn ipv4_mapped(addr: SocketAddr) -> SocketAddr {
+    match addr.addr {
+        IpAddr::Ipv4(ipv4) => {
+            let mut bytes = [0; 16];
+            bytes[..12].copy_from_slice(&IPV4_MAPPED_PREFIX);
+            bytes[12..].copy_from_slice(ipv4.as_bytes());
+            SocketAddr::new(IpAddr::Ipv6(Ipv6Addr(bytes)), addr.port)
+        }
+        IpAddr::Ipv6(_) => addr,
+    }
+}
+
 /// Turn a socket address buffer into a SocketAddr
 ///
-/// Only support INET (ipv4)
-pub unsafe fn socket_address_from(addr: *const u8) -> SocketAddr {
+/// Support INET (ipv4) and INET6 (ipv6), an IPv4-mapped IPv6 address is
+/// turned into the IPv4 address. Other address families are not supported
+/// by inet sockets, and fail with EAFNOSUPPORT.
+pub unsafe fn socket_address_from(addr: *const u8) -> Result<SocketAddr, SyscallError> {
     let addr = addr as *const u16;
-    let domain = Domain::try_from(*addr as usize).expect("Unsupported Domain (Address Family)");
-    match domain {
-        Domain::AF_UNIX => unimplemented!(),
+    let domain = Domain::try_from(*addr as usize).map_err(|_| SyscallError::EAFNOSUPPORT)?;
+    Ok(match domain {
+        // netlink 与 packet 地址由 NetlinkSocket 与 PacketSocket 自行处理
+        Domain::AF_UNIX | Domain::AF_NETLINK | Domain::AF_PACKET => {
+            return Err(SyscallError::EAFNOSUPPORT)
+        }
         Domain::AF_INET => {
             let port = u16::from_be(*addr.add(1));
             let a = (*(addr.add(2) as *const u32)).to_le_bytes();
@@ -792,48 +1183,58 @@ pub unsafe fn socket_address_from(addr: *const u8) -> SocketAddr {
             let addr = IpAddr::v4(a[0], a[1], a[2], a[3]);
             SocketAddr { addr, port }
         }
-    }
+        Domain::AF_INET6 => {
+            let port = u16::from_be(*addr.add(1));
+            // sin6_flowinfo 与 sin6_scope_id 被忽略，链路本地地址的出口接口由路由表决定
+            let a = *(addr.add(4) as *const [u8; 16]);
+
+            let addr = if a[..12] == IPV4_MAPPED_PREFIX {
+                IpAddr::v4(a[12], a[13], a[14], a[15])
+            } else {
+                IpAddr::Ipv6(Ipv6Addr(a))
+            };
+            SocketAddr { addr, port }
+        }
+    })
 }
-/// Only support INET (ipv4)
+
+/// Support INET (ipv4) and INET6 (ipv6)
 ///
 /// ipv4 socket address buffer:
 /// socket_domain (address_family) u16
 /// port u16 (big endian)
 /// addr u32 (big endian)
 ///
+/// ipv6 socket address buffer:
+/// socket_domain (address_family) u16
+/// port u16 (big endian)
+/// flowinfo u32
+/// addr [u8; 16]
+/// scope_id u32
+///
+/// The address is truncated if the buffer is too small.
+///
 /// TODO: Returns error if buf or buf_len is in invalid memory
 pub unsafe fn socket_address_to(addr: SocketAddr, buf: *mut u8, buf_len: *mut u32) -> AxResult {
-    let mut tot_len = *buf_len as usize;
-
-    *buf_len = 8;
-
-    // 写入 AF_INET
-    if tot_len == 0 {
-        return Ok(());
-    }
-    let domain = (Domain::AF_INET as u16).to_ne_bytes();
-    let write_len = tot_len.min(2);
-    copy_nonoverlapping(domain.as_ptr(), buf, write_len);
-    let buf = buf.add(write_len);
-    tot_len -= write_len;
-
-    // 写入 port
-    if tot_len == 0 {
-        return Ok(());
-    }
-    let port = &addr.port.to_be_bytes();
-    let write_len = tot_len.min(2);
-    copy_nonoverlapping(port.as_ptr(), buf, write_len);
-    let buf = buf.add(write_len);
-    tot_len -= write_len;
+    let tot_len = *buf_len as usize;
+
+    let mut raw = [0u8; 28];
+    raw[2..4].copy_from_slice(&addr.port.to_be_bytes());
+    let len = match addr.addr {
+        IpAddr::Ipv4(ipv4) => {
+            raw[..2].copy_from_slice(&(Domain::AF_INET as u16).to_ne_bytes());
+            raw[4..8].copy_from_slice(ipv4.as_bytes());
+            8
+        }
+        IpAddr::Ipv6(ipv6) => {
+            raw[..2].copy_from_slice(&(Domain::AF_INET6 as u16).to_ne_bytes());
+            raw[8..24].copy_from_slice(ipv6.as_bytes());
+            28
+        }
+    };
+    *buf_len = len as u32;
 
-    // 写入 address
-    if tot_len == 0 {
-        return Ok(());
-    }
-    let address = &addr.addr.as_bytes();
-    let write_len = tot_len.min(4);
-    copy_nonoverlapping(address.as_ptr(), buf, write_len);
+    copy_nonoverlapping(raw.as_ptr(), buf, tot_len.min(len));
 
     Ok(())
 }
@@ -5,9 +5,11 @@ use core::time::Duration;
 use axconfig::TASK_STACK_SIZE;
 use axhal::time::current_time;
 use axprocess::{
+    credentials::{MAY_EXEC, NGROUPS_MAX},
     current_process, current_task, exit_current_task,
-    flags::{CloneFlags, WaitStatus},
+    flags::{CloneFlags, WaitStatus, PER_CLEAR_ON_SETID},
     futex::clear_wait,
+    is_script,
     link::{deal_with_path, raw_ptr_to_ref_str, AT_FDCWD},
     set_child_tid, sleep_now_task, wait_pid, yield_now_task, Process, PID2PC,
 };
@@ -17,8 +19,8 @@ use axsync::Mutex;
 //     AxTaskRef,
 // };
 use crate::{
-    CloneArgs, RLimit, SyscallError, SyscallResult, TimeSecs, WaitFlags, RLIMIT_AS, RLIMIT_NOFILE,
-    RLIMIT_STACK,
+    syscall_fs::imp::check_search, CloneArgs, RLimit, SyscallError, SyscallResult, TimeSecs,
+    WaitFlags, RLIMIT_AS, RLIMIT_NOFILE, RLIMIT_STACK,
 };
 use axlog::{info, warn};
 use axtask::TaskId;
@@ -140,9 +142,29 @@ pub fn syscall_exec(args: [usize; 6]) -> SyscallResult {
     // }
     let curr_process = current_process();
 
+    // 检查对各级目录的搜索权限与文件的执行权限，并记下文件属性用于处理 set-user-ID 位
+    let node = axfs::api::lookup(&path).ok();
+    let attr = node.as_ref().and_then(|node| node.get_attr().ok());
+    if let (Some(node), Some(attr)) = (node.as_ref(), attr.as_ref()) {
+        let credentials = curr_process.credentials.lock();
+        check_search(&credentials, &path)?;
+        let acl = axfs::xattr::access_acl(node);
+        if !credentials.may_access(attr, acl.as_ref(), MAY_EXEC) {
+            return Err(SyscallError::EACCES);
+        }
+    }
+
     // 设置 file_path
     curr_process.set_file_path(path.clone());
 
+    // 脚本由 busybox sh 解释执行，不处理其 set-user-ID 位。
+    // 身份在加载新程序之前更新，加载失败时进程直接退出
+    let setid_attr = attr.as_ref().filter(|_| !is_script(&path));
+    if curr_process.credentials.lock().exec(setid_attr) {
+        // 有效 id 改变时不允许调用者关闭新程序的地址空间随机化
+        curr_process.set_personality(curr_process.get_personality() & !PER_CLEAR_ON_SETID);
+    }
+
     // 清空futex信号列表
     clear_wait(curr_process.pid(), true);
     let argc = args_vec.len();
@@ -447,23 +469,208 @@ pub fn syscall_umask(args: [usize; 6]) -> SyscallResult {
     Ok(current_process().fd_manager.set_mask(new_mask) as isize)
 }
 
-/// 获取用户 id。在实现多用户权限前默认为最高权限
+/// 获取真实用户 id
 pub fn syscall_getuid() -> SyscallResult {
-    Ok(0)
+    Ok(current_process().credentials.lock().ruid as isize)
 }
 
-/// 获取有效用户 id，即相当于哪个用户的权限。在实现多用户权限前默认为最高权限
+/// 获取有效用户 id，即相当于哪个用户的权限
 pub fn syscall_geteuid() -> SyscallResult {
-    Ok(0)
+    Ok(current_process().credentials.lock().euid as isize)
 }
 
-/// 获取用户组 id。在实现多用户权限前默认为最高权限
+/// 获取真实用户组 id
 pub fn syscall_getgid() -> SyscallResult {
-    Ok(0)
+    Ok(current_process().credentials.lock().rgid as isize)
 }
 
-/// 获取有效用户组 id，即相当于哪个用户的权限。在实现多用户权限前默认为最高权限
+/// 获取有效用户组 id，即相当于哪个用户组的权限
 pub fn syscall_getegid() -> SyscallResult {
+    Ok(current_process().credentials.lock().egid as isize)
+}
+
+/// 将 set 系列调用中的 id 参数转为 `Option`，-1 表示不修改
+fn id_arg(id: usize) -> Option<u32> {
+    let id = id as u32;
+    (id != u32::MAX).then_some(id)
+}
+
+/// 设置用户 id
+/// # Arguments
+/// * `uid` - u32
+pub fn syscall_setuid(args: [usize; 6]) -> SyscallResult {
+    let uid = id_arg(args[0]).ok_or(SyscallError::EINVAL)?;
+    current_process()
+        .credentials
+        .lock()
+        .setuid(uid)
+        .map_err(|_| SyscallError::EPERM)?;
+    Ok(0)
+}
+
+/// 设置用户组 id
+/// # Arguments
+/// * `gid` - u32
+pub fn syscall_setgid(args: [usize; 6]) -> SyscallResult {
+    let gid = id_arg(args[0]).ok_or(SyscallError::EINVAL)?;
+    current_process()
+        .credentials
+        .lock()
+        .setgid(gid)
+        .map_err(|_| SyscallError::EPERM)?;
+    Ok(0)
+}
+
+/// 设置真实与有效用户 id，-1 表示不修改
+/// # Arguments
+/// * `ruid` - u32
+/// * `euid` - u32
+pub fn syscall_setreuid(args: [usize; 6]) -> SyscallResult {
+    current_process()
+        .credentials
+        .lock()
+        .setreuid(id_arg(args[0]), id_arg(args[1]))
+        .map_err(|_| SyscallError::EPERM)?;
+    Ok(0)
+}
+
+/// 设置真实与有效用户组 id，-1 表示不修改
+/// # Arguments
+/// * `rgid` - u32
+/// * `egid` - u32
+pub fn syscall_setregid(args: [usize; 6]) -> SyscallResult {
+    current_process()
+        .credentials
+        .lock()
+        .setregid(id_arg(args[0]), id_arg(args[1]))
+        .map_err(|_| SyscallError::EPERM)?;
+    Ok(0)
+}
+
+/// 设置真实、有效与保存的用户 id，-1 表示不修改
+/// # Arguments
+/// * `ruid` - u32
+/// * `euid` - u32
+/// * `suid` - u32
+pub fn syscall_setresuid(args: [usize; 6]) -> SyscallResult {
+    current_process()
+        .credentials
+        .lock()
+        .setresuid(id_arg(args[0]), id_arg(args[1]), id_arg(args[2]))
+        .map_err(|_| SyscallError::EPERM)?;
+    Ok(0)
+}
+
+/// 设置真实、有效与保存的用户组 id，-1 表示不修改
+/// # Arguments
+/// * `rgid` - u32
+/// * `egid` - u32
+/// * `sgid` - u32
+pub fn syscall_setresgid(args: [usize; 6]) -> SyscallResult {
+    current_process()
+        .credentials
+        .lock()
+        .setresgid(id_arg(args[0]), id_arg(args[1]), id_arg(args[2]))
+        .map_err(|_| SyscallError::EPERM)?;
+    Ok(0)
+}
+
+/// 获取真实、有效与保存的用户 id
+/// # Arguments
+/// * `ruid` - *mut u32
+/// * `euid` - *mut u32
+/// * `suid` - *mut u32
+pub fn syscall_getresuid(args: [usize; 6]) -> SyscallResult {
+    let process = current_process();
+    let ptrs = [args[0], args[1], args[2]].map(|ptr| ptr as *mut u32);
+    for &ptr in ptrs.iter() {
+        if process.manual_alloc_type_for_lazy(ptr).is_err() {
+            return Err(SyscallError::EFAULT);
+        }
+    }
+    let credentials = process.credentials.lock();
+    let ids = [credentials.ruid, credentials.euid, credentials.suid];
+    for (ptr, id) in ptrs.into_iter().zip(ids) {
+        unsafe { *ptr = id };
+    }
+    Ok(0)
+}
+
+/// 获取真实、有效与保存的用户组 id
+/// # Arguments
+/// * `rgid` - *mut u32
+/// * `egid` - *mut u32
+/// * `sgid` - *mut u32
+pub fn syscall_getresgid(args: [usize; 6]) -> SyscallResult {
+    let process = current_process();
+    let ptrs = [args[0], args[1], args[2]].map(|ptr| ptr as *mut u32);
+    for &ptr in ptrs.iter() {
+        if process.manual_alloc_type_for_lazy(ptr).is_err() {
+            return Err(SyscallError::EFAULT);
+        }
+    }
+    let credentials = process.credentials.lock();
+    let ids = [credentials.rgid, credentials.egid, credentials.sgid];
+    for (ptr, id) in ptrs.into_iter().zip(ids) {
+        unsafe { *ptr = id };
+    }
+    Ok(0)
+}
+
+/// 获取附加组
+///
+/// `size` 为 0 时只返回附加组的数量
+/// # Arguments
+/// * `size` - i32
+/// * `list` - *mut u32
+pub fn syscall_getgroups(args: [usize; 6]) -> SyscallResult {
+    let size = args[0] as i32;
+    let list = args[1] as *mut u32;
+    if size < 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    let process = current_process();
+    let groups = process.credentials.lock().groups.clone();
+    if size == 0 {
+        return Ok(groups.len() as isize);
+    }
+    if (size as usize) < groups.len() {
+        return Err(SyscallError::EINVAL);
+    }
+    for (i, &gid) in groups.iter().enumerate() {
+        let ptr = unsafe { list.add(i) };
+        if process.manual_alloc_type_for_lazy(ptr).is_err() {
+            return Err(SyscallError::EFAULT);
+        }
+        unsafe { *ptr = gid };
+    }
+    Ok(groups.len() as isize)
+}
+
+/// 设置附加组，需要特权
+/// # Arguments
+/// * `size` - usize
+/// * `list` - *const u32
+pub fn syscall_setgroups(args: [usize; 6]) -> SyscallResult {
+    let size = args[0];
+    let list = args[1] as *const u32;
+    if size > NGROUPS_MAX {
+        return Err(SyscallError::EINVAL);
+    }
+    let process = current_process();
+    let mut groups = Vec::with_capacity(size);
+    for i in 0..size {
+        let ptr = unsafe { list.add(i) };
+        if process.manual_alloc_type_for_lazy(ptr).is_err() {
+            return Err(SyscallError::EFAULT);
+        }
+        groups.push(unsafe { *ptr });
+    }
+    process
+        .credentials
+        .lock()
+        .setgroups(groups)
+        .map_err(|_| SyscallError::EPERM)?;
     Ok(0)
 }
 
@@ -497,6 +704,7 @@ pub fn syscall_setsid() -> SyscallResult {
         process.get_heap_bottom(),
         process.fd_manager.fd_table.lock().clone(),
     );
+    *new_process.credentials.lock() = process.credentials.lock().clone();
     #[cfg(feature = "signal")]
     new_process
         .signal_modules
@@ -595,3 +803,18 @@ pub fn syscall_prctl(args: [usize; 6]) -> SyscallResult {
         _ => Ok(0),
     }
 }
+
+/// 设置进程的执行域，返回原来的执行域
+///
+/// 参数为 0xffffffff 时仅查询而不修改
+/// # Arguments
+/// * `persona` - u32
+pub fn syscall_personality(args: [usize; 6]) -> SyscallResult {
+    let persona = args[0] as u32;
+    let process = current_process();
+    let old = process.get_personality();
+    if persona != 0xffff_ffff {
+        process.set_personality(persona);
+    }
+    Ok(old as isize)
+}
@@ -3,7 +3,6 @@ use core::{slice::from_raw_parts_mut, time::Duration};
 use axhal::time::{current_time, current_time_nanos, nanos_to_ticks, NANOS_PER_SEC};
 
 use axprocess::{current_process, current_task, time_stat_output};
-use rand::{rngs::SmallRng, Fill, SeedableRng};
 
 use crate::{
     ClockId, ITimerVal, RusageFlags, SysInfo, SyscallError, SyscallResult, TimeSecs, TimeVal, Tms,
@@ -196,11 +195,9 @@ pub fn syscall_getrandom(args: [usize; 6]) -> SyscallResult {
 
     let buf = unsafe { from_raw_parts_mut(buf, len) };
 
-    // TODO: flags
-    // - GRND_RANDOM: use /dev/random or /dev/urandom
-    // - GRND_NONBLOCK: EAGAIN when block
-    let mut rng = SmallRng::from_seed([0; 32]);
-    buf.try_fill(&mut rng).unwrap();
+    // /dev/random 与 /dev/urandom 使用同一个内核随机数生成器，不会阻塞，
+    // 因此 GRND_RANDOM 与 GRND_NONBLOCK 均无需处理
+    axhal::random::fill_bytes(buf);
 
     Ok(buf.len() as isize)
 }
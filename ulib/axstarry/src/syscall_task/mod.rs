@@ -49,6 +49,16 @@ pub fn task_syscall(syscall_id: task_syscall_id::TaskSyscallId, args: [usize; 6]
         GETEUID => syscall_geteuid(),
         GETGID => syscall_getgid(),
         GETEGID => syscall_getegid(),
+        SETUID => syscall_setuid(args),
+        SETGID => syscall_setgid(args),
+        SETREUID => syscall_setreuid(args),
+        SETREGID => syscall_setregid(args),
+        SETRESUID => syscall_setresuid(args),
+        GETRESUID => syscall_getresuid(args),
+        SETRESGID => syscall_setresgid(args),
+        GETRESGID => syscall_getresgid(args),
+        GETGROUPS => syscall_getgroups(args),
+        SETGROUPS => syscall_setgroups(args),
         GETTID => syscall_gettid(),
         #[cfg(feature = "futex")]
         FUTEX => syscall_futex(args),
@@ -65,7 +75,6 @@ pub fn task_syscall(syscall_id: task_syscall_id::TaskSyscallId, args: [usize; 6]
         // 不做处理即可
         SIGTIMEDWAIT => Ok(0),
         SYSLOG => Ok(0),
-        MADVICE => Ok(0),
         SCHED_SETAFFINITY => Ok(0),
         SCHED_GETAFFINITY => syscall_sched_getaffinity(args),
         SCHED_SETSCHEDULER => syscall_sched_setscheduler(args),
@@ -76,6 +85,7 @@ pub fn task_syscall(syscall_id: task_syscall_id::TaskSyscallId, args: [usize; 6]
         // syscall below just for x86_64
         #[cfg(target_arch = "x86_64")]
         PRCTL => syscall_prctl(args),
+        PERSONALITY => syscall_personality(args),
         #[cfg(target_arch = "x86_64")]
         VFORK => syscall_vfork(),
         #[cfg(target_arch = "x86_64")]
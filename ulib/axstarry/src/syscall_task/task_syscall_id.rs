@@ -35,18 +35,28 @@ pub enum TaskSyscallId {
     GETRUSAGE = 165,
     UMASK = 166,
     PRCTL = 167,
+    PERSONALITY = 92,
     GETPID = 172,
     GETPPID = 173,
     GETUID = 174,
     GETEUID = 175,
     GETGID = 176,
     GETEGID = 177,
+    SETREGID = 143,
+    SETGID = 144,
+    SETREUID = 145,
+    SETUID = 146,
+    SETRESUID = 147,
+    GETRESUID = 148,
+    SETRESGID = 149,
+    GETRESGID = 150,
+    GETGROUPS = 158,
+    SETGROUPS = 159,
     GETTID = 178,
     SYSINFO = 179,
     CLONE = 220,
     CLONE3 = 435,
     EXECVE = 221,
-    MADVICE = 233,
     WAIT4 = 260,
     GETRANDOM = 278,
     SCHED_YIELD = 124,
@@ -97,6 +107,7 @@ numeric_enum_macro::numeric_enum! {
         GETRUSAGE = 98,
         UMASK = 95,
         PRCTL = 157,
+        PERSONALITY = 135,
         GETPID = 39,
         GETPPID = 110,
         GETUID = 102,
@@ -105,12 +116,21 @@ numeric_enum_macro::numeric_enum! {
         GETPGID = 121,
         SETPGID = 109,
         GETEGID = 108,
+        SETUID = 105,
+        SETGID = 106,
+        SETREUID = 113,
+        SETREGID = 114,
+        GETGROUPS = 115,
+        SETGROUPS = 116,
+        SETRESUID = 117,
+        GETRESUID = 118,
+        SETRESGID = 119,
+        GETRESGID = 120,
         GETTID = 186,
         SYSINFO = 99,
         CLONE = 56,
         CLONE3 = 435,
         EXECVE = 59,
-        MADVICE = 28,
         WAIT4 = 61,
         GETRANDOM = 318,
         SCHED_YIELD = 24,